@@ -0,0 +1,147 @@
+//! Threshold map used by the 'dissolve' transition.
+//!
+//! We generate a tileable blue noise texture with the void-and-cluster method
+//! (https://cv.ulichney.com/papers/1993-void-cluster.pdf). Its values are evenly distributed in
+//! 0..=255, and neighbouring pixels get thresholds that are far apart, so switching every pixel
+//! whose threshold is below the transition's progress looks like static instead of blotches.
+
+use std::sync::OnceLock;
+
+/// Width and height of the (square) noise tile
+pub(super) const SIZE: usize = 64;
+const LEN: usize = SIZE * SIZE;
+
+/// How spread out the energy of each point is. Ulichney recommends 1.5
+const SIGMA: f32 = 1.5;
+
+/// Returns the threshold map, generating it in the first call.
+///
+/// The map is `SIZE * SIZE` bytes long, row-major, and is meant to be tiled over the screen.
+pub(super) fn threshold_map() -> &'static [u8] {
+    static MAP: OnceLock<Box<[u8]>> = OnceLock::new();
+    MAP.get_or_init(generate)
+}
+
+struct Pattern {
+    points: Box<[bool]>,
+    energy: Box<[f32]>,
+}
+
+impl Pattern {
+    fn toggle(&mut self, i: usize, kernel: &[f32]) {
+        self.points[i] = !self.points[i];
+        let sign = if self.points[i] { 1.0 } else { -1.0 };
+        let (x, y) = (i % SIZE, i / SIZE);
+        for (j, energy) in self.energy.iter_mut().enumerate() {
+            let dx = (j % SIZE + SIZE - x) % SIZE;
+            let dy = (j / SIZE + SIZE - y) % SIZE;
+            *energy += sign * kernel[dy * SIZE + dx];
+        }
+    }
+
+    /// The point with the most energy, that is, the one with most points around it
+    fn tightest_cluster(&self) -> usize {
+        let mut best = 0;
+        let mut max = f32::MIN;
+        for (i, (&point, &energy)) in self.points.iter().zip(self.energy.iter()).enumerate() {
+            if point && energy > max {
+                max = energy;
+                best = i;
+            }
+        }
+        best
+    }
+
+    /// The empty spot with the least energy, that is, the one furthest away from every point
+    fn largest_void(&self) -> usize {
+        let mut best = 0;
+        let mut min = f32::MAX;
+        for (i, (&point, &energy)) in self.points.iter().zip(self.energy.iter()).enumerate() {
+            if !point && energy < min {
+                min = energy;
+                best = i;
+            }
+        }
+        best
+    }
+}
+
+fn generate() -> Box<[u8]> {
+    // gaussian falloff, with distances wrapping around so that the tile repeats seamlessly
+    let kernel: Box<[f32]> = (0..LEN)
+        .map(|i| {
+            let dx = (i % SIZE).min(SIZE - i % SIZE) as f32;
+            let dy = (i / SIZE).min(SIZE - i / SIZE) as f32;
+            (-(dx * dx + dy * dy) / (2.0 * SIGMA * SIGMA)).exp()
+        })
+        .collect();
+
+    let mut initial = Pattern {
+        points: vec![false; LEN].into_boxed_slice(),
+        energy: vec![0.0; LEN].into_boxed_slice(),
+    };
+
+    // we want the same map every time, so we use a fixed seed xorshift instead of a real rng
+    let mut seed: u32 = 0x9e37_79b9;
+    let mut ones = 0;
+    while ones < LEN / 10 {
+        seed ^= seed << 13;
+        seed ^= seed >> 17;
+        seed ^= seed << 5;
+        let i = seed as usize % LEN;
+        if !initial.points[i] {
+            initial.toggle(i, &kernel);
+            ones += 1;
+        }
+    }
+
+    // move points from clusters into voids until the pattern stabilizes
+    for _ in 0..LEN {
+        let cluster = initial.tightest_cluster();
+        initial.toggle(cluster, &kernel);
+        let void = initial.largest_void();
+        initial.toggle(void, &kernel);
+        if void == cluster {
+            break;
+        }
+    }
+
+    let mut ranks = vec![0usize; LEN];
+
+    // rank the initial points by removing the tightest clusters first
+    let mut pattern = Pattern {
+        points: initial.points.clone(),
+        energy: initial.energy.clone(),
+    };
+    for rank in (0..ones).rev() {
+        let cluster = pattern.tightest_cluster();
+        pattern.toggle(cluster, &kernel);
+        ranks[cluster] = rank;
+    }
+
+    // rank everything else by filling the largest voids first
+    for rank in ones..LEN {
+        let void = initial.largest_void();
+        initial.toggle(void, &kernel);
+        ranks[void] = rank;
+    }
+
+    ranks
+        .into_iter()
+        .map(|rank| (rank * 256 / LEN) as u8)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn thresholds_are_evenly_distributed() {
+        let mut count = [0usize; 256];
+        for &threshold in threshold_map() {
+            count[threshold as usize] += 1;
+        }
+        assert!(count.iter().all(|&c| c == LEN / 256));
+    }
+}
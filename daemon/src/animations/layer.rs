@@ -0,0 +1,78 @@
+//! `swww img --blend`: composites an image, and every frame of its animation, over a base image.
+//!
+//! Animation frames only store what changed since the previous frame, so they cannot be applied
+//! to what the wallpaper displays once it is composited. Instead, we keep the animation's own
+//! frame in a `Layer`, apply the frames to it, and composite it over the base onto the canvas.
+
+use utils::ipc::BlendMode;
+
+pub(super) struct Layer {
+    mode: BlendMode,
+    base: Box<[u8]>,
+    /// the animation's current frame, as it would be displayed without blending
+    frame: Box<[u8]>,
+}
+
+impl Layer {
+    /// `base` and `img` must have the same dimensions and pixel format
+    pub(super) fn new(mode: BlendMode, base: &[u8], img: &[u8]) -> Self {
+        Self {
+            mode,
+            base: base.into(),
+            frame: img.into(),
+        }
+    }
+
+    pub(super) fn frame_mut(&mut self) -> &mut [u8] {
+        &mut self.frame
+    }
+
+    /// Writes the current frame, composited over the base, into `canvas`
+    pub(super) fn composite(&self, canvas: &mut [u8]) {
+        composite(self.mode, &self.base, &self.frame, canvas);
+    }
+
+    pub(super) fn composited(&self) -> Box<[u8]> {
+        let mut canvas = vec![0; self.frame.len()].into_boxed_slice();
+        self.composite(&mut canvas);
+        canvas
+    }
+}
+
+fn composite(mode: BlendMode, base: &[u8], img: &[u8], canvas: &mut [u8]) {
+    // matching outside of the loop lets each of them be vectorized
+    match mode {
+        BlendMode::Additive => {
+            composite_with(base, img, canvas, |a, b| BlendMode::Additive.channel(a, b))
+        }
+        BlendMode::Screen => {
+            composite_with(base, img, canvas, |a, b| BlendMode::Screen.channel(a, b))
+        }
+        BlendMode::Overlay => {
+            composite_with(base, img, canvas, |a, b| BlendMode::Overlay.channel(a, b))
+        }
+    }
+}
+
+#[inline(always)]
+fn composite_with(base: &[u8], img: &[u8], canvas: &mut [u8], f: impl Fn(u8, u8) -> u8) {
+    for ((pixel, a), b) in canvas.iter_mut().zip(base).zip(img) {
+        *pixel = f(*a, *b);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn frames_are_composited_over_the_base() {
+        let mut layer = Layer::new(BlendMode::Additive, &[10, 20, 250], &[0, 0, 0]);
+        assert_eq!(*layer.composited(), [10, 20, 250]);
+
+        layer.frame_mut().copy_from_slice(&[5, 100, 10]);
+        let mut canvas = [0; 3];
+        layer.composite(&mut canvas);
+        assert_eq!(canvas, [15, 120, 255]);
+    }
+}
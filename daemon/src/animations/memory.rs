@@ -0,0 +1,213 @@
+//! `swww-daemon --max-animation-memory`: a limit on the memory held by the animations we play.
+//!
+//! Animation frames stay compressed, and we unpack each one straight onto the wallpapers when it
+//! is due, so an animation costs its compressed frames, plus the full frames it keeps unpacked:
+//! two to interpolate (the frames it blends between) and two to blend over a base (the base and
+//! the animation's current frame).
+//!
+//! We reserve that memory when the request arrives, before keeping any of it. If it does not fit,
+//! we fall back to reserving only the compressed frames, and play the animation without
+//! interpolating or blending it, unpacking each frame on the fly. If not even those fit, we only
+//! display its first frame. The wallpapers that display an animation hold its `Reservation`, so it
+//! is given back as soon as they stop displaying it. Streamed animations reserve their frames as
+//! they arrive instead, and stop loading once they no longer fit.
+
+use std::sync::{
+    atomic::{AtomicUsize, Ordering},
+    Arc,
+};
+
+use utils::ipc::Animation;
+
+static BUDGET: Budget = Budget::new();
+
+/// Sets `--max-animation-memory`, in bytes
+pub(crate) fn set_limit(bytes: usize) {
+    BUDGET.limit.store(bytes, Ordering::Relaxed);
+}
+
+struct Budget {
+    limit: AtomicUsize,
+    used: AtomicUsize,
+}
+
+impl Budget {
+    const fn new() -> Self {
+        Self {
+            limit: AtomicUsize::new(usize::MAX),
+            used: AtomicUsize::new(0),
+        }
+    }
+
+    /// Adds `bytes` to what is used, unless that goes over the limit once `freed` is given back
+    fn try_add(&self, bytes: usize, freed: usize) -> bool {
+        let limit = self.limit.load(Ordering::Relaxed);
+        self.used
+            .fetch_update(Ordering::AcqRel, Ordering::Acquire, |used| {
+                used.checked_add(bytes)
+                    .filter(|total| total.saturating_sub(freed) <= limit)
+            })
+            .is_ok()
+    }
+
+    #[cfg(test)]
+    fn try_reserve(&'static self, bytes: usize) -> Option<Reservation> {
+        self.try_add(bytes, 0).then(|| Reservation {
+            budget: self,
+            bytes: AtomicUsize::new(bytes),
+        })
+    }
+
+    fn too_much(&self, needed: usize) -> String {
+        format!(
+            "the animation needs {:.1}MiB, which goes over swww-daemon's --max-animation-memory \
+             ({:.1}MiB of {:.1}MiB already used)",
+            needed as f64 / MIB,
+            self.used.load(Ordering::Acquire) as f64 / MIB,
+            self.limit.load(Ordering::Relaxed) as f64 / MIB,
+        )
+    }
+}
+
+const MIB: f64 = (1 << 20) as f64;
+
+/// Memory held by an animation, given back when it is dropped
+pub(crate) struct Reservation {
+    budget: &'static Budget,
+    bytes: AtomicUsize,
+}
+
+impl Reservation {
+    /// Reserves `bytes` more, for frames of a streamed animation that just arrived. Fails with
+    /// why if they do not fit
+    pub(super) fn grow(&self, bytes: usize) -> Result<(), String> {
+        if !self.budget.try_add(bytes, 0) {
+            return Err(self
+                .budget
+                .too_much(self.bytes.load(Ordering::Acquire) + bytes));
+        }
+        self.bytes.fetch_add(bytes, Ordering::AcqRel);
+        Ok(())
+    }
+}
+
+impl Drop for Reservation {
+    fn drop(&mut self) {
+        self.budget
+            .used
+            .fetch_sub(*self.bytes.get_mut(), Ordering::AcqRel);
+    }
+}
+
+/// The memory playing `animation` takes, blended over a base if `layered`
+pub(crate) fn needed(animation: &Animation, layered: bool) -> usize {
+    let frame = animation
+        .animation
+        .first()
+        .map_or(0, |(frame, _)| frame.decompressed_len());
+    compressed(animation) + unpacked(frame, animation.interpolate, layered)
+}
+
+/// The memory the compressed frames of `animation` take, which is all it needs when played
+/// without interpolating or blending it
+pub(crate) fn compressed(animation: &Animation) -> usize {
+    animation
+        .animation
+        .iter()
+        .map(|(frame, _)| frame.compressed_len())
+        .sum()
+}
+
+/// The memory taken by the frames an animation keeps unpacked, if they take `frame` bytes each
+pub(crate) fn unpacked(frame: usize, interpolate: bool, layered: bool) -> usize {
+    let frames = if interpolate { 2 } else { 0 } + if layered { 2 } else { 0 };
+    frames * frame
+}
+
+/// The memory the `replaced` reservations, one for each of their holders, give back once those
+/// let go of them. Reservations that something else holds too are not given back
+pub(crate) fn freed(replaced: impl IntoIterator<Item = Arc<Reservation>>) -> usize {
+    let mut held: Vec<(Arc<Reservation>, usize)> = Vec::new();
+    for reservation in replaced {
+        match held.iter_mut().find(|(r, _)| Arc::ptr_eq(r, &reservation)) {
+            Some((_, holders)) => *holders += 1,
+            None => held.push((reservation, 1)),
+        }
+    }
+    held.iter()
+        // one more for the `Arc` in `held` itself
+        .filter(|(reservation, holders)| Arc::strong_count(reservation) == holders + 1)
+        .map(|(reservation, _)| reservation.bytes.load(Ordering::Acquire))
+        .sum()
+}
+
+/// Reserves `needed` bytes for each animation of a request, all or none of them. `freed` bytes,
+/// of what the animations take the place of, count as free, see `freed`. Fails with why if they
+/// do not fit
+pub(crate) fn reserve(needed: &[usize], freed: usize) -> Result<Vec<Arc<Reservation>>, String> {
+    let total = needed.iter().sum();
+    if !BUDGET.try_add(total, freed) {
+        return Err(BUDGET.too_much(total));
+    }
+    // the whole request fits, so we split what we added into a reservation per animation
+    Ok(needed
+        .iter()
+        .map(|&bytes| {
+            Arc::new(Reservation {
+                budget: &BUDGET,
+                bytes: AtomicUsize::new(bytes),
+            })
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reservations_stay_within_the_limit() {
+        static TEST_BUDGET: Budget = Budget::new();
+        TEST_BUDGET.limit.store(100, Ordering::Relaxed);
+
+        let first = TEST_BUDGET.try_reserve(60).unwrap();
+        assert!(TEST_BUDGET.try_reserve(50).is_none());
+        let second = TEST_BUDGET.try_reserve(40).unwrap();
+        assert!(TEST_BUDGET.try_reserve(1).is_none());
+
+        drop(first);
+        assert_eq!(TEST_BUDGET.used.load(Ordering::Acquire), 40);
+        assert!(TEST_BUDGET.try_reserve(60).is_some());
+
+        // what is about to be given back counts as free
+        assert!(TEST_BUDGET.try_add(70, 40));
+        assert!(!TEST_BUDGET.try_add(70, 40));
+        TEST_BUDGET.used.fetch_sub(70, Ordering::AcqRel);
+
+        // streamed animations grow theirs as frames arrive
+        assert!(second.grow(60).is_ok());
+        assert!(second.grow(1).is_err());
+        drop(second);
+        assert_eq!(TEST_BUDGET.used.load(Ordering::Acquire), 0);
+    }
+
+    #[test]
+    fn only_reservations_nothing_else_holds_are_freed() {
+        static TEST_BUDGET: Budget = Budget::new();
+        let reservation = Arc::new(TEST_BUDGET.try_reserve(10).unwrap());
+        // like two wallpapers displaying the same animation
+        let holders = [Arc::clone(&reservation), Arc::clone(&reservation)];
+        assert_eq!(freed(holders.iter().cloned()), 0);
+        drop(reservation);
+        assert_eq!(freed(holders.iter().cloned()), 10);
+        assert_eq!(freed(holders[..1].iter().cloned()), 0);
+    }
+
+    #[test]
+    fn only_interpolation_and_blending_keep_frames_unpacked() {
+        assert_eq!(unpacked(10, false, false), 0);
+        assert_eq!(unpacked(10, true, false), 20);
+        assert_eq!(unpacked(10, false, true), 20);
+        assert_eq!(unpacked(10, true, true), 40);
+    }
+}
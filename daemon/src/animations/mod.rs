@@ -1,22 +1,36 @@
-use log::error;
+use log::{debug, error, info};
+use rustix::fd::OwnedFd;
 
 use std::{
-    sync::Arc,
+    num::{NonZeroU16, NonZeroU8},
+    sync::{Arc, Condvar, Mutex},
     thread::{self, Scope},
+    time::{Duration, Instant},
 };
 
 use utils::{
-    compression::Decompressor,
-    ipc::{self, Animation, Answer, BgImg, ImgReq},
+    compression::{BitPack, Decompressor},
+    ipc::{self, Animation, AnimationClock, Answer, BgImg, Blend, ColorAdjustment, ImgReq},
 };
 
 use crate::{
-    wallpaper::{AnimationToken, Wallpaper},
+    wallpaper::{AnimationToken, Damage, Wallpaper},
     wayland::globals,
 };
 
 mod anim_barrier;
+mod blue_noise;
+mod layer;
+pub(crate) mod memory;
+mod render;
+mod selftest;
+mod stream;
 mod transitions;
+mod warp;
+use layer::Layer;
+pub(crate) use memory::set_limit as set_max_animation_memory;
+pub(crate) use selftest::selftest;
+pub(crate) use stream::{load_frames, FrameStream};
 use transitions::Transition;
 
 use self::anim_barrier::ArcAnimBarrier;
@@ -24,24 +38,172 @@ use self::anim_barrier::ArcAnimBarrier;
 ///The default thread stack size of 2MiB is way too overkill for our purposes
 const STACK_SIZE: usize = 1 << 17; //128KiB
 
+/// Transition fps for outputs whose refresh rate we do not know, with `FPS_FROM_OUTPUT`
+const FALLBACK_FPS: u16 = 60;
+
+/// With `FPS_AUTO`, we lower the fps of outputs on which a transition would otherwise draw more
+/// pixels per second than on a 4K output at 60 Hz, since that is where transitions start costing
+/// too much CPU
+const AUTO_PIXEL_RATE: u64 = 3840 * 2160 * 60;
+/// ...but never below this, where transitions start to look choppy
+const AUTO_MIN_FPS: u16 = 30;
+/// The fps the default steps were chosen for. Automatic steps are scaled from them, so that
+/// pixels change as fast per second at any fps
+const AUTO_STEP_FPS: u16 = 30;
+
+/// Whether animations are paused, which `--idle-pause` does while the system is idle
+static PAUSED: Mutex<bool> = Mutex::new(false);
+static RESUMED: Condvar = Condvar::new();
+
+/// Pauses or resumes every animation. Paused animations stop right before their next frame.
+/// Transitions are never paused
+pub(crate) fn set_paused(paused: bool) {
+    *PAUSED.lock().unwrap() = paused;
+    RESUMED.notify_all();
+}
+
+/// Blocks while animations are paused. Returns whether we had to
+fn wait_while_paused() -> bool {
+    let paused = PAUSED.lock().unwrap();
+    if !*paused {
+        return false;
+    }
+    drop(RESUMED.wait_while(paused, |paused| *paused).unwrap());
+    true
+}
+
 pub(super) struct Animator {
     anim_barrier: ArcAnimBarrier,
+    /// The shared clock used by `AnimationClock::Locked` animations
+    epoch: Instant,
+    /// Ceiling for the frame rate of every transition and animation, set with `--max-fps`
+    max_fps: Option<NonZeroU16>,
 }
 
 impl Animator {
-    pub(super) fn new() -> Self {
+    pub(super) fn new(max_fps: Option<NonZeroU16>) -> Self {
         Self {
             anim_barrier: ArcAnimBarrier::new(),
+            epoch: Instant::now(),
+            max_fps,
+        }
+    }
+
+    /// Lowers the transition's fps to `max_fps`, if it is above it
+    fn cap_transition_fps(transition: &mut ipc::Transition, max_fps: Option<NonZeroU16>) {
+        if let Some(max_fps) = max_fps {
+            if transition.fps > max_fps.get() {
+                info!(
+                    "transition fps {} is above --max-fps, using {max_fps}",
+                    transition.fps
+                );
+                transition.fps = max_fps.get();
+            }
+        }
+    }
+
+    /// The fps of `FPS_AUTO` for an output with `refresh` rate and `dim`ensions
+    fn auto_fps(refresh: Option<u16>, dim: (u32, u32)) -> u16 {
+        let pixels = (dim.0 as u64 * dim.1 as u64).max(1);
+        let budget = (AUTO_PIXEL_RATE / pixels).clamp(AUTO_MIN_FPS as u64, u16::MAX as u64 - 1);
+        refresh.unwrap_or(FALLBACK_FPS).min(budget as u16)
+    }
+
+    /// Scales a step that was not given explicitly for `fps`
+    fn auto_step(step: NonZeroU8, fps: u16) -> NonZeroU8 {
+        let scaled = (step.get() as u32 * AUTO_STEP_FPS as u32 + fps as u32 / 2) / fps as u32;
+        NonZeroU8::new(scaled.clamp(1, 255) as u8).unwrap()
+    }
+
+    /// Chooses the fps of `FPS_FROM_OUTPUT` and `FPS_AUTO` transitions, and the automatic step,
+    /// for an output with `refresh` rate and `dim`ensions
+    fn resolve_transition(
+        transition: &ipc::Transition,
+        refresh: Option<u16>,
+        dim: (u32, u32),
+        max_fps: Option<NonZeroU16>,
+    ) -> ipc::Transition {
+        let mut transition = transition.clone();
+        match transition.fps {
+            ipc::Transition::FPS_FROM_OUTPUT => {
+                transition.fps = refresh.unwrap_or(FALLBACK_FPS);
+            }
+            ipc::Transition::FPS_AUTO => transition.fps = Self::auto_fps(refresh, dim),
+            _ => (),
+        }
+        Self::cap_transition_fps(&mut transition, max_fps);
+        if transition.auto_step {
+            transition.step = Self::auto_step(transition.step, transition.fps);
+        }
+        transition
+    }
+
+    /// Splits the transition for each image into one per group of outputs with the same fps and
+    /// step. If the transition has a fixed fps, that is just one group per image. Returns the
+    /// index of the image each group displays, its outputs and its transition
+    fn group_by_fps(
+        transition: &ipc::Transition,
+        wallpapers: Vec<Vec<Arc<Wallpaper>>>,
+        max_fps: Option<NonZeroU16>,
+    ) -> Vec<(usize, Vec<Arc<Wallpaper>>, ipc::Transition)> {
+        let mut groups = Vec::with_capacity(wallpapers.len());
+        for (i, wallpapers) in wallpapers.into_iter().enumerate() {
+            if !matches!(
+                transition.fps,
+                ipc::Transition::FPS_FROM_OUTPUT | ipc::Transition::FPS_AUTO
+            ) {
+                let transition = Self::resolve_transition(transition, None, (0, 0), max_fps);
+                groups.push((i, wallpapers, transition));
+                continue;
+            }
+            let mut by_fps: Vec<(ipc::Transition, Vec<Arc<Wallpaper>>)> = Vec::new();
+            for wallpaper in wallpapers {
+                let transition = Self::resolve_transition(
+                    transition,
+                    wallpaper.refresh_rate(),
+                    wallpaper.get_dimensions(),
+                    max_fps,
+                );
+                match by_fps
+                    .iter_mut()
+                    .find(|(t, _)| t.fps == transition.fps && t.step == transition.step)
+                {
+                    Some((_, group)) => group.push(wallpaper),
+                    None => by_fps.push((transition, vec![wallpaper])),
+                }
+            }
+            for (transition, wallpapers) in by_fps {
+                groups.push((i, wallpapers, transition));
+            }
+        }
+        groups
+    }
+
+    /// How long each frame must be displayed for, at least, to respect `max_fps`
+    fn min_frame_time(max_fps: Option<NonZeroU16>) -> Duration {
+        match max_fps {
+            Some(max_fps) => Duration::from_secs(1) / max_fps.get() as u32,
+            None => Duration::ZERO,
         }
     }
 
+    /// `playhead` is set to the frame the incoming animation must continue from, in case the
+    /// transition already started playing it. `source`, if any, is drawn before the transition
+    /// starts, so that it transitions from it. `debug` is whether the request was sent with
+    /// `swww img --debug`
+    #[allow(clippy::too_many_arguments)]
     fn spawn_transition_thread<'a, 'b>(
         scope: &'a Scope<'b, '_>,
         transition: &'b ipc::Transition,
         img: &'b [u8],
+        source: Option<&'b [u8]>,
         path: &'b str,
         dim: (u32, u32),
         wallpapers: &'b mut Vec<Arc<Wallpaper>>,
+        incoming: Option<Arc<Animation>>,
+        playhead: &'b mut usize,
+        debug: bool,
+        requested: Instant,
     ) where
         'a: 'b,
     {
@@ -49,11 +211,14 @@ impl Animator {
             .name("transition".to_string()) //Name our threads  for better log messages
             .stack_size(STACK_SIZE) //the default of 2MB is way too overkill for this
             .spawn_scoped(scope, move || {
+                crate::set_request_debug(debug);
                 if wallpapers.is_empty() {
+                    debug!("no output needs {path}, skipping its transition");
                     return;
                 }
                 for w in wallpapers.iter_mut() {
                     w.set_img_info(BgImg::Img(path.to_string()));
+                    w.set_transparent(false);
                 }
 
                 let expect = wallpapers[0].get_dimensions();
@@ -62,46 +227,282 @@ impl Animator {
                     error!("image has wrong dimensions! Expect {expect:?}, actual {dim:?}");
                     return;
                 }
+                match source {
+                    Some(source) if source.len() != img.len() => error!(
+                        "source image has {} bytes, but the image has {}. Transitioning from what \
+                         the outputs display instead",
+                        source.len(),
+                        img.len()
+                    ),
+                    Some(source) => {
+                        for w in wallpapers.iter() {
+                            w.canvas_change(|canvas| canvas.copy_from_slice(source));
+                        }
+                    }
+                    None => (),
+                }
 
-                Transition::new(wallpapers, dim, transition).execute(img);
+                let start = Instant::now();
+                *playhead =
+                    Transition::new(wallpapers, dim, transition, requested).execute(img, incoming);
+                debug!(
+                    "{:?} transition of {path} at {} fps took {:?}, for {} outputs",
+                    transition.transition_type,
+                    transition.fps,
+                    start.elapsed(),
+                    wallpapers.len()
+                );
+                // if the new image is animated, its animation thread registers itself later.
+                // Wallpapers that got another image in the meantime are no longer in the list
+                for w in wallpapers.iter() {
+                    w.set_playing_animation(None);
+                    if let Some(name) = w.name() {
+                        crate::hook::img_displayed(path, &name);
+                    }
+                }
             })
             .unwrap(); // builder only fails if name contains null bytes
     }
 
+    /// Adjusts the colors of `img`, whose pixels have `channels` bytes. The fourth one, if any,
+    /// is left alone
+    pub(crate) fn adjust_img(img: &[u8], channels: usize, table: &[u8; 256]) -> Box<[u8]> {
+        let mut adjusted: Box<[u8]> = img.into();
+        for pixel in adjusted.chunks_exact_mut(channels) {
+            for byte in pixel[..3].iter_mut() {
+                *byte = table[*byte as usize];
+            }
+        }
+        adjusted
+    }
+
+    /// Adjusts the colors of every frame of `animation`. Frames only store the pixels that
+    /// changed, so this only works if the image it starts from was adjusted the same way
+    fn adjust_animation(animation: Animation, table: &[u8; 256]) -> Animation {
+        let frames = animation
+            .animation
+            .iter()
+            .map(|(frame, duration)| (frame.map_pixel_bytes(|b| table[b as usize]), *duration))
+            .collect();
+        Animation {
+            animation: frames,
+            ..animation
+        }
+    }
+
+    /// `streams` replaces `animations` for requests whose animations are streamed, and must be
+    /// given to `load_frames` once the request is answered. `sources` are the images to
+    /// transition from, and `blends` the bases to composite the images over, if the request set
+    /// them
+    #[allow(clippy::too_many_arguments)]
     pub(super) fn transition(
         &mut self,
         transition: ipc::Transition,
         imgs: Box<[ImgReq]>,
+        sources: Option<Box<[ImgReq]>>,
+        blends: Option<Box<[Blend]>>,
         animations: Option<Box<[Animation]>>,
-        mut wallpapers: Vec<Vec<Arc<Wallpaper>>>,
+        streams: Option<Box<[Arc<FrameStream>]>>,
+        wallpapers: Vec<Vec<Arc<Wallpaper>>>,
+        adjustment: ColorAdjustment,
+        debug: bool,
     ) -> Answer {
+        let requested = Instant::now();
         let barrier = self.anim_barrier.clone();
+        let epoch = self.epoch;
+        let mut groups = Self::group_by_fps(&transition, wallpapers, self.max_fps);
+        let min_frame_time = Self::min_frame_time(self.max_fps);
+        if let Some(animations) = animations.as_ref() {
+            if animations
+                .iter()
+                .flat_map(|a| a.animation.iter())
+                .any(|(_, duration)| *duration < min_frame_time)
+            {
+                info!(
+                    "animation is faster than --max-fps, slowing it down to {} fps",
+                    self.max_fps.map_or(0, NonZeroU16::get)
+                );
+            }
+        }
         thread::Builder::new()
             .stack_size(1 << 15)
             .name("animation spawner".to_string())
             .spawn(move || {
+                crate::set_request_debug(debug);
+                // we do this here, so that large images don't hold up the main loop
+                let (adjusted, adjusted_sources, adjusted_bases, animations) = if adjustment
+                    .is_none()
+                {
+                    (None, None, None, animations)
+                } else {
+                    let start = Instant::now();
+                    let table = adjustment.table();
+                    let adjusted: Vec<Box<[u8]>> = imgs
+                        .iter()
+                        .map(|img| {
+                            Self::adjust_img(img.img.bytes(), img.format.channels().into(), &table)
+                        })
+                        .collect();
+                    let adjusted_sources: Option<Vec<Box<[u8]>>> =
+                        sources.as_ref().map(|sources| {
+                            sources
+                                .iter()
+                                .map(|source| {
+                                    let channels = source.format.channels().into();
+                                    Self::adjust_img(source.img.bytes(), channels, &table)
+                                })
+                                .collect()
+                        });
+                    let adjusted_bases: Option<Vec<Box<[u8]>>> = blends.as_ref().map(|blends| {
+                        blends
+                            .iter()
+                            .map(|blend| {
+                                let channels = blend.base.format.channels().into();
+                                Self::adjust_img(blend.base.img.bytes(), channels, &table)
+                            })
+                            .collect()
+                    });
+                    let animations = animations.map(|animations| {
+                        animations
+                            .into_vec()
+                            .into_iter()
+                            .map(|animation| Self::adjust_animation(animation, &table))
+                            .collect()
+                    });
+                    debug!(
+                        "adjusting the colors with {adjustment:?} took {:?}",
+                        start.elapsed()
+                    );
+                    (Some(adjusted), adjusted_sources, adjusted_bases, animations)
+                };
+                // shared with the wallpapers, so that a later live fade can keep playing them
+                let animations: Option<Vec<Arc<Animation>>> = animations
+                    .map(|animations| animations.into_vec().into_iter().map(Arc::new).collect());
+
+                // with `--blend`, each group keeps the image itself to play its animation on, and
+                // transitions into it composited over the base
+                let layers: Vec<Option<Layer>> = groups
+                    .iter()
+                    .map(|(i, _, _)| {
+                        let blend = blends.as_ref()?.get(*i)?;
+                        let img = match adjusted.as_ref() {
+                            Some(adjusted) => &adjusted[*i],
+                            None => imgs[*i].img.bytes(),
+                        };
+                        let base = match adjusted_bases.as_ref() {
+                            Some(adjusted) => &adjusted[*i],
+                            None => blend.base.img.bytes(),
+                        };
+                        if base.len() != img.len() {
+                            error!(
+                                "base image has {} bytes, but the image has {}. Displaying the \
+                                 image without blending it",
+                                base.len(),
+                                img.len()
+                            );
+                            return None;
+                        }
+                        Some(Layer::new(blend.mode, base, img))
+                    })
+                    .collect();
+                let composited: Vec<Option<Box<[u8]>>> = layers
+                    .iter()
+                    .map(|layer| layer.as_ref().map(Layer::composited))
+                    .collect();
+
+                let mut playheads = vec![0; groups.len()];
                 thread::scope(|s| {
-                    for (ImgReq { img, path, dim, .. }, wallpapers) in
-                        imgs.iter().zip(wallpapers.iter_mut())
+                    for (((i, wallpapers, transition), playhead), composited) in groups
+                        .iter_mut()
+                        .zip(playheads.iter_mut())
+                        .zip(composited.iter())
                     {
+                        let ImgReq { img, path, dim, .. } = &imgs[*i];
+                        let img = match (composited, adjusted.as_ref()) {
+                            (Some(composited), _) => composited,
+                            (None, Some(adjusted)) => &adjusted[*i],
+                            (None, None) => img.bytes(),
+                        };
+                        let source = match (adjusted_sources.as_ref(), sources.as_ref()) {
+                            (Some(adjusted), _) => Some(&adjusted[*i][..]),
+                            (None, Some(sources)) => Some(sources[*i].img.bytes()),
+                            (None, None) => None,
+                        };
+                        // a live fade would apply the frames onto the composited image
+                        let incoming = animations
+                            .as_ref()
+                            .filter(|_| composited.is_none())
+                            .and_then(|a| a.get(*i).cloned());
                         Self::spawn_transition_thread(
                             s,
-                            &transition,
-                            img.bytes(),
+                            transition,
+                            img,
+                            source,
                             path.str(),
                             *dim,
                             wallpapers,
+                            incoming,
+                            playhead,
+                            debug,
+                            requested,
                         );
                     }
                 });
                 drop(imgs);
-                #[allow(clippy::drop_non_drop)]
-                drop(transition);
+                drop(adjusted);
+                drop(sources);
+                drop(adjusted_sources);
+                drop(blends);
+                drop(adjusted_bases);
+                drop(composited);
                 if let Some(animations) = animations {
                     thread::scope(|s| {
-                        for (animation, wallpapers) in animations.iter().zip(wallpapers) {
-                            let barrier = barrier.clone();
-                            Self::spawn_animation_thread(s, animation, wallpapers, barrier);
+                        for (((i, wallpapers, _), playhead), layer) in
+                            groups.into_iter().zip(playheads).zip(layers)
+                        {
+                            let animation = &animations[i];
+                            // independent animations get a barrier of their own, so they never
+                            // wait for anyone else
+                            let (barrier, playhead, skip_to) = match animation.clock {
+                                // animations that stop must start from the beginning, or they
+                                // would not play every loop
+                                AnimationClock::Locked if animation.loop_count.is_some() => {
+                                    (barrier.clone(), playhead, playhead)
+                                }
+                                // we catch up to the shared clock from wherever the transition
+                                // left it, since a live fade may have played some of it already
+                                AnimationClock::Locked => (
+                                    barrier.clone(),
+                                    playhead,
+                                    Self::locked_playhead(animation, epoch),
+                                ),
+                                AnimationClock::Independent => {
+                                    (ArcAnimBarrier::new(), playhead, playhead)
+                                }
+                            };
+                            Self::spawn_animation_thread(
+                                s,
+                                animation,
+                                wallpapers,
+                                barrier,
+                                playhead,
+                                skip_to,
+                                min_frame_time,
+                                layer,
+                            );
+                        }
+                    });
+                } else if let Some(streams) = streams {
+                    thread::scope(|s| {
+                        for ((i, wallpapers, _), layer) in groups.into_iter().zip(layers) {
+                            Self::spawn_streamed_animation_thread(
+                                s,
+                                &streams[i],
+                                wallpapers,
+                                barrier.clone(),
+                                min_frame_time,
+                                layer,
+                            );
                         }
                     });
                 }
@@ -110,11 +511,150 @@ impl Animator {
         Answer::Ok
     }
 
+    /// `swww img --sync`: displays the images on the next frame, without a transition or
+    /// animations, and answers the client through `stream` once they are committed. Unlike
+    /// `transition`, this does not spawn a thread per group of outputs nor group them by fps: we
+    /// only copy the images, attach them once the compositor asks for the next frame, and commit
+    /// every output together.
+    ///
+    /// With `--debug`, both log how long after the request they committed their first frame, so
+    /// the two can be compared. Here, that is the time to copy the images plus the wait for the
+    /// compositor's frame callbacks, which we log apart, since only the former is up to us. With a
+    /// transition, the first frame also waits for the spawner and transition threads to start and
+    /// for the transition's own frame pacing, and it is only the first step of the transition:
+    /// the image itself lands once the transition's duration is up
+    pub(super) fn sync(
+        &mut self,
+        imgs: Box<[ImgReq]>,
+        wallpapers: Vec<Vec<Arc<Wallpaper>>>,
+        adjustment: ColorAdjustment,
+        debug: bool,
+        stream: OwnedFd,
+    ) {
+        let requested = Instant::now();
+        thread::Builder::new()
+            .stack_size(STACK_SIZE)
+            .name("sync".to_string())
+            .spawn(move || {
+                crate::set_request_debug(debug);
+                let mut waited = Duration::ZERO;
+                let table = (!adjustment.is_none()).then(|| adjustment.table());
+                let mut committed = Vec::new();
+                // (image path, output name) for `--img-hook`
+                let mut displayed = Vec::new();
+                for (img, wallpapers) in imgs.iter().zip(wallpapers) {
+                    let ImgReq { path, dim, .. } = img;
+                    let Some(expect) = wallpapers.first().map(|w| w.get_dimensions()) else {
+                        debug!("no output needs {}, skipping it", path.str());
+                        continue;
+                    };
+                    if *dim != expect {
+                        error!("image has wrong dimensions! Expect {expect:?}, actual {dim:?}");
+                        continue;
+                    }
+                    let adjusted = table.as_ref().map(|table| {
+                        Self::adjust_img(img.img.bytes(), img.format.channels().into(), table)
+                    });
+                    let new = adjusted.as_deref().unwrap_or(img.img.bytes());
+                    let width = dim.0 as usize;
+                    let channels = globals::pixel_format().channels() as usize;
+                    let mut damage = Damage::empty();
+                    for w in wallpapers.iter() {
+                        w.set_img_info(BgImg::Img(path.str().to_string()));
+                        w.set_transparent(false);
+                        w.set_playing_animation(None);
+                        let changed = w.canvas_change(|canvas| {
+                            Damage::copy_changes(canvas, new, width, channels)
+                        });
+                        damage.merge(&changed);
+                    }
+                    // this waits for the frame callback of each output
+                    let waiting = Instant::now();
+                    crate::wallpaper::attach_buffers_and_damange_surfaces(
+                        &wallpapers,
+                        Some(damage),
+                    );
+                    waited += waiting.elapsed();
+                    displayed.extend(
+                        wallpapers
+                            .iter()
+                            .filter_map(|w| w.name())
+                            .map(|name| (path.str(), name)),
+                    );
+                    committed.extend(wallpapers);
+                }
+                if !committed.is_empty() {
+                    crate::wallpaper::commit_wallpapers(&committed);
+                }
+                for (path, name) in displayed {
+                    crate::hook::img_displayed(path, &name);
+                }
+                debug!(
+                    "--sync image committed {:?} after the request ({waited:?} of it waiting for \
+                     the compositor's next frame), for {} outputs",
+                    requested.elapsed(),
+                    committed.len()
+                );
+                if let Err(e) = Answer::Ok.send(&stream) {
+                    error!("error sending answer to client: {e}");
+                }
+            })
+            .unwrap(); // builder only fails if name contains null bytes
+    }
+
+    /// Finds the frame the shared clock is currently at, so that every locked animation with the
+    /// same frames ends up in phase, regardless of when it was started
+    fn locked_playhead(animation: &Animation, epoch: Instant) -> usize {
+        let cycle = animation.cycle_duration().as_secs_f64();
+        if cycle <= 0.0 {
+            return 0;
+        }
+
+        let mut position = epoch.elapsed().as_secs_f64() % cycle;
+        for (i, (_, duration)) in animation.animation.iter().enumerate() {
+            let duration = duration.as_secs_f64();
+            if position < duration {
+                return i;
+            }
+            position -= duration;
+        }
+        0
+    }
+
+    /// How many frames to apply, starting from the one after `playhead`, for an animation of `len`
+    /// frames to play `loop_count` times, ending on its last frame
+    fn frames_to_play(len: usize, loop_count: Option<u32>, playhead: usize) -> usize {
+        match loop_count {
+            Some(loops) => (loops as usize * len).saturating_sub(playhead + 1),
+            None => usize::MAX,
+        }
+    }
+
+    /// Forgets `animation` once it stopped, so that a live fade does not start playing it again
+    fn finish_animation(wallpapers: &[Arc<Wallpaper>], tokens: &[AnimationToken]) {
+        for (wallpaper, token) in wallpapers.iter().zip(tokens) {
+            if wallpaper.has_animation_id(token) {
+                wallpaper.set_playing_animation(None);
+                // we are about to drop its frames
+                wallpaper.release_animation_memory();
+            }
+        }
+    }
+
+    /// Frames are deltas from the one before, so they only apply onto the frame the wallpapers
+    /// display, `playhead`. To start elsewhere, like locked animations do, we apply the frames up
+    /// to `skip_to` first, without displaying them. `layer` is set if the animation is blended
+    /// over a base image
+    #[allow(clippy::too_many_arguments)]
     fn spawn_animation_thread<'a, 'b>(
         scope: &'a Scope<'b, '_>,
-        animation: &'b Animation,
+        animation: &'b Arc<Animation>,
         mut wallpapers: Vec<Arc<Wallpaper>>,
         barrier: ArcAnimBarrier,
+        playhead: usize,
+        skip_to: usize,
+        min_frame_time: Duration,
+        mut layer: Option<Layer>,
     ) where
         'a: 'b,
     {
@@ -126,54 +666,607 @@ impl Animator {
                 if animation.animation.len() <= 1 || wallpapers.is_empty() {
                     return;
                 }
-                log::debug!("Starting animation");
+                log::debug!("Starting animation at frame {skip_to}");
 
                 let mut tokens: Vec<AnimationToken> = wallpapers
                     .iter()
                     .map(|w| w.create_animation_token())
                     .collect();
+                for w in wallpapers.iter() {
+                    w.set_playing_animation(Some((
+                        Arc::clone(animation),
+                        playhead,
+                        layer.is_some(),
+                    )));
+                }
+                Self::skip_frames(
+                    animation,
+                    playhead,
+                    skip_to,
+                    &mut wallpapers,
+                    &mut tokens,
+                    layer.as_mut(),
+                );
+                if wallpapers.is_empty() {
+                    return;
+                }
+                Self::play(
+                    animation,
+                    wallpapers,
+                    tokens,
+                    &barrier,
+                    skip_to,
+                    min_frame_time,
+                    layer.as_mut(),
+                );
+            })
+            .unwrap(); // builder only fails if name contains null bytes
+    }
 
-                let mut now = std::time::Instant::now();
+    /// Plays `animation` from `playhead` on, until it stops or every wallpaper displays something
+    /// else. If it is blended, its frames are applied to `layer` instead of the canvas
+    fn play(
+        animation: &Animation,
+        mut wallpapers: Vec<Arc<Wallpaper>>,
+        mut tokens: Vec<AnimationToken>,
+        barrier: &ArcAnimBarrier,
+        playhead: usize,
+        min_frame_time: Duration,
+        mut layer: Option<&mut Layer>,
+    ) {
+        if animation.interpolate {
+            Self::play_interpolated(
+                animation,
+                wallpapers,
+                tokens,
+                barrier,
+                playhead,
+                min_frame_time,
+                layer,
+            );
+            return;
+        }
+        let len = animation.animation.len();
+        let frames = Self::frames_to_play(len, animation.loop_count, playhead);
 
-                let mut decompressor = Decompressor::new();
-                for (frame, duration) in animation.animation.iter().cycle() {
-                    barrier.wait(duration.div_f32(2.0));
+        let mut now = Instant::now();
 
-                    let mut i = 0;
-                    while i < wallpapers.len() {
-                        let token = &tokens[i];
-                        if !wallpapers[i].has_animation_id(token) {
-                            wallpapers.swap_remove(i);
-                            tokens.swap_remove(i);
-                            continue;
-                        }
+        let mut decompressor = Decompressor::new();
+        for (frame_index, (frame, duration)) in animation
+            .animation
+            .iter()
+            .enumerate()
+            .cycle()
+            .skip(playhead)
+            .take(frames)
+        {
+            let (duration, _) = frame_times(&wallpapers, *duration, min_frame_time);
+            if wait_while_paused() {
+                now = Instant::now();
+            }
+            barrier.wait(duration.div_f32(2.0));
 
-                        let result = wallpapers[i].canvas_change(|canvas| {
-                            decompressor.decompress(frame, canvas, globals::pixel_format())
-                        });
+            Self::unpack_frame(
+                frame,
+                (frame_index + 1) % len,
+                &mut wallpapers,
+                &mut tokens,
+                &mut decompressor,
+                layer.as_deref_mut(),
+            );
+            if wallpapers.is_empty() {
+                return;
+            }
 
-                        if let Err(e) = result {
-                            error!("failed to unpack frame: {e}");
-                            wallpapers.swap_remove(i);
-                            tokens.swap_remove(i);
-                            continue;
-                        }
+            crate::wallpaper::attach_buffers_and_damange_surfaces(&wallpapers, None);
+            let timeout = duration.saturating_sub(now.elapsed());
+            crate::spin_sleep(timeout);
+            crate::wallpaper::commit_wallpapers(&wallpapers);
 
-                        i += 1;
-                    }
+            now = Instant::now();
+        }
+        Self::finish_animation(&wallpapers, &tokens);
+    }
 
+    /// Applies the frames from `playhead` up to `skip_to` onto the wallpapers (or onto `layer`),
+    /// without displaying them, so that they hold frame `skip_to`
+    fn skip_frames(
+        animation: &Animation,
+        playhead: usize,
+        skip_to: usize,
+        wallpapers: &mut Vec<Arc<Wallpaper>>,
+        tokens: &mut Vec<AnimationToken>,
+        mut layer: Option<&mut Layer>,
+    ) {
+        let len = animation.animation.len();
+        let skipped = frames_between(len, playhead, skip_to);
+        let mut decompressor = Decompressor::new();
+        for (n, (index, (frame, _))) in animation
+            .animation
+            .iter()
+            .enumerate()
+            .cycle()
+            .skip(playhead % len)
+            .take(skipped)
+            .enumerate()
+        {
+            // a layer only needs compositing onto the wallpapers once, after its last frame
+            if let Some(layer) = layer.as_deref_mut().filter(|_| n + 1 < skipped) {
+                if let Err(e) =
+                    decompressor.decompress(frame, layer.frame_mut(), globals::pixel_format())
+                {
+                    error!("failed to unpack frame: {e}");
+                    wallpapers.clear();
+                    tokens.clear();
+                    return;
+                }
+                continue;
+            }
+            Self::unpack_frame(
+                frame,
+                (index + 1) % len,
+                wallpapers,
+                tokens,
+                &mut decompressor,
+                layer.as_deref_mut(),
+            );
+        }
+    }
+
+    /// Unpacks `frame` onto every wallpaper still playing our animation, forgetting the others.
+    /// `next` is the index of the frame that comes after it. With a `layer`, we unpack it there
+    /// instead, and composite the result onto the wallpapers
+    fn unpack_frame(
+        frame: &BitPack,
+        next: usize,
+        wallpapers: &mut Vec<Arc<Wallpaper>>,
+        tokens: &mut Vec<AnimationToken>,
+        decompressor: &mut Decompressor,
+        layer: Option<&mut Layer>,
+    ) {
+        let layer = match layer {
+            Some(layer) => {
+                if let Err(e) =
+                    decompressor.decompress(frame, layer.frame_mut(), globals::pixel_format())
+                {
+                    error!("failed to unpack frame: {e}");
+                    wallpapers.clear();
+                    tokens.clear();
+                    return;
+                }
+                Some(&*layer)
+            }
+            None => None,
+        };
+
+        let mut i = 0;
+        while i < wallpapers.len() {
+            let token = &tokens[i];
+            if !wallpapers[i].has_animation_id(token) {
+                wallpapers.swap_remove(i);
+                tokens.swap_remove(i);
+                continue;
+            }
+
+            let result = wallpapers[i].canvas_change(|canvas| {
+                match layer {
+                    Some(layer) => layer.composite(canvas),
+                    None => decompressor.decompress(frame, canvas, globals::pixel_format())?,
+                }
+                // we are still holding the canvas, so that a live fade sees this frame and its
+                // index together
+                wallpapers[i].set_playing_frame(token, next);
+                Ok::<(), String>(())
+            });
+
+            if let Err(e) = result {
+                error!("failed to unpack frame: {e}");
+                wallpapers.swap_remove(i);
+                tokens.swap_remove(i);
+                continue;
+            }
+
+            i += 1;
+        }
+    }
+
+    /// Plays an animation whose frames are still arriving. We start once `stream::BUFFERED` of it
+    /// arrived, and wait whenever we run out of frames. Once they all arrived, we continue like
+    /// any other animation
+    fn spawn_streamed_animation_thread<'a, 'b>(
+        scope: &'a Scope<'b, '_>,
+        stream: &'b Arc<FrameStream>,
+        mut wallpapers: Vec<Arc<Wallpaper>>,
+        barrier: ArcAnimBarrier,
+        min_frame_time: Duration,
+        mut layer: Option<Layer>,
+    ) where
+        'a: 'b,
+    {
+        thread::Builder::new()
+            .name("animation".to_string()) //Name our threads  for better log messages
+            .stack_size(STACK_SIZE) //the default of 2MB is way too overkill for this
+            .spawn_scoped(scope, move || {
+                if wallpapers.is_empty() {
+                    return;
+                }
+                let mut tokens: Vec<AnimationToken> = wallpapers
+                    .iter()
+                    .map(|w| w.create_animation_token())
+                    .collect();
+                // independent animations get a barrier of their own, like in `transition`
+                let barrier = match stream.clock() {
+                    AnimationClock::Locked => barrier,
+                    AnimationClock::Independent => ArcAnimBarrier::new(),
+                };
+                stream.wait_buffered(stream::BUFFERED);
+                log::debug!("Starting streamed animation");
+
+                let mut now = Instant::now();
+                let mut decompressor = Decompressor::new();
+                let mut playhead = 0;
+                // we cannot know the frame that comes after one until they all arrived, so we do
+                // not record which one is playing until then
+                let animation = loop {
+                    if wait_while_paused() {
+                        now = Instant::now();
+                    }
+                    let duration = stream.with_frame(playhead, |frame, duration| {
+                        Self::unpack_frame(
+                            frame,
+                            0,
+                            &mut wallpapers,
+                            &mut tokens,
+                            &mut decompressor,
+                            layer.as_mut(),
+                        );
+                        duration
+                    });
+                    let duration = match duration {
+                        Ok(duration) => frame_times(&wallpapers, duration, min_frame_time).0,
+                        Err(Some(animation)) => break animation,
+                        Err(None) => return,
+                    };
                     if wallpapers.is_empty() {
                         return;
                     }
+                    // we do not wait while holding the frame, since that would keep the next ones
+                    // from arriving
+                    barrier.wait(duration.div_f32(2.0));
 
-                    crate::wallpaper::attach_buffers_and_damange_surfaces(&wallpapers);
+                    crate::wallpaper::attach_buffers_and_damange_surfaces(&wallpapers, None);
                     let timeout = duration.saturating_sub(now.elapsed());
                     crate::spin_sleep(timeout);
                     crate::wallpaper::commit_wallpapers(&wallpapers);
 
-                    now = std::time::Instant::now();
+                    now = Instant::now();
+                    playhead += 1;
+                };
+
+                if animation.animation.len() <= 1 {
+                    return;
+                }
+                log::debug!("Every frame arrived, continuing at frame {playhead}");
+                for w in wallpapers.iter() {
+                    w.set_playing_animation(Some((
+                        Arc::clone(&animation),
+                        playhead,
+                        layer.is_some(),
+                    )));
                 }
+                Self::play(
+                    &animation,
+                    wallpapers,
+                    tokens,
+                    &barrier,
+                    playhead,
+                    min_frame_time,
+                    layer.as_mut(),
+                );
             })
             .unwrap(); // builder only fails if name contains null bytes
     }
+
+    /// Plays `animation` blending each frame into the next one, instead of switching between them
+    /// at once. We draw a blended frame every time the compositor is ready for one (or at most at
+    /// `max_fps`), so this costs a full pass over the canvas on every display refresh.
+    ///
+    /// Each frame still shows up exactly (unblended) at the same time it would without
+    /// interpolation, so locked animations stay in phase with the others.
+    ///
+    /// With `animation.warp`, we move what moved between frames instead of only blending them (see
+    /// `warp`), until that turns out too slow for the rate we draw at.
+    fn play_interpolated(
+        animation: &Animation,
+        mut wallpapers: Vec<Arc<Wallpaper>>,
+        mut tokens: Vec<AnimationToken>,
+        barrier: &ArcAnimBarrier,
+        playhead: usize,
+        min_frame_time: Duration,
+        mut layer: Option<&mut Layer>,
+    ) {
+        let len = animation.animation.len();
+        let frames = Self::frames_to_play(len, animation.loop_count, playhead);
+        let mut decompressor = Decompressor::new();
+        // the frame we are blending from, and the one we are blending into
+        let mut current: Box<[u8]> = wallpapers[0].canvas_change(|canvas| (&*canvas).into());
+        let mut next = current.clone();
+        let width = wallpapers[0].get_dimensions().0 as usize;
+        let channels = globals::pixel_format().channels() as usize;
+        let mut warping = animation.warp;
+        let mut fallback = warp::Fallback::default();
+
+        let mut now = Instant::now();
+        // how long the compositor takes to ask for a new frame. We use it to blend each frame
+        // for the moment it will actually be displayed
+        let mut step = min_frame_time;
+
+        for (frame_index, (frame, duration)) in animation
+            .animation
+            .iter()
+            .enumerate()
+            .cycle()
+            .skip(playhead)
+            .take(frames)
+        {
+            let (duration, frame_time) = frame_times(&wallpapers, *duration, min_frame_time);
+            // blended frames are unpacked onto the layer, which we then composite into `next`
+            let result = match layer.as_deref_mut() {
+                Some(layer) => decompressor
+                    .decompress(frame, layer.frame_mut(), globals::pixel_format())
+                    .map(|()| layer.composite(&mut next)),
+                None => {
+                    next.copy_from_slice(&current);
+                    decompressor.decompress(frame, &mut next, globals::pixel_format())
+                }
+            };
+            if let Err(e) = result {
+                error!("failed to unpack frame: {e}");
+                return;
+            }
+            let flow = if warping {
+                let start = Instant::now();
+                let flow = warp::Flow::estimate(&current, &next, width, channels);
+                warping = fallback.keep_warping(start.elapsed(), duration);
+                Some(flow).filter(|flow| !flow.is_still())
+            } else {
+                None
+            };
+            if wait_while_paused() {
+                now = Instant::now();
+            }
+            barrier.wait(duration.div_f32(2.0));
+
+            let frame_start = now;
+            loop {
+                let weight = blend_weight(frame_start.elapsed() + step, duration);
+                let flow = flow.as_ref().filter(|_| warping && weight != 256);
+                let drawing = Instant::now();
+
+                let mut i = 0;
+                while i < wallpapers.len() {
+                    let token = &tokens[i];
+                    if !wallpapers[i].has_animation_id(token) {
+                        wallpapers.swap_remove(i);
+                        tokens.swap_remove(i);
+                        continue;
+                    }
+                    wallpapers[i].canvas_change(|canvas| {
+                        if weight == 256 {
+                            canvas.copy_from_slice(&next);
+                            wallpapers[i].set_playing_frame(token, (frame_index + 1) % len);
+                        } else if let Some(flow) = flow {
+                            flow.warp(canvas, &current, &next, weight);
+                        } else {
+                            blend(canvas, &current, &next, weight);
+                        }
+                    });
+                    i += 1;
+                }
+                if flow.is_some() {
+                    warping = fallback.keep_warping(drawing.elapsed(), step);
+                }
+
+                if wallpapers.is_empty() {
+                    return;
+                }
+
+                crate::wallpaper::attach_buffers_and_damange_surfaces(&wallpapers, None);
+                let timeout = if weight == 256 {
+                    duration.saturating_sub(frame_start.elapsed())
+                } else {
+                    frame_time.saturating_sub(now.elapsed())
+                };
+                crate::spin_sleep(timeout);
+                crate::wallpaper::commit_wallpapers(&wallpapers);
+
+                step = now.elapsed();
+                now = Instant::now();
+                if weight == 256 {
+                    break;
+                }
+            }
+            std::mem::swap(&mut current, &mut next);
+        }
+        Self::finish_animation(&wallpapers, &tokens);
+    }
+}
+
+/// How long a frame that lasts for `duration` is displayed on `wallpapers`, and the least time
+/// between two draws (of blended frames, with interpolation). See `boosted_frame_times`
+fn frame_times(
+    wallpapers: &[Arc<Wallpaper>],
+    duration: Duration,
+    min_frame_time: Duration,
+) -> (Duration, Duration) {
+    // they all display the same frames, so we go with the fastest boost any of them has
+    let boost = wallpapers.iter().filter_map(|w| w.fps_boost()).min();
+    boosted_frame_times(boost, duration, min_frame_time)
+}
+
+/// Normally, frames last for their own `duration`, but at least `min_frame_time` (from
+/// `--max-fps`), which also caps how often we draw. `swww boost-fps` replaces both with its own
+/// frame time, so that the animation plays at exactly the boosted rate, faster or slower than its
+/// own
+fn boosted_frame_times(
+    boost: Option<Duration>,
+    duration: Duration,
+    min_frame_time: Duration,
+) -> (Duration, Duration) {
+    match boost {
+        Some(frame_time) => (frame_time, frame_time),
+        None => (duration.max(min_frame_time), min_frame_time),
+    }
+}
+
+/// How many frames to apply to go from frame `from` to frame `to` of an animation of `len` frames,
+/// going around if `to` comes before `from`
+fn frames_between(len: usize, from: usize, to: usize) -> usize {
+    (to % len + len - from % len) % len
+}
+
+/// How far we are into a frame that lasts for `duration`, from 0 (still the current frame) to 256
+/// (the next frame)
+fn blend_weight(elapsed: Duration, duration: Duration) -> u16 {
+    if elapsed >= duration {
+        256
+    } else {
+        (256.0 * elapsed.as_secs_f64() / duration.as_secs_f64()) as u16
+    }
+}
+
+/// Writes the mix of `current` and `next` into `canvas`, where `weight` goes from 0 (all of
+/// `current`) to 256 (all of `next`)
+fn blend(canvas: &mut [u8], current: &[u8], next: &[u8], weight: u16) {
+    for ((pixel, old), new) in canvas.iter_mut().zip(current).zip(next) {
+        let x = *old as u16 * (256 - weight);
+        let y = *new as u16 * weight;
+        *pixel = ((x + y) >> 8) as u8;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn transition_fps_is_capped() {
+        let max_fps = NonZeroU16::new(60);
+
+        let mut transition = ipc::TransitionBuilder::default().fps(144).build();
+        Animator::cap_transition_fps(&mut transition, max_fps);
+        assert_eq!(transition.fps, 60);
+
+        let mut transition = ipc::TransitionBuilder::default().fps(30).build();
+        Animator::cap_transition_fps(&mut transition, max_fps);
+        assert_eq!(transition.fps, 30);
+
+        let mut transition = ipc::TransitionBuilder::default().fps(144).build();
+        Animator::cap_transition_fps(&mut transition, None);
+        assert_eq!(transition.fps, 144);
+    }
+
+    #[test]
+    fn automatic_fps_and_step_follow_each_output() {
+        // refresh rate, unless the output is too big for it
+        assert_eq!(Animator::auto_fps(Some(60), (1920, 1080)), 60);
+        assert_eq!(Animator::auto_fps(Some(144), (1920, 1080)), 144);
+        assert_eq!(Animator::auto_fps(Some(120), (3840, 2160)), 60);
+        assert_eq!(Animator::auto_fps(Some(60), (7680, 4320)), AUTO_MIN_FPS);
+        assert_eq!(Animator::auto_fps(None, (1920, 1080)), FALLBACK_FPS);
+
+        let step = |step| NonZeroU8::new(step).unwrap();
+        assert_eq!(Animator::auto_step(step(90), 30), step(90));
+        assert_eq!(Animator::auto_step(step(90), 60), step(45));
+        assert_eq!(Animator::auto_step(step(2), 144), step(1));
+        assert_eq!(Animator::auto_step(step(90), 10), step(255));
+
+        // explicit values win
+        let transition = ipc::TransitionBuilder::new(ipc::TransitionType::Wipe)
+            .fps(ipc::Transition::FPS_AUTO)
+            .step(step(10))
+            .build();
+        let t = Animator::resolve_transition(&transition, Some(120), (3840, 2160), None);
+        assert_eq!((t.fps, t.step), (60, step(10)));
+        let transition = ipc::TransitionBuilder::new(ipc::TransitionType::Wipe)
+            .fps(60)
+            .build();
+        let t = Animator::resolve_transition(&transition, Some(144), (1920, 1080), None);
+        assert_eq!((t.fps, t.step), (60, step(45)));
+    }
+
+    #[test]
+    fn boosts_override_the_rate_of_animations() {
+        let ms = Duration::from_millis;
+        // without a boost, frames keep their duration, capped by --max-fps
+        assert_eq!(
+            boosted_frame_times(None, ms(100), ms(20)),
+            (ms(100), ms(20))
+        );
+        assert_eq!(boosted_frame_times(None, ms(10), ms(20)), (ms(20), ms(20)));
+        // with one, they last for the boosted frame time, even without --max-fps
+        let boost = Some(ms(16));
+        assert_eq!(boosted_frame_times(boost, ms(100), ms(0)), (ms(16), ms(16)));
+        assert_eq!(boosted_frame_times(boost, ms(5), ms(20)), (ms(16), ms(16)));
+    }
+
+    #[test]
+    fn min_frame_time_matches_max_fps() {
+        assert_eq!(
+            Animator::min_frame_time(NonZeroU16::new(50)),
+            Duration::from_millis(20)
+        );
+        assert_eq!(Animator::min_frame_time(None), Duration::ZERO);
+    }
+
+    #[test]
+    fn finite_animations_stop_on_their_last_frame() {
+        let len = 4;
+        // a gif that plays once goes through every frame, and then holds the last one
+        let frames = Animator::frames_to_play(len, Some(1), 0);
+        let last = (0..len).cycle().take(frames).last();
+        assert_eq!(frames, len - 1);
+        assert_eq!(
+            last.map(|frame_index| (frame_index + 1) % len),
+            Some(len - 1)
+        );
+
+        // the transition may have played some frames already
+        let frames = Animator::frames_to_play(len, Some(2), 1);
+        let last = (0..len).cycle().skip(1).take(frames).last();
+        assert_eq!(
+            last.map(|frame_index| (frame_index + 1) % len),
+            Some(len - 1)
+        );
+
+        assert_eq!(Animator::frames_to_play(len, None, 2), usize::MAX);
+    }
+
+    #[test]
+    fn skipping_frames_goes_around_the_animation() {
+        assert_eq!(frames_between(5, 0, 0), 0);
+        assert_eq!(frames_between(5, 0, 3), 3);
+        assert_eq!(frames_between(5, 3, 1), 3);
+        assert_eq!(frames_between(5, 4, 4), 0);
+    }
+
+    #[test]
+    fn blended_frames_follow_the_frame_time() {
+        let duration = Duration::from_millis(100);
+        assert_eq!(blend_weight(Duration::ZERO, duration), 0);
+        assert_eq!(blend_weight(Duration::from_millis(25), duration), 64);
+        assert_eq!(blend_weight(Duration::from_millis(50), duration), 128);
+        assert_eq!(blend_weight(duration, duration), 256);
+        assert_eq!(blend_weight(Duration::from_millis(150), duration), 256);
+
+        let current = [0, 100, 255, 40];
+        let next = [255, 200, 0, 40];
+        let mut canvas = [0; 4];
+        blend(&mut canvas, &current, &next, 0);
+        assert_eq!(canvas, current);
+        blend(&mut canvas, &current, &next, 128);
+        assert_eq!(canvas, [127, 150, 127, 40]);
+        blend(&mut canvas, &current, &next, 256);
+        assert_eq!(canvas, next);
+    }
 }
@@ -0,0 +1,577 @@
+//! Draws the frames of transitions, knowing nothing about wayland or about time. `transitions.rs`
+//! decides when to draw a frame and how far into the transition it is, and this draws it, which
+//! lets us test (and profile) every transition without a compositor.
+
+use utils::ipc::{self, Position, TransitionType};
+
+use crate::wallpaper::Damage;
+
+use super::blue_noise;
+
+pub(super) struct Renderer {
+    transition_type: TransitionType,
+    width: usize,
+    height: usize,
+    channels: usize,
+    angle: f64,
+    pos: Position,
+    wave: (f32, f32),
+    invert_x: bool,
+    invert_y: bool,
+    tile_size: usize,
+}
+
+/// How much of the transition the 'tiles' farthest from `pos` wait before they start revealing
+/// the new image. The rest of it is how long each tile takes
+const TILES_STAGGER: f64 = 0.5;
+
+impl Renderer {
+    pub(super) fn new(
+        transition: &ipc::Transition,
+        dimensions: (u32, u32),
+        channels: usize,
+    ) -> Self {
+        Self {
+            transition_type: transition.transition_type,
+            width: dimensions.0 as usize,
+            height: dimensions.1 as usize,
+            channels,
+            angle: transition.angle,
+            pos: transition.pos.clone(),
+            wave: transition.wave,
+            invert_x: transition.invert_x,
+            invert_y: transition.invert_y,
+            tile_size: transition.tile_size.get() as usize,
+        }
+    }
+
+    /// Draws the frame at `progress` onto `canvas`, which holds the previous frame. `progress` goes
+    /// from 0 (the transition just started) to 1 (it is done), already eased by the transition's
+    /// bezier curve.
+    ///
+    /// Most transitions move the pixels they reach towards `new` by at most `step` per frame, so
+    /// they depend on the previous frames too. `fade`, `dissolve`, `slide` and `tiles` only depend on
+    /// `progress`: `fade` blends from `old`, the image displayed when the transition started, and
+    /// `slide` pushes it away. The other transitions ignore it.
+    ///
+    /// Returns the region that changed, or `None` if it could be anywhere.
+    pub(super) fn render(
+        &self,
+        canvas: &mut [u8],
+        old: &[u8],
+        new: &[u8],
+        progress: f64,
+        step: u8,
+    ) -> Option<Damage> {
+        match self.transition_type {
+            TransitionType::None => canvas.copy_from_slice(new),
+            TransitionType::Simple => {
+                for (old, new) in canvas.iter_mut().zip(new) {
+                    change_byte(step, old, new);
+                }
+            }
+            TransitionType::Fade => {
+                let weight = (256.0 * progress).trunc().clamp(0.0, 256.0) as u16;
+                super::blend(canvas, old, new, weight);
+            }
+            TransitionType::Dissolve => self.dissolve(canvas, new, progress),
+            TransitionType::Slide => self.slide(canvas, old, new, progress),
+            TransitionType::Wipe => return Some(self.wipe(canvas, new, progress, step)),
+            TransitionType::Wave => return Some(self.wave(canvas, new, progress, step)),
+            TransitionType::Grow => return Some(self.grow(canvas, new, progress, step)),
+            TransitionType::Outer => return Some(self.outer(canvas, new, progress, step)),
+            TransitionType::Tiles => return Some(self.tiles(canvas, new, progress)),
+        }
+        None
+    }
+
+    fn dissolve(&self, canvas: &mut [u8], new: &[u8], progress: f64) {
+        let noise = blue_noise::threshold_map();
+        let (width, height, channels) = (self.width, self.height, self.channels);
+        let stride = width * channels;
+        let progress = (256.0 * progress).clamp(0.0, 256.0) as u16;
+        for line in 0..height {
+            let noise_line =
+                &noise[(line % blue_noise::SIZE) * blue_noise::SIZE..][..blue_noise::SIZE];
+            for col in 0..width {
+                if (noise_line[col % blue_noise::SIZE] as u16) < progress {
+                    let i = line * stride + col * channels;
+                    canvas[i..i + channels].copy_from_slice(&new[i..i + channels]);
+                }
+            }
+        }
+    }
+
+    /// Splits the screen into square tiles, each revealing `new` from its center outwards. The
+    /// tiles closer to `pos` start first, so the new image spreads out from there
+    fn tiles(&self, canvas: &mut [u8], new: &[u8], progress: f64) -> Damage {
+        let (width, height, channels) = (self.width, self.height, self.channels);
+        let stride = width * channels;
+        let tile = self.tile_size;
+        let ((center_x, center_y), max_dist) = self.circle();
+
+        let mut damage = Damage::empty();
+        for tile_y in (0..height).step_by(tile) {
+            let tile_h = tile.min(height - tile_y);
+            for tile_x in (0..width).step_by(tile) {
+                let tile_w = tile.min(width - tile_x);
+                let dist = f64::hypot(
+                    (tile_x + tile_w / 2) as f64 - center_x as f64,
+                    (tile_y + tile_h / 2) as f64 - center_y as f64,
+                ) / max_dist as f64;
+                let delay = dist.min(1.0) * TILES_STAGGER;
+                let local = ((progress - delay) / (1.0 - TILES_STAGGER)).clamp(0.0, 1.0);
+                let w = (tile_w as f64 * local).round() as usize;
+                let h = (tile_h as f64 * local).round() as usize;
+                if w == 0 || h == 0 {
+                    continue;
+                }
+                let col_begin = tile_x + (tile_w - w) / 2;
+                let line_begin = tile_y + (tile_h - h) / 2;
+                for line in line_begin..line_begin + h {
+                    let i = line * stride + col_begin * channels;
+                    canvas[i..i + w * channels].copy_from_slice(&new[i..i + w * channels]);
+                    damage.add_span(line, col_begin, col_begin + w);
+                }
+            }
+        }
+        damage
+    }
+
+    /// How far (in pixels, as (x, y)) the images move by the end of `slide`: just enough for the
+    /// old one to leave the screen, in the direction of the angle
+    fn slide_distance(&self) -> (isize, isize) {
+        let (width, height) = (self.width as f64, self.height as f64);
+        // '0' goes right to left and '90' top to bottom, like `wipe`
+        let (sin, cos) = self.angle.to_radians().sin_cos();
+        let (dx, dy) = (-cos, sin);
+        let scale_x = if dx.abs() > 1e-9 {
+            width / dx.abs()
+        } else {
+            f64::INFINITY
+        };
+        let scale_y = if dy.abs() > 1e-9 {
+            height / dy.abs()
+        } else {
+            f64::INFINITY
+        };
+        let scale = scale_x.min(scale_y);
+        ((dx * scale).round() as isize, (dy * scale).round() as isize)
+    }
+
+    /// Draws `old` moved by `progress` times the slide distance, and `new` right behind it, so
+    /// that it is back in place at the end. With diagonal angles, the images do not cover the
+    /// corner they uncover, so `new` wraps around into it
+    fn slide(&self, canvas: &mut [u8], old: &[u8], new: &[u8], progress: f64) {
+        let (width, height, channels) = (self.width, self.height, self.channels);
+        let stride = width * channels;
+        let (distance_x, distance_y) = self.slide_distance();
+        let shift_x = (distance_x as f64 * progress).round() as isize;
+        let shift_y = (distance_y as f64 * progress).round() as isize;
+
+        // the columns of the screen `old` still covers
+        let old_begin = shift_x.clamp(0, width as isize) as usize;
+        let old_end = (shift_x + width as isize).clamp(0, width as isize) as usize;
+        for line in 0..height {
+            let dst = &mut canvas[line * stride..][..stride];
+            let old_line = line as isize - shift_y;
+            let new_line = (old_line + distance_y).rem_euclid(height as isize) as usize;
+            let new = &new[new_line * stride..][..stride];
+            let new_shift = distance_x - shift_x;
+            if (0..height as isize).contains(&old_line) {
+                let old = &old[old_line as usize * stride..][..stride];
+                copy_wrapped(dst, old, old_begin..old_end, -shift_x, channels);
+                copy_wrapped(dst, new, 0..old_begin, new_shift, channels);
+                copy_wrapped(dst, new, old_end..width, new_shift, channels);
+            } else {
+                copy_wrapped(dst, new, 0..width, new_shift, channels);
+            }
+        }
+    }
+
+    /// The radius of the circle around the screen, and how far the line of `wipe` and `wave` has
+    /// to go, as (begin, end)
+    fn line_offsets(&self) -> (f64, (f64, f64)) {
+        let (width, height) = (self.width as f64, self.height as f64);
+        let circle_radius = (width.powi(2) + height.powi(2)).sqrt() / 2.0;
+        let (sin, cos) = self.angle.to_radians().sin_cos();
+        let begin = (sin.abs() * width + cos.abs() * height) * 2.0;
+        let end = circle_radius.powi(2) * 2.0;
+        (circle_radius, (begin, end))
+    }
+
+    fn wipe(&self, canvas: &mut [u8], new: &[u8], progress: f64, step: u8) -> Damage {
+        let (width, height, channels) = (self.width, self.height, self.channels);
+        let stride = width * channels;
+        let center = (width / 2, height / 2);
+        let (circle_radius, (begin, end)) = self.line_offsets();
+        let offset = begin + (end - begin) * progress;
+
+        let angle = self.angle.to_radians();
+        let a = circle_radius * angle.cos();
+        let b = circle_radius * angle.sin();
+
+        let mut damage = Damage::empty();
+        // line formula: (x-h)*a + (y-k)*b + C = r^2
+        // https://www.desmos.com/calculator/vpvzk12yar
+        for line in 0..height {
+            let y = ((height - line) as f64 - center.1 as f64) * b;
+            let x = (circle_radius.powi(2) - y - offset) / a + center.0 as f64;
+            let x = x.min(width as f64);
+            let (col_begin, col_end) = if a.is_sign_negative() {
+                (0usize, x as usize * channels)
+            } else {
+                (x as usize * channels, stride)
+            };
+            damage.add_span(line, col_begin / channels, col_end / channels);
+            for col in col_begin..col_end {
+                let old = unsafe { canvas.get_unchecked_mut(line * stride + col) };
+                let new = unsafe { new.get_unchecked(line * stride + col) };
+                change_byte(step, old, new);
+            }
+        }
+        damage
+    }
+
+    fn wave(&self, canvas: &mut [u8], new: &[u8], progress: f64, step: u8) -> Damage {
+        let (width, height, channels) = (self.width, self.height, self.channels);
+        let stride = width * channels;
+        let center = (width / 2, height / 2);
+        let (scale_x, scale_y) = (self.wave.0 as f64, self.wave.1 as f64);
+        let (circle_radius, (begin, end)) = self.line_offsets();
+        // the line goes past the far corner by the waves' height, so that their valleys reach it
+        let end = end + scale_y.abs() * circle_radius;
+        let offset = begin + (end - begin) * progress;
+
+        let (sin, cos) = self.angle.to_radians().sin_cos();
+        let a = circle_radius * cos;
+        let b = circle_radius * sin;
+
+        // graph: https://www.desmos.com/calculator/wunde042es
+        //
+        // checks if a pixel is to the left or right of the line
+        let is_low = |x: f64, y: f64| {
+            let x = x - center.0 as f64;
+            let y = y - center.1 as f64;
+
+            let lhs = y * sin - x * cos;
+
+            let f = ((x * sin + y * cos) / scale_x).sin() * scale_y;
+            let rhs = f - circle_radius + offset / circle_radius;
+            lhs <= rhs
+        };
+
+        let mut damage = Damage::empty();
+        // divide in 3 sections: the one we know will not be drawn to, the one we know WILL be
+        // drawn to, and the one we need to do a more expensive check on. We do this by creating 2
+        // lines: the first tangential to the wave's peaks, the second to its valeys. In-between is
+        // where we have to do the more expensive checks
+        for line in 0..height {
+            let y = ((height - line) as f64 - center.1 as f64 - scale_y * sin) * b;
+            let x = (circle_radius.powi(2) - y - offset) / a + center.0 as f64 + scale_y * cos;
+            let x = x.min(width as f64);
+            let (col_begin, col_end) = if a.is_sign_negative() {
+                (0usize, x as usize * channels)
+            } else {
+                (x as usize * channels, stride)
+            };
+            damage.add_span(line, col_begin / channels, col_end / channels);
+            for col in col_begin..col_end {
+                let old = unsafe { canvas.get_unchecked_mut(line * stride + col) };
+                let new = unsafe { new.get_unchecked(line * stride + col) };
+                change_byte(step, old, new);
+            }
+            let old_x = x;
+            let y = ((height - line) as f64 - center.1 as f64 + scale_y * sin) * b;
+            let x = (circle_radius.powi(2) - y - offset) / a + center.0 as f64 - scale_y * cos;
+            let x = x.min(width as f64);
+            let (col_begin, col_end) = if old_x < x {
+                (old_x as usize, x as usize)
+            } else {
+                (x as usize, old_x as usize)
+            };
+            damage.add_span(line, col_begin, col_end);
+            for col in col_begin..col_end {
+                if is_low(col as f64, line as f64) {
+                    let i = line * stride + col * channels;
+                    for j in 0..channels {
+                        let old = unsafe { canvas.get_unchecked_mut(i + j) };
+                        let new = unsafe { new.get_unchecked(i + j) };
+                        change_byte(step, old, new);
+                    }
+                }
+            }
+        }
+        damage
+    }
+
+    /// The center of the circle of `grow` and `outer` (and where `tiles` start), and the distance from it to the farthest
+    /// corner of the screen
+    fn circle(&self) -> ((usize, usize), f32) {
+        let (width, height) = (self.width as f32, self.height as f32);
+        let (center_x, center_y) = self.pos.to_pixel(
+            (self.width as u32, self.height as u32),
+            self.invert_x,
+            self.invert_y,
+        );
+        let mut x = center_x;
+        let mut y = center_y;
+        if x < width / 2.0 {
+            x = width - 1.0 - x;
+        }
+        if y < height / 2.0 {
+            y = height - 1.0 - y;
+        }
+        (
+            (center_x as usize, center_y as usize),
+            f32::sqrt(x.powi(2) + y.powi(2)),
+        )
+    }
+
+    fn grow(&self, canvas: &mut [u8], new: &[u8], progress: f64, step: u8) -> Damage {
+        let (width, height, channels) = (self.width, self.height, self.channels);
+        let stride = width * channels;
+        let ((center_x, center_y), dist_end) = self.circle();
+        let dist_center = dist_end * progress as f32;
+
+        let mut damage = Damage::empty();
+        let line_begin = center_y.saturating_sub(dist_center as usize);
+        let line_end = height.min(center_y + dist_center as usize);
+
+        // to plot half a circle with radius r, we do sqrt(r^2 - x^2)
+        for line in line_begin..line_end {
+            let offset =
+                (dist_center.powi(2) - (center_y as f32 - line as f32).powi(2)).sqrt() as usize;
+            let col_begin = center_x.saturating_sub(offset) * channels;
+            let col_end = width.min(center_x + offset) * channels;
+            damage.add_span(line, col_begin / channels, col_end / channels);
+            for col in col_begin..col_end {
+                let old = unsafe { canvas.get_unchecked_mut(line * stride + col) };
+                let new = unsafe { new.get_unchecked(line * stride + col) };
+                change_byte(step, old, new);
+            }
+        }
+        damage
+    }
+
+    fn outer(&self, canvas: &mut [u8], new: &[u8], progress: f64, step: u8) -> Damage {
+        let (width, height, channels) = (self.width, self.height, self.channels);
+        let stride = width * channels;
+        let ((center_x, center_y), dist_begin) = self.circle();
+        let dist_center = dist_begin * (1.0 - progress as f32);
+
+        let mut damage = Damage::empty();
+        // to plot half a circle with radius r, we do sqrt(r^2 - x^2)
+        for line in 0..height {
+            let offset =
+                (dist_center.powi(2) - (center_y as f32 - line as f32).powi(2)).sqrt() as usize;
+            let col_begin = center_x.saturating_sub(offset) * channels;
+            let col_end = width.min(center_x + offset) * channels;
+            damage.add_span(line, 0, col_begin / channels);
+            damage.add_span(line, col_end / channels, width);
+            for col in 0..col_begin {
+                let old = unsafe { canvas.get_unchecked_mut(line * stride + col) };
+                let new = unsafe { new.get_unchecked(line * stride + col) };
+                change_byte(step, old, new);
+            }
+            for col in col_end..stride {
+                let old = unsafe { canvas.get_unchecked_mut(line * stride + col) };
+                let new = unsafe { new.get_unchecked(line * stride + col) };
+                change_byte(step, old, new);
+            }
+        }
+        damage
+    }
+}
+
+/// Copies the pixels of `src` into the `cols` of `dst`, taking each one from `shift` columns to
+/// its right, and wrapping around the line's end
+fn copy_wrapped(
+    dst: &mut [u8],
+    src: &[u8],
+    cols: std::ops::Range<usize>,
+    shift: isize,
+    channels: usize,
+) {
+    let width = (dst.len() / channels) as isize;
+    let mut col = cols.start;
+    while col < cols.end {
+        let src_col = (col as isize + shift).rem_euclid(width) as usize;
+        let len = (cols.end - col).min(width as usize - src_col);
+        dst[col * channels..(col + len) * channels]
+            .copy_from_slice(&src[src_col * channels..(src_col + len) * channels]);
+        col += len;
+    }
+}
+
+#[inline(always)]
+fn change_byte(step: u8, old: &mut u8, new: &u8) {
+    if old.abs_diff(*new) < step {
+        *old = *new;
+    } else if *old > *new {
+        *old -= step;
+    } else {
+        *old += step;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use utils::ipc::TransitionBuilder;
+
+    const WIDTH: usize = 64;
+    const HEIGHT: usize = 64;
+    const CHANNELS: usize = 3;
+
+    /// Draws a single frame at `progress` over a black image, with a step big enough to turn the
+    /// pixels it reaches white right away
+    fn render(transition: &ipc::Transition, progress: f64) -> (Vec<u8>, Option<Damage>) {
+        let renderer = Renderer::new(transition, (WIDTH as u32, HEIGHT as u32), CHANNELS);
+        let old = vec![0u8; WIDTH * HEIGHT * CHANNELS];
+        let new = vec![255u8; WIDTH * HEIGHT * CHANNELS];
+        let mut canvas = old.clone();
+        let damage = renderer.render(&mut canvas, &old, &new, progress, 255);
+        (canvas, damage)
+    }
+
+    fn white_pixels(canvas: &[u8]) -> usize {
+        canvas
+            .chunks_exact(CHANNELS)
+            .filter(|pixel| pixel.iter().all(|&b| b == 255))
+            .count()
+    }
+
+    /// Whether the pixel at `(x, y)`, counting from the top left, is white
+    fn is_white(canvas: &[u8], x: usize, y: usize) -> bool {
+        let i = (y * WIDTH + x) * CHANNELS;
+        canvas[i..i + CHANNELS].iter().all(|&b| b == 255)
+    }
+
+    #[test]
+    fn none_and_simple_do_not_depend_on_progress() {
+        let none = TransitionBuilder::new(TransitionType::None).build();
+        let simple = TransitionBuilder::new(TransitionType::Simple).build();
+        for progress in [0.0, 0.5, 1.0] {
+            assert_eq!(white_pixels(&render(&none, progress).0), WIDTH * HEIGHT);
+            assert_eq!(white_pixels(&render(&simple, progress).0), WIDTH * HEIGHT);
+        }
+
+        // simple moves every byte by the step, no matter the progress
+        let renderer = Renderer::new(&simple, (WIDTH as u32, HEIGHT as u32), CHANNELS);
+        let mut canvas = vec![0u8; WIDTH * HEIGHT * CHANNELS];
+        let new = vec![255u8; WIDTH * HEIGHT * CHANNELS];
+        assert!(renderer.render(&mut canvas, &[], &new, 0.0, 2).is_none());
+        assert!(canvas.iter().all(|&b| b == 2));
+    }
+
+    #[test]
+    fn fade_blends_by_progress() {
+        let fade = TransitionBuilder::new(TransitionType::Fade).build();
+        assert!(render(&fade, 0.0).0.iter().all(|&b| b == 0));
+        assert!(render(&fade, 0.5).0.iter().all(|&b| b == 127));
+        assert!(render(&fade, 1.0).0.iter().all(|&b| b == 255));
+    }
+
+    #[test]
+    fn dissolve_switches_pixels_by_progress() {
+        let dissolve = TransitionBuilder::new(TransitionType::Dissolve).build();
+        assert_eq!(white_pixels(&render(&dissolve, 0.0).0), 0);
+        // the blue noise thresholds are evenly distributed
+        assert_eq!(white_pixels(&render(&dissolve, 0.5).0), WIDTH * HEIGHT / 2);
+        assert_eq!(white_pixels(&render(&dissolve, 1.0).0), WIDTH * HEIGHT);
+    }
+
+    #[test]
+    fn tiles_spread_out_from_pos() {
+        let tiles = TransitionBuilder::new(TransitionType::Tiles)
+            .tile_size(std::num::NonZeroU16::new(16).unwrap())
+            .build();
+        let (canvas, damage) = render(&tiles, 0.0);
+        assert_eq!(white_pixels(&canvas), 0);
+        assert!(damage.unwrap().is_empty());
+
+        // the tiles in the middle are further along than the ones in the corners
+        let (canvas, _) = render(&tiles, 0.5);
+        assert!(is_white(&canvas, WIDTH / 2 + 8, HEIGHT / 2 + 8));
+        assert!(!is_white(&canvas, 0, 0));
+        assert!(!is_white(&canvas, WIDTH - 1, HEIGHT - 1));
+
+        assert_eq!(white_pixels(&render(&tiles, 1.0).0), WIDTH * HEIGHT);
+    }
+
+    #[test]
+    fn wipe_and_wave_sweep_across_the_screen() {
+        for transition_type in [TransitionType::Wipe, TransitionType::Wave] {
+            let transition = TransitionBuilder::new(transition_type).angle(0.0).build();
+            let (canvas, damage) = render(&transition, 0.0);
+            assert!(white_pixels(&canvas) < WIDTH * HEIGHT / 20);
+            assert!(damage.is_some());
+
+            // at an angle of 0, it goes from right to left
+            let (canvas, _) = render(&transition, 0.5);
+            assert!(is_white(&canvas, WIDTH - 1, HEIGHT / 2));
+            assert!(!is_white(&canvas, 0, HEIGHT / 2));
+
+            assert_eq!(white_pixels(&render(&transition, 1.0).0), WIDTH * HEIGHT);
+        }
+    }
+
+    #[test]
+    fn slide_pushes_the_old_image_away() {
+        // at an angle of 0, the new image comes in from the right
+        let slide = TransitionBuilder::new(TransitionType::Slide)
+            .angle(0.0)
+            .build();
+        assert_eq!(white_pixels(&render(&slide, 0.0).0), 0);
+        let (canvas, damage) = render(&slide, 0.5);
+        assert!(damage.is_none());
+        assert_eq!(white_pixels(&canvas), WIDTH * HEIGHT / 2);
+        assert!(is_white(&canvas, WIDTH - 1, 0) && !is_white(&canvas, 0, 0));
+        assert_eq!(white_pixels(&render(&slide, 1.0).0), WIDTH * HEIGHT);
+
+        // diagonals start and end just the same
+        let diagonal = TransitionBuilder::new(TransitionType::Slide)
+            .angle(135.0)
+            .build();
+        assert_eq!(white_pixels(&render(&diagonal, 0.0).0), 0);
+        assert_eq!(white_pixels(&render(&diagonal, 1.0).0), WIDTH * HEIGHT);
+
+        // at 90, the new image comes down from the top, bottom line first
+        let top = TransitionBuilder::new(TransitionType::Slide)
+            .angle(90.0)
+            .build();
+        let renderer = Renderer::new(&top, (WIDTH as u32, HEIGHT as u32), CHANNELS);
+        let old = vec![0u8; WIDTH * HEIGHT * CHANNELS];
+        let new: Vec<u8> = (0..HEIGHT)
+            .flat_map(|line| [line as u8; WIDTH * CHANNELS])
+            .collect();
+        let mut canvas = old.clone();
+        renderer.render(&mut canvas, &old, &new, 0.25, 0);
+        let line = |canvas: &[u8], i: usize| canvas[i * WIDTH * CHANNELS];
+        assert_eq!(line(&canvas, 0), (HEIGHT * 3 / 4) as u8);
+        assert_eq!(line(&canvas, HEIGHT / 4 - 1), (HEIGHT - 1) as u8);
+        assert_eq!(line(&canvas, HEIGHT / 4), 0);
+    }
+
+    #[test]
+    fn grow_and_outer_follow_a_circle() {
+        let grow = TransitionBuilder::new(TransitionType::Grow).build();
+        let (canvas, damage) = render(&grow, 0.0);
+        assert_eq!(white_pixels(&canvas), 0);
+        assert!(damage.unwrap().is_empty());
+        // it grows from the center
+        let (canvas, _) = render(&grow, 0.5);
+        assert!(is_white(&canvas, WIDTH / 2, HEIGHT / 2));
+        assert!(!is_white(&canvas, 0, 0));
+        assert!(white_pixels(&render(&grow, 1.0).0) >= WIDTH * HEIGHT - 4);
+
+        let outer = TransitionBuilder::new(TransitionType::Outer).build();
+        assert!(white_pixels(&render(&outer, 0.0).0) <= 4);
+        // it closes in on the center
+        let (canvas, _) = render(&outer, 0.5);
+        assert!(!is_white(&canvas, WIDTH / 2, HEIGHT / 2));
+        assert!(is_white(&canvas, 0, 0));
+        assert_eq!(white_pixels(&render(&outer, 1.0).0), WIDTH * HEIGHT);
+    }
+}
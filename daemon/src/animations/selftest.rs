@@ -0,0 +1,154 @@
+//! `swww selftest` (`swww-daemon --selftest`): draws every transition offscreen, from one synthetic
+//! image to another, to check that each of them ends up displaying the new image and to time them.
+//!
+//! We draw the frames back to back, as fast as we can, with the progress each one would have at
+//! the transition's fps. That is what `transitions.rs` does as well, minus the waiting between
+//! frames and the final copy of the new image once the time is up.
+
+use std::time::{Duration, Instant};
+
+use keyframe::{keyframes, AnimationSequence};
+use utils::ipc::{TransitionBuilder, TransitionType};
+
+use super::{render::Renderer, transitions::bezier_curve};
+
+const TRANSITIONS: [TransitionType; 10] = [
+    TransitionType::None,
+    TransitionType::Simple,
+    TransitionType::Fade,
+    TransitionType::Dissolve,
+    TransitionType::Slide,
+    TransitionType::Tiles,
+    TransitionType::Wipe,
+    TransitionType::Wave,
+    TransitionType::Grow,
+    TransitionType::Outer,
+];
+
+const DIMENSIONS: (u32, u32) = (1920, 1080);
+const CHANNELS: usize = 4;
+
+/// How many frames a transition may draw after its time is up before we give up on it reaching
+/// the new image. Pixels move by at least 1 each frame, so every transition needs fewer than that
+const MAX_EXTRA_FRAMES: usize = 256;
+
+struct Outcome {
+    frames: usize,
+    elapsed: Duration,
+    /// whether the last frame is exactly the new image
+    completed: bool,
+}
+
+/// Runs every transition, printing how each one went. Fails if any of them did not reach the new
+/// image
+pub(crate) fn selftest() -> Result<(), String> {
+    let (old, new) = synthetic_images(DIMENSIONS);
+    println!(
+        "drawing every transition from one {}x{} image to another",
+        DIMENSIONS.0, DIMENSIONS.1
+    );
+    println!();
+    println!(
+        "{:<10} {:>7} {:>10} {:>10}  result",
+        "transition", "frames", "total", "per frame"
+    );
+
+    let mut failed = 0;
+    for transition_type in TRANSITIONS {
+        let outcome = run(transition_type, DIMENSIONS, &old, &new);
+        let per_frame = outcome.elapsed / outcome.frames.max(1) as u32;
+        println!(
+            "{:<10} {:>7} {:>8.1}ms {:>8.2}ms  {}",
+            format!("{transition_type:?}").to_lowercase(),
+            outcome.frames,
+            outcome.elapsed.as_secs_f64() * 1000.0,
+            per_frame.as_secs_f64() * 1000.0,
+            if outcome.completed { "pass" } else { "FAIL" }
+        );
+        if !outcome.completed {
+            failed += 1;
+        }
+    }
+
+    println!();
+    if failed == 0 {
+        println!("all {} transitions passed", TRANSITIONS.len());
+        Ok(())
+    } else {
+        Err(format!(
+            "{failed} of {} transitions did not reach the new image",
+            TRANSITIONS.len()
+        ))
+    }
+}
+
+/// Draws `transition_type`, with the same defaults as `swww img`, from `old` to `new`
+fn run(transition_type: TransitionType, dimensions: (u32, u32), old: &[u8], new: &[u8]) -> Outcome {
+    let transition = TransitionBuilder::new(transition_type).build();
+    let renderer = Renderer::new(&transition, dimensions, CHANNELS);
+    let step = transition.step.get();
+    let bezier = bezier_curve(&transition);
+    let mut seq: AnimationSequence<f32> =
+        keyframes![(0.0, 0.0, bezier), (1.0, transition.duration, bezier)];
+
+    // like in `transitions.rs`, `none` and `simple` take as many frames as they need, while the
+    // others last for their duration
+    let timed_frames = match transition_type {
+        TransitionType::None | TransitionType::Simple => 0,
+        _ => (transition.duration as f64 * transition.fps as f64).ceil() as usize,
+    };
+
+    let mut canvas = old.to_vec();
+    let mut frames = 0;
+    let start = Instant::now();
+    for frame in 0..timed_frames {
+        seq.advance_to(frame as f64 / transition.fps as f64);
+        renderer.render(&mut canvas, old, new, seq.now() as f64, step);
+        frames += 1;
+    }
+    for _ in 0..MAX_EXTRA_FRAMES {
+        if canvas == new {
+            break;
+        }
+        renderer.render(&mut canvas, old, new, 1.0, step);
+        frames += 1;
+    }
+
+    Outcome {
+        frames,
+        elapsed: start.elapsed(),
+        completed: canvas == new,
+    }
+}
+
+/// Two gradients, going in different directions, so that (almost) every pixel changes
+fn synthetic_images(dimensions: (u32, u32)) -> (Vec<u8>, Vec<u8>) {
+    let (width, height) = (dimensions.0 as usize, dimensions.1 as usize);
+    let mut old = Vec::with_capacity(width * height * CHANNELS);
+    let mut new = Vec::with_capacity(width * height * CHANNELS);
+    for y in 0..height {
+        for x in 0..width {
+            let horizontal = (x * 255 / (width - 1).max(1)) as u8;
+            let vertical = (y * 255 / (height - 1).max(1)) as u8;
+            old.extend([horizontal, vertical, 255 - horizontal, 0]);
+            new.extend([255 - vertical, horizontal, vertical, 0]);
+        }
+    }
+    (old, new)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn every_transition_reaches_the_new_image() {
+        let dimensions = (64, 48);
+        let (old, new) = synthetic_images(dimensions);
+        for transition_type in TRANSITIONS {
+            let outcome = run(transition_type, dimensions, &old, &new);
+            assert!(outcome.completed, "{transition_type:?} did not complete");
+            assert!(outcome.frames > 0);
+        }
+    }
+}
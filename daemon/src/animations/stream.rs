@@ -0,0 +1,274 @@
+use log::{debug, error};
+
+use std::{
+    sync::{Arc, Condvar, Mutex, Weak},
+    thread,
+    time::Duration,
+};
+
+use rustix::fd::OwnedFd;
+use utils::{
+    compression::BitPack,
+    ipc::{Animation, AnimationClock, ColorAdjustment, RequestRecv, StreamedAnimation},
+};
+
+use super::memory::Reservation;
+
+/// How much of a streamed animation we wait for before we start playing it, so that we do not
+/// pause right away if the client is just a little slower than the animation
+pub(super) const BUFFERED: Duration = Duration::from_secs(1);
+
+/// The frames of a `StreamedAnimation`, shared between the thread receiving them and the ones
+/// playing them
+pub(crate) struct FrameStream {
+    state: Mutex<State>,
+    cvar: Condvar,
+    /// `--max-animation-memory`: held by the wallpapers playing the animation, so it stops
+    /// growing once none of them do
+    memory: Mutex<Weak<Reservation>>,
+}
+
+enum State {
+    Loading(StreamedAnimation, Vec<(BitPack, Duration)>),
+    Loaded(Arc<Animation>),
+    /// The client went away before sending every frame
+    Failed,
+}
+
+impl FrameStream {
+    pub(crate) fn new(animation: StreamedAnimation) -> Self {
+        Self {
+            state: Mutex::new(State::Loading(animation, Vec::new())),
+            cvar: Condvar::new(),
+            memory: Mutex::new(Weak::new()),
+        }
+    }
+
+    /// Makes the frames that arrive from now on count against `reservation`
+    pub(crate) fn set_memory(&self, reservation: &Arc<Reservation>) {
+        *self.memory.lock().unwrap() = Arc::downgrade(reservation);
+    }
+
+    /// Reserves `bytes` for frames that just arrived. Fails with why if they do not fit
+    fn reserve(&self, bytes: usize) -> Result<(), String> {
+        match self.memory.lock().unwrap().upgrade() {
+            Some(reservation) => reservation.grow(bytes),
+            None => Ok(()),
+        }
+    }
+
+    fn push(&self, frames: impl IntoIterator<Item = (BitPack, Duration)>, last: bool) {
+        let mut state = self.state.lock().unwrap();
+        if let State::Loading(animation, loaded) = &mut *state {
+            loaded.extend(frames);
+            if last {
+                *state = State::Loaded(Arc::new(Animation {
+                    animation: std::mem::take(loaded).into(),
+                    clock: animation.clock,
+                    interpolate: animation.interpolate,
+                    warp: animation.warp,
+                    loop_count: animation.loop_count,
+                }));
+            }
+        }
+        self.cvar.notify_all();
+    }
+
+    /// Stops loading the frames, leaving what displays them with the first one
+    pub(crate) fn fail(&self) {
+        let mut state = self.state.lock().unwrap();
+        if let State::Loading(..) = &*state {
+            *state = State::Failed;
+        }
+        self.cvar.notify_all();
+    }
+
+    fn is_loading(&self) -> bool {
+        matches!(&*self.state.lock().unwrap(), State::Loading(..))
+    }
+
+    /// Whose clock the animation follows, which we know before any of its frames arrive
+    pub(super) fn clock(&self) -> AnimationClock {
+        match &*self.state.lock().unwrap() {
+            State::Loading(animation, _) => animation.clock,
+            State::Loaded(animation) => animation.clock,
+            State::Failed => AnimationClock::Independent,
+        }
+    }
+
+    /// Whether the animation interpolates between its frames, which we know before any of them
+    /// arrive
+    pub(crate) fn interpolates(&self) -> bool {
+        match &*self.state.lock().unwrap() {
+            State::Loading(animation, _) => animation.interpolate,
+            State::Loaded(animation) => animation.interpolate,
+            State::Failed => false,
+        }
+    }
+
+    /// Plays the animation without interpolating it, since that keeps frames unpacked
+    pub(crate) fn stop_interpolating(&self) {
+        if let State::Loading(animation, _) = &mut *self.state.lock().unwrap() {
+            animation.interpolate = false;
+        }
+    }
+
+    /// Blocks until the frames we have last for at least `duration`, or we stopped loading them
+    pub(super) fn wait_buffered(&self, duration: Duration) {
+        let _state = self
+            .cvar
+            .wait_while(self.state.lock().unwrap(), |state| match state {
+                State::Loading(_, frames) => {
+                    frames.iter().map(|(_, d)| *d).sum::<Duration>() < duration
+                }
+                _ => false,
+            })
+            .unwrap();
+    }
+
+    /// Calls `f` with the frame at `index`, waiting for it if it did not arrive yet. Once every
+    /// frame arrived, returns the whole animation instead, or `None` if loading them failed.
+    ///
+    /// The last frame, which goes back to the first image, always arrives along with the others'
+    /// end, so `f` is never called with it
+    pub(super) fn with_frame<R>(
+        &self,
+        index: usize,
+        f: impl FnOnce(&BitPack, Duration) -> R,
+    ) -> Result<R, Option<Arc<Animation>>> {
+        let mut state = self.state.lock().unwrap();
+        if matches!(&*state, State::Loading(_, frames) if index >= frames.len()) {
+            debug!("animation playback caught up with its loading, pausing at frame {index}");
+            state = self
+                .cvar
+                .wait_while(
+                    state,
+                    |state| matches!(state, State::Loading(_, frames) if index >= frames.len()),
+                )
+                .unwrap();
+        }
+        match &*state {
+            State::Loading(_, frames) => {
+                let (frame, duration) = &frames[index];
+                Ok(f(frame, *duration))
+            }
+            State::Loaded(animation) => Err(Some(Arc::clone(animation))),
+            State::Failed => Err(None),
+        }
+    }
+}
+
+/// Receives the frames the client sends through `stream` after an image request, until it sent
+/// every one of them or nobody is playing them anymore
+pub(crate) fn load_frames(
+    stream: OwnedFd,
+    streams: Box<[Arc<FrameStream>]>,
+    adjustment: ColorAdjustment,
+) {
+    thread::Builder::new()
+        .stack_size(1 << 15)
+        .name("animation loader".to_string())
+        .spawn(move || {
+            let table = (!adjustment.is_none()).then(|| adjustment.table());
+            while streams.iter().any(|s| s.is_loading()) {
+                // the animation threads hold the other references
+                if streams.iter().all(|s| Arc::strong_count(s) == 1) {
+                    debug!("nobody is playing the streamed animations anymore, stop loading them");
+                    break;
+                }
+                let bytes = match utils::ipc::read_socket(&stream) {
+                    Ok(bytes) => bytes,
+                    Err(e) => {
+                        error!("failed to receive animation frames: {e}");
+                        break;
+                    }
+                };
+                let RequestRecv::AnimationFrames(frames) = RequestRecv::receive(bytes) else {
+                    error!("client sent a request while streaming animation frames");
+                    break;
+                };
+                let Some(frame_stream) = streams.get(frames.img) else {
+                    error!(
+                        "client sent frames for image {}, which does not exist",
+                        frames.img
+                    );
+                    break;
+                };
+                let compressed = frames.frames.iter().map(|(f, _)| f.compressed_len()).sum();
+                if let Err(e) = frame_stream.reserve(compressed) {
+                    error!("{e}, so we stop loading its frames");
+                    break;
+                }
+                let last = frames.last;
+                let frames = frames.frames.into_vec().into_iter();
+                match table.as_ref() {
+                    Some(table) => frame_stream.push(
+                        frames.map(|(frame, duration)| {
+                            (frame.map_pixel_bytes(|b| table[b as usize]), duration)
+                        }),
+                        last,
+                    ),
+                    None => frame_stream.push(frames, last),
+                }
+            }
+            for frame_stream in streams.iter() {
+                frame_stream.fail();
+            }
+        })
+        .unwrap(); // builder only fails if name contains null bytes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use utils::{compression::Compressor, ipc::AnimationClock, ipc::PixelFormat};
+
+    fn frame() -> (BitPack, Duration) {
+        let frame = Compressor::new()
+            .compress(&[0; 4 * 3], &[255; 4 * 3], PixelFormat::Rgb)
+            .unwrap();
+        (frame, Duration::from_millis(600))
+    }
+
+    #[test]
+    fn streamed_frames_hand_over_the_animation_once_loaded() {
+        let stream = Arc::new(FrameStream::new(StreamedAnimation {
+            clock: AnimationClock::Independent,
+            interpolate: false,
+            warp: false,
+            loop_count: None,
+        }));
+        stream.push([frame()], false);
+        assert!(matches!(stream.with_frame(0, |_, d| d), Ok(d) if d == frame().1));
+
+        let loader = Arc::clone(&stream);
+        let handle = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(20));
+            loader.push([frame()], false);
+        });
+        // waits for the loader, instead of running out of frames
+        stream.wait_buffered(BUFFERED);
+        assert!(matches!(stream.with_frame(1, |_, d| d), Ok(d) if d == frame().1));
+        handle.join().unwrap();
+        stream.push([frame()], true);
+
+        let Err(Some(animation)) = stream.with_frame(2, |_, _| ()) else {
+            panic!("every frame arrived");
+        };
+        assert_eq!(animation.animation.len(), 3);
+        assert_eq!(animation.clock, AnimationClock::Independent);
+    }
+
+    #[test]
+    fn failed_streams_stop_playing() {
+        let stream = FrameStream::new(StreamedAnimation {
+            clock: AnimationClock::Locked,
+            interpolate: false,
+            warp: false,
+            loop_count: None,
+        });
+        stream.fail();
+        stream.wait_buffered(BUFFERED);
+        assert!(matches!(stream.with_frame(0, |_, _| ()), Err(None)));
+    }
+}
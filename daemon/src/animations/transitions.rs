@@ -4,16 +4,23 @@ use std::{
 };
 
 use log::debug;
-use utils::ipc::{Position, TransitionType};
+use utils::{
+    compression::Decompressor,
+    ipc::{Animation, TransitionType},
+};
 
 use crate::{
-    wallpaper::{AnimationToken, Wallpaper},
+    subscribers,
+    wallpaper::{AnimationToken, Damage, Wallpaper},
     wayland::globals,
 };
 
-use keyframe::{
-    functions::BezierCurve, keyframes, mint::Vector2, num_traits::Pow, AnimationSequence,
-};
+use super::render::Renderer;
+
+use keyframe::{functions::BezierCurve, keyframes, mint::Vector2, AnimationSequence};
+
+/// How often we tell subscribers how far a transition got, at most
+const PROGRESS_INTERVAL: Duration = Duration::from_millis(100);
 
 pub(super) struct Transition<'a> {
     animation_tokens: Vec<AnimationToken>,
@@ -23,11 +30,14 @@ pub(super) struct Transition<'a> {
     duration: f32,
     step: u8,
     fps: Duration,
-    angle: f64,
-    pos: Position,
     bezier: BezierCurve,
-    wave: (f32, f32),
-    invert_y: bool,
+    live: bool,
+    renderer: Renderer,
+    /// When the transition's `Timeline` started, and its duration, to report its progress
+    timeline: Option<(Instant, Duration)>,
+    last_progress: Option<Instant>,
+    /// When the daemon got the request, until we commit our first frame. See `Animator::sync`
+    requested: Option<Instant>,
 }
 
 /// All transitions return whether or not they completed
@@ -36,6 +46,7 @@ impl<'a> Transition<'a> {
         wallpapers: &'a mut Vec<Arc<Wallpaper>>,
         dimensions: (u32, u32),
         transition: &utils::ipc::Transition,
+        requested: Instant,
     ) -> Self {
         Transition {
             animation_tokens: wallpapers
@@ -48,68 +59,154 @@ impl<'a> Transition<'a> {
             duration: transition.duration,
             step: transition.step.get(),
             fps: Duration::from_nanos(1_000_000_000 / transition.fps as u64),
-            angle: transition.angle,
-            pos: transition.pos.clone(),
-            bezier: BezierCurve::from(
-                Vector2 {
-                    x: transition.bezier.0,
-                    y: transition.bezier.1,
-                },
-                Vector2 {
-                    x: transition.bezier.2,
-                    y: transition.bezier.3,
-                },
+            bezier: bezier_curve(transition),
+            live: transition.live,
+            renderer: Renderer::new(
+                transition,
+                dimensions,
+                globals::pixel_format().channels() as usize,
             ),
-            wave: transition.wave,
-            invert_y: transition.invert_y,
+            timeline: None,
+            last_progress: None,
+            requested: Some(requested),
         }
     }
 
-    pub(super) fn execute(mut self, new_img: &[u8]) {
+    /// `incoming` is the new image's animation, if it has one. Returns the index of its next
+    /// frame, since live fades start playing it
+    pub(super) fn execute(mut self, new_img: &[u8], incoming: Option<Arc<Animation>>) -> usize {
         debug!("Starting transitions");
+        let mut playhead = 0;
         match self.transition_type {
             TransitionType::None => self.none(new_img),
             TransitionType::Simple => self.simple(new_img),
-            TransitionType::Wipe => self.wipe(new_img),
-            TransitionType::Grow => self.grow(new_img),
-            TransitionType::Outer => self.outer(new_img),
-            TransitionType::Wave => self.wave(new_img),
-            TransitionType::Fade => self.fade(new_img),
+            TransitionType::Fade if self.live => playhead = self.fade_live(new_img, incoming),
+            TransitionType::Fade | TransitionType::Slide => self.fade(new_img),
+            TransitionType::Wipe
+            | TransitionType::Grow
+            | TransitionType::Outer
+            | TransitionType::Wave
+            | TransitionType::Dissolve
+            | TransitionType::Tiles => self.animate(new_img),
         };
         debug!("Transitions finished");
+        if subscribers::has_subscribers() {
+            self.send_progress(100);
+        }
+        playhead
+    }
+
+    /// `damage` should contain every pixel that changed since the last frame, or be `None` if we
+    /// do not know which ones did
+    fn updt_wallpapers(&mut self, now: &mut Instant, damage: Option<Damage>) {
+        self.updt_wallpapers_with(now, damage, |_| ())
     }
 
-    fn updt_wallpapers(&mut self, now: &mut Instant) {
+    /// Like `updt_wallpapers`, but calls `removed` with the index of every wallpaper we stop
+    /// drawing to, so that the caller can keep its own per wallpaper state in sync
+    fn updt_wallpapers_with(
+        &mut self,
+        now: &mut Instant,
+        damage: Option<Damage>,
+        mut removed: impl FnMut(usize),
+    ) {
         let mut i = 0;
         while i < self.wallpapers.len() {
             let token = &self.animation_tokens[i];
             if !self.wallpapers[i].has_animation_id(token) {
                 self.wallpapers.swap_remove(i);
                 self.animation_tokens.swap_remove(i);
+                removed(i);
                 continue;
             }
             i += 1;
         }
-        crate::wallpaper::attach_buffers_and_damange_surfaces(self.wallpapers);
+        crate::wallpaper::attach_buffers_and_damange_surfaces(self.wallpapers, damage);
         let timeout = self.fps.saturating_sub(now.elapsed());
         crate::spin_sleep(timeout);
         crate::wallpaper::commit_wallpapers(self.wallpapers);
+        self.log_latency();
         *now = Instant::now();
+        self.report_progress(*now);
+    }
+
+    /// Logs how long after the request we committed our first frame, once
+    fn log_latency(&mut self) {
+        if let Some(requested) = self.requested.take() {
+            debug!(
+                "first frame of the transition committed {:?} after the request",
+                requested.elapsed()
+            );
+        }
+    }
+
+    /// Tells the subscribers how far we got, at most once every `PROGRESS_INTERVAL`. Transitions
+    /// without a `Timeline` (`none` and `simple`) only report when they are done
+    fn report_progress(&mut self, now: Instant) {
+        let Some((start, duration)) = self.timeline else {
+            return;
+        };
+        if !subscribers::has_subscribers()
+            || self
+                .last_progress
+                .is_some_and(|last| now.saturating_duration_since(last) < PROGRESS_INTERVAL)
+        {
+            return;
+        }
+        self.last_progress = Some(now);
+        self.send_progress(progress_percent(
+            now.saturating_duration_since(start),
+            duration,
+        ));
     }
 
-    fn bezier_seq(&self, start: f32, end: f32) -> (AnimationSequence<f32>, Instant) {
+    fn send_progress(&self, percent: u8) {
+        for wallpaper in self.wallpapers.iter() {
+            if let Some(name) = wallpaper.name() {
+                subscribers::notify_progress(name, percent);
+            }
+        }
+    }
+
+    /// The transition's progress, from 0 to 1, eased by its bezier curve, and its `Timeline`
+    fn bezier_seq(&mut self) -> (AnimationSequence<f32>, Timeline) {
+        let duration = Duration::from_secs_f32(self.duration.max(0.0));
+        let start_time = Instant::now();
+        self.timeline = Some((start_time, duration));
         (
-            keyframes![(start, 0.0, self.bezier), (end, self.duration, self.bezier)],
-            Instant::now(),
+            keyframes![(0.0, 0.0, self.bezier), (1.0, self.duration, self.bezier)],
+            Timeline::new(duration, self.fps, start_time),
         )
     }
 
+    /// Displays exactly `new_img`. Every transition that has a duration ends with this, once its
+    /// time is up, no matter how far it got
+    fn finish(&mut self, new_img: &[u8], now: &mut Instant) {
+        let damage = self.copy_changes(new_img);
+        self.updt_wallpapers(now, Some(damage));
+    }
+
     fn none(&mut self, new: &[u8]) {
-        self.wallpapers
-            .iter()
-            .for_each(|w| w.canvas_change(|canvas| canvas.copy_from_slice(new)));
-        crate::wallpaper::attach_buffers_and_damange_surfaces(self.wallpapers);
+        let damage = self.copy_changes(new);
+        crate::wallpaper::attach_buffers_and_damange_surfaces(self.wallpapers, Some(damage));
         crate::wallpaper::commit_wallpapers(self.wallpapers);
+        self.log_latency();
+    }
+
+    /// Copies `new` onto every wallpaper, returning the region that changed in any of them
+    fn copy_changes(&mut self, new: &[u8]) -> Damage {
+        let width = self.dimensions.0 as usize;
+        let channels = globals::pixel_format().channels() as usize;
+        let mut damage = Damage::empty();
+        for wallpaper in self.wallpapers.iter() {
+            let changed = wallpaper
+                .canvas_change(|canvas| Damage::copy_changes(canvas, new, width, channels));
+            damage.merge(&changed);
+        }
+        if damage.is_empty() {
+            debug!("new image is identical to the old one, nothing to upload");
+        }
+        damage
     }
 
     fn simple(&mut self, new_img: &[u8]) {
@@ -120,304 +217,296 @@ impl<'a> Transition<'a> {
             done = true;
             for wallpaper in self.wallpapers.iter() {
                 wallpaper.canvas_change(|canvas| {
-                    for (old, new) in canvas.iter_mut().zip(new_img) {
-                        change_byte(step, old, new);
-                    }
+                    self.renderer.render(canvas, &[], new_img, 0.0, step);
                     done = canvas == new_img;
                 });
             }
-            self.updt_wallpapers(&mut now);
+            self.updt_wallpapers(&mut now, None);
         }
     }
 
+    /// Used by `fade` and `slide`, whose frames only depend on the image we started from and the
+    /// progress
     fn fade(&mut self, new_img: &[u8]) {
-        let mut step = 0;
-        let (mut seq, start) = self.bezier_seq(0.0, 1.0);
+        // we blend from what each wallpaper displayed when we started, so that how far we got
+        // only depends on the time that passed
+        let mut olds: Vec<Box<[u8]>> = self
+            .wallpapers
+            .iter()
+            .map(|w| w.canvas_change(|canvas| (&*canvas).into()))
+            .collect();
+        let (mut seq, mut timeline) = self.bezier_seq();
 
         let mut now = Instant::now();
-        while start.elapsed().as_secs_f64() < seq.duration() {
-            for wallpaper in self.wallpapers.iter() {
+        while let Some(frame) = timeline.frame(Instant::now()) {
+            seq.advance_to(frame.elapsed);
+            let progress = seq.now() as f64;
+            for (wallpaper, old) in self.wallpapers.iter().zip(&olds) {
                 wallpaper.canvas_change(|canvas| {
-                    for (old, new) in canvas.iter_mut().zip(new_img) {
-                        let x = *old as u16 * (256 - step);
-                        let y = *new as u16 * step;
-                        *old = ((x + y) >> 8) as u8;
-                    }
+                    self.renderer.render(canvas, old, new_img, progress, 0)
                 });
             }
-            self.updt_wallpapers(&mut now);
-            step = (256.0 * seq.now() as f64).trunc() as u16;
-            seq.advance_to(start.elapsed().as_secs_f64());
+            self.updt_wallpapers_with(&mut now, None, |i| {
+                olds.swap_remove(i);
+            });
         }
-        self.step = 4 + self.step / 4;
-        self.simple(new_img)
+        self.finish(new_img, &mut now);
     }
 
-    fn wave(&mut self, new_img: &[u8]) {
-        let width = self.dimensions.0;
-        let height = self.dimensions.1;
-        let mut now = Instant::now();
-        let center = (width / 2, height / 2);
-        let screen_diag = ((width.pow(2) + height.pow(2)) as f64).sqrt();
-
-        let angle = self.angle.to_radians();
-        let (sin, cos) = angle.sin_cos();
-        let (scale_x, scale_y) = (self.wave.0 as f64, self.wave.1 as f64);
-
-        let circle_radius = screen_diag / 2.0;
-
-        // graph: https://www.desmos.com/calculator/wunde042es
-        //
-        // checks if a pixel is to the left or right of the line
-        let is_low = |x: f64, y: f64, offset: f64| {
-            let x = x - center.0 as f64;
-            let y = y - center.1 as f64;
-
-            let lhs = y * sin - x * cos;
-
-            let f = ((x * sin + y * cos) / scale_x).sin() * scale_y;
-            let rhs = f - circle_radius + offset / circle_radius;
-            lhs <= rhs
-        };
-
-        let mut offset = (sin.abs() * width as f64 + cos.abs() * height as f64) * 2.0;
-        let a = circle_radius * cos;
-        let b = circle_radius * sin;
-        let max_offset = circle_radius.pow(2) * 2.0;
-        let (width, height) = (width as usize, height as usize);
+    /// Like `fade`, but the outgoing animations (and the incoming one) keep playing during it.
+    /// Returns the index of the incoming animation's next frame
+    fn fade_live(&mut self, new_img: &[u8], incoming: Option<Arc<Animation>>) -> usize {
+        // we take the frame being displayed and its index together, so that they match
+        let mut outgoing: Vec<LiveSource> = self
+            .wallpapers
+            .iter()
+            .map(|w| w.canvas_change(|canvas| LiveSource::new(canvas, w.playing_animation())))
+            .collect();
+        let mut incoming = LiveSource::new(new_img, incoming.map(|animation| (animation, 0)));
 
-        let (mut seq, start) = self.bezier_seq(offset as f32, max_offset as f32);
+        let (mut seq, mut timeline) = self.bezier_seq();
+        let mut now = Instant::now();
+        while let Some(frame) = timeline.frame(Instant::now()) {
+            seq.advance_to(frame.elapsed);
+            let progress = seq.now() as f64;
+            let frame_time = Instant::now();
+            incoming.advance(frame_time);
+            for (wallpaper, old) in self.wallpapers.iter().zip(outgoing.iter_mut()) {
+                old.advance(frame_time);
+                wallpaper.canvas_change(|canvas| {
+                    self.renderer
+                        .render(canvas, &old.buf, &incoming.buf, progress, 0)
+                });
+            }
+            self.updt_wallpapers_with(&mut now, None, |i| {
+                outgoing.swap_remove(i);
+            });
+        }
 
-        let step = self.step;
-        let channels = globals::pixel_format().channels() as usize;
-        let stride = width * channels;
-        while start.elapsed().as_secs_f64() < seq.duration() {
-            offset = seq.now() as f64;
-            seq.advance_to(start.elapsed().as_secs_f64());
+        self.finish(&incoming.buf, &mut now);
+        incoming.next
+    }
 
+    /// Every transition besides `none`, `simple` and the fades: draws each frame with the
+    /// `Renderer`, damaging only what it tells us changed
+    fn animate(&mut self, new_img: &[u8]) {
+        let (mut seq, mut timeline) = self.bezier_seq();
+        let mut now = Instant::now();
+        while let Some(frame) = timeline.frame(Instant::now()) {
+            seq.advance_to(frame.elapsed);
+            let progress = seq.now() as f64;
+            let step = frame.scale_step(self.step);
+            let mut damage = Some(Damage::empty());
             for wallpaper in self.wallpapers.iter() {
-                wallpaper.canvas_change(|canvas| {
-                    // divide in 3 sections: the one we know will not be drawn to, the one we know
-                    // WILL be drawn to, and the one we need to do a more expensive check on.
-                    // We do this by creating 2 lines: the first tangential to the wave's peaks,
-                    // the second to its valeys. In-between is where we have to do the more
-                    // expensive checks
-                    for line in 0..height {
-                        let y = ((height - line) as f64 - center.1 as f64 - scale_y * sin) * b;
-                        let x = (circle_radius.powi(2) - y - offset) / a
-                            + center.0 as f64
-                            + scale_y * cos;
-                        let x = x.min(width as f64);
-                        let (col_begin, col_end) = if a.is_sign_negative() {
-                            (0usize, x as usize * channels)
-                        } else {
-                            (x as usize * channels, stride)
-                        };
-                        for col in col_begin..col_end {
-                            let old = unsafe { canvas.get_unchecked_mut(line * stride + col) };
-                            let new = unsafe { new_img.get_unchecked(line * stride + col) };
-                            change_byte(step, old, new);
-                        }
-                        let old_x = x;
-                        let y = ((height - line) as f64 - center.1 as f64 + scale_y * sin) * b;
-                        let x = (circle_radius.powi(2) - y - offset) / a + center.0 as f64
-                            - scale_y * cos;
-                        let x = x.min(width as f64);
-                        let (col_begin, col_end) = if old_x < x {
-                            (old_x as usize, x as usize)
-                        } else {
-                            (x as usize, old_x as usize)
-                        };
-                        for col in col_begin..col_end {
-                            if is_low(col as f64, line as f64, offset) {
-                                let i = line * stride + col * channels;
-                                for j in 0..channels {
-                                    let old = unsafe { canvas.get_unchecked_mut(i + j) };
-                                    let new = unsafe { new_img.get_unchecked(i + j) };
-                                    change_byte(step, old, new);
-                                }
-                            }
-                        }
-                    }
+                let changed = wallpaper.canvas_change(|canvas| {
+                    self.renderer.render(canvas, &[], new_img, progress, step)
                 });
+                damage = match (damage, changed) {
+                    (Some(mut damage), Some(changed)) => {
+                        damage.merge(&changed);
+                        Some(damage)
+                    }
+                    _ => None,
+                };
             }
-
-            self.updt_wallpapers(&mut now);
+            self.updt_wallpapers(&mut now, damage);
         }
-        self.step = 4 + self.step / 4;
-        self.simple(new_img)
+        self.finish(new_img, &mut now);
     }
+}
 
-    fn wipe(&mut self, new_img: &[u8]) {
-        let width = self.dimensions.0;
-        let height = self.dimensions.1;
-        let mut now = Instant::now();
-        let center = (width / 2, height / 2);
-        let screen_diag = ((width.pow(2) + height.pow(2)) as f64).sqrt();
+/// The curve that eases the progress of `transition`
+pub(super) fn bezier_curve(transition: &utils::ipc::Transition) -> BezierCurve {
+    BezierCurve::from(
+        Vector2 {
+            x: transition.bezier.0,
+            y: transition.bezier.1,
+        },
+        Vector2 {
+            x: transition.bezier.2,
+            y: transition.bezier.3,
+        },
+    )
+}
 
-        let circle_radius = screen_diag / 2.0;
-        let max_offset = circle_radius.pow(2) * 2.0;
+/// Drives a transition by the time that actually passed since it started, instead of by how many
+/// frames we drew. If we are too slow to draw every frame (e.g. because the CPU is busy), we skip
+/// ahead, so that the transition still takes its duration.
+struct Timeline {
+    start: Instant,
+    duration: Duration,
+    /// how long each frame should take, at the transition's fps
+    frame_time: Duration,
+    last_frame: Instant,
+    finished: bool,
+}
 
-        let angle = self.angle.to_radians();
+/// One of the frames of a `Timeline`
+struct TimelineFrame {
+    /// seconds since the start of the transition
+    elapsed: f64,
+    /// how many frames' worth of time passed since the previous frame. This is 1 unless we are
+    /// lagging behind
+    frames: f64,
+}
 
-        let mut offset = {
-            let (x, y) = angle.sin_cos();
-            (x.abs() * width as f64 + y.abs() * height as f64) * 2.0
-        };
+impl Timeline {
+    fn new(duration: Duration, frame_time: Duration, start: Instant) -> Self {
+        Self {
+            start,
+            duration,
+            frame_time,
+            last_frame: start,
+            finished: false,
+        }
+    }
 
-        let a = circle_radius * angle.cos();
-        let b = circle_radius * angle.sin();
+    /// The frame to draw at `now`, or `None` once the transition's time is up, in which case the
+    /// final image must be drawn. After that, we always return `None`
+    fn frame(&mut self, now: Instant) -> Option<TimelineFrame> {
+        let elapsed = now.saturating_duration_since(self.start);
+        if self.finished || elapsed >= self.duration {
+            self.finished = true;
+            return None;
+        }
 
-        let (width, height) = (width as usize, height as usize);
-        let (mut seq, start) = self.bezier_seq(offset as f32, max_offset as f32);
+        let since_last = now.saturating_duration_since(self.last_frame);
+        self.last_frame = now;
+        let frames = if self.frame_time.is_zero() {
+            1.0
+        } else {
+            (since_last.as_secs_f64() / self.frame_time.as_secs_f64()).max(1.0)
+        };
+        Some(TimelineFrame {
+            elapsed: elapsed.as_secs_f64(),
+            frames,
+        })
+    }
+}
 
-        let step = self.step;
-        let channels = globals::pixel_format().channels() as usize;
-        let stride = width * channels;
-        while start.elapsed().as_secs_f64() < seq.duration() {
-            offset = seq.now() as f64;
-            seq.advance_to(start.elapsed().as_secs_f64());
-            for wallpaper in self.wallpapers.iter() {
-                wallpaper.canvas_change(|canvas| {
-                    // line formula: (x-h)*a + (y-k)*b + C = r^2
-                    // https://www.desmos.com/calculator/vpvzk12yar
-                    for line in 0..height {
-                        let y = ((height - line) as f64 - center.1 as f64) * b;
-                        let x = (circle_radius.powi(2) - y - offset) / a + center.0 as f64;
-                        let x = x.min(width as f64);
-                        let (col_begin, col_end) = if a.is_sign_negative() {
-                            (0usize, x as usize * channels)
-                        } else {
-                            (x as usize * channels, stride)
-                        };
-                        for col in col_begin..col_end {
-                            let old = unsafe { canvas.get_unchecked_mut(line * stride + col) };
-                            let new = unsafe { new_img.get_unchecked(line * stride + col) };
-                            change_byte(step, old, new);
-                        }
-                    }
-                });
-            }
-            self.updt_wallpapers(&mut now);
-        }
-        self.step = 4 + self.step / 4;
-        self.simple(new_img)
+impl TimelineFrame {
+    /// Scales how much pixels change per frame (the transition's `step`) by how many frames passed
+    /// since the last one, so that they change at the same speed no matter how many we skipped
+    fn scale_step(&self, step: u8) -> u8 {
+        (step as f64 * self.frames).round().min(255.0) as u8
     }
+}
 
-    fn grow(&mut self, new_img: &[u8]) {
-        let (width, height) = (self.dimensions.0 as f32, self.dimensions.1 as f32);
-        let (center_x, center_y) = self.pos.to_pixel(self.dimensions, self.invert_y);
-        let mut dist_center: f32 = 0.0;
-        let dist_end: f32 = {
-            let mut x = center_x;
-            let mut y = center_y;
-            if x < width / 2.0 {
-                x = width - 1.0 - x;
-            }
-            if y < height / 2.0 {
-                y = height - 1.0 - y;
-            }
-            f32::sqrt(x.pow(2) + y.pow(2))
-        };
+/// How far (from 0 to 99) a transition that lasts `duration` got after `elapsed`. We only report
+/// 100 once the new image is actually displayed
+fn progress_percent(elapsed: Duration, duration: Duration) -> u8 {
+    if duration.is_zero() {
+        return 99;
+    }
+    (elapsed.as_secs_f64() / duration.as_secs_f64() * 100.0).clamp(0.0, 99.0) as u8
+}
 
-        let (width, height) = (width as usize, height as usize);
-        let (center_x, center_y) = (center_x as usize, center_y as usize);
+/// What a live fade blends from, or into. If it is animated, it keeps playing during the fade
+struct LiveSource {
+    buf: Box<[u8]>,
+    animation: Option<Arc<Animation>>,
+    /// index of the next frame to apply, and when to apply it
+    next: usize,
+    next_at: Instant,
+    decompressor: Decompressor,
+}
 
-        let step = self.step;
-        let channels = globals::pixel_format().channels() as usize;
-        let stride = width * channels;
-        let (mut seq, start) = self.bezier_seq(0.0, dist_end);
-        let mut now = Instant::now();
-        while start.elapsed().as_secs_f64() < seq.duration() {
-            for wallpaper in self.wallpapers.iter() {
-                wallpaper.canvas_change(|canvas| {
-                    let line_begin = center_y.saturating_sub(dist_center as usize);
-                    let line_end = height.min(center_y + dist_center as usize);
-
-                    // to plot half a circle with radius r, we do sqrt(r^2 - x^2)
-                    for line in line_begin..line_end {
-                        let offset = (dist_center.powi(2) - (center_y as f32 - line as f32).powi(2))
-                            .sqrt() as usize;
-                        let col_begin = center_x.saturating_sub(offset) * channels;
-                        let col_end = width.min(center_x + offset) * channels;
-                        for col in col_begin..col_end {
-                            let old = unsafe { canvas.get_unchecked_mut(line * stride + col) };
-                            let new = unsafe { new_img.get_unchecked(line * stride + col) };
-                            change_byte(step, old, new);
-                        }
-                    }
-                });
+impl LiveSource {
+    /// `animation` is the animation `buf` belongs to, along with the index of its next frame
+    fn new(buf: &[u8], animation: Option<(Arc<Animation>, usize)>) -> Self {
+        let (animation, next, next_at) = match animation {
+            Some((animation, next)) if animation.animation.len() > 1 => {
+                let len = animation.animation.len();
+                let next = next % len;
+                // we don't know for how long the current frame has been displayed, so we act as
+                // if it just started
+                let current = animation.animation[(next + len - 1) % len].1;
+                (Some(animation), next, Instant::now() + current)
             }
-            self.updt_wallpapers(&mut now);
-
-            dist_center = seq.now();
-            seq.advance_to(start.elapsed().as_secs_f64());
+            _ => (None, 0, Instant::now()),
+        };
+        Self {
+            buf: buf.into(),
+            animation,
+            next,
+            next_at,
+            decompressor: Decompressor::new(),
         }
-        self.step = 4 + self.step / 4;
-        self.simple(new_img)
     }
 
-    fn outer(&mut self, new_img: &[u8]) {
-        let (width, height) = (self.dimensions.0 as f32, self.dimensions.1 as f32);
-        let (center_x, center_y) = self.pos.to_pixel(self.dimensions, self.invert_y);
-        let mut dist_center = {
-            let mut x = center_x;
-            let mut y = center_y;
-            if x < width / 2.0 {
-                x = width - 1.0 - x;
-            }
-            if y < height / 2.0 {
-                y = height - 1.0 - y;
-            }
-            f32::sqrt(x.pow(2) + y.pow(2))
+    /// Applies the animation's next frame, if it is time to
+    fn advance(&mut self, now: Instant) {
+        let Some(animation) = self.animation.as_ref() else {
+            return;
         };
-        let (width, height) = (width as usize, height as usize);
-        let (center_x, center_y) = (center_x as usize, center_y as usize);
-
-        let step = self.step;
-        let channels = globals::pixel_format().channels() as usize;
-        let stride = width * channels;
-        let (mut seq, start) = self.bezier_seq(dist_center, 0.0);
-        let mut now = Instant::now();
-        while start.elapsed().as_secs_f64() < seq.duration() {
-            for wallpaper in self.wallpapers.iter() {
-                wallpaper.canvas_change(|canvas| {
-                    // to plot half a circle with radius r, we do sqrt(r^2 - x^2)
-                    for line in 0..height {
-                        let offset = (dist_center.powi(2) - (center_y as f32 - line as f32).powi(2))
-                            .sqrt() as usize;
-                        let col_begin = center_x.saturating_sub(offset) * channels;
-                        let col_end = width.min(center_x + offset) * channels;
-                        for col in 0..col_begin {
-                            let old = unsafe { canvas.get_unchecked_mut(line * stride + col) };
-                            let new = unsafe { new_img.get_unchecked(line * stride + col) };
-                            change_byte(step, old, new);
-                        }
-                        for col in col_end..stride {
-                            let old = unsafe { canvas.get_unchecked_mut(line * stride + col) };
-                            let new = unsafe { new_img.get_unchecked(line * stride + col) };
-                            change_byte(step, old, new);
-                        }
-                    }
-                });
+        if now < self.next_at {
+            return;
+        }
+        let len = animation.animation.len();
+        let (frame, duration) = &animation.animation[self.next];
+        match self
+            .decompressor
+            .decompress(frame, &mut self.buf, globals::pixel_format())
+        {
+            Ok(()) => {
+                self.next = (self.next + 1) % len;
+                self.next_at += *duration;
+            }
+            Err(e) => {
+                // this happens if the output changed size since the animation was sent
+                debug!("stopped playing animation during fade: {e}");
+                self.animation = None;
             }
-            self.updt_wallpapers(&mut now);
-
-            dist_center = seq.now();
-            seq.advance_to(start.elapsed().as_secs_f64());
         }
-        self.step = 4 + self.step / 4;
-        self.simple(new_img)
     }
 }
 
-#[inline(always)]
-fn change_byte(step: u8, old: &mut u8, new: &u8) {
-    if old.abs_diff(*new) < step {
-        *old = *new;
-    } else if *old > *new {
-        *old -= step;
-    } else {
-        *old += step;
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn timeline_skips_delayed_frames() {
+        let start = Instant::now();
+        let at = |ms| start + Duration::from_millis(ms);
+        let mut timeline =
+            Timeline::new(Duration::from_millis(500), Duration::from_millis(10), start);
+
+        let frame = timeline.frame(at(0)).unwrap();
+        assert_eq!((frame.elapsed, frame.frames), (0.0, 1.0));
+        let frame = timeline.frame(at(10)).unwrap();
+        assert_eq!((frame.elapsed, frame.frames), (0.01, 1.0));
+
+        // drawing the previous frame took 300ms, so we jump straight to where we should be
+        let frame = timeline.frame(at(310)).unwrap();
+        assert_eq!(frame.elapsed, 0.31);
+        assert!((frame.frames - 30.0).abs() < 1e-9);
+        assert_eq!(frame.scale_step(2), 60);
+        assert_eq!(frame.scale_step(90), 255);
+
+        let frame = timeline.frame(at(490)).unwrap();
+        assert_eq!(frame.elapsed, 0.49);
+
+        // the transition ends on time, even though we drew far fewer frames than 500ms at 100fps
+        assert!(timeline.frame(at(500)).is_none());
+        // and once it has ended, it stays that way
+        assert!(timeline.frame(at(495)).is_none());
+    }
+
+    #[test]
+    fn progress_only_reaches_100_when_done() {
+        let duration = Duration::from_secs(2);
+        assert_eq!(progress_percent(Duration::ZERO, duration), 0);
+        assert_eq!(progress_percent(Duration::from_millis(500), duration), 25);
+        assert_eq!(progress_percent(duration, duration), 99);
+        assert_eq!(progress_percent(Duration::from_secs(3), duration), 99);
+        assert_eq!(progress_percent(Duration::ZERO, Duration::ZERO), 99);
+    }
+
+    #[test]
+    fn timeline_without_duration_ends_immediately() {
+        let start = Instant::now();
+        let mut timeline = Timeline::new(Duration::ZERO, Duration::from_millis(10), start);
+        assert!(timeline.frame(start).is_none());
     }
 }
@@ -0,0 +1,269 @@
+//! `swww img --animation-warp`: interpolates between the frames of an animation by moving their
+//! contents, instead of only crossfading them.
+//!
+//! Crossfading something that moves shows it twice, at half opacity, halfway through each frame.
+//! Instead, we estimate how each block of the next frame moved since the current one, by block
+//! matching over downscaled copies of both (the cheapest kind of optical flow), and draw every
+//! intermediate frame with each block partway along its motion. Blocks that did not move are
+//! crossfaded, as before.
+//!
+//! Estimating the motion costs about as much as a few crossfades, once per frame of the
+//! animation. Each warped frame then costs about twice as much as a crossfaded one. If that turns
+//! out to be too slow for the rate we draw at, `Fallback` makes us go back to crossfading.
+
+use log::warn;
+use std::time::Duration;
+
+/// We estimate the motion on copies of the frames this many times smaller
+const DOWNSCALE: usize = 4;
+/// Side of the blocks we estimate the motion of, in downscaled pixels
+const BLOCK: usize = 8;
+/// How far we look for each block, in downscaled pixels
+const RADIUS: isize = 4;
+
+/// How far each block of a frame moved since the previous frame
+pub(super) struct Flow {
+    width: usize,
+    height: usize,
+    channels: usize,
+    columns: usize,
+    rows: usize,
+    /// in pixels, for each block, row by row
+    motion: Box<[(isize, isize)]>,
+}
+
+impl Flow {
+    /// `current` and `next` are frames `width` pixels wide, with `channels` bytes per pixel
+    pub(super) fn estimate(current: &[u8], next: &[u8], width: usize, channels: usize) -> Self {
+        let height = current.len() / channels / width.max(1);
+        let (small_width, small_height) = (width / DOWNSCALE, height / DOWNSCALE);
+        let columns = small_width.div_ceil(BLOCK).max(1);
+        let rows = small_height.div_ceil(BLOCK).max(1);
+        let mut motion = vec![(0, 0); columns * rows].into_boxed_slice();
+
+        if small_width > 0 && small_height > 0 {
+            let current = downscale(current, width, height, channels);
+            let next = downscale(next, width, height, channels);
+            for row in 0..rows {
+                for column in 0..columns {
+                    let block = Block {
+                        x: column * BLOCK..((column + 1) * BLOCK).min(small_width),
+                        y: row * BLOCK..((row + 1) * BLOCK).min(small_height),
+                    };
+                    let (dx, dy) = block.motion(&current, &next, small_width, small_height);
+                    motion[row * columns + column] =
+                        (dx * DOWNSCALE as isize, dy * DOWNSCALE as isize);
+                }
+            }
+        }
+
+        Self {
+            width,
+            height,
+            channels,
+            columns,
+            rows,
+            motion,
+        }
+    }
+
+    /// Whether nothing moved, in which case warping is the same as crossfading
+    pub(super) fn is_still(&self) -> bool {
+        self.motion.iter().all(|motion| *motion == (0, 0))
+    }
+
+    /// Writes the frame `weight` of the way from `current` to `next` into `canvas`, where `weight`
+    /// goes from 0 (`current`) to 256 (`next`), like in `blend`
+    pub(super) fn warp(&self, canvas: &mut [u8], current: &[u8], next: &[u8], weight: u16) {
+        let Self {
+            width,
+            height,
+            channels,
+            columns,
+            rows,
+            ..
+        } = *self;
+        let block_side = BLOCK * DOWNSCALE;
+        let w = weight as isize;
+        let clamp = |v: isize, len: usize| v.clamp(0, len as isize - 1) as usize;
+
+        for y in 0..height {
+            let row = (y / block_side).min(rows - 1);
+            let mut x = 0;
+            while x < width {
+                let column = (x / block_side).min(columns - 1);
+                let end = if column == columns - 1 {
+                    width
+                } else {
+                    (column + 1) * block_side
+                };
+                let (dx, dy) = self.motion[row * columns + column];
+                // the block's contents are `weight` of the way along their motion: they come
+                // from further back in the current frame, and from further ahead in the next
+                let current_y = clamp(y as isize - dy * w / 256, height);
+                let next_y = clamp(y as isize + dy * (256 - w) / 256, height);
+                for x in x..end {
+                    let current_x = clamp(x as isize - dx * w / 256, width);
+                    let next_x = clamp(x as isize + dx * (256 - w) / 256, width);
+                    let old = &current[(current_y * width + current_x) * channels..][..channels];
+                    let new = &next[(next_y * width + next_x) * channels..][..channels];
+                    let pixel = &mut canvas[(y * width + x) * channels..][..channels];
+                    for ((pixel, old), new) in pixel.iter_mut().zip(old).zip(new) {
+                        let a = *old as u16 * (256 - weight);
+                        let b = *new as u16 * weight;
+                        *pixel = ((a + b) >> 8) as u8;
+                    }
+                }
+                x = end;
+            }
+        }
+    }
+}
+
+/// A block of the downscaled frames
+struct Block {
+    x: std::ops::Range<usize>,
+    y: std::ops::Range<usize>,
+}
+
+impl Block {
+    /// How far (in downscaled pixels) the block's contents moved from `current` to `next`. We
+    /// only move blocks whose match is clearly better than standing still, so that noise and
+    /// fading colors are crossfaded instead
+    fn motion(&self, current: &[u8], next: &[u8], width: usize, height: usize) -> (isize, isize) {
+        let difference = |dx: isize, dy: isize| -> u32 {
+            let mut sum = 0;
+            for y in self.y.clone() {
+                let from_y = (y as isize - dy).clamp(0, height as isize - 1) as usize;
+                for x in self.x.clone() {
+                    let from_x = (x as isize - dx).clamp(0, width as isize - 1) as usize;
+                    sum += next[y * width + x].abs_diff(current[from_y * width + from_x]) as u32;
+                }
+            }
+            sum
+        };
+
+        let still = difference(0, 0);
+        if still == 0 {
+            return (0, 0);
+        }
+        // on ties, the shortest motion wins, since flat areas match anywhere
+        let mut best = (still, 0, (0, 0));
+        for dy in -RADIUS..=RADIUS {
+            for dx in -RADIUS..=RADIUS {
+                let candidate = (difference(dx, dy), dx.abs() + dy.abs(), (dx, dy));
+                if candidate < best {
+                    best = candidate;
+                }
+            }
+        }
+        if best.0 * 4 < still * 3 {
+            best.2
+        } else {
+            (0, 0)
+        }
+    }
+}
+
+/// The average brightness of every `DOWNSCALE`x`DOWNSCALE` square of `frame`
+fn downscale(frame: &[u8], width: usize, height: usize, channels: usize) -> Box<[u8]> {
+    let (small_width, small_height) = (width / DOWNSCALE, height / DOWNSCALE);
+    let mut small = vec![0; small_width * small_height].into_boxed_slice();
+    for y in 0..small_height {
+        for x in 0..small_width {
+            let mut sum = 0u32;
+            for row in y * DOWNSCALE..(y + 1) * DOWNSCALE {
+                let start = (row * width + x * DOWNSCALE) * channels;
+                for pixel in frame[start..start + DOWNSCALE * channels].chunks_exact(channels) {
+                    sum += pixel[..3].iter().map(|b| *b as u32).sum::<u32>();
+                }
+            }
+            small[y * small_width + x] = (sum / (3 * (DOWNSCALE * DOWNSCALE) as u32)) as u8;
+        }
+    }
+    small
+}
+
+/// How many frames in a row may take too long to warp before we go back to crossfading
+const MAX_SLOW_FRAMES: u8 = 3;
+
+/// Decides when warping is too slow for the rate we draw at
+#[derive(Default)]
+pub(super) struct Fallback {
+    slow_frames: u8,
+}
+
+impl Fallback {
+    /// Records that warping a frame took `took`, when we have `budget` to draw each frame.
+    /// Returns whether we should keep warping. We keep half the budget for everything else
+    pub(super) fn keep_warping(&mut self, took: Duration, budget: Duration) -> bool {
+        if budget.is_zero() || took <= budget / 2 {
+            self.slow_frames = 0;
+        } else {
+            self.slow_frames = self.slow_frames.saturating_add(1);
+            if self.slow_frames == MAX_SLOW_FRAMES {
+                warn!("warping the animation takes too long for its frame rate, blending instead");
+            }
+        }
+        self.slow_frames < MAX_SLOW_FRAMES
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const WIDTH: usize = 128;
+    const HEIGHT: usize = 64;
+
+    /// A black frame with a white square, whose top left corner is at `x`
+    fn frame(x: usize) -> Vec<u8> {
+        let mut frame = vec![0; WIDTH * HEIGHT * 3];
+        for y in 16..48 {
+            for x in x..x + 32 {
+                frame[(y * WIDTH + x) * 3..][..3].fill(255);
+            }
+        }
+        frame
+    }
+
+    #[test]
+    fn moving_blocks_are_drawn_partway_along() {
+        let (current, next) = (frame(16), frame(32));
+        let flow = Flow::estimate(&current, &next, WIDTH, 3);
+        assert!(!flow.is_still());
+
+        let mut canvas = vec![0; current.len()];
+        flow.warp(&mut canvas, &current, &next, 128);
+        // halfway through, the square spans from 24 to 56, where a crossfade would show both
+        // squares at half brightness instead
+        let at = |x: usize| canvas[(32 * WIDTH + x) * 3];
+        assert_eq!(at(34), 255);
+        assert_eq!(at(52), 255);
+        assert!(at(60) < 128, "the square went too far");
+
+        flow.warp(&mut canvas, &current, &next, 0);
+        assert_eq!(canvas, current);
+    }
+
+    #[test]
+    fn still_frames_are_crossfaded() {
+        let current = frame(16);
+        let mut next = current.clone();
+        next.iter_mut().for_each(|b| *b /= 2);
+        let flow = Flow::estimate(&current, &next, WIDTH, 3);
+        assert!(flow.is_still());
+    }
+
+    #[test]
+    fn slow_warping_falls_back_to_crossfading() {
+        let budget = Duration::from_millis(16);
+        let mut fallback = Fallback::default();
+        assert!(fallback.keep_warping(Duration::from_millis(12), budget));
+        assert!(fallback.keep_warping(Duration::from_millis(2), budget));
+        assert!(fallback.keep_warping(Duration::from_millis(12), budget));
+        assert!(fallback.keep_warping(Duration::from_millis(12), budget));
+        assert!(!fallback.keep_warping(Duration::from_millis(12), budget));
+        assert!(fallback.keep_warping(Duration::from_millis(12), Duration::ZERO));
+    }
+}
@@ -1,16 +1,55 @@
+use std::{
+    num::{NonZeroU16, NonZeroU32},
+    path::PathBuf,
+    time::Duration,
+};
+
 use utils::ipc::PixelFormat;
 
+use crate::{wallpaper::OpaqueRegion, wayland::interfaces::zwlr_layer_shell_v1};
+
 pub struct Cli {
     pub format: Option<PixelFormat>,
+    /// `--format auto-preferred`: use the format the compositor prefers, if it tells us
+    pub prefer_compositor_format: bool,
+    pub swap_channels: Option<bool>,
+    pub max_fps: Option<NonZeroU16>,
+    /// in bytes
+    pub max_animation_memory: Option<usize>,
+    pub namespace: String,
     pub quiet: bool,
     pub no_cache: bool,
+    pub cache_dir: Option<PathBuf>,
+    pub single_pool: bool,
+    pub opaque_region: OpaqueRegion,
+    /// the zwlr_layer_shell_v1 layer to put our surfaces in
+    pub layer: u32,
+    pub idle_pause: Option<Duration>,
+    pub img_hook: Option<String>,
+    /// how long wallpapers fade into their image again after their output changes resolution
+    pub resize_fade_ms: Option<u32>,
+    /// draw every transition offscreen and exit, instead of starting the daemon
+    pub selftest: bool,
 }
 
 impl Cli {
     pub fn new() -> Self {
         let mut quiet = false;
         let mut no_cache = false;
+        let mut single_pool = false;
+        let mut selftest = false;
+        let mut cache_dir = None;
         let mut format = None;
+        let mut prefer_compositor_format = false;
+        let mut swap_channels = None;
+        let mut max_fps = None;
+        let mut max_animation_memory = None;
+        let mut opaque_region = OpaqueRegion::Full;
+        let mut layer = zwlr_layer_shell_v1::layer::BACKGROUND;
+        let mut idle_pause = None;
+        let mut img_hook = None;
+        let mut resize_fade_ms = None;
+        let mut namespace = std::env::var("SWWW_NAMESPACE").unwrap_or_default();
         let mut args = std::env::args();
         args.next(); // skip the first argument
 
@@ -21,21 +60,121 @@ impl Cli {
                     Some("xbgr") => format = Some(PixelFormat::Xbgr),
                     Some("rgb") => format = Some(PixelFormat::Rgb),
                     Some("bgr") => format = Some(PixelFormat::Bgr),
+                    Some("auto-preferred") => prefer_compositor_format = true,
+                    _ => {
+                        eprintln!("`--format` command line option must be one of: 'xrgb', 'xbgr', 'rgb', 'bgr' or 'auto-preferred'");
+                        std::process::exit(-2);
+                    }
+                },
+                "--swap-channels" => {
+                    match args.next().as_deref() {
+                        Some("on") => swap_channels = Some(true),
+                        Some("off") => swap_channels = Some(false),
+                        _ => {
+                            eprintln!("`--swap-channels` command line option must be one of: 'on' or 'off'");
+                            std::process::exit(-2);
+                        }
+                    }
+                }
+                "--max-fps" => {
+                    match args.next().as_deref().map(str::parse) {
+                        Some(Ok(fps)) => max_fps = Some(fps),
+                        _ => {
+                            eprintln!("`--max-fps` command line option must be a number between 1 and 65535");
+                            std::process::exit(-2);
+                        }
+                    }
+                }
+                "--max-animation-memory" => {
+                    match args.next().as_deref().map(str::parse::<NonZeroU32>) {
+                        Some(Ok(mib)) => max_animation_memory = Some((mib.get() as usize) << 20),
+                        _ => {
+                            eprintln!("`--max-animation-memory` command line option must be a positive number of MiB");
+                            std::process::exit(-2);
+                        }
+                    }
+                }
+                "--namespace" => {
+                    match args.next() {
+                        Some(ns) if !ns.contains('/') => namespace = ns,
+                        _ => {
+                            eprintln!("`--namespace` command line option must be given a name without '/'");
+                            std::process::exit(-2);
+                        }
+                    }
+                }
+                "--cache-dir" => match args.next() {
+                    Some(dir) if !dir.is_empty() => cache_dir = Some(PathBuf::from(dir)),
+                    _ => {
+                        eprintln!("`--cache-dir` command line option must be given a directory");
+                        std::process::exit(-2);
+                    }
+                },
+                "--opaque-region" => match args.next().as_deref().and_then(parse_opaque_region) {
+                    Some(region) => opaque_region = region,
+                    None => {
+                        eprintln!("`--opaque-region` command line option must be one of: 'full', 'none' or 4 comma separated margins (TOP,RIGHT,BOTTOM,LEFT)");
+                        std::process::exit(-2);
+                    }
+                },
+                "--no-opaque-region" => opaque_region = OpaqueRegion::None,
+                "--layer" => match args.next().as_deref() {
+                    Some("background") => layer = zwlr_layer_shell_v1::layer::BACKGROUND,
+                    Some("bottom") => layer = zwlr_layer_shell_v1::layer::BOTTOM,
                     _ => {
-                        eprintln!("`--format` command line option must be one of: 'xrgb', 'xbgr', 'rgb' or 'bgr'");
+                        eprintln!("`--layer` command line option must be one of: 'background' or 'bottom'");
                         std::process::exit(-2);
                     }
                 },
+                "--idle-pause" => match args.next().as_deref().map(str::parse::<NonZeroU32>) {
+                    Some(Ok(secs)) => idle_pause = Some(Duration::from_secs(secs.get() as u64)),
+                    _ => {
+                        eprintln!("`--idle-pause` command line option must be a positive number of seconds");
+                        std::process::exit(-2);
+                    }
+                },
+                "--img-hook" => match args.next() {
+                    Some(command) if !command.is_empty() => img_hook = Some(command),
+                    _ => {
+                        eprintln!("`--img-hook` command line option must be given a command");
+                        std::process::exit(-2);
+                    }
+                },
+                "--resize-fade" => {
+                    match args.next().as_deref().map(str::parse::<u32>) {
+                        Some(Ok(ms)) => resize_fade_ms = Some(ms),
+                        _ => {
+                            eprintln!("`--resize-fade` command line option must be a number of milliseconds");
+                            std::process::exit(-2);
+                        }
+                    }
+                }
                 "-q" | "--quiet" => quiet = true,
                 "--no-cache" => no_cache = true,
+                "--single-pool" => single_pool = true,
+                "--selftest" => selftest = true,
                 "-h" | "--help" => {
                     println!("swww-daemon");
                     println!();
                     println!("Options:");
                     println!();
-                    println!("  -f|--format <xrgb|xbgr|rgb|bgr>");
+                    println!("  -f|--format <xrgb|xbgr|rgb|bgr|auto-preferred>");
                     println!("          force the use of a specific wl_shm format.");
                     println!();
+                    println!(
+                        "          'auto-preferred' uses the format the compositor prefers, if it"
+                    );
+                    println!(
+                        "          says so (through linux-dmabuf feedback), and chooses for itself"
+                    );
+                    println!(
+                        "          otherwise. Among the formats the compositor prefers equally, it"
+                    );
+                    println!(
+                        "          picks the one it would have chosen itself (3 channel ones first)."
+                    );
+                    println!("          A format given explicitly always wins.");
+                    println!();
                     println!(
                         "          It is generally better to let swww-daemon chose for itself."
                     );
@@ -43,12 +182,103 @@ impl Cli {
                     println!("          Whatever you chose, make sure you compositor actually supports it!");
                     println!("          'xrgb' is the most compatible one.");
                     println!();
+                    println!("  --swap-channels <on|off>");
+                    println!("          force swapping the red and blue channels on or off.");
+                    println!();
+                    println!("          By default, this is derived from the wl_shm format.");
+                    println!("          Only use this as a workaround for drivers that display colors wrong.");
+                    println!();
+                    println!("  --max-fps <fps>");
+                    println!("          limit every transition and animation to at most this many frames per second.");
+                    println!();
+                    println!("          Useful to reduce CPU usage in low power devices. Animations whose frames");
+                    println!("          are shorter than that are played slower.");
+                    println!();
+                    println!("  --max-animation-memory <MiB>");
+                    println!("          limit the memory held by the animations being played, all together.");
+                    println!();
+                    println!("          Animations that would go over it are played without interpolation or");
+                    println!("          blending, unpacking each frame as it is due, or else only display their");
+                    println!("          first frame, with a warning. Streamed animations stop loading once they");
+                    println!("          no longer fit.");
+                    println!();
+                    println!("  --namespace <namespace>");
+                    println!("          listen on a socket of its own, so that many daemons can run at once.");
+                    println!();
+                    println!(
+                        "          Give 'swww' the same '--namespace' to talk to this daemon."
+                    );
+                    println!("          Defaults to the SWWW_NAMESPACE environment variable, if it is set.");
+                    println!();
                     println!("  --no-cache");
                     println!(
                         "         Don't search the cache for the last wallpaper for each output."
                     );
                     println!("          Useful if you always want to select which image 'swww' loads manually using 'swww img'");
                     println!();
+                    println!("  --cache-dir <dir>");
+                    println!("          use this directory for the cache, creating it if it does not exist.");
+                    println!();
+                    println!("          Same as setting the SWWW_CACHE_DIR environment variable. Make sure");
+                    println!(
+                        "          'swww' uses the same directory, or it will not find the cache."
+                    );
+                    println!();
+                    println!("  --single-pool");
+                    println!("          allocate the buffers of every output from a single shared memory pool.");
+                    println!();
+                    println!("          Uses fewer file descriptors and mappings, which helps memory constrained");
+                    println!("          devices. The pool never shrinks, though: its memory is only given back once");
+                    println!("          every output is gone.");
+                    println!();
+                    println!("  --opaque-region <full|none|TOP,RIGHT,BOTTOM,LEFT>");
+                    println!("          which part of the wallpaper to mark as opaque, when it is. Default: full");
+                    println!();
+                    println!("          Compositors do not draw (or blur) what is behind an opaque region.");
+                    println!("          Use 'none', or leave margins, if your compositor's blur rules should");
+                    println!("          apply to the wallpaper.");
+                    println!();
+                    println!("  --no-opaque-region");
+                    println!("          never mark the wallpaper as opaque. Same as '--opaque-region none'.");
+                    println!();
+                    println!("          Useful to debug transparency issues, but the compositor then has to");
+                    println!("          draw whatever is behind the wallpaper, which may reduce performance.");
+                    println!();
+                    println!("  --layer <background|bottom>");
+                    println!("          the layer-shell layer to display the wallpapers in. Default: background");
+                    println!();
+                    println!("          Use 'bottom' if other layer-shell clients in the background layer should");
+                    println!("          be drawn below the wallpaper.");
+                    println!();
+                    println!("  --idle-pause <seconds>");
+                    println!("          pause animations once the system is idle for this long.");
+                    println!();
+                    println!("          Requires a compositor supporting ext_idle_notifier_v1. Changing the");
+                    println!(
+                        "          wallpaper while idle plays its animation for that long again."
+                    );
+                    println!();
+                    println!("  --img-hook <command>");
+                    println!("          run a shell command whenever an output finishes displaying an image.");
+                    println!();
+                    println!("          It runs once per output, with the image's path and the output's name");
+                    println!("          as $1 and $2, as well as in the SWWW_IMAGE and SWWW_OUTPUT environment");
+                    println!("          variables. The daemon does not wait for it.");
+                    println!();
+                    println!("  --resize-fade <milliseconds>");
+                    println!("          how long wallpapers fade into their image again, resized, when their");
+                    println!(
+                        "          output changes resolution. 0 disables the fade. Default: 300"
+                    );
+                    println!();
+                    println!("  --selftest");
+                    println!("          draw every transition offscreen, print how many frames each one took,");
+                    println!(
+                        "          how long, and whether it reached the new image, then exit."
+                    );
+                    println!();
+                    println!("          Does not need a compositor. Exits with an error if any transition failed.");
+                    println!();
                     println!("  -q|--quiet    will only log errors");
                     println!("  -h|--help     print help");
                     println!("  -V|--version  print version");
@@ -68,8 +298,57 @@ impl Cli {
 
         Self {
             format,
+            prefer_compositor_format,
+            swap_channels,
+            max_fps,
+            max_animation_memory,
+            namespace,
             quiet,
             no_cache,
+            cache_dir,
+            single_pool,
+            opaque_region,
+            layer,
+            idle_pause,
+            img_hook,
+            resize_fade_ms,
+            selftest,
+        }
+    }
+}
+
+fn parse_opaque_region(arg: &str) -> Option<OpaqueRegion> {
+    match arg {
+        "full" => Some(OpaqueRegion::Full),
+        "none" => Some(OpaqueRegion::None),
+        margins => {
+            let mut inset = [0; 4];
+            let mut margins = margins.split(',');
+            for i in inset.iter_mut() {
+                *i = margins.next()?.trim().parse().ok()?;
+            }
+            margins
+                .next()
+                .is_none()
+                .then_some(OpaqueRegion::Inset(inset))
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn opaque_region_parsing() {
+        assert_eq!(parse_opaque_region("full"), Some(OpaqueRegion::Full));
+        assert_eq!(parse_opaque_region("none"), Some(OpaqueRegion::None));
+        assert_eq!(
+            parse_opaque_region("0,10,20,30"),
+            Some(OpaqueRegion::Inset([0, 10, 20, 30]))
+        );
+        assert_eq!(parse_opaque_region("0,10,20"), None);
+        assert_eq!(parse_opaque_region("0,10,20,30,40"), None);
+        assert_eq!(parse_opaque_region("0,10,-20,30"), None);
+    }
+}
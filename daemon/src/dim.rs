@@ -0,0 +1,425 @@
+//! `swww dim-schedule`: dims the wallpapers at night, following the time of day.
+//!
+//! We keep what each output displays before dimming it, so that static images can be dimmed
+//! again whenever the brightness changes. Animations and images blended over a base are only
+//! dimmed by the brightness they were displayed at.
+//!
+//! The sunrise and sunset at a location come from NOAA's general solar position equations, which
+//! are precise to a few minutes. That is more than enough for fading over an hour.
+
+use std::{
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
+
+use log::{debug, error, info};
+
+use utils::ipc::{ColorAdjustment, Daylight, DimSchedule, Transition, TransitionType};
+
+use crate::{
+    animations::Animator,
+    wallpaper::{Damage, Wallpaper},
+    wayland::globals,
+};
+
+/// How often we update the brightness while fading
+const FADE_STEP: Duration = Duration::from_secs(10);
+
+/// The longest we go without looking at the clock, in case it (or the timezone) changed
+const MAX_SLEEP: Duration = Duration::from_secs(60 * 60);
+
+const DAY_MINUTES: f64 = 24.0 * 60.0;
+
+/// What a wallpaper displays before we dim it
+#[derive(Clone)]
+pub(crate) struct Undimmed {
+    path: String,
+    img: Arc<[u8]>,
+    channels: usize,
+    /// the `swww img --brightness` and `--gamma` of the image
+    adjustment: ColorAdjustment,
+    /// the brightness the wallpaper is displayed at
+    drawn: f32,
+    /// when the transition to the image is (roughly) over, so that we do not draw over it
+    settled: Instant,
+}
+
+impl Undimmed {
+    pub(crate) fn new(
+        path: &str,
+        img: Arc<[u8]>,
+        channels: usize,
+        adjustment: ColorAdjustment,
+        drawn: f32,
+        settled: Instant,
+    ) -> Self {
+        Self {
+            path: path.to_string(),
+            img,
+            channels,
+            adjustment,
+            drawn,
+            settled,
+        }
+    }
+
+    pub(crate) fn path(&self) -> &str {
+        &self.path
+    }
+
+    pub(crate) fn set_drawn(&mut self, brightness: f32) {
+        self.drawn = brightness;
+    }
+}
+
+pub(crate) struct Dimmer {
+    schedule: DimSchedule,
+    /// the brightness the wallpapers are dimmed by now
+    brightness: f32,
+    next_update: Instant,
+    /// whether a thread is dimming the wallpapers again
+    redrawing: Arc<AtomicBool>,
+}
+
+impl Dimmer {
+    pub(crate) fn new(schedule: DimSchedule) -> Self {
+        info!("dimming the wallpapers with {schedule:?}");
+        Self {
+            schedule,
+            brightness: 1.0,
+            next_update: Instant::now(),
+            redrawing: Arc::new(AtomicBool::new(false)),
+        }
+    }
+
+    pub(crate) fn brightness(&self) -> f32 {
+        self.brightness
+    }
+
+    /// `adjustment`, dimmed by the current brightness
+    pub(crate) fn dim(&self, adjustment: ColorAdjustment) -> ColorAdjustment {
+        ColorAdjustment::new(adjustment.brightness * self.brightness, adjustment.gamma)
+    }
+
+    /// Recomputes the brightness, if it is time to, and dims the wallpapers again if it changed.
+    /// Returns how long until we must call this again
+    pub(crate) fn update(&mut self, wallpapers: &[Arc<Wallpaper>]) -> Duration {
+        let now = Instant::now();
+        if now < self.next_update {
+            return self.next_update - now;
+        }
+
+        let Some(time) = LocalTime::now() else {
+            self.next_update = now + MAX_SLEEP;
+            return MAX_SLEEP;
+        };
+        let sun = match self.schedule.daylight {
+            Daylight::Fixed { sunrise, sunset } => Sun::Rises {
+                sunrise: sunrise as f64,
+                sunset: sunset as f64,
+            },
+            Daylight::Location {
+                latitude,
+                longitude,
+            } => sun_times(
+                latitude as f64,
+                longitude as f64,
+                time.day_of_year,
+                time.utc_offset,
+            ),
+        };
+        let fade = self.schedule.fade.as_secs_f64() / 60.0;
+        let (brightness, mut sleep) =
+            brightness_at(time.minutes, sun, self.schedule.night_brightness, fade);
+        if brightness != self.brightness {
+            debug!("dimming the wallpapers to {brightness}");
+            self.brightness = brightness;
+        }
+        if redraw(wallpapers, self.brightness, &self.redrawing, false) {
+            sleep = sleep.min(FADE_STEP);
+        }
+        self.next_update = now + sleep;
+        sleep
+    }
+
+    /// Displays every wallpaper we dimmed as it is, and forgets what they displayed
+    pub(crate) fn stop(self, wallpapers: &[Arc<Wallpaper>]) {
+        info!("no longer dimming the wallpapers");
+        redraw(wallpapers, 1.0, &self.redrawing, true);
+        for wallpaper in wallpapers {
+            wallpaper.set_undimmed(None);
+        }
+    }
+}
+
+/// Roughly how long `transition` takes, so that we do not dim its wallpapers again before it is
+/// over
+pub(crate) fn settle_time(transition: &Transition) -> Duration {
+    let secs = match transition.transition_type {
+        // these take as many frames as they need
+        TransitionType::None | TransitionType::Simple => {
+            256.0 / transition.step.get() as f32 / transition.fps.max(1) as f32
+        }
+        _ => transition.duration,
+    };
+    Duration::try_from_secs_f32(secs)
+        .unwrap_or_default()
+        .saturating_add(Duration::from_secs(1))
+}
+
+/// Dims the wallpapers we know the undimmed contents of to `brightness`, in a new thread, since
+/// attaching the buffers waits for the compositor. Returns whether some of them are still in their
+/// transition, and must be dimmed later. With `force`, we neither wait for transitions nor for the
+/// previous redraw
+fn redraw(
+    wallpapers: &[Arc<Wallpaper>],
+    brightness: f32,
+    redrawing: &Arc<AtomicBool>,
+    force: bool,
+) -> bool {
+    if !force && redrawing.load(Ordering::Acquire) {
+        return true;
+    }
+
+    let now = Instant::now();
+    let mut pending = false;
+    let mut targets = Vec::new();
+    for wallpaper in wallpapers {
+        let Some(undimmed) = wallpaper.undimmed() else {
+            continue;
+        };
+        if !wallpaper.is_displaying(&undimmed.path) || wallpaper.is_animated() {
+            // it moved on to something we do not know the undimmed contents of
+            wallpaper.set_undimmed(None);
+        } else if undimmed.drawn == brightness {
+            continue;
+        } else if !force && undimmed.settled > now {
+            pending = true;
+        } else {
+            targets.push((Arc::clone(wallpaper), undimmed));
+        }
+    }
+    if targets.is_empty() {
+        return pending;
+    }
+
+    redrawing.store(true, Ordering::Release);
+    let redrawing = Arc::clone(redrawing);
+    std::thread::Builder::new()
+        .name("dim".to_string())
+        .stack_size(1 << 15)
+        .spawn(move || {
+            let channels = globals::pixel_format().channels() as usize;
+            let mut committed = Vec::new();
+            for (wallpaper, undimmed) in targets {
+                let Undimmed {
+                    path,
+                    img,
+                    channels: img_channels,
+                    adjustment,
+                    ..
+                } = undimmed;
+                let adjustment =
+                    ColorAdjustment::new(adjustment.brightness * brightness, adjustment.gamma);
+                let dimmed = Animator::adjust_img(&img, img_channels, &adjustment.table());
+                let width = wallpaper.get_dimensions().0 as usize;
+                let damage = wallpaper.canvas_change(|canvas| {
+                    if canvas.len() != dimmed.len() {
+                        return None;
+                    }
+                    Some(Damage::copy_changes(canvas, &dimmed, width, channels))
+                });
+                let Some(damage) = damage else {
+                    error!("{path} no longer fits its output, not dimming it");
+                    wallpaper.set_undimmed(None);
+                    continue;
+                };
+                if !damage.is_empty() {
+                    let wallpapers = [Arc::clone(&wallpaper)];
+                    // this waits for the frame callback of the output
+                    crate::wallpaper::attach_buffers_and_damange_surfaces(
+                        &wallpapers,
+                        Some(damage),
+                    );
+                    committed.push(Arc::clone(&wallpaper));
+                }
+                wallpaper.undimmed_drawn(&path, brightness);
+            }
+            if !committed.is_empty() {
+                crate::wallpaper::commit_wallpapers(&committed);
+            }
+            redrawing.store(false, Ordering::Release);
+        })
+        .unwrap(); // builder only fails if the name contains null bytes
+    pending
+}
+
+/// The local time, from the system's timezone
+struct LocalTime {
+    /// minutes since midnight
+    minutes: f64,
+    /// 0 based
+    day_of_year: u16,
+    /// in minutes
+    utc_offset: f64,
+}
+
+impl LocalTime {
+    fn now() -> Option<Self> {
+        // SAFETY: `time` accepts a null pointer, and `localtime_r` only writes to `tm`
+        let mut tm: libc::tm = unsafe { std::mem::zeroed() };
+        let time = unsafe { libc::time(std::ptr::null_mut()) };
+        if unsafe { libc::localtime_r(&time, &mut tm) }.is_null() {
+            error!("failed to get the local time, not dimming the wallpapers");
+            return None;
+        }
+        Some(Self {
+            minutes: tm.tm_hour as f64 * 60.0 + tm.tm_min as f64 + tm.tm_sec as f64 / 60.0,
+            day_of_year: tm.tm_yday as u16,
+            utc_offset: tm.tm_gmtoff as f64 / 60.0,
+        })
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Sun {
+    /// in minutes since local midnight
+    Rises { sunrise: f64, sunset: f64 },
+    /// polar day
+    AlwaysUp,
+    /// polar night
+    AlwaysDown,
+}
+
+/// The local sunrise and sunset on `day_of_year` at a location, in degrees. `utc_offset` is in
+/// minutes
+fn sun_times(latitude: f64, longitude: f64, day_of_year: u16, utc_offset: f64) -> Sun {
+    // the fractional year, in radians, at noon
+    let y = 2.0 * std::f64::consts::PI / 365.0 * day_of_year as f64;
+    // in minutes
+    let equation_of_time = 229.18
+        * (0.000075 + 0.001868 * y.cos()
+            - 0.032077 * y.sin()
+            - 0.014615 * (2.0 * y).cos()
+            - 0.040849 * (2.0 * y).sin());
+    let declination = 0.006918 - 0.399912 * y.cos() + 0.070257 * y.sin()
+        - 0.006758 * (2.0 * y).cos()
+        + 0.000907 * (2.0 * y).sin()
+        - 0.002697 * (3.0 * y).cos()
+        + 0.00148 * (3.0 * y).sin();
+
+    // the sun's center is 0.833 degrees below the horizon at sunrise, because of refraction and
+    // the size of its disk
+    let latitude = latitude.to_radians();
+    let cos_hour_angle = 90.833f64.to_radians().cos() / (latitude.cos() * declination.cos())
+        - latitude.tan() * declination.tan();
+    if cos_hour_angle > 1.0 {
+        return Sun::AlwaysDown;
+    } else if cos_hour_angle < -1.0 {
+        return Sun::AlwaysUp;
+    }
+    let hour_angle = cos_hour_angle.acos().to_degrees();
+
+    let local = |minutes: f64| (minutes + utc_offset).rem_euclid(DAY_MINUTES);
+    Sun::Rises {
+        sunrise: local(720.0 - 4.0 * (longitude + hour_angle) - equation_of_time),
+        sunset: local(720.0 - 4.0 * (longitude - hour_angle) - equation_of_time),
+    }
+}
+
+/// The brightness `minutes` after midnight, and how long until it changes. Around the sunrise and
+/// sunset, it fades linearly over `fade` minutes
+fn brightness_at(minutes: f64, sun: Sun, night: f32, fade: f64) -> (f32, Duration) {
+    let (sunrise, sunset) = match sun {
+        Sun::Rises { sunrise, sunset } => (sunrise, sunset),
+        Sun::AlwaysUp => return (1.0, MAX_SLEEP),
+        Sun::AlwaysDown => return (night, MAX_SLEEP),
+    };
+    let forward = |to: f64| (to - minutes).rem_euclid(DAY_MINUTES);
+    let distance = |to: f64| {
+        let forward = forward(to);
+        forward.min(DAY_MINUTES - forward)
+    };
+
+    let is_day =
+        (minutes - sunrise).rem_euclid(DAY_MINUTES) < (sunset - sunrise).rem_euclid(DAY_MINUTES);
+    let nearest = distance(sunrise).min(distance(sunset));
+    let half_fade = fade / 2.0;
+    let (day, sleep) = if nearest < half_fade {
+        let ramp = nearest / fade;
+        let day = if is_day { 0.5 + ramp } else { 0.5 - ramp };
+        (day, FADE_STEP)
+    } else {
+        let until_fade = forward(sunrise).min(forward(sunset)) - half_fade;
+        let sleep = Duration::from_secs_f64((until_fade * 60.0).max(1.0)).min(MAX_SLEEP);
+        (if is_day { 1.0 } else { 0.0 }, sleep)
+    };
+    (night + (1.0 - night) * day as f32, sleep)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn brightness_fades_around_sunrise_and_sunset() {
+        let sun = Sun::Rises {
+            sunrise: 7.0 * 60.0,
+            sunset: 19.0 * 60.0,
+        };
+        let at = |hour: f64, fade: f64| brightness_at(hour * 60.0, sun, 0.5, fade).0;
+        assert_eq!(at(12.0, 60.0), 1.0);
+        assert_eq!(at(0.0, 60.0), 0.5);
+        assert_eq!(at(6.0, 60.0), 0.5);
+        assert_eq!(at(7.0, 60.0), 0.75);
+        assert_eq!(at(19.25, 60.0), 0.625);
+        assert_eq!(at(6.99, 0.0), 0.5);
+        assert_eq!(at(7.0, 0.0), 1.0);
+
+        // the day may cross midnight
+        let sun = Sun::Rises {
+            sunrise: 22.0 * 60.0,
+            sunset: 6.0 * 60.0,
+        };
+        assert_eq!(brightness_at(0.0, sun, 0.5, 60.0).0, 1.0);
+        assert_eq!(brightness_at(12.0 * 60.0, sun, 0.5, 60.0).0, 0.5);
+    }
+
+    #[test]
+    fn brightness_is_updated_often_only_while_fading() {
+        let sun = Sun::Rises {
+            sunrise: 7.0 * 60.0,
+            sunset: 19.0 * 60.0,
+        };
+        assert_eq!(brightness_at(7.0 * 60.0, sun, 0.5, 60.0).1, FADE_STEP);
+        // the fade before the sunset starts at 18:30
+        assert_eq!(
+            brightness_at(18.0 * 60.0, sun, 0.5, 60.0).1,
+            Duration::from_secs(30 * 60)
+        );
+        assert_eq!(brightness_at(12.0 * 60.0, sun, 0.5, 60.0).1, MAX_SLEEP);
+    }
+
+    #[test]
+    fn sun_times_match_the_seasons() {
+        // around the march equinox, the day lasts about 12 hours everywhere
+        let Sun::Rises { sunrise, sunset } = sun_times(0.0, 0.0, 79, 0.0) else {
+            panic!("the sun should rise at the equator");
+        };
+        assert!((sunrise - 6.0 * 60.0).abs() < 15.0, "sunrise at {sunrise}");
+        assert!((sunset - 18.0 * 60.0).abs() < 15.0, "sunset at {sunset}");
+
+        // the timezone and the longitude shift it
+        let Sun::Rises { sunrise, .. } = sun_times(0.0, 15.0, 79, 60.0) else {
+            panic!("the sun should rise at the equator");
+        };
+        assert!((sunrise - 6.0 * 60.0).abs() < 15.0, "sunrise at {sunrise}");
+
+        assert_eq!(sun_times(80.0, 0.0, 172, 0.0), Sun::AlwaysUp);
+        assert_eq!(sun_times(80.0, 0.0, 355, 0.0), Sun::AlwaysDown);
+        assert_eq!(sun_times(-80.0, 0.0, 172, 0.0), Sun::AlwaysDown);
+    }
+}
@@ -0,0 +1,377 @@
+//! `swww freeze`: captures what the compositor is displaying on some outputs, through
+//! `zwlr_screencopy_manager_v1`, and sets it as their wallpaper.
+//!
+//! Each capture takes a few roundtrips: the compositor first tells us the buffer it wants, then
+//! copies the output into it, and finally tells us whether that worked. We only answer the client
+//! once every capture is done.
+
+use std::sync::Arc;
+
+use log::{debug, error};
+use rustix::fd::OwnedFd;
+use utils::ipc::{Answer, BgImg, ErrorKind, Mmap};
+
+use crate::{
+    wallpaper::Wallpaper,
+    wayland::{
+        globals,
+        interfaces::{
+            wl_buffer, wl_output::transform, wl_shm, wl_shm_pool, zwlr_screencopy_frame_v1,
+            zwlr_screencopy_manager_v1,
+        },
+        ObjectId, WlDynObj,
+    },
+};
+
+/// What queries report frozen outputs to be displaying
+pub(crate) const FROZEN: &str = "frozen desktop";
+
+pub(crate) struct Freeze {
+    /// the client that asked for the freeze. We only answer it once we are done
+    stream: OwnedFd,
+    captures: Vec<Capture>,
+    /// the outputs we failed to capture
+    failed: Vec<String>,
+}
+
+struct Capture {
+    frame: ObjectId,
+    wallpaper: Arc<Wallpaper>,
+    /// the buffer the compositor copies the output into, once it tells us what it should be like
+    buffer: Option<(ObjectId, Mmap, Frame)>,
+    y_invert: bool,
+}
+
+/// The layout of a captured frame, as the compositor describes it
+#[derive(Clone, Copy)]
+struct Frame {
+    format: u32,
+    width: u32,
+    height: u32,
+    stride: u32,
+}
+
+impl Freeze {
+    /// Starts capturing the outputs of `wallpapers`. On failure, `stream` is given back, so that
+    /// we can answer the client
+    pub(crate) fn start(
+        stream: OwnedFd,
+        wallpapers: Vec<Arc<Wallpaper>>,
+    ) -> Result<Self, (OwnedFd, String)> {
+        let Some(manager) = globals::screencopy_manager() else {
+            return Err((
+                stream,
+                "the compositor does not support zwlr_screencopy_manager_v1".to_string(),
+            ));
+        };
+        if wallpapers.is_empty() {
+            return Err((stream, "none of the requested outputs exist".to_string()));
+        }
+        let captures = wallpapers
+            .into_iter()
+            .map(|wallpaper| {
+                let frame = globals::object_create(WlDynObj::ScreencopyFrame);
+                zwlr_screencopy_manager_v1::req::capture_output(
+                    manager,
+                    frame,
+                    0,
+                    wallpaper.output(),
+                )
+                .unwrap();
+                Capture {
+                    frame,
+                    wallpaper,
+                    buffer: None,
+                    y_invert: false,
+                }
+            })
+            .collect();
+        Ok(Self {
+            stream,
+            captures,
+            failed: Vec::new(),
+        })
+    }
+
+    pub(crate) fn is_done(&self) -> bool {
+        self.captures.is_empty()
+    }
+
+    /// Answers the client. Only call this once we are done
+    pub(crate) fn finish(self) {
+        let answer = if self.failed.is_empty() {
+            Answer::Ok
+        } else {
+            Answer::Err(
+                ErrorKind::Compositor,
+                format!("failed to capture: {}", self.failed.join(", ")),
+            )
+        };
+        if let Err(e) = answer.send(&self.stream) {
+            error!("error sending answer to client: {e}");
+        }
+    }
+
+    fn capture(&mut self, frame: ObjectId) -> Option<&mut Capture> {
+        self.captures.iter_mut().find(|c| c.frame == frame)
+    }
+
+    /// Creates the buffer the compositor asked for, and asks it to copy the output into it
+    pub(crate) fn buffer(
+        &mut self,
+        frame: ObjectId,
+        format: u32,
+        width: u32,
+        height: u32,
+        stride: u32,
+    ) {
+        let Some(capture) = self.capture(frame) else {
+            return;
+        };
+        if capture.buffer.is_some() {
+            // version 1 only ever sends one buffer event, but we do not need more than one anyway
+            return;
+        }
+        if !is_supported_format(format) || stride < width * 4 {
+            debug!("unsupported screencopy buffer format: {format:#x}");
+            self.fail(frame);
+            return;
+        }
+
+        let len = stride as usize * height as usize;
+        let memory = Mmap::create(len);
+        let pool = globals::object_create(WlDynObj::ShmPool);
+        wl_shm::req::create_pool(pool, &memory.fd(), len as i32).unwrap();
+        let buffer = globals::object_create(WlDynObj::Buffer);
+        wl_shm_pool::req::create_buffer(
+            pool,
+            buffer,
+            0,
+            width as i32,
+            height as i32,
+            stride as i32,
+            format,
+        )
+        .unwrap();
+        // the buffer keeps the memory alive on the compositor's side
+        wl_shm_pool::req::destroy(pool).unwrap();
+        zwlr_screencopy_frame_v1::req::copy(frame, buffer).unwrap();
+
+        let frame_info = Frame {
+            format,
+            width,
+            height,
+            stride,
+        };
+        if let Some(capture) = self.capture(frame) {
+            capture.buffer = Some((buffer, memory, frame_info));
+        }
+    }
+
+    pub(crate) fn flags(&mut self, frame: ObjectId, flags: u32) {
+        if let Some(capture) = self.capture(frame) {
+            capture.y_invert = flags & zwlr_screencopy_frame_v1::flags::Y_INVERT != 0;
+        }
+    }
+
+    /// Displays the captured frame as the wallpaper
+    pub(crate) fn ready(&mut self, frame: ObjectId) {
+        let Some(i) = self.captures.iter().position(|c| c.frame == frame) else {
+            return;
+        };
+        let capture = self.captures.swap_remove(i);
+        let Some((buffer, memory, frame_info)) = capture.buffer else {
+            error!("compositor sent a screencopy frame without a buffer");
+            self.failed.push(capture.wallpaper.get_bg_info().name);
+            destroy(frame, None);
+            return;
+        };
+
+        let wallpaper = capture.wallpaper;
+        let dim = wallpaper.get_dimensions();
+        let img = frame_to_canvas(
+            memory.slice(),
+            frame_info,
+            wallpaper.transform(),
+            capture.y_invert,
+            dim,
+            globals::pixel_format().channels() as usize,
+            globals::must_swap_r_and_b_channels(),
+        );
+        destroy(frame, Some(buffer));
+
+        // waiting for the frame callback would block the main loop, so we draw in another thread,
+        // like `swww clear` does
+        std::thread::Builder::new()
+            .stack_size(1 << 15)
+            .name("freeze".to_string())
+            .spawn(move || {
+                let wallpapers = [wallpaper];
+                crate::wallpaper::stop_animations(&wallpapers);
+                let wallpaper = &wallpapers[0];
+                wallpaper.set_playing_animation(None);
+                wallpaper.set_transparent(false);
+                wallpaper.set_img_info(BgImg::Img(FROZEN.to_string()));
+                wallpaper.canvas_change(|canvas| {
+                    if canvas.len() == img.len() {
+                        canvas.copy_from_slice(&img);
+                    }
+                });
+                crate::wallpaper::attach_buffers_and_damange_surfaces(&wallpapers, None);
+                crate::wallpaper::commit_wallpapers(&wallpapers);
+            })
+            .unwrap(); // builder only fails if the name contains null bytes
+    }
+
+    pub(crate) fn fail(&mut self, frame: ObjectId) {
+        let Some(i) = self.captures.iter().position(|c| c.frame == frame) else {
+            return;
+        };
+        let capture = self.captures.swap_remove(i);
+        let name = capture.wallpaper.get_bg_info().name;
+        error!("failed to capture output {name}");
+        self.failed.push(name);
+        destroy(frame, capture.buffer.map(|(buffer, _, _)| buffer));
+    }
+
+    /// Gives up on the captures of outputs that went away
+    pub(crate) fn retain_outputs(&mut self, wallpapers: &[Arc<Wallpaper>]) {
+        let (kept, gone): (Vec<_>, Vec<_>) = std::mem::take(&mut self.captures)
+            .into_iter()
+            .partition(|c| wallpapers.iter().any(|w| Arc::ptr_eq(w, &c.wallpaper)));
+        self.captures = kept;
+        for capture in gone {
+            self.failed.push(capture.wallpaper.get_bg_info().name);
+            destroy(capture.frame, capture.buffer.map(|(buffer, _, _)| buffer));
+        }
+    }
+}
+
+fn destroy(frame: ObjectId, buffer: Option<ObjectId>) {
+    if let Err(e) = zwlr_screencopy_frame_v1::req::destroy(frame) {
+        error!("failed to destroy zwlr_screencopy_frame_v1: {e}");
+    }
+    if let Some(buffer) = buffer {
+        if let Err(e) = wl_buffer::req::destroy(buffer) {
+            error!("failed to destroy wl_buffer: {e}");
+        }
+    }
+}
+
+fn is_supported_format(format: u32) -> bool {
+    matches!(
+        format,
+        wl_shm::format::XRGB8888
+            | wl_shm::format::ARGB8888
+            | wl_shm::format::XBGR8888
+            | wl_shm::format::ABGR8888
+    )
+}
+
+/// Converts a captured frame into the contents of a canvas with dimensions `dim`
+///
+/// The frame is in the output's own orientation, while our canvas is in the orientation the
+/// compositor displays it in, so we must undo the output's `transform`. It is then scaled (without
+/// interpolation, since it usually already has the right size) to fill the canvas.
+fn frame_to_canvas(
+    data: &[u8],
+    frame: Frame,
+    transform: u32,
+    y_invert: bool,
+    dim: (u32, u32),
+    channels: usize,
+    swap: bool,
+) -> Box<[u8]> {
+    let rotated = matches!(
+        transform,
+        transform::_90 | transform::_270 | transform::FLIPPED_90 | transform::FLIPPED_270
+    );
+    // the frame's dimensions, in the orientation it is displayed in
+    let (w, h) = if rotated {
+        (frame.height, frame.width)
+    } else {
+        (frame.width, frame.height)
+    };
+    let bgr_order = matches!(
+        frame.format,
+        wl_shm::format::XRGB8888 | wl_shm::format::ARGB8888
+    );
+
+    let mut canvas = vec![0; dim.0 as usize * dim.1 as usize * channels];
+    for (i, pixel) in canvas.chunks_exact_mut(channels).enumerate() {
+        let x = (i as u64 % dim.0 as u64 * w as u64 / dim.0 as u64) as u32;
+        let y = (i as u64 / dim.0 as u64 * h as u64 / dim.1 as u64) as u32;
+        let (fx, fy) = match transform {
+            transform::_90 => (y, w - 1 - x),
+            transform::_180 => (w - 1 - x, h - 1 - y),
+            transform::_270 => (h - 1 - y, x),
+            transform::FLIPPED => (w - 1 - x, y),
+            transform::FLIPPED_90 => (y, x),
+            transform::FLIPPED_180 => (x, h - 1 - y),
+            transform::FLIPPED_270 => (h - 1 - y, w - 1 - x),
+            _ => (x, y),
+        };
+        let fy = if y_invert { frame.height - 1 - fy } else { fy };
+        let j = fy as usize * frame.stride as usize + fx as usize * 4;
+        let src = &data[j..j + 3];
+        let (r, g, b) = if bgr_order {
+            (src[2], src[1], src[0])
+        } else {
+            (src[0], src[1], src[2])
+        };
+        let (r, b) = if swap { (b, r) } else { (r, b) };
+        pixel[0] = r;
+        pixel[1] = g;
+        pixel[2] = b;
+        if channels == 4 {
+            pixel[3] = 255;
+        }
+    }
+    canvas.into_boxed_slice()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A 2x1 XBGR8888 frame: a red pixel followed by a blue one
+    const DATA: [u8; 8] = [255, 0, 0, 0, 0, 0, 255, 0];
+    const FRAME: Frame = Frame {
+        format: wl_shm::format::XBGR8888,
+        width: 2,
+        height: 1,
+        stride: 8,
+    };
+
+    #[test]
+    fn frames_are_converted_to_the_canvas_format() {
+        let canvas = frame_to_canvas(&DATA, FRAME, transform::NORMAL, false, (2, 1), 3, false);
+        assert_eq!(&*canvas, [255, 0, 0, 0, 0, 255]);
+        // our canvas is in bgr order, with an extra channel
+        let canvas = frame_to_canvas(&DATA, FRAME, transform::NORMAL, false, (2, 1), 4, true);
+        assert_eq!(&*canvas, [0, 0, 255, 255, 255, 0, 0, 255]);
+
+        let frame = Frame {
+            format: wl_shm::format::XRGB8888,
+            ..FRAME
+        };
+        let canvas = frame_to_canvas(&DATA, frame, transform::NORMAL, false, (2, 1), 3, false);
+        assert_eq!(&*canvas, [0, 0, 255, 255, 0, 0]);
+    }
+
+    #[test]
+    fn frames_are_transformed_and_scaled() {
+        // a rotated output is displayed sideways, so its 2x1 frame becomes 1x2
+        let canvas = frame_to_canvas(&DATA, FRAME, transform::_90, false, (1, 2), 3, false);
+        assert_eq!(&*canvas, [255, 0, 0, 0, 0, 255]);
+        let canvas = frame_to_canvas(&DATA, FRAME, transform::_270, false, (1, 2), 3, false);
+        assert_eq!(&*canvas, [0, 0, 255, 255, 0, 0]);
+        let canvas = frame_to_canvas(&DATA, FRAME, transform::FLIPPED, false, (2, 1), 3, false);
+        assert_eq!(&*canvas, [0, 0, 255, 255, 0, 0]);
+
+        let canvas = frame_to_canvas(&DATA, FRAME, transform::NORMAL, false, (4, 2), 3, false);
+        let red_blue = [255, 0, 0, 255, 0, 0, 0, 0, 255, 0, 0, 255];
+        assert_eq!(&canvas[..12], red_blue);
+        assert_eq!(&canvas[12..], red_blue);
+    }
+}
@@ -0,0 +1,69 @@
+//! `swww-daemon --img-hook`: runs a command whenever an output finishes displaying a new image, to
+//! integrate with other tools (like generating a color scheme from it).
+//!
+//! The command runs through `sh -c`, in a thread of its own that waits for it, so that a slow
+//! command never blocks us, and does not leave a zombie process behind once it exits.
+
+use std::{
+    process::{Command, Stdio},
+    sync::OnceLock,
+};
+
+use log::{debug, warn};
+
+static IMG_HOOK: OnceLock<String> = OnceLock::new();
+
+pub(crate) fn set_img_hook(command: String) {
+    let _ = IMG_HOOK.set(command);
+}
+
+/// Runs the `--img-hook` command, if there is one, now that `output` displays the image at `path`
+pub(crate) fn img_displayed(path: &str, output: &str) {
+    let Some(command) = IMG_HOOK.get() else {
+        return;
+    };
+    debug!("running --img-hook for {path} on {output}");
+    let mut command = img_hook_command(command, path, output);
+    std::thread::Builder::new()
+        .name("img hook".to_string())
+        .stack_size(1 << 14)
+        .spawn(move || match command.status() {
+            Ok(status) if !status.success() => warn!("--img-hook command exited with {status}"),
+            Ok(_) => (),
+            Err(e) => warn!("failed to run --img-hook command: {e}"),
+        })
+        .unwrap(); // builder only fails if the name contains null bytes
+}
+
+/// The image and the output are both positional parameters (`$1` and `$2`) and environment
+/// variables (`SWWW_IMAGE` and `SWWW_OUTPUT`)
+fn img_hook_command(command: &str, path: &str, output: &str) -> Command {
+    let mut cmd = Command::new("sh");
+    cmd.arg("-c")
+        .arg(command)
+        .arg("swww-daemon")
+        .arg(path)
+        .arg(output)
+        .env("SWWW_IMAGE", path)
+        .env("SWWW_OUTPUT", output)
+        .stdin(Stdio::null());
+    cmd
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn img_hook_gets_the_image_and_the_output() {
+        let output = img_hook_command(
+            r#"printf '%s %s %s %s' "$1" "$2" "$SWWW_IMAGE" "$SWWW_OUTPUT""#,
+            "/a b.png",
+            "DP-1",
+        )
+        .output()
+        .unwrap();
+        assert!(output.status.success());
+        assert_eq!(output.stdout, b"/a b.png DP-1 /a b.png DP-1");
+    }
+}
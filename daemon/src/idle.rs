@@ -0,0 +1,112 @@
+//! `swww-daemon --idle-pause`: pauses animations while the system is idle, through
+//! `ext_idle_notifier_v1`.
+//!
+//! The compositor tells us once the seat has been inactive for our timeout, and again once it is
+//! not. Wallpaper changes count as activity as well: while idle, a new wallpaper animates for
+//! another timeout before we pause it.
+
+use std::time::{Duration, Instant};
+
+use log::{info, warn};
+
+use crate::wayland::{globals, interfaces::ext_idle_notifier_v1, ObjectId, WlDynObj};
+
+pub(crate) struct IdlePause {
+    notification: ObjectId,
+    timeout: Duration,
+    /// whether the compositor told us the seat is idle
+    idle: bool,
+    last_change: Instant,
+    paused: bool,
+}
+
+impl IdlePause {
+    /// Asks the compositor to tell us when the seat is inactive for `timeout`. Returns `None` if
+    /// it does not support `ext_idle_notifier_v1`
+    pub(crate) fn new(timeout: Duration) -> Option<Self> {
+        let (Some(notifier), Some(seat)) = (globals::idle_notifier(), globals::seat()) else {
+            warn!("the compositor does not support ext_idle_notifier_v1, ignoring --idle-pause");
+            return None;
+        };
+        let notification = globals::object_create(WlDynObj::IdleNotification);
+        let millis = timeout.as_millis().min(u32::MAX as u128) as u32;
+        ext_idle_notifier_v1::req::get_idle_notification(notifier, notification, millis, seat)
+            .unwrap();
+        Some(Self {
+            notification,
+            timeout,
+            idle: false,
+            last_change: Instant::now(),
+            paused: false,
+        })
+    }
+
+    pub(crate) fn notification(&self) -> ObjectId {
+        self.notification
+    }
+
+    pub(crate) fn set_idle(&mut self, idle: bool) {
+        self.idle = idle;
+        self.update();
+    }
+
+    pub(crate) fn wallpaper_changed(&mut self) {
+        self.last_change = Instant::now();
+        self.update();
+    }
+
+    /// Pauses or resumes the animations, if it is time to. Returns how long until we must call
+    /// this again
+    pub(crate) fn update(&mut self) -> Option<Duration> {
+        let (paused, timeout) = self.state(self.last_change.elapsed());
+        if paused != self.paused {
+            self.paused = paused;
+            if paused {
+                info!("the system is idle, pausing animations");
+            } else {
+                info!("resuming animations");
+            }
+            crate::animations::set_paused(paused);
+        }
+        timeout
+    }
+
+    /// Whether the animations should be paused, `since_change` after the last wallpaper change,
+    /// and how long until that changes on its own
+    fn state(&self, since_change: Duration) -> (bool, Option<Duration>) {
+        if !self.idle {
+            return (false, None);
+        }
+        match self.timeout.checked_sub(since_change) {
+            Some(remaining) if !remaining.is_zero() => (false, Some(remaining)),
+            _ => (true, None),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::num::NonZeroU32;
+
+    #[test]
+    fn pauses_once_idle_and_unchanged_for_the_timeout() {
+        let mut idle = IdlePause {
+            notification: ObjectId::new(NonZeroU32::new(100).unwrap()),
+            timeout: Duration::from_secs(60),
+            idle: false,
+            last_change: Instant::now(),
+            paused: false,
+        };
+        assert_eq!(idle.state(Duration::from_secs(120)), (false, None));
+
+        idle.idle = true;
+        assert_eq!(idle.state(Duration::from_secs(120)), (true, None));
+        assert_eq!(idle.state(Duration::from_secs(60)), (true, None));
+        // a wallpaper changed while idle: it animates until a timeout after the change
+        assert_eq!(
+            idle.state(Duration::from_secs(15)),
+            (false, Some(Duration::from_secs(45)))
+        );
+    }
+}
@@ -4,16 +4,23 @@
 
 mod animations;
 mod cli;
+mod dim;
+mod freeze;
+mod hook;
+mod idle;
+mod mode_change;
+mod subscribers;
 mod wallpaper;
 #[allow(dead_code)]
 mod wayland;
+mod workspaces;
 use log::{debug, error, info, warn, LevelFilter};
 use rustix::{
     event::{poll, PollFd, PollFlags},
     fd::OwnedFd,
 };
 
-use wallpaper::Wallpaper;
+use wallpaper::{OpaqueRegion, ShellSurface, Wallpaper};
 use wayland::{
     globals::{self, Initializer},
     ObjectId,
@@ -22,20 +29,27 @@ use wayland::{
 use std::{
     fs,
     io::{IsTerminal, Write},
-    num::{NonZeroI32, NonZeroU32},
+    num::{NonZeroI32, NonZeroU16, NonZeroU32},
     path::PathBuf,
     sync::{
         atomic::{AtomicBool, Ordering},
-        Arc,
+        Arc, OnceLock,
     },
+    time::{Duration, Instant},
 };
 
 use utils::ipc::{
-    connect_to_socket, get_socket_path, read_socket, Answer, BgInfo, ImageReq, MmappedStr,
-    RequestRecv, RequestSend, Scale,
+    connect_to_socket, get_socket_path, read_socket, Animation, Answer, BgImg, BgInfo, Blend,
+    BoundGlobal, CacheEntry, ColorAdjustment, Diagnostics, DimSchedule, DimScheduleReq, ErrorKind,
+    GetBufferReq, ImageReq, ImgReq, MmappedStr, PixelFormat, Region, RequestRecv, RequestSend,
+    Scale, ShowPreloadedReq, Transition, TransitionBuilder, WallpaperBuffer, WorkspaceReq,
 };
 
-use animations::Animator;
+use animations::{
+    memory::{self, Reservation},
+    Animator, FrameStream,
+};
+use subscribers::Subscribers;
 
 // We need this because this might be set by signals, so we can't keep it in the daemon
 static EXIT: AtomicBool = AtomicBool::new(false);
@@ -48,6 +62,13 @@ fn should_daemon_exit() -> bool {
     EXIT.load(Ordering::Acquire)
 }
 
+/// `swww-daemon --namespace`, so that the `swww` commands we run reach us
+static NAMESPACE: OnceLock<String> = OnceLock::new();
+
+pub(crate) fn namespace() -> &'static str {
+    NAMESPACE.get().map_or("", String::as_str)
+}
+
 extern "C" fn signal_handler(_s: libc::c_int) {
     exit_daemon();
 }
@@ -57,10 +78,72 @@ struct Daemon {
     animator: Animator,
     use_cache: bool,
     fractional_scale_manager: Option<(ObjectId, NonZeroU32)>,
+    subscribers: Subscribers,
+    /// transition used by image requests that do not specify one
+    default_transition: Transition,
+    /// the wl_registry whose events we follow. It only changes when we reset
+    registry: ObjectId,
+    reset: Option<Reset>,
+    freeze: Option<freeze::Freeze>,
+    preloaded: Vec<Preloaded>,
+    /// the arguments set with `swww img --sticky`, to display an image on new outputs
+    sticky: Option<Box<[String]>>,
+    opaque_region: OpaqueRegion,
+    /// the zwlr_layer_shell_v1 layer our surfaces go in
+    layer: u32,
+    /// pauses animations while the system is idle, with `--idle-pause`
+    idle: Option<idle::IdlePause>,
+    /// the global name of `ext_workspace_manager_v1`, if the compositor supports it
+    workspace_manager: Option<u32>,
+    /// only exists once a client set an image for a workspace
+    workspaces: Option<workspaces::Workspaces>,
+    /// `swww dim-schedule`
+    dimmer: Option<dim::Dimmer>,
+}
+
+/// How long we keep a preloaded image, if it is not displayed
+const PRELOAD_TIMEOUT: Duration = Duration::from_secs(10 * 60);
+
+/// An image sent with `RequestRecv::Preload`, ready for `RequestRecv::ShowPreloaded` to display it
+struct Preloaded {
+    img: ImgReq,
+    animation: Option<Animation>,
+    /// `--max-animation-memory`: what `animation` takes, handed to the wallpapers displaying it
+    memory: Option<Arc<Reservation>>,
+    /// the arguments to display `img` again with, see `Wallpaper::replay`
+    replay: Option<Arc<[String]>>,
+    outputs: Vec<String>,
+    adjustment: ColorAdjustment,
+    expires: Instant,
+}
+
+/// An ongoing reset, started by `RequestRecv::Reset`. It takes two roundtrips to the compositor:
+/// one to learn its globals through a new wl_registry, and another to learn the formats of a newly
+/// bound wl_shm. Only after that do we recreate the outputs.
+///
+/// Note neither wl_registry nor (version 1 of) wl_shm can be destroyed, so each reset leaves one
+/// of each behind for as long as we are connected. That is fine, since resets should be rare.
+struct Reset {
+    /// the client that asked for the reset. We only answer it once we are done
+    stream: OwnedFd,
+    /// the callback that marks the end of the current roundtrip
+    callback: ObjectId,
+    shm_name: Option<u32>,
+    shm: Option<ObjectId>,
+    /// the wl_shm formats the compositor advertised, out of the ones we support
+    formats: Vec<PixelFormat>,
+    output_names: Vec<u32>,
 }
 
 impl Daemon {
-    fn new(initializer: &Initializer, no_cache: bool) -> Self {
+    fn new(
+        initializer: &Initializer,
+        no_cache: bool,
+        max_fps: Option<NonZeroU16>,
+        opaque_region: OpaqueRegion,
+        layer: u32,
+        idle_pause: Option<Duration>,
+    ) -> Self {
         log::info!(
             "Selected wl_shm format: {:?}",
             wayland::globals::pixel_format()
@@ -69,11 +152,52 @@ impl Daemon {
 
         let wallpapers = Vec::new();
 
+        // like `swww img` does when the fps is not given
+        let builtin = || {
+            TransitionBuilder::default()
+                .fps(Transition::FPS_AUTO)
+                .build()
+        };
+        let default_transition = match utils::cache::load_default_transition() {
+            Ok(Some(transition)) => {
+                info!("Loaded default transition from cache");
+                transition
+            }
+            Ok(None) => builtin(),
+            Err(e) => {
+                warn!("failed to load default transition from cache: {e}");
+                builtin()
+            }
+        };
+
         Self {
             wallpapers,
-            animator: Animator::new(),
+            animator: Animator::new(max_fps),
             use_cache: !no_cache,
             fractional_scale_manager,
+            subscribers: Subscribers::new(),
+            default_transition,
+            registry: globals::WL_REGISTRY,
+            reset: None,
+            freeze: None,
+            preloaded: Vec::new(),
+            sticky: None,
+            opaque_region,
+            layer,
+            idle: idle_pause.and_then(idle::IdlePause::new),
+            workspace_manager: initializer.workspace_manager(),
+            workspaces: None,
+            dimmer: None,
+        }
+    }
+
+    /// Applies the changes the compositor sent for `wallpaper`, displaying its image again if its
+    /// output changed resolution
+    fn commit_surface_changes(&self, wallpaper: &Arc<Wallpaper>) {
+        if let Some(resized) =
+            wallpaper.commit_surface_changes(self.use_cache, self.sticky.as_deref())
+        {
+            mode_change::redraw(Arc::clone(wallpaper), resized);
         }
     }
 
@@ -91,15 +215,27 @@ impl Daemon {
         wl_surface::req::set_input_region(surface, Some(region)).unwrap();
         wl_region::req::destroy(region).unwrap();
 
-        let layer_surface = globals::object_create(wayland::WlDynObj::LayerSurface);
-        zwlr_layer_shell_v1::req::get_layer_surface(
-            layer_surface,
-            surface,
-            Some(output),
-            zwlr_layer_shell_v1::layer::BACKGROUND,
-            "swww-daemon",
-        )
-        .unwrap();
+        let shell_surface = if globals::xdg_shell_fallback() {
+            let xdg_surface = globals::object_create(wayland::WlDynObj::XdgSurface);
+            xdg_wm_base::req::get_xdg_surface(xdg_surface, surface).unwrap();
+            let toplevel = globals::object_create(wayland::WlDynObj::XdgToplevel);
+            xdg_surface::req::get_toplevel(xdg_surface, toplevel).unwrap();
+            ShellSurface::Xdg {
+                surface: xdg_surface,
+                toplevel,
+            }
+        } else {
+            let layer_surface = globals::object_create(wayland::WlDynObj::LayerSurface);
+            zwlr_layer_shell_v1::req::get_layer_surface(
+                layer_surface,
+                surface,
+                Some(output),
+                self.layer,
+                "swww-daemon",
+            )
+            .unwrap();
+            ShellSurface::Layer(layer_surface)
+        };
 
         let viewport = globals::object_create(wayland::WlDynObj::Viewport);
         wp_viewporter::req::get_viewport(viewport, surface).unwrap();
@@ -120,13 +256,19 @@ impl Daemon {
             surface,
             viewport,
             wp_fractional,
-            layer_surface,
+            shell_surface,
+            self.opaque_region,
         )));
+        subscribers::notify_changed();
     }
 
     fn recv_socket_msg(&mut self, stream: OwnedFd) {
-        let bytes = match utils::ipc::read_socket(&stream) {
-            Ok(bytes) => bytes,
+        let bytes = match utils::ipc::read_request(&stream) {
+            Ok(Some(bytes)) => bytes,
+            Ok(None) => {
+                warn!("rejected a client that speaks another version of the socket protocol");
+                return;
+            }
             Err(e) => {
                 error!("FATAL: cannot read socket: {e}. Exiting...");
                 exit_daemon();
@@ -134,24 +276,101 @@ impl Daemon {
             }
         };
         let request = RequestRecv::receive(bytes);
+        let subscribe = matches!(request, RequestRecv::Subscribe);
         let answer = match request {
+            RequestRecv::Reset => {
+                if self.reset.is_some() {
+                    Answer::Err(
+                        ErrorKind::Busy,
+                        "the daemon is already resetting".to_string(),
+                    )
+                } else {
+                    // we answer once the reset is done
+                    self.start_reset(stream);
+                    return;
+                }
+            }
+            RequestRecv::Freeze(freeze) => {
+                if self.freeze.is_some() {
+                    Answer::Err(
+                        ErrorKind::Busy,
+                        "the daemon is already freezing outputs".to_string(),
+                    )
+                } else {
+                    let wallpapers = self.find_wallpapers_by_names(&freeze.outputs);
+                    match freeze::Freeze::start(stream, wallpapers) {
+                        // we answer once every capture is done
+                        Ok(freeze) => {
+                            self.freeze = Some(freeze);
+                            return;
+                        }
+                        Err((stream, e)) => {
+                            let answer = Answer::Err(ErrorKind::Compositor, e);
+                            if let Err(e) = answer.send(&stream) {
+                                error!("error sending answer to client: {e}");
+                            }
+                            return;
+                        }
+                    }
+                }
+            }
+            RequestRecv::Batch(requests) => self.batch(requests),
+            RequestRecv::Img(img) if img.streamed.is_some() => {
+                // we answer right away, and then receive the frames through the same stream
+                let streams: Box<[Arc<FrameStream>]> = img
+                    .streamed
+                    .iter()
+                    .flatten()
+                    .map(|animation| Arc::new(FrameStream::new(*animation)))
+                    .collect();
+                let adjustment = img.adjustment;
+                let answer = self.img(img, Some(streams.clone()));
+                match answer.send(&stream) {
+                    Ok(()) => animations::load_frames(stream, streams, adjustment),
+                    Err(e) => error!("error sending answer to client: {e}"),
+                }
+                return;
+            }
+            RequestRecv::Img(img) if img.sync => {
+                // we answer once the images are committed
+                self.sync_img(img, stream);
+                return;
+            }
+            request => self.answer(request),
+        };
+        if let Err(e) = answer.send(&stream) {
+            error!("error sending answer to client: {e}");
+        } else if subscribe {
+            self.subscribers.add(stream);
+        }
+    }
+
+    /// Handles the requests that we answer right away
+    fn answer(&mut self, request: RequestRecv) -> Answer {
+        match request {
             RequestRecv::Clear(clear) => {
+                if let Err(answer) = self.check_output_names(&clear.outputs) {
+                    return answer;
+                }
                 let wallpapers = self.find_wallpapers_by_names(&clear.outputs);
                 std::thread::Builder::new()
                     .stack_size(1 << 15)
                     .name("clear".to_string())
-                    .spawn(move || {
-                        crate::wallpaper::stop_animations(&wallpapers);
-                        for wallpaper in &wallpapers {
-                            wallpaper.set_img_info(utils::ipc::BgImg::Color(clear.color));
-                            wallpaper.clear(clear.color);
-                        }
-                        crate::wallpaper::attach_buffers_and_damange_surfaces(&wallpapers);
-                        crate::wallpaper::commit_wallpapers(&wallpapers);
-                    })
+                    .spawn(move || clear_wallpapers(&wallpapers, clear.color))
                     .unwrap(); // builder only failed if the name contains null bytes
                 Answer::Ok
             }
+            RequestRecv::ClearAndKill(clear) => {
+                if let Err(answer) = self.check_output_names(&clear.outputs) {
+                    return answer;
+                }
+                // we clear right here, instead of in another thread, so that it is done before
+                // we exit
+                let wallpapers = self.find_wallpapers_by_names(&clear.outputs);
+                clear_wallpapers(&wallpapers, clear.color);
+                exit_daemon();
+                Answer::Ok
+            }
             RequestRecv::Ping => Answer::Ping(
                 self.wallpapers
                     .iter()
@@ -161,28 +380,645 @@ impl Daemon {
                 exit_daemon();
                 Answer::Ok
             }
-            RequestRecv::Query => Answer::Info(self.wallpapers_info()),
-            RequestRecv::Img(ImageReq {
-                transition,
+            RequestRecv::Query | RequestRecv::Subscribe => Answer::Info(self.wallpapers_info()),
+            RequestRecv::CacheList => match utils::cache::list() {
+                Ok(entries) => Answer::CacheList(entries.into()),
+                Err(e) => Answer::Err(ErrorKind::Cache, format!("failed to read the cache: {e}")),
+            },
+            RequestRecv::Preload(ImageReq {
                 imgs,
                 outputs,
                 animations,
-            }) => {
-                let mut used_wallpapers = Vec::new();
-                for names in outputs.iter() {
-                    let wallpapers = self.find_wallpapers_by_names(names);
-                    crate::wallpaper::stop_animations(&wallpapers);
-                    used_wallpapers.push(wallpapers);
+                adjustment,
+                replay,
+                ..
+            }) => self.preload(imgs, &outputs, animations, adjustment, replay_args(replay)),
+            RequestRecv::ShowPreloaded(show) => self.show_preloaded(show),
+            RequestRecv::SetSticky(sticky) => {
+                self.sticky = if sticky.args.is_empty() {
+                    None
+                } else {
+                    Some(sticky.args)
+                };
+                Answer::Ok
+            }
+            RequestRecv::SetWorkspace(workspace) => self.set_workspace(workspace),
+            RequestRecv::BoostFps(boost) => {
+                for wallpaper in self.find_wallpapers_by_names(&boost.outputs) {
+                    wallpaper.boost_fps(boost.fps, boost.duration);
+                }
+                Answer::Ok
+            }
+            RequestRecv::SetDimSchedule(DimScheduleReq { schedule }) => {
+                self.set_dim_schedule(schedule);
+                Answer::Ok
+            }
+            RequestRecv::Diagnostics => Answer::Diagnostics(Diagnostics {
+                globals: globals::bound_globals()
+                    .into_iter()
+                    .map(|(interface, version)| BoundGlobal {
+                        interface: interface.to_string(),
+                        version,
+                    })
+                    .collect(),
+                pixel_format: globals::pixel_format(),
+                swap_channels: globals::must_swap_r_and_b_channels(),
+            }),
+            RequestRecv::SetDefaultTransition(transition) => {
+                self.default_transition = transition;
+                Answer::Ok
+            }
+            // `recv_socket_msg` handles the ones with streamed animations
+            RequestRecv::Img(img) => self.img(img, None),
+            RequestRecv::GetBuffer(GetBufferReq { output }) => {
+                let Some(wallpaper) = self.wallpapers.iter().find(|w| w.has_name(&output)) else {
+                    return Answer::Err(ErrorKind::Other, format!("output {output} was not found"));
+                };
+                match wallpaper.buffer_contents() {
+                    Some(bytes) => Answer::Buffer(WallpaperBuffer {
+                        dim: wallpaper.get_dimensions(),
+                        format: globals::client_pixel_format(),
+                        bytes,
+                    }),
+                    None => Answer::Err(
+                        ErrorKind::Other,
+                        format!("output {output} has no wallpaper set yet"),
+                    ),
+                }
+            }
+            RequestRecv::AnimationFrames(_) => Answer::Err(
+                ErrorKind::Other,
+                "animation frames must follow the image request that streams them".to_string(),
+            ),
+            RequestRecv::Reset | RequestRecv::Freeze(_) | RequestRecv::Batch(_) => {
+                unreachable!("recv_socket_msg handles these")
+            }
+        }
+    }
+
+    fn img(&mut self, img: ImageReq, streams: Option<Box<[Arc<FrameStream>]>>) -> Answer {
+        let ImageReq {
+            transition,
+            force,
+            debug,
+            adjustment,
+            imgs,
+            sources,
+            mut blends,
+            regions,
+            outputs,
+            mut animations,
+            replay,
+            ..
+        } = img;
+        if let Err(answer) = self.check_output_names(outputs.iter().flatten()) {
+            return answer;
+        }
+        let used_wallpapers = self.wallpapers_for_imgs(&imgs, &outputs, force);
+        let memory = reserve_animation_memory(
+            &imgs,
+            &mut animations,
+            streams.as_deref(),
+            &mut blends,
+            &used_wallpapers,
+        );
+        if let Err(e) = self.set_regions(&outputs, regions.as_deref()) {
+            return Answer::Err(ErrorKind::Other, e);
+        }
+        set_request_debug(debug);
+        for (i, wallpapers) in used_wallpapers.iter().enumerate() {
+            crate::wallpaper::stop_animations(wallpapers);
+            if let Some(reservation) = memory.get(i) {
+                for wallpaper in wallpapers {
+                    wallpaper.hold_animation_memory(Arc::clone(reservation));
+                }
+            }
+        }
+        for (stream, reservation) in streams.iter().flatten().zip(memory.iter()) {
+            stream.set_memory(reservation);
+        }
+        keep_replay(&imgs, &used_wallpapers, replay_args(replay));
+        let transition = transition.unwrap_or_else(|| self.default_transition.clone());
+        if debug {
+            log_img_request(&transition, &imgs, &used_wallpapers, animations.as_deref());
+        }
+        let dimmable = animations.is_none() && streams.is_none() && blends.is_none();
+        let adjustment = self.record_undimmed(
+            &imgs,
+            &used_wallpapers,
+            dimmable,
+            adjustment,
+            dim::settle_time(&transition),
+        );
+        let answer = self.animator.transition(
+            transition,
+            imgs,
+            sources,
+            blends,
+            animations,
+            streams,
+            used_wallpapers,
+            adjustment,
+            debug,
+        );
+        set_request_debug(false);
+        answer
+    }
+
+    /// Finds the wallpapers each image goes to. Unless `force` is set, wallpapers already
+    /// displaying the image are left out
+    fn wallpapers_for_imgs(
+        &self,
+        imgs: &[ImgReq],
+        outputs: &[Box<[MmappedStr]>],
+        force: bool,
+    ) -> Vec<Vec<Arc<Wallpaper>>> {
+        let mut used_wallpapers = Vec::new();
+        for (names, img) in outputs.iter().zip(imgs.iter()) {
+            let mut wallpapers = self.find_wallpapers_by_names(names);
+            // there is no telling whether an image read from stdin changed
+            if !force && img.path.str() != "-" {
+                wallpapers.retain(|w| !w.is_displaying(img.path.str()));
+            }
+            used_wallpapers.push(wallpapers);
+        }
+        used_wallpapers
+    }
+
+    /// `swww img --region`: makes the wallpapers of each image cover its region, or their whole
+    /// outputs if the request has none. Changes nothing if any region does not fit its output
+    fn set_regions(
+        &self,
+        outputs: &[Box<[MmappedStr]>],
+        regions: Option<&[Region]>,
+    ) -> Result<(), String> {
+        let mut changes = Vec::new();
+        for (i, names) in outputs.iter().enumerate() {
+            let region = regions.map(|regions| regions[i]);
+            let wallpapers = self
+                .wallpapers
+                .iter()
+                .filter(|w| names.is_empty() || names.iter().any(|n| w.has_name(n.str())));
+            for wallpaper in wallpapers {
+                if let Some(region) = region.filter(|region| !wallpaper.fits(region)) {
+                    return Err(format!(
+                        "region {region} does not fit output {}",
+                        wallpaper.name().unwrap_or_default()
+                    ));
+                }
+                changes.push((wallpaper, region));
+            }
+        }
+        for (wallpaper, region) in changes {
+            wallpaper.set_region(region);
+        }
+        Ok(())
+    }
+
+    /// `swww img --sync`: skips the transition and animation threads of `img` entirely
+    fn sync_img(&mut self, img: ImageReq, stream: OwnedFd) {
+        let ImageReq {
+            force,
+            debug,
+            adjustment,
+            imgs,
+            regions,
+            outputs,
+            replay,
+            ..
+        } = img;
+        let checked = self
+            .check_output_names(outputs.iter().flatten())
+            .and_then(|()| {
+                self.set_regions(&outputs, regions.as_deref())
+                    .map_err(|e| Answer::Err(ErrorKind::Other, e))
+            });
+        if let Err(answer) = checked {
+            if let Err(e) = answer.send(&stream) {
+                error!("error sending answer to client: {e}");
+            }
+            return;
+        }
+        set_request_debug(debug);
+        let used_wallpapers = self.wallpapers_for_imgs(&imgs, &outputs, force);
+        for wallpapers in used_wallpapers.iter() {
+            crate::wallpaper::stop_animations(wallpapers);
+        }
+        keep_replay(&imgs, &used_wallpapers, replay_args(replay));
+        let adjustment =
+            self.record_undimmed(&imgs, &used_wallpapers, true, adjustment, Duration::ZERO);
+        self.animator
+            .sync(imgs, used_wallpapers, adjustment, debug, stream);
+        set_request_debug(false);
+    }
+
+    /// Handles every request of a batch in order, without going back to the main loop in between,
+    /// so that they take effect together. If any of them cannot be batched, we handle none
+    fn batch(&mut self, requests: Box<[RequestRecv]>) -> Answer {
+        for request in requests.iter() {
+            let e = match request {
+                RequestRecv::Batch(_) => "batches cannot be nested",
+                RequestRecv::Kill | RequestRecv::ClearAndKill(_) => {
+                    "a batch cannot kill the daemon"
+                }
+                RequestRecv::Subscribe | RequestRecv::Reset | RequestRecv::Freeze(_) => {
+                    "subscriptions, resets and freezes cannot be part of a batch"
                 }
-                self.animator
-                    .transition(transition, imgs, animations, used_wallpapers)
+                RequestRecv::Img(img) if img.streamed.is_some() => {
+                    "images with streamed animations cannot be part of a batch"
+                }
+                RequestRecv::Img(img) if img.sync => {
+                    "images sent with --sync cannot be part of a batch"
+                }
+                _ => continue,
+            };
+            return Answer::Err(ErrorKind::Other, e.to_string());
+        }
+        debug!("Handling a batch of {} requests", requests.len());
+        Answer::Batch(
+            requests
+                .into_vec()
+                .into_iter()
+                .map(|request| self.answer(request))
+                .collect(),
+        )
+    }
+
+    /// Drops all of our outputs and starts renegotiating the wl_shm format. The cache, the default
+    /// transition, our subscribers and the optional protocols we bound are all kept.
+    fn start_reset(&mut self, stream: OwnedFd) {
+        use wayland::interfaces::wl_display;
+        info!("Resetting: selecting the wl_shm format and creating the outputs again");
+
+        // their dimensions and pixel format may no longer match the outputs'
+        self.preloaded.clear();
+
+        crate::wallpaper::stop_animations(&self.wallpapers);
+        // we will no longer route frame callbacks to these, so we release any thread waiting for
+        // one. Otherwise, it would keep its wallpaper (and thus its surface) alive forever
+        for wallpaper in self.wallpapers.iter() {
+            wallpaper.frame_callback_completed();
+        }
+        self.wallpapers.clear();
+        subscribers::notify_changed();
+        globals::reset_pixel_format();
+
+        let registry = globals::object_create(wayland::WlDynObj::Registry);
+        wl_display::req::get_registry(registry).unwrap();
+        self.registry = registry;
+        let callback = globals::object_create(wayland::WlDynObj::Callback);
+        wl_display::req::sync(callback).unwrap();
+        self.reset = Some(Reset {
+            stream,
+            callback,
+            shm_name: None,
+            shm: None,
+            formats: Vec::new(),
+            output_names: Vec::new(),
+        });
+    }
+
+    /// Called at the end of each of the reset's roundtrips
+    fn continue_reset(&mut self) {
+        use wayland::interfaces::wl_display;
+        let Some(mut reset) = self.reset.take() else {
+            return;
+        };
+
+        if reset.shm.is_none() {
+            if let Some(name) = reset.shm_name {
+                let shm = globals::object_create(wayland::WlDynObj::Shm);
+                globals::bind_global(name, shm, "wl_shm", 1);
+                reset.shm = Some(shm);
+                reset.callback = globals::object_create(wayland::WlDynObj::Callback);
+                wl_display::req::sync(reset.callback).unwrap();
+                self.reset = Some(reset);
+                return;
             }
+        }
+
+        let answer = if reset.shm.is_some() {
+            globals::select_preferred_pixel_format(&reset.formats);
+            info!("Selected wl_shm format: {:?}", globals::pixel_format());
+            Answer::Ok
+        } else {
+            error!("compositor did not advertise wl_shm while resetting");
+            Answer::Err(
+                ErrorKind::Compositor,
+                "compositor did not advertise wl_shm while resetting".to_string(),
+            )
         };
-        if let Err(e) = answer.send(&stream) {
+        for name in reset.output_names {
+            self.new_output(name);
+        }
+        if let Err(e) = answer.send(&reset.stream) {
             error!("error sending answer to client: {e}");
         }
     }
 
+    /// Binds the workspace manager the first time a client sets an image for a workspace
+    fn set_workspace(&mut self, workspace: WorkspaceReq) -> Answer {
+        if self.workspaces.is_none() {
+            let Some(name) = self.workspace_manager else {
+                return Answer::Err(
+                    ErrorKind::Compositor,
+                    "the compositor does not support ext_workspace_v1".to_string(),
+                );
+            };
+            let manager = globals::object_create(wayland::WlDynObj::WorkspaceManager);
+            globals::bind_global(name, manager, "ext_workspace_manager_v1", 1);
+            self.workspaces = Some(workspaces::Workspaces::new());
+        }
+        let workspaces = self.workspaces.as_mut().unwrap();
+        let activations = workspaces.set_image(workspace.workspace, workspace.args);
+        self.activate_workspaces(activations);
+        Answer::Ok
+    }
+
+    /// Runs `swww` to display the images set for the workspaces that became active
+    fn activate_workspaces(&self, activations: Vec<workspaces::Activation>) {
+        for (args, outputs) in activations {
+            let names: Vec<String> = self
+                .wallpapers
+                .iter()
+                .filter(|w| outputs.contains(&w.output()))
+                .map(|w| w.get_bg_info().name)
+                .collect();
+            if names.is_empty() {
+                continue;
+            }
+            let names = names.join(",");
+            std::thread::Builder::new()
+                .name("workspace loader".to_string())
+                .stack_size(1 << 14)
+                .spawn(move || {
+                    if let Err(e) = crate::wallpaper::load_sticky(&names, &args) {
+                        warn!("failed to display workspace image: {e}");
+                    }
+                })
+                .unwrap(); // builder only fails if the name contains null bytes
+        }
+    }
+
+    /// Keeps the images, replacing whatever was preloaded for their outputs before. Declines them
+    /// if their animations go over `--max-animation-memory`
+    fn preload(
+        &mut self,
+        imgs: Box<[ImgReq]>,
+        outputs: &[Box<[MmappedStr]>],
+        animations: Option<Box<[Animation]>>,
+        adjustment: ColorAdjustment,
+        replay: Option<Arc<[String]>>,
+    ) -> Answer {
+        let outputs: Vec<Vec<String>> = outputs
+            .iter()
+            .map(|names| {
+                if names.is_empty() {
+                    self.wallpapers.iter().filter_map(|w| w.name()).collect()
+                } else {
+                    names.iter().map(|name| name.str().to_string()).collect()
+                }
+            })
+            .collect();
+        // like `reserve_animation_memory`, falling back to not interpolating the animations, or
+        // else to dropping them
+        let mut animations = animations;
+        let memory = match animations.as_deref_mut() {
+            Some(preloaded_animations) => {
+                let needed: Vec<usize> = preloaded_animations
+                    .iter()
+                    .map(|animation| memory::needed(animation, false))
+                    .collect();
+                let compressed: Vec<usize> = preloaded_animations
+                    .iter()
+                    .map(memory::compressed)
+                    .collect();
+                // preloads whose every output gets a new image are dropped below
+                let freed = memory::freed(
+                    self.preloaded
+                        .iter()
+                        .filter(|preloaded| {
+                            let replaced = |output| outputs.iter().flatten().any(|n| n == output);
+                            preloaded.outputs.iter().all(replaced)
+                        })
+                        .filter_map(|preloaded| preloaded.memory.clone()),
+                );
+                match memory::reserve(&needed, freed) {
+                    Ok(memory) => memory,
+                    Err(e) => match memory::reserve(&compressed, freed) {
+                        Ok(memory) if compressed != needed => {
+                            warn!("{e}, so we play it without interpolating it");
+                            for animation in preloaded_animations.iter_mut() {
+                                animation.interpolate = false;
+                            }
+                            memory
+                        }
+                        _ => {
+                            warn!("{e}, so we only display its first frame");
+                            animations = None;
+                            Vec::new()
+                        }
+                    },
+                }
+            }
+            None => Vec::new(),
+        };
+
+        // animations are either sent for every image, or for none of them
+        let mut animations = animations.map(|animations| animations.into_vec().into_iter());
+        let mut memory = memory.into_iter();
+        for (img, names) in imgs.into_vec().into_iter().zip(outputs) {
+            for preloaded in self.preloaded.iter_mut() {
+                preloaded.outputs.retain(|output| !names.contains(output));
+            }
+            self.preloaded
+                .retain(|preloaded| !preloaded.outputs.is_empty());
+            debug!("preloaded {} for {names:?}", img.path.str());
+            self.preloaded.push(Preloaded {
+                img,
+                animation: animations.as_mut().and_then(Iterator::next),
+                memory: memory.next(),
+                replay: replay.clone(),
+                outputs: names,
+                adjustment,
+                expires: Instant::now() + PRELOAD_TIMEOUT,
+            });
+        }
+        Answer::Ok
+    }
+
+    /// Displays the images preloaded for the requested outputs, answering with what we displayed
+    /// where, for the client to store in the cache. An image preloaded for several outputs at once
+    /// is displayed on all of them, since we cannot split it
+    fn show_preloaded(&mut self, show: ShowPreloadedReq) -> Answer {
+        let requested = |output: &String| {
+            show.outputs.is_empty() || show.outputs.iter().any(|o| o.str() == output)
+        };
+        let (shown, kept) = std::mem::take(&mut self.preloaded)
+            .into_iter()
+            .partition::<Vec<_>, _>(|preloaded| preloaded.outputs.iter().any(requested));
+        self.preloaded = kept;
+        if shown.is_empty() {
+            return Answer::Err(
+                ErrorKind::NotPreloaded,
+                "no image is preloaded for the requested outputs".to_string(),
+            );
+        }
+
+        let transition = show
+            .transition
+            .unwrap_or_else(|| self.default_transition.clone());
+        let mut entries = Vec::new();
+        for preloaded in shown {
+            let names = preloaded.outputs;
+            let wallpapers: Vec<Arc<Wallpaper>> = self
+                .wallpapers
+                .iter()
+                .filter(|w| names.iter().any(|name| w.has_name(name)))
+                .cloned()
+                .collect();
+            crate::wallpaper::stop_animations(&wallpapers);
+            if let Some(reservation) = &preloaded.memory {
+                for wallpaper in &wallpapers {
+                    wallpaper.hold_animation_memory(Arc::clone(reservation));
+                }
+            }
+            let wallpapers = vec![wallpapers];
+            let imgs = Box::new([preloaded.img]);
+            keep_replay(&imgs[..], &wallpapers, preloaded.replay);
+            let adjustment = self.record_undimmed(
+                &imgs[..],
+                &wallpapers,
+                preloaded.animation.is_none(),
+                preloaded.adjustment,
+                dim::settle_time(&transition),
+            );
+            for output in names {
+                entries.push(CacheEntry {
+                    output,
+                    img_path: imgs[0].path.str().to_string(),
+                    animated: preloaded.animation.is_some(),
+                });
+            }
+            self.animator.transition(
+                transition.clone(),
+                imgs,
+                None,
+                None,
+                preloaded
+                    .animation
+                    .map(|animation| Box::new([animation]) as Box<[_]>),
+                None,
+                wallpapers,
+                adjustment,
+                false,
+            );
+        }
+        Answer::CacheList(entries.into())
+    }
+
+    /// `swww dim-schedule`: when setting the first schedule, we take what static images display as
+    /// their undimmed contents. `None` displays them as they are again
+    fn set_dim_schedule(&mut self, schedule: Option<DimSchedule>) {
+        let Some(schedule) = schedule else {
+            if let Some(dimmer) = self.dimmer.take() {
+                dimmer.stop(&self.wallpapers);
+            }
+            return;
+        };
+        if self.dimmer.is_none() {
+            let channels = globals::pixel_format().channels() as usize;
+            for wallpaper in self.wallpapers.iter() {
+                let BgImg::Img(path) = wallpaper.get_bg_info().img else {
+                    continue;
+                };
+                if wallpaper.is_animated() {
+                    continue;
+                }
+                if let Some(contents) = wallpaper.buffer_contents() {
+                    wallpaper.set_undimmed(Some(dim::Undimmed::new(
+                        &path,
+                        contents.into(),
+                        channels,
+                        ColorAdjustment::NONE,
+                        1.0,
+                        Instant::now(),
+                    )));
+                }
+            }
+        }
+        let dimmer = self.dimmer.insert(dim::Dimmer::new(schedule));
+        dimmer.update(&self.wallpapers);
+    }
+
+    /// `swww dim-schedule`: remembers what the wallpapers of each image display before we dim
+    /// them, so that we can dim them again as the brightness changes. Only `dimmable` images are
+    /// (the others are dimmed once, when displayed). `settle` is how long displaying them takes.
+    /// Returns `adjustment` dimmed by the current brightness
+    fn record_undimmed(
+        &self,
+        imgs: &[ImgReq],
+        wallpapers: &[Vec<Arc<Wallpaper>>],
+        dimmable: bool,
+        adjustment: ColorAdjustment,
+        settle: Duration,
+    ) -> ColorAdjustment {
+        let Some(dimmer) = self.dimmer.as_ref() else {
+            return adjustment;
+        };
+        let settled = Instant::now() + settle;
+        for (img, wallpapers) in imgs.iter().zip(wallpapers) {
+            if wallpapers.is_empty() {
+                continue;
+            }
+            let undimmed = dimmable.then(|| {
+                dim::Undimmed::new(
+                    img.path.str(),
+                    img.img.bytes().into(),
+                    img.format.channels().into(),
+                    adjustment,
+                    dimmer.brightness(),
+                    settled,
+                )
+            });
+            for wallpaper in wallpapers {
+                wallpaper.set_undimmed(undimmed.clone());
+            }
+        }
+        dimmer.dim(adjustment)
+    }
+
+    /// Drops the preloaded images that were not displayed in time. Returns how long until the
+    /// next one expires, if any is left
+    fn expire_preloads(&mut self) -> Option<Duration> {
+        let now = Instant::now();
+        self.preloaded.retain(|preloaded| {
+            let keep = preloaded.expires > now;
+            if !keep {
+                debug!("dropping preloaded {}", preloaded.img.path.str());
+            }
+            keep
+        });
+        self.preloaded
+            .iter()
+            .map(|preloaded| preloaded.expires - now)
+            .min()
+    }
+
+    /// Gives up on capturing the outputs we no longer have
+    fn retain_freeze_outputs(&mut self) {
+        if let Some(freeze) = self.freeze.as_mut() {
+            freeze.retain_outputs(&self.wallpapers);
+        }
+        self.finish_freeze_if_done();
+    }
+
+    fn finish_freeze_if_done(&mut self) {
+        if self.freeze.as_ref().is_some_and(|freeze| freeze.is_done()) {
+            self.freeze.take().unwrap().finish();
+        }
+    }
+
     fn wallpapers_info(&self) -> Box<[BgInfo]> {
         self.wallpapers
             .iter()
@@ -190,6 +1026,16 @@ impl Daemon {
             .collect()
     }
 
+    /// Fails, listing the outputs we do know about, if any of `names` is not one of them
+    fn check_output_names<'a>(
+        &self,
+        names: impl IntoIterator<Item = &'a MmappedStr>,
+    ) -> Result<(), Answer> {
+        let known: Vec<String> = self.wallpapers.iter().filter_map(|w| w.name()).collect();
+        let pending = self.wallpapers.len() - known.len();
+        check_output_names(names, &known, pending)
+    }
+
     fn find_wallpapers_by_names(&self, names: &[MmappedStr]) -> Vec<Arc<Wallpaper>> {
         self.wallpapers
             .iter()
@@ -215,19 +1061,35 @@ impl wayland::interfaces::wl_registry::EvHandler for Daemon {
         if interface == "wl_output" {
             if version < 4 {
                 error!("your compositor must support at least version 4 of wl_output");
+            } else if let Some(reset) = self.reset.as_mut() {
+                reset.output_names.push(name);
             } else {
                 self.new_output(name);
             }
+        } else if interface == "wl_shm" {
+            if let Some(reset) = self.reset.as_mut() {
+                reset.shm_name = Some(name);
+            }
         }
     }
 
     fn global_remove(&mut self, name: u32) {
+        if let Some(reset) = self.reset.as_mut() {
+            reset.output_names.retain(|&n| n != name);
+        }
         self.wallpapers.retain(|w| !w.has_output_name(name));
+        self.retain_freeze_outputs();
+        subscribers::notify_changed();
     }
 }
 
 impl wayland::interfaces::wl_shm::EvHandler for Daemon {
     fn format(&mut self, format: u32) {
+        if let Some(reset) = self.reset.as_mut() {
+            globals::negotiate_pixel_format(format);
+            reset.formats.extend(globals::shm_pixel_format(format));
+            return;
+        }
         warn!(
             "received a wl_shm format after initialization: {format}. This shouldn't be possible"
         );
@@ -238,8 +1100,8 @@ impl wayland::interfaces::wl_output::EvHandler for Daemon {
     fn geometry(
         &mut self,
         sender_id: ObjectId,
-        _x: i32,
-        _y: i32,
+        x: i32,
+        y: i32,
         _physical_width: i32,
         _physical_height: i32,
         _subpixel: i32,
@@ -254,15 +1116,17 @@ impl wayland::interfaces::wl_output::EvHandler for Daemon {
                 } else {
                     wallpaper.set_transform(transform as u32);
                 }
+                wallpaper.set_position(x, y);
                 break;
             }
         }
     }
 
-    fn mode(&mut self, sender_id: ObjectId, _flags: u32, width: i32, height: i32, _refresh: i32) {
+    fn mode(&mut self, sender_id: ObjectId, _flags: u32, width: i32, height: i32, refresh: i32) {
         for wallpaper in self.wallpapers.iter() {
             if wallpaper.has_output(sender_id) {
                 wallpaper.set_dimensions(width, height);
+                wallpaper.set_refresh(refresh);
                 break;
             }
         }
@@ -271,7 +1135,8 @@ impl wayland::interfaces::wl_output::EvHandler for Daemon {
     fn done(&mut self, sender_id: ObjectId) {
         for wallpaper in self.wallpapers.iter() {
             if wallpaper.has_output(sender_id) {
-                wallpaper.commit_surface_changes(self.use_cache);
+                self.commit_surface_changes(wallpaper);
+                subscribers::notify_changed();
                 break;
             }
         }
@@ -281,7 +1146,7 @@ impl wayland::interfaces::wl_output::EvHandler for Daemon {
         for wallpaper in self.wallpapers.iter() {
             if wallpaper.has_output(sender_id) {
                 match NonZeroI32::new(factor) {
-                    Some(factor) => wallpaper.set_scale(Scale::Whole(factor)),
+                    Some(factor) => wallpaper.set_output_scale(factor),
                     None => error!("received scale factor of 0 from compositor"),
                 }
                 break;
@@ -320,7 +1185,12 @@ impl wayland::interfaces::wl_surface::EvHandler for Daemon {
         for wallpaper in self.wallpapers.iter() {
             if wallpaper.has_surface(sender_id) {
                 match NonZeroI32::new(factor) {
-                    Some(factor) => wallpaper.set_scale(Scale::Whole(factor)),
+                    Some(factor) => {
+                        // unlike the output's events, this is not followed by a `done`
+                        wallpaper.set_preferred_buffer_scale(factor);
+                        self.commit_surface_changes(wallpaper);
+                        subscribers::notify_changed();
+                    }
                     None => error!("received scale factor of 0 from compositor"),
                 }
                 break;
@@ -329,7 +1199,8 @@ impl wayland::interfaces::wl_surface::EvHandler for Daemon {
     }
 
     fn preferred_buffer_transform(&mut self, _sender_id: ObjectId, _transform: u32) {
-        warn!("Received PreferredBufferTransform. We currently ignore those")
+        // the compositor transforms our buffers itself, which is fine for a static wallpaper
+        debug!("Received PreferredBufferTransform. We currently ignore those")
     }
 }
 
@@ -346,6 +1217,14 @@ impl wayland::interfaces::wl_buffer::EvHandler for Daemon {
 
 impl wayland::interfaces::wl_callback::EvHandler for Daemon {
     fn done(&mut self, sender_id: ObjectId, _callback_data: u32) {
+        if self
+            .reset
+            .as_ref()
+            .is_some_and(|reset| reset.callback == sender_id)
+        {
+            self.continue_reset();
+            return;
+        }
         for wallpaper in self.wallpapers.iter() {
             if wallpaper.has_callback(sender_id) {
                 wallpaper.frame_callback_completed();
@@ -358,7 +1237,7 @@ impl wayland::interfaces::wl_callback::EvHandler for Daemon {
 impl wayland::interfaces::zwlr_layer_surface_v1::EvHandler for Daemon {
     fn configure(&mut self, sender_id: ObjectId, serial: u32, _width: u32, _height: u32) {
         for wallpaper in self.wallpapers.iter() {
-            if wallpaper.has_layer_surface(sender_id) {
+            if wallpaper.has_shell_surface(sender_id) {
                 wayland::interfaces::zwlr_layer_surface_v1::req::ack_configure(sender_id, serial)
                     .unwrap();
                 break;
@@ -367,7 +1246,170 @@ impl wayland::interfaces::zwlr_layer_surface_v1::EvHandler for Daemon {
     }
 
     fn closed(&mut self, sender_id: ObjectId) {
-        self.wallpapers.retain(|w| !w.has_layer_surface(sender_id));
+        self.wallpapers.retain(|w| !w.has_shell_surface(sender_id));
+        self.retain_freeze_outputs();
+    }
+}
+
+impl wayland::interfaces::xdg_wm_base::EvHandler for Daemon {
+    fn ping(&mut self, serial: u32) {
+        wayland::interfaces::xdg_wm_base::req::pong(serial).unwrap();
+    }
+}
+
+impl wayland::interfaces::xdg_surface::EvHandler for Daemon {
+    fn configure(&mut self, sender_id: ObjectId, serial: u32) {
+        for wallpaper in self.wallpapers.iter() {
+            if wallpaper.has_shell_surface(sender_id) {
+                wayland::interfaces::xdg_surface::req::ack_configure(sender_id, serial).unwrap();
+                break;
+            }
+        }
+    }
+}
+
+impl wayland::interfaces::xdg_toplevel::EvHandler for Daemon {
+    fn configure(&mut self, _sender_id: ObjectId, _width: i32, _height: i32, _states: &[u8]) {
+        // we are fullscreen, so we just follow the size of the output
+    }
+
+    fn close(&mut self, sender_id: ObjectId) {
+        wayland::interfaces::zwlr_layer_surface_v1::EvHandler::closed(self, sender_id);
+    }
+}
+
+impl wayland::interfaces::zwlr_screencopy_frame_v1::EvHandler for Daemon {
+    fn buffer(&mut self, sender_id: ObjectId, format: u32, width: u32, height: u32, stride: u32) {
+        if let Some(freeze) = self.freeze.as_mut() {
+            freeze.buffer(sender_id, format, width, height, stride);
+        }
+        self.finish_freeze_if_done();
+    }
+
+    fn flags(&mut self, sender_id: ObjectId, flags: u32) {
+        if let Some(freeze) = self.freeze.as_mut() {
+            freeze.flags(sender_id, flags);
+        }
+    }
+
+    fn ready(&mut self, sender_id: ObjectId, _tv_sec_hi: u32, _tv_sec_lo: u32, _tv_nsec: u32) {
+        if let Some(freeze) = self.freeze.as_mut() {
+            freeze.ready(sender_id);
+        }
+        self.finish_freeze_if_done();
+    }
+
+    fn failed(&mut self, sender_id: ObjectId) {
+        if let Some(freeze) = self.freeze.as_mut() {
+            freeze.fail(sender_id);
+        }
+        self.finish_freeze_if_done();
+    }
+}
+
+impl wayland::interfaces::ext_idle_notification_v1::EvHandler for Daemon {
+    fn idled(&mut self, sender_id: ObjectId) {
+        if let Some(idle) = self.idle.as_mut() {
+            if idle.notification() == sender_id {
+                idle.set_idle(true);
+            }
+        }
+    }
+
+    fn resumed(&mut self, sender_id: ObjectId) {
+        if let Some(idle) = self.idle.as_mut() {
+            if idle.notification() == sender_id {
+                idle.set_idle(false);
+            }
+        }
+    }
+}
+
+impl wayland::interfaces::ext_workspace_manager_v1::EvHandler for Daemon {
+    fn workspace_group(&mut self, _sender_id: ObjectId, workspace_group: ObjectId) {
+        if let Some(workspaces) = self.workspaces.as_mut() {
+            workspaces.new_group(workspace_group);
+        }
+    }
+
+    fn workspace(&mut self, _sender_id: ObjectId, workspace: ObjectId) {
+        if let Some(workspaces) = self.workspaces.as_mut() {
+            workspaces.new_workspace(workspace);
+        }
+    }
+
+    fn done(&mut self, _sender_id: ObjectId) {
+        if let Some(workspaces) = self.workspaces.as_mut() {
+            let activations = workspaces.done();
+            self.activate_workspaces(activations);
+        }
+    }
+
+    fn finished(&mut self, _sender_id: ObjectId) {
+        warn!("the compositor stopped sending workspace events, workspace images no longer work");
+        self.workspaces = None;
+        // the compositor destroys the manager, so we cannot bind it again
+        self.workspace_manager = None;
+    }
+}
+
+impl wayland::interfaces::ext_workspace_group_handle_v1::EvHandler for Daemon {
+    fn capabilities(&mut self, _sender_id: ObjectId, _capabilities: u32) {}
+
+    fn output_enter(&mut self, sender_id: ObjectId, output: ObjectId) {
+        if let Some(workspaces) = self.workspaces.as_mut() {
+            workspaces.output_enter(sender_id, output);
+        }
+    }
+
+    fn output_leave(&mut self, sender_id: ObjectId, output: ObjectId) {
+        if let Some(workspaces) = self.workspaces.as_mut() {
+            workspaces.output_leave(sender_id, output);
+        }
+    }
+
+    fn workspace_enter(&mut self, sender_id: ObjectId, workspace: ObjectId) {
+        if let Some(workspaces) = self.workspaces.as_mut() {
+            workspaces.workspace_enter(sender_id, workspace);
+        }
+    }
+
+    fn workspace_leave(&mut self, sender_id: ObjectId, workspace: ObjectId) {
+        if let Some(workspaces) = self.workspaces.as_mut() {
+            workspaces.workspace_leave(sender_id, workspace);
+        }
+    }
+
+    fn removed(&mut self, sender_id: ObjectId) {
+        if let Some(workspaces) = self.workspaces.as_mut() {
+            workspaces.group_removed(sender_id);
+        }
+    }
+}
+
+impl wayland::interfaces::ext_workspace_handle_v1::EvHandler for Daemon {
+    fn id(&mut self, _sender_id: ObjectId, _id: &str) {}
+
+    fn name(&mut self, sender_id: ObjectId, name: &str) {
+        if let Some(workspaces) = self.workspaces.as_mut() {
+            workspaces.set_name(sender_id, name);
+        }
+    }
+
+    fn coordinates(&mut self, _sender_id: ObjectId, _coordinates: &[u8]) {}
+
+    fn state(&mut self, sender_id: ObjectId, state: u32) {
+        if let Some(workspaces) = self.workspaces.as_mut() {
+            workspaces.set_state(sender_id, state);
+        }
+    }
+
+    fn capabilities(&mut self, _sender_id: ObjectId, _capabilities: u32) {}
+
+    fn removed(&mut self, sender_id: ObjectId) {
+        if let Some(workspaces) = self.workspaces.as_mut() {
+            workspaces.workspace_removed(sender_id);
+        }
     }
 }
 
@@ -378,7 +1420,8 @@ impl wayland::interfaces::wp_fractional_scale_v1::EvHandler for Daemon {
                 match NonZeroI32::new(scale as i32) {
                     Some(factor) => {
                         wallpaper.set_scale(Scale::Fractional(factor));
-                        wallpaper.commit_surface_changes(self.use_cache);
+                        self.commit_surface_changes(wallpaper);
+                        subscribers::notify_changed();
                     }
                     None => error!("received scale factor of 0 from compositor"),
                 }
@@ -392,18 +1435,53 @@ fn main() -> Result<(), String> {
     // first, get the command line arguments and make the logger
     let cli = cli::Cli::new();
     make_logger(cli.quiet);
+    if cli.selftest {
+        return animations::selftest();
+    }
+    if let Some(cache_dir) = cli.cache_dir.as_ref() {
+        // through the environment, the `swww` processes we spawn to load the cache use it too.
+        // Nothing else is running yet, so this is the safest moment to change it
+        std::env::set_var("SWWW_CACHE_DIR", cache_dir);
+    }
+    // so that we warn about invalid SWWW_READ_TRIES or SWWW_READ_SLEEP_MS right away
+    utils::ipc::ReadRetries::from_env();
 
     // initialize the wayland connection, getting all the necessary globals
-    let initializer = wayland::globals::init(cli.format);
+    let initializer = wayland::globals::init(
+        cli.format,
+        cli.prefer_compositor_format,
+        cli.swap_channels,
+        cli.idle_pause.is_some(),
+    );
+    if cli.single_pool {
+        wayland::bump_pool::use_shared_pool();
+    }
+    if let Some(command) = cli.img_hook {
+        hook::set_img_hook(command);
+    }
+    if let Some(bytes) = cli.max_animation_memory {
+        animations::set_max_animation_memory(bytes);
+    }
+    if let Some(ms) = cli.resize_fade_ms {
+        mode_change::set_fade_ms(ms);
+    }
+    let _ = NAMESPACE.set(cli.namespace.clone());
 
     // create the socket listener and setup the signal handlers
     // this will also return an error if there is an `swww-daemon` instance already
     // running
-    let listener = SocketWrapper::new()?;
+    let listener = SocketWrapper::new(get_socket_path(&cli.namespace))?;
     setup_signals();
 
     // use the initializer to create the Daemon, then drop it to free up the memory
-    let mut daemon = Daemon::new(&initializer, cli.no_cache);
+    let mut daemon = Daemon::new(
+        &initializer,
+        cli.no_cache,
+        cli.max_fps,
+        cli.opaque_region,
+        cli.layer,
+        cli.idle_pause,
+    );
     for &output_name in initializer.output_names() {
         daemon.new_output(output_name);
     }
@@ -419,13 +1497,28 @@ fn main() -> Result<(), String> {
     let mut fds = [
         PollFd::new(&wayland_fd, PollFlags::IN),
         PollFd::new(&listener.0, PollFlags::IN),
+        PollFd::new(subscribers::changed_fd(), PollFlags::IN),
     ];
 
     // main loop
     while !should_daemon_exit() {
         use wayland::{interfaces::*, wire, WlDynObj};
 
-        if let Err(e) = poll(&mut fds, -1) {
+        // wake up in time to drop preloaded images that expire, to pause animations and to dim
+        // the wallpapers
+        let idle_timeout = daemon.idle.as_mut().and_then(idle::IdlePause::update);
+        let dim_timeout = daemon
+            .dimmer
+            .as_mut()
+            .map(|dimmer| dimmer.update(&daemon.wallpapers));
+        let timeout = [daemon.expire_preloads(), idle_timeout, dim_timeout]
+            .into_iter()
+            .flatten()
+            .min()
+            .map_or(-1, |timeout| {
+                timeout.as_millis().min(i32::MAX as u128) as i32 + 1
+            });
+        if let Err(e) = poll(&mut fds, timeout) {
             match e {
                 rustix::io::Errno::INTR => continue,
                 _ => return Err(format!("failed to poll file descriptors: {e:?}")),
@@ -441,11 +1534,37 @@ fn main() -> Result<(), String> {
 
             match msg.sender_id() {
                 globals::WL_DISPLAY => wl_display::event(&mut daemon, msg, payload),
-                globals::WL_REGISTRY => wl_registry::event(&mut daemon, msg, payload),
+                globals::WL_REGISTRY if daemon.registry == globals::WL_REGISTRY => {
+                    wl_registry::event(&mut daemon, msg, payload)
+                }
+                globals::WL_REGISTRY => debug!("ignoring event from the wl_registry before reset"),
                 globals::WL_COMPOSITOR => error!("wl_compositor has no events"),
                 globals::WL_SHM => wl_shm::event(&mut daemon, msg, payload),
                 globals::WP_VIEWPORTER => error!("wp_viewporter has no events"),
+                globals::XDG_WM_BASE if globals::xdg_shell_fallback() => {
+                    xdg_wm_base::event(&mut daemon, msg, payload)
+                }
                 globals::ZWLR_LAYER_SHELL_V1 => error!("zwlr_layer_shell_v1 has no events"),
+                other if globals::seat() == Some(other) => {
+                    // we only bind it for the idle notifier, so we ignore its capabilities
+                }
+                // the compositor creates these, so they are not in our object manager
+                other
+                    if daemon
+                        .workspaces
+                        .as_ref()
+                        .is_some_and(|w| w.is_group(other)) =>
+                {
+                    ext_workspace_group_handle_v1::event(&mut daemon, msg, payload)
+                }
+                other
+                    if daemon
+                        .workspaces
+                        .as_ref()
+                        .is_some_and(|w| w.is_workspace(other)) =>
+                {
+                    ext_workspace_handle_v1::event(&mut daemon, msg, payload)
+                }
                 other => {
                     let obj_id = globals::object_type_get(other);
                     match obj_id {
@@ -455,13 +1574,33 @@ fn main() -> Result<(), String> {
                         Some(WlDynObj::LayerSurface) => {
                             zwlr_layer_surface_v1::event(&mut daemon, msg, payload)
                         }
+                        Some(WlDynObj::XdgSurface) => xdg_surface::event(&mut daemon, msg, payload),
+                        Some(WlDynObj::XdgToplevel) => {
+                            xdg_toplevel::event(&mut daemon, msg, payload)
+                        }
                         Some(WlDynObj::Buffer) => wl_buffer::event(&mut daemon, msg, payload),
                         Some(WlDynObj::ShmPool) => error!("wl_shm_pool has no events"),
                         Some(WlDynObj::Callback) => wl_callback::event(&mut daemon, msg, payload),
                         Some(WlDynObj::Viewport) => error!("wp_viewport has no events"),
+                        Some(WlDynObj::Registry) if other == daemon.registry => {
+                            wl_registry::event(&mut daemon, msg, payload)
+                        }
+                        Some(WlDynObj::Registry) => {
+                            debug!("ignoring event from a wl_registry before reset")
+                        }
+                        Some(WlDynObj::Shm) => wl_shm::event(&mut daemon, msg, payload),
                         Some(WlDynObj::FractionalScale) => {
                             wp_fractional_scale_v1::event(&mut daemon, msg, payload)
                         }
+                        Some(WlDynObj::ScreencopyFrame) => {
+                            zwlr_screencopy_frame_v1::event(&mut daemon, msg, payload)
+                        }
+                        Some(WlDynObj::IdleNotification) => {
+                            ext_idle_notification_v1::event(&mut daemon, msg, payload)
+                        }
+                        Some(WlDynObj::WorkspaceManager) => {
+                            ext_workspace_manager_v1::event(&mut daemon, msg, payload)
+                        }
                         None => error!("Received event for deleted object ({other:?})"),
                     }
                 }
@@ -475,8 +1614,20 @@ fn main() -> Result<(), String> {
                 Err(e) => return Err(format!("failed to accept incoming connection: {e}")),
             }
         }
+
+        if !fds[2].revents().is_empty() {
+            if let Some(idle) = daemon.idle.as_mut() {
+                idle.wallpaper_changed();
+            }
+            let wallpapers = &daemon.wallpapers;
+            daemon
+                .subscribers
+                .broadcast(|| wallpapers.iter().map(|w| w.get_bg_info()).collect());
+        }
     }
     crate::wallpaper::stop_animations(&daemon.wallpapers);
+    // paused animations would never get to notice they were stopped
+    animations::set_paused(false);
 
     // wait for the animation threads to finish.
     while !daemon.wallpapers.is_empty() {
@@ -521,12 +1672,48 @@ fn setup_signals() {
     debug!("Finished setting up signal handlers")
 }
 
+/// Fails if any of `names` is not one of the `known` outputs. `pending` outputs did not get their
+/// name yet: the compositor sends it some time after the output shows up, so a request may name
+/// one of them before we know it
+fn check_output_names<'a>(
+    names: impl IntoIterator<Item = &'a MmappedStr>,
+    known: &[String],
+    pending: usize,
+) -> Result<(), Answer> {
+    match names
+        .into_iter()
+        .find(|name| !known.iter().any(|known| known == name.str()))
+    {
+        Some(name) => Err(Answer::Err(
+            ErrorKind::UnknownOutput,
+            utils::ipc::unknown_output_message(name.str(), known, pending),
+        )),
+        None => Ok(()),
+    }
+}
+
+fn clear_wallpapers(wallpapers: &[Arc<Wallpaper>], color: [u8; 4]) {
+    crate::wallpaper::stop_animations(wallpapers);
+    let mut used_single_pixel_buffer = false;
+    for wallpaper in wallpapers {
+        let [r, g, b, _] = color;
+        wallpaper.set_img_info(utils::ipc::BgImg::Color([r, g, b]));
+        wallpaper.set_playing_animation(None);
+        used_single_pixel_buffer = wallpaper.clear_with_single_pixel_buffer(color);
+        if !used_single_pixel_buffer {
+            wallpaper.clear(color);
+        }
+    }
+    if !used_single_pixel_buffer {
+        crate::wallpaper::attach_buffers_and_damange_surfaces(wallpapers, None);
+    }
+    crate::wallpaper::commit_wallpapers(wallpapers);
+}
+
 /// This is a wrapper that makes sure to delete the socket when it is dropped
-struct SocketWrapper(OwnedFd);
+struct SocketWrapper(OwnedFd, PathBuf);
 impl SocketWrapper {
-    fn new() -> Result<Self, String> {
-        let socket_addr = get_socket_path();
-
+    fn new(socket_addr: PathBuf) -> Result<Self, String> {
         if socket_addr.exists() {
             if is_daemon_running(&socket_addr)? {
                 return Err(
@@ -572,20 +1759,147 @@ impl SocketWrapper {
         rustix::net::listen(&socket, 0).unwrap();
 
         debug!("Created socket in {:?}", socket_addr);
-        Ok(Self(socket))
+        Ok(Self(socket, socket_addr))
     }
 }
 
 impl Drop for SocketWrapper {
     fn drop(&mut self) {
-        let socket_addr = get_socket_path();
-        if let Err(e) = fs::remove_file(&socket_addr) {
+        let socket_addr = &self.1;
+        if let Err(e) = fs::remove_file(socket_addr) {
             error!("Failed to remove socket at {socket_addr:?}: {e}");
         }
         info!("Removed socket at {:?}", socket_addr);
     }
 }
 
+/// `--max-animation-memory`: reserves the memory the animations of a request take, one
+/// reservation per image, or none if it has no animations. What the `wallpapers` they go to hold
+/// counts as free.
+///
+/// If they do not fit, we only reserve their compressed frames, and stop interpolating and
+/// blending them, which is what keeps frames unpacked. If not even that fits, we drop the
+/// animations, displaying only their first frame. We warn about it either way
+fn reserve_animation_memory(
+    imgs: &[ImgReq],
+    animations: &mut Option<Box<[Animation]>>,
+    streams: Option<&[Arc<FrameStream>]>,
+    blends: &mut Option<Box<[Blend]>>,
+    wallpapers: &[Vec<Arc<Wallpaper>>],
+) -> Vec<Arc<Reservation>> {
+    if animations.is_none() && streams.is_none() {
+        return Vec::new();
+    }
+    let layered = blends.is_some();
+    // nothing plays animations without wallpapers
+    let needed = |need: &dyn Fn(usize) -> usize| -> Vec<usize> {
+        (0..imgs.len())
+            .map(|i| if wallpapers[i].is_empty() { 0 } else { need(i) })
+            .collect()
+    };
+    let whole = needed(&|i| match (animations.as_deref(), streams) {
+        (Some(animations), _) => memory::needed(&animations[i], layered),
+        (None, Some(streams)) => {
+            let frame = imgs[i].img.bytes().len();
+            memory::unpacked(frame, streams[i].interpolates(), layered)
+        }
+        (None, None) => 0,
+    });
+    // streamed animations reserve their compressed frames as they arrive
+    let compressed = needed(&|i| {
+        animations
+            .as_deref()
+            .map_or(0, |a| memory::compressed(&a[i]))
+    });
+    let freed = memory::freed(
+        wallpapers
+            .iter()
+            .flatten()
+            .filter_map(|w| w.animation_memory()),
+    );
+
+    let e = match memory::reserve(&whole, freed) {
+        Ok(memory) => return memory,
+        Err(e) => e,
+    };
+    if compressed != whole {
+        if let Ok(memory) = memory::reserve(&compressed, freed) {
+            warn!("{e}, so we play it without interpolating or blending it");
+            for animation in animations.iter_mut().flatten() {
+                animation.interpolate = false;
+            }
+            for stream in streams.into_iter().flatten() {
+                stream.stop_interpolating();
+            }
+            *blends = None;
+            return memory;
+        }
+    }
+    warn!("{e}, so we only display its first frame");
+    *animations = None;
+    for stream in streams.into_iter().flatten() {
+        stream.fail();
+    }
+    Vec::new()
+}
+
+/// The arguments an image request was sent with, see `ImageRequestBuilder::replay`
+fn replay_args(replay: Option<Box<[MmappedStr]>>) -> Option<Arc<[String]>> {
+    replay.map(|args| args.iter().map(|arg| arg.str().to_string()).collect())
+}
+
+/// Makes the `wallpapers` of each image keep the arguments to display it again with, or forget
+/// the ones they had if there are none
+fn keep_replay(imgs: &[ImgReq], wallpapers: &[Vec<Arc<Wallpaper>>], replay: Option<Arc<[String]>>) {
+    for (img, wallpapers) in imgs.iter().zip(wallpapers) {
+        for wallpaper in wallpapers {
+            wallpaper.set_replay(img.path.str(), replay.clone());
+        }
+    }
+}
+
+/// Logs what a request sent with `swww img --debug` asks us to do
+fn log_img_request(
+    transition: &Transition,
+    imgs: &[ImgReq],
+    wallpapers: &[Vec<Arc<Wallpaper>>],
+    animations: Option<&[Animation]>,
+) {
+    debug!(
+        "debug request: {:?} transition, {}s at {} fps, step {}",
+        transition.transition_type, transition.duration, transition.fps, transition.step
+    );
+    for (i, (img, wallpapers)) in imgs.iter().zip(wallpapers).enumerate() {
+        let names: Vec<String> = wallpapers.iter().filter_map(|w| w.name()).collect();
+        debug!(
+            "debug request: image {} is {}x{} {:?} ({} bytes), for {names:?}",
+            img.path.str(),
+            img.dim.0,
+            img.dim.1,
+            img.format,
+            img.img.bytes().len(),
+        );
+        if let Some(animation) = animations.and_then(|a| a.get(i)) {
+            debug!(
+                "debug request: it is animated, with {} frames ({:?} per cycle)",
+                animation.animation.len(),
+                animation.cycle_duration()
+            );
+        }
+    }
+}
+
+thread_local! {
+    /// Whether the request this thread is handling was sent with `swww img --debug`
+    static REQUEST_DEBUG: std::cell::Cell<bool> = const { std::cell::Cell::new(false) };
+}
+
+/// Makes the `Logger` print every debug message from this thread, regardless of its level. The
+/// threads that handle a debug request call this when they start
+pub(crate) fn set_request_debug(debug: bool) {
+    REQUEST_DEBUG.with(|d| d.set(debug));
+}
+
 struct Logger {
     level_filter: LevelFilter,
     start: std::time::Instant,
@@ -595,6 +1909,7 @@ struct Logger {
 impl log::Log for Logger {
     fn enabled(&self, metadata: &log::Metadata) -> bool {
         metadata.level() <= self.level_filter
+            || (metadata.level() <= LevelFilter::Debug && REQUEST_DEBUG.with(|d| d.get()))
     }
 
     fn log(&self, record: &log::Record) {
@@ -644,7 +1959,8 @@ fn make_logger(quiet: bool) {
         start: std::time::Instant::now(),
         is_term: std::io::stderr().is_terminal(),
     }))
-    .map(|()| log::set_max_level(level_filter))
+    // debug requests may log more than `level_filter` allows, so the `Logger` filters instead
+    .map(|()| log::set_max_level(LevelFilter::Debug))
     .unwrap();
 }
 
@@ -679,3 +1995,61 @@ fn spin_sleep(duration: std::time::Duration) {
         std::thread::yield_now();
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Sends `request` through a socket, receiving it as the daemon does
+    fn receive(request: RequestSend) -> RequestRecv {
+        let (client, daemon) = std::os::unix::net::UnixStream::pair().unwrap();
+        request.send(&client.into()).unwrap();
+        RequestRecv::receive(read_socket(&daemon.into()).unwrap())
+    }
+
+    fn unknown_output_error(result: Result<(), Answer>) -> Option<String> {
+        match result {
+            Err(Answer::Err(ErrorKind::UnknownOutput, e)) => Some(e),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn requests_for_unknown_outputs_list_the_valid_ones() {
+        let known = ["HDMI-A-1".to_string(), "DP-1".to_string()];
+        let clear = utils::ipc::ClearSend {
+            color: [0, 0, 0, 255],
+            outputs: Box::new(["DP-1".to_string(), "DP-2".to_string()]),
+        };
+        let RequestRecv::Clear(clear) = receive(RequestSend::Clear(clear.create_request())) else {
+            panic!("expected a clear request");
+        };
+        assert_eq!(
+            unknown_output_error(check_output_names(&clear.outputs, &known, 0)).as_deref(),
+            Some("output DP-2 was not found. Valid outputs are: DP-1, HDMI-A-1")
+        );
+        assert!(check_output_names(&clear.outputs[..1], &known, 0).is_ok());
+
+        let mut builder =
+            utils::ipc::ImageRequestBuilder::with_default_transition().without_output_cache();
+        let img = utils::ipc::ImgSend {
+            path: "0x000000".to_string(),
+            img: Box::new([0; 3]),
+            dim: (1, 1),
+            format: PixelFormat::Rgb,
+        };
+        builder.push(img, &["eDP-1".to_string()], None);
+        let RequestRecv::Img(img) = receive(RequestSend::Img(builder.build())) else {
+            panic!("expected an image request");
+        };
+        let e = unknown_output_error(check_output_names(img.outputs.iter().flatten(), &[], 1));
+        assert!(e
+            .unwrap()
+            .contains("1 more output(s) did not get their name"));
+        let e = unknown_output_error(check_output_names(img.outputs.iter().flatten(), &[], 0));
+        assert_eq!(
+            e.as_deref(),
+            Some("output eDP-1 was not found: no outputs are connected")
+        );
+    }
+}
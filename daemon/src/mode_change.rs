@@ -0,0 +1,185 @@
+//! Redraws the wallpapers whose outputs change resolution, like after a mode switch, instead of
+//! leaving them stretched (or broken) until they are set again.
+//!
+//! Right away, we stretch what the wallpaper displayed to the new resolution, so that it never
+//! shows anything broken. Then, if we can, we display its image again with `swww img`, which
+//! resizes it properly (animations included), fading from the stretched copy in
+//! `swww-daemon --resize-fade` milliseconds. We run it with the arguments the image was set with
+//! (see `Wallpaper::replay`), so that it keeps its `--resize`, `--region`, `--blend` and so on.
+//! This covers images restored from the cache too, since `swww restore` sets them the same way.
+//! Images set without them, like the ones read from stdin or spanned over several outputs, keep
+//! the stretched copy until they are set again, and so do images whose file is gone.
+
+use std::{
+    process::Command,
+    sync::{
+        atomic::{AtomicU32, Ordering},
+        Arc,
+    },
+};
+
+use log::{debug, warn};
+
+use crate::wallpaper::Wallpaper;
+use crate::wayland::globals;
+
+/// `swww-daemon --resize-fade`, in milliseconds. 0 means no fade at all
+static FADE_MS: AtomicU32 = AtomicU32::new(300);
+
+pub(crate) fn set_fade_ms(ms: u32) {
+    FADE_MS.store(ms, Ordering::Relaxed);
+}
+
+/// What a wallpaper displayed before its output changed resolution
+pub(crate) struct Resized {
+    pub(crate) contents: Box<[u8]>,
+    pub(crate) dim: (u32, u32),
+}
+
+/// Stretches what `wallpaper` displayed before it was `resized` to its new dimensions, then
+/// displays its image again, in a thread of its own
+pub(crate) fn redraw(wallpaper: Arc<Wallpaper>, resized: Resized) {
+    std::thread::Builder::new()
+        .name("mode change".to_string())
+        .stack_size(1 << 15)
+        .spawn(move || {
+            let dim = wallpaper.get_dimensions();
+            let channels = globals::pixel_format().channels() as usize;
+            let stretched = stretch(&resized.contents, resized.dim, dim, channels);
+            let drawn = wallpaper.canvas_change(|canvas| {
+                if canvas.len() != stretched.len() {
+                    // it changed resolution again, and another thread takes care of that
+                    return false;
+                }
+                canvas.copy_from_slice(&stretched);
+                true
+            });
+            if !drawn {
+                return;
+            }
+            let wallpapers = [Arc::clone(&wallpaper)];
+            // this waits for the frame callback of the output
+            crate::wallpaper::attach_buffers_and_damange_surfaces(&wallpapers, None);
+            crate::wallpaper::commit_wallpapers(&wallpapers);
+
+            let info = wallpaper.get_bg_info();
+            let Some(args) = wallpaper.replay() else {
+                debug!(
+                    "{} cannot be displayed again on {}, stretching it instead",
+                    info.img, info.name
+                );
+                return;
+            };
+            debug!(
+                "displaying {} again on {}, at {}x{}",
+                info.img, info.name, dim.0, dim.1
+            );
+            if let Err(e) = reload(&args, &info.name) {
+                warn!("failed to display {} again on {}: {e}", info.img, info.name);
+            }
+        })
+        .unwrap(); // builder only fails if the name contains null bytes
+}
+
+/// Displays an image on `output` again, running `swww img` with the `args` it was set with
+fn reload(args: &[String], output: &str) -> std::io::Result<()> {
+    let status = reload_command(args, output).status()?;
+    if !status.success() {
+        return Err(std::io::Error::other(format!("swww exited with {status}")));
+    }
+    Ok(())
+}
+
+fn reload_command(args: &[String], output: &str) -> Command {
+    let mut cmd = Command::new("swww");
+    let namespace = crate::namespace();
+    if !namespace.is_empty() {
+        cmd.arg(format!("--namespace={namespace}"));
+    }
+    cmd.args(["img", &format!("--outputs={output}"), "--force"]);
+    match FADE_MS.load(Ordering::Relaxed) {
+        0 => cmd.arg("--transition-type=none"),
+        ms => cmd.args([
+            "--transition-type=fade".to_string(),
+            format!("--transition-duration={}", ms as f32 / 1000.0),
+        ]),
+    };
+    cmd.args(args);
+    cmd
+}
+
+/// Stretches `contents`, of dimensions `from`, to dimensions `to`, interpolating linearly between
+/// its pixels
+fn stretch(contents: &[u8], from: (u32, u32), to: (u32, u32), channels: usize) -> Box<[u8]> {
+    let (from_width, from_height) = (from.0 as usize, from.1 as usize);
+    let (to_width, to_height) = (to.0 as usize, to.1 as usize);
+    if from == to {
+        return contents.into();
+    }
+
+    // where each pixel we draw comes from, in 1/256ths of a source pixel, and the weight of the
+    // next source pixel
+    let sample = |i: usize, to: usize, from: usize| -> (usize, usize, u32) {
+        let position = ((i * 2 + 1) * from * 256 / (to * 2)).saturating_sub(128);
+        let first = (position / 256).min(from - 1);
+        let second = (first + 1).min(from - 1);
+        (first, second, (position % 256) as u32)
+    };
+    let columns: Box<[_]> = (0..to_width)
+        .map(|x| sample(x, to_width, from_width))
+        .collect();
+
+    let mut stretched = vec![0; to_width * to_height * channels].into_boxed_slice();
+    for (y, row) in stretched.chunks_exact_mut(to_width * channels).enumerate() {
+        let (top, bottom, dy) = sample(y, to_height, from_height);
+        let top = &contents[top * from_width * channels..][..from_width * channels];
+        let bottom = &contents[bottom * from_width * channels..][..from_width * channels];
+        for (pixel, &(left, right, dx)) in row.chunks_exact_mut(channels).zip(columns.iter()) {
+            for (c, byte) in pixel.iter_mut().enumerate() {
+                let at = |line: &[u8], x: usize| line[x * channels + c] as u32;
+                let top = at(top, left) * (256 - dx) + at(top, right) * dx;
+                let bottom = at(bottom, left) * (256 - dx) + at(bottom, right) * dx;
+                *byte = ((top * (256 - dy) + bottom * dy) >> 16) as u8;
+            }
+        }
+    }
+    stretched
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn stretching_keeps_the_colors_in_place() {
+        // a black column and a white one, with 3 bytes per pixel
+        let contents = [0, 0, 0, 255, 255, 255, 0, 0, 0, 255, 255, 255];
+        let stretched = stretch(&contents, (2, 2), (4, 1), 3);
+        let reds: Vec<u8> = stretched.chunks_exact(3).map(|pixel| pixel[0]).collect();
+        assert_eq!(reds[0], 0);
+        assert!(reds[1] < reds[2]);
+        assert_eq!(reds[3], 255);
+
+        assert_eq!(&*stretch(&contents, (2, 2), (2, 2), 3), &contents);
+    }
+
+    #[test]
+    fn reloading_fades_into_the_image_unless_told_otherwise() {
+        let args = |cmd: &Command| -> Vec<String> {
+            cmd.get_args()
+                .map(|arg| arg.to_string_lossy().into_owned())
+                .collect()
+        };
+        let replay = ["--resize=fit".to_string(), "/a.png".to_string()];
+        let fading = args(&reload_command(&replay, "DP-1"));
+        assert!(fading.contains(&"--transition-type=fade".to_string()));
+        assert!(fading.contains(&"--force".to_string()));
+        // the image keeps the options it was set with
+        assert_eq!(fading[fading.len() - 2..], replay);
+
+        set_fade_ms(0);
+        let instant = args(&reload_command(&replay, "DP-1"));
+        set_fade_ms(300);
+        assert!(instant.contains(&"--transition-type=none".to_string()));
+    }
+}
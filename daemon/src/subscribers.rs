@@ -0,0 +1,122 @@
+//! Clients that asked to be told whenever the information we would answer a `Query` with changes.
+//!
+//! Changes may happen in any thread (e.g. the transition threads set the new image's info), but
+//! only the main loop talks to the subscribers. So other threads just write to an eventfd, which
+//! the main loop polls.
+//!
+//! Subscribers are also sent the progress of running transitions. Transition threads throttle
+//! those themselves, and only bother reporting it if there is someone to send it to.
+
+use std::sync::{
+    atomic::{AtomicBool, Ordering},
+    Mutex, OnceLock,
+};
+
+use log::{debug, error};
+use rustix::{
+    event::{eventfd, EventfdFlags},
+    fd::OwnedFd,
+};
+use utils::ipc::{Answer, BgInfo, TransitionProgress};
+
+/// Whether anything changed besides transition progress since the last broadcast
+static INFO_CHANGED: AtomicBool = AtomicBool::new(false);
+/// The latest progress of each output's transition since the last broadcast
+static PROGRESS: Mutex<Vec<TransitionProgress>> = Mutex::new(Vec::new());
+/// Whether we have any subscribers, so that transitions can skip reporting their progress
+static HAS_SUBSCRIBERS: AtomicBool = AtomicBool::new(false);
+
+/// The eventfd the main loop should poll, to know when to call `Subscribers::broadcast`
+pub(crate) fn changed_fd() -> &'static OwnedFd {
+    static CHANGED: OnceLock<OwnedFd> = OnceLock::new();
+    CHANGED.get_or_init(|| {
+        eventfd(0, EventfdFlags::CLOEXEC | EventfdFlags::NONBLOCK)
+            .expect("failed to create eventfd")
+    })
+}
+
+/// Tells the main loop that the wallpapers' information changed. Can be called from any thread
+pub(crate) fn notify_changed() {
+    INFO_CHANGED.store(true, Ordering::Release);
+    wake_main_loop();
+}
+
+/// Whether anyone wants to know about transition progress
+pub(crate) fn has_subscribers() -> bool {
+    HAS_SUBSCRIBERS.load(Ordering::Acquire)
+}
+
+/// Tells the subscribers how far the transition in `output` got. Can be called from any thread.
+/// If it is called many times before the main loop broadcasts, only the latest one is sent
+pub(crate) fn notify_progress(output: String, percent: u8) {
+    {
+        let mut progress = PROGRESS.lock().unwrap();
+        match progress.iter_mut().find(|p| p.output == output) {
+            Some(p) => p.percent = percent,
+            None => progress.push(TransitionProgress { output, percent }),
+        }
+    }
+    wake_main_loop();
+}
+
+fn wake_main_loop() {
+    if let Err(e) = rustix::io::write(changed_fd(), &1u64.to_ne_bytes()) {
+        error!("failed to notify subscribers of change: {e}");
+    }
+}
+
+pub(crate) struct Subscribers {
+    streams: Vec<OwnedFd>,
+}
+
+impl Subscribers {
+    pub(crate) fn new() -> Self {
+        Self {
+            streams: Vec::new(),
+        }
+    }
+
+    pub(crate) fn add(&mut self, stream: OwnedFd) {
+        // we must never block the main loop because a subscriber isn't reading its messages
+        if let Err(e) = rustix::io::ioctl_fionbio(&stream, true) {
+            error!("failed to set subscriber socket as non blocking: {e}");
+            return;
+        }
+        debug!("New subscriber");
+        self.streams.push(stream);
+        HAS_SUBSCRIBERS.store(true, Ordering::Release);
+    }
+
+    /// Sends what changed since the last call to every subscriber: the result of `infos`, if the
+    /// wallpapers' information changed, and the transitions' progress. Subscribers that
+    /// disconnected (or that are too slow to read what we send them) are dropped
+    pub(crate) fn broadcast(&mut self, infos: impl FnOnce() -> Box<[BgInfo]>) {
+        // reset the eventfd's counter
+        let mut buf = [0u8; 8];
+        let _ = rustix::io::read(changed_fd(), &mut buf);
+        let changed = INFO_CHANGED.swap(false, Ordering::AcqRel);
+        let progress = std::mem::take(&mut *PROGRESS.lock().unwrap());
+
+        if self.streams.is_empty() {
+            return;
+        }
+
+        if changed {
+            self.send(&Answer::Info(infos()));
+        }
+        if !progress.is_empty() {
+            self.send(&Answer::Progress(progress.into()));
+        }
+    }
+
+    fn send(&mut self, answer: &Answer) {
+        self.streams.retain(|stream| match answer.send(stream) {
+            Ok(()) => true,
+            Err(e) => {
+                debug!("Dropping subscriber: {e}");
+                false
+            }
+        });
+        HAS_SUBSCRIBERS.store(!self.streams.is_empty(), Ordering::Release);
+    }
+}
@@ -1,23 +1,98 @@
 use log::{debug, error, warn};
-use utils::ipc::{BgImg, BgInfo, Scale};
+use utils::ipc::{Animation, BgImg, BgInfo, Region, Scale};
 
 use std::{
-    num::NonZeroI32,
+    num::{NonZeroI32, NonZeroU16},
     sync::{
         atomic::{AtomicBool, AtomicUsize, Ordering},
         Arc, Condvar, Mutex, RwLock,
     },
+    time::{Duration, Instant},
 };
 
+use crate::animations::memory::Reservation;
+use crate::dim::Undimmed;
+use crate::mode_change::Resized;
 use crate::wayland::{
     bump_pool::BumpPool,
     globals,
     interfaces::{
-        wl_output, wl_surface, wp_fractional_scale_v1, wp_viewport, zwlr_layer_surface_v1,
+        wl_buffer, wl_compositor, wl_output, wl_region, wl_surface, wp_fractional_scale_v1,
+        wp_single_pixel_buffer_manager_v1, wp_viewport, xdg_surface, xdg_toplevel,
+        zwlr_layer_surface_v1,
     },
+    wire::WlFixed,
     ObjectId, WlDynObj,
 };
 
+/// Which part of our surfaces we tell the compositor is opaque, while we display something opaque.
+/// Set with `swww-daemon --opaque-region`
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub(crate) enum OpaqueRegion {
+    /// The whole surface, so that the compositor can skip drawing whatever is behind it
+    #[default]
+    Full,
+    /// Nothing, like surfaces that never set their opaque region
+    None,
+    /// Everything but margins of that many (logical) pixels, in the same order as css: top, right,
+    /// bottom and left
+    Inset([u32; 4]),
+}
+
+impl OpaqueRegion {
+    /// The opaque rectangle of a surface with these (logical) dimensions, as x, y, width and
+    /// height. `None` if there is none
+    fn rect(&self, width: i32, height: i32) -> Option<(i32, i32, i32, i32)> {
+        let [top, right, bottom, left] = match self {
+            Self::Full => [0; 4],
+            Self::None => return None,
+            Self::Inset(inset) => inset.map(|i| i.min(i32::MAX as u32) as i32),
+        };
+        let w = width.saturating_sub(left).saturating_sub(right);
+        let h = height.saturating_sub(top).saturating_sub(bottom);
+        (w > 0 && h > 0).then_some((left, top, w, h))
+    }
+}
+
+/// The role our wl_surfaces have
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum ShellSurface {
+    Layer(ObjectId),
+    /// A fullscreen window, for compositors without zwlr_layer_shell_v1
+    Xdg {
+        surface: ObjectId,
+        toplevel: ObjectId,
+    },
+}
+
+impl ShellSurface {
+    /// Whether `id` is (part of) this shell surface
+    fn has(&self, id: ObjectId) -> bool {
+        match *self {
+            Self::Layer(layer_surface) => layer_surface == id,
+            Self::Xdg { surface, toplevel } => surface == id || toplevel == id,
+        }
+    }
+
+    fn destroy(&self) {
+        match *self {
+            Self::Layer(layer_surface) => {
+                if let Err(e) = zwlr_layer_surface_v1::req::destroy(layer_surface) {
+                    error!("error destroying zwlr_layer_surface_v1: {e:?}");
+                }
+            }
+            Self::Xdg { surface, toplevel } => {
+                if let Err(e) = xdg_toplevel::req::destroy(toplevel) {
+                    error!("error destroying xdg_toplevel: {e:?}");
+                }
+                if let Err(e) = xdg_surface::req::destroy(surface) {
+                    error!("error destroying xdg_surface: {e:?}");
+                }
+            }
+        }
+    }
+}
+
 #[derive(Debug)]
 struct AnimationState {
     id: AtomicUsize,
@@ -52,6 +127,85 @@ impl FrameCallbackHandler {
     }
 }
 
+/// A rectangle, in buffer coordinates, containing every pixel that changed since the last frame
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) struct Damage {
+    x_begin: u32,
+    y_begin: u32,
+    x_end: u32,
+    y_end: u32,
+}
+
+impl Damage {
+    /// A damage region with nothing in it
+    pub(crate) const fn empty() -> Self {
+        Self {
+            x_begin: u32::MAX,
+            y_begin: u32::MAX,
+            x_end: 0,
+            y_end: 0,
+        }
+    }
+
+    /// Grows the region to include the pixels `x_begin..x_end` of `line`
+    #[inline]
+    pub(crate) fn add_span(&mut self, line: usize, x_begin: usize, x_end: usize) {
+        if x_begin >= x_end {
+            return;
+        }
+        self.x_begin = self.x_begin.min(x_begin as u32);
+        self.x_end = self.x_end.max(x_end as u32);
+        self.y_begin = self.y_begin.min(line as u32);
+        self.y_end = self.y_end.max(line as u32 + 1);
+    }
+
+    /// Copies `new` onto `canvas`, returning the region that actually changed. Both are images
+    /// `width` pixels wide with `channels` bytes per pixel. Only the changed bytes get written, so
+    /// setting an image over a similar one damages (and uploads) as little as possible
+    pub(crate) fn copy_changes(
+        canvas: &mut [u8],
+        new: &[u8],
+        width: usize,
+        channels: usize,
+    ) -> Self {
+        let mut damage = Self::empty();
+        let stride = width * channels;
+        let lines = canvas
+            .chunks_exact_mut(stride)
+            .zip(new.chunks_exact(stride));
+        for (line, (old, new)) in lines.enumerate() {
+            let Some(begin) = old.iter().zip(new).position(|(a, b)| a != b) else {
+                continue;
+            };
+            let end = old.iter().zip(new).rposition(|(a, b)| a != b).unwrap() + 1;
+            old[begin..end].copy_from_slice(&new[begin..end]);
+            damage.add_span(line, begin / channels, end.div_ceil(channels));
+        }
+        damage
+    }
+
+    /// Grows the region to also include `other`
+    pub(crate) fn merge(&mut self, other: &Self) {
+        self.x_begin = self.x_begin.min(other.x_begin);
+        self.y_begin = self.y_begin.min(other.y_begin);
+        self.x_end = self.x_end.max(other.x_end);
+        self.y_end = self.y_end.max(other.y_end);
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.x_begin >= self.x_end || self.y_begin >= self.y_end
+    }
+
+    /// Returns `(x, y, width, height)`, clipped to a buffer of `width` by `height` pixels
+    fn rect(&self, width: i32, height: i32) -> (i32, i32, i32, i32) {
+        let x_begin = (self.x_begin as i32).clamp(0, width);
+        let y_begin = (self.y_begin as i32).clamp(0, height);
+        let x_end = (self.x_end as i32).clamp(x_begin, width);
+        let y_end = (self.y_end as i32).clamp(y_begin, height);
+        (x_begin, y_begin, x_end - x_begin, y_end - y_begin)
+    }
+}
+
 /// Owns all the necessary information for drawing.
 #[derive(Clone, Debug)]
 struct WallpaperInner {
@@ -59,8 +213,11 @@ struct WallpaperInner {
     desc: Option<String>,
     width: NonZeroI32,
     height: NonZeroI32,
+    position: (i32, i32),
     scale_factor: Scale,
     transform: u32,
+    /// in mHz, as the compositor sends it. 0 means unknown
+    refresh: i32,
 }
 
 impl Default for WallpaperInner {
@@ -70,8 +227,10 @@ impl Default for WallpaperInner {
             desc: None,
             width: unsafe { NonZeroI32::new_unchecked(4) },
             height: unsafe { NonZeroI32::new_unchecked(4) },
+            position: (0, 0),
             scale_factor: Scale::Whole(unsafe { NonZeroI32::new_unchecked(1) }),
             transform: wl_output::transform::NORMAL,
+            refresh: 0,
         }
     }
 }
@@ -83,7 +242,7 @@ pub(super) struct Wallpaper {
     wp_viewport: ObjectId,
     #[allow(unused)]
     wp_fractional: Option<ObjectId>,
-    layer_surface: ObjectId,
+    shell_surface: ShellSurface,
 
     inner: RwLock<WallpaperInner>,
     inner_staging: Mutex<WallpaperInner>,
@@ -94,6 +253,34 @@ pub(super) struct Wallpaper {
     frame_callback_handler: FrameCallbackHandler,
     img: Mutex<BgImg>,
     pool: Mutex<BumpPool>,
+    /// single pixel buffer currently attached to the surface, and the color it was created with
+    single_pixel_buffer: Mutex<Option<(ObjectId, [u8; 4])>>,
+    /// whether the next attached buffer must be damaged in full, regardless of what the
+    /// transition tells us, because the surface's contents changed in ways it doesn't know about
+    needs_full_damage: AtomicBool,
+    /// animation being displayed, along with the index of the next frame it will apply (so that
+    /// live fades can keep playing it) and whether it is blended over a base image
+    playing_animation: Mutex<Option<(Arc<Animation>, usize, bool)>>,
+    /// whether the compositor told us which scale it prefers for our surface (through
+    /// wl_surface.preferred_buffer_scale), in which case we ignore the scale of the output
+    has_preferred_buffer_scale: AtomicBool,
+    opaque_region: OpaqueRegion,
+    /// whether what we display is opaque. Only then do we set `opaque_region`
+    opaque: AtomicBool,
+    /// `swww img --region`: the part of the output our surface covers, if not all of it. We keep
+    /// drawing the whole output, and the viewport crops it
+    region: Mutex<Option<Region>>,
+    /// `swww boost-fps`: the fps our animations play at, regardless of their own frame durations
+    /// and of `--max-fps`, and until when
+    fps_boost: Mutex<Option<(NonZeroU16, Instant)>>,
+    /// `swww dim-schedule`: what we display before dimming it, if we know
+    undimmed: Mutex<Option<Undimmed>>,
+    /// `swww-daemon --max-animation-memory`: the memory of the animation we display, shared with
+    /// the other wallpapers displaying it, until we stop displaying it
+    animation_memory: Mutex<Option<Arc<Reservation>>>,
+    /// the path of the image we display, and the arguments of `swww img` it was set with, to
+    /// display it again when our output changes resolution
+    replay: Mutex<Option<(String, Arc<[String]>)>>,
 }
 
 impl Wallpaper {
@@ -103,20 +290,29 @@ impl Wallpaper {
         wl_surface: ObjectId,
         wp_viewport: ObjectId,
         wp_fractional: Option<ObjectId>,
-        layer_surface: ObjectId,
+        shell_surface: ShellSurface,
+        opaque_region: OpaqueRegion,
     ) -> Self {
         let inner = RwLock::default();
         let inner_staging = Mutex::default();
 
-        // Configure the layer surface
-        zwlr_layer_surface_v1::req::set_anchor(layer_surface, 15).unwrap();
-        zwlr_layer_surface_v1::req::set_exclusive_zone(layer_surface, -1).unwrap();
-        zwlr_layer_surface_v1::req::set_margin(layer_surface, 0, 0, 0, 0).unwrap();
-        zwlr_layer_surface_v1::req::set_keyboard_interactivity(
-            layer_surface,
-            zwlr_layer_surface_v1::keyboard_interactivity::NONE,
-        )
-        .unwrap();
+        match shell_surface {
+            ShellSurface::Layer(layer_surface) => {
+                zwlr_layer_surface_v1::req::set_anchor(layer_surface, 15).unwrap();
+                zwlr_layer_surface_v1::req::set_exclusive_zone(layer_surface, -1).unwrap();
+                zwlr_layer_surface_v1::req::set_margin(layer_surface, 0, 0, 0, 0).unwrap();
+                zwlr_layer_surface_v1::req::set_keyboard_interactivity(
+                    layer_surface,
+                    zwlr_layer_surface_v1::keyboard_interactivity::NONE,
+                )
+                .unwrap();
+            }
+            ShellSurface::Xdg { toplevel, .. } => {
+                xdg_toplevel::req::set_title(toplevel, "swww-daemon").unwrap();
+                xdg_toplevel::req::set_app_id(toplevel, "swww-daemon").unwrap();
+                xdg_toplevel::req::set_fullscreen(toplevel, Some(output)).unwrap();
+            }
+        }
         wl_surface::req::set_buffer_scale(wl_surface, 1).unwrap();
 
         let frame_callback_handler = FrameCallbackHandler::new(wl_surface);
@@ -131,7 +327,7 @@ impl Wallpaper {
             wl_surface,
             wp_viewport,
             wp_fractional,
-            layer_surface,
+            shell_surface,
             inner,
             inner_staging,
             animation_state: AnimationState {
@@ -141,17 +337,30 @@ impl Wallpaper {
             frame_callback_handler,
             img: Mutex::new(BgImg::Color([0, 0, 0])),
             pool,
+            single_pixel_buffer: Mutex::new(None),
+            needs_full_damage: AtomicBool::new(true),
+            playing_animation: Mutex::new(None),
+            has_preferred_buffer_scale: AtomicBool::new(false),
+            opaque_region,
+            opaque: AtomicBool::new(true),
+            region: Mutex::new(None),
+            fps_boost: Mutex::new(None),
+            undimmed: Mutex::new(None),
+            animation_memory: Mutex::new(None),
+            replay: Mutex::new(None),
         }
     }
 
     pub fn get_bg_info(&self) -> BgInfo {
         let inner = self.inner.read().unwrap();
         BgInfo {
-            name: inner.name.clone().unwrap_or("?".to_string()),
+            name: inner.name.clone().unwrap_or(BgInfo::UNNAMED.to_string()),
             dim: (inner.width.get() as u32, inner.height.get() as u32),
+            position: inner.position,
             scale_factor: inner.scale_factor,
             img: self.img.lock().unwrap().clone(),
-            pixel_format: globals::pixel_format(),
+            pixel_format: globals::client_pixel_format(),
+            animated: self.playing_animation.lock().unwrap().is_some(),
         }
     }
 
@@ -190,10 +399,34 @@ impl Wallpaper {
         }
     }
 
+    pub fn set_position(&self, x: i32, y: i32) {
+        self.inner_staging.lock().unwrap().position = (x, y);
+    }
+
     pub fn set_transform(&self, transform: u32) {
         self.inner_staging.lock().unwrap().transform = transform;
     }
 
+    pub fn set_refresh(&self, refresh: i32) {
+        self.inner_staging.lock().unwrap().refresh = refresh;
+    }
+
+    /// The scale of the output, from wl_output.scale. We only use it until the compositor tells us
+    /// the scale it prefers for our surface
+    pub fn set_output_scale(&self, factor: NonZeroI32) {
+        if !self.has_preferred_buffer_scale.load(Ordering::Acquire) {
+            self.set_scale(Scale::Whole(factor));
+        }
+    }
+
+    /// The scale the compositor prefers for our surface, from wl_surface.preferred_buffer_scale.
+    /// A fractional scale still takes precedence over it
+    pub fn set_preferred_buffer_scale(&self, factor: NonZeroI32) {
+        self.has_preferred_buffer_scale
+            .store(true, Ordering::Release);
+        self.set_scale(Scale::Whole(factor));
+    }
+
     pub fn set_scale(&self, scale: Scale) {
         let mut lock = self.inner_staging.lock().unwrap();
         if matches!(lock.scale_factor, Scale::Fractional(_)) && matches!(scale, Scale::Whole(_)) {
@@ -227,22 +460,45 @@ impl Wallpaper {
         }
     }
 
-    pub fn commit_surface_changes(&self, use_cache: bool) {
+    /// `sticky` are the arguments set with `swww img --sticky`, if any. When the output gets its
+    /// name, we display the sticky image on it, or else the image in the cache (if `use_cache`)
+    ///
+    /// Returns what we displayed before, if the output changed resolution after we drew on it,
+    /// for `mode_change::redraw` to display it again
+    #[must_use]
+    pub fn commit_surface_changes(
+        &self,
+        use_cache: bool,
+        sticky: Option<&[String]>,
+    ) -> Option<Resized> {
         use wl_output::transform;
         let mut inner = self.inner.write().unwrap();
         let staging = self.inner_staging.lock().unwrap();
 
-        if inner.name != staging.name && use_cache {
+        if inner.name != staging.name {
             let name = staging.name.clone().unwrap_or("".to_string());
-            std::thread::Builder::new()
-                .name("cache loader".to_string())
-                .stack_size(1 << 14)
-                .spawn(move || {
-                    if let Err(e) = utils::cache::load(&name) {
-                        warn!("failed to load cache: {e}");
-                    }
-                })
-                .unwrap(); // builder only fails if `name` contains null bytes
+            if let Some(args) = sticky {
+                let args = args.to_vec();
+                std::thread::Builder::new()
+                    .name("sticky loader".to_string())
+                    .stack_size(1 << 14)
+                    .spawn(move || {
+                        if let Err(e) = load_sticky(&name, &args) {
+                            warn!("failed to display sticky image: {e}");
+                        }
+                    })
+                    .unwrap(); // builder only fails if `name` contains null bytes
+            } else if use_cache {
+                std::thread::Builder::new()
+                    .name("cache loader".to_string())
+                    .stack_size(1 << 14)
+                    .spawn(move || {
+                        if let Err(e) = utils::cache::load(&name, crate::namespace()) {
+                            warn!("failed to load cache: {e}");
+                        }
+                    })
+                    .unwrap(); // builder only fails if `name` contains null bytes
+            }
         }
 
         let (width, height) = if matches!(
@@ -254,29 +510,53 @@ impl Wallpaper {
             (staging.width, staging.height)
         };
 
-        if staging.scale_factor != inner.scale_factor || staging.transform != inner.transform {
-            match staging.scale_factor {
-                Scale::Whole(i) => {
-                    // unset destination
-                    wp_viewport::req::set_destination(self.wp_viewport, -1, -1).unwrap();
-                    wl_surface::req::set_buffer_scale(self.wl_surface, i.get()).unwrap();
-                }
-                Scale::Fractional(_) => {
-                    wl_surface::req::set_buffer_scale(self.wl_surface, 1).unwrap();
-                    wp_viewport::req::set_destination(self.wp_viewport, width.get(), height.get())
-                        .unwrap();
-                }
+        let mut region = self.region.lock().unwrap();
+        let region_dropped = match *region {
+            Some(r) if !r.fits((width.get() as u32, height.get() as u32)) => {
+                warn!(
+                    "region {r} does not fit output {} anymore, covering all of it",
+                    self.output_name
+                );
+                *region = None;
+                true
             }
+            _ => false,
+        };
+        drop(region);
+
+        if staging.scale_factor != inner.scale_factor
+            || staging.transform != inner.transform
+            || region_dropped
+            || self.has_single_pixel_buffer() && (inner.width, inner.height) != (width, height)
+        {
+            self.update_viewport(staging.scale_factor, width, height);
         }
 
+        // the dimensions of what we drew so far
+        let drawn_dim = inner
+            .scale_factor
+            .mul_dim(inner.width.get(), inner.height.get());
         inner.scale_factor = staging.scale_factor;
         inner.transform = staging.transform;
+        inner.position = staging.position;
+        inner.refresh = staging.refresh;
         inner.name.clone_from(&staging.name);
         inner.desc.clone_from(&staging.desc);
         if (inner.width, inner.height) == (width, height) {
-            return;
+            return None;
         }
         self.stop_animations();
+        // single pixel buffers already stretch to any resolution
+        let resized = if self.has_single_pixel_buffer() {
+            None
+        } else {
+            let dim = drawn_dim;
+            let mut pool = self.pool.lock().unwrap();
+            pool.last_drawn().map(|contents| Resized {
+                contents: contents.into(),
+                dim: (dim.0 as u32, dim.1 as u32),
+            })
+        };
         inner.width = width;
         inner.height = height;
 
@@ -284,21 +564,237 @@ impl Wallpaper {
         drop(inner);
         drop(staging);
 
-        zwlr_layer_surface_v1::req::set_size(
-            self.layer_surface,
-            width.get() as u32,
-            height.get() as u32,
-        )
-        .unwrap();
-
+        self.configure_layer_surface(width, height);
+        let (surface_width, surface_height) = self.surface_dimensions(width, height);
+        self.update_opaque_region(surface_width, surface_height);
         let (w, h) = scale_factor.mul_dim(width.get(), height.get());
         self.pool.lock().unwrap().resize(w, h);
+        self.needs_full_damage.store(true, Ordering::Release);
 
         self.frame_callback_handler
             .request_frame_callback(self.wl_surface);
         wl_surface::req::commit(self.wl_surface).unwrap();
         self.configured
             .store(true, std::sync::atomic::Ordering::Release);
+        resized
+    }
+
+    fn update_viewport(&self, scale_factor: Scale, width: NonZeroI32, height: NonZeroI32) {
+        let region = *self.region.lock().unwrap();
+        let (surface_width, surface_height) = self.surface_dimensions(width, height);
+        if self.has_single_pixel_buffer() {
+            // single pixel buffers are 1x1, so we always have to stretch them with the viewport
+            wl_surface::req::set_buffer_scale(self.wl_surface, 1).unwrap();
+            self.set_viewport_source(None);
+            wp_viewport::req::set_destination(self.wp_viewport, surface_width, surface_height)
+                .unwrap();
+            return;
+        }
+
+        match scale_factor {
+            Scale::Whole(i) => {
+                if region.is_some() {
+                    wp_viewport::req::set_destination(
+                        self.wp_viewport,
+                        surface_width,
+                        surface_height,
+                    )
+                    .unwrap();
+                } else {
+                    // unset destination
+                    wp_viewport::req::set_destination(self.wp_viewport, -1, -1).unwrap();
+                }
+                wl_surface::req::set_buffer_scale(self.wl_surface, i.get()).unwrap();
+            }
+            Scale::Fractional(_) => {
+                wl_surface::req::set_buffer_scale(self.wl_surface, 1).unwrap();
+                wp_viewport::req::set_destination(self.wp_viewport, surface_width, surface_height)
+                    .unwrap();
+            }
+        }
+        self.set_viewport_source(
+            region.map(|region| viewport_source(&region, scale_factor, width, height)),
+        );
+    }
+
+    /// Crops our buffers to `source`, in buffer coordinates (after `buffer_scale`), or unsets the
+    /// crop if `None`
+    fn set_viewport_source(&self, source: Option<[f64; 4]>) {
+        let [x, y, width, height] = source.unwrap_or([-1.0; 4]);
+        wp_viewport::req::set_source(
+            self.wp_viewport,
+            WlFixed::from(x),
+            WlFixed::from(y),
+            WlFixed::from(width),
+            WlFixed::from(height),
+        )
+        .unwrap();
+    }
+
+    /// The logical dimensions of our surface, given those of the output
+    fn surface_dimensions(&self, width: NonZeroI32, height: NonZeroI32) -> (i32, i32) {
+        match *self.region.lock().unwrap() {
+            Some(region) => (region.width as i32, region.height as i32),
+            None => (width.get(), height.get()),
+        }
+    }
+
+    /// Anchors the layer surface so that it covers our region, or else the whole output. Fullscreen
+    /// windows get the size of the output anyway
+    fn configure_layer_surface(&self, width: NonZeroI32, height: NonZeroI32) {
+        use zwlr_layer_surface_v1::anchor;
+        let ShellSurface::Layer(layer_surface) = self.shell_surface else {
+            return;
+        };
+        match *self.region.lock().unwrap() {
+            Some(region) => {
+                zwlr_layer_surface_v1::req::set_anchor(layer_surface, anchor::TOP | anchor::LEFT)
+                    .unwrap();
+                zwlr_layer_surface_v1::req::set_margin(
+                    layer_surface,
+                    region.y as i32,
+                    0,
+                    0,
+                    region.x as i32,
+                )
+                .unwrap();
+                zwlr_layer_surface_v1::req::set_size(layer_surface, region.width, region.height)
+                    .unwrap();
+            }
+            None => {
+                zwlr_layer_surface_v1::req::set_anchor(
+                    layer_surface,
+                    anchor::TOP | anchor::BOTTOM | anchor::LEFT | anchor::RIGHT,
+                )
+                .unwrap();
+                zwlr_layer_surface_v1::req::set_margin(layer_surface, 0, 0, 0, 0).unwrap();
+                zwlr_layer_surface_v1::req::set_size(
+                    layer_surface,
+                    width.get() as u32,
+                    height.get() as u32,
+                )
+                .unwrap();
+            }
+        }
+    }
+
+    /// Whether `region` fits within our output
+    pub(super) fn fits(&self, region: &Region) -> bool {
+        let inner = self.inner.read().unwrap();
+        region.fits((inner.width.get() as u32, inner.height.get() as u32))
+    }
+
+    /// `swww img --region`: makes our surface only cover `region` of the output, or all of it if
+    /// `None`. `region` must fit within the output. Fullscreen windows always cover all of it
+    pub(super) fn set_region(&self, region: Option<Region>) {
+        let region = match self.shell_surface {
+            ShellSurface::Layer(_) => region,
+            ShellSurface::Xdg { .. } => {
+                if region.is_some() {
+                    warn!("cannot cover only a region of the output without layer-shell");
+                }
+                None
+            }
+        };
+        {
+            let mut lock = self.region.lock().unwrap();
+            if *lock == region {
+                return;
+            }
+            *lock = region;
+        }
+        debug!("Output {} region: {region:?}", self.output_name);
+        let inner = self.inner.read().unwrap();
+        let (scale_factor, width, height) = (inner.scale_factor, inner.width, inner.height);
+        drop(inner);
+        self.configure_layer_surface(width, height);
+        self.update_viewport(scale_factor, width, height);
+        let (surface_width, surface_height) = self.surface_dimensions(width, height);
+        self.update_opaque_region(surface_width, surface_height);
+        // the region takes effect right away, even if no new image is coming
+        wl_surface::req::commit(self.wl_surface).unwrap();
+    }
+
+    /// `swww boost-fps`: plays our animations at `fps`, instead of at their own rate (capped by
+    /// `--max-fps`), for `duration`. Replaces any boost we already had
+    pub(super) fn boost_fps(&self, fps: NonZeroU16, duration: Duration) {
+        debug!(
+            "Output {} fps boost: {fps} for {duration:?}",
+            self.output_name
+        );
+        *self.fps_boost.lock().unwrap() = Some((fps, Instant::now() + duration));
+    }
+
+    /// How long each animation frame lasts while we are boosted, or `None` if we are not
+    pub(crate) fn fps_boost(&self) -> Option<Duration> {
+        let mut boost = self.fps_boost.lock().unwrap();
+        let frame_time = boosted_frame_time(*boost, Instant::now());
+        if frame_time.is_none() {
+            // forget expired boosts, so that we go back to the rate of the animations for good
+            *boost = None;
+        }
+        frame_time
+    }
+
+    pub(crate) fn undimmed(&self) -> Option<Undimmed> {
+        self.undimmed.lock().unwrap().clone()
+    }
+
+    pub(crate) fn set_undimmed(&self, undimmed: Option<Undimmed>) {
+        *self.undimmed.lock().unwrap() = undimmed;
+    }
+
+    /// Records that we displayed the image at `path` dimmed to `brightness`, unless we moved on to
+    /// another one in the meantime
+    pub(crate) fn undimmed_drawn(&self, path: &str, brightness: f32) {
+        if let Some(undimmed) = self.undimmed.lock().unwrap().as_mut() {
+            if undimmed.path() == path {
+                undimmed.set_drawn(brightness);
+            }
+        }
+    }
+
+    /// Records whether what we display is opaque, updating the surface's opaque region if that
+    /// changed. Like all surface state, it only takes effect on the next commit
+    fn set_opaque(&self, opaque: bool) {
+        if self.opaque.swap(opaque, Ordering::AcqRel) != opaque {
+            let inner = self.inner.read().unwrap();
+            let (width, height) = (inner.width, inner.height);
+            drop(inner);
+            let (width, height) = self.surface_dimensions(width, height);
+            self.update_opaque_region(width, height);
+        }
+    }
+
+    /// `width` and `height` are the surface's logical dimensions
+    fn update_opaque_region(&self, width: i32, height: i32) {
+        // we never set anything, so the compositor uses the initial, empty, region
+        if self.opaque_region == OpaqueRegion::None {
+            return;
+        }
+        let rect = if self.opaque.load(Ordering::Acquire) {
+            self.opaque_region.rect(width, height)
+        } else {
+            None
+        };
+        match rect {
+            Some((x, y, width, height)) => {
+                let region = globals::object_create(WlDynObj::Region);
+                wl_compositor::req::create_region(region).unwrap();
+                wl_region::req::add(region, x, y, width, height).unwrap();
+                wl_surface::req::set_opaque_region(self.wl_surface, Some(region)).unwrap();
+                wl_region::req::destroy(region).unwrap();
+            }
+            None => wl_surface::req::set_opaque_region(self.wl_surface, None).unwrap(),
+        }
+    }
+
+    fn has_single_pixel_buffer(&self) -> bool {
+        self.single_pixel_buffer.lock().unwrap().is_some()
+    }
+
+    pub(super) fn name(&self) -> Option<String> {
+        self.inner.read().unwrap().name.clone()
     }
 
     pub(super) fn has_name(&self, name: &str) -> bool {
@@ -312,6 +808,14 @@ impl Wallpaper {
         self.output == output
     }
 
+    pub(super) fn output(&self) -> ObjectId {
+        self.output
+    }
+
+    pub(super) fn transform(&self) -> u32 {
+        self.inner.read().unwrap().transform
+    }
+
     pub(super) fn has_output_name(&self, name: u32) -> bool {
         self.output_name == name
     }
@@ -320,8 +824,9 @@ impl Wallpaper {
         self.wl_surface == wl_surface
     }
 
-    pub(super) fn has_layer_surface(&self, layer_surface: ObjectId) -> bool {
-        self.layer_surface == layer_surface
+    /// Whether `id` is our zwlr_layer_surface_v1, or our xdg_surface or xdg_toplevel
+    pub(super) fn has_shell_surface(&self, id: ObjectId) -> bool {
+        self.shell_surface.has(id)
     }
 
     pub(super) fn try_set_buffer_release_flag(
@@ -350,6 +855,11 @@ impl Wallpaper {
             == token.id
     }
 
+    /// The output's refresh rate, rounded to whole frames per second, if the compositor told us
+    pub(super) fn refresh_rate(&self) -> Option<u16> {
+        refresh_to_fps(self.inner.read().unwrap().refresh)
+    }
+
     pub(super) fn get_dimensions(&self) -> (u32, u32) {
         let inner = self.inner.read().unwrap();
         let dim = inner
@@ -362,7 +872,77 @@ impl Wallpaper {
     where
         F: FnOnce(&mut [u8]) -> T,
     {
-        f(self.pool.lock().unwrap().get_drawable())
+        let mut pool = self.pool.lock().unwrap();
+        let transparent = pool.is_transparent();
+        let canvas = pool.get_drawable();
+        let single_pixel_buffer = self.single_pixel_buffer.lock().unwrap().take();
+        if let Some((buffer, color)) = single_pixel_buffer {
+            // we were displaying a single pixel buffer, so our canvas is out of date. We must also
+            // undo the viewport changes, since we will be attaching a full sized buffer next
+            fill_canvas(canvas, color);
+            let inner = self.inner.read().unwrap();
+            let (scale_factor, width, height) = (inner.scale_factor, inner.width, inner.height);
+            drop(inner);
+            self.update_viewport(scale_factor, width, height);
+            self.set_opaque(!transparent);
+            self.needs_full_damage.store(true, Ordering::Release);
+            if let Err(e) = wl_buffer::req::destroy(buffer) {
+                error!("failed to destroy single pixel wl_buffer: {e:?}");
+            }
+        }
+        f(canvas)
+    }
+
+    /// A copy of what we are displaying, in the wl_shm format. `None` if we never drew anything
+    pub(super) fn buffer_contents(&self) -> Option<Box<[u8]>> {
+        let mut pool = self.pool.lock().unwrap();
+        if let Some((_, color)) = *self.single_pixel_buffer.lock().unwrap() {
+            let (width, height) = self.get_dimensions();
+            let channels = globals::pixel_format().channels() as usize;
+            let mut canvas = vec![0; width as usize * height as usize * channels];
+            fill_canvas(&mut canvas, color);
+            return Some(canvas.into());
+        }
+        pool.last_drawn().map(Into::into)
+    }
+
+    /// The animation we are playing and the index of its next frame, unless it is blended over a
+    /// base image (with `swww img --blend`): then what we display is not its frame, so its next
+    /// frames cannot be applied onto it
+    pub(super) fn playing_animation(&self) -> Option<(Arc<Animation>, usize)> {
+        match self.playing_animation.lock().unwrap().as_ref() {
+            Some((animation, next, false)) => Some((Arc::clone(animation), *next)),
+            _ => None,
+        }
+    }
+
+    /// Whether we are playing an animation, blended or not
+    pub(super) fn is_animated(&self) -> bool {
+        self.playing_animation.lock().unwrap().is_some()
+    }
+
+    /// `playing` is the animation, the index of its next frame and whether it is blended
+    pub(super) fn set_playing_animation(&self, playing: Option<(Arc<Animation>, usize, bool)>) {
+        let started_or_stopped = {
+            let mut lock = self.playing_animation.lock().unwrap();
+            let was_playing = lock.is_some();
+            *lock = playing;
+            was_playing != lock.is_some()
+        };
+        // this is part of our `BgInfo`
+        if started_or_stopped {
+            crate::subscribers::notify_changed();
+        }
+    }
+
+    /// Records the index of the next frame the playing animation will apply. Does nothing if
+    /// `token` is outdated, since then someone else took over the wallpaper
+    pub(super) fn set_playing_frame(&self, token: &AnimationToken, next: usize) {
+        if self.has_animation_id(token) {
+            if let Some((_, frame, _)) = self.playing_animation.lock().unwrap().as_mut() {
+                *frame = next;
+            }
+        }
     }
 
     pub(super) fn create_animation_token(&self) -> AnimationToken {
@@ -377,14 +957,114 @@ impl Wallpaper {
 
     fn stop_animations(&self) {
         self.animation_state.id.fetch_add(1, Ordering::AcqRel);
+        self.release_animation_memory();
+    }
+
+    pub(crate) fn animation_memory(&self) -> Option<Arc<Reservation>> {
+        self.animation_memory.lock().unwrap().clone()
+    }
+
+    /// Holds `reservation` until we stop displaying the animation it is for
+    pub(crate) fn hold_animation_memory(&self, reservation: Arc<Reservation>) {
+        *self.animation_memory.lock().unwrap() = Some(reservation);
+    }
+
+    pub(crate) fn release_animation_memory(&self) {
+        *self.animation_memory.lock().unwrap() = None;
+    }
+
+    /// Keeps the arguments the image at `path` was set with, if the client sent any
+    pub(crate) fn set_replay(&self, path: &str, args: Option<Arc<[String]>>) {
+        *self.replay.lock().unwrap() = args.map(|args| (path.to_string(), args));
+    }
+
+    /// The arguments to display our image again with, unless we display something else by now
+    pub(crate) fn replay(&self) -> Option<Arc<[String]>> {
+        let replay = self.replay.lock().unwrap();
+        let (path, args) = replay.as_ref()?;
+        self.is_displaying(path).then(|| Arc::clone(args))
+    }
+
+    pub(super) fn clear(&self, color: [u8; 4]) {
+        self.set_transparent(color[3] < 255);
+        self.canvas_change(|canvas| fill_canvas(canvas, color))
+    }
+
+    /// Chooses whether our shm buffers have an alpha channel. Only translucent clears need one, so
+    /// we must go back to being opaque before displaying an image
+    pub(super) fn set_transparent(&self, transparent: bool) {
+        if transparent && globals::wl_shm_alpha_format().is_none() {
+            warn!(
+                "wl_shm format {:?} has no alpha channel. Using an opaque color instead",
+                globals::pixel_format()
+            );
+            return;
+        }
+        if self.pool.lock().unwrap().set_transparent(transparent) {
+            self.needs_full_damage.store(true, Ordering::Release);
+        }
+        if !self.has_single_pixel_buffer() {
+            self.set_opaque(!transparent);
+        }
     }
 
-    pub(super) fn clear(&self, color: [u8; 3]) {
-        self.canvas_change(|canvas| {
-            for pixel in canvas.chunks_exact_mut(globals::pixel_format().channels().into()) {
-                pixel[0..3].copy_from_slice(&color);
+    /// Clears the wallpaper by attaching a single pixel buffer, instead of filling a whole shm
+    /// buffer with the same color
+    ///
+    /// Returns `false` if the compositor does not support `wp_single_pixel_buffer_manager_v1`, in
+    /// which case nothing is done. Note this does not commit the surface.
+    pub(super) fn clear_with_single_pixel_buffer(&self, color: [u8; 4]) -> bool {
+        let manager = match globals::single_pixel_buffer_manager() {
+            Some(manager) => manager,
+            None => return false,
+        };
+
+        // `color` is in the same byte order as our wl_shm format, but here we need actual rgb
+        let [r, g, b, a] = premultiply_alpha(color);
+        let (r, g, b) = if globals::must_swap_r_and_b_channels() {
+            (b, g, r)
+        } else {
+            (r, g, b)
+        };
+
+        // the protocol expects the full u32 range for each channel
+        let expand = |c: u8| c as u32 * 0x0101_0101;
+        let buffer = globals::object_create(WlDynObj::Buffer);
+        wp_single_pixel_buffer_manager_v1::req::create_u32_rgba_buffer(
+            manager,
+            buffer,
+            expand(r),
+            expand(g),
+            expand(b),
+            expand(a),
+        )
+        .unwrap();
+
+        let previous = self
+            .single_pixel_buffer
+            .lock()
+            .unwrap()
+            .replace((buffer, color));
+
+        let inner = self.inner.read().unwrap();
+        self.update_viewport(inner.scale_factor, inner.width, inner.height);
+        drop(inner);
+        self.set_opaque(a == 255);
+
+        wl_surface::req::attach(self.wl_surface, Some(buffer), 0, 0).unwrap();
+        wl_surface::req::damage_buffer(self.wl_surface, 0, 0, 1, 1).unwrap();
+
+        if let Some((previous, _)) = previous {
+            if let Err(e) = wl_buffer::req::destroy(previous) {
+                error!("failed to destroy single pixel wl_buffer: {e:?}");
             }
-        })
+        }
+        true
+    }
+
+    /// Whether we are displaying (or transitioning to) the image at `path`
+    pub(super) fn is_displaying(&self, path: &str) -> bool {
+        matches!(&*self.img.lock().unwrap(), BgImg::Img(img) if img == path)
     }
 
     pub(super) fn set_img_info(&self, img_info: BgImg) {
@@ -394,9 +1074,68 @@ impl Wallpaper {
             img_info
         );
         *self.img.lock().unwrap() = img_info;
+        crate::subscribers::notify_changed();
+    }
+}
+
+/// Fills the canvas with `color`. Its alpha is only taken into account if our buffers have an alpha
+/// channel
+fn fill_canvas(canvas: &mut [u8], color: [u8; 4]) {
+    let color = premultiply_alpha(color);
+    let channels = globals::pixel_format().channels().into();
+    for pixel in canvas.chunks_exact_mut(channels) {
+        pixel.copy_from_slice(&color[..channels]);
+    }
+}
+
+/// The part of our buffers `region` covers, as the viewport source: in logical pixels for whole
+/// scales, since we set the buffer scale, and in buffer pixels for fractional ones
+fn viewport_source(
+    region: &Region,
+    scale_factor: Scale,
+    width: NonZeroI32,
+    height: NonZeroI32,
+) -> [f64; 4] {
+    let rect = [region.x, region.y, region.width, region.height].map(f64::from);
+    match scale_factor {
+        Scale::Whole(_) => rect,
+        Scale::Fractional(_) => {
+            // the buffer's dimensions are rounded, so we make sure not to go past them
+            let (buf_width, buf_height) = scale_factor.mul_dim(width.get(), height.get());
+            let [x, y, w, h] = rect.map(|v| v * scale_factor.as_f32() as f64);
+            let (x, y) = (x.min(buf_width as f64), y.min(buf_height as f64));
+            [
+                x,
+                y,
+                w.min(buf_width as f64 - x),
+                h.min(buf_height as f64 - y),
+            ]
+        }
+    }
+}
+
+/// How long each frame lasts under an fps `boost` that lasts until some instant, if it is still
+/// active at `now`
+fn boosted_frame_time(boost: Option<(NonZeroU16, Instant)>, now: Instant) -> Option<Duration> {
+    match boost {
+        Some((fps, until)) if now < until => Some(Duration::from_secs(1) / fps.get() as u32),
+        _ => None,
     }
 }
 
+/// Rounds a refresh rate in mHz, as wl_output sends it, to whole frames per second
+fn refresh_to_fps(refresh: i32) -> Option<u16> {
+    let fps = (refresh.saturating_add(500)) / 1000;
+    (fps > 0).then(|| fps.min(u16::MAX as i32) as u16)
+}
+
+/// Wayland expects colors with an alpha channel to already be multiplied by it
+fn premultiply_alpha(color: [u8; 4]) -> [u8; 4] {
+    let alpha = color[3] as u16;
+    let mul = |c: u8| ((c as u16 * alpha + 127) / 255) as u8;
+    [mul(color[0]), mul(color[1]), mul(color[2]), color[3]]
+}
+
 /// stops all animations for the passed wallpapers
 pub(crate) fn stop_animations(wallpapers: &[Arc<Wallpaper>]) {
     wallpapers
@@ -405,7 +1144,13 @@ pub(crate) fn stop_animations(wallpapers: &[Arc<Wallpaper>]) {
 }
 
 /// attaches all pending buffers and damages all surfaces with one single request
-pub(crate) fn attach_buffers_and_damange_surfaces(wallpapers: &[Arc<Wallpaper>]) {
+///
+/// If `damage` is `None`, the whole buffers are damaged. Otherwise, only the part of them inside
+/// `damage` is, which saves the compositor from uploading everything again.
+pub(crate) fn attach_buffers_and_damange_surfaces(
+    wallpapers: &[Arc<Wallpaper>],
+    damage: Option<Damage>,
+) {
     #[rustfmt::skip]
     // Note this is little-endian specific
     const MSG: [u8; 56] = [
@@ -417,7 +1162,7 @@ pub(crate) fn attach_buffers_and_damange_surfaces(wallpapers: &[Arc<Wallpaper>])
         0, 0, 0, 0,             // wl_surface object id (to be filled)
         9, 0,                   // damage opcode
         24, 0,                  // msg length
-        0, 0, 0, 0, 0, 0, 0, 0, // damage first arguments
+        0, 0, 0, 0, 0, 0, 0, 0, // damage first arguments (to be filled)
         0, 0, 0, 0, 0, 0, 0, 0, // damage second arguments (to be filled)
         0, 0, 0, 0,             // wl_surface object id (to be filled)
         3, 0,                   // frame opcode
@@ -443,6 +1188,11 @@ pub(crate) fn attach_buffers_and_damange_surfaces(wallpapers: &[Arc<Wallpaper>])
                 .scale_factor
                 .mul_dim(inner.width.get(), inner.height.get());
             drop(inner);
+            let full_damage = wallpaper.needs_full_damage.swap(false, Ordering::AcqRel);
+            let (x, y, width, height) = match damage {
+                Some(damage) if !full_damage => damage.rect(width, height),
+                _ => (0, 0, width, height),
+            };
 
             // attach
             msg[0..4].copy_from_slice(&wallpaper.wl_surface.get().to_ne_bytes());
@@ -450,6 +1200,8 @@ pub(crate) fn attach_buffers_and_damange_surfaces(wallpapers: &[Arc<Wallpaper>])
 
             //damage buffer
             msg[20..24].copy_from_slice(&wallpaper.wl_surface.get().to_ne_bytes());
+            msg[28..32].copy_from_slice(&x.to_ne_bytes());
+            msg[32..36].copy_from_slice(&y.to_ne_bytes());
             msg[36..40].copy_from_slice(&width.to_ne_bytes());
             msg[40..44].copy_from_slice(&height.to_ne_bytes());
 
@@ -464,6 +1216,20 @@ pub(crate) fn attach_buffers_and_damange_surfaces(wallpapers: &[Arc<Wallpaper>])
     unsafe { crate::wayland::wire::send_unchecked(msg.as_ref(), &[]).unwrap() }
 }
 
+/// Runs `swww` with the arguments set by `swww img --sticky` (or `--workspace`), to display its
+/// image on `outputs`, a comma separated list
+pub(crate) fn load_sticky(outputs: &str, args: &[String]) -> std::io::Result<()> {
+    let status = std::process::Command::new("swww")
+        .args(args)
+        .arg(format!("--outputs={outputs}"))
+        .spawn()?
+        .wait()?;
+    if !status.success() {
+        return Err(std::io::Error::other(format!("swww exited with {status}")));
+    }
+    Ok(())
+}
+
 /// commits multiple wallpapers at once with a single message through the socket
 pub(crate) fn commit_wallpapers(wallpapers: &[Arc<Wallpaper>]) {
     // Note this is little-endian specific
@@ -487,7 +1253,12 @@ pub(crate) fn commit_wallpapers(wallpapers: &[Arc<Wallpaper>]) {
 impl Drop for Wallpaper {
     fn drop(&mut self) {
         // note we shouldn't panic in a drop implementation
-       
+
+        if let Ok(Some((buffer, _))) = self.single_pixel_buffer.get_mut().map(Option::take) {
+            if let Err(e) = wl_buffer::req::destroy(buffer) {
+                error!("error destroying single pixel wl_buffer: {e:?}");
+            }
+        }
         if let Err(e) = wp_viewport::req::destroy(self.wp_viewport) {
             error!("error destroying wp_viewport: {e:?}");
         }
@@ -496,9 +1267,7 @@ impl Drop for Wallpaper {
                 error!("error destroying wp_fractional_scale_v1: {e:?}");
             }
         }
-        if let Err(e) = zwlr_layer_surface_v1::req::destroy(self.layer_surface) {
-            error!("error destroying zwlr_layer_surface_v1: {e:?}");
-        }
+        self.shell_surface.destroy();
 
         if let Err(e) = wl_surface::req::destroy(self.wl_surface) {
             error!("error destroying wl_surface: {e:?}");
@@ -516,3 +1285,110 @@ impl Drop for Wallpaper {
 
 unsafe impl Sync for Wallpaper {}
 unsafe impl Send for Wallpaper {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn alpha_is_premultiplied() {
+        assert_eq!(premultiply_alpha([255, 128, 0, 255]), [255, 128, 0, 255]);
+        assert_eq!(premultiply_alpha([255, 128, 0, 128]), [128, 64, 0, 128]);
+        assert_eq!(premultiply_alpha([255, 128, 0, 0]), [0, 0, 0, 0]);
+    }
+
+    #[test]
+    fn opaque_region_rects() {
+        assert_eq!(
+            OpaqueRegion::Full.rect(1920, 1080),
+            Some((0, 0, 1920, 1080))
+        );
+        assert_eq!(OpaqueRegion::None.rect(1920, 1080), None);
+        let inset = OpaqueRegion::Inset([30, 0, 10, 20]);
+        assert_eq!(inset.rect(1920, 1080), Some((20, 30, 1900, 1040)));
+        assert_eq!(OpaqueRegion::Inset([600, 0, 600, 0]).rect(1920, 1080), None);
+        assert_eq!(OpaqueRegion::Inset([u32::MAX; 4]).rect(1920, 1080), None);
+    }
+
+    #[test]
+    fn refresh_is_rounded_to_fps() {
+        assert_eq!(refresh_to_fps(59_951), Some(60));
+        assert_eq!(refresh_to_fps(143_856), Some(144));
+        assert_eq!(refresh_to_fps(0), None);
+        assert_eq!(refresh_to_fps(-1), None);
+    }
+
+    #[test]
+    fn fps_boost_only_applies_until_it_expires() {
+        let now = Instant::now();
+        let boost = Some((NonZeroU16::new(50).unwrap(), now + Duration::from_secs(2)));
+        assert_eq!(
+            boosted_frame_time(boost, now),
+            Some(Duration::from_millis(20))
+        );
+        assert_eq!(
+            boosted_frame_time(boost, now + Duration::from_secs(2)),
+            None
+        );
+        assert_eq!(boosted_frame_time(None, now), None);
+    }
+
+    #[test]
+    fn viewport_source_covers_the_region_in_buffer_coordinates() {
+        let region = Region {
+            x: 0,
+            y: 30,
+            width: 1536,
+            height: 834,
+        };
+        let (width, height) = (
+            NonZeroI32::new(1536).unwrap(),
+            NonZeroI32::new(864).unwrap(),
+        );
+        let whole = Scale::Whole(NonZeroI32::new(2).unwrap());
+        assert_eq!(
+            viewport_source(&region, whole, width, height),
+            [0.0, 30.0, 1536.0, 834.0]
+        );
+        // 1.25
+        let fractional = Scale::Fractional(NonZeroI32::new(150).unwrap());
+        assert_eq!(
+            viewport_source(&region, fractional, width, height),
+            [0.0, 37.5, 1920.0, 1042.5]
+        );
+    }
+
+    #[test]
+    fn damage_contains_every_span() {
+        let mut damage = Damage::empty();
+        damage.add_span(10, 5, 20);
+        damage.add_span(12, 2, 8);
+        damage.add_span(40, 30, 30); // empty span
+        assert_eq!(damage.rect(100, 100), (2, 10, 18, 3));
+    }
+
+    #[test]
+    fn damage_is_clipped_to_the_buffer() {
+        let mut damage = Damage::empty();
+        damage.add_span(0, 50, 150);
+        assert_eq!(damage.rect(100, 100), (50, 0, 50, 1));
+        assert_eq!(Damage::empty().rect(100, 100).2, 0);
+    }
+
+    #[test]
+    fn only_changed_pixels_are_copied_and_damaged() {
+        let (width, height, channels) = (64, 32, 4);
+        let mut canvas = vec![0u8; width * height * channels];
+        let mut new = canvas.clone();
+        // a nearly identical image: a single pixel differs
+        new[(10 * width + 20) * channels + 1] = 255;
+
+        let damage = Damage::copy_changes(&mut canvas, &new, width, channels);
+        assert_eq!(canvas, new);
+        assert_eq!(damage.rect(width as i32, height as i32), (20, 10, 1, 1));
+
+        let damage = Damage::copy_changes(&mut canvas, &new, width, channels);
+        assert!(damage.is_empty());
+        assert_eq!(damage.rect(width as i32, height as i32).2, 0);
+    }
+}
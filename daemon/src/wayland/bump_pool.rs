@@ -1,4 +1,10 @@
-use std::sync::atomic::{AtomicBool, Ordering};
+use std::{
+    ptr::NonNull,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Mutex,
+    },
+};
 
 use utils::ipc::Mmap;
 
@@ -25,12 +31,14 @@ impl ReleaseFlag {
 struct Buffer {
     object_id: ObjectId,
     released: ReleaseFlag,
+    /// where the buffer starts in its wl_shm_pool
+    offset: usize,
 }
 
 impl Buffer {
     fn new(
         pool_id: ObjectId,
-        offset: i32,
+        offset: usize,
         width: i32,
         height: i32,
         stride: i32,
@@ -40,12 +48,19 @@ impl Buffer {
 
         let object_id = globals::object_create(super::WlDynObj::Buffer);
         super::interfaces::wl_shm_pool::req::create_buffer(
-            pool_id, object_id, offset, width, height, stride, format,
+            pool_id,
+            object_id,
+            offset as i32,
+            width,
+            height,
+            stride,
+            format,
         )
         .expect("WlShmPool failed to create buffer");
         Self {
             object_id,
             released,
+            offset,
         }
     }
 
@@ -56,6 +71,128 @@ impl Buffer {
     }
 }
 
+static USE_SHARED_POOL: AtomicBool = AtomicBool::new(false);
+static SHARED_POOL: Mutex<Option<SharedPool>> = Mutex::new(None);
+
+/// Makes every `BumpPool` created from now on take its buffers from a single wl_shm_pool, shared
+/// by all outputs, instead of creating one of its own. This saves file descriptors and mappings,
+/// at the cost of memory being harder to give back, since the pool can never shrink.
+pub(crate) fn use_shared_pool() {
+    USE_SHARED_POOL.store(true, Ordering::Release);
+}
+
+/// The most memory a wl_shm_pool can have, since its size is an i32
+const MAX_POOL_LEN: usize = i32::MAX as usize;
+
+/// The wl_shm_pool every `BumpPool` takes its buffers from, after `use_shared_pool`
+#[derive(Debug)]
+struct SharedPool {
+    pool_id: ObjectId,
+    /// made with `Mmap::create_growable`, so that it never moves while the `BumpPool`s write into
+    /// it: each of them only touches its own regions, through `Memory::Shared`
+    mmap: Mmap,
+    regions: Regions,
+    /// how many `BumpPool`s are using us. We destroy the pool once none are
+    users: usize,
+}
+
+// SAFETY: we only ever touch the pool through `SHARED_POOL`'s lock, and never hand out references
+// to its memory
+unsafe impl Send for SharedPool {}
+
+impl SharedPool {
+    /// Allocates `len` bytes, growing the pool if necessary. Returns their offset
+    fn alloc(&mut self, len: usize) -> usize {
+        let offset = self.regions.alloc(len);
+        let new_len = self.regions.len;
+        if new_len > self.mmap.len() {
+            if new_len > MAX_POOL_LEN {
+                panic!("Buffers have grown too big. We cannot allocate any more.")
+            }
+            self.mmap.remap(new_len);
+            super::interfaces::wl_shm_pool::req::resize(self.pool_id, new_len as i32).unwrap();
+            log::info!("shared wl_shm_pool size: {}Kb", new_len / 1024);
+        }
+        offset
+    }
+}
+
+/// Hands out the regions of the shared pool, first fit, and takes them back once they are free.
+/// It only grows when no free region is big enough
+#[derive(Debug)]
+struct Regions {
+    len: usize,
+    /// free regions, as (offset, len). Sorted by offset, and never adjacent to each other
+    free: Vec<(usize, usize)>,
+}
+
+impl Regions {
+    fn new(len: usize) -> Self {
+        Self {
+            len,
+            free: vec![(0, len)],
+        }
+    }
+
+    fn alloc(&mut self, len: usize) -> usize {
+        if let Some(i) = self.free.iter().position(|&(_, free)| free >= len) {
+            let (offset, free) = self.free[i];
+            if free == len {
+                self.free.remove(i);
+            } else {
+                self.free[i] = (offset + len, free - len);
+            }
+            return offset;
+        }
+        // grow, starting from the free region at the end, if there is one
+        let offset = match self.free.last() {
+            Some(&(offset, free)) if offset + free == self.len => {
+                self.free.pop();
+                offset
+            }
+            _ => self.len,
+        };
+        self.len = offset + len;
+        offset
+    }
+
+    fn free(&mut self, offset: usize, len: usize) {
+        let i = self.free.partition_point(|&(o, _)| o < offset);
+        self.free.insert(i, (offset, len));
+        if i + 1 < self.free.len() && offset + len == self.free[i + 1].0 {
+            self.free[i].1 += self.free.remove(i + 1).1;
+        }
+        if i > 0 && self.free[i - 1].0 + self.free[i - 1].1 == offset {
+            self.free[i - 1].1 += self.free.remove(i).1;
+        }
+    }
+}
+
+#[derive(Debug)]
+enum Memory {
+    /// a wl_shm_pool of our own, with our buffers one after the other
+    Own { pool_id: ObjectId, mmap: Mmap },
+    /// regions of the `SharedPool`, starting from `base`, where its memory is mapped. It never
+    /// moves, and outlives us
+    Shared { base: NonNull<u8> },
+}
+
+/// The first of `buffers` the compositor released, which is the only kind we may write into
+fn released_buffer(buffers: &[Buffer]) -> Option<usize> {
+    buffers.iter().position(|b| b.released.is_released())
+}
+
+/// Where our next buffer goes in a pool of our own: past every buffer the compositor may still be
+/// reading, including those we destroyed before it released them
+fn own_offset(buffers: &[Buffer], retired: &[(usize, usize)], len: usize) -> usize {
+    buffers
+        .iter()
+        .map(|b| b.offset + len)
+        .chain(retired.iter().map(|&(offset, len)| offset + len))
+        .max()
+        .unwrap_or(0)
+}
+
 #[derive(Debug)]
 /// A pool implementation that only gives buffers of a fixed size, creating new ones if none of
 /// them are freed. It also takes care of copying the previous buffer's content over to the new one
@@ -64,12 +201,20 @@ impl Buffer {
 /// Current implementation will automatically unmap the underlying shared memory when we aren't
 /// animating and all created buffers have been released
 pub(crate) struct BumpPool {
-    pool_id: ObjectId,
-    mmap: Mmap,
+    memory: Memory,
     buffers: Vec<Buffer>,
+    /// regions whose buffers we destroyed before the compositor released them. We only reuse
+    /// them once it releases one of our newer buffers, since until then it may still be reading
+    /// them (and they might hold what we must copy into our next buffer)
+    retired: Vec<(usize, usize)>,
     width: i32,
     height: i32,
     last_used_buffer: usize,
+    /// where the content we drew last is, so that we can copy it into our next buffer. `None` when
+    /// there is nothing worth copying
+    last_drawn: Option<usize>,
+    /// whether our buffers use the wl_shm format with an alpha channel
+    transparent: bool,
 }
 
 impl BumpPool {
@@ -77,19 +222,42 @@ impl BumpPool {
     pub(crate) fn new(width: i32, height: i32) -> Self {
         let len =
             width as usize * height as usize * super::globals::pixel_format().channels() as usize;
-        let mmap = Mmap::create(len);
-        let pool_id = globals::object_create(super::WlDynObj::ShmPool);
-        super::interfaces::wl_shm::req::create_pool(pool_id, &mmap.fd(), len as i32)
-            .expect("failed to create WlShmPool object");
+        let memory = if USE_SHARED_POOL.load(Ordering::Acquire) {
+            let mut shared = SHARED_POOL.lock().unwrap();
+            let shared = shared.get_or_insert_with(|| {
+                let mmap = Mmap::create_growable(len, MAX_POOL_LEN);
+                let pool_id = globals::object_create(super::WlDynObj::ShmPool);
+                super::interfaces::wl_shm::req::create_pool(pool_id, &mmap.fd(), len as i32)
+                    .expect("failed to create WlShmPool object");
+                SharedPool {
+                    pool_id,
+                    mmap,
+                    regions: Regions::new(len),
+                    users: 0,
+                }
+            });
+            shared.users += 1;
+            // SAFETY: mappings never start at 0
+            let base = unsafe { NonNull::new_unchecked(shared.mmap.as_mut_ptr()) };
+            Memory::Shared { base }
+        } else {
+            let mmap = Mmap::create(len);
+            let pool_id = globals::object_create(super::WlDynObj::ShmPool);
+            super::interfaces::wl_shm::req::create_pool(pool_id, &mmap.fd(), len as i32)
+                .expect("failed to create WlShmPool object");
+            Memory::Own { pool_id, mmap }
+        };
         let buffers = Vec::with_capacity(2);
 
         Self {
-            pool_id,
-            mmap,
+            memory,
             buffers,
+            retired: Vec::new(),
             width,
             height,
             last_used_buffer: 0,
+            last_drawn: None,
+            transparent: false,
         }
     }
 
@@ -104,11 +272,29 @@ impl BumpPool {
     ) -> bool {
         if let Some(b) = self.buffers.iter().find(|b| b.object_id == buffer_id) {
             b.released.set_released();
-            if !is_animating && self.buffers.iter().all(|b| b.released.is_released()) {
-                for buffer in self.buffers.drain(..) {
-                    buffer.destroy();
+            let all_released = self.buffers.iter().all(|b| b.released.is_released());
+            // the compositor moved on from the buffers we destroyed before this one
+            let retired = std::mem::take(&mut self.retired);
+            match &mut self.memory {
+                Memory::Own { mmap, .. } => {
+                    if !is_animating && all_released {
+                        for buffer in self.buffers.drain(..) {
+                            buffer.destroy();
+                        }
+                        mmap.unmap();
+                    }
+                }
+                Memory::Shared { .. } => {
+                    if !retired.is_empty() {
+                        let mut shared = SHARED_POOL.lock().unwrap();
+                        let shared = shared.as_mut().unwrap();
+                        for (offset, len) in retired {
+                            shared.regions.free(offset, len);
+                        }
+                    }
+                    // we keep our buffers, since giving back their regions would lose what we
+                    // must copy into the next one. The mapping is everyone's, so it stays
                 }
-                self.mmap.unmap();
             }
             true
         } else {
@@ -122,45 +308,47 @@ impl BumpPool {
             * super::globals::pixel_format().channels() as usize
     }
 
-    fn buffer_offset(&self, buffer_index: usize) -> usize {
-        self.buffer_len() * buffer_index
-    }
-
-    fn occupied_bytes(&self) -> usize {
-        self.buffer_offset(self.buffers.len())
-    }
-
     /// resizes the pool and creates a new WlBuffer at the next free offset
     fn grow(&mut self) {
         let len = self.buffer_len();
-        let new_len = self.occupied_bytes() + len;
 
-        // we unmap the shared memory file descriptor when animations are done, so here we must
-        // ensure the bytes are actually mmaped
-        self.mmap.ensure_mapped();
-
-        if new_len > self.mmap.len() {
-            if new_len > i32::MAX as usize {
-                panic!("Buffers have grown too big. We cannot allocate any more.")
+        let (pool_id, offset, pool_len) = match &mut self.memory {
+            Memory::Own { pool_id, mmap } => {
+                // we unmap the shared memory file descriptor when animations are done, so here we
+                // must ensure the bytes are actually mmaped
+                mmap.ensure_mapped();
+                let offset = own_offset(&self.buffers, &self.retired, len);
+                let new_len = offset + len;
+                if new_len > mmap.len() {
+                    if new_len > MAX_POOL_LEN {
+                        panic!("Buffers have grown too big. We cannot allocate any more.")
+                    }
+                    mmap.remap(new_len);
+                    super::interfaces::wl_shm_pool::req::resize(*pool_id, new_len as i32).unwrap();
+                }
+                (*pool_id, offset, mmap.len())
             }
-            self.mmap.remap(new_len);
-            super::interfaces::wl_shm_pool::req::resize(self.pool_id, new_len as i32).unwrap();
-        }
+            Memory::Shared { .. } => {
+                let mut shared = SHARED_POOL.lock().unwrap();
+                let shared = shared.as_mut().unwrap();
+                let offset = shared.alloc(len);
+                (shared.pool_id, offset, shared.mmap.len())
+            }
+        };
 
-        let new_buffer_index = self.buffers.len();
         self.buffers.push(Buffer::new(
-            self.pool_id,
-            self.buffer_offset(new_buffer_index) as i32,
+            pool_id,
+            offset,
             self.width,
             self.height,
             self.width * super::globals::pixel_format().channels() as i32,
-            super::globals::wl_shm_format(),
+            self.format(),
         ));
 
         log::info!(
             "BumpPool with: {} buffers. Size: {}Kb",
             self.buffers.len(),
-            self.mmap.len() / 1024
+            pool_len / 1024
         );
     }
 
@@ -168,32 +356,60 @@ impl BumpPool {
     ///
     /// This function automatically handles copying the previous buffer over onto the new one
     pub(crate) fn get_drawable(&mut self) -> &mut [u8] {
-        let (i, buf) = match self
-            .buffers
-            .iter()
-            .enumerate()
-            .find(|(_, b)| b.released.is_released())
-        {
-            Some((i, buf)) => (i, buf),
+        let i = match released_buffer(&self.buffers) {
+            Some(i) => i,
             None => {
                 self.grow();
-                (self.buffers.len() - 1, self.buffers.last().unwrap())
+                self.buffers.len() - 1
             }
         };
 
         let len = self.buffer_len();
-        let offset = self.buffer_offset(i);
+        let buf = &self.buffers[i];
+        let offset = buf.offset;
         buf.released.unset_released();
 
-        if self.last_used_buffer != i {
-            let last_offset = self.buffer_offset(self.last_used_buffer);
-            self.mmap
-                .slice_mut()
-                .copy_within(last_offset..last_offset + len, offset);
-            self.last_used_buffer = i;
+        let last_drawn = self.last_drawn.filter(|&last| last != offset);
+        self.last_used_buffer = i;
+        self.last_drawn = Some(offset);
+
+        match &mut self.memory {
+            Memory::Own { mmap, .. } => {
+                mmap.ensure_mapped();
+                if let Some(last_offset) = last_drawn {
+                    mmap.slice_mut()
+                        .copy_within(last_offset..last_offset + len, offset);
+                }
+                &mut mmap.slice_mut()[offset..offset + len]
+            }
+            Memory::Shared { base } => {
+                // SAFETY: both are regions of ours, so nobody else touches them. They do not
+                // overlap, and the pool was at least this long when we allocated them
+                unsafe {
+                    let buffer = base.as_ptr().add(offset);
+                    if let Some(last_offset) = last_drawn {
+                        std::ptr::copy_nonoverlapping(base.as_ptr().add(last_offset), buffer, len);
+                    }
+                    std::slice::from_raw_parts_mut(buffer, len)
+                }
+            }
         }
+    }
 
-        &mut self.mmap.slice_mut()[offset..offset + len]
+    /// The content we drew last, if any
+    pub(crate) fn last_drawn(&mut self) -> Option<&[u8]> {
+        let offset = self.last_drawn?;
+        let len = self.buffer_len();
+        match &mut self.memory {
+            Memory::Own { mmap, .. } => {
+                mmap.ensure_mapped();
+                Some(&mmap.slice()[offset..offset + len])
+            }
+            // SAFETY: like in `get_drawable`
+            Memory::Shared { base } => unsafe {
+                Some(std::slice::from_raw_parts(base.as_ptr().add(offset), len))
+            },
+        }
     }
 
     /// gets the last buffer we've drawn to
@@ -201,24 +417,176 @@ impl BumpPool {
         self.buffers[self.last_used_buffer].object_id
     }
 
+    fn format(&self) -> u32 {
+        if self.transparent {
+            super::globals::wl_shm_alpha_format().unwrap_or_else(super::globals::wl_shm_format)
+        } else {
+            super::globals::wl_shm_format()
+        }
+    }
+
+    /// Destroys all of our buffers. Their regions can be reused right away, except for those the
+    /// compositor (or our next buffer) may still need
+    fn destroy_buffers(&mut self) {
+        let len = self.buffer_len();
+        let mut shared = match self.memory {
+            Memory::Own { .. } => None,
+            Memory::Shared { .. } => Some(SHARED_POOL.lock().unwrap()),
+        };
+        for buffer in self.buffers.drain(..) {
+            if !buffer.released.is_released() || self.last_drawn == Some(buffer.offset) {
+                self.retired.push((buffer.offset, len));
+            } else if let Some(shared) = shared.as_mut() {
+                shared.as_mut().unwrap().regions.free(buffer.offset, len);
+            }
+            buffer.destroy();
+        }
+    }
+
+    /// Chooses whether our buffers should have an alpha channel. Since a wl_buffer's format cannot
+    /// change, this recreates all buffers, keeping the last one's content.
+    ///
+    /// Returns whether anything changed
+    pub(crate) fn is_transparent(&self) -> bool {
+        self.transparent
+    }
+
+    pub(crate) fn set_transparent(&mut self, transparent: bool) -> bool {
+        if self.transparent == transparent {
+            return false;
+        }
+        self.transparent = transparent;
+        // `get_drawable` copies the content over once it creates the next buffer
+        self.destroy_buffers();
+        true
+    }
+
     /// We assume `width` and `height` have already been multiplied by their scale factor
     pub(crate) fn resize(&mut self, width: i32, height: i32) {
+        // the regions of our buffers have the old size, so we must give them back with it
+        self.last_drawn = None;
+        self.destroy_buffers();
         self.width = width;
         self.height = height;
         self.last_used_buffer = 0;
-        for buffer in self.buffers.drain(..) {
-            buffer.destroy();
-        }
     }
 }
 
 impl Drop for BumpPool {
     fn drop(&mut self) {
-        for buffer in self.buffers.drain(..) {
-            buffer.destroy();
-        }
-        if let Err(e) = super::interfaces::wl_shm_pool::req::destroy(self.pool_id) {
-            log::error!("failed to destroy wl_shm_pool: {e}");
+        let len = self.buffer_len();
+        match &mut self.memory {
+            Memory::Own { pool_id, .. } => {
+                for buffer in self.buffers.drain(..) {
+                    buffer.destroy();
+                }
+                if let Err(e) = super::interfaces::wl_shm_pool::req::destroy(*pool_id) {
+                    log::error!("failed to destroy wl_shm_pool: {e}");
+                }
+            }
+            Memory::Shared { .. } => {
+                // our surface is gone along with us, so the compositor no longer needs anything
+                // we drew. Once nobody uses the pool, we get rid of it entirely
+                let mut guard = SHARED_POOL.lock().unwrap();
+                let shared = guard.as_mut().unwrap();
+                for buffer in self.buffers.drain(..) {
+                    shared.regions.free(buffer.offset, len);
+                    buffer.destroy();
+                }
+                for (offset, len) in self.retired.drain(..) {
+                    shared.regions.free(offset, len);
+                }
+                shared.users -= 1;
+                if shared.users == 0 {
+                    let shared = guard.take().unwrap();
+                    if let Err(e) = super::interfaces::wl_shm_pool::req::destroy(shared.pool_id) {
+                        log::error!("failed to destroy wl_shm_pool: {e}");
+                    }
+                }
+            }
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::num::NonZeroU32;
+
+    #[test]
+    fn buffers_are_only_written_once_released() {
+        let buffer = |id, offset| Buffer {
+            object_id: ObjectId::new(NonZeroU32::new(id).unwrap()),
+            released: ReleaseFlag(AtomicBool::new(true)),
+            offset,
+        };
+        let mut buffers = vec![buffer(1, 0)];
+        // we committed our only buffer, and the compositor has yet to release it
+        buffers[0].released.unset_released();
+        assert_eq!(released_buffer(&buffers), None);
+        let offset = own_offset(&buffers, &[], 100);
+        assert_eq!(offset, 100);
+        buffers.push(buffer(2, offset));
+        assert_eq!(released_buffer(&buffers), Some(1));
+
+        // both are busy until the release event arrives, however late
+        buffers[1].released.unset_released();
+        assert_eq!(released_buffer(&buffers), None);
+        buffers[0].released.set_released();
+        assert_eq!(released_buffer(&buffers), Some(0));
+
+        // we resized before the compositor released our buffers: the new ones go past them
+        let retired = [(0, 100), (100, 100)];
+        assert_eq!(own_offset(&[], &retired, 150), 200);
+        assert_eq!(own_offset(&[buffer(3, 200)], &retired, 150), 350);
+        // once it releases one of the new ones, the old ones are free again
+        assert_eq!(own_offset(&[], &[], 150), 0);
+    }
+
+    #[test]
+    fn shared_pool_memory_never_moves() {
+        // what outputs drew into their regions stays where it is, while others grow the pool
+        let mut mmap = Mmap::create_growable(100, MAX_POOL_LEN);
+        let base = mmap.as_mut_ptr();
+        // SAFETY: the first 100 bytes are backed by the file
+        unsafe { base.write_bytes(7, 100) };
+        mmap.remap(4 * 4096);
+        assert_eq!(mmap.as_mut_ptr(), base);
+        assert_eq!(mmap.len(), 4 * 4096);
+        // SAFETY: and now all of them are
+        let bytes = unsafe { std::slice::from_raw_parts(base, mmap.len()) };
+        assert!(bytes[..100].iter().all(|b| *b == 7));
+        assert!(bytes[100..].iter().all(|b| *b == 0));
+    }
+
+    #[test]
+    fn shared_pool_regions_are_reused() {
+        let mut regions = Regions::new(100);
+        let a = regions.alloc(40);
+        let b = regions.alloc(40);
+        assert_eq!((a, b), (0, 40));
+        // does not fit in what is left, so the pool grows from the end
+        let c = regions.alloc(40);
+        assert_eq!(c, 80);
+        assert_eq!(regions.len, 120);
+
+        // a released region is reused before growing again
+        regions.free(b, 40);
+        assert_eq!(regions.alloc(30), 40);
+        assert_eq!(regions.alloc(10), 70);
+        assert_eq!(regions.len, 120);
+        assert!(regions.free.is_empty());
+
+        // adjacent free regions merge, so bigger buffers fit in them
+        regions.free(0, 40);
+        regions.free(70, 10);
+        regions.free(40, 30);
+        assert_eq!(regions.free, [(0, 80)]);
+        assert_eq!(regions.alloc(80), 0);
+
+        // a free region at the end is grown, instead of left behind
+        regions.free(80, 40);
+        assert_eq!(regions.alloc(60), 80);
+        assert_eq!(regions.len, 140);
+    }
+}
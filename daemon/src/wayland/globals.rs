@@ -17,7 +17,7 @@ use rustix::{
     net::SocketAddrAny,
 };
 
-use log::{debug, error, info};
+use log::{debug, error, info, warn};
 use utils::ipc::PixelFormat;
 
 use super::{ObjectId, ObjectManager, WlDynObj};
@@ -36,10 +36,12 @@ pub const WL_COMPOSITOR: ObjectId = ObjectId(unsafe { NonZeroU32::new_unchecked(
 pub const WL_SHM: ObjectId = ObjectId(unsafe { NonZeroU32::new_unchecked(4) });
 pub const WP_VIEWPORTER: ObjectId = ObjectId(unsafe { NonZeroU32::new_unchecked(5) });
 pub const ZWLR_LAYER_SHELL_V1: ObjectId = ObjectId(unsafe { NonZeroU32::new_unchecked(6) });
+/// Without zwlr_layer_shell_v1, we bind xdg_wm_base in its place (see `xdg_shell_fallback`)
+pub const XDG_WM_BASE: ObjectId = ZWLR_LAYER_SHELL_V1;
 
 /// wl_display and wl_registry will always be available, but these globals could theoretically be
 /// absent. Nevertheless, they are required for `swww-daemon` to function, so we will need to bind
-/// all of them.
+/// all of them. The only exception is zwlr_layer_shell_v1, which we can replace with xdg_wm_base
 const REQUIRED_GLOBALS: [&str; 4] = [
     "wl_compositor",
     "wl_shm",
@@ -48,14 +50,30 @@ const REQUIRED_GLOBALS: [&str; 4] = [
 ];
 /// Minimal version necessary for `REQUIRED_GLOBALS`
 const VERSIONS: [u32; 4] = [4, 1, 1, 3];
+/// Highest version of `REQUIRED_GLOBALS` we know how to use. We bind the highest version the
+/// compositor supports, up to these. wl_compositor 6 gives us wl_surface.preferred_buffer_scale
+const MAX_VERSIONS: [u32; 4] = [6, 1, 1, 3];
 
 static mut WAYLAND_FD: OwnedFd = unsafe { std::mem::zeroed() };
 static mut FRACTIONAL_SCALE_SUPPORT: bool = false;
+static mut SINGLE_PIXEL_BUFFER_SUPPORT: bool = false;
+static mut SCREENCOPY_SUPPORT: bool = false;
+static mut IDLE_NOTIFY_SUPPORT: bool = false;
+static mut XDG_SHELL_FALLBACK: bool = false;
 static mut PIXEL_FORMAT: PixelFormat = PixelFormat::Xrgb;
+static mut FORCED_PIXEL_FORMAT: bool = false;
+/// `--format auto-preferred`
+static mut PREFER_COMPOSITOR_FORMAT: bool = false;
+/// whether we can open other connections to the compositor, to probe it
+static mut CAN_PROBE: bool = false;
+static mut SWAP_CHANNELS: Option<bool> = None;
 static mut OBJECT_MANAGER: Mutex<ObjectManager> = Mutex::new(ObjectManager::new());
 
 static INITIALIZED: AtomicBool = AtomicBool::new(false);
 
+/// The globals we bound (other than the outputs), and the versions we bound, for diagnostics
+static BOUND_GLOBALS: Mutex<Vec<(&'static str, u32)>> = Mutex::new(Vec::new());
+
 #[must_use]
 pub fn wayland_fd() -> BorrowedFd<'static> {
     debug_assert!(INITIALIZED.load(std::sync::atomic::Ordering::Relaxed));
@@ -67,6 +85,104 @@ pub fn fractional_scale_support() -> bool {
     unsafe { FRACTIONAL_SCALE_SUPPORT }
 }
 
+#[must_use]
+pub fn single_pixel_buffer_support() -> bool {
+    unsafe { SINGLE_PIXEL_BUFFER_SUPPORT }
+}
+
+#[must_use]
+pub fn screencopy_support() -> bool {
+    unsafe { SCREENCOPY_SUPPORT }
+}
+
+/// Whether we bound the idle notifier, along with a seat to use it with. We only do that for
+/// `--idle-pause`
+#[must_use]
+pub fn idle_notify_support() -> bool {
+    unsafe { IDLE_NOTIFY_SUPPORT }
+}
+
+/// Whether the compositor lacks zwlr_layer_shell_v1, so that we bound xdg_wm_base instead, and
+/// display the wallpapers in fullscreen xdg_toplevels
+#[must_use]
+pub fn xdg_shell_fallback() -> bool {
+    unsafe { XDG_SHELL_FALLBACK }
+}
+
+/// The fractional scale manager, if the compositor supports it
+#[must_use]
+pub fn fractional_scale_manager() -> Option<ObjectId> {
+    if fractional_scale_support() {
+        Some(ObjectId(unsafe { NonZeroU32::new_unchecked(7) }))
+    } else {
+        None
+    }
+}
+
+/// The single pixel buffer manager, if the compositor supports it
+///
+/// Optional globals are bound right after the required ones, so its id depends on whether we have
+/// also bound the fractional scale manager
+#[must_use]
+pub fn single_pixel_buffer_manager() -> Option<ObjectId> {
+    if single_pixel_buffer_support() {
+        let id = 7 + fractional_scale_support() as u32;
+        Some(ObjectId(unsafe { NonZeroU32::new_unchecked(id) }))
+    } else {
+        None
+    }
+}
+
+/// The wlr screencopy manager, if the compositor supports it. It is bound after the other optional
+/// globals
+#[must_use]
+pub fn screencopy_manager() -> Option<ObjectId> {
+    if screencopy_support() {
+        let id = 7 + fractional_scale_support() as u32 + single_pixel_buffer_support() as u32;
+        Some(ObjectId(unsafe { NonZeroU32::new_unchecked(id) }))
+    } else {
+        None
+    }
+}
+
+/// The idle notifier, if we bound it. It comes after the screencopy manager
+#[must_use]
+pub fn idle_notifier() -> Option<ObjectId> {
+    if idle_notify_support() {
+        let id = 7
+            + fractional_scale_support() as u32
+            + single_pixel_buffer_support() as u32
+            + screencopy_support() as u32;
+        Some(ObjectId(unsafe { NonZeroU32::new_unchecked(id) }))
+    } else {
+        None
+    }
+}
+
+/// The seat we ask the idle notifier about, bound right after it. We never use it for anything
+/// else, so we ignore its events
+#[must_use]
+pub fn seat() -> Option<ObjectId> {
+    idle_notifier().map(|id| ObjectId(unsafe { NonZeroU32::new_unchecked(id.get() + 1) }))
+}
+
+/// Binds the global `name` to `id`, remembering it for `bound_globals`. Binding an interface again
+/// (like wl_shm, when resetting) replaces the version we remember
+pub fn bind_global(name: u32, id: ObjectId, interface: &'static str, version: u32) {
+    super::interfaces::wl_registry::req::bind(name, id, interface, version).unwrap();
+    let mut bound = BOUND_GLOBALS.lock().unwrap();
+    match bound.iter_mut().find(|(bound, _)| *bound == interface) {
+        Some((_, bound_version)) => *bound_version = version,
+        None => bound.push((interface, version)),
+    }
+}
+
+/// The globals we bound so far, other than the outputs, along with their versions
+#[must_use]
+pub fn bound_globals() -> Vec<(&'static str, u32)> {
+    BOUND_GLOBALS.lock().unwrap().clone()
+}
+
 #[must_use]
 pub fn object_type_get(object_id: ObjectId) -> Option<WlDynObj> {
     debug_assert!(INITIALIZED.load(std::sync::atomic::Ordering::Relaxed));
@@ -92,6 +208,63 @@ pub fn pixel_format() -> PixelFormat {
     unsafe { PIXEL_FORMAT }
 }
 
+/// Forgets the wl_shm format we selected, so that we select it again from the formats passed to
+/// `negotiate_pixel_format` next. Does nothing if the format was forced with `--format`.
+///
+/// Note nothing that is using the current format must be alive when calling this
+pub fn reset_pixel_format() {
+    debug_assert!(INITIALIZED.load(std::sync::atomic::Ordering::Relaxed));
+    unsafe {
+        if !FORCED_PIXEL_FORMAT {
+            PIXEL_FORMAT = PixelFormat::Xrgb;
+        }
+    }
+}
+
+/// Updates the selected pixel format with one the compositor has advertised through wl_shm
+pub fn negotiate_pixel_format(format: u32) {
+    let forced = unsafe { FORCED_PIXEL_FORMAT };
+    match format {
+        super::interfaces::wl_shm::format::XRGB8888 => {
+            debug!("available shm format: Xrbg");
+        }
+        super::interfaces::wl_shm::format::XBGR8888 => {
+            debug!("available shm format: Xbgr");
+            if !forced && pixel_format() == PixelFormat::Xrgb {
+                unsafe { PIXEL_FORMAT = PixelFormat::Xbgr }
+            }
+        }
+        super::interfaces::wl_shm::format::RGB888 => {
+            debug!("available shm format: Rbg");
+            if !forced && pixel_format() != PixelFormat::Bgr {
+                unsafe { PIXEL_FORMAT = PixelFormat::Rgb }
+            }
+        }
+        super::interfaces::wl_shm::format::BGR888 => {
+            debug!("available shm format: Bgr");
+            if !forced {
+                unsafe { PIXEL_FORMAT = PixelFormat::Bgr }
+            }
+        }
+        _ => (),
+    }
+}
+
+/// Whether the R and B channels must be swapped when writing into our buffers. This is derived from
+/// the pixel format, unless it was forced with `--swap-channels`
+#[must_use]
+pub fn must_swap_r_and_b_channels() -> bool {
+    debug_assert!(INITIALIZED.load(std::sync::atomic::Ordering::Relaxed));
+    unsafe { SWAP_CHANNELS }.unwrap_or_else(|| pixel_format().must_swap_r_and_b_channels())
+}
+
+/// The pixel format we tell clients to use. It has the same number of channels as the one we
+/// actually use, but takes `must_swap_r_and_b_channels` into account
+#[must_use]
+pub fn client_pixel_format() -> PixelFormat {
+    pixel_format().with_r_and_b_swap(must_swap_r_and_b_channels())
+}
+
 #[must_use]
 pub fn wl_shm_format() -> u32 {
     debug_assert!(INITIALIZED.load(std::sync::atomic::Ordering::Relaxed));
@@ -103,23 +276,55 @@ pub fn wl_shm_format() -> u32 {
     }
 }
 
+/// The wl_shm format with an alpha channel that has the same layout as `wl_shm_format`, if there is
+/// one. ARGB8888 is always supported, since it is mandatory in the protocol
+#[must_use]
+pub fn wl_shm_alpha_format() -> Option<u32> {
+    debug_assert!(INITIALIZED.load(std::sync::atomic::Ordering::Relaxed));
+    match unsafe { PIXEL_FORMAT } {
+        PixelFormat::Xrgb => Some(super::interfaces::wl_shm::format::ARGB8888),
+        PixelFormat::Xbgr => Some(super::interfaces::wl_shm::format::ABGR8888),
+        PixelFormat::Rgb | PixelFormat::Bgr => None,
+    }
+}
+
 /// Note that this function assumes the logger has already been set up
-pub fn init(pixel_format: Option<PixelFormat>) -> Initializer {
+///
+/// We only bind the idle notifier (and a seat) if `idle_notify` is set
+///
+/// With `prefer_compositor_format` (`--format auto-preferred`), we select the pixel format the
+/// compositor prefers, if it tells us. A `pixel_format` forced with `--format` still wins
+pub fn init(
+    pixel_format: Option<PixelFormat>,
+    prefer_compositor_format: bool,
+    swap_channels: Option<bool>,
+    idle_notify: bool,
+) -> Initializer {
     // if we have initialized already, return imediatelly with an empty Initializer
-    let mut initializer = Initializer::new(pixel_format);
+    let mut initializer = Initializer::new();
     if INITIALIZED.load(std::sync::atomic::Ordering::SeqCst) {
         return initializer;
     }
 
+    // with a WAYLAND_SOCKET, we cannot open other connections to probe the pixel format
+    let can_probe = std::env::var_os("WAYLAND_SOCKET").is_none();
+
     // initialize the two most important globals:
     //   * the wayland file descriptor; and
     //   * the object manager
     // we optionally initialize the pixel_format, if necessary
     unsafe {
         WAYLAND_FD = connect();
+        CAN_PROBE = can_probe;
+        PREFER_COMPOSITOR_FORMAT = prefer_compositor_format;
         if let Some(format) = pixel_format {
             info!("Forced usage of wl_shm format: {:?}", format);
             PIXEL_FORMAT = format;
+            FORCED_PIXEL_FORMAT = true;
+        }
+        if let Some(swap) = swap_channels {
+            info!("Forced swapping of the R and B channels: {swap}");
+            SWAP_CHANNELS = Some(swap);
         }
     }
     // the only globals that can break catastrophically are WAYLAND_FD and OBJECT_MANAGER, that we
@@ -128,7 +333,7 @@ pub fn init(pixel_format: Option<PixelFormat>) -> Initializer {
 
     // these functions already require for the wayland file descriptor and the object manager to
     // have been initialized, which we just did above
-    super::interfaces::wl_display::req::get_registry().unwrap();
+    super::interfaces::wl_display::req::get_registry(WL_REGISTRY).unwrap();
     super::interfaces::wl_display::req::sync(ObjectId::new(NonZeroU32::new(3).unwrap())).unwrap();
 
     const IDS: [ObjectId; 4] = [WL_COMPOSITOR, WL_SHM, WP_VIEWPORTER, ZWLR_LAYER_SHELL_V1];
@@ -148,6 +353,15 @@ pub fn init(pixel_format: Option<PixelFormat>) -> Initializer {
         }
     }
 
+    // without layer shell, we make do with fullscreen xdg_toplevels
+    if initializer.global_names[3] == 0 {
+        if let Some(name) = initializer.xdg_wm_base {
+            unsafe { XDG_SHELL_FALLBACK = true };
+            initializer.global_names[3] = name.get();
+            initializer.global_versions[3] = 1;
+        }
+    }
+
     // if we failed to find some necessary global, panic
     if let Some((_, missing)) = initializer
         .global_names
@@ -158,24 +372,68 @@ pub fn init(pixel_format: Option<PixelFormat>) -> Initializer {
         panic!("Compositor does not implement required interface: {missing}");
     }
 
+    if xdg_shell_fallback() {
+        warn!(
+            "the compositor does not support zwlr_layer_shell_v1, falling back to fullscreen \
+            xdg_toplevel windows. They may end up above other windows"
+        );
+    } else {
+        info!("using zwlr_layer_shell_v1 for the wallpapers");
+    }
+
     // bind all the globals we need
     for (i, name) in initializer.global_names.into_iter().enumerate() {
         let id = IDS[i];
-        let interface = REQUIRED_GLOBALS[i];
-        let version = VERSIONS[i];
-        super::interfaces::wl_registry::req::bind(name, id, interface, version).unwrap();
+        let interface = if id == XDG_WM_BASE && xdg_shell_fallback() {
+            "xdg_wm_base"
+        } else {
+            REQUIRED_GLOBALS[i]
+        };
+        let version = initializer.global_versions[i].min(MAX_VERSIONS[i]);
+        bind_global(name, id, interface, version);
     }
 
     // bind fractional scale, if it is supported
     if let Some((id, name)) = initializer.fractional_scale.as_ref() {
         unsafe { FRACTIONAL_SCALE_SUPPORT = true };
-        super::interfaces::wl_registry::req::bind(
+        bind_global(name.get(), *id, "wp_fractional_scale_manager_v1", 1);
+    }
+
+    // bind the single pixel buffer manager, if it is supported
+    if let Some(name) = initializer.single_pixel_buffer.as_ref() {
+        unsafe { SINGLE_PIXEL_BUFFER_SUPPORT = true };
+        bind_global(
             name.get(),
-            *id,
-            "wp_fractional_scale_manager_v1",
+            single_pixel_buffer_manager().unwrap(),
+            "wp_single_pixel_buffer_manager_v1",
+            1,
+        );
+    }
+
+    // bind the screencopy manager, if it is supported
+    if let Some(name) = initializer.screencopy.as_ref() {
+        unsafe { SCREENCOPY_SUPPORT = true };
+        bind_global(
+            name.get(),
+            screencopy_manager().unwrap(),
+            "zwlr_screencopy_manager_v1",
+            1,
+        );
+    }
+
+    // bind the idle notifier and a seat, if we want them and they are supported
+    if !idle_notify {
+        initializer.idle_notifier = None;
+    }
+    if let (Some(notifier), Some(seat)) = (initializer.idle_notifier, initializer.seat) {
+        unsafe { IDLE_NOTIFY_SUPPORT = true };
+        bind_global(
+            notifier.get(),
+            idle_notifier().unwrap(),
+            "ext_idle_notifier_v1",
             1,
-        )
-        .unwrap();
+        );
+        bind_global(seat.get(), self::seat().unwrap(), "wl_seat", 1);
     }
 
     let callback_id = initializer.callback_id();
@@ -193,6 +451,10 @@ pub fn init(pixel_format: Option<PixelFormat>) -> Initializer {
             other => {
                 if other == callback_id {
                     super::interfaces::wl_callback::event(&mut initializer, msg, payload);
+                } else if Some(other) == seat() {
+                    // wl_seat.capabilities, which we do not care about
+                } else if other == XDG_WM_BASE && xdg_shell_fallback() {
+                    super::interfaces::xdg_wm_base::event(&mut initializer, msg, payload);
                 } else {
                     error!("received unexpected event from compositor during initialization")
                 }
@@ -200,9 +462,318 @@ pub fn init(pixel_format: Option<PixelFormat>) -> Initializer {
         }
     }
 
+    if can_probe {
+        select_preferred_pixel_format(&initializer.formats);
+        verify_pixel_format(&initializer.formats);
+    } else if prefer_compositor_format && pixel_format.is_none() {
+        info!("cannot ask the compositor which wl_shm format it prefers through WAYLAND_SOCKET");
+    }
+
     initializer
 }
 
+/// Our pixel formats, from the most preferred to the least, as `negotiate_pixel_format` selects
+/// them (3 channel formats take less memory)
+const FORMAT_PREFERENCE: [PixelFormat; 4] = [
+    PixelFormat::Bgr,
+    PixelFormat::Rgb,
+    PixelFormat::Xbgr,
+    PixelFormat::Xrgb,
+];
+
+/// The formats to try, in order: the selected one, and then the others the compositor advertised.
+/// Xrgb is mandatory in the protocol, so it is always among them
+fn candidate_formats(selected: PixelFormat, advertised: &[PixelFormat]) -> Vec<PixelFormat> {
+    let mut candidates = vec![selected];
+    for format in FORMAT_PREFERENCE {
+        if (format == PixelFormat::Xrgb || advertised.contains(&format))
+            && !candidates.contains(&format)
+        {
+            candidates.push(format);
+        }
+    }
+    candidates
+}
+
+/// `--format auto-preferred`: selects the pixel format the compositor prefers out of the
+/// `advertised` ones, instead of the one `negotiate_pixel_format` selected. We keep that one if the
+/// compositor does not tell us its preference, or prefers none of our formats. Does nothing
+/// without `--format auto-preferred`, or if the format was forced with `--format`
+pub fn select_preferred_pixel_format(advertised: &[PixelFormat]) {
+    if unsafe { !PREFER_COMPOSITOR_FORMAT || FORCED_PIXEL_FORMAT || !CAN_PROBE } {
+        return;
+    }
+    let preferred = probe_preferred_formats().and_then(|tranches| {
+        debug!("formats the compositor prefers, most preferred first: {tranches:?}");
+        preferred_format(&tranches, advertised)
+    });
+    match preferred {
+        Some(format) => {
+            info!("compositor prefers wl_shm format {format:?}");
+            unsafe { PIXEL_FORMAT = format };
+        }
+        None => info!(
+            "compositor did not tell us which of our wl_shm formats it prefers, selecting one \
+             ourselves"
+        ),
+    }
+}
+
+/// Our pixel format for a wl_shm format code, if we support it. We do not support 10 bit formats,
+/// like XRGB2101010: images, transitions and animations all have 8 bit channels
+#[must_use]
+pub fn shm_pixel_format(format: u32) -> Option<PixelFormat> {
+    use super::interfaces::wl_shm::format;
+    match format {
+        format::XRGB8888 => Some(PixelFormat::Xrgb),
+        format::XBGR8888 => Some(PixelFormat::Xbgr),
+        format::RGB888 => Some(PixelFormat::Rgb),
+        format::BGR888 => Some(PixelFormat::Bgr),
+        _ => None,
+    }
+}
+
+/// Our pixel format for a DRM format code, if we support it. These are the same as wl_shm's, except
+/// for ARGB8888 and XRGB8888, which are 0 and 1 in wl_shm. We take the formats with an alpha
+/// channel as their opaque variants, since those are what we clear translucent colors with
+fn drm_pixel_format(fourcc: u32) -> Option<PixelFormat> {
+    use super::interfaces::wl_shm::format;
+    const DRM_ARGB8888: u32 = u32::from_le_bytes(*b"AR24");
+    const DRM_XRGB8888: u32 = u32::from_le_bytes(*b"XR24");
+    match fourcc {
+        DRM_ARGB8888 | DRM_XRGB8888 => Some(PixelFormat::Xrgb),
+        format::ABGR8888 => Some(PixelFormat::Xbgr),
+        format::ARGB8888 | format::XRGB8888 => None,
+        _ => shm_pixel_format(fourcc),
+    }
+}
+
+/// The first of our formats, by `FORMAT_PREFERENCE`, in the most preferred of the compositor's
+/// `tranches` that has any we can use: the compositor must have `advertised` it through wl_shm
+fn preferred_format(
+    tranches: &[Vec<PixelFormat>],
+    advertised: &[PixelFormat],
+) -> Option<PixelFormat> {
+    tranches.iter().find_map(|tranche| {
+        FORMAT_PREFERENCE.into_iter().find(|format| {
+            tranche.contains(format)
+                && (*format == PixelFormat::Xrgb || advertised.contains(format))
+        })
+    })
+}
+
+/// The formats the compositor prefers, grouped in tranches from the most preferred to the least,
+/// keeping only the ones we support. wl_shm has no way to tell us, so we use the default feedback
+/// of linux-dmabuf (version 4 or later): it is what the compositor imports buffers in best, and
+/// the formats it can take without converting our wl_shm buffers first.
+///
+/// Like `probe_shm_format`, we do it on a throwaway connection of our own. Returns `None` if the
+/// compositor does not support it, or if we cannot talk to the compositor through it
+fn probe_preferred_formats() -> Option<Vec<Vec<PixelFormat>>> {
+    use super::wire::{WireMsg, WireMsgBuilder};
+    use std::os::unix::fs::FileExt;
+
+    let socket: OwnedFd = std::os::unix::net::UnixStream::connect(display_socket_path())
+        .ok()?
+        .into();
+    let socket = socket.as_fd();
+    let id = |id| ObjectId(NonZeroU32::new(id).unwrap());
+    let (registry, registry_done, dmabuf, feedback, feedback_done) =
+        (id(2), id(3), id(4), id(5), id(6));
+
+    // wl_display::get_registry and wl_display::sync
+    let mut msg = WireMsgBuilder::new(WL_DISPLAY, 1);
+    msg.add_new_specified_id(registry);
+    msg.send_to(socket).ok()?;
+    let mut msg = WireMsgBuilder::new(WL_DISPLAY, 0);
+    msg.add_new_specified_id(registry_done);
+    msg.send_to(socket).ok()?;
+
+    let mut dmabuf_name = None;
+    loop {
+        let (mut msg, payload) = WireMsg::recv_from(socket).ok()?;
+        if msg.sender_id() == registry && msg.op() == 0 {
+            // wl_registry::global
+            let name = msg.next_u32(&payload);
+            let interface = msg.next_string(&payload);
+            let version = msg.next_u32(&payload);
+            if interface == "zwp_linux_dmabuf_v1" && version >= 4 {
+                dmabuf_name = Some(name);
+            }
+        } else if msg.sender_id() == registry_done {
+            break;
+        }
+    }
+
+    // wl_registry::bind, zwp_linux_dmabuf_v1::get_default_feedback and wl_display::sync, in case
+    // the compositor never finishes sending the feedback
+    let mut msg = WireMsgBuilder::new(registry, 0);
+    msg.add_u32(dmabuf_name?);
+    msg.add_new_unspecified_id(dmabuf, "zwp_linux_dmabuf_v1", 4);
+    msg.send_to(socket).ok()?;
+    let mut msg = WireMsgBuilder::new(dmabuf, 2);
+    msg.add_new_specified_id(feedback);
+    msg.send_to(socket).ok()?;
+    let mut msg = WireMsgBuilder::new(WL_DISPLAY, 0);
+    msg.add_new_specified_id(feedback_done);
+    msg.send_to(socket).ok()?;
+
+    let mut fds = std::collections::VecDeque::new();
+    // each entry is a format (u32), 4 bytes of padding and a modifier (u64)
+    let mut table = Vec::new();
+    let mut tranche = Vec::new();
+    let mut tranches = Vec::new();
+    loop {
+        let (mut msg, payload) = WireMsg::recv_with_fds_from(socket, &mut fds).ok()?;
+        if msg.sender_id() == feedback {
+            match msg.op() {
+                // done
+                0 => return Some(tranches),
+                // format_table
+                1 => {
+                    let file = std::fs::File::from(fds.pop_front()?);
+                    table = vec![0; msg.next_u32(&payload) as usize];
+                    file.read_exact_at(&mut table, 0).ok()?;
+                }
+                // tranche_done
+                3 => tranches.push(std::mem::take(&mut tranche)),
+                // tranche_formats
+                5 => {
+                    for index in msg.next_array(&payload).chunks_exact(2) {
+                        let index = u16::from_ne_bytes([index[0], index[1]]) as usize;
+                        let Some(entry) = table.get(index * 16..index * 16 + 4) else {
+                            continue;
+                        };
+                        let fourcc = u32::from_ne_bytes(entry.try_into().unwrap());
+                        if let Some(format) = drm_pixel_format(fourcc) {
+                            if !tranche.contains(&format) {
+                                tranche.push(format);
+                            }
+                        }
+                    }
+                }
+                // main_device, tranche_target_device and tranche_flags
+                _ => (),
+            }
+        } else if msg.sender_id() == WL_DISPLAY && msg.op() == 0 {
+            // wl_display::error
+            return None;
+        } else if msg.sender_id() == feedback_done {
+            return None;
+        }
+    }
+}
+
+/// Makes sure the compositor accepts buffers in the pixel format we selected, falling back to the
+/// next best one if it does not. Otherwise, we would only find out through a protocol error, once
+/// we try to display something
+fn verify_pixel_format(advertised: &[PixelFormat]) {
+    let selected = pixel_format();
+    for format in candidate_formats(selected, advertised) {
+        unsafe { PIXEL_FORMAT = format };
+        match probe_shm_format(wl_shm_format()) {
+            Some(true) => {
+                if format != selected {
+                    error!("falling back to wl_shm format {format:?}");
+                }
+                return;
+            }
+            Some(false) => error!("compositor does not accept buffers in wl_shm format {format:?}"),
+            None => {
+                debug!("could not verify wl_shm format {format:?}, using it anyway");
+                return;
+            }
+        }
+    }
+    panic!("compositor does not accept buffers in any of the wl_shm formats we support");
+}
+
+/// Whether the compositor accepts wl_shm buffers in `format`. A wl_shm error is fatal for the
+/// connection it happens in, so we try it on a throwaway connection of its own. Returns `None` if
+/// we cannot talk to the compositor through it
+fn probe_shm_format(format: u32) -> Option<bool> {
+    use super::wire::{WireMsg, WireMsgBuilder};
+
+    let socket: OwnedFd = std::os::unix::net::UnixStream::connect(display_socket_path())
+        .ok()?
+        .into();
+    let socket = socket.as_fd();
+    let id = |id| ObjectId(NonZeroU32::new(id).unwrap());
+    let (registry, registry_done, shm, pool, buffer, buffer_done) =
+        (id(2), id(3), id(4), id(5), id(6), id(7));
+
+    // wl_display::get_registry and wl_display::sync
+    let mut msg = WireMsgBuilder::new(WL_DISPLAY, 1);
+    msg.add_new_specified_id(registry);
+    msg.send_to(socket).ok()?;
+    let mut msg = WireMsgBuilder::new(WL_DISPLAY, 0);
+    msg.add_new_specified_id(registry_done);
+    msg.send_to(socket).ok()?;
+
+    let mut shm_name = None;
+    loop {
+        let (mut msg, payload) = WireMsg::recv_from(socket).ok()?;
+        if msg.sender_id() == registry && msg.op() == 0 {
+            // wl_registry::global
+            let name = msg.next_u32(&payload);
+            if msg.next_string(&payload) == "wl_shm" {
+                shm_name = Some(name);
+            }
+        } else if msg.sender_id() == registry_done {
+            break;
+        }
+    }
+
+    // wl_registry::bind, wl_shm::create_pool, wl_shm_pool::create_buffer and wl_display::sync
+    let mut msg = WireMsgBuilder::new(registry, 0);
+    msg.add_u32(shm_name?);
+    msg.add_new_unspecified_id(shm, "wl_shm", 1);
+    msg.send_to(socket).ok()?;
+
+    let bytes_per_pixel = match format {
+        super::interfaces::wl_shm::format::RGB888 | super::interfaces::wl_shm::format::BGR888 => 3,
+        _ => 4,
+    };
+    let memory = utils::ipc::Mmap::create(4);
+    let fd = memory.fd();
+    let mut msg = WireMsgBuilder::new(shm, 0);
+    msg.add_new_specified_id(pool);
+    msg.add_fd(&fd);
+    msg.add_i32(4);
+    msg.send_to(socket).ok()?;
+
+    let mut msg = WireMsgBuilder::new(pool, 0);
+    msg.add_new_specified_id(buffer);
+    msg.add_i32(0);
+    msg.add_i32(1);
+    msg.add_i32(1);
+    msg.add_i32(bytes_per_pixel);
+    msg.add_u32(format);
+    msg.send_to(socket).ok()?;
+
+    let mut msg = WireMsgBuilder::new(WL_DISPLAY, 0);
+    msg.add_new_specified_id(buffer_done);
+    msg.send_to(socket).ok()?;
+
+    loop {
+        let (msg, _) = WireMsg::recv_from(socket).ok()?;
+        if msg.sender_id() == WL_DISPLAY && msg.op() == 0 {
+            // wl_display::error
+            return Some(false);
+        } else if msg.sender_id() == buffer_done {
+            return Some(true);
+        }
+    }
+}
+
+fn is_connected(fd: &OwnedFd) -> bool {
+    match rustix::net::getpeername(fd) {
+        Ok(_) => true,
+        Err(rustix::io::Errno::NOTCONN) => false,
+        Err(e) => panic!("failed to query wayland socket peer: {e}"),
+    }
+}
+
 /// copy-pasted from `wayland-client.rs`
 fn connect() -> OwnedFd {
     if let Ok(txt) = std::env::var("WAYLAND_SOCKET") {
@@ -221,6 +792,12 @@ fn connect() -> OwnedFd {
             .and_then(|f| rustix::io::fcntl_setfd(&fd, f))
             .expect("failed to set flags on socket");
 
+        // the parent usually passes us a socket that is already connected (e.g. one end of a
+        // socketpair), and connecting it again fails with EISCONN
+        if is_connected(&fd) {
+            return fd;
+        }
+
         let socket_addr =
             rustix::net::getsockname(&fd).expect("failed to get wayland socket address");
         if let SocketAddrAny::Unix(addr) = socket_addr {
@@ -230,55 +807,77 @@ fn connect() -> OwnedFd {
             panic!("socket address is not a unix socket");
         }
     } else {
-        let socket_name = std::env::var_os("WAYLAND_DISPLAY")
-            .map(Into::<PathBuf>::into)
-            .expect("failed to detect wayland compositor: WAYLAND_DISPLAY not set");
-
-        let socket_path = if socket_name.is_absolute() {
-            socket_name
-        } else {
-            let mut socket_path = std::env::var_os("XDG_RUNTIME_DIR")
-                .map(Into::<PathBuf>::into)
-                .expect("failed to detect wayland compositor: XDG_RUNTIME_DIR not set");
-            if !socket_path.is_absolute() {
-                panic!("failed to detect wayland compositor: socket_path is not absolute");
-            }
-            socket_path.push(socket_name);
-            socket_path
-        };
-
-        std::os::unix::net::UnixStream::connect(socket_path)
+        std::os::unix::net::UnixStream::connect(display_socket_path())
             .expect("failed to connect to socket")
             .into()
     }
 }
 
+fn display_socket_path() -> PathBuf {
+    let socket_name = std::env::var_os("WAYLAND_DISPLAY")
+        .map(Into::<PathBuf>::into)
+        .expect("failed to detect wayland compositor: WAYLAND_DISPLAY not set");
+
+    if socket_name.is_absolute() {
+        socket_name
+    } else {
+        let mut socket_path = std::env::var_os("XDG_RUNTIME_DIR")
+            .map(Into::<PathBuf>::into)
+            .expect("failed to detect wayland compositor: XDG_RUNTIME_DIR not set");
+        if !socket_path.is_absolute() {
+            panic!("failed to detect wayland compositor: socket_path is not absolute");
+        }
+        socket_path.push(socket_name);
+        socket_path
+    }
+}
+
 /// Helper struct to do all the initialization in this file
 pub struct Initializer {
     global_names: [u32; REQUIRED_GLOBALS.len()],
+    /// the versions the compositor advertised for each of `global_names`
+    global_versions: [u32; REQUIRED_GLOBALS.len()],
     output_names: Vec<u32>,
     fractional_scale: Option<(ObjectId, NonZeroU32)>,
-    forced_shm_format: bool,
+    single_pixel_buffer: Option<NonZeroU32>,
+    screencopy: Option<NonZeroU32>,
+    idle_notifier: Option<NonZeroU32>,
+    /// the first seat the compositor advertised
+    seat: Option<NonZeroU32>,
+    /// we only bind it later, if a client sets an image for a workspace
+    workspace_manager: Option<NonZeroU32>,
+    /// we only bind it if the compositor lacks zwlr_layer_shell_v1
+    xdg_wm_base: Option<NonZeroU32>,
+    /// the wl_shm formats the compositor advertised, out of the ones we support
+    formats: Vec<PixelFormat>,
     should_exit: bool,
 }
 
 impl Initializer {
-    fn new(cli_format: Option<PixelFormat>) -> Self {
+    fn new() -> Self {
         Self {
             global_names: [0; REQUIRED_GLOBALS.len()],
+            global_versions: [0; REQUIRED_GLOBALS.len()],
             output_names: Vec::new(),
             fractional_scale: None,
-            forced_shm_format: cli_format.is_some(),
+            single_pixel_buffer: None,
+            screencopy: None,
+            idle_notifier: None,
+            seat: None,
+            workspace_manager: None,
+            xdg_wm_base: None,
+            formats: Vec::new(),
             should_exit: false,
         }
     }
 
     fn callback_id(&self) -> ObjectId {
-        if self.fractional_scale.is_some() {
-            ObjectId(unsafe { NonZeroU32::new_unchecked(8) })
-        } else {
-            ObjectId(unsafe { NonZeroU32::new_unchecked(7) })
-        }
+        let id = 7
+            + self.fractional_scale.is_some() as u32
+            + self.single_pixel_buffer.is_some() as u32
+            + self.screencopy.is_some() as u32
+            + 2 * idle_notify_support() as u32;
+        ObjectId(unsafe { NonZeroU32::new_unchecked(id) })
     }
 
     pub fn output_names(&self) -> &[u32] {
@@ -288,13 +887,17 @@ impl Initializer {
     pub fn fractional_scale(&self) -> Option<&(ObjectId, NonZeroU32)> {
         self.fractional_scale.as_ref()
     }
+
+    /// The global name of `ext_workspace_manager_v1`, if the compositor advertised it
+    pub fn workspace_manager(&self) -> Option<u32> {
+        self.workspace_manager.map(NonZeroU32::get)
+    }
 }
 
 impl super::interfaces::wl_display::EvHandler for Initializer {
     fn delete_id(&mut self, id: u32) {
         if id == 3 // initial callback for the roundtrip
-            || self.fractional_scale.is_none() && id == 7
-            || self.fractional_scale.is_some() && id == 8
+            || id == self.callback_id().get()
         {
             self.should_exit = true;
         } else {
@@ -303,6 +906,12 @@ impl super::interfaces::wl_display::EvHandler for Initializer {
     }
 }
 
+impl super::interfaces::xdg_wm_base::EvHandler for Initializer {
+    fn ping(&mut self, serial: u32) {
+        super::interfaces::xdg_wm_base::req::pong(serial).unwrap();
+    }
+}
+
 impl super::interfaces::wl_callback::EvHandler for Initializer {
     fn done(&mut self, sender_id: ObjectId, _callback_data: u32) {
         debug!(
@@ -325,6 +934,26 @@ impl super::interfaces::wl_registry::EvHandler for Initializer {
                     name.try_into().unwrap(),
                 ));
             }
+            "wp_single_pixel_buffer_manager_v1" => {
+                self.single_pixel_buffer = Some(name.try_into().unwrap());
+            }
+            "zwlr_screencopy_manager_v1" => {
+                self.screencopy = Some(name.try_into().unwrap());
+            }
+            "ext_idle_notifier_v1" => {
+                self.idle_notifier = Some(name.try_into().unwrap());
+            }
+            "ext_workspace_manager_v1" => {
+                self.workspace_manager = Some(name.try_into().unwrap());
+            }
+            "xdg_wm_base" => {
+                self.xdg_wm_base = Some(name.try_into().unwrap());
+            }
+            "wl_seat" => {
+                if self.seat.is_none() {
+                    self.seat = Some(name.try_into().unwrap());
+                }
+            }
             "wl_output" => {
                 if version < 4 {
                     error!("wl_output implementation must have at least version 4 for swww-daemon")
@@ -342,6 +971,7 @@ impl super::interfaces::wl_registry::EvHandler for Initializer {
                             );
                         }
                         self.global_names[i] = name;
+                        self.global_versions[i] = version;
                         break;
                     }
                 }
@@ -356,29 +986,9 @@ impl super::interfaces::wl_registry::EvHandler for Initializer {
 
 impl super::interfaces::wl_shm::EvHandler for Initializer {
     fn format(&mut self, format: u32) {
-        match format {
-            super::interfaces::wl_shm::format::XRGB8888 => {
-                debug!("available shm format: Xrbg");
-            }
-            super::interfaces::wl_shm::format::XBGR8888 => {
-                debug!("available shm format: Xbgr");
-                if !self.forced_shm_format && pixel_format() == PixelFormat::Xrgb {
-                    unsafe { PIXEL_FORMAT = PixelFormat::Xbgr }
-                }
-            }
-            super::interfaces::wl_shm::format::RGB888 => {
-                debug!("available shm format: Rbg");
-                if !self.forced_shm_format && pixel_format() != PixelFormat::Bgr {
-                    unsafe { PIXEL_FORMAT = PixelFormat::Rgb }
-                }
-            }
-            super::interfaces::wl_shm::format::BGR888 => {
-                debug!("available shm format: Bgr");
-                if !self.forced_shm_format {
-                    unsafe { PIXEL_FORMAT = PixelFormat::Bgr }
-                }
-            }
-            _ => (),
+        negotiate_pixel_format(format);
+        if let Some(format) = shm_pixel_format(format) {
+            self.formats.push(format);
         }
     }
 }
@@ -388,3 +998,56 @@ impl Drop for Initializer {
         debug!("Initialization Over");
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn detects_connected_sockets() {
+        let (a, _b) = std::os::unix::net::UnixStream::pair().unwrap();
+        assert!(is_connected(&a.into()));
+
+        let unconnected = std::os::unix::net::UnixDatagram::unbound().unwrap();
+        assert!(!is_connected(&unconnected.into()));
+    }
+
+    #[test]
+    fn falls_back_to_advertised_formats() {
+        assert_eq!(
+            candidate_formats(PixelFormat::Bgr, &[PixelFormat::Xbgr, PixelFormat::Bgr]),
+            [PixelFormat::Bgr, PixelFormat::Xbgr, PixelFormat::Xrgb]
+        );
+        // a format forced with --format may not have been advertised at all
+        assert_eq!(
+            candidate_formats(PixelFormat::Rgb, &[]),
+            [PixelFormat::Rgb, PixelFormat::Xrgb]
+        );
+        assert_eq!(
+            candidate_formats(PixelFormat::Xrgb, &[PixelFormat::Xrgb]),
+            [PixelFormat::Xrgb]
+        );
+    }
+
+    #[test]
+    fn prefers_the_compositors_most_preferred_tranche() {
+        use PixelFormat::*;
+        let tranches = [vec![Xbgr], vec![Bgr, Xrgb]];
+        assert_eq!(preferred_format(&tranches, &[Xbgr, Bgr]), Some(Xbgr));
+        // we can only use formats wl_shm advertised, except for the mandatory Xrgb
+        assert_eq!(preferred_format(&tranches, &[Bgr]), Some(Bgr));
+        assert_eq!(preferred_format(&tranches, &[]), Some(Xrgb));
+        assert_eq!(preferred_format(&[vec![Rgb]], &[Xbgr]), None);
+    }
+
+    #[test]
+    fn drm_formats_map_to_ours() {
+        use super::super::interfaces::wl_shm::format;
+        assert_eq!(drm_pixel_format(0x3432_5258), Some(PixelFormat::Xrgb));
+        assert_eq!(drm_pixel_format(0x3432_5241), Some(PixelFormat::Xrgb));
+        assert_eq!(drm_pixel_format(format::XBGR8888), Some(PixelFormat::Xbgr));
+        assert_eq!(drm_pixel_format(format::BGR888), Some(PixelFormat::Bgr));
+        // wl_shm's XRGB8888 is not DRM's
+        assert_eq!(drm_pixel_format(format::XRGB8888), None);
+    }
+}
@@ -29,28 +29,13 @@ pub mod wl_display {
         ///own set of error codes.  The message is a brief description
         ///of the error, for (debugging) convenience.
         fn error(&mut self, object_id: ObjectId, code: u32, message: &str) {
-            let interface = match object_id {
-                globals::WL_DISPLAY => "wl_display",
-                globals::WL_REGISTRY => "wl_registry",
-                globals::WL_COMPOSITOR => "wl_compositor",
-                globals::WL_SHM => "wl_shm",
-                globals::WP_VIEWPORTER => "wp_viewporter",
-                globals::ZWLR_LAYER_SHELL_V1 => "zwlr_layer_shell_v1",
-                other => match super::super::globals::object_type_get(other) {
-                    Some(super::super::WlDynObj::Output) => "wl_output",
-                    Some(super::super::WlDynObj::Surface) => "wl_surface",
-                    Some(super::super::WlDynObj::Region) => "wl_region",
-                    Some(super::super::WlDynObj::LayerSurface) => "zwlr_layer_surface_v1",
-                    Some(super::super::WlDynObj::Buffer) => "wl_buffer",
-                    Some(super::super::WlDynObj::ShmPool) => "wl_shm_pool",
-                    Some(super::super::WlDynObj::Callback) => "wl_callback",
-                    Some(super::super::WlDynObj::Viewport) => "wl_viewport",
-                    Some(super::super::WlDynObj::FractionalScale) => "wp_fractional_scale_v1",
-                    None => "???",
-                },
-            };
-
-            panic!("Protocol error on interface {interface}. Code {code}: {message}");
+            let interface = interface_name(object_id);
+            let error = error_name(interface, code).unwrap_or("unknown error");
+            log::error!(
+                "Protocol error on {interface}#{}: {error} (code {code}): {message}",
+                object_id.get()
+            );
+            panic!("Protocol error on {interface}#{}: {error}", object_id.get());
         }
         ///acknowledge object ID deletion
         ///
@@ -62,6 +47,104 @@ pub mod wl_display {
         fn delete_id(&mut self, id: u32);
     }
 
+    /// The name of the interface `object_id` was created with, or `"???"` if we do not know it
+    fn interface_name(object_id: ObjectId) -> &'static str {
+        use super::super::WlDynObj;
+        match object_id {
+            globals::WL_DISPLAY => "wl_display",
+            globals::WL_REGISTRY => "wl_registry",
+            globals::WL_COMPOSITOR => "wl_compositor",
+            globals::WL_SHM => "wl_shm",
+            globals::WP_VIEWPORTER => "wp_viewporter",
+            globals::ZWLR_LAYER_SHELL_V1 if globals::xdg_shell_fallback() => "xdg_wm_base",
+            globals::ZWLR_LAYER_SHELL_V1 => "zwlr_layer_shell_v1",
+            other if globals::fractional_scale_manager() == Some(other) => {
+                "wp_fractional_scale_manager_v1"
+            }
+            other if globals::single_pixel_buffer_manager() == Some(other) => {
+                "wp_single_pixel_buffer_manager_v1"
+            }
+            other if globals::screencopy_manager() == Some(other) => "zwlr_screencopy_manager_v1",
+            other if globals::idle_notifier() == Some(other) => "ext_idle_notifier_v1",
+            other if globals::seat() == Some(other) => "wl_seat",
+            other => match globals::object_type_get(other) {
+                Some(WlDynObj::Output) => "wl_output",
+                Some(WlDynObj::Surface) => "wl_surface",
+                Some(WlDynObj::Region) => "wl_region",
+                Some(WlDynObj::LayerSurface) => "zwlr_layer_surface_v1",
+                Some(WlDynObj::XdgSurface) => "xdg_surface",
+                Some(WlDynObj::XdgToplevel) => "xdg_toplevel",
+                Some(WlDynObj::Buffer) => "wl_buffer",
+                Some(WlDynObj::ShmPool) => "wl_shm_pool",
+                Some(WlDynObj::Callback) => "wl_callback",
+                Some(WlDynObj::Viewport) => "wp_viewport",
+                Some(WlDynObj::FractionalScale) => "wp_fractional_scale_v1",
+                Some(WlDynObj::Registry) => "wl_registry",
+                Some(WlDynObj::Shm) => "wl_shm",
+                Some(WlDynObj::ScreencopyFrame) => "zwlr_screencopy_frame_v1",
+                Some(WlDynObj::IdleNotification) => "ext_idle_notification_v1",
+                Some(WlDynObj::WorkspaceManager) => "ext_workspace_manager_v1",
+                None => "???",
+            },
+        }
+    }
+
+    /// The name of error `code` for `interface`, as it is written in the protocol xml
+    ///
+    /// Compositors also send the global `wl_display` errors (like `invalid_method`) on any object,
+    /// using the same codes. Since we cannot tell them apart, interfaces that define their own
+    /// errors take precedence
+    pub(super) fn error_name(interface: &str, code: u32) -> Option<&'static str> {
+        let names: &[&str] = match interface {
+            "wl_shm" => &["invalid_format", "invalid_stride", "invalid_fd"],
+            "wl_surface" => &[
+                "invalid_scale",
+                "invalid_transform",
+                "invalid_size",
+                "invalid_offset",
+                "defunct_role_object",
+            ],
+            "wp_viewporter" => &["viewport_exists"],
+            "wp_viewport" => &["bad_value", "bad_size", "out_of_buffer", "no_surface"],
+            "wp_fractional_scale_manager_v1" => &["fractional_scale_exists"],
+            "zwlr_layer_shell_v1" => &["role", "invalid_layer", "already_constructed"],
+            "zwlr_layer_surface_v1" => &[
+                "invalid_surface_state",
+                "invalid_size",
+                "invalid_anchor",
+                "invalid_keyboard_interactivity",
+                "invalid_exclusive_edge",
+            ],
+            "zwlr_screencopy_frame_v1" => &["already_used", "invalid_buffer"],
+            "xdg_wm_base" => &[
+                "role",
+                "defunct_surfaces",
+                "not_the_topmost_popup",
+                "invalid_popup_parent",
+                "invalid_surface_state",
+                "invalid_positioner",
+                "unresponsive",
+            ],
+            // its codes start at 1, so 0 is still wl_display's
+            "xdg_surface" => &[
+                "invalid_object",
+                "not_constructed",
+                "already_constructed",
+                "unconfigured_buffer",
+                "invalid_serial",
+                "invalid_size",
+                "defunct_role_object",
+            ],
+            _ => &[
+                "invalid_object",
+                "invalid_method",
+                "no_memory",
+                "implementation",
+            ],
+        };
+        names.get(code as usize).copied()
+    }
+
     pub fn event<T: EvHandler>(state: &mut T, mut wire_msg: WireMsg, payload: WaylandPayload) {
         match wire_msg.op() {
             0 => {
@@ -107,9 +190,9 @@ pub mod wl_display {
         ///client disconnects, not when the client side proxy is destroyed.
         ///Therefore, clients should invoke get_registry as infrequently as
         ///possible to avoid wasting memory.
-        pub fn get_registry() -> rustix::io::Result<()> {
+        pub fn get_registry(registry: ObjectId) -> rustix::io::Result<()> {
             let mut wire_msg_builder = WireMsgBuilder::new(globals::WL_DISPLAY, 1);
-            wire_msg_builder.add_new_specified_id(globals::WL_REGISTRY);
+            wire_msg_builder.add_new_specified_id(registry);
             wire_msg_builder.send()
         }
     }
@@ -1834,6 +1917,55 @@ pub mod wp_fractional_scale_v1 {
         }
     }
 }
+///single pixel buffer factory
+///
+///The wp_single_pixel_buffer_manager_v1 interface is a factory for
+///single-pixel buffers.
+pub mod wp_single_pixel_buffer_manager_v1 {
+    use super::*;
+
+    ///Events for this interface
+    pub mod ev {}
+    ///Requests for this interface
+    pub mod req {
+        use super::*;
+        ///destroy the manager
+        ///
+        ///Destroy the wp_single_pixel_buffer_manager_v1 object.
+        ///
+        ///The child objects created via this interface are unaffected.
+        ///
+        ///THIS IS A DESTRUCTOR
+        pub fn destroy(sender_id: ObjectId) -> rustix::io::Result<()> {
+            let wire_msg_builder = WireMsgBuilder::new(sender_id, 0);
+            wire_msg_builder.send()
+        }
+        ///create a 1x1 buffer from 32-bit RGBA values
+        ///
+        ///Create a single-pixel buffer from four 32-bit RGBA values.
+        ///
+        ///Unless specified in another protocol extension, the RGBA values use
+        ///pre-multiplied alpha.
+        ///
+        ///The width and height of the buffer are 1.
+        pub fn create_u32_rgba_buffer(
+            sender_id: ObjectId,
+            id: ObjectId,
+            r: u32,
+            g: u32,
+            b: u32,
+            a: u32,
+        ) -> rustix::io::Result<()> {
+            let mut wire_msg_builder = WireMsgBuilder::new(sender_id, 1);
+            wire_msg_builder.add_new_specified_id(id);
+            wire_msg_builder.add_u32(r);
+            wire_msg_builder.add_u32(g);
+            wire_msg_builder.add_u32(b);
+            wire_msg_builder.add_u32(a);
+            wire_msg_builder.send()
+        }
+    }
+}
 ///create surfaces that are layers of the desktop
 ///
 ///Clients can use this interface to assign the surface_layer role to
@@ -2263,3 +2395,836 @@ pub mod zwlr_layer_surface_v1 {
         pub const RIGHT: u32 = 8u32;
     }
 }
+///create desktop-style surfaces
+///
+///The xdg_wm_base interface is exposed as a global object enabling clients
+///to turn their wl_surfaces into windows in a desktop environment. It
+///defines the basic functionality needed for clients and the compositor to
+///create windows that can be dragged, resized, maximized, etc, as well as
+///creating transient windows such as popup menus.
+///
+///We only use it when the compositor does not support zwlr_layer_shell_v1.
+pub mod xdg_wm_base {
+    use super::*;
+
+    pub trait EvHandler {
+        ///check if the client is alive
+        ///
+        ///The ping event asks the client if it's still alive. Pass the
+        ///serial specified in the event back to the compositor by sending
+        ///a "pong" request back with the specified serial. See xdg_wm_base.pong.
+        ///
+        ///A compositor is free to ping in any way it wants, but a client must
+        ///always respond to any xdg_wm_base object it created.
+        fn ping(&mut self, serial: u32);
+    }
+
+    pub fn event<T: EvHandler>(state: &mut T, mut wire_msg: WireMsg, payload: WaylandPayload) {
+        match wire_msg.op() {
+            0 => state.ping(wire_msg.next_u32(&payload)),
+            e => log::error!("unrecognized event opcode: {e} for interface xdg_wm_base"),
+        }
+    }
+
+    ///Requests for this interface
+    pub mod req {
+        use super::*;
+        ///create a shell surface from a surface
+        ///
+        ///This creates an xdg_surface for the given surface. While xdg_surface
+        ///itself is not a role, the corresponding surface may only be assigned
+        ///a role extending xdg_surface, such as xdg_toplevel or xdg_popup. It is
+        ///illegal to create an xdg_surface for a wl_surface which already has an
+        ///assigned role and this will result in a role error.
+        ///
+        ///This creates an xdg_surface for the given surface. An xdg_surface is
+        ///used as basis to define a role to a given surface, such as xdg_toplevel
+        ///or xdg_popup. It also manages functionality shared between xdg_surface
+        ///based surface roles.
+        pub fn get_xdg_surface(id: ObjectId, surface: ObjectId) -> rustix::io::Result<()> {
+            let mut wire_msg_builder = WireMsgBuilder::new(globals::XDG_WM_BASE, 2);
+            wire_msg_builder.add_new_specified_id(id);
+            wire_msg_builder.add_object(Some(surface));
+            wire_msg_builder.send()
+        }
+        ///respond to a ping event
+        ///
+        ///A client must respond to a ping event with a pong request or
+        ///the client may be deemed unresponsive. See xdg_wm_base.ping
+        ///and xdg_wm_base.error.unresponsive.
+        pub fn pong(serial: u32) -> rustix::io::Result<()> {
+            let mut wire_msg_builder = WireMsgBuilder::new(globals::XDG_WM_BASE, 3);
+            wire_msg_builder.add_u32(serial);
+            wire_msg_builder.send()
+        }
+    }
+}
+///desktop user interface surface base interface
+///
+///An interface that may be implemented by a wl_surface, for
+///implementations that provide a desktop-style user interface.
+///
+///It provides a base set of functionality required to construct user
+///interface elements requiring management by the compositor, such as
+///toplevel windows, menus, etc. The types of functionality are split into
+///xdg_surface roles.
+pub mod xdg_surface {
+    use super::*;
+
+    pub trait EvHandler {
+        ///suggest a surface change
+        ///
+        ///The configure event marks the end of a configure sequence. A configure
+        ///sequence is a set of one or more events configuring the state of the
+        ///xdg_surface, including the final xdg_surface.configure event.
+        ///
+        ///If the client receives multiple configure events before it can respond
+        ///to one, it is free to discard all but the last event it received.
+        fn configure(&mut self, sender_id: ObjectId, serial: u32);
+    }
+
+    pub fn event<T: EvHandler>(state: &mut T, mut wire_msg: WireMsg, payload: WaylandPayload) {
+        match wire_msg.op() {
+            0 => {
+                let serial = wire_msg.next_u32(&payload);
+                state.configure(wire_msg.sender_id(), serial);
+            }
+            e => log::error!("unrecognized event opcode: {e} for interface xdg_surface"),
+        }
+    }
+
+    ///Requests for this interface
+    pub mod req {
+        use super::*;
+        ///destroy the xdg_surface
+        ///
+        ///Destroy the xdg_surface object. An xdg_surface must only be destroyed
+        ///after its role object has been destroyed, otherwise
+        ///a defunct_role_object error is raised.
+        ///
+        ///THIS IS A DESTRUCTOR
+        pub fn destroy(sender_id: ObjectId) -> rustix::io::Result<()> {
+            let wire_msg_builder = WireMsgBuilder::new(sender_id, 0);
+            wire_msg_builder.send()
+        }
+        ///assign the xdg_toplevel surface role
+        ///
+        ///This creates an xdg_toplevel object for the given xdg_surface and gives
+        ///the associated wl_surface the xdg_toplevel role.
+        pub fn get_toplevel(sender_id: ObjectId, id: ObjectId) -> rustix::io::Result<()> {
+            let mut wire_msg_builder = WireMsgBuilder::new(sender_id, 1);
+            wire_msg_builder.add_new_specified_id(id);
+            wire_msg_builder.send()
+        }
+        ///ack a configure event
+        ///
+        ///When a configure event is received, if a client commits the
+        ///surface in response to the configure event, then the client
+        ///must make an ack_configure request sometime before the commit
+        ///request, passing along the serial of the configure event.
+        pub fn ack_configure(sender_id: ObjectId, serial: u32) -> rustix::io::Result<()> {
+            let mut wire_msg_builder = WireMsgBuilder::new(sender_id, 4);
+            wire_msg_builder.add_u32(serial);
+            wire_msg_builder.send()
+        }
+    }
+}
+///toplevel surface
+///
+///This interface defines an xdg_surface role which allows a surface to,
+///among other things, set window-like properties such as maximize,
+///fullscreen, and minimize, set application-specific metadata like title and
+///id, and well as trigger user interactive operations such as interactive
+///resize and move.
+pub mod xdg_toplevel {
+    use super::*;
+
+    pub trait EvHandler {
+        ///suggest a surface change
+        ///
+        ///This configure event asks the client to resize its toplevel surface or
+        ///to change its state. The configured state should not be applied
+        ///immediately. See xdg_surface.configure for details.
+        ///
+        ///The width and height arguments specify a hint to the window
+        ///about how its surface should be resized in window geometry
+        ///coordinates.
+        fn configure(&mut self, sender_id: ObjectId, width: i32, height: i32, states: &[u8]);
+        ///surface wants to be closed
+        ///
+        ///The close event is sent by the compositor when the user
+        ///wants the surface to be closed. This should be equivalent to
+        ///the user clicking the close button in client-side decorations,
+        ///if your application has any.
+        fn close(&mut self, sender_id: ObjectId);
+    }
+
+    pub fn event<T: EvHandler>(state: &mut T, mut wire_msg: WireMsg, payload: WaylandPayload) {
+        match wire_msg.op() {
+            0 => {
+                let width = wire_msg.next_i32(&payload);
+                let height = wire_msg.next_i32(&payload);
+                let states = wire_msg.next_array(&payload);
+                state.configure(wire_msg.sender_id(), width, height, states);
+            }
+            1 => state.close(wire_msg.sender_id()),
+            e => log::error!("unrecognized event opcode: {e} for interface xdg_toplevel"),
+        }
+    }
+
+    ///Requests for this interface
+    pub mod req {
+        use super::*;
+        ///destroy the xdg_toplevel
+        ///
+        ///This request destroys the role surface and unmaps the surface;
+        ///see "Unmapping" behavior in interface section for details.
+        ///
+        ///THIS IS A DESTRUCTOR
+        pub fn destroy(sender_id: ObjectId) -> rustix::io::Result<()> {
+            let wire_msg_builder = WireMsgBuilder::new(sender_id, 0);
+            wire_msg_builder.send()
+        }
+        ///set surface title
+        ///
+        ///Set a short title for the surface.
+        pub fn set_title(sender_id: ObjectId, title: &str) -> rustix::io::Result<()> {
+            let mut wire_msg_builder = WireMsgBuilder::new(sender_id, 2);
+            wire_msg_builder.add_string(title);
+            wire_msg_builder.send()
+        }
+        ///set application ID
+        ///
+        ///Set an application identifier for the surface.
+        pub fn set_app_id(sender_id: ObjectId, app_id: &str) -> rustix::io::Result<()> {
+            let mut wire_msg_builder = WireMsgBuilder::new(sender_id, 3);
+            wire_msg_builder.add_string(app_id);
+            wire_msg_builder.send()
+        }
+        ///set the window as fullscreen on an output
+        ///
+        ///Make the surface fullscreen.
+        ///
+        ///The output passed by the request indicates the client's preference as
+        ///to which display it should be set fullscreen on. If this value is NULL,
+        ///it's up to the compositor to choose which display will be used to map
+        ///this surface.
+        pub fn set_fullscreen(
+            sender_id: ObjectId,
+            output: Option<ObjectId>,
+        ) -> rustix::io::Result<()> {
+            let mut wire_msg_builder = WireMsgBuilder::new(sender_id, 11);
+            wire_msg_builder.add_object(output);
+            wire_msg_builder.send()
+        }
+    }
+}
+///manager to inform clients and begin capturing
+///
+///This object is a manager which offers requests to start capturing from a
+///source.
+pub mod zwlr_screencopy_manager_v1 {
+    use super::*;
+
+    ///Events for this interface
+    pub mod ev {}
+    ///Requests for this interface
+    pub mod req {
+        use super::*;
+        ///capture an output
+        ///
+        ///Capture the next frame of an entire output.
+        pub fn capture_output(
+            sender_id: ObjectId,
+            frame: ObjectId,
+            overlay_cursor: i32,
+            output: ObjectId,
+        ) -> rustix::io::Result<()> {
+            let mut wire_msg_builder = WireMsgBuilder::new(sender_id, 0);
+            wire_msg_builder.add_new_specified_id(frame);
+            wire_msg_builder.add_i32(overlay_cursor);
+            wire_msg_builder.add_object(Some(output));
+            wire_msg_builder.send()
+        }
+        ///destroy the manager
+        ///
+        ///All objects created by the manager will still remain valid, until their
+        ///appropriate destroy request has been called.
+        ///
+        ///THIS IS A DESTRUCTOR
+        pub fn destroy(sender_id: ObjectId) -> rustix::io::Result<()> {
+            let wire_msg_builder = WireMsgBuilder::new(sender_id, 2);
+            wire_msg_builder.send()
+        }
+    }
+}
+///a frame ready for copy
+///
+///This object represents a single frame.
+///
+///When created, a series of buffer events will be sent, each representing a
+///supported buffer type. The "buffer_done" event is sent afterwards to
+///indicate that all supported buffer types have been enumerated. The client
+///will then be able to send a "copy" request. If the capture is successful,
+///the compositor will send a "flags" event followed by a "ready" event.
+///
+///For objects version 2 or lower, wl_shm buffers are always supported, ie.
+///the "buffer" event is guaranteed to be sent.
+///
+///If the capture failed, the "failed" event is sent. This can happen anytime
+///before the "ready" event.
+///
+///Once either a "ready" or a "failed" event is received, the client should
+///destroy the frame.
+pub mod zwlr_screencopy_frame_v1 {
+    use super::*;
+
+    pub trait EvHandler {
+        ///wl_shm buffer information
+        ///
+        ///Provides information about wl_shm buffer parameters that need to be
+        ///used for this frame. This event is sent once after the frame is created
+        ///if wl_shm buffers are supported.
+        fn buffer(
+            &mut self,
+            sender_id: ObjectId,
+            format: u32,
+            width: u32,
+            height: u32,
+            stride: u32,
+        );
+        ///frame flags
+        ///
+        ///Provides flags about the frame. This event is sent once before the
+        ///"ready" event.
+        fn flags(&mut self, sender_id: ObjectId, flags: u32);
+        ///indicates frame is available for reading
+        ///
+        ///Called as soon as the frame is copied, indicating it is available
+        ///for reading. This event includes the time at which the presentation took place.
+        ///
+        ///After receiving this event, the client should destroy the object.
+        fn ready(&mut self, sender_id: ObjectId, tv_sec_hi: u32, tv_sec_lo: u32, tv_nsec: u32);
+        ///frame copy failed
+        ///
+        ///This event indicates that the attempted frame copy has failed.
+        ///
+        ///After receiving this event, the client should destroy the object.
+        fn failed(&mut self, sender_id: ObjectId);
+    }
+
+    pub fn event<T: EvHandler>(state: &mut T, mut wire_msg: WireMsg, payload: WaylandPayload) {
+        match wire_msg.op() {
+            0 => {
+                let format = wire_msg.next_u32(&payload);
+                let width = wire_msg.next_u32(&payload);
+                let height = wire_msg.next_u32(&payload);
+                let stride = wire_msg.next_u32(&payload);
+                state.buffer(wire_msg.sender_id(), format, width, height, stride);
+            }
+            1 => {
+                let flags = wire_msg.next_u32(&payload);
+                state.flags(wire_msg.sender_id(), flags);
+            }
+            2 => {
+                let tv_sec_hi = wire_msg.next_u32(&payload);
+                let tv_sec_lo = wire_msg.next_u32(&payload);
+                let tv_nsec = wire_msg.next_u32(&payload);
+                state.ready(wire_msg.sender_id(), tv_sec_hi, tv_sec_lo, tv_nsec);
+            }
+            3 => state.failed(wire_msg.sender_id()),
+            e => {
+                log::error!("unrecognized event opcode: {e} for interface zwlr_screencopy_frame_v1")
+            }
+        }
+    }
+
+    ///Requests for this interface
+    pub mod req {
+        use super::*;
+        ///copy the frame
+        ///
+        ///Copy the frame to the supplied buffer. The buffer must have the
+        ///correct size, see zwlr_screencopy_frame_v1.buffer. The buffer needs to
+        ///have a supported format.
+        ///
+        ///If the frame is successfully copied, "flags" and "ready" events are
+        ///sent. Otherwise, a "failed" event is sent.
+        pub fn copy(sender_id: ObjectId, buffer: ObjectId) -> rustix::io::Result<()> {
+            let mut wire_msg_builder = WireMsgBuilder::new(sender_id, 0);
+            wire_msg_builder.add_object(Some(buffer));
+            wire_msg_builder.send()
+        }
+        ///delete this object, used or not
+        ///
+        ///Destroys the frame. This request can be sent at any time by the client.
+        ///
+        ///THIS IS A DESTRUCTOR
+        pub fn destroy(sender_id: ObjectId) -> rustix::io::Result<()> {
+            let wire_msg_builder = WireMsgBuilder::new(sender_id, 1);
+            wire_msg_builder.send()
+        }
+    }
+    pub mod error {
+        ///the object has already been used to copy a wl_buffer
+        pub const ALREADY_USED: u32 = 0u32;
+        ///buffer attributes are invalid
+        pub const INVALID_BUFFER: u32 = 1u32;
+    }
+    pub mod flags {
+        ///contents are y-inverted
+        pub const Y_INVERT: u32 = 1u32;
+    }
+}
+///control idle notifications
+///
+///This interface allows clients to monitor user idle status.
+///
+///After binding to this global, clients can create ext_idle_notification_v1
+///objects to get notified when the user is idle for a given amount of time.
+pub mod ext_idle_notifier_v1 {
+    use super::*;
+
+    ///Events for this interface
+    pub mod ev {}
+    ///Requests for this interface
+    pub mod req {
+        use super::*;
+        ///destroy the manager
+        ///
+        ///Destroy the manager object. All objects created via this interface
+        ///remain valid.
+        ///
+        ///THIS IS A DESTRUCTOR
+        pub fn destroy(sender_id: ObjectId) -> rustix::io::Result<()> {
+            let wire_msg_builder = WireMsgBuilder::new(sender_id, 0);
+            wire_msg_builder.send()
+        }
+        ///create a notification object
+        ///
+        ///Create a new idle notification object.
+        ///
+        ///The notification object has a minimum timeout duration and is tied to a
+        ///seat. The client will be notified if the seat is inactive for at least
+        ///the provided timeout. See ext_idle_notification_v1 for more details.
+        ///
+        ///A zero timeout is valid and means the client wants to be notified as
+        ///soon as possible when the seat is inactive.
+        pub fn get_idle_notification(
+            sender_id: ObjectId,
+            id: ObjectId,
+            timeout: u32,
+            seat: ObjectId,
+        ) -> rustix::io::Result<()> {
+            let mut wire_msg_builder = WireMsgBuilder::new(sender_id, 1);
+            wire_msg_builder.add_new_specified_id(id);
+            wire_msg_builder.add_u32(timeout);
+            wire_msg_builder.add_object(Some(seat));
+            wire_msg_builder.send()
+        }
+    }
+}
+///idle notification
+///
+///This interface is used by the compositor to send idle notification events
+///to clients.
+///
+///Initially the notification object is not idle. The notification object
+///becomes idle when no user activity has happened for at least the timeout
+///duration, starting from the creation of the notification object. User
+///activity may include input events or a presence sensor, but is
+///compositor-specific. If an idle inhibitor is active (e.g. another client
+///has created a zwp_idle_inhibitor_v1 on a visible surface), the notification
+///object cannot become idle.
+///
+///When the notification object becomes idle, an idled event is sent. When
+///user activity starts again, the notification object stops being idle,
+///a resumed event is sent and the timeout is restarted.
+pub mod ext_idle_notification_v1 {
+    use super::*;
+
+    pub trait EvHandler {
+        ///notification object is idle
+        ///
+        ///This event is sent when the notification object becomes idle.
+        ///
+        ///It's a compositor protocol error to send this event twice without a
+        ///resumed event in-between.
+        fn idled(&mut self, sender_id: ObjectId);
+        ///notification object is no longer idle
+        ///
+        ///This event is sent when the notification object stops being idle.
+        ///
+        ///It's a compositor protocol error to send this event twice without an
+        ///idled event in-between. It's a compositor protocol error to send this
+        ///event prior to any idled event.
+        fn resumed(&mut self, sender_id: ObjectId);
+    }
+
+    pub fn event<T: EvHandler>(state: &mut T, wire_msg: WireMsg, _payload: WaylandPayload) {
+        match wire_msg.op() {
+            0 => state.idled(wire_msg.sender_id()),
+            1 => state.resumed(wire_msg.sender_id()),
+            e => {
+                log::error!("unrecognized event opcode: {e} for interface ext_idle_notification_v1")
+            }
+        }
+    }
+
+    ///Requests for this interface
+    pub mod req {
+        use super::*;
+        ///destroy the notification object
+        ///
+        ///Destroy the notification object.
+        ///
+        ///THIS IS A DESTRUCTOR
+        pub fn destroy(sender_id: ObjectId) -> rustix::io::Result<()> {
+            let wire_msg_builder = WireMsgBuilder::new(sender_id, 0);
+            wire_msg_builder.send()
+        }
+    }
+}
+///list and control workspaces
+///
+///Workspaces, also called virtual desktops, are groups of surfaces. A
+///compositor with a concept of workspaces may only show some such groups of
+///surfaces (those of 'active' workspaces) at a time.
+///
+///This global advertises the workspace groups and workspaces the compositor
+///has, through a series of events followed by a done event, that marks the
+///end of each atomic set of changes.
+pub mod ext_workspace_manager_v1 {
+    use super::*;
+
+    pub trait EvHandler {
+        ///a workspace_group has been created
+        ///
+        ///This event is emitted whenever a new workspace group has been created.
+        ///
+        ///All initial details of the workspace group (outputs) will be
+        ///sent immediately after this event via the corresponding events in
+        ///ext_workspace_group_handle_v1 and ext_workspace_handle_v1.
+        fn workspace_group(&mut self, sender_id: ObjectId, workspace_group: ObjectId);
+        ///workspace has been created
+        ///
+        ///This event is emitted whenever a new workspace has been created.
+        ///
+        ///All initial details of the workspace (name, coordinates, state) will
+        ///be sent immediately after this event via the corresponding events in
+        ///ext_workspace_handle_v1.
+        ///
+        ///Workspaces start off unassigned to any workspace group.
+        fn workspace(&mut self, sender_id: ObjectId, workspace: ObjectId);
+        ///all information about the workspaces and workspace groups has been sent
+        ///
+        ///This event is sent after all changes in all workspaces and workspace groups
+        ///have been sent.
+        ///
+        ///This allows changes to one or more ext_workspace_group_handle_v1
+        ///properties and ext_workspace_handle_v1 properties
+        ///to be seen as atomic, even if they happen via multiple events.
+        ///In particular, an output moving from one workspace group to
+        ///another sends an output_enter event and an output_leave event to the two
+        ///ext_workspace_group_handle_v1 objects in question. The compositor sends
+        ///the done event only after updating the output information in both
+        ///workspace groups.
+        fn done(&mut self, sender_id: ObjectId);
+        ///the compositor has finished with the workspace_manager
+        ///
+        ///This event indicates that the compositor is done sending events to the
+        ///ext_workspace_manager_v1. The server will destroy the object
+        ///immediately after sending this request.
+        fn finished(&mut self, sender_id: ObjectId);
+    }
+
+    pub fn event<T: EvHandler>(state: &mut T, mut wire_msg: WireMsg, payload: WaylandPayload) {
+        match wire_msg.op() {
+            0 => {
+                let workspace_group = wire_msg.next_new_specified_id(&payload);
+                state.workspace_group(wire_msg.sender_id(), workspace_group);
+            }
+            1 => {
+                let workspace = wire_msg.next_new_specified_id(&payload);
+                state.workspace(wire_msg.sender_id(), workspace);
+            }
+            2 => state.done(wire_msg.sender_id()),
+            3 => state.finished(wire_msg.sender_id()),
+            e => {
+                log::error!("unrecognized event opcode: {e} for interface ext_workspace_manager_v1")
+            }
+        }
+    }
+
+    ///Requests for this interface
+    pub mod req {
+        use super::*;
+        ///all requests about the workspaces have been sent
+        ///
+        ///The client must send this request after it has finished sending other
+        ///requests. The compositor must process a series of requests preceding a
+        ///commit request atomically.
+        pub fn commit(sender_id: ObjectId) -> rustix::io::Result<()> {
+            let wire_msg_builder = WireMsgBuilder::new(sender_id, 0);
+            wire_msg_builder.send()
+        }
+        ///stop sending events
+        ///
+        ///Indicates the client no longer wishes to receive events for new
+        ///workspace groups. However the compositor may emit further workspace
+        ///events, until the finished event is emitted. The compositor is expected
+        ///to send the finished event eventually once the stop request has been
+        ///processed.
+        pub fn stop(sender_id: ObjectId) -> rustix::io::Result<()> {
+            let wire_msg_builder = WireMsgBuilder::new(sender_id, 1);
+            wire_msg_builder.send()
+        }
+    }
+}
+///a workspace group assigned to a set of outputs
+///
+///A ext_workspace_group_handle_v1 object represents a workspace group
+///that is assigned a set of outputs and contains a number of workspaces.
+///
+///The set of outputs assigned to the workspace group is conveyed to the client
+///via output_enter and output_leave events, and its workspaces are conveyed with
+///workspace events.
+pub mod ext_workspace_group_handle_v1 {
+    use super::*;
+
+    pub trait EvHandler {
+        ///compositor capabilities
+        ///
+        ///This event advertises the capabilities supported by the compositor. If
+        ///a capability isn't supported, clients should hide or disable the UI
+        ///elements that expose this functionality.
+        fn capabilities(&mut self, sender_id: ObjectId, capabilities: u32);
+        ///output assigned to workspace group
+        ///
+        ///This event is emitted whenever an output is assigned to the workspace
+        ///group or a new `wl_output` object is bound by the client, which was
+        ///already assigned to this workspace_group.
+        fn output_enter(&mut self, sender_id: ObjectId, output: ObjectId);
+        ///output removed from workspace group
+        ///
+        ///This event is emitted whenever an output is removed from the workspace
+        ///group.
+        fn output_leave(&mut self, sender_id: ObjectId, output: ObjectId);
+        ///workspace added to workspace group
+        ///
+        ///This event is emitted whenever a workspace is assigned to this group.
+        ///A workspace may only ever be assigned to a single group at a single point
+        ///in time, but can be re-assigned during it's lifetime.
+        fn workspace_enter(&mut self, sender_id: ObjectId, workspace: ObjectId);
+        ///workspace removed from workspace group
+        ///
+        ///This event is emitted whenever a workspace is removed from this group.
+        fn workspace_leave(&mut self, sender_id: ObjectId, workspace: ObjectId);
+        ///this workspace group has been removed
+        ///
+        ///This event is send when the group associated with the ext_workspace_group_handle_v1
+        ///has been removed. After sending this request the compositor will immediately consider
+        ///the object inert. Any requests will be ignored except the destroy request.
+        fn removed(&mut self, sender_id: ObjectId);
+    }
+
+    pub fn event<T: EvHandler>(state: &mut T, mut wire_msg: WireMsg, payload: WaylandPayload) {
+        match wire_msg.op() {
+            0 => {
+                let capabilities = wire_msg.next_u32(&payload);
+                state.capabilities(wire_msg.sender_id(), capabilities);
+            }
+            1..=4 => {
+                let Some(object) = wire_msg.next_object(&payload) else {
+                    log::error!("ext_workspace_group_handle_v1 event with a null object");
+                    return;
+                };
+                match wire_msg.op() {
+                    1 => state.output_enter(wire_msg.sender_id(), object),
+                    2 => state.output_leave(wire_msg.sender_id(), object),
+                    3 => state.workspace_enter(wire_msg.sender_id(), object),
+                    _ => state.workspace_leave(wire_msg.sender_id(), object),
+                }
+            }
+            5 => state.removed(wire_msg.sender_id()),
+            e => log::error!(
+                "unrecognized event opcode: {e} for interface ext_workspace_group_handle_v1"
+            ),
+        }
+    }
+
+    ///Requests for this interface
+    pub mod req {
+        use super::*;
+        ///create a new workspace
+        ///
+        ///Request that the compositor create a new workspace with the given name
+        ///and assign it to this group.
+        ///
+        ///There is no guarantee that the compositor will create a new workspace,
+        ///or that the created workspace will have the provided name.
+        pub fn create_workspace(sender_id: ObjectId, workspace: &str) -> rustix::io::Result<()> {
+            let mut wire_msg_builder = WireMsgBuilder::new(sender_id, 0);
+            wire_msg_builder.add_string(workspace);
+            wire_msg_builder.send()
+        }
+        ///destroy the ext_workspace_group_handle_v1 object
+        ///
+        ///Destroys the ext_workspace_group_handle_v1 object.
+        ///
+        ///This request should be send either when the client does not want to
+        ///use the workspace group object any more or after the removed event to finalize
+        ///the destruction of the object.
+        ///
+        ///THIS IS A DESTRUCTOR
+        pub fn destroy(sender_id: ObjectId) -> rustix::io::Result<()> {
+            let wire_msg_builder = WireMsgBuilder::new(sender_id, 1);
+            wire_msg_builder.send()
+        }
+    }
+}
+///a workspace handing a group of surfaces
+///
+///A ext_workspace_handle_v1 object represents a workspace that handles a
+///group of surfaces.
+///
+///Each workspace has:
+///- a name, conveyed to the client with the name event
+///- potentially an id conveyed with the id event
+///- a list of states, conveyed to the client with the state event
+///- and optionally a set of coordinates, conveyed to the client with the
+///coordinates event
+///
+///The client may request that the compositor activate or deactivate the workspace.
+pub mod ext_workspace_handle_v1 {
+    use super::*;
+
+    pub trait EvHandler {
+        ///workspace id
+        ///
+        ///If this event is emitted, it will be send immediately after the
+        ///ext_workspace_handle_v1 is created or when an id is assigned to
+        ///a workspace (at most once during it's lifetime).
+        ///
+        ///An id will never change during the lifetime of the `ext_workspace_handle_v1`
+        ///and is guaranteed to be unique during it's lifetime.
+        fn id(&mut self, sender_id: ObjectId, id: &str);
+        ///workspace name changed
+        ///
+        ///This event is emitted immediately after the ext_workspace_handle_v1 is
+        ///created and whenever the name of the workspace changes.
+        ///
+        ///A name is meant to be human-readable and can be displayed to a user.
+        ///Unlike the id it is neither stable nor unique.
+        fn name(&mut self, sender_id: ObjectId, name: &str);
+        ///workspace coordinates changed
+        ///
+        ///This event is used to organize workspaces into an N-dimensional grid
+        ///within a workspace group, and if supported, is emitted immediately after
+        ///the ext_workspace_handle_v1 is created and whenever the coordinates of
+        ///the workspace change.
+        fn coordinates(&mut self, sender_id: ObjectId, coordinates: &[u8]);
+        ///the state of the workspace changed
+        ///
+        ///This event is emitted immediately after the ext_workspace_handle_v1 is
+        ///created and each time the workspace state changes, either because of a
+        ///compositor action or because of a request in this protocol.
+        ///
+        ///Missing states convey the opposite meaning, e.g. an unset active bit
+        ///means the workspace is currently inactive.
+        fn state(&mut self, sender_id: ObjectId, state: u32);
+        ///compositor capabilities
+        ///
+        ///This event advertises the capabilities supported by the compositor. If
+        ///a capability isn't supported, clients should hide or disable the UI
+        ///elements that expose this functionality.
+        fn capabilities(&mut self, sender_id: ObjectId, capabilities: u32);
+        ///this workspace has been removed
+        ///
+        ///This event is send when the workspace associated with the ext_workspace_handle_v1
+        ///has been removed. After sending this request, the compositor will immediately consider
+        ///the object inert. Any requests will be ignored except the destroy request.
+        fn removed(&mut self, sender_id: ObjectId);
+    }
+
+    pub fn event<T: EvHandler>(state: &mut T, mut wire_msg: WireMsg, payload: WaylandPayload) {
+        match wire_msg.op() {
+            0 => {
+                let id = wire_msg.next_string(&payload);
+                state.id(wire_msg.sender_id(), id);
+            }
+            1 => {
+                let name = wire_msg.next_string(&payload);
+                state.name(wire_msg.sender_id(), name);
+            }
+            2 => {
+                let coordinates = wire_msg.next_array(&payload);
+                state.coordinates(wire_msg.sender_id(), coordinates);
+            }
+            3 => {
+                let workspace_state = wire_msg.next_u32(&payload);
+                state.state(wire_msg.sender_id(), workspace_state);
+            }
+            4 => {
+                let capabilities = wire_msg.next_u32(&payload);
+                state.capabilities(wire_msg.sender_id(), capabilities);
+            }
+            5 => state.removed(wire_msg.sender_id()),
+            e => {
+                log::error!("unrecognized event opcode: {e} for interface ext_workspace_handle_v1")
+            }
+        }
+    }
+
+    ///Requests for this interface
+    pub mod req {
+        use super::*;
+        ///destroy the ext_workspace_handle_v1 object
+        ///
+        ///Destroys the ext_workspace_handle_v1 object.
+        ///
+        ///This request should be made either when the client does not want to
+        ///use the workspace object any more or after the remove event to finalize
+        ///the destruction of the object.
+        ///
+        ///THIS IS A DESTRUCTOR
+        pub fn destroy(sender_id: ObjectId) -> rustix::io::Result<()> {
+            let wire_msg_builder = WireMsgBuilder::new(sender_id, 0);
+            wire_msg_builder.send()
+        }
+    }
+    pub mod state {
+        ///the workspace is active
+        pub const ACTIVE: u32 = 1u32;
+        ///the workspace requests attention
+        pub const URGENT: u32 = 2u32;
+        ///the workspace is not visible
+        pub const HIDDEN: u32 = 4u32;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn protocol_error_names() {
+        use wl_display::error_name;
+        assert_eq!(
+            error_name("wl_surface", wl_surface::error::INVALID_SIZE),
+            Some("invalid_size")
+        );
+        assert_eq!(
+            error_name(
+                "zwlr_layer_surface_v1",
+                zwlr_layer_surface_v1::error::INVALID_ANCHOR
+            ),
+            Some("invalid_anchor")
+        );
+        assert_eq!(
+            error_name("wl_buffer", wl_display::error::INVALID_METHOD),
+            Some("invalid_method")
+        );
+        assert_eq!(error_name("wp_viewporter", 1), None);
+    }
+}
@@ -46,17 +46,27 @@ impl ObjectId {
     }
 }
 
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum WlDynObj {
     Output,
     Surface,
     Region,
     LayerSurface,
+    /// Only used without zwlr_layer_shell_v1, along with `XdgToplevel`
+    XdgSurface,
+    XdgToplevel,
     Buffer,
     ShmPool,
     Callback,
     Viewport,
     FractionalScale,
+    /// Only created when resetting the daemon, since the ones we use normally are globals
+    Registry,
+    Shm,
+    ScreencopyFrame,
+    IdleNotification,
+    /// Only bound once a client sets a wallpaper for a workspace
+    WorkspaceManager,
 }
 
 /// Object Manager for creating, removing, and maintaining Wayland Objects
@@ -64,8 +74,12 @@ pub struct ObjectManager {
     /// stores the object types. The position in this vector + the base offset is the object id
     /// for example, if objects[0] == LayerSurface, then the object of id 0 + BASE_OFFSET = 7 is of
     /// the type "LayerSurface"
+    ///
+    /// Removed objects leave a `None` behind, which `create` reuses, lowest first. Trailing `None`s
+    /// are dropped, so this only grows as much as the number of objects alive at once
     objects: Vec<Option<WlDynObj>>,
-    /// the next id we ought to generate
+    /// the next id we ought to generate: the position of the first `None` in `objects`, or its
+    /// length if there is none
     next: u32,
 }
 
@@ -73,6 +87,15 @@ impl ObjectManager {
     /// Ids 1-6, inclusive, are all already taken by the globals in `globals.rs`
     const BASE_OFFSET: u32 = 7;
 
+    /// Optional globals take the ids right after `BASE_OFFSET`, so we must skip them
+    fn offset() -> u32 {
+        Self::BASE_OFFSET
+            + globals::fractional_scale_support() as u32
+            + globals::single_pixel_buffer_support() as u32
+            + globals::screencopy_support() as u32
+            + 2 * globals::idle_notify_support() as u32
+    }
+
     pub const fn new() -> Self {
         Self {
             objects: Vec::new(),
@@ -84,18 +107,17 @@ impl ObjectManager {
     ///
     /// Returns
     ///   * 'Some(WlDynObj)' if the object still exists
-    ///   * 'None' if the object was already deleted
+    ///   * 'None' if the object was already deleted, or was never created by us
     #[must_use]
     pub fn get(&self, object_id: ObjectId) -> Option<WlDynObj> {
-        let offset = Self::BASE_OFFSET + globals::fractional_scale_support() as u32;
-        let pos = object_id.get() - offset;
-        self.objects[pos as usize]
+        let pos = object_id.get().checked_sub(Self::offset())?;
+        self.objects.get(pos as usize).copied().flatten()
     }
 
     /// creates a new Id to use in requests
     #[must_use]
     pub fn create(&mut self, object: WlDynObj) -> ObjectId {
-        let offset = Self::BASE_OFFSET + globals::fractional_scale_support() as u32;
+        let offset = Self::offset();
         if self.next as usize == self.objects.len() {
             self.next += 1;
             self.objects.push(Some(object));
@@ -122,12 +144,21 @@ impl ObjectManager {
     /// Removing the same element twice currently works just fine and does not panic,
     /// but that may change in the future
     pub fn remove(&mut self, object_id: ObjectId) {
-        let offset = Self::BASE_OFFSET + globals::fractional_scale_support() as u32;
-        let pos = object_id.get() - offset;
-        self.objects[pos as usize] = None;
+        let offset = Self::offset();
+        let Some(pos) = object_id.get().checked_sub(offset) else {
+            return;
+        };
+        match self.objects.get_mut(pos as usize) {
+            Some(object) => *object = None,
+            None => return,
+        }
         if pos < self.next {
             self.next = pos;
         }
+        // `next` can never point past the `None`s we drop here, since it is the first of them
+        while self.objects.last().is_some_and(Option::is_none) {
+            self.objects.pop();
+        }
     }
 }
 
@@ -166,4 +197,51 @@ mod tests {
         let id7 = manager.create(WlDynObj::Region);
         assert_eq!(id7, id2);
     }
+
+    #[test]
+    fn object_ids_stay_compact() {
+        let mut manager = ObjectManager::new();
+        // like a long running transition, creating and destroying a buffer every frame
+        let surface = manager.create(WlDynObj::Surface);
+        for _ in 0..1000 {
+            let buffer = manager.create(WlDynObj::Buffer);
+            let callback = manager.create(WlDynObj::Callback);
+            assert!(buffer.get() < ObjectManager::BASE_OFFSET + 3);
+            assert!(callback.get() < ObjectManager::BASE_OFFSET + 3);
+            manager.remove(buffer);
+            manager.remove(callback);
+        }
+        assert_eq!(manager.objects.len(), 1);
+        assert_eq!(manager.get(surface), Some(WlDynObj::Surface));
+
+        manager.remove(surface);
+        assert!(manager.objects.is_empty());
+        assert_eq!(manager.create(WlDynObj::Region), surface);
+    }
+
+    #[test]
+    fn object_types_are_correct_after_reuse() {
+        let mut manager = ObjectManager::new();
+        let output = manager.create(WlDynObj::Output);
+        let surface = manager.create(WlDynObj::Surface);
+        let buffer = manager.create(WlDynObj::Buffer);
+
+        manager.remove(surface);
+        assert_eq!(manager.get(surface), None);
+        let callback = manager.create(WlDynObj::Callback);
+        assert_eq!(callback, surface);
+        assert_eq!(manager.get(callback), Some(WlDynObj::Callback));
+        assert_eq!(manager.get(output), Some(WlDynObj::Output));
+        assert_eq!(manager.get(buffer), Some(WlDynObj::Buffer));
+
+        manager.remove(buffer);
+        // removed trailing ids are forgotten, rather than kept around as `None`
+        assert_eq!(manager.get(buffer), None);
+        assert_eq!(manager.get(obj_from_u32(1000)), None);
+        assert_eq!(manager.get(obj_from_u32(1)), None);
+        // removing an id twice, or one we never created, does nothing
+        manager.remove(buffer);
+        manager.remove(obj_from_u32(1000));
+        assert_eq!(manager.create(WlDynObj::Region), buffer);
+    }
 }
@@ -1,15 +1,15 @@
 //! Implementation of the Wayland Wire Protocol
 //!
 //! There are some things that are specific for `swww-daemon` (for example, our ancillary buffer
-//! for receiving socket messages is always empty, since none of the events we care about have file
-//! descriptors), but I tried to actually make it fairly complete. This means types like `WlFixed`
-//! exist even if they aren't used at all in the rest of the codebase.
+//! for receiving socket messages is empty on our main connection, since none of the events we care
+//! about there have file descriptors), but I tried to actually make it fairly complete. This means
+//! types like `WlFixed` exist even if they aren't used at all in the rest of the codebase.
 
 use rustix::{
     fd::{AsRawFd, BorrowedFd, OwnedFd},
     io, net,
 };
-use std::num::NonZeroU32;
+use std::{collections::VecDeque, num::NonZeroU32};
 
 use super::{globals::wayland_fd, ObjectId};
 
@@ -49,21 +49,54 @@ impl WaylandPayload {
 
 impl WireMsg {
     pub fn recv() -> rustix::io::Result<(Self, WaylandPayload)> {
+        Self::recv_from(wayland_fd())
+    }
+
+    /// Like `recv`, but from a connection other than our main one
+    pub fn recv_from(fd: BorrowedFd) -> rustix::io::Result<(Self, WaylandPayload)> {
+        Self::recv_inner(fd, None)
+    }
+
+    /// Like `recv_from`, but keeps the file descriptors that arrive, in order, in `fds`. They do
+    /// not necessarily belong to this message: the compositor sends them along with whole batches
+    /// of messages, so they belong to the first messages (from now on) that carry any
+    pub fn recv_with_fds_from(
+        fd: BorrowedFd,
+        fds: &mut VecDeque<OwnedFd>,
+    ) -> rustix::io::Result<(Self, WaylandPayload)> {
+        Self::recv_inner(fd, Some(fds))
+    }
+
+    fn recv_inner(
+        fd: BorrowedFd,
+        mut received_fds: Option<&mut VecDeque<OwnedFd>>,
+    ) -> rustix::io::Result<(Self, WaylandPayload)> {
         let fds = Vec::new();
 
         let mut header_buf = [0u32; 2];
 
-        // we don't need these because no events we care about send file descriptors
-        let mut ancillary_buf = [0; 0];
-        let mut control = net::RecvAncillaryBuffer::new(i32_slice_to_u8_mut(&mut ancillary_buf));
+        // most of the time, we don't need these because no events we care about send file
+        // descriptors
+        let mut ancillary_buf = [0u8; rustix::cmsg_space!(ScmRights(4))];
+        let ancillary_len = if received_fds.is_some() {
+            ancillary_buf.len()
+        } else {
+            0
+        };
+        let mut control = net::RecvAncillaryBuffer::new(&mut ancillary_buf[..ancillary_len]);
+        let mut keep_fds = |control: &mut net::RecvAncillaryBuffer| {
+            if let Some(received_fds) = received_fds.as_deref_mut() {
+                for msg in control.drain() {
+                    if let net::RecvAncillaryMessage::ScmRights(fds) = msg {
+                        received_fds.extend(fds);
+                    }
+                }
+            }
+        };
 
         let iov = io::IoSliceMut::new(u32_slice_to_u8_mut(&mut header_buf));
-        net::recvmsg(
-            wayland_fd(),
-            &mut [iov],
-            &mut control,
-            net::RecvFlags::empty(),
-        )?;
+        net::recvmsg(fd, &mut [iov], &mut control, net::RecvFlags::empty())?;
+        keep_fds(&mut control);
 
         let sender_id = ObjectId(
             NonZeroU32::new(header_buf[0])
@@ -79,13 +112,9 @@ impl WireMsg {
             // be left in an inconsistent state (a message without a header)
             rustix::io::retry_on_intr(|| {
                 let iov = io::IoSliceMut::new(u32_slice_to_u8_mut(&mut payload));
-                net::recvmsg(
-                    wayland_fd(),
-                    &mut [iov],
-                    &mut control,
-                    net::RecvFlags::WAITALL,
-                )
+                net::recvmsg(fd, &mut [iov], &mut control, net::RecvFlags::WAITALL)
             })?;
+            keep_fds(&mut control);
         }
 
         Ok((
@@ -240,6 +269,11 @@ impl WireMsgBuilder {
     }
 
     pub fn send(self) -> rustix::io::Result<()> {
+        self.send_to(wayland_fd())
+    }
+
+    /// Like `send`, but through a connection other than our main one
+    pub fn send_to(self, socket: BorrowedFd) -> rustix::io::Result<()> {
         let Self { mut msg, fds } = self;
         let len = msg.len() << 2;
         // put the correct length in the upper part of the header's second word
@@ -249,18 +283,26 @@ impl WireMsgBuilder {
         for fd in fds {
             borrowed_fds.push(unsafe { BorrowedFd::borrow_raw(fd) });
         }
-        unsafe { send_unchecked(u32_slice_to_u8(&msg), &borrowed_fds) }
+        unsafe { send_unchecked_to(socket, u32_slice_to_u8(&msg), &borrowed_fds) }
     }
 }
 
 /// try to send a raw message through the wayland socket. We do no input validation whatsoever
 pub unsafe fn send_unchecked(msg: &[u8], fds: &[BorrowedFd]) -> rustix::io::Result<()> {
+    send_unchecked_to(wayland_fd(), msg, fds)
+}
+
+unsafe fn send_unchecked_to(
+    socket: BorrowedFd,
+    msg: &[u8],
+    fds: &[BorrowedFd],
+) -> rustix::io::Result<()> {
     let iov = io::IoSlice::new(msg);
     let mut control_buf = [0u8; rustix::cmsg_space!(ScmRights(1))];
     let mut control = net::SendAncillaryBuffer::new(&mut control_buf);
     let msg = net::SendAncillaryMessage::ScmRights(fds);
     control.push(msg);
-    net::sendmsg(wayland_fd(), &[iov], &mut control, net::SendFlags::NOSIGNAL).map(|_| ())
+    net::sendmsg(socket, &[iov], &mut control, net::SendFlags::NOSIGNAL).map(|_| ())
 }
 
 impl<'a> WlSlice<'a> {
@@ -408,11 +450,6 @@ fn u32_slice_to_u8_mut(src: &mut [u32]) -> &mut [u8] {
     unsafe { std::slice::from_raw_parts_mut(src.as_mut_ptr() as *mut u8, len) }
 }
 
-fn i32_slice_to_u8_mut(src: &mut [i32]) -> &mut [u8] {
-    let len = src.len() << 2;
-    unsafe { std::slice::from_raw_parts_mut(src.as_mut_ptr() as *mut u8, len) }
-}
-
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -0,0 +1,228 @@
+//! `swww img --workspace`: displays an image whenever a workspace becomes active, through
+//! `ext_workspace_manager_v1`.
+//!
+//! Layer surfaces are not part of any workspace, so we follow which workspaces are active
+//! instead, and run `swww` with the arguments set for a workspace (like we do for sticky images)
+//! on the outputs of its group whenever it becomes active. We only bind the workspace manager once
+//! a client sets an image for a workspace.
+
+use log::{debug, error};
+
+use crate::wayland::{
+    interfaces::{ext_workspace_group_handle_v1, ext_workspace_handle_v1},
+    ObjectId,
+};
+
+/// The arguments to run `swww` with, and the outputs to run it for
+pub(crate) type Activation = (Box<[String]>, Vec<ObjectId>);
+
+struct Group {
+    id: ObjectId,
+    outputs: Vec<ObjectId>,
+    workspaces: Vec<ObjectId>,
+}
+
+struct Workspace {
+    id: ObjectId,
+    name: String,
+    active: bool,
+    /// whether it was active at the end of the last atomic set of changes
+    was_active: bool,
+}
+
+pub(crate) struct Workspaces {
+    groups: Vec<Group>,
+    workspaces: Vec<Workspace>,
+    /// the arguments set with `swww img --workspace`, by workspace name
+    images: Vec<(String, Box<[String]>)>,
+}
+
+impl Workspaces {
+    pub(crate) fn new() -> Self {
+        Self {
+            groups: Vec::new(),
+            workspaces: Vec::new(),
+            images: Vec::new(),
+        }
+    }
+
+    pub(crate) fn is_group(&self, id: ObjectId) -> bool {
+        self.groups.iter().any(|g| g.id == id)
+    }
+
+    pub(crate) fn is_workspace(&self, id: ObjectId) -> bool {
+        self.workspaces.iter().any(|w| w.id == id)
+    }
+
+    /// Sets the arguments to display an image with when workspace `name` becomes active, or
+    /// forgets them if `args` is empty. Returns what to display right away, if it is active now
+    pub(crate) fn set_image(&mut self, name: String, args: Box<[String]>) -> Vec<Activation> {
+        self.images.retain(|(n, _)| *n != name);
+        if args.is_empty() {
+            return Vec::new();
+        }
+        let activations = self
+            .workspaces
+            .iter()
+            .filter(|w| w.was_active && w.name == name)
+            .filter_map(|w| self.activation(w.id, &args))
+            .collect();
+        self.images.push((name, args));
+        activations
+    }
+
+    pub(crate) fn new_group(&mut self, id: ObjectId) {
+        self.groups.push(Group {
+            id,
+            outputs: Vec::new(),
+            workspaces: Vec::new(),
+        });
+    }
+
+    pub(crate) fn new_workspace(&mut self, id: ObjectId) {
+        self.workspaces.push(Workspace {
+            id,
+            name: String::new(),
+            active: false,
+            was_active: false,
+        });
+    }
+
+    fn group_mut(&mut self, id: ObjectId) -> Option<&mut Group> {
+        self.groups.iter_mut().find(|g| g.id == id)
+    }
+
+    fn workspace_mut(&mut self, id: ObjectId) -> Option<&mut Workspace> {
+        self.workspaces.iter_mut().find(|w| w.id == id)
+    }
+
+    pub(crate) fn output_enter(&mut self, group: ObjectId, output: ObjectId) {
+        if let Some(group) = self.group_mut(group) {
+            group.outputs.push(output);
+        }
+    }
+
+    pub(crate) fn output_leave(&mut self, group: ObjectId, output: ObjectId) {
+        if let Some(group) = self.group_mut(group) {
+            group.outputs.retain(|&o| o != output);
+        }
+    }
+
+    pub(crate) fn workspace_enter(&mut self, group: ObjectId, workspace: ObjectId) {
+        if let Some(group) = self.group_mut(group) {
+            group.workspaces.push(workspace);
+        }
+    }
+
+    pub(crate) fn workspace_leave(&mut self, group: ObjectId, workspace: ObjectId) {
+        if let Some(group) = self.group_mut(group) {
+            group.workspaces.retain(|&w| w != workspace);
+        }
+    }
+
+    pub(crate) fn group_removed(&mut self, group: ObjectId) {
+        self.groups.retain(|g| g.id != group);
+        if let Err(e) = ext_workspace_group_handle_v1::req::destroy(group) {
+            error!("failed to destroy ext_workspace_group_handle_v1: {e}");
+        }
+    }
+
+    pub(crate) fn set_name(&mut self, workspace: ObjectId, name: &str) {
+        if let Some(workspace) = self.workspace_mut(workspace) {
+            workspace.name = name.to_string();
+        }
+    }
+
+    pub(crate) fn set_state(&mut self, workspace: ObjectId, state: u32) {
+        if let Some(workspace) = self.workspace_mut(workspace) {
+            workspace.active = state & ext_workspace_handle_v1::state::ACTIVE != 0;
+        }
+    }
+
+    pub(crate) fn workspace_removed(&mut self, workspace: ObjectId) {
+        self.workspaces.retain(|w| w.id != workspace);
+        for group in self.groups.iter_mut() {
+            group.workspaces.retain(|&w| w != workspace);
+        }
+        if let Err(e) = ext_workspace_handle_v1::req::destroy(workspace) {
+            error!("failed to destroy ext_workspace_handle_v1: {e}");
+        }
+    }
+
+    /// Called at the end of each atomic set of changes. Returns what to display for the
+    /// workspaces that became active since the last one
+    pub(crate) fn done(&mut self) -> Vec<Activation> {
+        let mut activations = Vec::new();
+        for workspace in self.workspaces.iter() {
+            if !workspace.active || workspace.was_active {
+                continue;
+            }
+            debug!("workspace {:?} became active", workspace.name);
+            if let Some((_, args)) = self.images.iter().find(|(n, _)| *n == workspace.name) {
+                activations.extend(self.activation(workspace.id, args));
+            }
+        }
+        for workspace in self.workspaces.iter_mut() {
+            workspace.was_active = workspace.active;
+        }
+        activations
+    }
+
+    /// Displaying `args` on the outputs of the group `workspace` is in, if any
+    fn activation(&self, workspace: ObjectId, args: &[String]) -> Option<Activation> {
+        let group = self
+            .groups
+            .iter()
+            .find(|g| g.workspaces.contains(&workspace))?;
+        (!group.outputs.is_empty()).then(|| (args.into(), group.outputs.clone()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::num::NonZeroU32;
+
+    fn id(id: u32) -> ObjectId {
+        ObjectId::new(NonZeroU32::new(id).unwrap())
+    }
+
+    fn args(image: &str) -> Box<[String]> {
+        Box::new(["img".to_string(), image.to_string()])
+    }
+
+    #[test]
+    fn images_follow_the_active_workspace_of_each_group() {
+        let mut workspaces = Workspaces::new();
+        let (group, output) = (id(0xff00_0000), id(20));
+        workspaces.new_group(group);
+        workspaces.output_enter(group, output);
+        for (i, name) in [(1, "1"), (2, "2")] {
+            workspaces.new_workspace(id(0xff00_0000 + i));
+            workspaces.set_name(id(0xff00_0000 + i), name);
+            workspaces.workspace_enter(group, id(0xff00_0000 + i));
+        }
+        workspaces.set_state(id(0xff00_0001), ext_workspace_handle_v1::state::ACTIVE);
+        assert!(workspaces.done().is_empty());
+
+        // workspace 1 is already active, so we display its image right away
+        assert_eq!(
+            workspaces.set_image("1".to_string(), args("a.png")),
+            [(args("a.png"), vec![output])]
+        );
+        assert!(workspaces
+            .set_image("2".to_string(), args("b.png"))
+            .is_empty());
+
+        workspaces.set_state(id(0xff00_0001), 0);
+        workspaces.set_state(id(0xff00_0002), ext_workspace_handle_v1::state::ACTIVE);
+        assert_eq!(workspaces.done(), [(args("b.png"), vec![output])]);
+        // nothing changed
+        assert!(workspaces.done().is_empty());
+
+        workspaces.set_image("1".to_string(), Box::new([]));
+        workspaces.set_state(id(0xff00_0001), ext_workspace_handle_v1::state::ACTIVE);
+        workspaces.set_state(id(0xff00_0002), 0);
+        assert!(workspaces.done().is_empty());
+    }
+}
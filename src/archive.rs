@@ -0,0 +1,304 @@
+//! Reading a single file out of a zip or tar archive, for `swww img theme.zip#sunset.png`.
+//!
+//! We only need to find one entry and read it into memory, so we parse the archives ourselves
+//! instead of pulling in a crate for each format. Deflated zip entries are inflated with
+//! `miniz_oxide`, which we already build for png.
+
+use std::path::Path;
+
+const ZIP_LOCAL_HEADER: [u8; 4] = *b"PK\x03\x04";
+const ZIP_CENTRAL_HEADER: [u8; 4] = *b"PK\x01\x02";
+const ZIP_END_OF_CENTRAL_DIRECTORY: [u8; 4] = *b"PK\x05\x06";
+const ZIP_END_OF_CENTRAL_DIRECTORY_SIZE: usize = 22;
+
+const TAR_BLOCK_SIZE: usize = 512;
+
+/// Reads `entry` from the zip or tar `archive`
+pub fn read_entry(archive: &Path, entry: &str) -> Result<Vec<u8>, String> {
+    let bytes =
+        std::fs::read(archive).map_err(|e| format!("failed to read {}: {e}", archive.display()))?;
+    let entry = entry.trim_start_matches("./");
+    let result = if bytes.starts_with(&ZIP_LOCAL_HEADER)
+        || bytes.starts_with(&ZIP_END_OF_CENTRAL_DIRECTORY)
+    {
+        zip_entry(&bytes, entry)
+    } else {
+        tar_entry(&bytes, entry)
+    };
+    match result {
+        Ok(Some(bytes)) => Ok(bytes),
+        Ok(None) => Err(format!(
+            "there is no file named '{entry}' in {}",
+            archive.display()
+        )),
+        Err(e) => Err(format!("failed to read {}: {e}", archive.display())),
+    }
+}
+
+fn u16_at(bytes: &[u8], offset: usize) -> Result<u16, String> {
+    bytes
+        .get(offset..offset + 2)
+        .map(|b| u16::from_le_bytes([b[0], b[1]]))
+        .ok_or_else(|| "truncated zip archive".to_string())
+}
+
+fn u32_at(bytes: &[u8], offset: usize) -> Result<u32, String> {
+    bytes
+        .get(offset..offset + 4)
+        .map(|b| u32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+        .ok_or_else(|| "truncated zip archive".to_string())
+}
+
+fn zip_entry(bytes: &[u8], entry: &str) -> Result<Option<Vec<u8>>, String> {
+    // the end of central directory record is followed by a comment of up to u16::MAX bytes
+    let search_start = bytes
+        .len()
+        .saturating_sub(ZIP_END_OF_CENTRAL_DIRECTORY_SIZE + u16::MAX as usize);
+    let end = bytes
+        .len()
+        .checked_sub(ZIP_END_OF_CENTRAL_DIRECTORY_SIZE)
+        .and_then(|last| {
+            (search_start..=last)
+                .rev()
+                .find(|&i| bytes[i..].starts_with(&ZIP_END_OF_CENTRAL_DIRECTORY))
+        })
+        .ok_or_else(|| "not a zip archive (no end of central directory)".to_string())?;
+
+    let entries = u16_at(bytes, end + 10)?;
+    let mut offset = u32_at(bytes, end + 16)? as usize;
+    if entries == u16::MAX || offset == u32::MAX as usize {
+        return Err("zip64 archives are not supported".to_string());
+    }
+
+    for _ in 0..entries {
+        if !bytes[offset.min(bytes.len())..].starts_with(&ZIP_CENTRAL_HEADER) {
+            return Err("corrupted zip central directory".to_string());
+        }
+        let flags = u16_at(bytes, offset + 8)?;
+        let method = u16_at(bytes, offset + 10)?;
+        let compressed_size = u32_at(bytes, offset + 20)? as usize;
+        let size = u32_at(bytes, offset + 24)? as usize;
+        let name_len = u16_at(bytes, offset + 28)? as usize;
+        let extra_len = u16_at(bytes, offset + 30)? as usize;
+        let comment_len = u16_at(bytes, offset + 32)? as usize;
+        let local_header = u32_at(bytes, offset + 42)? as usize;
+        let name = bytes
+            .get(offset + 46..offset + 46 + name_len)
+            .ok_or_else(|| "truncated zip archive".to_string())?;
+        offset += 46 + name_len + extra_len + comment_len;
+
+        if name != entry.as_bytes() {
+            continue;
+        }
+        if flags & 1 != 0 {
+            return Err(format!("'{entry}' is encrypted"));
+        }
+        if compressed_size == u32::MAX as usize || size == u32::MAX as usize {
+            return Err("zip64 archives are not supported".to_string());
+        }
+
+        if !bytes[local_header.min(bytes.len())..].starts_with(&ZIP_LOCAL_HEADER) {
+            return Err(format!("corrupted zip local header for '{entry}'"));
+        }
+        let data_start = local_header
+            + 30
+            + u16_at(bytes, local_header + 26)? as usize
+            + u16_at(bytes, local_header + 28)? as usize;
+        let data = bytes
+            .get(data_start..data_start + compressed_size)
+            .ok_or_else(|| "truncated zip archive".to_string())?;
+
+        return match method {
+            0 => Ok(Some(data.to_vec())),
+            8 => miniz_oxide::inflate::decompress_to_vec_with_limit(data, size)
+                .map(Some)
+                .map_err(|e| format!("failed to inflate '{entry}': {e}")),
+            _ => Err(format!(
+                "'{entry}' uses an unsupported compression method ({method}), only stored and \
+                 deflated entries are supported"
+            )),
+        };
+    }
+    Ok(None)
+}
+
+/// A nul terminated string in a tar header
+fn tar_str(field: &[u8]) -> &[u8] {
+    let len = field.iter().position(|&b| b == 0).unwrap_or(field.len());
+    &field[..len]
+}
+
+fn tar_size(field: &[u8]) -> Result<usize, String> {
+    if field[0] & 0x80 != 0 {
+        return Err("tar entries larger than 8GiB are not supported".to_string());
+    }
+    let octal = String::from_utf8_lossy(tar_str(field));
+    let octal = octal.trim_matches(|c: char| c == ' ' || c == '\0');
+    if octal.is_empty() {
+        return Ok(0);
+    }
+    usize::from_str_radix(octal, 8).map_err(|_| format!("corrupted tar entry size: '{octal}'"))
+}
+
+fn tar_entry(bytes: &[u8], entry: &str) -> Result<Option<Vec<u8>>, String> {
+    let mut offset = 0;
+    // set by the GNU and pax headers that precede an entry whose name does not fit in its own
+    let mut long_name: Option<Vec<u8>> = None;
+
+    while let Some(header) = bytes.get(offset..offset + TAR_BLOCK_SIZE) {
+        // the archive ends with (at least) two zeroed blocks
+        if header.iter().all(|&b| b == 0) {
+            break;
+        }
+        let size = tar_size(&header[124..136])?;
+        let data_start = offset + TAR_BLOCK_SIZE;
+        let data = bytes
+            .get(data_start..data_start + size)
+            .ok_or_else(|| "truncated tar archive".to_string())?;
+        offset = data_start + size.div_ceil(TAR_BLOCK_SIZE) * TAR_BLOCK_SIZE;
+
+        match header[156] {
+            b'L' => {
+                long_name = Some(tar_str(data).to_vec());
+                continue;
+            }
+            b'x' => {
+                long_name = pax_path(data).or(long_name);
+                continue;
+            }
+            _ => (),
+        }
+
+        let name = long_name.take().unwrap_or_else(|| {
+            let name = tar_str(&header[0..100]);
+            let prefix = tar_str(&header[345..500]);
+            if header[257..262] == *b"ustar" && !prefix.is_empty() {
+                [prefix, b"/", name].concat()
+            } else {
+                name.to_vec()
+            }
+        });
+        let name = name.strip_prefix(b"./").unwrap_or(&name);
+        if name != entry.as_bytes() {
+            continue;
+        }
+        return match header[156] {
+            b'0' | b'\0' | b'7' => Ok(Some(data.to_vec())),
+            _ => Err(format!("'{entry}' is not a regular file")),
+        };
+    }
+    Ok(None)
+}
+
+/// The `path` record of a pax extended header, made of "<len> <key>=<value>\n" records
+fn pax_path(mut data: &[u8]) -> Option<Vec<u8>> {
+    while !data.is_empty() {
+        let space = data.iter().position(|&b| b == b' ')?;
+        let len: usize = std::str::from_utf8(&data[..space]).ok()?.parse().ok()?;
+        let record = data.get(space + 1..len)?;
+        if let Some(path) = record.strip_prefix(b"path=") {
+            return Some(path.strip_suffix(b"\n").unwrap_or(path).to_vec());
+        }
+        data = &data[len..];
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tar_header(name: &str, size: usize, typeflag: u8) -> Vec<u8> {
+        let mut header = vec![0; TAR_BLOCK_SIZE];
+        header[..name.len()].copy_from_slice(name.as_bytes());
+        header[124..135].copy_from_slice(format!("{size:011o}").as_bytes());
+        header[156] = typeflag;
+        header[257..263].copy_from_slice(b"ustar\0");
+        header
+    }
+
+    fn tar(entries: &[(&str, &[u8])]) -> Vec<u8> {
+        let mut tar = Vec::new();
+        for (name, data) in entries {
+            tar.extend(tar_header(name, data.len(), b'0'));
+            tar.extend_from_slice(data);
+            tar.resize(tar.len().div_ceil(TAR_BLOCK_SIZE) * TAR_BLOCK_SIZE, 0);
+        }
+        tar.resize(tar.len() + 2 * TAR_BLOCK_SIZE, 0);
+        tar
+    }
+
+    /// A zip with `entries`, deflating the ones marked so
+    fn zip(entries: &[(&str, &[u8], bool)]) -> Vec<u8> {
+        let mut zip = Vec::new();
+        let mut central = Vec::new();
+        for (name, data, deflate) in entries {
+            let (method, compressed) = if *deflate {
+                (8u16, miniz_oxide::deflate::compress_to_vec(data, 6))
+            } else {
+                (0u16, data.to_vec())
+            };
+            let mut fields = Vec::new();
+            fields.extend(20u16.to_le_bytes()); // version needed
+            fields.extend(0u16.to_le_bytes()); // flags
+            fields.extend(method.to_le_bytes());
+            fields.extend([0; 8]); // time, date and crc32, which we do not check
+            fields.extend((compressed.len() as u32).to_le_bytes());
+            fields.extend((data.len() as u32).to_le_bytes());
+            fields.extend((name.len() as u16).to_le_bytes());
+            fields.extend(0u16.to_le_bytes()); // extra field length
+
+            central.extend(ZIP_CENTRAL_HEADER);
+            central.extend(20u16.to_le_bytes()); // version made by
+            central.extend(&fields);
+            central.extend([0; 8]); // comment length, disk, internal and external attributes
+            central.extend([0; 2]);
+            central.extend((zip.len() as u32).to_le_bytes());
+            central.extend(name.as_bytes());
+
+            zip.extend(ZIP_LOCAL_HEADER);
+            zip.extend(&fields);
+            zip.extend(name.as_bytes());
+            zip.extend(compressed);
+        }
+        let central_offset = zip.len() as u32;
+        zip.extend(&central);
+        zip.extend(ZIP_END_OF_CENTRAL_DIRECTORY);
+        zip.extend([0; 4]); // disk numbers
+        zip.extend((entries.len() as u16).to_le_bytes());
+        zip.extend((entries.len() as u16).to_le_bytes());
+        zip.extend((central.len() as u32).to_le_bytes());
+        zip.extend(central_offset.to_le_bytes());
+        zip.extend(0u16.to_le_bytes()); // comment length
+        zip
+    }
+
+    #[test]
+    fn reads_tar_entries() {
+        let long = format!("{}/sunset.png", "themes".repeat(20));
+        let mut archive = tar_header("././@LongLink", long.len() + 1, b'L');
+        archive.extend(long.as_bytes());
+        archive.resize(2 * TAR_BLOCK_SIZE, 0);
+        // the long name replaces the one in the next header
+        archive.extend(tar(&[
+            ("ignored", b"long"),
+            ("./a.png", b"first"),
+            ("dir/b.png", &[7; 600]),
+        ]));
+
+        assert_eq!(tar_entry(&archive, &long).unwrap().unwrap(), b"long");
+        assert_eq!(tar_entry(&archive, "a.png").unwrap().unwrap(), b"first");
+        assert_eq!(tar_entry(&archive, "dir/b.png").unwrap().unwrap(), [7; 600]);
+        assert_eq!(tar_entry(&archive, "ignored").unwrap(), None);
+        assert_eq!(tar_entry(&archive, "c.png").unwrap(), None);
+    }
+
+    #[test]
+    fn reads_zip_entries() {
+        let archive = zip(&[("a.png", b"stored", false), ("dir/b.png", &[7; 600], true)]);
+        assert_eq!(zip_entry(&archive, "a.png").unwrap().unwrap(), b"stored");
+        assert_eq!(zip_entry(&archive, "dir/b.png").unwrap().unwrap(), [7; 600]);
+        assert_eq!(zip_entry(&archive, "b.png").unwrap(), None);
+        assert!(zip_entry(b"not a zip", "a.png").is_err());
+    }
+}
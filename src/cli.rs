@@ -1,22 +1,37 @@
 /// Note: this file only has basic declarations and some definitions in order to be possible to
 /// import it in the build script, to automate shell completion
-use clap::{Parser, ValueEnum};
+use clap::{Parser, Subcommand, ValueEnum};
 use std::path::PathBuf;
 
 fn from_hex(hex: &str) -> Result<[u8; 3], String> {
+    parse_hex(hex)
+}
+
+/// Like `from_hex`, but also accepts an alpha value at the end (rrggbbaa). Colors without one are
+/// opaque
+fn from_hex_alpha(hex: &str) -> Result<[u8; 4], String> {
+    if hex.chars().filter(|&c| c.is_ascii_alphanumeric()).count() == 8 {
+        parse_hex(hex)
+    } else {
+        from_hex(hex).map(|[r, g, b]| [r, g, b, 0xff])
+    }
+}
+
+fn parse_hex<const N: usize>(hex: &str) -> Result<[u8; N], String> {
     let chars = hex
         .chars()
         .filter(|&c| c.is_ascii_alphanumeric())
         .map(|c| c.to_ascii_uppercase() as u8);
 
-    if chars.clone().count() != 6 {
+    if chars.clone().count() != N * 2 {
         return Err(format!(
-            "expected 6 characters, found {}",
+            "expected {} characters, found {}",
+            N * 2,
             chars.clone().count()
         ));
     }
 
-    let mut color = [0, 0, 0];
+    let mut color = [0; N];
 
     for (i, c) in chars.enumerate() {
         match c {
@@ -79,6 +94,9 @@ pub enum TransitionType {
     None,
     Simple,
     Fade,
+    Dissolve,
+    Slide,
+    Tiles,
     Left,
     Right,
     Top,
@@ -92,6 +110,19 @@ pub enum TransitionType {
     Grow,
 }
 
+/// A complete transition, under a single name. See `main.rs` for the options each one sets
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum TransitionPreset {
+    /// A slow 'fade' that eases in and out
+    GentleFade,
+    /// A smooth, diagonal 'wipe' at 60 fps
+    CinematicWipe,
+    /// A quick 'grow' from the center that overshoots a little
+    Pop,
+    /// A slow 'wave' with shallow waves, from top to bottom
+    Ripple,
+}
+
 impl std::str::FromStr for TransitionType {
     type Err = &'static str;
 
@@ -111,13 +142,46 @@ impl std::str::FromStr for TransitionType {
             "wave" => Ok(Self::Wave),
             "random" => Ok(Self::Random),
             "fade" => Ok(Self::Fade),
+            "dissolve" => Ok(Self::Dissolve),
+            "slide" => Ok(Self::Slide),
+            "tiles" => Ok(Self::Tiles),
             _ => Err("unrecognized transition type.\nValid transitions are:\n\
-                     \tsimple | fade | left | right | top | bottom | wipe | grow | center | outer | random | wave\n\
+                     \tsimple | fade | dissolve | left | right | top | bottom | wipe | wave | slide | tiles | grow | center | outer | random\n\
                      see swww img --help for more details"),
         }
     }
 }
 
+/// Selects outputs by their scale factor, like `>1` or `=2`
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ScaleFilter {
+    pub cmp: ScaleCmp,
+    pub scale: f32,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ScaleCmp {
+    Less,
+    LessOrEqual,
+    Equal,
+    GreaterOrEqual,
+    Greater,
+}
+
+impl ScaleFilter {
+    pub fn matches(&self, scale: f32) -> bool {
+        // fractional scales are sent in 120ths, so they are rarely exact
+        const EPSILON: f32 = 1.0 / 240.0;
+        match self.cmp {
+            ScaleCmp::Less => scale < self.scale - EPSILON,
+            ScaleCmp::LessOrEqual => scale <= self.scale + EPSILON,
+            ScaleCmp::Equal => (scale - self.scale).abs() <= EPSILON,
+            ScaleCmp::GreaterOrEqual => scale >= self.scale - EPSILON,
+            ScaleCmp::Greater => scale > self.scale + EPSILON,
+        }
+    }
+}
+
 #[derive(Clone)]
 pub enum CliCoord {
     Percent(f32),
@@ -140,8 +204,19 @@ impl CliPosition {
 #[derive(Clone)]
 pub enum CliImage {
     Path(PathBuf),
+    /// http(s) url to download the image from
+    Url(String),
+    /// Video file, that we turn into an animation with `ffmpeg`
+    Video(PathBuf),
+    /// File inside a zip or tar archive, given as `<archive>#<entry>`
+    Archive {
+        archive: PathBuf,
+        entry: String,
+    },
     /// Single rgb color
     Color([u8; 3]),
+    /// Animation exported with `swww export`
+    Precomputed(PathBuf),
 }
 
 #[derive(Parser)]
@@ -154,6 +229,28 @@ pub enum CliImage {
 ///
 ///Note `swww` will only work in a compositor that implements the layer-shell protocol. Typically,
 ///wlr-roots based compositors.
+pub struct Cli {
+    /// Talk to the daemon started with the same namespace (see `swww-daemon --namespace`).
+    ///
+    /// Lets you control many independent daemons. By default, we talk to the one without a
+    /// namespace.
+    #[arg(long, global = true, env = "SWWW_NAMESPACE", default_value = "", value_parser = parse_namespace)]
+    pub namespace: String,
+
+    /// How long to wait for each answer of the daemon before giving up, in seconds (can have
+    /// decimals).
+    ///
+    /// Bounds every exchange with the daemon, so that a wedged daemon makes this fail with an
+    /// error instead of hanging forever. Defaults to 5 seconds. 'swww check' has a '--timeout' of
+    /// its own, and 'swww query --watch' waits for updates for as long as it runs.
+    #[arg(long, global = true, env = "SWWW_QUERY_TIMEOUT", value_name = "SECS", value_parser = parse_timeout)]
+    pub query_timeout: Option<std::time::Duration>,
+
+    #[command(subcommand)]
+    pub command: Swww,
+}
+
+#[derive(Subcommand)]
 pub enum Swww {
     ///Fills the specified outputs with the given color.
     ///
@@ -169,34 +266,248 @@ pub enum Swww {
     ///as the animation frames of every gif ever set for a given version of `swww`.
     ClearCache,
 
+    ///Inspects the swww cache, through the daemon.
+    #[command(subcommand)]
+    Cache(Cache),
+
     /// Sends an image (or animated gif) for the daemon to display.
     ///
     /// Use `-` to read from stdin
     Img(Img),
 
     ///Kills the daemon
-    Kill,
+    Kill(Kill),
+
+    ///Stops displaying the image set with 'swww img --sticky' on outputs that show up later.
+    ///
+    ///The outputs keep displaying whatever they are displaying now.
+    Unstick,
+
+    ///Stops displaying the image set with 'swww img --workspace' when that workspace becomes active.
+    UnsetWorkspace(UnsetWorkspace),
+
+    ///Sends an image for the daemon to keep, ready to be displayed with 'swww show-preloaded'.
+    ///
+    ///Takes the same options as 'swww img', except the transition ones, which are given to
+    ///'swww show-preloaded' instead. Each output holds a single preloaded image: preloading another
+    ///one replaces it. The daemon drops preloaded images that are not displayed within 10 minutes.
+    Preload(Img),
+
+    ///Displays the images sent with 'swww preload', with a transition.
+    ///
+    ///Since the image is already resized and in the daemon's memory, the transition starts right
+    ///away. Use this to switch wallpapers on a schedule, preloading the next one in advance.
+    ShowPreloaded(ShowPreloaded),
+
+    ///Sets what the outputs are currently displaying (windows included) as their wallpaper.
+    ///
+    ///The daemon captures the outputs through the wlr-screencopy protocol, so this only works on
+    ///compositors that support it. The captured image is not stored in the cache: the outputs go
+    ///back to their previous image when the daemon is restarted.
+    Freeze(Freeze),
+
+    ///Temporarily changes the frame rate of the animations playing on some outputs.
+    ///
+    ///For the given duration, their animations play at <FPS> frames per second, instead of at their
+    ///own rate capped by the daemon's '--max-fps' (with '--animation-interpolate', the blended
+    ///frames are drawn at that rate too). Afterwards, they go back to their own rate. Boosting an
+    ///output that is already boosted replaces its boost, instead of adding to it. Transitions are
+    ///not affected.
+    BoostFps(BoostFps),
+
+    ///Dims the wallpapers at night, following the time of day.
+    ///
+    ///During the day, the wallpapers are displayed as they are. At night, their colors are
+    ///multiplied by '--night-brightness'. The day lasts either from '--sunrise' to '--sunset', or
+    ///from sunrise to sunset at '--location'. Around both, the brightness fades from one to the
+    ///other over '--fade' minutes. Static images are dimmed again as the brightness changes, while
+    ///animations keep the brightness they started with. Setting a schedule replaces the previous
+    ///one, and '--off' stops dimming. The daemon forgets the schedule when it is restarted.
+    DimSchedule(DimSchedule),
+
+    ///Precomputes an animated image for an output, saving it to a file.
+    ///
+    ///Decoding, resizing and compressing the frames of a large animation takes a while. This does
+    ///it once, for the dimensions and pixel format of the given output, so that passing the file
+    ///to 'swww img' displays it right away.
+    Export(Export),
+
+    ///Compresses the frames of an animated image for outputs, and stores them in the cache.
+    ///
+    ///'swww img' then displays the image on those outputs (or any other with the same dimensions)
+    ///without compressing its frames again. Use this to prepare a slideshow of animations in
+    ///advance. Outputs whose frames are already cached are skipped.
+    CacheAnimation(CacheAnimation),
+
+    ///Saves what an output is currently displaying to a png file.
+    ///
+    ///The image has the output's dimensions (with its scale factor applied), and does not include
+    ///any windows, only the wallpaper. Useful for sharing or debugging.
+    Save(Save),
+
+    ///Makes the daemon select its pixel format again and recreate all of its outputs.
+    ///
+    ///Use this if the compositor changed its capabilities without restarting, and the daemon is
+    ///now displaying things wrong. The outputs reload their images from the cache afterwards
+    ///(unless the daemon was started with '--no-cache'). Everything else, like the default
+    ///transition, is kept.
+    Reset,
+
+    ///Checks whether the daemon is running and responsive.
+    ///
+    ///Prints nothing and exits successfully if the daemon answers in time and is ready to display
+    ///images. Otherwise, prints the reason to stderr and exits with an error. Meant for service
+    ///supervisors and scripts.
+    Check(Check),
+
+    ///Prints the globals the daemon bound, and their versions, along with its pixel format.
+    ///
+    ///This is what the daemon negotiated with the compositor. Please include it when reporting
+    ///bugs that only happen with some compositors.
+    Diagnostics,
+
+    ///Draws every transition offscreen, to check that they work and to time them.
+    ///
+    ///Runs 'swww-daemon --selftest', which draws each transition from one synthetic image to
+    ///another, with the default options, and prints how many frames it took, how long, and whether
+    ///it ended up displaying the new image. It does not need the daemon to be running, nor a
+    ///compositor. Exits with an error if any transition failed.
+    Selftest,
 
     ///Asks the daemon to print output information (names and dimensions).
     ///
     ///You may use this to find out valid values for the <swww-img --outputs> option. If you want
     ///more detailed information about your outputs, I would recommend trying wlr-randr.
-    Query,
+    Query(Query),
+
+    ///Sets the transition used by 'swww img' when none of the transition options are given.
+    ///
+    ///Takes the same transition options as 'swww img'. Unless '--persist' is given, the daemon
+    ///forgets it when it is restarted.
+    DefaultTransition(DefaultTransition),
+}
+
+#[derive(Subcommand)]
+pub enum Cache {
+    ///Lists the image stored for each output, and whether its animation frames are cached too.
+    ///
+    ///This is the image 'swww restore' (or the daemon, when an output shows up) will load.
+    List,
 }
 
 #[derive(Parser)]
 pub struct Clear {
     /// Color to fill the screen with.
     ///
-    /// Must be given in rrggbb format (note there is no prepended '#').
-    #[arg(value_parser = from_hex, default_value = "000000")]
-    pub color: [u8; 3],
+    /// Must be given in rrggbb format (note there is no prepended '#'). You may also append an
+    /// alpha value (rrggbbaa), where 00 is fully transparent, to let whatever is below the
+    /// wallpaper show through.
+    #[arg(value_parser = from_hex_alpha, default_value = "000000")]
+    pub color: [u8; 4],
 
     /// Comma separated list of outputs to display the image at.
     ///
     /// If it isn't set, the image is displayed on all outputs.
+    ///
+    /// Outputs may also be glob patterns, like 'DP-*', where '*' matches any sequence of characters
+    /// and '?' matches a single one.
     #[clap(short, long, default_value = "")]
     pub outputs: String,
+
+    /// Select the output by its position in the compositor's layout, instead of by its name.
+    #[arg(long, conflicts_with = "outputs")]
+    pub output_position: Option<OutputPosition>,
+}
+
+#[derive(Parser)]
+pub struct Freeze {
+    /// Comma separated list of outputs to freeze.
+    ///
+    /// If it isn't set, every output is frozen. Outputs may also be glob patterns, like in
+    /// 'swww img'.
+    #[arg(short, long, default_value = "")]
+    pub outputs: String,
+
+    /// Select the output by its position in the compositor's layout, instead of by its name.
+    #[arg(long, conflicts_with = "outputs")]
+    pub output_position: Option<OutputPosition>,
+}
+
+#[derive(Parser)]
+pub struct BoostFps {
+    /// The frame rate to play animations at while the boost lasts
+    pub fps: std::num::NonZeroU16,
+
+    /// How long the boost lasts, in seconds (can have decimals)
+    #[arg(short, long, default_value = "5", value_name = "SECS", value_parser = parse_duration)]
+    pub duration: std::time::Duration,
+
+    /// Comma separated list of outputs to boost.
+    ///
+    /// If it isn't set, every output is boosted. Outputs may also be glob patterns, like in
+    /// 'swww img'.
+    #[arg(short, long, default_value = "")]
+    pub outputs: String,
+
+    /// Select the output by its position in the compositor's layout, instead of by its name.
+    #[arg(long, conflicts_with = "outputs")]
+    pub output_position: Option<OutputPosition>,
+}
+
+#[derive(Parser)]
+pub struct DimSchedule {
+    /// How bright the wallpapers are at night, from 0 (black) to 1 (not dimmed at all)
+    #[arg(short, long, default_value = "0.6", value_parser = parse_night_brightness)]
+    pub night_brightness: f32,
+
+    /// When the day starts, as HH:MM in local time. Requires '--sunset'
+    #[arg(
+        long,
+        value_parser = parse_time_of_day,
+        requires = "sunset",
+        required_unless_present_any = ["location", "off"]
+    )]
+    pub sunrise: Option<u16>,
+
+    /// When the night starts, as HH:MM in local time. Requires '--sunrise'
+    #[arg(long, value_parser = parse_time_of_day, requires = "sunrise")]
+    pub sunset: Option<u16>,
+
+    /// Follow the sunrise and sunset at this location, given as 'latitude,longitude' in degrees.
+    ///
+    /// North and east are positive, so '-33.9,18.4' is Cape Town. The daemon computes the sunrise
+    /// and sunset for each day, using the system's timezone.
+    #[arg(
+        long,
+        value_parser = parse_location,
+        allow_hyphen_values = true,
+        conflicts_with_all = ["sunrise", "sunset"]
+    )]
+    pub location: Option<(f32, f32)>,
+
+    /// How long fading from one brightness to the other takes, centered on the sunrise and
+    /// sunset. 0 switches right away
+    #[arg(short, long, default_value = "60", value_name = "MINUTES")]
+    pub fade: u16,
+
+    /// Stop dimming, displaying the wallpapers as they are
+    #[arg(long, conflicts_with_all = ["sunrise", "sunset", "location"])]
+    pub off: bool,
+}
+
+/// Selects a single output by where it is in the compositor's layout. Ties (like two outputs with
+/// the same x, for 'leftmost') go to the topmost (or leftmost) one, and then to the first name in
+/// alphabetical order
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum OutputPosition {
+    /// The output whose left edge is the furthest to the left
+    Leftmost,
+    /// The output whose right edge is the furthest to the right
+    Rightmost,
+    /// The output whose top edge is the highest
+    Topmost,
+    /// The output whose bottom edge is the lowest
+    Bottommost,
 }
 
 #[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash, ValueEnum)]
@@ -214,27 +525,181 @@ pub enum ResizeStrategy {
     Fit,
 }
 
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum AnimationClock {
+    /// Follow the daemon's shared animation clock.
+    ///
+    /// Animations set through different invocations will stay in phase with each other.
+    #[default]
+    Locked,
+    /// Start from the first frame and keep time independently of any other animation
+    Independent,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum BlendMode {
+    /// Add the image to the base. Black leaves the base as it is
+    Additive,
+    /// Like additive, but softer on bright parts. Black leaves the base as it is
+    Screen,
+    /// Darken the dark parts of the base and brighten the bright ones, keeping its contrast
+    Overlay,
+}
+
+#[derive(Parser)]
+pub struct Query {
+    /// Print the information as a JSON array, instead of human readable text.
+    ///
+    /// Each element has the fields "name", "dim", "position", "scale_factor", "img",
+    /// "pixel_format" and "animated".
+    #[arg(short, long)]
+    pub json: bool,
+
+    /// Only print the outputs that are currently playing an animation.
+    #[arg(long)]
+    pub animated_only: bool,
+
+    /// Keep running, printing the information again whenever it changes (e.g. when a new image is
+    /// set, or an output is added or removed).
+    ///
+    /// In a terminal (and without '--json'), the information is redrawn in place instead. Press
+    /// Ctrl-C to stop.
+    #[arg(short, long)]
+    pub watch: bool,
+
+    /// With '--watch', also print how far each transition got, every so often while it runs.
+    ///
+    /// Each line looks like 'DP-1: transition 42%' (or, with '--json', like
+    /// '{"name":"DP-1","transition_progress":42}'). Every transition ends with 100%.
+    #[arg(short, long, requires = "watch")]
+    pub progress: bool,
+}
+
+#[derive(Parser)]
+pub struct Kill {
+    /// Clear all outputs to this color before exiting. Defaults to black, if no color is given.
+    ///
+    /// Must be given in rrggbb format (note there is no prepended '#').
+    #[arg(long, num_args = 0..=1, default_missing_value = "000000", value_parser = from_hex)]
+    pub clear: Option<[u8; 3]>,
+}
+
+#[derive(Parser)]
+pub struct Export {
+    /// Path or http(s) url of the animated image (or video) to export
+    #[arg(value_parser = parse_image)]
+    pub image: CliImage,
+
+    /// File to write the precomputed animation to. Must end in '.swww'
+    pub file: PathBuf,
+
+    /// Output whose dimensions and pixel format to precompute the animation for.
+    ///
+    /// If it isn't set, the first output the daemon knows about is used.
+    #[clap(short, long, default_value = "")]
+    pub output: String,
+
+    /// Filter to use when scaling the frames. See 'swww img --help'
+    #[arg(short, long, default_value = "Lanczos3")]
+    pub filter: Filter,
+}
+
+#[derive(Parser)]
+pub struct CacheAnimation {
+    /// Path of the animated image (or video) to cache the frames of
+    #[arg(value_parser = parse_image)]
+    pub image: CliImage,
+
+    /// Comma separated list of outputs to cache the frames for.
+    ///
+    /// If it isn't set, they are cached for every output. Outputs may also be glob patterns, like
+    /// in 'swww img'.
+    #[arg(short, long, visible_alias = "output", default_value = "")]
+    pub outputs: String,
+
+    /// Filter to use when scaling the frames. See 'swww img --help'
+    #[arg(short, long, default_value = "Lanczos3")]
+    pub filter: Filter,
+}
+
+#[derive(Parser)]
+pub struct Save {
+    /// File to write the png to
+    pub file: PathBuf,
+
+    /// Output whose wallpaper to save.
+    ///
+    /// If it isn't set, the first output the daemon knows about is used.
+    #[clap(short, long, default_value = "")]
+    pub output: String,
+}
+
+#[derive(Parser)]
+pub struct Check {
+    /// How long to wait for the daemon's answer, in seconds (can have decimals).
+    #[arg(short, long, default_value = "1")]
+    pub timeout: f32,
+
+    /// Print a message when the daemon is responsive, too.
+    #[arg(short, long)]
+    pub verbose: bool,
+}
+
 #[derive(Parser)]
 pub struct Restore {
     /// Comma separated list of outputs to restore.
     ///
     /// If it isn't set, all outputs will be restored.
+    ///
+    /// Outputs may also be glob patterns, like 'DP-*', where '*' matches any sequence of characters
+    /// and '?' matches a single one.
     #[arg(short, long, default_value = "")]
     pub outputs: String,
 }
 
+#[derive(Parser)]
+pub struct UnsetWorkspace {
+    ///Name of the workspace, as set with 'swww img --workspace'
+    pub workspace: String,
+}
+
 #[derive(Parser)]
 pub struct Img {
-    /// Path of image or hexcode (starting with 0x) to display
+    /// Path of image, http(s) url or hexcode (starting with 0x) to display
+    ///
+    /// Urls are downloaded with `curl`, so it must be installed to use them. Images inside a zip or
+    /// tar archive can be given as '<archive>#<file>', like 'theme.zip#sunset.png'.
     #[arg(value_parser = parse_image)]
     pub image: CliImage,
 
     /// Comma separated list of outputs to display the image at.
     ///
     /// If it isn't set, the image is displayed on all outputs.
+    ///
+    /// Outputs may also be glob patterns, like 'DP-*', where '*' matches any sequence of characters
+    /// and '?' matches a single one.
     #[arg(short, long, default_value = "")]
     pub outputs: String,
 
+    /// Select the output by its position in the compositor's layout, instead of by its name.
+    #[arg(long, conflicts_with = "outputs")]
+    pub output_position: Option<OutputPosition>,
+
+    /// Comma separated list of outputs NOT to display the image at. Every other output is
+    /// selected.
+    ///
+    /// Glob patterns are accepted, like in '--outputs'. It is an error if this leaves no output.
+    #[arg(long, conflicts_with_all = ["outputs", "output_position"])]
+    pub output_all_except: Option<String>,
+
+    /// Only display the image on the (selected) outputs whose scale factor matches this.
+    ///
+    /// Either a scale, like '2', to select the outputs with exactly that scale factor, or a scale
+    /// preceded by one of '<', '<=', '=', '>=' or '>'. For example, '>1' selects HiDPI outputs.
+    /// It is an error if no output matches.
+    #[arg(long, value_parser = parse_scale_filter)]
+    pub filter_output_by_scale: Option<ScaleFilter>,
+
     /// Do not resize the image. Equivalent to `--resize=no`
     ///
     /// If this is set, the image won't be resized, and will be centralized in the middle of the
@@ -252,7 +717,38 @@ pub struct Img {
     )]
     pub resize: ResizeStrategy,
 
+    /// Which part of the image to keep when cropping it, with '--resize crop'.
+    ///
+    /// Takes the same values as '--transition-pos', but relative to the image, instead of the
+    /// screen: what is kept is as centered on that point as it can be. For example, 'top' keeps
+    /// the top of portraits (instead of their middle), and '0.3,0.6' keeps the part around that
+    /// point of interest. Defaults to 'center'.
+    #[arg(long, default_value = "center", value_parser = parse_coords, conflicts_with = "span")]
+    pub crop_anchor: CliPosition,
+
+    /// Span a single image across all the selected outputs, instead of displaying it whole on each
+    /// one of them.
+    ///
+    /// Each output gets the part of the image that corresponds to its position in the
+    /// compositor's layout, so that the seams line up. The image is resized to cover the whole
+    /// layout, cropping out parts that don't fit. Animated images only display their first frame.
+    #[arg(long)]
+    pub span: bool,
+
+    /// Only cover this rectangle of the outputs, given as 'x,y,width,height' in logical pixels
+    /// from their top left corner.
+    ///
+    /// The image is still made for the whole output, and only the part of it inside the rectangle
+    /// is displayed. The rest of the output is left uncovered, for example to leave a strip of the
+    /// screen to something else. The rectangle must fit within every output. Displaying an image
+    /// without it covers the whole output again.
+    #[arg(long, value_parser = parse_region, conflicts_with = "span")]
+    pub region: Option<(u32, u32, u32, u32)>,
+
     /// Which color to fill the padding with when output image does not fill screen
+    ///
+    /// This applies to '--resize=no' and '--resize=fit'. The padding is part of the image sent to
+    /// the daemon, so it transitions in along with the rest of the image.
     #[arg(value_parser = from_hex, long, default_value = "000000")]
     pub fill_color: [u8; 3],
 
@@ -273,12 +769,219 @@ pub struct Img {
     #[arg(short, long, default_value = "Lanczos3")]
     pub filter: Filter,
 
+    ///Dither the image when the daemon uses a 3 byte pixel format ('rgb' or 'bgr').
+    ///
+    ///Images with more than 8 bits per channel (like 16 bit pngs) are otherwise rounded down to 8
+    ///bits, which can make smooth gradients show visible bands. With this, they are reduced with
+    ///ordered dithering instead. It makes no difference for 8 bit images, nor for animation
+    ///frames, and it does nothing when the daemon uses 'xrgb' or 'xbgr'.
+    #[arg(long, env = "SWWW_DITHER")]
+    pub dither: bool,
+
+    ///How the animation (if the image is animated) keeps time.
+    ///
+    ///'locked' makes it follow the daemon's shared clock, so that setting the same animated
+    ///image on different outputs through separate commands keeps them in phase. 'independent'
+    ///makes it start from the first frame and ignore every other animation.
+    #[arg(long, env = "SWWW_ANIMATION_CLOCK", default_value = "locked")]
+    pub animation_clock: AnimationClock,
+
+    ///Blend each frame of the animation (if the image is animated) into the next one.
+    ///
+    ///This makes low frame rate animations look smoother on high refresh rate displays, by
+    ///drawing intermediate frames as often as the compositor asks for them (respecting the
+    ///daemon's `--max-fps`). Note every one of those frames is a full pass over the image, so this
+    ///costs a lot more CPU than just playing the animation.
+    #[arg(long, env = "SWWW_ANIMATION_INTERPOLATE")]
+    pub animation_interpolate: bool,
+
+    ///With '--animation-interpolate', move what moved between frames instead of only blending.
+    ///
+    ///Blending something that moves shows it twice, half transparent, between frames. With this,
+    ///the daemon estimates how each block of the image moved from one frame to the next, and draws
+    ///the intermediate frames with every block partway along its motion. This suits animations
+    ///where things pan or slide, and does nothing for ones that only change colors.
+    ///
+    ///This is expensive: estimating the motion costs a few full passes over the image for every
+    ///frame of the animation, and each intermediate frame then costs about twice as much CPU as
+    ///with plain blending. If the daemon cannot draw the warped frames in time for its frame rate,
+    ///it goes back to plain blending for the rest of the animation.
+    #[arg(long, env = "SWWW_ANIMATION_WARP", requires = "animation_interpolate")]
+    pub animation_warp: bool,
+
+    ///Multiply the colors of the image by this factor, to make it look alike on outputs that are
+    ///dimmer (or brighter) than others.
+    ///
+    ///Values below 1 darken the image and values above 1 brighten it. Use it with '--outputs', so
+    ///that it only applies to the outputs that need it. Only the wallpaper changes, not the rest
+    ///of the display. Clamped between 0 and 4.
+    #[arg(long, default_value = "1.0")]
+    pub brightness: f32,
+
+    ///Apply this gamma correction to the colors of the image, like '--brightness'.
+    ///
+    ///Values above 1 brighten the midtones, while values below 1 darken them, leaving black and
+    ///white as they are. Clamped between 0.1 and 10.
+    #[arg(long, default_value = "1.0")]
+    pub gamma: f32,
+
+    ///Display the image even on outputs that are already displaying it.
+    ///
+    ///By default, the daemon skips outputs whose current image has the same path as the new one,
+    ///so that running this repeatedly with the same image does not restart the transition. Use
+    ///this if the file changed since it was set.
+    #[arg(long)]
+    pub force: bool,
+
+    ///Display the image on the next frame the compositor draws, as fast as possible.
+    ///
+    ///The daemon skips the transition (any transition options are ignored) and its transition
+    ///scheduling altogether, and commits the image as soon as the compositor asks for the next
+    ///frame. Animated images only display their first frame. This command returns once the image
+    ///is committed, which makes it suited for scripts that must know when the wallpaper changed.
+    #[arg(long)]
+    pub sync: bool,
+
+    ///Transition from this image instead of from what the outputs currently display.
+    ///
+    ///Accepts the same images as the main argument, except for precomputed animations, and is
+    ///resized for each output the same way. Only its first frame is used. Useful to script
+    ///montages, where each transition must start from a known image.
+    #[arg(long, value_name = "IMAGE", value_parser = parse_image, conflicts_with_all = ["sync", "span"])]
+    pub from: Option<CliImage>,
+
+    ///Composite the image, and every frame of its animation, over the '--blend-over' image with
+    ///this blend mode, instead of displaying it as it is.
+    ///
+    ///For effects like rain or particles on top of a static wallpaper: the animation only needs to
+    ///draw the effect over a black background. This costs a pass over the whole output for every
+    ///frame of the animation.
+    #[arg(long, value_enum, requires = "blend_over", conflicts_with_all = ["sync", "span"])]
+    pub blend: Option<BlendMode>,
+
+    ///The image to composite the image over, with '--blend'.
+    ///
+    ///Accepts the same images as the main argument, except for precomputed animations, and is
+    ///resized for each output the same way. Only its first frame is used.
+    #[arg(long, value_name = "IMAGE", value_parser = parse_image, requires = "blend")]
+    pub blend_over: Option<CliImage>,
+
+    ///Also display the image on outputs that show up later, like a monitor plugged in afterwards.
+    ///
+    ///The daemon remembers the image and the options it was set with, and displays it (without a
+    ///transition) on every output that connects from now on, instead of the image in the cache.
+    ///Setting another sticky image replaces this one, and 'swww unstick' undoes it. Since it is
+    ///meant for every output, it cannot be combined with the options that select outputs.
+    #[arg(long, conflicts_with_all = ["outputs", "output_position", "output_all_except", "filter_output_by_scale", "span"])]
+    pub sticky: bool,
+
+    ///Display the image whenever the workspace with this name becomes active, instead of now.
+    ///
+    ///The daemon remembers the image and the options it was set with, like with '--sticky', and
+    ///displays it (without a transition) on the outputs of the workspace's group every time the
+    ///workspace becomes active. If it is active already, the image is displayed right away.
+    ///Setting another image for the same workspace replaces this one, and 'swww unset-workspace'
+    ///undoes it. Requires a compositor that supports the ext-workspace protocol.
+    #[arg(long, value_name = "NAME", conflicts_with_all = ["sticky", "outputs", "output_position", "output_all_except", "filter_output_by_scale", "span"])]
+    pub workspace: Option<String>,
+
+    ///Store the image, decoded and resized for each output, in the cache, and use it next time.
+    ///
+    ///Sending the same image to the same outputs again (with the same resize strategy, filter and
+    ///fill color) then skips decoding and resizing it, which is useful for slideshows that cycle
+    ///through a few large images. A cached image is not used if the file was modified after it
+    ///was stored. Note these take a lot of disk space: the image size times the number of pixels
+    ///of each output. 'swww clear-cache' removes them.
+    #[arg(long, env = "SWWW_CACHE_DECODED")]
+    pub cache_decoded: bool,
+
+    ///Store the animation frames and decoded images of the image in the cache by the hash of its
+    ///contents, instead of by its path.
+    ///
+    ///Identical images under different paths (like copies and symlinks) then share their cache
+    ///entries instead of each storing their own, which saves disk space in large collections with
+    ///duplicates. Costs a pass over the file to hash it. Entries stored by path before are still
+    ///used. Only applies to image files, not to urls, videos or archives.
+    #[arg(long, env = "SWWW_CACHE_BY_CONTENT")]
+    pub cache_by_content: bool,
+
+    ///Make the daemon log in detail how it handles this image, like its size and format, the
+    ///transition and how long it took, even if it was started with '--quiet'.
+    ///
+    ///The logs go to the daemon's stderr, not to this command's.
+    #[arg(long)]
+    pub debug: bool,
+
+    #[command(flatten)]
+    pub transition: Transition,
+
+    /// Whether none of the transition options were given, in which case the daemon uses its
+    /// default transition. This is not an option, `main` sets it after parsing
+    #[arg(skip)]
+    pub default_transition: bool,
+}
+
+#[derive(Parser)]
+pub struct ShowPreloaded {
+    /// Comma separated list of outputs to display the preloaded images at.
+    ///
+    /// If it isn't set, every preloaded image is displayed. Outputs may also be glob patterns, like
+    /// in 'swww img'.
+    #[arg(short, long, default_value = "")]
+    pub outputs: String,
+
+    #[command(flatten)]
+    pub transition: Transition,
+
+    /// Whether none of the transition options were given, in which case the daemon uses its
+    /// default transition. This is not an option, `main` sets it after parsing
+    #[arg(skip)]
+    pub default_transition: bool,
+}
+
+#[derive(Parser)]
+pub struct DefaultTransition {
+    /// Also store the transition in the cache, so that the daemon still uses it after restarting
+    #[arg(long)]
+    pub persist: bool,
+
+    #[command(flatten)]
+    pub transition: Transition,
+}
+
+/// The default `--transition-step` of every transition type but 'simple'
+pub const DEFAULT_TRANSITION_STEP: std::num::NonZeroU8 = match std::num::NonZeroU8::new(90) {
+    Some(step) => step,
+    None => unreachable!(),
+};
+
+#[derive(Parser)]
+pub struct Transition {
+    ///Sets all the transition options at once, from a named preset.
+    ///
+    ///Any transition option given explicitly (in the command line or through its environment
+    ///variable) overrides the value the preset sets for it. The presets are:
+    ///
+    ///'gentle-fade': --transition-type fade --transition-duration 2
+    /// --transition-bezier .42,0,.58,1
+    ///
+    ///'cinematic-wipe': --transition-type wipe --transition-duration 2.5 --transition-fps 60
+    /// --transition-angle 30 --transition-bezier .65,0,.35,1
+    ///
+    ///'pop': --transition-type grow --transition-pos center --transition-duration 0.8
+    /// --transition-fps 60 --transition-bezier .34,1.56,.64,1
+    ///
+    ///'ripple': --transition-type wave --transition-duration 2.5 --transition-angle 90
+    /// --transition-wave subtle --transition-bezier .42,0,.58,1
+    #[arg(long, env = "SWWW_TRANSITION_PRESET")]
+    pub transition_preset: Option<TransitionPreset>,
+
     ///Sets the type of transition. Default is 'simple', that fades into the new image
     ///
     ///Possible transitions are:
     ///
-    ///none | simple | fade | left | right | top | bottom | wipe | wave | grow | center | any |
-    /// outer | random
+    ///none | simple | fade | dissolve | left | right | top | bottom | wipe | wave | slide | tiles |
+    /// grow | center | any | outer | random
     ///
     ///The 'left', 'right', 'top' and 'bottom' options make the transition happen from that
     ///position to its opposite in the screen.
@@ -289,11 +992,21 @@ pub struct Img {
     ///'fade' is similar to 'simple' but the fade is controlled through the --transition-bezier
     /// flag
     ///
+    ///'dissolve' switches individual pixels over to the new image in a noise pattern, also
+    /// controlled through the --transition-bezier flag
+    ///
     ///'wipe' is similar to 'left' but allows you to specify the angle for transition with the
     /// `--transition-angle` flag.
     ///
     ///'wave' is similar to 'wipe' sweeping line is wavy
     ///
+    ///'slide' pushes the old image off the screen while the new one slides in behind it, in the
+    /// direction of `--transition-angle`. It is controlled through the --transition-bezier flag
+    ///
+    ///'tiles' splits the screen into square tiles, each filling in with the new image from its
+    /// center. Tiles closer to `--transition-pos` start first, and `--transition-tile-size` sets
+    /// their size
+    ///
     ///'grow' causes a growing circle to transition across the screen and allows changing the
     /// circle's center position with the `--transition-pos` flag.
     ///
@@ -315,11 +1028,13 @@ pub struct Img {
     ///Larger values will make the transition faster, but more abrupt. A value of 255 will always
     ///switch to the new image immediately.
     ///
-    /// This defaults to 2 when transition-type is 'simple', and 90 otherwise
+    /// This defaults to 2 when transition-type is 'simple', and 90 otherwise. Those are meant for
+    /// 30 fps: if this is not given, the daemon scales them for the fps of each output, so that
+    /// transitions look the same at any frame rate
     #[arg(
         long,
         env = "SWWW_TRANSITION_STEP",
-        default_value = "90",
+        default_value_t = DEFAULT_TRANSITION_STEP,
         default_value_if("transition_type", "simple", "2")
     )]
     pub transition_step: std::num::NonZeroU8,
@@ -336,18 +1051,42 @@ pub struct Img {
     ///
     ///Also note this is **different** from the transition-step. That one controls by how much we
     ///approach the new image every frame.
-    #[arg(long, env = "SWWW_TRANSITION_FPS", default_value = "30")]
+    ///
+    ///If neither this nor '--transition-fps-from-output' are given, the daemon uses the refresh rate
+    ///of each output, lowered (down to 30) for outputs with so many pixels that it would be too
+    ///costly, like 4K ones above 60 Hz
+    // 65535 is how we ask the daemon to pick the fps, so it cannot be asked for
+    #[arg(long, env = "SWWW_TRANSITION_FPS", default_value = "30", value_parser = clap::value_parser!(u16).range(1..utils::ipc::Transition::FPS_AUTO as i64))]
     pub transition_fps: u16,
 
-    ///This is used for the 'wipe' and 'wave' transitions. It controls the angle of the wipe
+    ///Use each output's refresh rate as the frame rate for the transition, instead of
+    ///'--transition-fps'.
+    ///
+    ///Outputs with different refresh rates each transition at their own. If the compositor did not
+    ///tell the daemon an output's refresh rate, it uses 60.
+    #[arg(
+        long,
+        env = "SWWW_TRANSITION_FPS_FROM_OUTPUT",
+        default_value = "false",
+        conflicts_with = "transition_fps"
+    )]
+    pub transition_fps_from_output: bool,
+
+    ///This is used for the 'wipe', 'wave' and 'slide' transitions. It controls the angle of the wipe
     ///
     ///Note that the angle is in degrees, where '0' is right to left and '90' is top to bottom,
     /// and '270' bottom to top
-    #[arg(long, env = "SWWW_TRANSITION_ANGLE", default_value = "45")]
+    ///
+    ///It can also be the side (or corner) of the screen the transition comes from, like the
+    /// 'left', 'right', 'top' and 'bottom' transition types: 'right' | 'top-right' | 'top' |
+    /// 'top-left' | 'left' | 'bottom-left' | 'bottom' | 'bottom-right', or their compass
+    /// equivalents: 'east' | 'north-east' | 'north' | ... These always refer to the screen, so
+    /// '--invert-y' does not change them
+    #[arg(long, env = "SWWW_TRANSITION_ANGLE", default_value = "45", value_parser = parse_angle)]
     pub transition_angle: f64,
 
-    ///This is only used for the 'grow','outer' transitions. It controls the center of circle
-    /// (default is 'center').
+    ///This is only used for the 'grow','outer' and 'tiles' transitions. It controls the center of
+    /// circle, or where the tiles start from (default is 'center').
     ///
     ///Position values can be given in both percentage values and pixel values:
     ///  float values are interpreted as percentages and integer values as pixel values
@@ -360,6 +1099,10 @@ pub struct Img {
     #[arg(long, env = "SWWW_TRANSITION_POS", default_value = "center", value_parser=parse_coords)]
     pub transition_pos: CliPosition,
 
+    /// inverts the x position sent in 'transition_pos' flag, so that it starts from the right
+    #[arg(long, env = "INVERT_X", default_value = "false")]
+    pub invert_x: bool,
+
     /// inverts the y position sent in 'transiiton_pos' flag
     #[arg(long, env = "INVERT_Y", default_value = "false")]
     pub invert_y: bool,
@@ -368,15 +1111,138 @@ pub struct Img {
     ///https://cubic-bezier.com is a good website to get these values from
     ///
     ///eg: 0.0,0.0,1.0,1.0 for linear animation
+    ///
+    ///The x values (first and third numbers) must be between 0 and 1, otherwise the curve would
+    ///go back in time. The y values may be outside of that range.
     #[arg(long, env = "SWWW_TRANSITION_BEZIER", default_value = ".54,0,.34,.99", value_parser = parse_bezier)]
     pub transition_bezier: (f32, f32, f32, f32),
 
     ///currently only used for 'wave' transition to control the width and height of each wave
+    ///
+    ///Besides two numbers, this also accepts the presets 'subtle' (40,5) and 'strong' (12,60).
+    ///The width is clamped to [1, 500] and the height to [0, 200]
     #[arg(long, env = "SWWW_TRANSITION_WAVE", default_value = "20,20", value_parser = parse_wave)]
     pub transition_wave: (f32, f32),
+
+    ///only used for the 'fade' transition. Keeps playing the animation currently being displayed
+    ///while fading out of it, instead of freezing it. If the new image is animated as well, it
+    ///starts playing during the fade, too
+    #[arg(long, env = "SWWW_TRANSITION_LIVE", default_value = "false")]
+    pub transition_live: bool,
+
+    ///only used for the 'tiles' transition. The side of each tile, in pixels
+    #[arg(long, env = "SWWW_TRANSITION_TILE_SIZE", default_value = "64")]
+    pub transition_tile_size: std::num::NonZeroU16,
+
+    /// Whether neither '--transition-fps' nor '--transition-fps-from-output' were given (nor set
+    /// by the preset), so that the daemon picks the fps for each output. `main` sets it
+    #[arg(skip)]
+    pub auto_fps: bool,
+
+    /// Whether '--transition-step' was not given, so that the daemon scales it for the fps of each
+    /// output. `main` sets it
+    #[arg(skip)]
+    pub auto_step: bool,
+}
+
+fn parse_namespace(raw: &str) -> Result<String, String> {
+    if raw.contains('/') {
+        return Err("namespace must not contain '/'".to_string());
+    }
+    Ok(raw.to_string())
+}
+
+fn parse_timeout(raw: &str) -> Result<std::time::Duration, String> {
+    raw.parse::<f32>()
+        .ok()
+        .and_then(|secs| std::time::Duration::try_from_secs_f32(secs).ok())
+        .filter(|timeout| !timeout.is_zero())
+        .ok_or_else(|| format!("invalid timeout: {raw}. Expected a positive number of seconds"))
+}
+
+fn parse_duration(raw: &str) -> Result<std::time::Duration, String> {
+    raw.parse::<f32>()
+        .ok()
+        .and_then(|secs| std::time::Duration::try_from_secs_f32(secs).ok())
+        .filter(|duration| !duration.is_zero())
+        .ok_or_else(|| format!("invalid duration: {raw}. Expected a positive number of seconds"))
+}
+
+fn parse_night_brightness(raw: &str) -> Result<f32, String> {
+    let range = utils::ipc::DimSchedule::NIGHT_BRIGHTNESS;
+    match raw.parse::<f32>() {
+        Ok(brightness) if range.contains(&brightness) => Ok(brightness),
+        _ => Err(format!(
+            "invalid brightness: {raw}. Expected a number between {} and {}",
+            range.start(),
+            range.end()
+        )),
+    }
+}
+
+/// `HH:MM`, in minutes since midnight
+fn parse_time_of_day(raw: &str) -> Result<u16, String> {
+    let err = || format!("invalid time: {raw}. Expected HH:MM, like 07:30 or 19:00");
+    let (hours, minutes) = raw.split_once(':').ok_or_else(err)?;
+    let hours = hours.trim().parse::<u16>().map_err(|_| err())?;
+    let minutes = minutes.trim().parse::<u16>().map_err(|_| err())?;
+    if hours >= 24 || minutes >= 60 {
+        return Err(err());
+    }
+    Ok(hours * 60 + minutes)
+}
+
+/// `latitude,longitude`, in degrees
+fn parse_location(raw: &str) -> Result<(f32, f32), String> {
+    let values = raw
+        .split(',')
+        .map(|s| s.trim().parse::<f32>().map_err(|e| format!("{s:?}: {e}")))
+        .collect::<Result<Vec<_>, _>>()?;
+    match values[..] {
+        [latitude, longitude]
+            if (-90.0..=90.0).contains(&latitude) && (-180.0..=180.0).contains(&longitude) =>
+        {
+            Ok((latitude, longitude))
+        }
+        [_, _] => Err(
+            "the latitude must be between -90 and 90, and the longitude between -180 and 180"
+                .to_string(),
+        ),
+        _ => Err("expected 2 values: latitude,longitude".to_string()),
+    }
+}
+
+fn parse_scale_filter(raw: &str) -> Result<ScaleFilter, String> {
+    let raw = raw.trim();
+    let (cmp, scale) = if let Some(scale) = raw.strip_prefix("<=") {
+        (ScaleCmp::LessOrEqual, scale)
+    } else if let Some(scale) = raw.strip_prefix(">=") {
+        (ScaleCmp::GreaterOrEqual, scale)
+    } else if let Some(scale) = raw.strip_prefix('<') {
+        (ScaleCmp::Less, scale)
+    } else if let Some(scale) = raw.strip_prefix('>') {
+        (ScaleCmp::Greater, scale)
+    } else if let Some(scale) = raw.strip_prefix('=') {
+        (ScaleCmp::Equal, scale)
+    } else {
+        (ScaleCmp::Equal, raw)
+    };
+    match scale.trim().parse::<f32>() {
+        Ok(scale) if scale.is_finite() && scale > 0.0 => Ok(ScaleFilter { cmp, scale }),
+        _ => Err(format!(
+            "invalid scale filter '{raw}': expected a positive scale, optionally preceded by \
+             '<', '<=', '=', '>=' or '>'"
+        )),
+    }
 }
 
 fn parse_wave(raw: &str) -> Result<(f32, f32), String> {
+    match raw {
+        "subtle" => return Ok((40.0, 5.0)),
+        "strong" => return Ok((12.0, 60.0)),
+        _ => (),
+    }
+
     let mut iter = raw.split(',');
     let mut parse = || {
         iter.next()
@@ -385,9 +1251,49 @@ fn parse_wave(raw: &str) -> Result<(f32, f32), String> {
     };
 
     let parsed = (parse()?, parse()?);
+    if !parsed.0.is_finite() || !parsed.1.is_finite() {
+        return Err("Invalid wave: both values must be finite numbers".to_string());
+    }
     Ok(parsed)
 }
 
+/// `x,y,width,height`, where the width and height cannot be 0
+fn parse_region(raw: &str) -> Result<(u32, u32, u32, u32), String> {
+    let values = raw
+        .split(',')
+        .map(|s| s.trim().parse::<u32>().map_err(|e| format!("{s:?}: {e}")))
+        .collect::<Result<Vec<_>, _>>()?;
+    match values[..] {
+        [_, _, 0, _] | [_, _, _, 0] => Err("the width and height must not be 0".to_string()),
+        [x, y, width, height] => Ok((x, y, width, height)),
+        _ => Err("expected 4 values: x,y,width,height".to_string()),
+    }
+}
+
+/// An angle in degrees, or the side (or corner) of the screen the transition comes from, with
+/// optional dashes ('top-left', 'north-west' and 'northwest' are the same)
+fn parse_angle(raw: &str) -> Result<f64, String> {
+    let keyword = raw.replace('-', "").to_ascii_lowercase();
+    let angle = match keyword.as_str() {
+        "right" | "east" => 0.0,
+        "topright" | "northeast" => 45.0,
+        "top" | "north" => 90.0,
+        "topleft" | "northwest" => 135.0,
+        "left" | "west" => 180.0,
+        "bottomleft" | "southwest" => 225.0,
+        "bottom" | "south" => 270.0,
+        "bottomright" | "southeast" => 315.0,
+        _ => {
+            return raw.parse::<f64>().map_err(|_| {
+                format!(
+                "expected an angle in degrees, or a direction like 'left' or 'north', got '{raw}'"
+            )
+            })
+        }
+    };
+    Ok(angle)
+}
+
 fn parse_bezier(raw: &str) -> Result<(f32, f32, f32, f32), String> {
     let mut iter = raw.split(',');
     let mut parse = || {
@@ -400,14 +1306,53 @@ fn parse_bezier(raw: &str) -> Result<(f32, f32, f32, f32), String> {
     if parsed == (0.0, 0.0, 0.0, 0.0) {
         return Err("Invalid bezier curve: 0,0,0,0 (try using 0,0,1,1 instead)".to_string());
     }
+    if ![parsed.0, parsed.1, parsed.2, parsed.3]
+        .iter()
+        .all(|v| v.is_finite())
+    {
+        return Err("Invalid bezier curve: all values must be finite numbers".to_string());
+    }
+    // the curve's x axis is time, so it must always go forward
+    if !(0.0..=1.0).contains(&parsed.0) || !(0.0..=1.0).contains(&parsed.2) {
+        return Err(format!(
+            "Invalid bezier curve: x values must be between 0 and 1, got {} and {}",
+            parsed.0, parsed.2
+        ));
+    }
     Ok(parsed)
 }
 
+/// Extensions of the files we decode as videos, instead of images
+const VIDEO_EXTENSIONS: [&str; 7] = ["mp4", "m4v", "mkv", "webm", "mov", "avi", "ogv"];
+
+/// Extensions of the archives we read images from, with `<archive>#<entry>`
+const ARCHIVE_EXTENSIONS: [&str; 2] = ["zip", "tar"];
+
 pub fn parse_image(raw: &str) -> Result<CliImage, String> {
+    if raw.starts_with("http://") || raw.starts_with("https://") {
+        return Ok(CliImage::Url(raw.to_string()));
+    }
     let path = PathBuf::from(raw);
     if path.exists() {
+        let is_video = path.extension().is_some_and(|ext| {
+            VIDEO_EXTENSIONS
+                .iter()
+                .any(|video| ext.eq_ignore_ascii_case(video))
+        });
+        if is_video {
+            return Ok(CliImage::Video(path));
+        }
+        if path
+            .extension()
+            .is_some_and(|ext| ext == utils::precomputed::EXTENSION)
+        {
+            return Ok(CliImage::Precomputed(path));
+        }
         return Ok(CliImage::Path(path));
     }
+    if let Some(image) = parse_archive_entry(raw) {
+        return Ok(image);
+    }
     if let Some(color) = raw.strip_prefix("0x") {
         if let Ok(color) = from_hex(color) {
             return Ok(CliImage::Color(color));
@@ -416,6 +1361,23 @@ pub fn parse_image(raw: &str) -> Result<CliImage, String> {
     Err(format!("Path '{}' does not exist", raw))
 }
 
+/// `<archive>#<entry>`, where `<archive>` is an existing zip or tar file. Both may contain '#'
+/// themselves, so we try every one of them
+fn parse_archive_entry(raw: &str) -> Option<CliImage> {
+    raw.match_indices('#').find_map(|(i, _)| {
+        let (archive, entry) = (PathBuf::from(&raw[..i]), &raw[i + 1..]);
+        let is_archive = archive.extension().is_some_and(|ext| {
+            ARCHIVE_EXTENSIONS
+                .iter()
+                .any(|archive| ext.eq_ignore_ascii_case(archive))
+        });
+        (is_archive && !entry.is_empty() && archive.is_file()).then(|| CliImage::Archive {
+            archive,
+            entry: entry.to_string(),
+        })
+    })
+}
+
 // parses Percents and numbers in format of "<coord1>,<coord2>"
 fn parse_coords(raw: &str) -> Result<CliPosition, String> {
     let coords = raw.split(',').map(|s| s.trim()).collect::<Vec<&str>>();
@@ -532,4 +1494,177 @@ mod tests {
         let color = from_hex("000000").unwrap();
         assert_eq!(color, [0, 0, 0]);
     }
+
+    #[test]
+    fn should_convert_colors_with_alpha_from_hex() {
+        assert_eq!(from_hex_alpha("101010").unwrap(), [16, 16, 16, 255]);
+        assert_eq!(from_hex_alpha("10101080").unwrap(), [16, 16, 16, 128]);
+        assert_eq!(from_hex_alpha("ffffff00").unwrap(), [255, 255, 255, 0]);
+        assert!(from_hex_alpha("1010108").is_err());
+    }
+
+    #[test]
+    fn should_parse_urls() {
+        assert!(matches!(
+            parse_image("https://example.com/wall.png"),
+            Ok(CliImage::Url(url)) if url == "https://example.com/wall.png"
+        ));
+        assert!(matches!(
+            parse_image("http://example.com/wall.png"),
+            Ok(CliImage::Url(_))
+        ));
+        assert!(parse_image("ftp://example.com/wall.png").is_err());
+    }
+
+    #[test]
+    fn should_detect_videos() {
+        let dir = std::env::temp_dir().join(format!("swww-test-videos-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let video = dir.join("clip.MP4");
+        let image = dir.join("wall.png");
+        let precomputed = dir.join("wall.swww");
+        std::fs::write(&video, []).unwrap();
+        std::fs::write(&image, []).unwrap();
+        std::fs::write(&precomputed, []).unwrap();
+
+        assert!(matches!(
+            parse_image(video.to_str().unwrap()),
+            Ok(CliImage::Video(_))
+        ));
+        assert!(matches!(
+            parse_image(image.to_str().unwrap()),
+            Ok(CliImage::Path(_))
+        ));
+        assert!(matches!(
+            parse_image(precomputed.to_str().unwrap()),
+            Ok(CliImage::Precomputed(_))
+        ));
+
+        let archive = dir.join("theme#1.zip");
+        std::fs::write(&archive, []).unwrap();
+        assert!(matches!(
+            parse_image(&format!("{}#dir/sun#set.png", archive.display())),
+            Ok(CliImage::Archive { archive: a, entry }) if a == archive && entry == "dir/sun#set.png"
+        ));
+        assert!(parse_image(&format!("{}#", archive.display())).is_err());
+        assert!(parse_image(&format!("{}#a.png", image.display())).is_err());
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn should_filter_scales() {
+        let hidpi = parse_scale_filter(">1").unwrap();
+        assert!(hidpi.matches(2.0));
+        assert!(hidpi.matches(1.25));
+        assert!(!hidpi.matches(1.0));
+
+        let exact = parse_scale_filter("1.5").unwrap();
+        assert_eq!(exact.cmp, ScaleCmp::Equal);
+        assert!(exact.matches(180.0 / 120.0));
+        assert!(!exact.matches(2.0));
+
+        assert!(parse_scale_filter("<=1").unwrap().matches(1.0));
+        assert!(!parse_scale_filter("<1").unwrap().matches(1.0));
+        assert!(parse_scale_filter("=0").is_err());
+        assert!(parse_scale_filter(">x").is_err());
+    }
+
+    #[test]
+    fn should_parse_waves() {
+        assert_eq!(parse_wave("20,20").unwrap(), (20.0, 20.0));
+        assert_eq!(parse_wave("subtle").unwrap(), (40.0, 5.0));
+        assert_eq!(parse_wave("strong").unwrap(), (12.0, 60.0));
+        assert!(parse_wave("20").is_err(), "accepting too few values");
+        assert!(parse_wave("NaN,20").is_err(), "accepting NaN");
+        assert!(parse_wave("20,inf").is_err(), "accepting infinity");
+    }
+
+    #[test]
+    fn should_parse_angles() {
+        assert_eq!(parse_angle("30").unwrap(), 30.0);
+        assert_eq!(parse_angle("-12.5").unwrap(), -12.5);
+        for (keywords, angle) in [
+            (["right", "east"], 0.0),
+            (["top-right", "north-east"], 45.0),
+            (["top", "north"], 90.0),
+            (["top-left", "north-west"], 135.0),
+            (["left", "west"], 180.0),
+            (["bottom-left", "south-west"], 225.0),
+            (["bottom", "south"], 270.0),
+            (["bottom-right", "south-east"], 315.0),
+        ] {
+            for keyword in keywords {
+                assert_eq!(parse_angle(keyword).unwrap(), angle, "{keyword}");
+            }
+        }
+        assert_eq!(parse_angle("NorthEast").unwrap(), 45.0);
+        assert!(parse_angle("up").is_err(), "accepting unknown direction");
+    }
+
+    #[test]
+    fn should_parse_regions() {
+        assert_eq!(parse_region("0,30,1920,1050").unwrap(), (0, 30, 1920, 1050));
+        assert_eq!(parse_region("10, 0, 800, 600").unwrap(), (10, 0, 800, 600));
+        assert!(parse_region("0,0,1920").is_err(), "accepting 3 values");
+        assert!(
+            parse_region("0,0,1920,1080,1").is_err(),
+            "accepting 5 values"
+        );
+        assert!(
+            parse_region("0,0,0,1080").is_err(),
+            "accepting an empty region"
+        );
+        assert!(
+            parse_region("-1,0,1920,1080").is_err(),
+            "accepting negative values"
+        );
+    }
+
+    #[test]
+    fn should_parse_times_of_day() {
+        assert_eq!(parse_time_of_day("07:30").unwrap(), 7 * 60 + 30);
+        assert_eq!(parse_time_of_day("0:00").unwrap(), 0);
+        assert_eq!(parse_time_of_day("23:59").unwrap(), 23 * 60 + 59);
+        assert!(parse_time_of_day("24:00").is_err(), "accepting hour 24");
+        assert!(parse_time_of_day("12:60").is_err(), "accepting minute 60");
+        assert!(parse_time_of_day("1230").is_err(), "accepting no colon");
+    }
+
+    #[test]
+    fn should_parse_locations() {
+        assert_eq!(parse_location("-33.9,18.4").unwrap(), (-33.9, 18.4));
+        assert!(parse_location("91,0").is_err(), "accepting latitude 91");
+        assert!(
+            parse_location("0,-181").is_err(),
+            "accepting longitude -181"
+        );
+        assert!(parse_location("52.5").is_err(), "accepting a single value");
+    }
+
+    #[test]
+    fn should_accept_valid_beziers() {
+        assert_eq!(parse_bezier("0,0,1,1").unwrap(), (0.0, 0.0, 1.0, 1.0));
+        assert_eq!(
+            parse_bezier(".54,0,.34,.99").unwrap(),
+            (0.54, 0.0, 0.34, 0.99)
+        );
+        assert_eq!(parse_bezier("0.5,-1,0.5,2").unwrap(), (0.5, -1.0, 0.5, 2.0));
+    }
+
+    #[test]
+    fn should_reject_invalid_beziers() {
+        assert!(
+            parse_bezier("0,0,0,0").is_err(),
+            "accepting degenerate curve"
+        );
+        assert!(parse_bezier("0,0,1").is_err(), "accepting too few values");
+        assert!(parse_bezier("-0.1,0,1,1").is_err(), "accepting negative x1");
+        assert!(
+            parse_bezier("0,0,1.5,1").is_err(),
+            "accepting x2 greater than 1"
+        );
+        assert!(parse_bezier("NaN,0,1,1").is_err(), "accepting NaN");
+        assert!(parse_bezier("0,inf,1,1").is_err(), "accepting infinite y1");
+    }
 }
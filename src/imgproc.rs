@@ -1,11 +1,13 @@
 use fast_image_resize::{FilterType, PixelType, ResizeAlg, ResizeOptions, Resizer};
 use image::{
     codecs::{gif::GifDecoder, png::PngDecoder, webp::WebPDecoder},
-    AnimationDecoder, DynamicImage, Frames, GenericImageView, ImageFormat,
+    AnimationDecoder, Frames, ImageFormat,
 };
 use std::{
+    borrow::Borrow,
     io::{stdin, Cursor, Read},
     path::Path,
+    process::{Command, Stdio},
     time::Duration,
 };
 
@@ -18,25 +20,146 @@ use crate::cli::ResizeStrategy;
 
 use super::cli;
 
+/// How long we wait to connect to the server when downloading an image
+const DOWNLOAD_CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+/// How long the whole download may take
+const DOWNLOAD_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// We only turn the beginning of a video into an animation, since every frame has to be kept in
+/// memory (and in the daemon's)
+const MAX_VIDEO_DURATION: Duration = Duration::from_secs(30);
+/// Maximum number of frames we take from a video, regardless of its duration
+const MAX_VIDEO_FRAMES: u32 = 600;
+
 pub struct ImgBuf {
     bytes: Box<[u8]>,
     format: ImageFormat,
     is_animated: bool,
+    /// Where the image came from, for error messages
+    source: String,
 }
 
 impl ImgBuf {
     /// Create a new ImgBuf from a given path. Use - for Stdin
     pub fn new(path: &Path) -> Result<Self, String> {
-        let bytes = if let Some("-") = path.to_str() {
+        let (bytes, source) = if let Some("-") = path.to_str() {
             let mut bytes = Vec::new();
             stdin()
                 .read_to_end(&mut bytes)
                 .map_err(|e| format!("failed to read standard input: {e}"))?;
-            bytes
+            (bytes, "standard input".to_string())
         } else {
-            std::fs::read(path).map_err(|e| format!("failed to read file: {e}"))?
+            let bytes = std::fs::read(path)
+                .map_err(|e| format!("failed to read {}: {e}", path.display()))?;
+            (bytes, path.display().to_string())
+        };
+
+        // a download that never started, for example
+        if bytes.is_empty() {
+            return Err(format!("not a valid image: {source} (it is empty)"));
+        }
+        Self::from_bytes(bytes, source)
+    }
+
+    /// Read the image from the file `entry` of a zip or tar `archive`, without extracting it
+    pub fn from_archive(archive: &Path, entry: &str) -> Result<Self, String> {
+        let bytes = crate::archive::read_entry(archive, entry)?;
+        let source = format!("'{entry}' in {}", archive.display());
+        if bytes.is_empty() {
+            return Err(format!("not a valid image: {source} (it is empty)"));
+        }
+        Self::from_bytes(bytes, source)
+    }
+
+    /// Download the image at `url`.
+    ///
+    /// We do this by calling `curl`, so that we don't have to pull an entire http and tls stack
+    /// just for this.
+    pub fn from_url(url: &str) -> Result<Self, String> {
+        let output = Command::new("curl")
+            .args(["--silent", "--show-error", "--fail", "--location"])
+            .args([
+                "--connect-timeout",
+                &DOWNLOAD_CONNECT_TIMEOUT.as_secs().to_string(),
+            ])
+            .args(["--max-time", &DOWNLOAD_TIMEOUT.as_secs().to_string()])
+            // we append the content type at the end of the body, after a new line
+            .args([
+                "--write-out",
+                "\n%{content_type}",
+                "--output",
+                "-",
+                "--",
+                url,
+            ])
+            .stdin(Stdio::null())
+            .output()
+            .map_err(|e| format!("failed to run curl to download {url} (is it installed?): {e}"))?;
+
+        if !output.status.success() {
+            // see curl's man page, in the EXIT CODES section
+            return Err(match output.status.code() {
+                Some(28) => format!(
+                    "timed out downloading {url} (timeout is {}s)",
+                    DOWNLOAD_TIMEOUT.as_secs()
+                ),
+                _ => format!(
+                    "failed to download {url}: {}",
+                    String::from_utf8_lossy(&output.stderr).trim()
+                ),
+            });
+        }
+
+        let mut bytes = output.stdout;
+        let newline = match bytes.iter().rposition(|b| *b == b'\n') {
+            Some(i) => i,
+            None => return Err(format!("curl returned malformed output for {url}")),
         };
+        let content_type = String::from_utf8_lossy(&bytes[newline + 1..]).to_string();
+        bytes.truncate(newline);
+
+        // some servers don't bother setting the content type, so we only reject a known bad one
+        if !content_type.is_empty()
+            && !content_type.starts_with("image/")
+            && !content_type.starts_with("application/octet-stream")
+        {
+            return Err(format!(
+                "{url} is not an image (its content type is '{content_type}')"
+            ));
+        }
+
+        Self::from_bytes(bytes, url.to_string())
+    }
 
+    /// Turn the first `MAX_VIDEO_DURATION` (and at most `MAX_VIDEO_FRAMES`) of the video at
+    /// `path` into an animation.
+    ///
+    /// We have `ffmpeg` transcode it into an apng, which we already know how to animate, and which
+    /// keeps each frame's timing.
+    pub fn from_video(path: &Path) -> Result<Self, String> {
+        let output = Command::new("ffmpeg")
+            .args(["-nostdin", "-loglevel", "error", "-i"])
+            .arg(path)
+            .args(["-t", &MAX_VIDEO_DURATION.as_secs().to_string()])
+            .args(["-frames:v", &MAX_VIDEO_FRAMES.to_string()])
+            .args(["-an", "-pix_fmt", "rgb24", "-plays", "0", "-f", "apng", "-"])
+            .stdin(Stdio::null())
+            .output()
+            .map_err(|e| {
+                format!("failed to run ffmpeg to decode {path:?} (is it installed?): {e}")
+            })?;
+
+        if !output.status.success() {
+            return Err(format!(
+                "failed to decode video {path:?}: {}",
+                String::from_utf8_lossy(&output.stderr).trim()
+            ));
+        }
+
+        Self::from_bytes(output.stdout, path.display().to_string())
+    }
+
+    fn from_bytes(bytes: Vec<u8>, source: String) -> Result<Self, String> {
         let reader = image::io::Reader::new(Cursor::new(&bytes))
             .with_guessed_format()
             .map_err(|e| format!("failed to detect the image's format: {e}"))?;
@@ -45,13 +168,17 @@ impl ImgBuf {
         let is_animated = match format {
             Some(ImageFormat::Gif) => true,
             Some(ImageFormat::WebP) => WebPDecoder::new(Cursor::new(&bytes))
-                .map_err(|e| format!("failed to decode Webp Image: {e}"))?
+                .map_err(|e| format!("not a valid image: {source} ({e})"))?
                 .has_animation(),
             Some(ImageFormat::Png) => PngDecoder::new(Cursor::new(&bytes))
-                .map_err(|e| format!("failed to decode Png Image: {e}"))?
+                .map_err(|e| format!("not a valid image: {source} ({e})"))?
                 .is_apng()
                 .map_err(|e| format!("failed to detect if Png is animated: {e}"))?,
-            None => return Err("Unknown image format".to_string()),
+            None => {
+                return Err(format!(
+                    "not a valid image: {source} (unknown image format)"
+                ))
+            }
             _ => false,
         };
 
@@ -59,6 +186,7 @@ impl ImgBuf {
             format: format.unwrap(), // this is ok because we return err earlier if it is None
             bytes: bytes.into_boxed_slice(),
             is_animated,
+            source,
         })
     }
 
@@ -66,30 +194,59 @@ impl ImgBuf {
         self.is_animated
     }
 
-    /// Decode the ImgBuf into am RgbImage
-    pub fn decode(&self, format: PixelFormat) -> Result<Image, String> {
+    /// The encoded image, as it was read
+    pub fn bytes(&self) -> &[u8] {
+        &self.bytes
+    }
+
+    /// How many times the animation should play, according to the gif's Netscape extension.
+    /// `None` means forever, which is also what we do for every other format, and for gifs that do
+    /// not say (even though browsers play those only once)
+    pub fn loop_count(&self) -> Option<u32> {
+        if self.format != ImageFormat::Gif {
+            return None;
+        }
+        match gif::DecodeOptions::new().read_info(Cursor::new(&self.bytes)) {
+            Ok(decoder) => match decoder.repeat() {
+                gif::Repeat::Finite(n) if n > 0 => Some(n.into()),
+                _ => None,
+            },
+            Err(_) => None,
+        }
+    }
+
+    /// Decode the ImgBuf into am RgbImage. With `dither`, 3 channel formats are dithered down from
+    /// 16 bits per channel (see `ipc::convert_into_dithered`)
+    pub fn decode(&self, format: PixelFormat, dither: bool) -> Result<Image, String> {
         let mut reader = image::io::Reader::new(Cursor::new(&self.bytes));
         reader.set_format(self.format);
         let dynimage = reader
             .decode()
-            .map_err(|e| format!("failed to decode image: {e}"))?;
+            .map_err(|e| format!("not a valid image: {} ({e})", self.source))?;
 
         let width = dynimage.width();
         let height = dynimage.height();
 
-        let bytes = {
-            let mut img = if format.channels() == 3 {
-                dynimage.into_rgb8().into_raw().into_boxed_slice()
+        let channels = format.channels() as usize;
+        let bytes = if dither && channels == 3 {
+            let rgba = dynimage.into_rgba16();
+            let mut bytes = vec![0; width as usize * height as usize * channels].into_boxed_slice();
+            ipc::convert_into_dithered(&rgba, width as usize, &mut bytes, format);
+            bytes
+        } else {
+            // `image` converts straight into the channels we want (for free, if the image already
+            // has them), so all that is left is their order
+            let mut bytes = if channels == 3 {
+                dynimage.into_rgb8().into_raw()
             } else {
-                dynimage.into_rgba8().into_raw().into_boxed_slice()
+                dynimage.into_rgba8().into_raw()
             };
-
             if format.must_swap_r_and_b_channels() {
-                for pixel in img.chunks_exact_mut(format.channels() as usize) {
+                for pixel in bytes.chunks_exact_mut(channels) {
                     pixel.swap(0, 2);
                 }
             }
-            img
+            bytes.into_boxed_slice()
         };
 
         Ok(Image {
@@ -159,8 +316,8 @@ impl Image {
     }
 
     fn from_frame(frame: image::Frame, format: PixelFormat) -> Self {
-        let dynimage = DynamicImage::ImageRgba8(frame.into_buffer());
-        let (width, height) = dynimage.dimensions();
+        let rgba = frame.into_buffer();
+        let (width, height) = rgba.dimensions();
 
         // NOTE: when animating frames, we ALWAYS use 3 channels
 
@@ -169,12 +326,8 @@ impl Image {
             PixelFormat::Rgb | PixelFormat::Xrgb => PixelFormat::Rgb,
         };
 
-        let mut bytes = dynimage.into_rgb8().into_raw().into_boxed_slice();
-        if format.must_swap_r_and_b_channels() {
-            for pixel in bytes.chunks_exact_mut(3) {
-                pixel.swap(0, 2);
-            }
-        }
+        let mut bytes = vec![0; width as usize * height as usize * 3].into_boxed_slice();
+        ipc::convert_into(&rgba, &mut bytes, format);
 
         Self {
             width,
@@ -185,69 +338,183 @@ impl Image {
     }
 }
 
+fn frame_duration(frame: &image::Frame) -> Duration {
+    let (dur_num, dur_div) = frame.delay().numer_denom_ms();
+    Duration::from_millis((dur_num / dur_div).into())
+}
+
+/// Decodes the frames one at a time, as the iterator is consumed
+pub fn decoded_frames(
+    frames: Frames<'_>,
+    format: PixelFormat,
+) -> impl Iterator<Item = (Image, Duration)> + '_ {
+    frames.map_while(Result::ok).map(move |frame| {
+        let duration = frame_duration(&frame);
+        (Image::from_frame(frame, format), duration)
+    })
+}
+
+/// Decodes every frame at once, so that they can be compressed for many output sizes without
+/// decoding them again for each one. Note this keeps all of them in memory.
+pub fn decode_frames(frames: Frames, format: PixelFormat) -> Vec<(Image, Duration)> {
+    decoded_frames(frames, format).collect()
+}
+
 pub fn compress_frames(
-    mut frames: Frames,
+    frames: Frames,
     dim: (u32, u32),
     format: PixelFormat,
     filter: FilterType,
     resize: ResizeStrategy,
+    anchor: &Position,
     color: &[u8; 3],
 ) -> Result<Vec<(BitPack, Duration)>, String> {
-    let mut compressor = Compressor::new();
+    compress_images(
+        decoded_frames(frames, format),
+        dim,
+        format,
+        filter,
+        resize,
+        anchor,
+        color,
+    )
+}
+
+/// Like `compress_frames`, but for frames that were already decoded with `decode_frames`
+pub fn compress_decoded_frames(
+    frames: &[(Image, Duration)],
+    dim: (u32, u32),
+    format: PixelFormat,
+    filter: FilterType,
+    resize: ResizeStrategy,
+    anchor: &Position,
+    color: &[u8; 3],
+) -> Result<Vec<(BitPack, Duration)>, String> {
+    let frames = frames.iter().map(|(img, duration)| (img, *duration));
+    compress_images(frames, dim, format, filter, resize, anchor, color)
+}
+
+fn compress_images<I: Borrow<Image>>(
+    frames: impl Iterator<Item = (I, Duration)>,
+    dim: (u32, u32),
+    format: PixelFormat,
+    filter: FilterType,
+    resize: ResizeStrategy,
+    anchor: &Position,
+    color: &[u8; 3],
+) -> Result<Vec<(BitPack, Duration)>, String> {
+    let mut compressor = FrameCompressor::new(dim, format, filter, resize, anchor, color);
     let mut compressed_frames = Vec::new();
+    let mut empty = true;
+    for (img, duration) in frames {
+        empty = false;
+        compressed_frames.extend(compressor.push(img.borrow(), duration)?);
+    }
+    if empty {
+        return Err("animation has no frames".to_string());
+    }
+    compressed_frames.extend(compressor.finish()?);
+    Ok(compressed_frames)
+}
 
-    // The first frame should always exist
-    let first = frames.next().unwrap().unwrap();
-    let first_duration = first.delay().numer_denom_ms();
-    let mut first_duration = Duration::from_millis((first_duration.0 / first_duration.1).into());
-    let first_img = Image::from_frame(first, format);
-    let first_img = match resize {
-        ResizeStrategy::No => img_pad(&first_img, dim, color)?,
-        ResizeStrategy::Crop => img_resize_crop(&first_img, dim, filter)?,
-        ResizeStrategy::Fit => img_resize_fit(&first_img, dim, filter, color)?,
-    };
+/// Compresses an animation one frame at a time, so that its first frames can be used before the
+/// last ones are even decoded.
+///
+/// Frames identical to the previous one are merged into it, by adding up their durations, so each
+/// compressed frame is only handed out once we know how long it lasts
+pub struct FrameCompressor {
+    compressor: Compressor,
+    dim: (u32, u32),
+    format: PixelFormat,
+    filter: FilterType,
+    resize: ResizeStrategy,
+    /// where to keep the images when cropping them, see `img_resize_crop`
+    anchor: Position,
+    color: [u8; 3],
+    /// The first image, which the last frame goes back to, and how long it lasts
+    first: Option<(Box<[u8]>, Duration)>,
+    /// The last image we pushed
+    canvas: Option<Box<[u8]>>,
+    /// The last compressed frame, which may still grow longer
+    pending: Option<(BitPack, Duration)>,
+}
 
-    let mut canvas: Option<Box<[u8]>> = None;
-    while let Some(Ok(frame)) = frames.next() {
-        let (dur_num, dur_div) = frame.delay().numer_denom_ms();
-        let duration = Duration::from_millis((dur_num / dur_div).into());
+impl FrameCompressor {
+    pub fn new(
+        dim: (u32, u32),
+        format: PixelFormat,
+        filter: FilterType,
+        resize: ResizeStrategy,
+        anchor: &Position,
+        color: &[u8; 3],
+    ) -> Self {
+        Self {
+            compressor: Compressor::new(),
+            dim,
+            format,
+            filter,
+            resize,
+            anchor: anchor.clone(),
+            color: *color,
+            first: None,
+            canvas: None,
+            pending: None,
+        }
+    }
 
-        let img = Image::from_frame(frame, format);
-        let img = match resize {
-            ResizeStrategy::No => img_pad(&img, dim, color)?,
-            ResizeStrategy::Crop => img_resize_crop(&img, dim, filter)?,
-            ResizeStrategy::Fit => img_resize_fit(&img, dim, filter, color)?,
+    /// Adds the next image of the animation. Returns the previous compressed frame, once it is
+    /// done
+    pub fn push(
+        &mut self,
+        img: &Image,
+        duration: Duration,
+    ) -> Result<Option<(BitPack, Duration)>, String> {
+        let img = match self.resize {
+            ResizeStrategy::No => img_pad(img, self.dim, &self.color)?,
+            ResizeStrategy::Crop => img_resize_crop(img, self.dim, self.filter, &self.anchor)?,
+            ResizeStrategy::Fit => img_resize_fit(img, self.dim, self.filter, &self.color)?,
         };
 
-        if let Some(canvas) = canvas.as_ref() {
-            match compressor.compress(canvas, &img, format) {
-                Some(bytes) => compressed_frames.push((bytes, duration)),
-                None => match compressed_frames.last_mut() {
-                    Some(last) => last.1 += duration,
-                    None => first_duration += duration,
-                },
-            }
-        } else {
-            match compressor.compress(&first_img, &img, format) {
-                Some(bytes) => compressed_frames.push((bytes, duration)),
-                None => first_duration += duration,
+        let Some((first_img, first_duration)) = self.first.as_mut() else {
+            self.first = Some((img, duration));
+            return Ok(None);
+        };
+        let prev = self.canvas.as_deref().unwrap_or(first_img);
+        let done = match self.compressor.compress(prev, &img, self.format) {
+            Some(bytes) => self.pending.replace((bytes, duration)),
+            None => {
+                match self.pending.as_mut() {
+                    Some(pending) => pending.1 += duration,
+                    None => *first_duration += duration,
+                }
+                None
             }
-        }
-        canvas = Some(img);
+        };
+        self.canvas = Some(img);
+        Ok(done)
     }
 
-    //Add the first frame we got earlier:
-    if let Some(canvas) = canvas.as_ref() {
-        match compressor.compress(canvas, &first_img, format) {
-            Some(bytes) => compressed_frames.push((bytes, first_duration)),
-            None => match compressed_frames.last_mut() {
-                Some(last) => last.1 += first_duration,
-                None => first_duration += first_duration,
-            },
+    /// Returns the frames we have not handed out yet, including the one going from the last
+    /// image back to the first
+    pub fn finish(mut self) -> Result<Vec<(BitPack, Duration)>, String> {
+        let mut frames = Vec::with_capacity(2);
+        let (Some(canvas), Some((first_img, first_duration))) = (&self.canvas, &self.first) else {
+            return Ok(frames);
+        };
+        match self.compressor.compress(canvas, first_img, self.format) {
+            Some(bytes) => {
+                frames.extend(self.pending.take());
+                frames.push((bytes, *first_duration));
+            }
+            None => {
+                if let Some(mut pending) = self.pending.take() {
+                    pending.1 += *first_duration;
+                    frames.push(pending);
+                }
+            }
         }
+        Ok(frames)
     }
-
-    Ok(compressed_frames)
 }
 
 pub fn make_filter(filter: &cli::Filter) -> fast_image_resize::FilterType {
@@ -382,10 +649,13 @@ pub fn img_resize_fit(
     }
 }
 
+/// Resizes `img` to cover `dimensions`, cropping out what does not fit. What is left is as centered
+/// on `anchor`, a point of the image (see `Position::to_pixel`), as it can be
 pub fn img_resize_crop(
     img: &Image,
     dimensions: (u32, u32),
     filter: FilterType,
+    anchor: &Position,
 ) -> Result<Box<[u8]>, String> {
     let (width, height) = dimensions;
     let resized_img = if (img.width, img.height) != (width, height) {
@@ -408,7 +678,11 @@ pub fn img_resize_crop(
         let mut resizer = Resizer::new();
         let options = ResizeOptions::new()
             .resize_alg(ResizeAlg::Convolution(filter))
-            .fit_into_destination(Some((0.5, 0.5)));
+            .fit_into_destination(Some(crop_centering(
+                (img.width, img.height),
+                dimensions,
+                anchor,
+            )));
 
         if let Err(e) = resizer.resize(&src, &mut dst, Some(&options)) {
             return Err(e.to_string());
@@ -422,11 +696,81 @@ pub fn img_resize_crop(
     Ok(resized_img)
 }
 
-pub fn make_transition(img: &cli::Img) -> ipc::Transition {
-    let mut angle = img.transition_angle;
-    let step = img.transition_step;
+/// The `centering` fast_image_resize must crop an image of `img_dim` with, to keep the part of it
+/// closest to being centered on `anchor`. For each axis, 0 keeps the start of the image and 1 its
+/// end
+fn crop_centering(img_dim: (u32, u32), dimensions: (u32, u32), anchor: &Position) -> (f64, f64) {
+    let scale = f64::max(
+        dimensions.0 as f64 / img_dim.0 as f64,
+        dimensions.1 as f64 / img_dim.1 as f64,
+    );
+    let (x, y) = anchor.to_pixel(img_dim, false, false);
+    let centering = |anchor: f32, len: u32, dst: u32| {
+        // how much of the image we keep and crop out, in its own pixels
+        let kept = dst as f64 / scale;
+        let cropped = len as f64 - kept;
+        if cropped < 1.0 {
+            return 0.5;
+        }
+        ((anchor as f64 - kept / 2.0) / cropped).clamp(0.0, 1.0)
+    };
+    (
+        centering(x, img_dim.0, dimensions.0),
+        centering(y, img_dim.1, dimensions.1),
+    )
+}
 
-    let x = match img.transition_pos.x {
+/// Stretches `img` to cover a `layout` of outputs, and returns the part of it in `region` (given
+/// as x, y, width and height, relative to the layout's top left corner), resized to `dimensions`
+pub fn img_span(
+    img: &Image,
+    layout: (u32, u32),
+    region: (u32, u32, u32, u32),
+    dimensions: (u32, u32),
+    filter: FilterType,
+) -> Result<Box<[u8]>, String> {
+    let scale = f64::max(
+        layout.0 as f64 / img.width as f64,
+        layout.1 as f64 / img.height as f64,
+    );
+    // the parts of the scaled image that fall outside of the layout, on each side
+    let offset_x = (img.width as f64 * scale - layout.0 as f64) / 2.0;
+    let offset_y = (img.height as f64 * scale - layout.1 as f64) / 2.0;
+
+    let x = ((region.0 as f64 + offset_x) / scale).round() as u32;
+    let y = ((region.1 as f64 + offset_y) / scale).round() as u32;
+    let width = (region.2 as f64 / scale).round().max(1.0) as u32;
+    let height = (region.3 as f64 / scale).round().max(1.0) as u32;
+
+    // the region already has the aspect ratio of `dimensions`, so there is nothing left to crop
+    img_resize_crop(
+        &img.crop(x, y, width, height),
+        dimensions,
+        filter,
+        &Position::CENTER,
+    )
+}
+
+pub fn make_position(position: &cli::CliPosition) -> Position {
+    let coord = |coord: &cli::CliCoord| match *coord {
+        cli::CliCoord::Percent(p) => Coord::Percent(p),
+        cli::CliCoord::Pixel(p) => Coord::Pixel(p),
+    };
+    Position::new(coord(&position.x), coord(&position.y))
+}
+
+pub fn make_transition(transition: &cli::Transition) -> ipc::Transition {
+    let mut angle = transition.transition_angle;
+    let wave = ipc::Transition::clamp_wave(transition.transition_wave);
+    if wave != transition.transition_wave {
+        eprintln!(
+            "Warning: transition wave {:?} is outside of the supported range, using {wave:?}",
+            transition.transition_wave
+        );
+    }
+    let step = transition.transition_step;
+
+    let x = match transition.transition_pos.x {
         cli::CliCoord::Percent(x) => {
             if !(0.0..=1.0).contains(&x) {
                 println!(
@@ -438,7 +782,7 @@ pub fn make_transition(img: &cli::Img) -> ipc::Transition {
         cli::CliCoord::Pixel(x) => Coord::Pixel(x),
     };
 
-    let y = match img.transition_pos.y {
+    let y = match transition.transition_pos.y {
         cli::CliCoord::Percent(y) => {
             if !(0.0..=1.0).contains(&y) {
                 println!(
@@ -452,10 +796,13 @@ pub fn make_transition(img: &cli::Img) -> ipc::Transition {
 
     let mut pos = Position::new(x, y);
 
-    let transition_type = match img.transition_type {
+    let transition_type = match transition.transition_type {
         cli::TransitionType::None => ipc::TransitionType::None,
         cli::TransitionType::Simple => ipc::TransitionType::Simple,
         cli::TransitionType::Fade => ipc::TransitionType::Fade,
+        cli::TransitionType::Dissolve => ipc::TransitionType::Dissolve,
+        cli::TransitionType::Slide => ipc::TransitionType::Slide,
+        cli::TransitionType::Tiles => ipc::TransitionType::Tiles,
         cli::TransitionType::Wipe => ipc::TransitionType::Wipe,
         cli::TransitionType::Outer => ipc::TransitionType::Outer,
         cli::TransitionType::Grow => ipc::TransitionType::Grow,
@@ -497,25 +844,141 @@ pub fn make_transition(img: &cli::Img) -> ipc::Transition {
                 Coord::Percent(fastrand::f32()),
             );
             angle = fastrand::f64();
-            match fastrand::u8(0..4) {
+            match fastrand::u8(0..7) {
                 0 => ipc::TransitionType::Simple,
                 1 => ipc::TransitionType::Wipe,
                 2 => ipc::TransitionType::Outer,
                 3 => ipc::TransitionType::Grow,
+                4 => ipc::TransitionType::Dissolve,
+                5 => ipc::TransitionType::Slide,
+                6 => ipc::TransitionType::Tiles,
                 _ => unreachable!(),
             }
         }
     };
 
     ipc::Transition {
-        duration: img.transition_duration,
+        duration: transition.transition_duration,
         step,
-        fps: img.transition_fps,
-        bezier: img.transition_bezier,
+        auto_step: transition.auto_step,
+        fps: if transition.transition_fps_from_output {
+            ipc::Transition::FPS_FROM_OUTPUT
+        } else if transition.auto_fps {
+            ipc::Transition::FPS_AUTO
+        } else {
+            transition.transition_fps
+        },
+        bezier: transition.transition_bezier,
         angle,
         pos,
         transition_type,
-        wave: img.transition_wave,
-        invert_y: img.invert_y,
+        wave,
+        invert_x: transition.invert_x,
+        invert_y: transition.invert_y,
+        live: transition.transition_live,
+        tile_size: transition.transition_tile_size,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::{
+        codecs::gif::{GifEncoder, Repeat},
+        Delay, Frame, RgbaImage,
+    };
+
+    fn gif(repeat: Option<Repeat>) -> ImgBuf {
+        let mut bytes = Vec::new();
+        {
+            let mut encoder = GifEncoder::new(&mut bytes);
+            if let Some(repeat) = repeat {
+                encoder.set_repeat(repeat).unwrap();
+            }
+            for color in [[0, 0, 0, 255], [255, 255, 255, 255]] {
+                let frame = Frame::from_parts(
+                    RgbaImage::from_pixel(2, 2, image::Rgba(color)),
+                    0,
+                    0,
+                    Delay::from_numer_denom_ms(100, 1),
+                );
+                encoder.encode_frame(frame).unwrap();
+            }
+        }
+        ImgBuf::from_bytes(bytes, "test.gif".to_string()).unwrap()
+    }
+
+    #[test]
+    fn should_read_gif_loop_count() {
+        assert_eq!(gif(Some(Repeat::Finite(1))).loop_count(), Some(1));
+        assert_eq!(gif(Some(Repeat::Finite(3))).loop_count(), Some(3));
+        assert_eq!(gif(Some(Repeat::Infinite)).loop_count(), None);
+        assert_eq!(gif(None).loop_count(), None);
+    }
+
+    /// Writes `bytes` to a temporary file and tries to read it as an image, decoding it too
+    fn load_file(name: &str, bytes: &[u8]) -> Result<Image, String> {
+        let path = std::env::temp_dir().join(format!("swww-test-{}-{name}", std::process::id()));
+        std::fs::write(&path, bytes).unwrap();
+        let result = ImgBuf::new(&path).and_then(|imgbuf| imgbuf.decode(PixelFormat::Xrgb, false));
+        std::fs::remove_file(&path).unwrap();
+        result
+    }
+
+    #[test]
+    fn should_reject_invalid_images() {
+        for (name, bytes) in [
+            ("empty.png", &b""[..]),
+            ("text.png", &b"this is not an image\n"[..]),
+            // a png signature, with nothing after it
+            ("truncated.png", &b"\x89PNG\r\n\x1a\n\0\0\0\x0dIHDR"[..]),
+        ] {
+            match load_file(name, bytes) {
+                Ok(_) => panic!("{name} should not be a valid image"),
+                Err(e) => assert!(
+                    e.starts_with("not a valid image: ") && e.contains(name),
+                    "unexpected error for {name}: {e}"
+                ),
+            }
+        }
+
+        let mut png = Vec::new();
+        image::RgbImage::from_pixel(2, 2, image::Rgb([1, 2, 3]))
+            .write_to(&mut Cursor::new(&mut png), ImageFormat::Png)
+            .unwrap();
+        assert!(load_file("valid.png", &png).is_ok());
+    }
+
+    #[test]
+    fn crops_around_the_anchor() {
+        let at = |x, y| Position::new(x, y);
+        // a landscape image in a square: only the x axis is cropped
+        let dim = ((200, 100), (100, 100));
+        assert_eq!(crop_centering(dim.0, dim.1, &Position::CENTER), (0.5, 0.5));
+        let left = at(Coord::Percent(0.0), Coord::Percent(0.5));
+        assert_eq!(crop_centering(dim.0, dim.1, &left), (0.0, 0.5));
+        let right = at(Coord::Percent(1.0), Coord::Percent(0.0));
+        assert_eq!(crop_centering(dim.0, dim.1, &right), (1.0, 0.5));
+        // centered on pixel 75, so the kept part starts at pixel 25
+        let pixel = at(Coord::Pixel(75.0), Coord::Percent(0.5));
+        assert_eq!(crop_centering(dim.0, dim.1, &pixel), (0.25, 0.5));
+
+        // y grows from the bottom, like in `--transition-pos`
+        let top = at(Coord::Percent(0.5), Coord::Percent(1.0));
+        assert_eq!(crop_centering((100, 200), (50, 50), &top), (0.5, 0.0));
+
+        // the left half is black and the right one white
+        let img = Image {
+            width: 4,
+            height: 2,
+            format: PixelFormat::Rgb,
+            bytes: (0..8)
+                .flat_map(|i| [if i % 4 < 2 { 0 } else { 255 }; 3])
+                .collect(),
+        };
+        let cropped = img_resize_crop(&img, (2, 2), FilterType::Box, &left).unwrap();
+        assert!(cropped.iter().all(|&b| b == 0));
+        let cropped = img_resize_crop(&img, (2, 2), FilterType::Box, &right).unwrap();
+        assert!(cropped.iter().all(|&b| b == 255));
     }
 }
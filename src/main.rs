@@ -1,28 +1,89 @@
-use clap::Parser;
-use std::time::Duration;
+use clap::{parser::ValueSource, ArgMatches, CommandFactory, FromArgMatches, ValueEnum};
+use std::{
+    io::{IsTerminal, Write},
+    os::fd::OwnedFd,
+    path::PathBuf,
+    process::ExitCode,
+    sync::atomic::{AtomicU8, Ordering},
+    time::Duration,
+};
 
 use utils::{
     cache,
-    ipc::{self, connect_to_socket, get_socket_path, read_socket, Answer, RequestSend},
+    compression::BitPack,
+    ipc::{
+        self, connect_to_socket, connect_to_socket_with_backoff, get_socket_path, read_answer,
+        read_socket, Answer, RequestSend,
+    },
+    precomputed,
 };
 
 mod imgproc;
 use imgproc::*;
 
+mod archive;
+
 mod cli;
-use cli::{CliImage, ResizeStrategy, Swww};
+use cli::{Cli, CliImage, ResizeStrategy, Swww};
+
+/// The status we exit with if we fail. See `daemon_error`
+static EXIT_CODE: AtomicU8 = AtomicU8::new(1);
+
+fn main() -> ExitCode {
+    match run() {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("Error: {e:?}");
+            ExitCode::from(EXIT_CODE.load(Ordering::Relaxed))
+        }
+    }
+}
 
-fn main() -> Result<(), String> {
-    let swww = Swww::parse();
+fn run() -> Result<(), String> {
+    let matches = Cli::command().get_matches();
+    let Cli {
+        namespace,
+        query_timeout,
+        command: mut swww,
+    } = Cli::from_arg_matches(&matches).unwrap_or_else(|e| e.exit());
+    if let Some(timeout) = query_timeout {
+        ipc::set_client_timeout(timeout);
+    }
+    match (&mut swww, matches.subcommand()) {
+        (Swww::Img(img), Some(("img", img_matches))) => {
+            img.default_transition = !has_transition_options(img_matches);
+            mark_automatic_options(&mut img.transition, img_matches);
+            apply_transition_preset(&mut img.transition, img_matches);
+        }
+        (Swww::ShowPreloaded(show), Some(("show-preloaded", show_matches))) => {
+            show.default_transition = !has_transition_options(show_matches);
+            mark_automatic_options(&mut show.transition, show_matches);
+            apply_transition_preset(&mut show.transition, show_matches);
+        }
+        (Swww::DefaultTransition(default), Some(("default-transition", default_matches))) => {
+            mark_automatic_options(&mut default.transition, default_matches);
+            apply_transition_preset(&mut default.transition, default_matches);
+        }
+        _ => (),
+    }
 
     if let Swww::ClearCache = &swww {
         return cache::clean().map_err(|e| format!("failed to clean the cache: {e}"));
     }
+    if let Swww::Selftest = &swww {
+        return selftest();
+    }
+
+    let socket_path = get_socket_path(&namespace);
+    if let Swww::Check(check) = &swww {
+        return check_daemon(check, &socket_path);
+    }
 
+    let max_wait = daemon_wait_time()?;
     loop {
-        let socket = connect_to_socket(&get_socket_path(), 5, 100)?;
+        let socket = connect_to_socket_with_backoff(&socket_path, max_wait)?;
         RequestSend::Ping.send(&socket)?;
-        let bytes = read_socket(&socket)?;
+        let bytes = read_answer(&socket)?;
         let answer = Answer::receive(bytes);
         if let Answer::Ping(configured) = answer {
             if configured {
@@ -34,28 +95,229 @@ fn main() -> Result<(), String> {
         std::thread::sleep(Duration::from_millis(1));
     }
 
-    process_swww_args(&swww)
+    process_swww_args(&swww, &namespace, &socket_path)
+}
+
+/// How long to wait for the daemon's socket to show up, in case it was just launched
+fn daemon_wait_time() -> Result<Duration, String> {
+    match std::env::var("SWWW_DAEMON_WAIT") {
+        Ok(secs) => secs
+            .parse::<f32>()
+            .ok()
+            .and_then(|secs| Duration::try_from_secs_f32(secs).ok())
+            .ok_or_else(|| format!("invalid SWWW_DAEMON_WAIT value: {secs}")),
+        Err(_) => Ok(Duration::from_secs(5)),
+    }
+}
+
+/// Pings the daemon once, failing if it does not answer within the timeout, or if it is not ready
+/// to display images yet
+fn check_daemon(check: &cli::Check, socket_path: &PathBuf) -> Result<(), String> {
+    let timeout = Duration::try_from_secs_f32(check.timeout)
+        .ok()
+        .filter(|timeout| !timeout.is_zero())
+        .ok_or_else(|| format!("invalid timeout: {}", check.timeout))?;
+    let socket = connect_to_socket(socket_path, 1, 0)?;
+    RequestSend::Ping.send(&socket)?;
+    let bytes = ipc::read_socket_with_timeout(&socket, timeout)
+        .map_err(|e| format!("daemon did not answer: {e}"))?;
+    match Answer::receive(bytes) {
+        Answer::Ping(true) => {
+            if check.verbose {
+                println!("swww-daemon is running");
+            }
+            Ok(())
+        }
+        Answer::Ping(false) => {
+            Err("daemon is running, but its outputs are not configured yet".to_string())
+        }
+        _ => Err("Daemon did not return Answer::Ping, as expected".to_string()),
+    }
+}
+
+/// The transitions are drawn by the daemon's code, so we have it run them
+fn selftest() -> Result<(), String> {
+    let status = std::process::Command::new("swww-daemon")
+        .arg("--selftest")
+        .status()
+        .map_err(|e| format!("failed to run swww-daemon: {e}"))?;
+    if status.success() {
+        Ok(())
+    } else {
+        Err("some transitions failed the selftest".to_string())
+    }
+}
+
+/// Whether any of the transition options was given, either in the command line or through
+/// environment variables
+fn has_transition_options(matches: &ArgMatches) -> bool {
+    cli::Transition::command().get_arguments().any(|arg| {
+        match matches.value_source(arg.get_id().as_str()) {
+            Some(ValueSource::DefaultValue) | None => false,
+            Some(_) => true,
+        }
+    })
+}
+
+/// Lets the daemon choose the fps and step for each output, unless they were given explicitly,
+/// either in the command line or through environment variables
+fn mark_automatic_options(transition: &mut cli::Transition, matches: &ArgMatches) {
+    let given = |id: &str| {
+        matches!(
+            matches.value_source(id),
+            Some(ValueSource::CommandLine | ValueSource::EnvVariable)
+        )
+    };
+    transition.auto_fps = !given("transition_fps") && !given("transition_fps_from_output");
+    transition.auto_step = !given("transition_step");
+}
+
+/// Sets the options of `--transition-preset`, except for those that were given explicitly, either
+/// in the command line or through environment variables
+fn apply_transition_preset(transition: &mut cli::Transition, matches: &ArgMatches) {
+    let Some(preset) = transition.transition_preset else {
+        return;
+    };
+    let given = |id: &str| {
+        matches!(
+            matches.value_source(id),
+            Some(ValueSource::CommandLine | ValueSource::EnvVariable)
+        )
+    };
+    let ease_in_out = (0.42, 0.0, 0.58, 1.0);
+
+    let (transition_type, duration, fps, angle, bezier) = match preset {
+        cli::TransitionPreset::GentleFade => {
+            (cli::TransitionType::Fade, 2.0, None, None, ease_in_out)
+        }
+        cli::TransitionPreset::CinematicWipe => (
+            cli::TransitionType::Wipe,
+            2.5,
+            Some(60),
+            Some(30.0),
+            (0.65, 0.0, 0.35, 1.0),
+        ),
+        cli::TransitionPreset::Pop => (
+            cli::TransitionType::Grow,
+            0.8,
+            Some(60),
+            None,
+            (0.34, 1.56, 0.64, 1.0),
+        ),
+        cli::TransitionPreset::Ripple => (
+            cli::TransitionType::Wave,
+            2.5,
+            None,
+            Some(90.0),
+            ease_in_out,
+        ),
+    };
+    if !given("transition_type") {
+        transition.transition_type = transition_type;
+        // the default step depends on the transition type, so it must follow it
+        if !given("transition_step") {
+            transition.transition_step = cli::DEFAULT_TRANSITION_STEP;
+        }
+    }
+    if !given("transition_duration") {
+        transition.transition_duration = duration;
+    }
+    if let Some(fps) = fps {
+        if !given("transition_fps") && !given("transition_fps_from_output") {
+            transition.transition_fps = fps;
+            transition.auto_fps = false;
+        }
+    }
+    if let Some(angle) = angle {
+        if !given("transition_angle") {
+            transition.transition_angle = angle;
+        }
+    }
+    if !given("transition_bezier") {
+        transition.transition_bezier = bezier;
+    }
+    match preset {
+        cli::TransitionPreset::Pop if !given("transition_pos") => {
+            transition.transition_pos = cli::CliPosition {
+                x: cli::CliCoord::Percent(0.5),
+                y: cli::CliCoord::Percent(0.5),
+            };
+        }
+        cli::TransitionPreset::Ripple if !given("transition_wave") => {
+            transition.transition_wave = (40.0, 5.0);
+        }
+        _ => (),
+    }
 }
 
-fn process_swww_args(args: &Swww) -> Result<(), String> {
-    let request = match make_request(args)? {
+fn process_swww_args(args: &Swww, namespace: &str, socket_path: &PathBuf) -> Result<(), String> {
+    // images for a workspace are only displayed once it is active, so we just send how to display
+    // them
+    if let Swww::Img(
+        img @ cli::Img {
+            workspace: Some(workspace),
+            ..
+        },
+    ) = args
+    {
+        let args = sticky_args(img, namespace)?.into();
+        return set_workspace(workspace.clone(), args, socket_path);
+    }
+    // checked before sending anything, so that we do not display an image that cannot be sticky
+    let sticky = match args {
+        Swww::Img(img) if img.sticky => Some(sticky_args(img, namespace)?),
+        _ => None,
+    };
+    let request = match make_request(args, socket_path)? {
         Some(request) => request,
-        None => return Ok(()),
+        None => {
+            // images with streamed animations are sent by `make_request` itself
+            if let Some(args) = sticky {
+                set_sticky(args.into(), socket_path)?;
+            }
+            return Ok(());
+        }
     };
-    let socket = connect_to_socket(&get_socket_path(), 5, 100)?;
+    let socket = connect_to_socket(socket_path, 5, 100)?;
     request.send(&socket)?;
-    let bytes = read_socket(&socket)?;
+    let bytes = read_answer(&socket)?;
+    if let Swww::Query(cli::Query {
+        json,
+        animated_only,
+        watch: true,
+        progress,
+    }) = args
+    {
+        return watch_outputs(socket, bytes, *json, *animated_only, *progress);
+    }
     drop(socket);
     match Answer::receive(bytes) {
-        Answer::Err(msg) => return Err(msg.to_string()),
-        Answer::Info(info) => info.iter().for_each(|i| println!("{}", i)),
+        Answer::Err(kind, msg) => return Err(daemon_error(kind, &msg)),
+        Answer::Info(info) => match args {
+            Swww::Query(q) => print_info(&info, q.json, q.animated_only),
+            _ => print_info(&info, false, false),
+        },
+        Answer::CacheList(entries) => {
+            if let Swww::ShowPreloaded(_) = args {
+                // the daemon tells us what it displayed, since only it knows what was preloaded
+                for entry in entries.iter() {
+                    if let Err(e) = cache::store(&entry.output, &entry.img_path) {
+                        eprintln!("ERROR: failed to store cache: {e}");
+                    }
+                }
+            } else {
+                entries.iter().for_each(|e| println!("{e}"));
+            }
+        }
         Answer::Ok => {
-            if let Swww::Kill = args {
+            if let Some(args) = sticky {
+                set_sticky(args.into(), socket_path)?;
+            }
+            if let Swww::Kill(_) = args {
                 #[cfg(debug_assertions)]
                 let tries = 20;
                 #[cfg(not(debug_assertions))]
                 let tries = 10;
-                let socket_path = get_socket_path();
                 for _ in 0..tries {
                     if !socket_path.exists() {
                         return Ok(());
@@ -70,41 +332,535 @@ fn process_swww_args(args: &Swww) -> Result<(), String> {
         Answer::Ping(_) => {
             return Ok(());
         }
+        Answer::Progress(_) => {
+            return Err("daemon sent transition progress, but we did not subscribe".to_string())
+        }
+        Answer::Batch(_) => {
+            return Err("daemon answered a batch, but we did not send one".to_string())
+        }
+        Answer::Buffer(_) => {
+            return Err("daemon sent a buffer, but we did not ask for one".to_string())
+        }
+        Answer::Diagnostics(diagnostics) => println!("{diagnostics}"),
     }
     Ok(())
 }
 
-fn make_request(args: &Swww) -> Result<Option<RequestSend>, String> {
+/// Turns an error the daemon answered with into ours, making us exit with the status matching its
+/// kind, so that scripts can tell them apart. Every other error exits with 1
+fn daemon_error(kind: ipc::ErrorKind, msg: &str) -> String {
+    EXIT_CODE.store(daemon_error_exit_code(kind), Ordering::Relaxed);
+    msg.to_string()
+}
+
+fn daemon_error_exit_code(kind: ipc::ErrorKind) -> u8 {
+    match kind {
+        ipc::ErrorKind::Other => 1,
+        ipc::ErrorKind::Busy => 2,
+        ipc::ErrorKind::Cache => 3,
+        ipc::ErrorKind::Compositor => 4,
+        ipc::ErrorKind::NotPreloaded => 5,
+        ipc::ErrorKind::UnknownOutput => 6,
+    }
+}
+
+/// The arguments for the daemon to run `swww` with, to display `img` the same way on the outputs
+/// that show up later (or when a workspace becomes active). The daemon appends
+/// `--outputs=<outputs>` to them
+fn sticky_args(img: &cli::Img, namespace: &str) -> Result<Vec<String>, String> {
+    let mut args = Vec::new();
+    if !namespace.is_empty() {
+        args.push(format!("--namespace={namespace}"));
+    }
+    args.extend(["img".to_string(), "--transition-type=none".to_string()]);
+    args.extend(img_args(img)?);
+    Ok(args)
+}
+
+/// The options of `swww img` that display `img` the way it was requested, followed by the image,
+/// for the daemon to display it again. Leaves out the outputs and the transition
+fn img_args(img: &cli::Img) -> Result<Vec<String>, String> {
+    let image = sticky_image(&img.image)?;
+    let [r, g, b] = img.fill_color;
+
+    let mut args = vec![
+        format!(
+            "--resize={}",
+            img.resize.to_possible_value().unwrap().get_name()
+        ),
+        format!("--fill-color={r:02x}{g:02x}{b:02x}"),
+        format!("--filter={}", filter_name(&img.filter)),
+        format!(
+            "--animation-clock={}",
+            img.animation_clock.to_possible_value().unwrap().get_name()
+        ),
+    ];
+    let anchor = make_position(&img.crop_anchor);
+    if anchor != ipc::Position::CENTER {
+        args.push(format!("--crop-anchor={}", position_arg(&anchor)));
+    }
+    if let Some((x, y, width, height)) = img.region {
+        args.push(format!("--region={x},{y},{width},{height}"));
+    }
+    if img.animation_interpolate {
+        args.push("--animation-interpolate".to_string());
+    }
+    if img.animation_warp {
+        args.push("--animation-warp".to_string());
+    }
+    if img.dither {
+        args.push("--dither".to_string());
+    }
+    if img.cache_decoded {
+        args.push("--cache-decoded".to_string());
+    }
+    if img.cache_by_content {
+        args.push("--cache-by-content".to_string());
+    }
+    if img.brightness != 1.0 {
+        args.push(format!("--brightness={}", img.brightness));
+    }
+    if img.gamma != 1.0 {
+        args.push(format!("--gamma={}", img.gamma));
+    }
+    if let (Some(mode), Some(base)) = (img.blend, &img.blend_over) {
+        args.push(format!(
+            "--blend={}",
+            mode.to_possible_value().unwrap().get_name()
+        ));
+        args.push(format!("--blend-over={}", sticky_image(base)?));
+    }
+    args.push(image);
+    Ok(args)
+}
+
+/// `position` as `swww img` parses it: pixels are integers, while percentages always have a decimal
+/// point
+fn position_arg(position: &ipc::Position) -> String {
+    let coord = |coord: &ipc::Coord| match *coord {
+        ipc::Coord::Pixel(p) => format!("{p}"),
+        ipc::Coord::Percent(p) => format!("{p:?}"),
+    };
+    format!("{},{}", coord(&position.x), coord(&position.y))
+}
+
+/// How to give `image` to the `swww` the daemon runs, which may run from anywhere
+fn sticky_image(image: &CliImage) -> Result<String, String> {
+    let image = match image {
+        CliImage::Path(path) | CliImage::Video(path) => canonical_path(path)?,
+        CliImage::Archive { archive, entry } => format!("{}#{entry}", canonical_path(archive)?),
+        CliImage::Url(url) => url.clone(),
+        CliImage::Color([r, g, b]) => format!("0x{r:02x}{g:02x}{b:02x}"),
+        CliImage::Precomputed(_) => {
+            return Err(
+                "precomputed animations only fit a single output, so they cannot be \
+                        sticky or set for a workspace"
+                    .to_string(),
+            )
+        }
+    };
+    if image == "STDIN" {
+        return Err("images read from stdin cannot be sticky or set for a workspace".to_string());
+    }
+    Ok(image)
+}
+
+fn blend_mode(mode: cli::BlendMode) -> ipc::BlendMode {
+    match mode {
+        cli::BlendMode::Additive => ipc::BlendMode::Additive,
+        cli::BlendMode::Screen => ipc::BlendMode::Screen,
+        cli::BlendMode::Overlay => ipc::BlendMode::Overlay,
+    }
+}
+
+fn filter_name(filter: &cli::Filter) -> &'static str {
+    match filter {
+        cli::Filter::Nearest => "Nearest",
+        cli::Filter::Bilinear => "Bilinear",
+        cli::Filter::CatmullRom => "CatmullRom",
+        cli::Filter::Mitchell => "Mitchell",
+        cli::Filter::Lanczos3 => "Lanczos3",
+    }
+}
+
+/// Everything besides the path, dimensions and pixel format that changes the image we send, to
+/// tell apart the decoded images in the cache
+fn decoded_image_variant(img: &cli::Img) -> String {
+    let [r, g, b] = img.fill_color;
+    let mut variant = format!(
+        "{}_{}_{r:02x}{g:02x}{b:02x}",
+        img.resize.to_possible_value().unwrap().get_name(),
+        filter_name(&img.filter)
+    );
+    // so that images cropped around their center keep the entries they had before
+    let anchor = make_position(&img.crop_anchor);
+    if img.resize == ResizeStrategy::Crop && anchor != ipc::Position::CENTER {
+        variant.push_str(&format!("_at{}", position_arg(&anchor).replace(',', "x")));
+    }
+    if img.dither {
+        variant.push_str("_dithered");
+    }
+    variant
+}
+
+/// Sets the daemon's sticky image, or clears it if `args` is empty
+fn set_sticky(args: Box<[String]>, socket_path: &PathBuf) -> Result<(), String> {
+    let socket = connect_to_socket(socket_path, 5, 100)?;
+    RequestSend::SetSticky(ipc::StickySend { args }.create_request()).send(&socket)?;
+    match Answer::receive(read_answer(&socket)?) {
+        Answer::Ok => Ok(()),
+        Answer::Err(kind, msg) => Err(daemon_error(kind, &msg)),
+        _ => Err("daemon sent an unexpected answer when setting the sticky image".to_string()),
+    }
+}
+
+/// Sets how the daemon displays an image when `workspace` becomes active, or stops it from doing so
+/// if `args` is empty
+fn set_workspace(
+    workspace: String,
+    args: Box<[String]>,
+    socket_path: &PathBuf,
+) -> Result<(), String> {
+    let socket = connect_to_socket(socket_path, 5, 100)?;
+    RequestSend::SetWorkspace(ipc::WorkspaceSend { workspace, args }.create_request())
+        .send(&socket)?;
+    match Answer::receive(read_answer(&socket)?) {
+        Answer::Ok => Ok(()),
+        Answer::Err(kind, msg) => Err(daemon_error(kind, &msg)),
+        _ => Err("daemon sent an unexpected answer when setting the workspace image".to_string()),
+    }
+}
+
+/// Prints `info`. With `animated_only`, outputs that are not playing an animation are skipped
+fn print_info(info: &[ipc::BgInfo], json: bool, animated_only: bool) {
+    let info = info.iter().filter(|i| !animated_only || i.animated);
+    if json {
+        let json: Vec<String> = info.map(|i| i.json()).collect();
+        println!("[{}]", json.join(","));
+    } else {
+        info.for_each(|i| println!("{}", i))
+    }
+}
+
+/// Prints the answer to our subscription, and then every update the daemon sends us, until it
+/// closes the connection. Transition progress is only printed if `progress` is set.
+///
+/// In a terminal, we redraw everything in place on each update instead, like `watch swww query`
+/// would, but without polling. JSON is always printed line by line, for status bars
+fn watch_outputs(
+    socket: OwnedFd,
+    mut bytes: ipc::SocketMsg,
+    json: bool,
+    animated_only: bool,
+    progress: bool,
+) -> Result<(), String> {
+    ipc::remove_read_timeout(&socket)?;
+    if !json && std::io::stdout().is_terminal() {
+        return redraw_outputs(socket, bytes, animated_only, progress);
+    }
+    loop {
+        match Answer::receive(bytes) {
+            Answer::Info(info) => print_info(&info, json, animated_only),
+            Answer::Progress(transitions) => {
+                if progress {
+                    for p in transitions.iter() {
+                        if json {
+                            println!("{}", p.json());
+                        } else {
+                            println!("{p}");
+                        }
+                    }
+                }
+            }
+            Answer::Err(kind, msg) => return Err(daemon_error(kind, &msg)),
+            _ => return Err("daemon sent an unexpected answer to our subscription".to_string()),
+        }
+        bytes = match read_socket(&socket) {
+            Ok(bytes) => bytes,
+            Err(e) => return Err(format!("lost connection to the daemon: {e}")),
+        };
+    }
+}
+
+/// `swww query --watch` in a terminal: clears it and prints the information (and the progress of
+/// the running transitions) again whenever it changes. Ctrl-C leaves the last one on screen
+fn redraw_outputs(
+    socket: OwnedFd,
+    mut bytes: ipc::SocketMsg,
+    animated_only: bool,
+    progress: bool,
+) -> Result<(), String> {
+    let mut info: Box<[ipc::BgInfo]> = Box::new([]);
+    let mut transitions = Vec::new();
+    loop {
+        match Answer::receive(bytes) {
+            Answer::Info(new) => info = new,
+            Answer::Progress(new) if progress => update_progress(&mut transitions, &new),
+            Answer::Progress(_) => (),
+            Answer::Err(kind, msg) => return Err(daemon_error(kind, &msg)),
+            _ => return Err("daemon sent an unexpected answer to our subscription".to_string()),
+        }
+
+        let mut screen = String::from("\x1b[H\x1b[2J");
+        for i in info.iter().filter(|i| !animated_only || i.animated) {
+            screen.push_str(&format!("{i}\n"));
+        }
+        for p in transitions.iter() {
+            screen.push_str(&format!("{p}\n"));
+        }
+        let mut stdout = std::io::stdout().lock();
+        stdout
+            .write_all(screen.as_bytes())
+            .and_then(|()| stdout.flush())
+            .map_err(|e| format!("failed to write to stdout: {e}"))?;
+        // finished transitions are displayed once, at 100%
+        transitions.retain(|p| p.percent < 100);
+
+        bytes = match read_socket(&socket) {
+            Ok(bytes) => bytes,
+            Err(e) => return Err(format!("lost connection to the daemon: {e}")),
+        };
+    }
+}
+
+/// Keeps the latest progress of each output's transition, in the order they started
+fn update_progress(
+    transitions: &mut Vec<ipc::TransitionProgress>,
+    new: &[ipc::TransitionProgress],
+) {
+    for p in new {
+        match transitions.iter_mut().find(|t| t.output == p.output) {
+            Some(t) => t.percent = p.percent,
+            None => transitions.push(p.clone()),
+        }
+    }
+}
+
+fn make_request(args: &Swww, socket_path: &PathBuf) -> Result<Option<RequestSend>, String> {
     match args {
         Swww::Clear(c) => {
-            let (format, _, _) = get_format_dims_and_outputs(&[])?;
+            let (format, _, _) = get_format_dims_and_outputs(&[], socket_path)?;
             let mut color = c.color;
             if format.must_swap_r_and_b_channels() {
                 color.swap(0, 2);
             }
-            let clear = ipc::ClearSend {
-                color,
-                outputs: split_cmdline_outputs(&c.outputs),
+            let outputs = match c.output_position {
+                Some(position) => output_at_position(position, socket_path)?,
+                None => expand_output_patterns(split_cmdline_outputs(&c.outputs), socket_path)?,
             };
+            let clear = ipc::ClearSend { color, outputs };
             Ok(Some(RequestSend::Clear(clear.create_request())))
         }
         Swww::Restore(restore) => {
-            let requested_outputs = split_cmdline_outputs(&restore.outputs);
-            restore_from_cache(&requested_outputs)?;
+            let requested_outputs =
+                expand_output_patterns(split_cmdline_outputs(&restore.outputs), socket_path)?;
+            restore_from_cache(&requested_outputs, socket_path)?;
             Ok(None)
         }
         Swww::ClearCache => unreachable!("there is no request for clear-cache"),
+        Swww::Check(_) => unreachable!("check sends its own request"),
+        Swww::Selftest => unreachable!("there is no request for selftest"),
         Swww::Img(img) => {
-            let requested_outputs = split_cmdline_outputs(&img.outputs);
-            let (format, dims, outputs) = get_format_dims_and_outputs(&requested_outputs)?;
+            let requested_outputs = select_img_outputs(img, socket_path)?;
+            if img.span && !matches!(img.image, CliImage::Color(_) | CliImage::Precomputed(_)) {
+                let infos = query_outputs(&requested_outputs, socket_path)?;
+                let img_request = make_span_request(img, &infos, false)?;
+                return Ok(Some(RequestSend::Img(img_request)));
+            }
+            if let Some(region) = img.region {
+                check_region(&make_region(region), &requested_outputs, socket_path)?;
+            }
+            let (format, dims, outputs) =
+                get_format_dims_and_outputs(&requested_outputs, socket_path)?;
             // let imgbuf = ImgBuf::new(&img.path)?;
 
-            let img_request = make_img_request(img, &dims, format, &outputs)?;
+            let (img_request, streamed) = make_img_request(img, &dims, format, &outputs, false)?;
+            match streamed {
+                Some(streamed) => {
+                    stream_animations(img, img_request, streamed, socket_path)?;
+                    Ok(None)
+                }
+                None => Ok(Some(RequestSend::Img(img_request))),
+            }
+        }
+        Swww::Preload(img) => {
+            if img.sticky {
+                return Err("preloaded images cannot be sticky".to_string());
+            }
+            if img.workspace.is_some() {
+                return Err("preloaded images cannot be set for a workspace".to_string());
+            }
+            if img.sync {
+                return Err("preloaded images cannot be displayed with --sync".to_string());
+            }
+            if img.from.is_some() {
+                return Err("preloaded images cannot have a transition --from".to_string());
+            }
+            if img.blend.is_some() {
+                return Err("preloaded images cannot be blended".to_string());
+            }
+            if img.region.is_some() {
+                return Err("preloaded images cannot have a --region".to_string());
+            }
+            let requested_outputs = select_img_outputs(img, socket_path)?;
+            if img.span && !matches!(img.image, CliImage::Color(_) | CliImage::Precomputed(_)) {
+                let infos = query_outputs(&requested_outputs, socket_path)?;
+                let img_request = make_span_request(img, &infos, true)?;
+                return Ok(Some(RequestSend::Preload(img_request)));
+            }
+            let (format, dims, outputs) =
+                get_format_dims_and_outputs(&requested_outputs, socket_path)?;
+
+            // one image per output, so that they can be displayed separately
+            let (dims, outputs): (Vec<_>, Vec<_>) = dims
+                .iter()
+                .zip(outputs)
+                .flat_map(|(&dim, outputs)| outputs.into_iter().map(move |o| (dim, vec![o])))
+                .unzip();
+            let (img_request, _) = make_img_request(img, &dims, format, &outputs, true)?;
 
-            Ok(Some(RequestSend::Img(img_request)))
+            Ok(Some(RequestSend::Preload(img_request)))
+        }
+        Swww::ShowPreloaded(show) => {
+            let transition = if show.default_transition {
+                None
+            } else {
+                Some(make_transition(&show.transition))
+            };
+            let show = ipc::ShowPreloadedSend {
+                transition,
+                outputs: expand_output_patterns(split_cmdline_outputs(&show.outputs), socket_path)?,
+            };
+            Ok(Some(RequestSend::ShowPreloaded(show.create_request())))
+        }
+        Swww::Freeze(freeze) => {
+            let outputs = match freeze.output_position {
+                Some(position) => output_at_position(position, socket_path)?,
+                None => {
+                    expand_output_patterns(split_cmdline_outputs(&freeze.outputs), socket_path)?
+                }
+            };
+            let freeze = ipc::FreezeSend { outputs };
+            Ok(Some(RequestSend::Freeze(freeze.create_request())))
+        }
+        Swww::BoostFps(boost) => {
+            let outputs = match boost.output_position {
+                Some(position) => output_at_position(position, socket_path)?,
+                None => expand_output_patterns(split_cmdline_outputs(&boost.outputs), socket_path)?,
+            };
+            let boost = ipc::BoostFpsSend {
+                fps: boost.fps,
+                duration: boost.duration,
+                outputs,
+            };
+            Ok(Some(RequestSend::BoostFps(boost.create_request())))
+        }
+        Swww::DimSchedule(dim) => {
+            let daylight = match (dim.sunrise, dim.sunset, dim.location) {
+                _ if dim.off => None,
+                (Some(sunrise), Some(sunset), _) => Some(ipc::Daylight::Fixed { sunrise, sunset }),
+                (_, _, Some((latitude, longitude))) => Some(ipc::Daylight::Location {
+                    latitude,
+                    longitude,
+                }),
+                _ => unreachable!("clap requires --sunrise, --location or --off"),
+            };
+            let schedule = ipc::DimScheduleSend {
+                schedule: daylight.map(|daylight| ipc::DimSchedule {
+                    daylight,
+                    night_brightness: dim.night_brightness,
+                    fade: Duration::from_secs(dim.fade as u64 * 60),
+                }),
+            };
+            Ok(Some(RequestSend::SetDimSchedule(schedule.create_request())))
+        }
+        Swww::DefaultTransition(default) => {
+            let transition = make_transition(&default.transition);
+            if default.persist {
+                cache::store_default_transition(&transition)
+                    .map_err(|e| format!("failed to store default transition in cache: {e}"))?;
+            }
+            Ok(Some(RequestSend::SetDefaultTransition(
+                transition.create_request(),
+            )))
+        }
+        Swww::Export(export) => {
+            export_animation(export, socket_path)?;
+            Ok(None)
+        }
+        Swww::CacheAnimation(cache_animation) => {
+            cache_animation_frames(cache_animation, socket_path)?;
+            Ok(None)
+        }
+        Swww::Save(save) => {
+            save_wallpaper(save, socket_path)?;
+            Ok(None)
+        }
+        Swww::Cache(cli::Cache::List) => Ok(Some(RequestSend::CacheList)),
+        Swww::Diagnostics => Ok(Some(RequestSend::Diagnostics)),
+        Swww::Reset => Ok(Some(RequestSend::Reset)),
+        Swww::Unstick => Ok(Some(RequestSend::SetSticky(
+            ipc::StickySend { args: Box::new([]) }.create_request(),
+        ))),
+        Swww::UnsetWorkspace(unset) => Ok(Some(RequestSend::SetWorkspace(
+            ipc::WorkspaceSend {
+                workspace: unset.workspace.clone(),
+                args: Box::new([]),
+            }
+            .create_request(),
+        ))),
+        Swww::Kill(cli::Kill { clear: None }) => Ok(Some(RequestSend::Kill)),
+        Swww::Kill(cli::Kill {
+            clear: Some([r, g, b]),
+        }) => {
+            let (format, _, _) = get_format_dims_and_outputs(&[], socket_path)?;
+            let mut color = [*r, *g, *b, 0xff];
+            if format.must_swap_r_and_b_channels() {
+                color.swap(0, 2);
+            }
+            let clear = ipc::ClearSend {
+                color,
+                outputs: Box::new([]),
+            };
+            Ok(Some(RequestSend::ClearAndKill(clear.create_request())))
         }
-        Swww::Kill => Ok(Some(RequestSend::Kill)),
-        Swww::Query => Ok(Some(RequestSend::Query)),
+        Swww::Query(query) => Ok(Some(if query.watch {
+            RequestSend::Subscribe
+        } else {
+            RequestSend::Query
+        })),
+    }
+}
+
+/// Preloaded images are not stored in the cache: `swww show-preloaded` does that once they are
+/// displayed
+fn img_request_builder(img: &cli::Img, preload: bool) -> ipc::ImageRequestBuilder {
+    let builder = if preload {
+        ipc::ImageRequestBuilder::with_default_transition().without_output_cache()
+    } else if img.sync {
+        ipc::ImageRequestBuilder::with_default_transition().sync()
+    } else if img.default_transition {
+        ipc::ImageRequestBuilder::with_default_transition()
+    } else {
+        ipc::ImageRequestBuilder::new(make_transition(&img.transition))
+    };
+    let builder = if img.force { builder.force() } else { builder };
+    // the cache only has animations cropped around their center
+    let builder = if make_position(&img.crop_anchor) != ipc::Position::CENTER {
+        builder.without_animation_cache()
+    } else {
+        builder
+    };
+    let builder = builder.adjust(ipc::ColorAdjustment::new(img.brightness, img.gamma));
+    // spanned images are split between their outputs, which `swww img` cannot redo for only one
+    let builder = match img_args(img) {
+        Ok(args) if !img.span => builder.replay(args),
+        _ => builder,
+    };
+    if img.debug {
+        builder.debug()
+    } else {
+        builder
     }
 }
 
@@ -113,145 +869,834 @@ fn make_img_request(
     dims: &[(u32, u32)],
     pixel_format: ipc::PixelFormat,
     outputs: &[Vec<String>],
-) -> Result<ipc::Mmap, String> {
-    let transition = make_transition(img);
-    let mut img_req_builder = ipc::ImageRequestBuilder::new(transition);
+    preload: bool,
+) -> Result<(ipc::Mmap, Option<StreamedAnimations>), String> {
+    let mut img_req_builder = img_request_builder(img, preload);
+    let region = img.region.map(make_region);
+    let sources = match &img.from {
+        Some(from) => Some(make_stills(img, from, "--from", dims, pixel_format)?),
+        None => None,
+    };
+    let bases = match (img.blend, &img.blend_over) {
+        (Some(mode), Some(base)) => Some((
+            blend_mode(mode),
+            make_stills(img, base, "--blend-over", dims, pixel_format)?,
+        )),
+        _ => None,
+    };
 
     match &img.image {
         CliImage::Color(color) => {
-            for (&dim, outputs) in dims.iter().zip(outputs) {
+            let path = format!("0x{:02x}{:02x}{:02x}", color[0], color[1], color[2]);
+            for (i, (&dim, outputs)) in dims.iter().zip(outputs).enumerate() {
                 img_req_builder.push(
-                    ipc::ImgSend {
-                        img: image::RgbImage::from_pixel(dim.0, dim.1, image::Rgb(*color))
-                            .to_vec()
-                            .into_boxed_slice(),
-                        path: format!("0x{:02x}{:02x}{:02x}", color[0], color[1], color[2]),
-                        dim,
-                        format: pixel_format,
-                    },
+                    color_img(*color, path.clone(), dim, pixel_format),
                     outputs,
                     None,
                 );
+                if let Some(sources) = sources.as_ref() {
+                    img_req_builder.push_source(&sources[i]);
+                }
+                if let Some((mode, bases)) = bases.as_ref() {
+                    img_req_builder.push_blend(*mode, &bases[i]);
+                }
+                if let Some(region) = region.as_ref() {
+                    img_req_builder.push_region(region);
+                }
             }
         }
-        CliImage::Path(img_path) => {
-            let imgbuf = ImgBuf::new(img_path)?;
-            let img_raw = imgbuf.decode(pixel_format)?;
-
+        CliImage::Precomputed(file) => {
+            let precomputed = precomputed::import(file)?;
+            if precomputed.format != pixel_format {
+                return Err(format!(
+                    "{file:?} was exported for the {:?} pixel format, but the daemon is using {:?}. \
+                     Export it again",
+                    precomputed.format, pixel_format
+                ));
+            }
+            // the animation can only be sent once, so all outputs go in the same image
+            let mut all_outputs = Vec::new();
             for (&dim, outputs) in dims.iter().zip(outputs) {
-                let path = match img_path.canonicalize() {
-                    Ok(p) => p.to_string_lossy().to_string(),
-                    Err(e) => {
-                        if let Some("-") = img_path.to_str() {
-                            "STDIN".to_string()
-                        } else {
-                            return Err(format!("failed no canonicalize image path: {e}"));
-                        }
+                if dim != precomputed.dim {
+                    return Err(format!(
+                        "{file:?} was exported for {}x{} outputs, but {outputs:?} are {}x{}. \
+                         Export it again for them",
+                        precomputed.dim.0, precomputed.dim.1, dim.0, dim.1
+                    ));
+                }
+                all_outputs.extend_from_slice(outputs);
+            }
+            let path = canonical_path(file)?;
+
+            let mut animation = precomputed.animation;
+            animation.clock = match img.animation_clock {
+                cli::AnimationClock::Locked => ipc::AnimationClock::Locked,
+                cli::AnimationClock::Independent => ipc::AnimationClock::Independent,
+            };
+            animation.interpolate = img.animation_interpolate;
+            animation.warp = img.animation_warp;
+            img_req_builder.push(
+                ipc::ImgSend {
+                    img: precomputed.img,
+                    path,
+                    dim: precomputed.dim,
+                    format: pixel_format,
+                },
+                &all_outputs,
+                // `--sync` only displays the first frame
+                (!img.sync).then_some(animation),
+            );
+            // every output has the same dimensions
+            if let Some(sources) = sources.as_ref() {
+                img_req_builder.push_source(&sources[0]);
+            }
+            if let Some((mode, bases)) = bases.as_ref() {
+                img_req_builder.push_blend(*mode, &bases[0]);
+            }
+            if let Some(region) = region.as_ref() {
+                img_req_builder.push_region(region);
+            }
+        }
+        CliImage::Path(_) | CliImage::Archive { .. } | CliImage::Url(_) | CliImage::Video(_) => {
+            let animation_clock = match img.animation_clock {
+                cli::AnimationClock::Locked => ipc::AnimationClock::Locked,
+                cli::AnimationClock::Independent => ipc::AnimationClock::Independent,
+            };
+            let (imgbuf, path, img_path) = load_image(&img.image)?;
+            if img.cache_by_content && matches!(img.image, CliImage::Path(_)) && path != "STDIN" {
+                if let Err(e) = cache::link_content(&img_path, imgbuf.bytes()) {
+                    eprintln!("Error linking {img_path:?} to its contents in the cache: {e}");
+                }
+            }
+            // urls and videos are downloaded (or extracted) to temporary files every time, and
+            // archive entries do not have a modification time of their own
+            let cache_decoded = img.cache_decoded && matches!(img.image, CliImage::Path(_));
+            let variant = decoded_image_variant(img);
+            // only decoded if one of the outputs is missing from the cache
+            let mut img_raw = None;
+
+            // when some animation is not in the cache, we display the first frame right away
+            // and send the frames as we compress them, instead of making the user wait for all
+            // of them. Preloaded images are not displayed yet, so there is no hurry
+            let mut streamed = None;
+            let animations =
+                if imgbuf.is_animated() && img.resize == ResizeStrategy::Crop && !img.sync {
+                    let cached = cached_animations(img, &path, dims, pixel_format, animation_clock);
+                    if !preload && cached.iter().any(Option::is_none) {
+                        streamed = Some(cached);
+                        dims.iter().map(|_| None).collect()
+                    } else {
+                        make_animations(img, &imgbuf, dims, pixel_format, animation_clock, cached)?
                     }
+                } else {
+                    dims.iter().map(|_| None).collect()
                 };
+            let streamed_animation = streamed.is_some().then(|| ipc::StreamedAnimation {
+                clock: animation_clock,
+                interpolate: img.animation_interpolate,
+                warp: img.animation_warp,
+                loop_count: imgbuf.loop_count(),
+            });
 
-                let animation = if !imgbuf.is_animated() {
+            for (i, ((&dim, outputs), animation)) in
+                dims.iter().zip(outputs).zip(animations).enumerate()
+            {
+                let path = path.clone();
+
+                let cached = if cache_decoded {
+                    cache::load_decoded_image(&img_path, dim, pixel_format, &variant)
+                        .unwrap_or_else(|e| {
+                            eprintln!("Error loading decoded image from cache: {e}");
+                            None
+                        })
+                } else {
                     None
-                } else if img.resize == ResizeStrategy::Crop {
-                    match cache::load_animation_frames(img_path, dim, pixel_format) {
-                        Ok(Some(animation)) => Some(animation),
-                        otherwise => {
-                            if let Err(e) = otherwise {
-                                eprintln!("Error loading cache for {:?}: {e}", img_path);
+                };
+                let img = match cached {
+                    Some(cached) => cached,
+                    None => {
+                        if img_raw.is_none() {
+                            img_raw = Some(imgbuf.decode(pixel_format, img.dither)?);
+                        }
+                        let resized = resize_img(img, img_raw.as_ref().unwrap(), dim)?;
+                        if cache_decoded {
+                            if let Err(e) = cache::store_decoded_image(
+                                &resized,
+                                &img_path,
+                                dim,
+                                pixel_format,
+                                &variant,
+                            ) {
+                                eprintln!("Error storing decoded image in cache: {e}");
                             }
-
-                            Some({
-                                ipc::Animation {
-                                    animation: compress_frames(
-                                        imgbuf.as_frames()?,
-                                        dim,
-                                        pixel_format,
-                                        make_filter(&img.filter),
-                                        img.resize,
-                                        &img.fill_color,
-                                    )?
-                                    .into_boxed_slice(),
-                                }
-                            })
                         }
+                        resized
                     }
-                } else {
-                    None
                 };
 
-                let img = match img.resize {
-                    ResizeStrategy::No => img_pad(&img_raw, dim, &img.fill_color)?,
-                    ResizeStrategy::Crop => {
-                        img_resize_crop(&img_raw, dim, make_filter(&img.filter))?
-                    }
-                    ResizeStrategy::Fit => {
-                        img_resize_fit(&img_raw, dim, make_filter(&img.filter), &img.fill_color)?
-                    }
+                let img = ipc::ImgSend {
+                    img,
+                    path,
+                    dim,
+                    format: pixel_format,
                 };
+                match streamed_animation {
+                    Some(streamed) => img_req_builder.push_streamed(img, outputs, streamed),
+                    None => img_req_builder.push(img, outputs, animation),
+                }
+                if let Some(sources) = sources.as_ref() {
+                    img_req_builder.push_source(&sources[i]);
+                }
+                if let Some((mode, bases)) = bases.as_ref() {
+                    img_req_builder.push_blend(*mode, &bases[i]);
+                }
+                if let Some(region) = region.as_ref() {
+                    img_req_builder.push_region(region);
+                }
+            }
 
-                img_req_builder.push(
-                    ipc::ImgSend {
-                        img,
-                        path,
-                        dim,
-                        format: pixel_format,
-                    },
-                    outputs,
-                    animation,
+            if let Some(cached) = streamed {
+                let streamed = StreamedAnimations {
+                    imgbuf,
+                    path,
+                    dims: dims.to_vec(),
+                    format: pixel_format,
+                    cached,
+                };
+                return Ok((img_req_builder.build(), Some(streamed)));
+            }
+        }
+    }
+
+    Ok((img_req_builder.build(), None))
+}
+
+/// An image filled with `color`
+fn color_img(
+    color: [u8; 3],
+    path: String,
+    dim: (u32, u32),
+    format: ipc::PixelFormat,
+) -> ipc::ImgSend {
+    ipc::ImgSend {
+        img: image::RgbImage::from_pixel(dim.0, dim.1, image::Rgb(color))
+            .to_vec()
+            .into_boxed_slice(),
+        path,
+        dim,
+        format,
+    }
+}
+
+/// Resizes `img_raw` to `dim`, with the resize strategy, filter and fill color of `img`
+fn resize_img(img: &cli::Img, img_raw: &Image, dim: (u32, u32)) -> Result<Box<[u8]>, String> {
+    match img.resize {
+        ResizeStrategy::No => img_pad(img_raw, dim, &img.fill_color),
+        ResizeStrategy::Crop => img_resize_crop(
+            img_raw,
+            dim,
+            make_filter(&img.filter),
+            &make_position(&img.crop_anchor),
+        ),
+        ResizeStrategy::Fit => {
+            img_resize_fit(img_raw, dim, make_filter(&img.filter), &img.fill_color)
+        }
+    }
+}
+
+/// The image given to `option` (like `swww img --from`), for each of `dims`. Only its first frame
+/// is used
+fn make_stills(
+    img: &cli::Img,
+    still: &CliImage,
+    option: &str,
+    dims: &[(u32, u32)],
+    pixel_format: ipc::PixelFormat,
+) -> Result<Vec<ipc::ImgSend>, String> {
+    if let CliImage::Color(color) = still {
+        let path = format!("0x{:02x}{:02x}{:02x}", color[0], color[1], color[2]);
+        return Ok(dims
+            .iter()
+            .map(|&dim| color_img(*color, path.clone(), dim, pixel_format))
+            .collect());
+    }
+    let (imgbuf, path, _) =
+        load_image(still).map_err(|e| format!("failed to load the {option} image: {e}"))?;
+    let img_raw = imgbuf.decode(pixel_format, img.dither)?;
+    dims.iter()
+        .map(|&dim| {
+            Ok(ipc::ImgSend {
+                img: resize_img(img, &img_raw, dim)?,
+                path: path.clone(),
+                dim,
+                format: pixel_format,
+            })
+        })
+        .collect()
+}
+
+/// The animations of an image request whose frames we send after it, as we compress them. See
+/// `ipc::ImageRequestBuilder::push_streamed`
+struct StreamedAnimations {
+    imgbuf: ImgBuf,
+    /// The path we sent the images with, under which we cache the animations
+    path: String,
+    dims: Vec<(u32, u32)>,
+    format: ipc::PixelFormat,
+    /// The animations we found in the cache, which we send all at once
+    cached: Vec<Option<ipc::Animation>>,
+}
+
+/// Sends `request`, and then the frames of its animations. We decode each frame only once,
+/// compressing it for every size that was not in the cache, and send it right away, so that the
+/// daemon can start playing the animations before we are done
+fn stream_animations(
+    img: &cli::Img,
+    request: ipc::Mmap,
+    streamed: StreamedAnimations,
+    socket_path: &PathBuf,
+) -> Result<(), String> {
+    let StreamedAnimations {
+        imgbuf,
+        path,
+        dims,
+        format,
+        cached,
+    } = streamed;
+    let socket = connect_to_socket(socket_path, 5, 100)?;
+    RequestSend::Img(request).send(&socket)?;
+    match Answer::receive(read_answer(&socket)?) {
+        Answer::Ok => (),
+        Answer::Err(kind, msg) => return Err(daemon_error(kind, &msg)),
+        _ => return Err("daemon sent an unexpected answer to our image request".to_string()),
+    }
+
+    // the daemon hangs up once nobody is playing the animations anymore (because some other
+    // image was set in the meantime, for example), so failing to send is not an error
+    let send = |img: usize, frames: &[(BitPack, Duration)], last: bool| {
+        let frames = ipc::AnimationFramesSend {
+            img: img as u8,
+            frames,
+            last,
+        };
+        RequestSend::AnimationFrames(frames.create_request())
+            .send(&socket)
+            .is_ok()
+    };
+
+    let filter = make_filter(&img.filter);
+    let anchor = make_position(&img.crop_anchor);
+    // for each animation we compress: its index, compressor, frames, and how many we already sent
+    let mut compressing = Vec::new();
+    for (i, animation) in cached.iter().enumerate() {
+        match animation {
+            Some(animation) => {
+                if !send(i, &animation.animation, true) {
+                    return Ok(());
+                }
+            }
+            None => {
+                let compressor = FrameCompressor::new(
+                    dims[i],
+                    format,
+                    filter,
+                    img.resize,
+                    &anchor,
+                    &img.fill_color,
                 );
+                compressing.push((i, compressor, Vec::new(), 0));
+            }
+        }
+    }
+
+    for (image, duration) in decoded_frames(imgbuf.as_frames()?, format) {
+        for (i, compressor, frames, sent) in compressing.iter_mut() {
+            if let Some(frame) = compressor.push(&image, duration)? {
+                frames.push(frame);
+                if !send(*i, &frames[*sent..], false) {
+                    return Ok(());
+                }
+                *sent = frames.len();
             }
         }
     }
 
+    for (i, compressor, mut frames, sent) in compressing {
+        frames.extend(compressor.finish()?);
+        if !send(i, &frames[sent..], true) {
+            return Ok(());
+        }
+        // the cache only has animations cropped around their center
+        if path != "STDIN" && anchor == ipc::Position::CENTER {
+            let animation = ipc::Animation {
+                animation: frames.into_boxed_slice(),
+                clock: ipc::AnimationClock::default(),
+                interpolate: false,
+                warp: false,
+                loop_count: imgbuf.loop_count(),
+            };
+            if let Err(e) =
+                cache::store_animation(&animation, std::path::Path::new(&path), dims[i], format)
+            {
+                eprintln!("Error storing cache for {path}: {e}");
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Loads the animation for each of the `dims` from the cache. They are stored under the image's
+/// canonical `path`, and never looked up for stdin, since it is a different image every time, nor
+/// for images cropped around another point than their center
+fn cached_animations(
+    img: &cli::Img,
+    path: &str,
+    dims: &[(u32, u32)],
+    pixel_format: ipc::PixelFormat,
+    clock: ipc::AnimationClock,
+) -> Vec<Option<ipc::Animation>> {
+    if path == "STDIN" || make_position(&img.crop_anchor) != ipc::Position::CENTER {
+        return dims.iter().map(|_| None).collect();
+    }
+    let img_path = std::path::Path::new(path);
+    dims.iter()
+        .map(
+            |&dim| match cache::load_animation_frames(img_path, dim, pixel_format) {
+                Ok(animation) => animation.map(|animation| ipc::Animation {
+                    clock,
+                    interpolate: img.animation_interpolate,
+                    warp: img.animation_warp,
+                    ..animation
+                }),
+                Err(e) => {
+                    eprintln!("Error loading cache for {:?}: {e}", img_path);
+                    None
+                }
+            },
+        )
+        .collect()
+}
+
+/// Compresses the `animations` that were not in the cache. If more than one is missing, we decode
+/// the frames only once and resize them for each size, which saves a lot of work in setups with
+/// monitors of different resolutions.
+fn make_animations(
+    img: &cli::Img,
+    imgbuf: &ImgBuf,
+    dims: &[(u32, u32)],
+    pixel_format: ipc::PixelFormat,
+    clock: ipc::AnimationClock,
+    mut animations: Vec<Option<ipc::Animation>>,
+) -> Result<Vec<Option<ipc::Animation>>, String> {
+    let missing = animations.iter().filter(|a| a.is_none()).count();
+    let decoded = if missing > 1 {
+        Some(decode_frames(imgbuf.as_frames()?, pixel_format))
+    } else {
+        None
+    };
+
+    for (animation, &dim) in animations.iter_mut().zip(dims) {
+        if animation.is_some() {
+            continue;
+        }
+        let filter = make_filter(&img.filter);
+        let anchor = make_position(&img.crop_anchor);
+        let frames = match decoded.as_ref() {
+            Some(frames) => compress_decoded_frames(
+                frames,
+                dim,
+                pixel_format,
+                filter,
+                img.resize,
+                &anchor,
+                &img.fill_color,
+            )?,
+            None => compress_frames(
+                imgbuf.as_frames()?,
+                dim,
+                pixel_format,
+                filter,
+                img.resize,
+                &anchor,
+                &img.fill_color,
+            )?,
+        };
+        *animation = Some(ipc::Animation {
+            animation: frames.into_boxed_slice(),
+            clock,
+            interpolate: img.animation_interpolate,
+            warp: img.animation_warp,
+            loop_count: imgbuf.loop_count(),
+        });
+    }
+    Ok(animations)
+}
+
+/// Precomputes the animation for the dimensions and pixel format of `export.output`, and writes it
+/// to `export.file`, for `swww img` to load later
+fn export_animation(export: &cli::Export, socket_path: &PathBuf) -> Result<(), String> {
+    if export
+        .file
+        .extension()
+        .map_or(true, |ext| ext != precomputed::EXTENSION)
+    {
+        return Err(format!(
+            "{:?} must end in '.{}', so that 'swww img' recognizes it",
+            export.file,
+            precomputed::EXTENSION
+        ));
+    }
+    let infos = query_outputs(&split_cmdline_outputs(&export.output), socket_path)?;
+    let dim = infos[0].real_dim();
+    let pixel_format = infos[0].pixel_format;
+
+    let (imgbuf, path, _) = load_image(&export.image)?;
+    if !imgbuf.is_animated() {
+        return Err(format!(
+            "{path} is not animated, so there is nothing to precompute"
+        ));
+    }
+    let filter = make_filter(&export.filter);
+    let img = img_resize_crop(
+        &imgbuf.decode(pixel_format, false)?,
+        dim,
+        filter,
+        &ipc::Position::CENTER,
+    )?;
+    let frames = compress_frames(
+        imgbuf.as_frames()?,
+        dim,
+        pixel_format,
+        filter,
+        ResizeStrategy::Crop,
+        &ipc::Position::CENTER,
+        &[0, 0, 0],
+    )?;
+    let animation = ipc::Animation {
+        animation: frames.into_boxed_slice(),
+        clock: ipc::AnimationClock::Locked,
+        interpolate: false,
+        warp: false,
+        loop_count: imgbuf.loop_count(),
+    };
+    precomputed::export(&export.file, dim, pixel_format, &img, &animation)
+        .map_err(|e| format!("failed to write {:?}: {e}", export.file))
+}
+
+/// Compresses the frames of `cache_animation.image` for the dimensions of each of its outputs, and
+/// stores them in the cache, like `swww img` would. Dimensions already in the cache are skipped
+fn cache_animation_frames(
+    cache_animation: &cli::CacheAnimation,
+    socket_path: &PathBuf,
+) -> Result<(), String> {
+    let requested = split_cmdline_outputs(&cache_animation.outputs);
+    let requested = expand_output_patterns(requested, socket_path)?;
+    let (pixel_format, mut dims, _) = get_format_dims_and_outputs(&requested, socket_path)?;
+    dims.sort_unstable();
+    dims.dedup();
+
+    let (imgbuf, path, _) = load_image(&cache_animation.image)?;
+    if path == "STDIN" {
+        return Err("images read from stdin are never cached".to_string());
+    }
+    if !imgbuf.is_animated() {
+        return Err(format!(
+            "{path} is not animated, so there are no frames to cache"
+        ));
+    }
+    let img_path = std::path::Path::new(&path);
+    dims.retain(
+        |&dim| match cache::load_animation_frames(img_path, dim, pixel_format) {
+            Ok(animation) => animation.is_none(),
+            Err(e) => {
+                eprintln!("Error loading cache for {path}: {e}");
+                true
+            }
+        },
+    );
+
+    let filter = make_filter(&cache_animation.filter);
+    // like `make_animations`, we only decode the frames once for several sizes
+    let decoded = if dims.len() > 1 {
+        Some(decode_frames(imgbuf.as_frames()?, pixel_format))
+    } else {
+        None
+    };
+    for dim in dims {
+        let frames = match decoded.as_ref() {
+            Some(frames) => compress_decoded_frames(
+                frames,
+                dim,
+                pixel_format,
+                filter,
+                ResizeStrategy::Crop,
+                &ipc::Position::CENTER,
+                &[0, 0, 0],
+            )?,
+            None => compress_frames(
+                imgbuf.as_frames()?,
+                dim,
+                pixel_format,
+                filter,
+                ResizeStrategy::Crop,
+                &ipc::Position::CENTER,
+                &[0, 0, 0],
+            )?,
+        };
+        let animation = ipc::Animation {
+            animation: frames.into_boxed_slice(),
+            clock: ipc::AnimationClock::default(),
+            interpolate: false,
+            warp: false,
+            loop_count: imgbuf.loop_count(),
+        };
+        cache::store_animation(&animation, img_path, dim, pixel_format)
+            .map_err(|e| format!("failed to store the frames of {path} in the cache: {e}"))?;
+    }
+    Ok(())
+}
+
+/// Asks the daemon for what an output displays, and writes it to a png
+fn save_wallpaper(save: &cli::Save, socket_path: &PathBuf) -> Result<(), String> {
+    let infos = query_outputs(&split_cmdline_outputs(&save.output), socket_path)?;
+    let info = infos
+        .first()
+        .ok_or("daemon did not answer with any output to save")?;
+    let get_buffer = ipc::GetBufferSend {
+        output: info.name.clone(),
+    };
+    let socket = connect_to_socket(socket_path, 5, 100)?;
+    RequestSend::GetBuffer(get_buffer.create_request()).send(&socket)?;
+    let buffer = match Answer::receive(read_answer(&socket)?) {
+        Answer::Buffer(buffer) => buffer,
+        Answer::Err(kind, msg) => return Err(daemon_error(kind, &msg)),
+        _ => return Err("daemon did not answer with the output's buffer".to_string()),
+    };
+
+    let (width, height) = buffer.dim;
+    let rgb = buffer.to_rgb();
+    if rgb.len() != width as usize * height as usize * 3 {
+        return Err(format!(
+            "daemon sent a buffer of {} bytes, which does not match its dimensions ({width}x{height})",
+            buffer.bytes.len()
+        ));
+    }
+    image::save_buffer_with_format(
+        &save.file,
+        &rgb,
+        width,
+        height,
+        image::ExtendedColorType::Rgb8,
+        image::ImageFormat::Png,
+    )
+    .map_err(|e| format!("failed to write {:?}: {e}", save.file))
+}
+
+fn make_span_request(
+    img: &cli::Img,
+    infos: &[ipc::BgInfo],
+    preload: bool,
+) -> Result<ipc::Mmap, String> {
+    let pixel_format = infos[0].pixel_format;
+    let (imgbuf, path, _) = load_image(&img.image)?;
+    if imgbuf.is_animated() {
+        eprintln!("WARNING: spanning animated images is not supported. Using the first frame only");
+    }
+    let img_raw = imgbuf.decode(pixel_format, img.dither)?;
+
+    // bounding box of all outputs, in the compositor's (logical) coordinates
+    let left = infos.iter().map(|i| i.position.0).min().unwrap_or(0);
+    let top = infos.iter().map(|i| i.position.1).min().unwrap_or(0);
+    let right = infos
+        .iter()
+        .map(|i| i.position.0 + i.dim.0 as i32)
+        .max()
+        .unwrap_or(0);
+    let bottom = infos
+        .iter()
+        .map(|i| i.position.1 + i.dim.1 as i32)
+        .max()
+        .unwrap_or(0);
+    let layout = ((right - left) as u32, (bottom - top) as u32);
+
+    let mut img_req_builder = img_request_builder(img, preload);
+    for info in infos {
+        let region = (
+            (info.position.0 - left) as u32,
+            (info.position.1 - top) as u32,
+            info.dim.0,
+            info.dim.1,
+        );
+        // we crop in logical coordinates, but resize to the real dimensions, so that outputs with
+        // different scale factors still line up
+        let dim = info.real_dim();
+        img_req_builder.push(
+            ipc::ImgSend {
+                img: img_span(&img_raw, layout, region, dim, make_filter(&img.filter))?,
+                path: path.clone(),
+                dim,
+                format: pixel_format,
+            },
+            std::slice::from_ref(&info.name),
+            None,
+        );
+    }
+
     Ok(img_req_builder.build())
 }
 
+/// Reads (or downloads) the image, returning it along with the path we tell the daemon about, and
+/// the one we use to look up the animation cache
+fn load_image(image: &CliImage) -> Result<(ImgBuf, String, PathBuf), String> {
+    match image {
+        CliImage::Path(img_path) => Ok((
+            ImgBuf::new(img_path)?,
+            canonical_path(img_path)?,
+            img_path.clone(),
+        )),
+        // the caches key on the archive and the entry together
+        CliImage::Archive { archive, entry } => {
+            let path = format!("{}#{entry}", canonical_path(archive)?);
+            Ok((
+                ImgBuf::from_archive(archive, entry)?,
+                path.clone(),
+                PathBuf::from(path),
+            ))
+        }
+        CliImage::Url(url) => Ok((ImgBuf::from_url(url)?, url.clone(), PathBuf::from(url))),
+        CliImage::Video(video_path) => Ok((
+            ImgBuf::from_video(video_path)?,
+            canonical_path(video_path)?,
+            video_path.clone(),
+        )),
+        CliImage::Color(_) => Err("colors are not images".to_string()),
+        CliImage::Precomputed(_) => {
+            Err("precomputed animations can only be displayed as they were exported".to_string())
+        }
+    }
+}
+
+fn canonical_path(img_path: &std::path::Path) -> Result<String, String> {
+    match img_path.canonicalize() {
+        Ok(p) => Ok(p.to_string_lossy().to_string()),
+        Err(e) => {
+            if let Some("-") = img_path.to_str() {
+                Ok("STDIN".to_string())
+            } else {
+                Err(format!("failed no canonicalize image path: {e}"))
+            }
+        }
+    }
+}
+
+fn make_region((x, y, width, height): (u32, u32, u32, u32)) -> ipc::Region {
+    ipc::Region {
+        x,
+        y,
+        width,
+        height,
+    }
+}
+
+/// `swww img --region` must fit within every output the image goes to
+fn check_region(
+    region: &ipc::Region,
+    requested_outputs: &[String],
+    socket_path: &PathBuf,
+) -> Result<(), String> {
+    for info in query_outputs(requested_outputs, socket_path)? {
+        if !region.fits(info.dim) {
+            return Err(format!(
+                "--region {region} does not fit output {}, which is {}x{}",
+                info.name, info.dim.0, info.dim.1
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// Queries the daemon for information on the `requested_outputs`, or on every output if that is
+/// empty. Fails like the daemon does, with `ErrorKind::UnknownOutput`, if it does not know any of
+/// them.
+///
+/// Outputs only get their name some time after they show up, so if the daemon has outputs without
+/// one, we give them a moment to get it before failing
+fn query_outputs(
+    requested_outputs: &[String],
+    socket_path: &PathBuf,
+) -> Result<Vec<ipc::BgInfo>, String> {
+    let mut tries = 0;
+    loop {
+        let socket = connect_to_socket(socket_path, 5, 100)?;
+        RequestSend::Query.send(&socket)?;
+        let bytes = read_answer(&socket)?;
+        drop(socket);
+        let infos = match Answer::receive(bytes) {
+            Answer::Info(infos) => infos.into_vec(),
+            Answer::Err(kind, e) => {
+                return Err(daemon_error(
+                    kind,
+                    &format!("daemon error when sending query: {e}"),
+                ))
+            }
+            _ => unreachable!(),
+        };
+
+        let known: Vec<String> = infos
+            .iter()
+            .filter(|info| info.name != ipc::BgInfo::UNNAMED)
+            .map(|info| info.name.clone())
+            .collect();
+        let pending = infos.len() - known.len();
+        match requested_outputs.iter().find(|o| !known.contains(o)) {
+            Some(_) if pending > 0 && tries < 10 => {
+                tries += 1;
+                std::thread::sleep(Duration::from_millis(100));
+            }
+            Some(unknown) => {
+                let e = ipc::unknown_output_message(unknown, &known, pending);
+                return Err(daemon_error(ipc::ErrorKind::UnknownOutput, &e));
+            }
+            None if infos.is_empty() => return Err("the daemon has no outputs".to_owned()),
+            None => {
+                return Ok(infos
+                    .into_iter()
+                    .filter(|info| {
+                        requested_outputs.is_empty() || requested_outputs.contains(&info.name)
+                    })
+                    .collect())
+            }
+        }
+    }
+}
+
 #[allow(clippy::type_complexity)]
 fn get_format_dims_and_outputs(
     requested_outputs: &[String],
+    socket_path: &PathBuf,
 ) -> Result<(ipc::PixelFormat, Vec<(u32, u32)>, Vec<Vec<String>>), String> {
     let mut outputs: Vec<Vec<String>> = Vec::new();
     let mut dims: Vec<(u32, u32)> = Vec::new();
     let mut imgs: Vec<ipc::BgImg> = Vec::new();
 
-    let socket = connect_to_socket(&get_socket_path(), 5, 100)?;
-    RequestSend::Query.send(&socket)?;
-    let bytes = read_socket(&socket)?;
-    drop(socket);
-    let answer = Answer::receive(bytes);
-    match answer {
-        Answer::Info(infos) => {
-            let mut format = ipc::PixelFormat::Xrgb;
-            for info in infos.iter() {
-                format = info.pixel_format;
-                let info_img = &info.img;
-                let name = info.name.to_string();
-                if !requested_outputs.is_empty() && !requested_outputs.contains(&name) {
-                    continue;
-                }
-                let real_dim = info.real_dim();
-                if let Some((_, output)) = dims
-                    .iter_mut()
-                    .zip(&imgs)
-                    .zip(&mut outputs)
-                    .find(|((dim, img), _)| real_dim == **dim && info_img == *img)
-                {
-                    output.push(name);
-                } else {
-                    outputs.push(vec![name]);
-                    dims.push(real_dim);
-                    imgs.push(info_img.clone());
-                }
-            }
-            if outputs.is_empty() {
-                Err("none of the requested outputs are valid".to_owned())
-            } else {
-                Ok((format, dims, outputs))
-            }
+    let infos = query_outputs(requested_outputs, socket_path)?;
+    let format = infos[0].pixel_format;
+    for info in infos {
+        let real_dim = info.real_dim();
+        if let Some((_, output)) = dims
+            .iter_mut()
+            .zip(&imgs)
+            .zip(&mut outputs)
+            .find(|((dim, img), _)| real_dim == **dim && info.img == **img)
+        {
+            output.push(info.name);
+        } else {
+            outputs.push(vec![info.name]);
+            dims.push(real_dim);
+            imgs.push(info.img);
         }
-        Answer::Err(e) => Err(format!("daemon error when sending query: {e}")),
-        _ => unreachable!(),
     }
+    Ok((format, dims, outputs))
 }
 
 fn split_cmdline_outputs(outputs: &str) -> Box<[String]> {
@@ -262,36 +1707,499 @@ fn split_cmdline_outputs(outputs: &str) -> Box<[String]> {
         .collect()
 }
 
-fn restore_from_cache(requested_outputs: &[String]) -> Result<(), String> {
-    let (_, _, outputs) = get_format_dims_and_outputs(requested_outputs)?;
+/// Whether `output` is a glob pattern (with `*` or `?`), instead of the name of an output
+fn is_output_pattern(output: &str) -> bool {
+    output.contains(['*', '?'])
+}
+
+/// Replaces every glob pattern in `requested_outputs` with the names of the outputs it matches.
+/// We only query the daemon if there is at least one pattern
+fn expand_output_patterns(
+    requested_outputs: Box<[String]>,
+    socket_path: &PathBuf,
+) -> Result<Box<[String]>, String> {
+    if !requested_outputs.iter().any(|o| is_output_pattern(o)) {
+        return Ok(requested_outputs);
+    }
+
+    let names: Vec<String> = query_outputs(&[], socket_path)?
+        .into_iter()
+        .map(|info| info.name)
+        .collect();
+    let mut expanded: Vec<String> = Vec::new();
+    for requested in requested_outputs.into_vec() {
+        if !is_output_pattern(&requested) {
+            if !expanded.contains(&requested) {
+                expanded.push(requested);
+            }
+            continue;
+        }
+        let mut matches = names.iter().filter(|name| glob_match(&requested, name));
+        let Some(first) = matches.next() else {
+            return Err(format!(
+                "output pattern '{requested}' does not match any output"
+            ));
+        };
+        for name in std::iter::once(first).chain(matches) {
+            if !expanded.contains(name) {
+                expanded.push(name.clone());
+            }
+        }
+    }
+    Ok(expanded.into_boxed_slice())
+}
+
+/// The outputs selected with `--outputs` (or `--output-position`), and `--filter-output-by-scale`
+fn select_img_outputs(img: &cli::Img, socket_path: &PathBuf) -> Result<Box<[String]>, String> {
+    let requested_outputs = match (img.output_position, &img.output_all_except) {
+        (Some(position), _) => output_at_position(position, socket_path)?,
+        (None, Some(excluded)) => {
+            let names: Vec<String> = query_outputs(&[], socket_path)?
+                .into_iter()
+                .map(|info| info.name)
+                .collect();
+            outputs_except(&names, &split_cmdline_outputs(excluded))?
+        }
+        (None, None) => expand_output_patterns(split_cmdline_outputs(&img.outputs), socket_path)?,
+    };
+    match img.filter_output_by_scale {
+        Some(filter) => filter_outputs_by_scale(&requested_outputs, filter, socket_path),
+        None => Ok(requested_outputs),
+    }
+}
+
+/// Every one of `names` that does not match any of the `excluded` names (or glob patterns)
+fn outputs_except(names: &[String], excluded: &[String]) -> Result<Box<[String]>, String> {
+    let selected: Box<[String]> = names
+        .iter()
+        .filter(|name| !excluded.iter().any(|excluded| glob_match(excluded, name)))
+        .cloned()
+        .collect();
+    if selected.is_empty() {
+        return Err(
+            "excluding the outputs given to --output-all-except leaves no output".to_string(),
+        );
+    }
+    Ok(selected)
+}
+
+/// The name of the output at `position` in the compositor's layout
+fn output_at_position(
+    position: cli::OutputPosition,
+    socket_path: &PathBuf,
+) -> Result<Box<[String]>, String> {
+    let infos = query_outputs(&[], socket_path)?;
+    match find_output_at_position(&infos, position) {
+        Some(info) => Ok(Box::new([info.name.clone()])),
+        None => Err("the daemon does not know about any output".to_string()),
+    }
+}
+
+fn find_output_at_position(
+    infos: &[ipc::BgInfo],
+    position: cli::OutputPosition,
+) -> Option<&ipc::BgInfo> {
+    use cli::OutputPosition;
+    // smallest key wins. The second coordinate breaks ties, and then the name
+    let key = |info: &ipc::BgInfo| {
+        let (x, y) = info.position;
+        let (right, bottom) = (x + info.dim.0 as i32, y + info.dim.1 as i32);
+        match position {
+            OutputPosition::Leftmost => (x, y),
+            OutputPosition::Rightmost => (-right, y),
+            OutputPosition::Topmost => (y, x),
+            OutputPosition::Bottommost => (-bottom, x),
+        }
+    };
+    infos
+        .iter()
+        .min_by(|a, b| key(a).cmp(&key(b)).then_with(|| a.name.cmp(&b.name)))
+}
+
+/// Selects the outputs whose scale factor matches `filter`, out of `requested_outputs` (or all of
+/// them, if it is empty)
+fn filter_outputs_by_scale(
+    requested_outputs: &[String],
+    filter: cli::ScaleFilter,
+    socket_path: &PathBuf,
+) -> Result<Box<[String]>, String> {
+    let matching: Box<[String]> = query_outputs(requested_outputs, socket_path)?
+        .into_iter()
+        .filter(|info| filter.matches(info.scale_factor.as_f32()))
+        .map(|info| info.name)
+        .collect();
+    if matching.is_empty() {
+        return Err("no output matches --filter-output-by-scale".to_string());
+    }
+    Ok(matching)
+}
+
+/// Matches `name` against `pattern`, where `*` matches any sequence of characters (including an
+/// empty one) and `?` matches exactly one character
+fn glob_match(pattern: &str, name: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let name: Vec<char> = name.chars().collect();
+    let (mut p, mut n) = (0, 0);
+    // position of the last `*` we saw in the pattern, and of the character in the name we tried to
+    // match right after it, so we can backtrack
+    let mut backtrack = None;
+    while n < name.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                backtrack = Some((p, n));
+                p += 1;
+            }
+            Some(&c) if c == '?' || c == name[n] => {
+                p += 1;
+                n += 1;
+            }
+            _ => match backtrack {
+                Some((star, matched)) => {
+                    backtrack = Some((star, matched + 1));
+                    p = star + 1;
+                    n = matched + 1;
+                }
+                None => return false,
+            },
+        }
+    }
+    pattern[p..].iter().all(|&c| c == '*')
+}
+
+fn restore_from_cache(requested_outputs: &[String], socket_path: &PathBuf) -> Result<(), String> {
+    let (_, _, outputs) = get_format_dims_and_outputs(requested_outputs, socket_path)?;
 
     for output in outputs.iter().flatten() {
         let img_path = utils::cache::get_previous_image_path(output)
             .map_err(|e| format!("failed to get previous image path: {e}"))?;
         #[allow(deprecated)]
-        if let Err(e) = process_swww_args(&Swww::Img(cli::Img {
-            image: cli::parse_image(&img_path)?,
-            outputs: output.to_string(),
-            no_resize: false,
-            resize: ResizeStrategy::Crop,
-            fill_color: [0, 0, 0],
-            filter: cli::Filter::Lanczos3,
-            transition_type: cli::TransitionType::None,
-            transition_step: std::num::NonZeroU8::MAX,
-            transition_duration: 0.0,
-            transition_fps: 30,
-            transition_angle: 0.0,
-            transition_pos: cli::CliPosition {
-                x: cli::CliCoord::Pixel(0.0),
-                y: cli::CliCoord::Pixel(0.0),
-            },
-            invert_y: false,
-            transition_bezier: (0.0, 0.0, 0.0, 0.0),
-            transition_wave: (0.0, 0.0),
-        })) {
+        if let Err(e) = process_swww_args(
+            &Swww::Img(cli::Img {
+                image: cli::parse_image(&img_path)?,
+                outputs: output.to_string(),
+                output_position: None,
+                output_all_except: None,
+                filter_output_by_scale: None,
+                no_resize: false,
+                resize: ResizeStrategy::Crop,
+                crop_anchor: cli::CliPosition {
+                    x: cli::CliCoord::Percent(0.5),
+                    y: cli::CliCoord::Percent(0.5),
+                },
+                span: false,
+                region: None,
+                fill_color: [0, 0, 0],
+                filter: cli::Filter::Lanczos3,
+                animation_clock: cli::AnimationClock::Locked,
+                animation_interpolate: false,
+                animation_warp: false,
+                brightness: 1.0,
+                gamma: 1.0,
+                // the file may have changed since it was set
+                force: true,
+                sync: false,
+                from: None,
+                blend: None,
+                blend_over: None,
+                sticky: false,
+                workspace: None,
+                dither: false,
+                cache_decoded: false,
+                cache_by_content: false,
+                debug: false,
+                transition: cli::Transition {
+                    transition_preset: None,
+                    transition_type: cli::TransitionType::None,
+                    transition_step: std::num::NonZeroU8::MAX,
+                    transition_duration: 0.0,
+                    transition_fps: 30,
+                    transition_fps_from_output: false,
+                    transition_angle: 0.0,
+                    transition_pos: cli::CliPosition {
+                        x: cli::CliCoord::Pixel(0.0),
+                        y: cli::CliCoord::Pixel(0.0),
+                    },
+                    invert_x: false,
+                    invert_y: false,
+                    transition_bezier: (0.0, 0.0, 0.0, 0.0),
+                    transition_wave: (0.0, 0.0),
+                    transition_live: false,
+                    transition_tile_size: ipc::Transition::DEFAULT_TILE_SIZE,
+                    auto_fps: false,
+                    auto_step: false,
+                },
+                default_transition: false,
+            }),
+            // only sticky images need the namespace
+            "",
+            socket_path,
+        ) {
             eprintln!("WARNING: failed to load cache for output {output}: {e}");
         }
     }
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::Parser;
+
+    fn img_matches(args: &[&str]) -> ArgMatches {
+        let matches =
+            Cli::command().get_matches_from(["swww", "img", "0x000000"].iter().chain(args));
+        matches.subcommand_matches("img").unwrap().clone()
+    }
+
+    #[test]
+    fn should_detect_transition_options() {
+        assert!(!has_transition_options(&img_matches(&[])));
+        assert!(!has_transition_options(&img_matches(&["--resize", "fit"])));
+        assert!(has_transition_options(&img_matches(&[
+            "--transition-type",
+            "wipe"
+        ])));
+        assert!(has_transition_options(&img_matches(&[
+            "--transition-fps",
+            "60"
+        ])));
+        assert!(has_transition_options(&img_matches(&["--invert-y"])));
+    }
+
+    #[test]
+    fn explicit_options_override_transition_presets() {
+        let transition = |args: &[&str]| {
+            let matches = img_matches(args);
+            let mut transition = cli::Transition::from_arg_matches(&matches).unwrap();
+            apply_transition_preset(&mut transition, &matches);
+            transition
+        };
+
+        let wipe = transition(&["--transition-preset", "cinematic-wipe"]);
+        assert!(matches!(wipe.transition_type, cli::TransitionType::Wipe));
+        assert_eq!(wipe.transition_duration, 2.5);
+        assert_eq!(wipe.transition_fps, 60);
+        assert_eq!(wipe.transition_angle, 30.0);
+        assert_eq!(wipe.transition_bezier, (0.65, 0.0, 0.35, 1.0));
+
+        let wipe = transition(&[
+            "--transition-preset",
+            "cinematic-wipe",
+            "--transition-angle",
+            "120",
+            "--transition-fps",
+            "144",
+        ]);
+        assert!(matches!(wipe.transition_type, cli::TransitionType::Wipe));
+        assert_eq!(wipe.transition_angle, 120.0);
+        assert_eq!(wipe.transition_fps, 144);
+
+        // the step of 'simple' does not stick around when the preset changes the type
+        let fade = transition(&["--transition-preset", "gentle-fade"]);
+        assert!(matches!(fade.transition_type, cli::TransitionType::Fade));
+        assert_eq!(fade.transition_step.get(), 90);
+
+        let ripple = transition(&["--transition-preset", "ripple", "-t", "wipe"]);
+        assert!(matches!(ripple.transition_type, cli::TransitionType::Wipe));
+        assert_eq!(ripple.transition_wave, (40.0, 5.0));
+
+        let plain = transition(&[]);
+        assert!(matches!(plain.transition_type, cli::TransitionType::Simple));
+        assert_eq!(plain.transition_duration, 3.0);
+    }
+
+    #[test]
+    fn fps_and_step_are_automatic_unless_given() {
+        let transition = |args: &[&str]| {
+            let matches = img_matches(args);
+            let mut transition = cli::Transition::from_arg_matches(&matches).unwrap();
+            mark_automatic_options(&mut transition, &matches);
+            apply_transition_preset(&mut transition, &matches);
+            (transition.auto_fps, transition.auto_step)
+        };
+        assert_eq!(transition(&["-t", "wipe"]), (true, true));
+        assert_eq!(transition(&["--transition-fps", "60"]), (false, true));
+        assert_eq!(transition(&["--transition-fps-from-output"]), (false, true));
+        assert_eq!(transition(&["--transition-step", "10"]), (true, false));
+        // the preset's own fps counts as given
+        assert_eq!(
+            transition(&["--transition-preset", "cinematic-wipe"]),
+            (false, true)
+        );
+        assert_eq!(
+            transition(&["--transition-preset", "gentle-fade"]),
+            (true, true)
+        );
+    }
+
+    #[test]
+    fn watch_keeps_the_latest_progress_of_each_output() {
+        let progress = |output: &str, percent| ipc::TransitionProgress {
+            output: output.to_string(),
+            percent,
+        };
+        let mut transitions = Vec::new();
+        update_progress(&mut transitions, &[progress("DP-1", 10)]);
+        update_progress(
+            &mut transitions,
+            &[progress("DP-2", 5), progress("DP-1", 40)],
+        );
+        assert_eq!(transitions, [progress("DP-1", 40), progress("DP-2", 5)]);
+    }
+
+    #[test]
+    fn should_make_sticky_args() {
+        let cli = Cli::try_parse_from(["swww", "img", "--sticky", "--resize", "fit", "0x11aa33"])
+            .unwrap();
+        let Swww::Img(img) = cli.command else {
+            panic!("expected an img command");
+        };
+        assert_eq!(
+            sticky_args(&img, "").unwrap(),
+            [
+                "img",
+                "--transition-type=none",
+                "--resize=fit",
+                "--fill-color=000000",
+                "--filter=Lanczos3",
+                "--animation-clock=locked",
+                "0x11aa33"
+            ]
+        );
+        assert_eq!(sticky_args(&img, "work").unwrap()[0], "--namespace=work");
+
+        let cli = Cli::try_parse_from([
+            "swww",
+            "img",
+            "--sticky",
+            "--blend=screen",
+            "--blend-over=0x000010",
+            "0x11aa33",
+        ])
+        .unwrap();
+        let Swww::Img(img) = cli.command else {
+            panic!("expected an img command");
+        };
+        let args = sticky_args(&img, "").unwrap();
+        assert_eq!(
+            args[args.len() - 3..],
+            ["--blend=screen", "--blend-over=0x000010", "0x11aa33"]
+        );
+        // the mode and the base go together
+        assert!(Cli::try_parse_from(["swww", "img", "--blend=screen", "0x000000"]).is_err());
+        assert!(Cli::try_parse_from(["swww", "img", "--blend-over=0x000000", "0x000000"]).is_err());
+
+        // the crop anchor is given back as it is parsed
+        for (anchor, arg) in [("top", "0.5,1.0"), ("200,40", "200,40")] {
+            let cli =
+                Cli::try_parse_from(["swww", "img", "--crop-anchor", anchor, "0x11aa33"]).unwrap();
+            let Swww::Img(img) = cli.command else {
+                panic!("expected an img command");
+            };
+            let expected = format!("--crop-anchor={arg}");
+            assert!(sticky_args(&img, "").unwrap().contains(&expected));
+        }
+        // and pixels are not rounded
+        let anchor = ipc::Position::new(ipc::Coord::Pixel(12.5), ipc::Coord::Pixel(-3.0));
+        assert_eq!(position_arg(&anchor), "12.5,-3");
+
+        let cli =
+            Cli::try_parse_from(["swww", "img", "--region", "0,30,1920,1050", "0x11aa33"]).unwrap();
+        let Swww::Img(img) = cli.command else {
+            panic!("expected an img command");
+        };
+        let expected = "--region=0,30,1920,1050".to_string();
+        assert!(sticky_args(&img, "").unwrap().contains(&expected));
+
+        // the daemon displays images again with the same options, on a single output
+        let cli =
+            Cli::try_parse_from(["swww", "img", "--brightness=0.5", "--gamma=2.2", "0x11aa33"])
+                .unwrap();
+        let Swww::Img(img) = cli.command else {
+            panic!("expected an img command");
+        };
+        let replay = ["swww", "img", "--outputs=DP-1", "--force"]
+            .map(String::from)
+            .into_iter()
+            .chain(img_args(&img).unwrap());
+        let Swww::Img(replayed) = Cli::try_parse_from(replay).unwrap().command else {
+            panic!("expected an img command");
+        };
+        assert_eq!((replayed.brightness, replayed.gamma), (0.5, 2.2));
+
+        // sticky images are set on every output
+        assert!(
+            Cli::try_parse_from(["swww", "img", "--sticky", "-o", "DP-1", "0x000000"]).is_err()
+        );
+    }
+
+    #[test]
+    fn transition_fps_never_means_auto() {
+        let parse = |fps| Cli::try_parse_from(["swww", "img", "--transition-fps", fps, "0x000000"]);
+        assert!(parse("65534").is_ok());
+        assert!(parse("65535").is_err());
+    }
+
+    #[test]
+    fn should_match_output_globs() {
+        assert!(glob_match("DP-*", "DP-1"));
+        assert!(glob_match("DP-*", "DP-"));
+        assert!(glob_match("*-?", "HDMI-A"));
+        assert!(glob_match("*1*", "eDP-1"));
+        assert!(glob_match("e*P*1", "eDP-1"));
+        assert!(!glob_match("DP-*", "eDP-1"));
+        assert!(!glob_match("DP-?", "DP-10"));
+        assert!(!glob_match("HDMI", "HDMI-A-1"));
+    }
+
+    #[test]
+    fn should_exclude_outputs() {
+        let names: Vec<String> = ["DP-1", "DP-2", "HDMI-A-1"].map(String::from).into();
+        assert_eq!(
+            &*outputs_except(&names, &["DP-2".to_string()]).unwrap(),
+            ["DP-1", "HDMI-A-1"]
+        );
+        assert_eq!(
+            &*outputs_except(&names, &["DP-*".to_string(), "eDP-1".to_string()]).unwrap(),
+            ["HDMI-A-1"]
+        );
+        assert!(outputs_except(&names, &["*".to_string()]).is_err());
+    }
+
+    #[test]
+    fn should_find_outputs_by_position() {
+        use cli::OutputPosition;
+        let info = |name: &str, position, dim| ipc::BgInfo {
+            name: name.to_string(),
+            dim,
+            position,
+            scale_factor: ipc::Scale::Whole(std::num::NonZeroI32::new(1).unwrap()),
+            img: ipc::BgImg::Color([0, 0, 0]),
+            pixel_format: ipc::PixelFormat::Xrgb,
+            animated: false,
+        };
+        // two stacked outputs on the left, and a larger one on the right
+        let infos = [
+            info("DP-2", (0, 1080), (1920, 1080)),
+            info("DP-1", (0, 0), (1920, 1080)),
+            info("HDMI-A-1", (1920, 0), (2560, 1440)),
+        ];
+        let find = |position| find_output_at_position(&infos, position).map(|i| i.name.as_str());
+        assert_eq!(find(OutputPosition::Leftmost), Some("DP-1"));
+        assert_eq!(find(OutputPosition::Rightmost), Some("HDMI-A-1"));
+        assert_eq!(find(OutputPosition::Topmost), Some("DP-1"));
+        assert_eq!(find(OutputPosition::Bottommost), Some("DP-2"));
+
+        // exact ties go to the first name
+        let infos = [
+            info("DP-2", (0, 0), (1920, 1080)),
+            info("DP-1", (0, 0), (1920, 1080)),
+        ];
+        assert_eq!(
+            find_output_at_position(&infos, OutputPosition::Leftmost).map(|i| i.name.as_str()),
+            Some("DP-1")
+        );
+        assert!(find_output_at_position(&[], OutputPosition::Leftmost).is_none());
+    }
+}
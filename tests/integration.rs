@@ -43,13 +43,18 @@ fn make_test_imgs() {
     }
 }
 
+/// Keeps the tests from touching the user's real cache
+const TEST_CACHE_DIR: &str = "test_images/cache";
+
 fn cmd() -> Command {
-    Command::cargo_bin("swww").unwrap()
+    let mut cmd = Command::cargo_bin("swww").unwrap();
+    cmd.env("SWWW_CACHE_DIR", TEST_CACHE_DIR);
+    cmd
 }
 
 fn start_daemon() -> Command {
     let mut cmd = Command::cargo_bin("swww-daemon").unwrap();
-    cmd.arg("--no-cache");
+    cmd.args(["--no-cache", "--cache-dir", TEST_CACHE_DIR]);
     cmd
 }
 
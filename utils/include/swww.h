@@ -0,0 +1,50 @@
+/* C interface for setting wallpapers with swww-daemon. See utils/src/ffi.rs for how to build
+ * the shared library. */
+
+#ifndef SWWW_H
+#define SWWW_H
+
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+#define SWWW_OK 0
+/* a pointer was null, a string was not valid utf8, or the transition type is unknown */
+#define SWWW_ERR_INVALID_ARGUMENT -1
+/* failed to talk to the daemon. It is probably not running */
+#define SWWW_ERR_CONNECTION -2
+/* the daemon does not know the requested output */
+#define SWWW_ERR_NO_OUTPUT -3
+/* failed to open or decode the image */
+#define SWWW_ERR_IMAGE -4
+/* the daemon answered with an error, or with something we do not understand */
+#define SWWW_ERR_DAEMON -5
+
+#define SWWW_TRANSITION_SIMPLE 0
+#define SWWW_TRANSITION_FADE 1
+#define SWWW_TRANSITION_OUTER 2
+#define SWWW_TRANSITION_WIPE 3
+#define SWWW_TRANSITION_GROW 4
+#define SWWW_TRANSITION_WAVE 5
+#define SWWW_TRANSITION_NONE 6
+#define SWWW_TRANSITION_DISSOLVE 7
+#define SWWW_TRANSITION_SLIDE 8
+#define SWWW_TRANSITION_TILES 9
+
+/* Displays the image at `path` on `output`, like `swww img --outputs <output> <path>`, resizing it
+ * to fill the output. Animated images only display their first frame. Blocks until the daemon
+ * answers.
+ *
+ * `namespace` and `output` may be NULL (or empty), for the default namespace and all outputs.
+ *
+ * Returns SWWW_OK, or one of the (negative) SWWW_ERR_* codes. */
+int swww_img(const char *namespace_, const char *path, const char *output,
+             uint8_t transition_type);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif /* SWWW_H */
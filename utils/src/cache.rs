@@ -3,47 +3,123 @@
 //! The idea is:
 //!   1. the client registers the last image sent for each output in a file
 //!   2. the daemon spawns a client that reloads that image when an output is created
+//!
+//! We also store the animation frames and decoded images of the images sent, keyed by their path,
+//! or by the hash of their contents if the client linked the path to it with `link_content`.
 
 use std::{
     fs::File,
     io::{self, Read, Write},
     path::{Path, PathBuf},
+    time::{Duration, UNIX_EPOCH},
 };
 
-use crate::ipc::{Animation, Mmap, PixelFormat};
+use crate::ipc::{Animation, CacheEntry, Mmap, PixelFormat, Transition};
 
-pub(crate) fn store(output_name: &str, img_path: &str) -> io::Result<()> {
-    let mut filepath = cache_dir()?;
-    filepath.push(output_name);
+/// Name of the file in which we store the daemon's default transition
+const DEFAULT_TRANSITION_FILE: &str = "default.transition";
+/// Prefix of the decoded images' filenames, so that they are not mistaken for animation frames
+const DECODED_IMAGE_PREFIX: &str = "decoded__";
+/// The source's modification time: seconds and nanoseconds
+const DECODED_HEADER_SIZE: usize = 8 + 4;
+/// Starts the animation frames and decoded images we cache, followed by `CACHE_VERSION`
+const CACHE_MAGIC: [u8; 4] = *b"swww";
+/// The layout of the animation frames and decoded images we cache. Bump it whenever that changes
+/// (like the serialization of `Animation` or `BitPack`), so that we do not decode files written
+/// by other builds into garbage. Their filenames only change with the crate's version
+const CACHE_VERSION: u32 = 1;
+const CACHE_HEADER_SIZE: usize = 4 + 4;
+/// Prefix of the files that link an image's path to the hash of its contents
+const CONTENT_LINK_PREFIX: &str = "content__";
+/// The path's modification time (seconds and nanoseconds) and the hash of its contents
+const CONTENT_LINK_SIZE: usize = 8 + 4 + 16;
+/// Prefix of the cache keys made from a content hash, instead of a path
+const CONTENT_KEY_PREFIX: &str = "content-";
+
+pub fn store(output_name: &str, img_path: &str) -> io::Result<()> {
+    let filepath = output_file(cache_dir()?, output_name)?;
     File::create(filepath)?.write_all(img_path.as_bytes())
 }
 
+/// The file storing the image of `output_name`, in `cache_dir`. Output names come from users
+/// (through `swww img --outputs`) as well as from the compositor, so we refuse the ones that would
+/// point outside of the cache directory, or to the other files we keep there
+fn output_file(mut cache_dir: PathBuf, output_name: &str) -> io::Result<PathBuf> {
+    if !is_valid_output_name(output_name) {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::InvalidInput,
+            format!("'{output_name}' cannot be cached as an output name"),
+        ));
+    }
+    cache_dir.push(output_name);
+    Ok(cache_dir)
+}
+
+fn is_valid_output_name(output_name: &str) -> bool {
+    !output_name.is_empty()
+        && output_name != "."
+        && output_name != ".."
+        && !output_name.contains(['/', '\\', '\0'])
+        && output_name != DEFAULT_TRANSITION_FILE
+        && !output_name.starts_with(CONTENT_LINK_PREFIX)
+}
+
 pub(crate) fn store_animation_frames(
     animation: &[u8],
     path: &Path,
     dimensions: (u32, u32),
     pixel_format: PixelFormat,
 ) -> io::Result<()> {
-    let filename = animation_filename(path, dimensions, pixel_format);
     let mut filepath = cache_dir()?;
+    let filename = animation_filename(&cache_key(&filepath, path), dimensions, pixel_format);
     filepath.push(&filename);
 
     if !filepath.is_file() {
-        File::create(filepath)?.write_all(animation)
+        let mut file = File::create(filepath)?;
+        file.write_all(&cache_header(CACHE_VERSION))?;
+        file.write_all(animation)
     } else {
         Ok(())
     }
 }
 
+/// Stores an animation the client compressed without an `ImageRequestBuilder`, like the ones it
+/// streams with `ImageRequestBuilder::push_streamed`
+pub fn store_animation(
+    animation: &Animation,
+    path: &Path,
+    dimensions: (u32, u32),
+    pixel_format: PixelFormat,
+) -> io::Result<()> {
+    let mut buf = Vec::new();
+    animation.serialize(&mut buf);
+    store_animation_frames(&buf, path, dimensions, pixel_format)
+}
+
 pub fn load_animation_frames(
     path: &Path,
     dimensions: (u32, u32),
     pixel_format: PixelFormat,
 ) -> io::Result<Option<Animation>> {
-    let filename = animation_filename(path, dimensions, pixel_format);
-    let cache_dir = cache_dir()?;
-    let mut filepath = cache_dir.clone();
-    filepath.push(filename);
+    load_animation_frames_in(&cache_dir()?, path, dimensions, pixel_format)
+}
+
+fn load_animation_frames_in(
+    cache_dir: &Path,
+    path: &Path,
+    dimensions: (u32, u32),
+    pixel_format: PixelFormat,
+) -> io::Result<Option<Animation>> {
+    let key = cache_key(cache_dir, path);
+    let mut filepath = cache_dir.join(animation_filename(&key, dimensions, pixel_format));
+    // the frames may have been stored before the path was linked to its contents
+    if !filepath.is_file() && key.starts_with(CONTENT_KEY_PREFIX) {
+        filepath = cache_dir.join(animation_filename(
+            &path_key(path),
+            dimensions,
+            pixel_format,
+        ));
+    }
 
     let read_dir = cache_dir.read_dir()?;
 
@@ -52,8 +128,12 @@ pub fn load_animation_frames(
             let fd = File::open(&filepath)?.into();
             let len = rustix::fs::seek(&fd, rustix::fs::SeekFrom::End(0))?;
             let mmap = Mmap::from_fd(fd, len as usize);
+            let Some(bytes) = strip_cache_header(mmap.slice(), CACHE_VERSION) else {
+                remove_stale(&filepath);
+                return Ok(None);
+            };
 
-            match std::panic::catch_unwind(|| Animation::deserialize(&mmap, mmap.slice())) {
+            match std::panic::catch_unwind(|| Animation::deserialize(&mmap, bytes)) {
                 Ok((frames, _)) => return Ok(Some(frames)),
                 Err(e) => eprintln!("Error loading animation frames: {e:?}"),
             }
@@ -62,11 +142,239 @@ pub fn load_animation_frames(
     Ok(None)
 }
 
-pub fn get_previous_image_path(output_name: &str) -> io::Result<String> {
+/// Stores an image already decoded and resized for an output, so that sending it again does not
+/// have to do that work. `variant` must describe everything else that affects the result (like
+/// the resize strategy and filter). The source's modification time is stored along with it, so
+/// that we do not use it if the file changes. Images stored by content do not need it, since
+/// their key changes along with the contents
+pub fn store_decoded_image(
+    img: &[u8],
+    path: &Path,
+    dimensions: (u32, u32),
+    pixel_format: PixelFormat,
+    variant: &str,
+) -> io::Result<()> {
+    store_decoded_image_in(&cache_dir()?, img, path, dimensions, pixel_format, variant)
+}
+
+fn store_decoded_image_in(
+    cache_dir: &Path,
+    img: &[u8],
+    path: &Path,
+    dimensions: (u32, u32),
+    pixel_format: PixelFormat,
+    variant: &str,
+) -> io::Result<()> {
+    let key = cache_key(cache_dir, path);
+    let mtime = if key.starts_with(CONTENT_KEY_PREFIX) {
+        Duration::ZERO
+    } else {
+        modification_time(path)?
+    };
+    let filepath = cache_dir.join(decoded_image_filename(
+        &key,
+        dimensions,
+        pixel_format,
+        variant,
+    ));
+
+    let mut buf = Vec::with_capacity(CACHE_HEADER_SIZE + DECODED_HEADER_SIZE + img.len());
+    buf.extend_from_slice(&cache_header(CACHE_VERSION));
+    buf.extend_from_slice(&mtime.as_secs().to_ne_bytes());
+    buf.extend_from_slice(&mtime.subsec_nanos().to_ne_bytes());
+    buf.extend_from_slice(img);
+    File::create(filepath)?.write_all(&buf)
+}
+
+/// Loads an image stored with `store_decoded_image`, if the source did not change since
+pub fn load_decoded_image(
+    path: &Path,
+    dimensions: (u32, u32),
+    pixel_format: PixelFormat,
+    variant: &str,
+) -> io::Result<Option<Box<[u8]>>> {
+    load_decoded_image_in(&cache_dir()?, path, dimensions, pixel_format, variant)
+}
+
+fn load_decoded_image_in(
+    cache_dir: &Path,
+    path: &Path,
+    dimensions: (u32, u32),
+    pixel_format: PixelFormat,
+    variant: &str,
+) -> io::Result<Option<Box<[u8]>>> {
+    let mut key = cache_key(cache_dir, path);
+    let mut filepath = cache_dir.join(decoded_image_filename(
+        &key,
+        dimensions,
+        pixel_format,
+        variant,
+    ));
+    // it may have been stored before the path was linked to its contents
+    if !filepath.is_file() && key.starts_with(CONTENT_KEY_PREFIX) {
+        key = path_key(path);
+        filepath = cache_dir.join(decoded_image_filename(
+            &key,
+            dimensions,
+            pixel_format,
+            variant,
+        ));
+    }
+    if !filepath.is_file() {
+        return Ok(None);
+    }
+
+    let mut buf = Vec::new();
+    File::open(&filepath)?.read_to_end(&mut buf)?;
+    let Some(buf) = strip_cache_header(&buf, CACHE_VERSION) else {
+        remove_stale(&filepath);
+        return Ok(None);
+    };
+    let expected_len = DECODED_HEADER_SIZE
+        + dimensions.0 as usize * dimensions.1 as usize * pixel_format.channels() as usize;
+    if buf.len() != expected_len {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::InvalidData,
+            "cached decoded image is corrupted".to_string(),
+        ));
+    }
+
+    if !key.starts_with(CONTENT_KEY_PREFIX) {
+        let mtime = modification_time(path)?;
+        let secs = u64::from_ne_bytes(buf[0..8].try_into().unwrap());
+        let nanos = u32::from_ne_bytes(buf[8..12].try_into().unwrap());
+        if secs != mtime.as_secs() || nanos != mtime.subsec_nanos() {
+            return Ok(None);
+        }
+    }
+    Ok(Some(buf[DECODED_HEADER_SIZE..].into()))
+}
+
+/// Makes the animation frames and decoded images of `path` be stored (and looked up) by the hash
+/// of its `contents` from now on, so that identical images under different paths (like copies
+/// and symlinks) share a single entry. The link is ignored once the file is modified. Entries
+/// stored by path before are still found, until the ones by content are stored
+pub fn link_content(path: &Path, contents: &[u8]) -> io::Result<()> {
+    link_content_in(&cache_dir()?, path, contents)
+}
+
+fn link_content_in(cache_dir: &Path, path: &Path, contents: &[u8]) -> io::Result<()> {
+    let mtime = modification_time(path)?;
+    let mut buf = Vec::with_capacity(CACHE_HEADER_SIZE + CONTENT_LINK_SIZE);
+    buf.extend_from_slice(&cache_header(CACHE_VERSION));
+    buf.extend_from_slice(&mtime.as_secs().to_ne_bytes());
+    buf.extend_from_slice(&mtime.subsec_nanos().to_ne_bytes());
+    buf.extend_from_slice(&content_hash(contents).to_le_bytes());
+    File::create(cache_dir.join(content_link_filename(path)))?.write_all(&buf)
+}
+
+/// The hash `path` was linked to with `link_content`, if it was not modified since
+fn content_link(cache_dir: &Path, path: &Path) -> Option<u128> {
+    let filepath = cache_dir.join(content_link_filename(path));
+    let buf = std::fs::read(filepath).ok()?;
+    let buf = strip_cache_header(&buf, CACHE_VERSION)?;
+    if buf.len() != CONTENT_LINK_SIZE {
+        return None;
+    }
+    let mtime = modification_time(path).ok()?;
+    let secs = u64::from_ne_bytes(buf[0..8].try_into().unwrap());
+    let nanos = u32::from_ne_bytes(buf[8..12].try_into().unwrap());
+    if secs != mtime.as_secs() || nanos != mtime.subsec_nanos() {
+        return None;
+    }
+    Some(u128::from_le_bytes(buf[12..28].try_into().unwrap()))
+}
+
+/// 128 bits FNV-1a. We need a hash that stays the same across builds, and that is wide enough that
+/// different images are unlikely to share one by accident. It does not resist attacks: files made
+/// to collide would share a cache entry
+fn content_hash(contents: &[u8]) -> u128 {
+    const OFFSET: u128 = 0x6c62272e07bb014262b821756295c58d;
+    const PRIME: u128 = 0x0000000001000000000000000000013b;
+    contents.iter().fold(OFFSET, |hash, byte| {
+        (hash ^ *byte as u128).wrapping_mul(PRIME)
+    })
+}
+
+/// What we store the cache entries of `path` under: the hash of its contents, if it was linked to
+/// them, or else the path itself
+fn cache_key(cache_dir: &Path, path: &Path) -> String {
+    match content_link(cache_dir, path) {
+        Some(hash) => format!("{CONTENT_KEY_PREFIX}{hash:032x}"),
+        None => path_key(path),
+    }
+}
+
+fn path_key(path: &Path) -> String {
+    path.to_string_lossy().replace('/', "_")
+}
+
+fn cache_header(version: u32) -> [u8; CACHE_HEADER_SIZE] {
+    let mut header = [0; CACHE_HEADER_SIZE];
+    header[0..4].copy_from_slice(&CACHE_MAGIC);
+    header[4..8].copy_from_slice(&version.to_le_bytes());
+    header
+}
+
+/// Returns what follows the header of a cached file, or `None` if it was written with another
+/// `version` (or before we versioned them)
+fn strip_cache_header(bytes: &[u8], version: u32) -> Option<&[u8]> {
+    if bytes.len() < CACHE_HEADER_SIZE || bytes[0..4] != CACHE_MAGIC {
+        return None;
+    }
+    (bytes[4..8] == version.to_le_bytes()).then(|| &bytes[CACHE_HEADER_SIZE..])
+}
+
+/// Removes a cached file we cannot read, since we will never be able to
+fn remove_stale(filepath: &Path) {
+    eprintln!("WARNING: ignoring cache file {filepath:?} written by an incompatible swww version");
+    if let Err(e) = std::fs::remove_file(filepath) {
+        eprintln!("WARNING: failed to remove cache file {filepath:?}: {e}");
+    }
+}
+
+fn modification_time(path: &Path) -> io::Result<Duration> {
+    std::fs::metadata(path)?
+        .modified()?
+        .duration_since(UNIX_EPOCH)
+        .map_err(|e| std::io::Error::other(e.to_string()))
+}
+
+/// Stores the default transition, so that the daemon still uses it after it restarts
+pub fn store_default_transition(transition: &Transition) -> io::Result<()> {
     let mut filepath = cache_dir()?;
-    clean_previous_verions(&filepath);
+    filepath.push(DEFAULT_TRANSITION_FILE);
+    File::create(filepath)?.write_all(&transition.serialize())
+}
+
+pub fn load_default_transition() -> io::Result<Option<Transition>> {
+    let mut filepath = cache_dir()?;
+    filepath.push(DEFAULT_TRANSITION_FILE);
+    if !filepath.is_file() {
+        return Ok(None);
+    }
+
+    let mut buf = Vec::with_capacity(Transition::SERIALIZED_SIZE);
+    File::open(filepath)?.read_to_end(&mut buf)?;
+    // transitions stored before the tile size existed lack its last 2 bytes. A tile size of 0
+    // deserializes to the default one
+    if buf.len() == Transition::SERIALIZED_SIZE - 2 {
+        buf.resize(Transition::SERIALIZED_SIZE, 0);
+    }
+    if buf.len() != Transition::SERIALIZED_SIZE || buf[5] == 0 {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::InvalidData,
+            "stored default transition is corrupted".to_string(),
+        ));
+    }
+    Ok(Some(Transition::deserialize(&buf)))
+}
 
-    filepath.push(output_name);
+pub fn get_previous_image_path(output_name: &str) -> io::Result<String> {
+    let cache_dir = cache_dir()?;
+    clean_previous_verions(&cache_dir);
+
+    let filepath = output_file(cache_dir, output_name)?;
     if !filepath.is_file() {
         return Ok("".to_string());
     }
@@ -82,7 +390,46 @@ pub fn get_previous_image_path(output_name: &str) -> io::Result<String> {
     })
 }
 
-pub fn load(output_name: &str) -> io::Result<()> {
+/// Lists the image stored for each output, sorted by output name
+pub fn list() -> io::Result<Vec<CacheEntry>> {
+    let cache_dir = cache_dir()?;
+    let filenames: Vec<String> = cache_dir
+        .read_dir()?
+        .flatten()
+        .filter(|entry| entry.path().is_file())
+        .filter_map(|entry| entry.file_name().into_string().ok())
+        .collect();
+
+    let mut entries = Vec::new();
+    for filename in filenames.iter() {
+        // only the animation frames and decoded images we've cached will have a _v token,
+        // indicating their version
+        if filename.rfind("_v").is_some() || filename == DEFAULT_TRANSITION_FILE {
+            continue;
+        }
+        let img_path = get_previous_image_path(filename)?;
+        if img_path.is_empty() {
+            continue;
+        }
+        let prefixes = [
+            format!("{}__", path_key(Path::new(&img_path))),
+            format!("{}__", cache_key(&cache_dir, Path::new(&img_path))),
+        ];
+        let animated = filenames
+            .iter()
+            .any(|f| prefixes.iter().any(|prefix| f.starts_with(prefix)));
+        entries.push(CacheEntry {
+            output: filename.clone(),
+            img_path,
+            animated,
+        });
+    }
+    entries.sort_by(|a, b| a.output.cmp(&b.output));
+    Ok(entries)
+}
+
+/// Displays the image cached for `output_name` again, through the daemon of `namespace`
+pub fn load(output_name: &str, namespace: &str) -> io::Result<()> {
     let img_path = get_previous_image_path(output_name)?;
     if img_path.is_empty() {
         return Ok(());
@@ -99,7 +446,11 @@ pub fn load(output_name: &str) -> io::Result<()> {
         }
     }
 
-    std::process::Command::new("swww")
+    let mut command = std::process::Command::new("swww");
+    if !namespace.is_empty() {
+        command.arg(format!("--namespace={namespace}"));
+    }
+    command
         .arg("img")
         .args([
             &format!("--outputs={output_name}"),
@@ -159,7 +510,11 @@ fn create_dir(p: &Path) -> io::Result<()> {
 }
 
 fn cache_dir() -> io::Result<PathBuf> {
-    if let Ok(path) = std::env::var("XDG_CACHE_HOME") {
+    if let Some(path) = std::env::var_os("SWWW_CACHE_DIR").filter(|p| !p.is_empty()) {
+        let path: PathBuf = path.into();
+        std::fs::create_dir_all(&path)?;
+        Ok(path)
+    } else if let Ok(path) = std::env::var("XDG_CACHE_HOME") {
         let mut path: PathBuf = path.into();
         path.push("swww");
         create_dir(&path)?;
@@ -173,16 +528,34 @@ fn cache_dir() -> io::Result<PathBuf> {
     } else {
         Err(std::io::Error::new(
             std::io::ErrorKind::Other,
-            "failed to read both $XDG_CACHE_HOME and $HOME environment variables".to_string(),
+            "failed to read $SWWW_CACHE_DIR, $XDG_CACHE_HOME and $HOME environment variables"
+                .to_string(),
         ))
     }
 }
 
+/// `key` is what `cache_key` returns
+#[must_use]
+fn animation_filename(key: &str, dimensions: (u32, u32), pixel_format: PixelFormat) -> PathBuf {
+    format!(
+        "{key}__{}x{}_{:?}_v{}",
+        dimensions.0,
+        dimensions.1,
+        pixel_format,
+        env!("CARGO_PKG_VERSION"),
+    )
+    .into()
+}
+
 #[must_use]
-fn animation_filename(path: &Path, dimensions: (u32, u32), pixel_format: PixelFormat) -> PathBuf {
+fn decoded_image_filename(
+    key: &str,
+    dimensions: (u32, u32),
+    pixel_format: PixelFormat,
+    variant: &str,
+) -> PathBuf {
     format!(
-        "{}__{}x{}_{:?}_v{}",
-        path.to_string_lossy().replace('/', "_"),
+        "{DECODED_IMAGE_PREFIX}{key}__{}x{}_{:?}_{variant}_v{}",
         dimensions.0,
         dimensions.1,
         pixel_format,
@@ -190,3 +563,156 @@ fn animation_filename(path: &Path, dimensions: (u32, u32), pixel_format: PixelFo
     )
     .into()
 }
+
+/// Links are looked up with both relative and canonical paths, so we always name them after the
+/// canonical one
+#[must_use]
+fn content_link_filename(path: &Path) -> PathBuf {
+    let path = path.canonicalize().unwrap_or_else(|_| path.to_path_buf());
+    format!(
+        "{CONTENT_LINK_PREFIX}{}_v{}",
+        path_key(&path),
+        env!("CARGO_PKG_VERSION"),
+    )
+    .into()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn decoded_images_are_invalidated_when_the_source_changes() {
+        let dir = std::env::temp_dir().join(format!("swww-cache-test-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let source = dir.join("source.png");
+        let file = File::create(&source).unwrap();
+        file.set_modified(UNIX_EPOCH + Duration::from_secs(1000))
+            .unwrap();
+
+        let dim = (2, 2);
+        let format = PixelFormat::Xrgb;
+        let img = [7u8; 2 * 2 * 4];
+        let stored = store_decoded_image_in(&dir, &img, &source, dim, format, "crop");
+        let loaded = load_decoded_image_in(&dir, &source, dim, format, "crop");
+        let other_variant = load_decoded_image_in(&dir, &source, dim, format, "fit");
+        file.set_modified(UNIX_EPOCH + Duration::from_secs(2000))
+            .unwrap();
+        let changed = load_decoded_image_in(&dir, &source, dim, format, "crop");
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        stored.unwrap();
+        assert_eq!(loaded.unwrap().as_deref(), Some(&img[..]));
+        assert!(other_variant.unwrap().is_none());
+        assert!(changed.unwrap().is_none());
+    }
+
+    #[test]
+    fn linked_copies_share_their_entries() {
+        let dir = std::env::temp_dir().join(format!("swww-cache-content-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let (a, b) = (dir.join("a.png"), dir.join("b.png"));
+        std::fs::write(&a, b"same").unwrap();
+        std::fs::write(&b, b"same").unwrap();
+        let dim = (2, 2);
+        let format = PixelFormat::Xrgb;
+        let (by_path, by_content) = ([1u8; 2 * 2 * 4], [2u8; 2 * 2 * 4]);
+
+        // stored before linking: still found by path once linked
+        store_decoded_image_in(&dir, &by_path, &a, dim, format, "crop").unwrap();
+        link_content_in(&dir, &a, b"same").unwrap();
+        let old = load_decoded_image_in(&dir, &a, dim, format, "crop");
+        store_decoded_image_in(&dir, &by_content, &a, dim, format, "crop").unwrap();
+        let unlinked = load_decoded_image_in(&dir, &b, dim, format, "crop");
+        link_content_in(&dir, &b, b"same").unwrap();
+        let shared = load_decoded_image_in(&dir, &b, dim, format, "crop");
+        // modifying a file invalidates its link
+        std::fs::write(&b, b"different").unwrap();
+        File::options()
+            .write(true)
+            .open(&b)
+            .unwrap()
+            .set_modified(UNIX_EPOCH + Duration::from_secs(1000))
+            .unwrap();
+        let modified = load_decoded_image_in(&dir, &b, dim, format, "crop");
+        let stored = decoded_images(&dir);
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(old.unwrap().as_deref(), Some(&by_path[..]));
+        assert!(unlinked.unwrap().is_none());
+        assert_eq!(shared.unwrap().as_deref(), Some(&by_content[..]));
+        assert!(modified.unwrap().is_none());
+        // a single entry by content, besides the one by path
+        assert_eq!(stored, 2);
+        assert_ne!(content_hash(b"same"), content_hash(b"different"));
+    }
+
+    #[test]
+    fn output_names_stay_inside_the_cache_directory() {
+        assert!(is_valid_output_name("DP-1"));
+        assert!(is_valid_output_name("HDMI-A-1"));
+        assert!(!is_valid_output_name(""));
+        assert!(!is_valid_output_name(".."));
+        assert!(!is_valid_output_name("../../.bashrc"));
+        assert!(!is_valid_output_name("/etc/passwd"));
+        assert!(!is_valid_output_name(DEFAULT_TRANSITION_FILE));
+        assert!(output_file(PathBuf::from("/cache"), "../DP-1").is_err());
+        assert_eq!(
+            output_file(PathBuf::from("/cache"), "DP-1").unwrap(),
+            PathBuf::from("/cache/DP-1")
+        );
+    }
+
+    /// How many decoded images are stored in `dir`
+    fn decoded_images(dir: &Path) -> usize {
+        dir.read_dir()
+            .unwrap()
+            .flatten()
+            .filter(|e| {
+                e.file_name()
+                    .to_string_lossy()
+                    .starts_with(DECODED_IMAGE_PREFIX)
+            })
+            .count()
+    }
+
+    #[test]
+    fn other_cache_versions_are_ignored_and_removed() {
+        let dir = std::env::temp_dir().join(format!("swww-cache-version-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let source = dir.join("source.gif");
+        File::create(&source).unwrap();
+        let dim = (2, 2);
+        let format = PixelFormat::Xrgb;
+
+        // decoded images written before the version was bumped
+        store_decoded_image_in(&dir, &[7u8; 2 * 2 * 4], &source, dim, format, "crop").unwrap();
+        let decoded = dir.join(decoded_image_filename(
+            &path_key(&source),
+            dim,
+            format,
+            "crop",
+        ));
+        let mut bytes = std::fs::read(&decoded).unwrap();
+        bytes[..CACHE_HEADER_SIZE].copy_from_slice(&cache_header(CACHE_VERSION - 1));
+        std::fs::write(&decoded, &bytes).unwrap();
+        let old_decoded = load_decoded_image_in(&dir, &source, dim, format, "crop");
+        let decoded_removed = !decoded.exists();
+
+        // animation frames written before we versioned the cache
+        let animation = dir.join(animation_filename(&path_key(&source), dim, format));
+        std::fs::write(&animation, [0xff; 64]).unwrap();
+        let old_animation = load_animation_frames_in(&dir, &source, dim, format);
+        let animation_removed = !animation.exists();
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert!(old_decoded.unwrap().is_none());
+        assert!(decoded_removed);
+        assert!(old_animation.unwrap().is_none());
+        assert!(animation_removed);
+        assert_eq!(
+            strip_cache_header(&cache_header(CACHE_VERSION), CACHE_VERSION),
+            Some(&[][..])
+        );
+    }
+}
@@ -6,7 +6,7 @@ use comp::pack_bytes;
 use decomp::{unpack_bytes_3channels, unpack_bytes_4channels};
 use std::ffi::{c_char, c_int};
 
-use crate::ipc::{ImageRequestBuilder, Mmap, MmappedBytes, PixelFormat};
+use crate::ipc::{Mmap, MmappedBytes, PixelFormat, SerializeBuf};
 mod comp;
 mod cpu;
 mod decomp;
@@ -58,16 +58,16 @@ pub struct BitPack {
 }
 
 impl BitPack {
-    pub(crate) fn serialize(&self, buf: &mut ImageRequestBuilder) {
+    pub(crate) fn serialize(&self, buf: &mut impl SerializeBuf) {
         let Self {
             expected_buf_size,
             compressed_size,
             ..
         } = self;
-        buf.extend(&(self.bytes().len() as u32).to_ne_bytes());
-        buf.extend(&(expected_buf_size).to_ne_bytes());
-        buf.extend(&(compressed_size).to_ne_bytes());
-        buf.extend(self.bytes());
+        buf.extend_from_slice(&(self.bytes().len() as u32).to_ne_bytes());
+        buf.extend_from_slice(&(expected_buf_size).to_ne_bytes());
+        buf.extend_from_slice(&(compressed_size).to_ne_bytes());
+        buf.extend_from_slice(self.bytes());
     }
 
     #[must_use]
@@ -87,6 +87,20 @@ impl BitPack {
         )
     }
 
+    /// How many bytes the frame takes while compressed
+    #[inline]
+    #[must_use]
+    pub fn compressed_len(&self) -> usize {
+        self.bytes().len()
+    }
+
+    /// How many bytes the buffer the frame is unpacked onto takes
+    #[inline]
+    #[must_use]
+    pub fn decompressed_len(&self) -> usize {
+        self.expected_buf_size as usize
+    }
+
     #[inline]
     #[must_use]
     fn bytes(&self) -> &[u8] {
@@ -95,6 +109,85 @@ impl BitPack {
             Inner::Mmapped(m) => m.bytes(),
         }
     }
+
+    /// Returns a copy of this frame with `f` applied to every color byte it changes.
+    ///
+    /// Since the pixels that did not change are not stored, the frame we unpack the result onto
+    /// must have had `f` applied to it as well.
+    #[must_use]
+    pub fn map_pixel_bytes(&self, f: impl Fn(u8) -> u8) -> BitPack {
+        let mut diff = vec![0; self.compressed_size as usize];
+        // SAFETY: diff has exactly the capacity we give LZ4
+        let size = unsafe {
+            let bytes = self.bytes();
+            LZ4_decompress_safe(
+                bytes.as_ptr() as _,
+                diff.as_mut_ptr() as _,
+                bytes.len() as c_int,
+                self.compressed_size,
+            )
+        };
+        assert_eq!(size, self.compressed_size, "BitPack is malformed!");
+
+        // same walk as in `unpack_bytes_3channels`: a run of pixels to skip, followed by a run of
+        // pixels to copy, whose bytes come right after. The final bytes are just padding
+        let len = diff.len() - 3;
+        let mut i = 0;
+        while i < len {
+            while diff[i] == u8::MAX {
+                i += 1;
+            }
+            i += 1;
+
+            let mut to_cpy = 0;
+            while diff[i] == u8::MAX {
+                to_cpy += u8::MAX as usize;
+                i += 1;
+            }
+            to_cpy += diff[i] as usize;
+            i += 1;
+
+            for byte in diff[i..i + to_cpy * 3].iter_mut() {
+                *byte = f(*byte);
+            }
+            i += to_cpy * 3;
+        }
+
+        BitPack {
+            inner: Inner::Boxed(lz4_compress(&diff)),
+            expected_buf_size: self.expected_buf_size,
+            compressed_size: self.compressed_size,
+        }
+    }
+}
+
+/// Compresses `buf` with LZ4
+///
+/// # Panics:
+///   * `buf.len() > LZ4_MAX_INPUT_SIZE`
+fn lz4_compress(buf: &[u8]) -> Box<[u8]> {
+    // This should only be a problem with 64k monitors and beyond, (hopefully) far into the
+    // future
+    assert!(
+        buf.len() <= LZ4_MAX_INPUT_SIZE,
+        "frame is too large! cannot compress with LZ4!"
+    );
+
+    // SAFETY: the above assertion ensures this will never fail
+    let size = unsafe { LZ4_compressBound(buf.len() as c_int) } as usize;
+    let mut v = vec![0; size];
+    // SAFETY: we've ensured above that size >= LZ4_compressBound, so this should always work
+    let n = unsafe {
+        LZ4_compress_HC(
+            buf.as_ptr().cast(),
+            v.as_mut_ptr() as _,
+            buf.len() as c_int,
+            size as c_int,
+            9,
+        ) as usize
+    };
+    v.truncate(n);
+    v.into_boxed_slice()
 }
 
 /// Struct responsible for compressing our data. We use it to cache vector extensions that might
@@ -143,27 +236,7 @@ impl Compressor {
             return None;
         }
 
-        // This should only be a problem with 64k monitors and beyond, (hopefully) far into the
-        // future
-        assert!(
-            self.buf.len() <= LZ4_MAX_INPUT_SIZE,
-            "frame is too large! cannot compress with LZ4!"
-        );
-
-        // SAFETY: the above assertion ensures this will never fail
-        let size = unsafe { LZ4_compressBound(self.buf.len() as c_int) } as usize;
-        let mut v = vec![0; size];
-        // SAFETY: we've ensured above that size >= LZ4_compressBound, so this should always work
-        let n = unsafe {
-            LZ4_compress_HC(
-                self.buf.as_ptr().cast(),
-                v.as_mut_ptr() as _,
-                self.buf.len() as c_int,
-                size as c_int,
-                9,
-            ) as usize
-        };
-        v.truncate(n);
+        let v = lz4_compress(&self.buf);
 
         let expected_buf_size = if pixel_format.channels() == 3 {
             cur.len() as u32
@@ -172,7 +245,7 @@ impl Compressor {
         };
 
         Some(BitPack {
-            inner: Inner::Boxed(v.into_boxed_slice()),
+            inner: Inner::Boxed(v),
             expected_buf_size,
             compressed_size: self.buf.len() as i32,
         })
@@ -443,4 +516,34 @@ mod tests {
             }
         }
     }
+
+    #[test]
+    fn mapped_pixel_bytes() {
+        let f = |byte: u8| byte / 2;
+        for format in FORMATS {
+            // long runs, so that both counts need more than one byte
+            let frame1: Vec<u8> = (0..3000).map(|_| fastrand::u8(..)).collect();
+            let mut frame2 = frame1.clone();
+            for (i, byte) in frame2.iter_mut().enumerate() {
+                if (300..1500).contains(&i) || i >= 2700 {
+                    *byte = byte.wrapping_add(1);
+                }
+            }
+            let compressed = Compressor::new()
+                .compress(&frame1, &frame2, format)
+                .unwrap()
+                .map_pixel_bytes(f);
+
+            let mapped1: Vec<u8> = frame1.iter().copied().map(f).collect();
+            let mut buf = buf_from(&mapped1, format.channels().into());
+            Decompressor::new()
+                .decompress(&compressed, &mut buf, format)
+                .unwrap();
+            let channels = format.channels() as usize;
+            for (pixel, expected) in buf.chunks_exact(channels).zip(frame2.chunks_exact(3)) {
+                let expected: Vec<u8> = expected.iter().copied().map(f).collect();
+                assert_eq!(pixel[..3], expected[..]);
+            }
+        }
+    }
 }
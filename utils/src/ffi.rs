@@ -0,0 +1,193 @@
+//! A C interface for setting wallpapers, for programs that would rather link against us than
+//! spawn `swww img`. Only compiled with the `ffi` feature. To build it as a shared library, run:
+//!
+//! ```sh
+//! cargo rustc -p utils --release --features ffi --crate-type cdylib
+//! ```
+//!
+//! The declarations are in `utils/include/swww.h`. Everything here must stay backwards compatible:
+//! only add new functions and error codes, never change the existing ones.
+
+use std::{
+    ffi::{c_char, c_int, CStr},
+    path::Path,
+};
+
+use image::imageops::FilterType;
+
+use crate::ipc::{
+    self, get_socket_path, Answer, ImageRequestBuilder, ImgSend, RequestSend, TransitionBuilder,
+    TransitionType,
+};
+
+pub const SWWW_OK: c_int = 0;
+/// A pointer was null, a string was not valid utf8, or the transition type is unknown
+pub const SWWW_ERR_INVALID_ARGUMENT: c_int = -1;
+/// Failed to talk to the daemon. It is probably not running
+pub const SWWW_ERR_CONNECTION: c_int = -2;
+/// The daemon does not know the requested output
+pub const SWWW_ERR_NO_OUTPUT: c_int = -3;
+/// Failed to open or decode the image
+pub const SWWW_ERR_IMAGE: c_int = -4;
+/// The daemon answered with an error, or with something we do not understand
+pub const SWWW_ERR_DAEMON: c_int = -5;
+
+/// Displays the image at `path` on `output`, like `swww img --outputs <output> <path>`, resizing
+/// it to fill the output. Animated images only display their first frame. Blocks until the daemon
+/// answers.
+///
+/// `namespace` selects the daemon, like `swww --namespace`, and `output` the output. Either may be
+/// null (or empty), for the default namespace and all outputs, respectively. `transition_type` is
+/// one of the `SWWW_TRANSITION_*` constants, and the transition otherwise has the same defaults as
+/// in `swww img`.
+///
+/// Returns `SWWW_OK`, or one of the (negative) `SWWW_ERR_*` constants.
+///
+/// # Safety
+///
+/// Every non null pointer must point to a nul terminated string, that is valid for the duration of
+/// the call.
+#[no_mangle]
+pub unsafe extern "C" fn swww_img(
+    namespace: *const c_char,
+    path: *const c_char,
+    output: *const c_char,
+    transition_type: u8,
+) -> c_int {
+    // SAFETY: the caller guarantees the pointers are valid strings, or null
+    let (namespace, path, output) = unsafe { (str_arg(namespace), str_arg(path), str_arg(output)) };
+    let (Ok(namespace), Ok(Some(path)), Ok(output)) = (namespace, path, output) else {
+        return SWWW_ERR_INVALID_ARGUMENT;
+    };
+    let Some(transition_type) = transition_type_from_u8(transition_type) else {
+        return SWWW_ERR_INVALID_ARGUMENT;
+    };
+
+    match img(
+        namespace.unwrap_or_default(),
+        Path::new(path),
+        output.unwrap_or_default(),
+        transition_type,
+    ) {
+        Ok(()) => SWWW_OK,
+        Err(code) => code,
+    }
+}
+
+/// # Safety
+///
+/// `s` must be null, or point to a valid nul terminated string
+unsafe fn str_arg<'a>(s: *const c_char) -> Result<Option<&'a str>, ()> {
+    if s.is_null() {
+        return Ok(None);
+    }
+    // SAFETY: guaranteed by the caller
+    unsafe { CStr::from_ptr(s) }
+        .to_str()
+        .map(Some)
+        .map_err(|_| ())
+}
+
+fn transition_type_from_u8(n: u8) -> Option<TransitionType> {
+    Some(match n {
+        0 => TransitionType::Simple,
+        1 => TransitionType::Fade,
+        2 => TransitionType::Outer,
+        3 => TransitionType::Wipe,
+        4 => TransitionType::Grow,
+        5 => TransitionType::Wave,
+        6 => TransitionType::None,
+        7 => TransitionType::Dissolve,
+        8 => TransitionType::Slide,
+        9 => TransitionType::Tiles,
+        _ => return None,
+    })
+}
+
+fn img(
+    namespace: &str,
+    path: &Path,
+    output: &str,
+    transition_type: TransitionType,
+) -> Result<(), c_int> {
+    let socket_path = get_socket_path(namespace);
+    let infos = match request(&socket_path, RequestSend::Query)? {
+        Answer::Info(infos) => infos,
+        _ => return Err(SWWW_ERR_DAEMON),
+    };
+    let infos: Vec<ipc::BgInfo> = infos
+        .into_vec()
+        .into_iter()
+        .filter(|info| output.is_empty() || info.name == output)
+        .collect();
+    if infos.is_empty() {
+        return Err(SWWW_ERR_NO_OUTPUT);
+    }
+
+    let image = image::open(path).map_err(|_| SWWW_ERR_IMAGE)?;
+    let img_path = std::fs::canonicalize(path).map_err(|_| SWWW_ERR_IMAGE)?;
+    let img_path = img_path.to_str().ok_or(SWWW_ERR_INVALID_ARGUMENT)?;
+
+    let mut builder = ImageRequestBuilder::new(TransitionBuilder::new(transition_type).build());
+    for info in infos.iter() {
+        let dim = info.real_dim();
+        let format = info.pixel_format;
+        let resized = image.resize_to_fill(dim.0, dim.1, FilterType::Lanczos3);
+        let rgba = resized.into_rgba8();
+        let mut img = vec![0; dim.0 as usize * dim.1 as usize * format.channels() as usize];
+        ipc::convert_into(&rgba, &mut img, format);
+        builder.push(
+            ImgSend {
+                path: img_path.to_string(),
+                img: img.into_boxed_slice(),
+                dim,
+                format,
+            },
+            std::slice::from_ref(&info.name),
+            None,
+        );
+    }
+
+    match request(&socket_path, RequestSend::Img(builder.build()))? {
+        Answer::Ok => Ok(()),
+        _ => Err(SWWW_ERR_DAEMON),
+    }
+}
+
+fn request(socket_path: &std::path::PathBuf, request: RequestSend) -> Result<Answer, c_int> {
+    let socket = ipc::connect_to_socket(socket_path, 5, 100).map_err(|_| SWWW_ERR_CONNECTION)?;
+    request.send(&socket).map_err(|_| SWWW_ERR_CONNECTION)?;
+    let bytes = ipc::read_socket(&socket).map_err(|_| SWWW_ERR_CONNECTION)?;
+    // `Answer::receive` panics on answers it does not understand (say, from a newer daemon), and
+    // panics must not unwind into our caller
+    std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| Answer::receive(bytes)))
+        .map_err(|_| SWWW_ERR_DAEMON)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn transition_types_match_the_wire_format() {
+        for n in 0..=9 {
+            let transition_type = transition_type_from_u8(n).unwrap();
+            assert_eq!(transition_type as u8, n);
+        }
+        assert!(transition_type_from_u8(10).is_none());
+    }
+
+    #[test]
+    fn rejects_null_paths() {
+        // SAFETY: null pointers are allowed
+        let code = unsafe {
+            swww_img(
+                std::ptr::null(),
+                std::ptr::null(),
+                std::ptr::null(),
+                TransitionType::Fade as u8,
+            )
+        };
+        assert_eq!(code, SWWW_ERR_INVALID_ARGUMENT);
+    }
+}
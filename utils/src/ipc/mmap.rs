@@ -12,6 +12,8 @@ pub struct Mmap {
     fd: OwnedFd,
     ptr: NonNull<std::ffi::c_void>,
     len: usize,
+    /// how many bytes we mapped, if more than `len`. See `create_growable`
+    reserved: usize,
     mmaped: bool,
 }
 
@@ -35,16 +37,22 @@ impl Mmap {
             fd,
             ptr,
             len,
+            reserved: 0,
             mmaped: true,
         }
     }
 
+    /// How many bytes we mapped
+    fn mapped_len(&self) -> usize {
+        self.len.max(self.reserved)
+    }
+
     #[inline]
     /// Unmaps without destroying the file descriptor
     ///
     /// This is only ever used in the daemon, when animations finish, in order to free up memory
     pub fn unmap(&mut self) {
-        if let Err(e) = unsafe { munmap(self.ptr.as_ptr(), self.len) } {
+        if let Err(e) = unsafe { munmap(self.ptr.as_ptr(), self.mapped_len()) } {
             eprintln!("ERROR WHEN UNMAPPING MEMORY: {e}");
         } else {
             self.mmaped = false;
@@ -61,7 +69,7 @@ impl Mmap {
             self.ptr = unsafe {
                 let ptr = mmap(
                     std::ptr::null_mut(),
-                    self.len,
+                    self.mapped_len(),
                     Self::PROT,
                     Self::FLAGS,
                     &self.fd,
@@ -78,6 +86,10 @@ impl Mmap {
     #[inline]
     pub fn remap(&mut self, new_len: usize) {
         rustix::io::retry_on_intr(|| rustix::fs::ftruncate(&self.fd, new_len as u64)).unwrap();
+        if new_len <= self.reserved {
+            self.len = new_len;
+            return;
+        }
 
         #[cfg(target_os = "linux")]
         {
@@ -118,6 +130,39 @@ impl Mmap {
         };
     }
 
+    /// Like `create`, but maps `max_len` bytes right away, so that growing it up to them with
+    /// `remap` never moves it. Only the first `len` bytes are backed by the file (and memory) until
+    /// then, so the rest must not be touched. This lets several users share one mapping, each
+    /// writing into its own part of it while others grow it
+    #[must_use]
+    pub fn create_growable(len: usize, max_len: usize) -> Self {
+        let fd = create_shm_fd().unwrap();
+        rustix::io::retry_on_intr(|| rustix::fs::ftruncate(&fd, len as u64)).unwrap();
+
+        let ptr = unsafe {
+            let flags = Self::FLAGS.union(MapFlags::NORESERVE);
+            let ptr = mmap(std::ptr::null_mut(), max_len, Self::PROT, flags, &fd, 0).unwrap();
+            // SAFETY: the function above will never return a null pointer if it succeeds
+            // POSIX says that the implementation will never select an address at 0
+            NonNull::new_unchecked(ptr)
+        };
+        Self {
+            fd,
+            ptr,
+            len,
+            reserved: max_len,
+            mmaped: true,
+        }
+    }
+
+    /// Where the memory starts. Unlike `slice_mut`, this lets each user of a mapping made with
+    /// `create_growable` borrow only its own part of it
+    #[inline]
+    #[must_use]
+    pub fn as_mut_ptr(&self) -> *mut u8 {
+        self.ptr.as_ptr().cast()
+    }
+
     #[must_use]
     pub(crate) fn from_fd(fd: OwnedFd, len: usize) -> Self {
         let ptr = unsafe {
@@ -138,6 +183,7 @@ impl Mmap {
             fd,
             ptr,
             len,
+            reserved: 0,
             mmaped: true,
         }
     }
@@ -1,4 +1,4 @@
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
 use rustix::fd::OwnedFd;
 
@@ -6,21 +6,55 @@ mod mmap;
 mod socket;
 mod types;
 
-use crate::cache;
+use crate::{cache, precomputed};
 pub use mmap::*;
 pub use socket::*;
 pub use types::*;
 
+/// Somewhere we can serialize animations into: an image request, or a precomputed animation file
+pub(crate) trait SerializeBuf {
+    fn extend_from_slice(&mut self, bytes: &[u8]);
+}
+
+impl SerializeBuf for Vec<u8> {
+    fn extend_from_slice(&mut self, bytes: &[u8]) {
+        Vec::extend_from_slice(self, bytes)
+    }
+}
+
+impl SerializeBuf for ImageRequestBuilder {
+    fn extend_from_slice(&mut self, bytes: &[u8]) {
+        self.extend(bytes)
+    }
+}
+
 pub struct ImageRequestBuilder {
     memory: Mmap,
     len: usize,
     img_count: u8,
     img_count_index: usize,
+    /// whether to store the images in the cache, as the ones their outputs are displaying
+    cache_outputs: bool,
+    /// whether to store the animations in the cache, for the next time the same image is sent
+    cache_animations: bool,
+    /// see `replay`
+    replay: Option<Vec<String>>,
 }
 
 impl ImageRequestBuilder {
     #[inline]
     pub fn new(transition: Transition) -> Self {
+        Self::with_transition(Some(&transition))
+    }
+
+    /// Creates a request that will use whatever transition was set with
+    /// `RequestSend::SetDefaultTransition`
+    #[inline]
+    pub fn with_default_transition() -> Self {
+        Self::with_transition(None)
+    }
+
+    fn with_transition(transition: Option<&Transition>) -> Self {
         let memory = Mmap::create(1 << (20 + 3)); // start with 8 MB
         let len = 0;
         let mut builder = Self {
@@ -28,14 +62,98 @@ impl ImageRequestBuilder {
             len,
             img_count: 0,
             img_count_index: 0,
+            cache_outputs: true,
+            cache_animations: true,
+            replay: None,
         };
-        transition.serialize(&mut builder);
+        // the first byte holds flags: bit 0 is set if a transition follows (otherwise, the daemon
+        // should use its default one), bit 1 is `force`, bit 2 is `debug`, bit 3 is `sync`, bit 4
+        // is set if every image is followed by the one to transition from (see `push_source`) and
+        // bit 5 if they are followed by a blend mode and the base to blend them over (see
+        // `push_blend`) and bit 6 if they are followed by the region they cover (see
+        // `push_region`). Bit 7 is set if the images are followed by the arguments to display them
+        // again (see `replay`)
+        match transition {
+            Some(transition) => {
+                builder.push_byte(1);
+                builder.extend(&transition.serialize());
+            }
+            None => builder.push_byte(0),
+        }
+        builder.extend(&ColorAdjustment::NONE.serialize());
         builder.img_count_index = builder.len;
         builder.len += 1;
-        assert_eq!(builder.len, 52);
         builder
     }
 
+    /// Makes the daemon display the images even on outputs that are already displaying an image
+    /// with the same path
+    #[inline]
+    #[must_use]
+    pub fn force(mut self) -> Self {
+        self.memory.slice_mut()[0] |= 0b10;
+        self
+    }
+
+    /// Makes the daemon log in detail how it handles this request, even if it was started with
+    /// `--quiet`
+    #[inline]
+    #[must_use]
+    pub fn debug(mut self) -> Self {
+        self.memory.slice_mut()[0] |= 0b100;
+        self
+    }
+
+    /// Makes the daemon display the images on the next frame, without a transition and without
+    /// their animations, and answer only once they are committed
+    #[inline]
+    #[must_use]
+    pub fn sync(mut self) -> Self {
+        self.memory.slice_mut()[0] |= 0b1000;
+        self
+    }
+
+    /// Makes the daemon apply `adjustment` to every image before displaying it
+    #[inline]
+    #[must_use]
+    pub fn adjust(mut self, adjustment: ColorAdjustment) -> Self {
+        // it comes right before the image count
+        let start = self.img_count_index - ColorAdjustment::SERIALIZED_SIZE;
+        self.memory.slice_mut()[start..self.img_count_index]
+            .copy_from_slice(&adjustment.serialize());
+        self
+    }
+
+    /// Does not store the images in the cache as the ones their outputs display. For
+    /// `RequestSend::Preload`, since preloaded images are not displayed yet
+    #[inline]
+    #[must_use]
+    pub fn without_output_cache(mut self) -> Self {
+        self.cache_outputs = false;
+        self
+    }
+
+    /// Does not store the animations in the cache. For animations that differ from what the cache
+    /// has for their path and dimensions, like the ones cropped around another point than the
+    /// center
+    #[inline]
+    #[must_use]
+    pub fn without_animation_cache(mut self) -> Self {
+        self.cache_animations = false;
+        self
+    }
+
+    /// Makes the daemon display the images again when their outputs change resolution, by running
+    /// `swww` with `args`: the options of `swww img` they were set with, followed by the image.
+    /// The daemon adds the outputs and the transition. Without them, it stretches what the
+    /// outputs displayed instead
+    #[inline]
+    #[must_use]
+    pub fn replay(mut self, args: Vec<String>) -> Self {
+        self.replay = Some(args);
+        self
+    }
+
     fn push_byte(&mut self, byte: u8) {
         if self.len >= self.memory.len() {
             self.grow();
@@ -58,41 +176,34 @@ impl ImageRequestBuilder {
 
     #[inline]
     pub fn push(&mut self, img: ImgSend, outputs: &[String], animation: Option<Animation>) {
-        self.img_count += 1;
-
+        self.push_img(&img, outputs);
         let ImgSend {
             path,
-            img,
             dim: dims,
             format,
+            ..
         } = &img;
-        self.serialize_bytes(path.as_bytes());
-        self.serialize_bytes(img);
-        self.extend(&dims.0.to_ne_bytes());
-        self.extend(&dims.1.to_ne_bytes());
-        self.push_byte(*format as u8);
-
-        self.push_byte(outputs.len() as u8);
-        for output in outputs.iter() {
-            self.serialize_bytes(output.as_bytes());
-        }
 
         let animation_start = self.len + 1;
         if let Some(animation) = animation.as_ref() {
-            self.push_byte(1);
+            // 0 means there is no animation, otherwise the two lowest bits are the animation clock
+            // + 1, the third one is whether to interpolate it, the fourth whether it is streamed
+            // and the fifth whether to warp it
+            self.push_byte(
+                (animation.clock as u8 + 1)
+                    | ((animation.interpolate as u8) << 2)
+                    | ((animation.warp as u8) << 4),
+            );
             animation.serialize(self);
         } else {
             self.push_byte(0);
         }
 
-        // cache the request
-        for output in outputs.iter() {
-            if let Err(e) = super::cache::store(output, path) {
-                eprintln!("ERROR: failed to store cache: {e}");
-            }
-        }
-
-        if animation.is_some() && path != "-" {
+        // precomputed animations are already stored in a file of their own
+        let precomputed = Path::new(path)
+            .extension()
+            .is_some_and(|ext| ext == precomputed::EXTENSION);
+        if self.cache_animations && animation.is_some() && path != "-" && !precomputed {
             let p = PathBuf::from(&path);
             if let Err(e) = cache::store_animation_frames(
                 &self.memory.slice()[animation_start..],
@@ -105,12 +216,111 @@ impl ImageRequestBuilder {
         }
     }
 
+    /// Like `push`, but the animation's frames are sent later, with `RequestSend::AnimationFrames`
+    /// over the same connection, once the daemon answered this request. That way, the daemon
+    /// displays the image without waiting for every frame to be compressed.
+    ///
+    /// Either every image in a request has a streamed animation, or none of them does. Streamed
+    /// animations are not stored in the cache: the client must do it once it compressed them
+    #[inline]
+    pub fn push_streamed(
+        &mut self,
+        img: ImgSend,
+        outputs: &[String],
+        animation: StreamedAnimation,
+    ) {
+        self.push_img(&img, outputs);
+        self.push_byte(
+            (animation.clock as u8 + 1)
+                | ((animation.interpolate as u8) << 2)
+                | 0b1000
+                | ((animation.warp as u8) << 4),
+        );
+        // 0 means forever
+        self.extend(&animation.loop_count.unwrap_or(0).to_ne_bytes());
+    }
+
+    /// Makes the transition to the image pushed last start from `source`, instead of from what its
+    /// outputs currently display. `source` must have the same dimensions as that image.
+    ///
+    /// Either every image in a request has a source, or none of them does
+    #[inline]
+    pub fn push_source(&mut self, source: &ImgSend) {
+        self.memory.slice_mut()[0] |= 0b10000;
+        self.serialize_img(source);
+    }
+
+    /// Makes the daemon composite the image pushed last, and every frame of its animation, over
+    /// `base` with `mode`, instead of displaying them as they are. `base` must have the same
+    /// dimensions as that image. Must be called after `push_source`, if both are used.
+    ///
+    /// Either every image in a request is blended, or none of them is
+    #[inline]
+    pub fn push_blend(&mut self, mode: BlendMode, base: &ImgSend) {
+        self.memory.slice_mut()[0] |= 0b100000;
+        self.push_byte(mode as u8);
+        self.serialize_img(base);
+    }
+
+    /// Makes the wallpapers of the image pushed last only cover `region` of their outputs, leaving
+    /// the rest uncovered. The image still has the dimensions of the whole output: the daemon only
+    /// displays the part inside `region`. Must be called after `push_source` and `push_blend`, if
+    /// they are used.
+    ///
+    /// Either every image in a request has a region, or none of them does
+    #[inline]
+    pub fn push_region(&mut self, region: &Region) {
+        self.memory.slice_mut()[0] |= 0b1000000;
+        self.extend(&region.serialize());
+    }
+
+    fn push_img(&mut self, img: &ImgSend, outputs: &[String]) {
+        self.img_count += 1;
+        self.serialize_img(img);
+
+        let ImgSend { path, .. } = img;
+        self.push_byte(outputs.len() as u8);
+        for output in outputs.iter() {
+            self.serialize_bytes(output.as_bytes());
+        }
+
+        // cache the request
+        if self.cache_outputs {
+            for output in outputs.iter() {
+                if let Err(e) = super::cache::store(output, path) {
+                    eprintln!("ERROR: failed to store cache: {e}");
+                }
+            }
+        }
+    }
+
     #[inline]
     pub fn build(mut self) -> Mmap {
         self.memory.slice_mut()[self.img_count_index] = self.img_count;
+        if let Some(args) = self.replay.take() {
+            self.memory.slice_mut()[0] |= 0b10000000;
+            self.push_byte(args.len() as u8);
+            for arg in args.iter() {
+                self.serialize_bytes(arg.as_bytes());
+            }
+        }
         self.memory
     }
 
+    fn serialize_img(&mut self, img: &ImgSend) {
+        let ImgSend {
+            path,
+            img,
+            dim: dims,
+            format,
+        } = img;
+        self.serialize_bytes(path.as_bytes());
+        self.serialize_bytes(img);
+        self.extend(&dims.0.to_ne_bytes());
+        self.extend(&dims.1.to_ne_bytes());
+        self.push_byte(*format as u8);
+    }
+
     fn serialize_bytes(&mut self, bytes: &[u8]) {
         self.extend(&(bytes.len() as u32).to_ne_bytes());
         self.extend(bytes);
@@ -123,6 +333,63 @@ pub enum RequestSend {
     Clear(Mmap),
     Img(Mmap),
     Kill,
+    /// Like `Query`, but the daemon keeps the connection open, sending a new `Answer::Info`
+    /// whenever something changes
+    Subscribe,
+    /// Sets the transition used by image requests that do not specify one. Create the `Mmap`
+    /// with `Transition::create_request`
+    SetDefaultTransition(Mmap),
+    /// Asks for the images stored in the cache
+    CacheList,
+    /// Clears the outputs and then kills the daemon. Create the `Mmap` with
+    /// `ClearSend::create_request`
+    ClearAndKill(Mmap),
+    /// Makes the daemon renegotiate its wl_shm format and recreate all of its outputs. The daemon
+    /// only answers once it is done
+    Reset,
+    /// Like `Img`, but the daemon only keeps the images, without displaying them, until
+    /// `ShowPreloaded`. Build the `Mmap` with an `ImageRequestBuilder` (whose transition is
+    /// ignored), using `ImageRequestBuilder::without_output_cache`
+    Preload(Mmap),
+    /// Displays the images preloaded for some outputs. Create the `Mmap` with
+    /// `ShowPreloadedSend::create_request`. The daemon answers with the `CacheList` of what it
+    /// displayed, for the client to store in the cache
+    ShowPreloaded(Mmap),
+    /// Sets how the daemon displays an image on outputs that show up later, or stops it from doing
+    /// so. Create the `Mmap` with `StickySend::create_request`
+    SetSticky(Mmap),
+    /// Captures what the compositor displays on some outputs and sets it as their wallpaper.
+    /// Create the `Mmap` with `FreezeSend::create_request`. The daemon only answers once every
+    /// capture is done
+    Freeze(Mmap),
+    /// Several requests, that the daemon handles one after the other before doing anything else,
+    /// so that they take effect together. Create the `Mmap` with `BatchSend::create_request`. The
+    /// daemon answers with an `Answer::Batch`, holding the answer to each request. The batch is
+    /// not atomic: the requests are applied in order, and one failing does not undo the ones
+    /// before it, nor stop the ones after it
+    Batch(Mmap),
+    /// Asks for the contents of an output's buffer. Create the `Mmap` with
+    /// `GetBufferSend::create_request`. The daemon answers with an `Answer::Buffer`
+    GetBuffer(Mmap),
+    /// Frames of an animation pushed with `ImageRequestBuilder::push_streamed`. Only valid on the
+    /// connection the image request was sent through, after the daemon answered it. Create the
+    /// `Mmap` with `AnimationFramesSend::create_request`. The daemon does not answer these
+    AnimationFrames(Mmap),
+    /// Sets how the daemon displays an image whenever a workspace becomes active, or stops it from
+    /// doing so. Create the `Mmap` with `WorkspaceSend::create_request`. The daemon answers with an
+    /// error if the compositor does not support `ext_workspace_v1`
+    SetWorkspace(Mmap),
+    /// Temporarily plays the animations of some outputs at exactly the given fps, instead of at
+    /// their own rate capped by `swww-daemon --max-fps`. A new boost replaces the previous one of
+    /// each output. Create the `Mmap` with
+    /// `BoostFpsSend::create_request`
+    BoostFps(Mmap),
+    /// Sets (or disables) the schedule the daemon dims the wallpapers by at night. Create the
+    /// `Mmap` with `DimScheduleSend::create_request`
+    SetDimSchedule(Mmap),
+    /// Asks for the globals the daemon bound and the pixel format it negotiated, for bug reports.
+    /// The daemon answers with an `Answer::Diagnostics`
+    Diagnostics,
 }
 
 pub enum RequestRecv {
@@ -131,26 +398,70 @@ pub enum RequestRecv {
     Clear(ClearReq),
     Img(ImageReq),
     Kill,
+    Subscribe,
+    SetDefaultTransition(Transition),
+    CacheList,
+    ClearAndKill(ClearReq),
+    Reset,
+    Preload(ImageReq),
+    ShowPreloaded(ShowPreloadedReq),
+    SetSticky(StickyReq),
+    Freeze(FreezeReq),
+    Batch(Box<[RequestRecv]>),
+    GetBuffer(GetBufferReq),
+    AnimationFrames(AnimationFramesReq),
+    SetWorkspace(WorkspaceReq),
+    BoostFps(BoostFpsReq),
+    SetDimSchedule(DimScheduleReq),
+    Diagnostics,
 }
 
 impl RequestSend {
-    pub fn send(&self, stream: &OwnedFd) -> Result<(), String> {
-        let mut socket_msg = [0u8; 16];
-        socket_msg[0..8].copy_from_slice(&match self {
-            Self::Ping => 0u64.to_ne_bytes(),
-            Self::Query => 1u64.to_ne_bytes(),
-            Self::Clear(_) => 2u64.to_ne_bytes(),
-            Self::Img(_) => 3u64.to_ne_bytes(),
-            Self::Kill => 4u64.to_ne_bytes(),
-        });
+    fn code(&self) -> u8 {
+        match self {
+            Self::Ping => 0,
+            Self::Query => 1,
+            Self::Clear(_) => 2,
+            Self::Img(_) => 3,
+            Self::Kill => 4,
+            Self::Subscribe => 5,
+            Self::SetDefaultTransition(_) => 6,
+            Self::CacheList => 7,
+            Self::ClearAndKill(_) => 8,
+            Self::Reset => 9,
+            Self::Preload(_) => 10,
+            Self::ShowPreloaded(_) => 11,
+            Self::SetSticky(_) => 12,
+            Self::Freeze(_) => 13,
+            Self::Batch(_) => 14,
+            Self::GetBuffer(_) => 15,
+            Self::AnimationFrames(_) => 16,
+            Self::SetWorkspace(_) => 17,
+            Self::BoostFps(_) => 18,
+            Self::SetDimSchedule(_) => 19,
+            Self::Diagnostics => 20,
+        }
+    }
 
-        let mmap = match self {
-            Self::Clear(clear) => Some(clear),
-            Self::Img(img) => Some(img),
+    fn mmap(&self) -> Option<&Mmap> {
+        match self {
+            Self::Clear(clear) | Self::ClearAndKill(clear) => Some(clear),
+            Self::Img(img) | Self::Preload(img) | Self::ShowPreloaded(img) => Some(img),
+            Self::SetSticky(sticky) => Some(sticky),
+            Self::Freeze(freeze) => Some(freeze),
+            Self::SetDefaultTransition(transition) => Some(transition),
+            Self::Batch(batch) => Some(batch),
+            Self::GetBuffer(get_buffer) => Some(get_buffer),
+            Self::AnimationFrames(frames) => Some(frames),
+            Self::SetWorkspace(workspace) => Some(workspace),
+            Self::BoostFps(boost) => Some(boost),
+            Self::SetDimSchedule(schedule) => Some(schedule),
             _ => None,
-        };
+        }
+    }
 
-        match send_socket_msg(stream, &mut socket_msg, mmap) {
+    pub fn send(&self, stream: &OwnedFd) -> Result<(), String> {
+        match send_socket_msg(stream, self.code(), self.mmap()) {
             Ok(true) => (),
             Ok(false) => return Err("failed to send full length of message in socket!".to_string()),
             Err(e) => return Err(format!("failed to write serialized request: {e}")),
@@ -167,71 +478,241 @@ impl RequestRecv {
         let ret = match socket_msg.code {
             0 => Self::Ping,
             1 => Self::Query,
-            2 => {
+            2 => Self::Clear(ClearReq::deserialize(&socket_msg.shm.unwrap())),
+            3 => Self::Img(receive_image_request(socket_msg.shm.unwrap())),
+            5 => Self::Subscribe,
+            6 => {
                 let mmap = socket_msg.shm.unwrap();
-                let bytes = mmap.slice();
-                let len = bytes[0] as usize;
-                let mut outputs = Vec::with_capacity(len);
-                let mut i = 1;
-                for _ in 0..len {
-                    let output = MmappedStr::new(&mmap, &bytes[i..]);
-                    i += 4 + output.str().len();
-                    outputs.push(output);
-                }
-                let color = [bytes[i], bytes[i + 1], bytes[i + 2]];
-                Self::Clear(ClearReq {
-                    color,
-                    outputs: outputs.into(),
-                })
+                Self::SetDefaultTransition(Transition::deserialize(mmap.slice()))
             }
-            3 => {
-                let mmap = socket_msg.shm.unwrap();
-                let bytes = mmap.slice();
-                let transition = Transition::deserialize(&bytes[0..]);
-                let len = bytes[51] as usize;
+            7 => Self::CacheList,
+            8 => Self::ClearAndKill(ClearReq::deserialize(&socket_msg.shm.unwrap())),
+            9 => Self::Reset,
+            10 => Self::Preload(receive_image_request(socket_msg.shm.unwrap())),
+            11 => Self::ShowPreloaded(ShowPreloadedReq::deserialize(&socket_msg.shm.unwrap())),
+            12 => Self::SetSticky(StickyReq::deserialize(&socket_msg.shm.unwrap())),
+            13 => Self::Freeze(FreezeReq::deserialize(&socket_msg.shm.unwrap())),
+            14 => Self::Batch(
+                receive_batch(&socket_msg.shm.unwrap())
+                    .into_iter()
+                    .map(Self::receive)
+                    .collect(),
+            ),
+            15 => Self::GetBuffer(GetBufferReq::deserialize(&socket_msg.shm.unwrap())),
+            16 => Self::AnimationFrames(AnimationFramesReq::deserialize(&socket_msg.shm.unwrap())),
+            17 => Self::SetWorkspace(WorkspaceReq::deserialize(&socket_msg.shm.unwrap())),
+            18 => Self::BoostFps(BoostFpsReq::deserialize(&socket_msg.shm.unwrap())),
+            19 => Self::SetDimSchedule(DimScheduleReq::deserialize(&socket_msg.shm.unwrap())),
+            20 => Self::Diagnostics,
+            _ => Self::Kill,
+        };
+        ret
+    }
+}
 
-                let mut imgs = Vec::with_capacity(len);
-                let mut outputs = Vec::with_capacity(len);
-                let mut animations = Vec::with_capacity(len);
+/// Requests for the daemon to handle together. See `RequestSend::Batch`
+pub struct BatchSend {
+    pub requests: Box<[RequestSend]>,
+}
 
-                let mut i = 52;
-                for _ in 0..len {
-                    let (img, offset) = ImgReq::deserialize(&mmap, &bytes[i..]);
-                    i += offset;
-                    imgs.push(img);
-
-                    let n_outputs = bytes[i] as usize;
-                    i += 1;
-                    let mut out = Vec::with_capacity(n_outputs);
-                    for _ in 0..n_outputs {
-                        let output = MmappedStr::new(&mmap, &bytes[i..]);
-                        i += 4 + output.str().len();
-                        out.push(output);
-                    }
-                    outputs.push(out.into());
-
-                    if bytes[i] == 1 {
-                        let (animation, offset) = Animation::deserialize(&mmap, &bytes[i + 1..]);
-                        i += offset;
-                        animations.push(animation);
-                    }
-                    i += 1;
+impl BatchSend {
+    /// Fails if any of the requests cannot be part of a batch: other batches, and the requests
+    /// that kill the daemon or that it only answers later (`Subscribe`, `Reset` and `Freeze`)
+    pub fn create_request(&self) -> Result<Mmap, String> {
+        for request in self.requests.iter() {
+            match request {
+                RequestSend::Batch(_) => return Err("batches cannot be nested".to_string()),
+                RequestSend::Kill | RequestSend::ClearAndKill(_) => {
+                    return Err("a batch cannot kill the daemon".to_string())
+                }
+                RequestSend::Subscribe | RequestSend::Reset | RequestSend::Freeze(_) => {
+                    return Err(
+                        "subscriptions, resets and freezes cannot be part of a batch".to_string(),
+                    )
+                }
+                RequestSend::AnimationFrames(_) => {
+                    return Err(
+                        "animation frames must follow the image request that streams them"
+                            .to_string(),
+                    )
                 }
+                _ => (),
+            }
+        }
+        let msgs: Vec<_> = self
+            .requests
+            .iter()
+            .map(|request| (request.code(), request.mmap().map(Mmap::slice)))
+            .collect();
+        Ok(serialize_batch(&msgs))
+    }
+}
 
-                Self::Img(ImageReq {
-                    transition,
-                    imgs: imgs.into(),
-                    outputs: outputs.into(),
-                    animations: if animations.is_empty() {
-                        None
-                    } else {
-                        Some(animations.into())
-                    },
-                })
+/// Serializes messages (requests or answers) into a single `Mmap`: their count, and then the
+/// code, length and contents of each one
+fn serialize_batch(msgs: &[(u8, Option<&[u8]>)]) -> Mmap {
+    // 4 - message count
+    // 5 + len - code, len and contents of each message
+    let len = 4 + msgs
+        .iter()
+        .map(|(_, bytes)| 5 + bytes.map_or(0, <[u8]>::len))
+        .sum::<usize>();
+    let mut mmap = Mmap::create(len);
+    let buf = mmap.slice_mut();
+    buf[0..4].copy_from_slice(&(msgs.len() as u32).to_ne_bytes());
+    let mut i = 4;
+    for (code, bytes) in msgs {
+        let bytes = bytes.unwrap_or_default();
+        buf[i] = *code;
+        buf[i + 1..i + 5].copy_from_slice(&(bytes.len() as u32).to_ne_bytes());
+        buf[i + 5..i + 5 + bytes.len()].copy_from_slice(bytes);
+        i += 5 + bytes.len();
+    }
+    mmap
+}
+
+/// Splits the messages serialized with `serialize_batch`. Each one is copied into an `Mmap` of its
+/// own, since that is what their deserialization expects
+fn receive_batch(mmap: &Mmap) -> Vec<SocketMsg> {
+    let bytes = mmap.slice();
+    let len = u32::from_ne_bytes(bytes[0..4].try_into().unwrap()) as usize;
+    let mut msgs = Vec::with_capacity(len);
+    let mut i = 4;
+    for _ in 0..len {
+        let code = bytes[i];
+        let size = u32::from_ne_bytes(bytes[i + 1..i + 5].try_into().unwrap()) as usize;
+        i += 5;
+        let shm = (size > 0).then(|| {
+            let mut shm = Mmap::create(size);
+            shm.slice_mut().copy_from_slice(&bytes[i..i + size]);
+            shm
+        });
+        i += size;
+        msgs.push(SocketMsg { code, shm });
+    }
+    msgs
+}
+
+/// Deserializes the requests built with an `ImageRequestBuilder`
+fn receive_image_request(mmap: Mmap) -> ImageReq {
+    let bytes = mmap.slice();
+    let force = bytes[0] & 0b10 != 0;
+    let debug = bytes[0] & 0b100 != 0;
+    let sync = bytes[0] & 0b1000 != 0;
+    let has_sources = bytes[0] & 0b10000 != 0;
+    let has_blends = bytes[0] & 0b100000 != 0;
+    let has_regions = bytes[0] & 0b1000000 != 0;
+    let has_replay = bytes[0] & 0b10000000 != 0;
+    let (transition, mut i) = if bytes[0] & 1 == 0 {
+        (None, 1)
+    } else {
+        let transition = Transition::deserialize(&bytes[1..]);
+        (Some(transition), 1 + Transition::SERIALIZED_SIZE)
+    };
+    let adjustment = ColorAdjustment::deserialize(&bytes[i..]);
+    i += ColorAdjustment::SERIALIZED_SIZE;
+    let len = bytes[i] as usize;
+    i += 1;
+
+    let mut imgs = Vec::with_capacity(len);
+    let mut outputs = Vec::with_capacity(len);
+    let mut animations = Vec::with_capacity(len);
+    let mut streamed = Vec::new();
+    let mut sources = Vec::new();
+    let mut blends = Vec::new();
+    let mut regions = Vec::new();
+
+    for _ in 0..len {
+        let (img, offset) = ImgReq::deserialize(&mmap, &bytes[i..]);
+        i += offset;
+        imgs.push(img);
+
+        let n_outputs = bytes[i] as usize;
+        i += 1;
+        let mut out = Vec::with_capacity(n_outputs);
+        for _ in 0..n_outputs {
+            let output = MmappedStr::new(&mmap, &bytes[i..]);
+            i += 4 + output.str().len();
+            out.push(output);
+        }
+        outputs.push(out.into());
+
+        if bytes[i] & 0b1000 != 0 {
+            let loop_count = u32::from_ne_bytes(bytes[i + 1..i + 5].try_into().unwrap());
+            streamed.push(StreamedAnimation {
+                clock: if bytes[i] & 0b11 == 2 {
+                    AnimationClock::Independent
+                } else {
+                    AnimationClock::Locked
+                },
+                interpolate: bytes[i] & 0b100 != 0,
+                warp: bytes[i] & 0b10000 != 0,
+                loop_count: (loop_count != 0).then_some(loop_count),
+            });
+            i += 4;
+        } else if bytes[i] != 0 {
+            let (mut animation, offset) = Animation::deserialize(&mmap, &bytes[i + 1..]);
+            if bytes[i] & 0b11 == 2 {
+                animation.clock = AnimationClock::Independent;
             }
-            _ => Self::Kill,
-        };
-        ret
+            animation.interpolate = bytes[i] & 0b100 != 0;
+            animation.warp = bytes[i] & 0b10000 != 0;
+            i += offset;
+            animations.push(animation);
+        }
+        i += 1;
+
+        if has_sources {
+            let (source, offset) = ImgReq::deserialize(&mmap, &bytes[i..]);
+            i += offset;
+            sources.push(source);
+        }
+        if has_blends {
+            let mode = BlendMode::from_u8(bytes[i]);
+            let (base, offset) = ImgReq::deserialize(&mmap, &bytes[i + 1..]);
+            i += 1 + offset;
+            blends.push(Blend { mode, base });
+        }
+        if has_regions {
+            regions.push(Region::deserialize(&bytes[i..]));
+            i += Region::SERIALIZED_SIZE;
+        }
+    }
+
+    let replay = has_replay.then(|| {
+        let len = bytes[i] as usize;
+        i += 1;
+        let mut args = Vec::with_capacity(len);
+        for _ in 0..len {
+            let arg = MmappedStr::new(&mmap, &bytes[i..]);
+            i += 4 + arg.str().len();
+            args.push(arg);
+        }
+        args.into()
+    });
+
+    ImageReq {
+        transition,
+        force,
+        debug,
+        sync,
+        adjustment,
+        imgs: imgs.into(),
+        sources: has_sources.then(|| sources.into()),
+        blends: has_blends.then(|| blends.into()),
+        regions: has_regions.then(|| regions.into()),
+        outputs: outputs.into(),
+        animations: if animations.is_empty() {
+            None
+        } else {
+            Some(animations.into())
+        },
+        streamed: if streamed.is_empty() {
+            None
+        } else {
+            Some(streamed.into())
+        },
+        replay,
     }
 }
 
@@ -239,21 +720,45 @@ pub enum Answer {
     Ok,
     Ping(bool),
     Info(Box<[BgInfo]>),
-    Err(String),
+    /// The kind of error, and a human readable message
+    Err(ErrorKind, String),
+    CacheList(Box<[CacheEntry]>),
+    /// Only sent to subscribers, while transitions are running
+    Progress(Box<[TransitionProgress]>),
+    /// The answer to each request of a `RequestSend::Batch`, in the same order
+    Batch(Box<[Answer]>),
+    /// What an output is displaying, answering `RequestSend::GetBuffer`
+    Buffer(WallpaperBuffer),
+    /// What the daemon negotiated with the compositor, answering `RequestSend::Diagnostics`
+    Diagnostics(Diagnostics),
 }
 
 impl Answer {
+    fn code(&self) -> u8 {
+        match self {
+            Self::Ok => 0,
+            Self::Ping(true) => 1,
+            Self::Ping(false) => 2,
+            Self::Info(_) => 3,
+            Self::Err(..) => 4,
+            Self::CacheList(_) => 5,
+            Self::Progress(_) => 6,
+            Self::Batch(_) => 7,
+            Self::Buffer(_) => 8,
+            Self::Diagnostics(_) => 9,
+        }
+    }
+
     pub fn send(&self, stream: &OwnedFd) -> Result<(), String> {
-        let mut socket_msg = [0u8; 16];
-        socket_msg[0..8].copy_from_slice(&match self {
-            Self::Ok => 0u64.to_ne_bytes(),
-            Self::Ping(true) => 1u64.to_ne_bytes(),
-            Self::Ping(false) => 2u64.to_ne_bytes(),
-            Self::Info(_) => 3u64.to_ne_bytes(),
-            Self::Err(_) => 4u64.to_ne_bytes(),
-        });
+        match send_socket_msg(stream, self.code(), self.serialize().as_ref()) {
+            Ok(true) => Ok(()),
+            Ok(false) => Err("failed to send full length of message in socket!".to_string()),
+            Err(e) => Err(format!("failed to write serialized request: {e}")),
+        }
+    }
 
-        let mmap = match self {
+    fn serialize(&self) -> Option<Mmap> {
+        match self {
             Self::Info(infos) => {
                 let len = 1 + infos.iter().map(|i| i.serialized_size()).sum::<usize>();
                 let mut mmap = Mmap::create(len);
@@ -268,21 +773,63 @@ impl Answer {
 
                 Some(mmap)
             }
-            Self::Err(s) => {
-                let len = 4 + s.len();
+            Self::Err(kind, s) => {
+                let len = 5 + s.len();
                 let mut mmap = Mmap::create(len);
                 let bytes = mmap.slice_mut();
-                bytes[0..4].copy_from_slice(&(s.as_bytes().len() as u32).to_ne_bytes());
-                bytes[4..len].copy_from_slice(s.as_bytes());
+                bytes[0] = *kind as u8;
+                bytes[1..5].copy_from_slice(&(s.as_bytes().len() as u32).to_ne_bytes());
+                bytes[5..len].copy_from_slice(s.as_bytes());
                 Some(mmap)
             }
-            _ => None,
-        };
+            Self::CacheList(entries) => {
+                let len = 4 + entries.iter().map(|e| e.serialized_size()).sum::<usize>();
+                let mut mmap = Mmap::create(len);
+                let bytes = mmap.slice_mut();
 
-        match send_socket_msg(stream, &mut socket_msg, mmap.as_ref()) {
-            Ok(true) => Ok(()),
-            Ok(false) => Err("failed to send full length of message in socket!".to_string()),
-            Err(e) => Err(format!("failed to write serialized request: {e}")),
+                bytes[0..4].copy_from_slice(&(entries.len() as u32).to_ne_bytes());
+                let mut i = 4;
+
+                for entry in entries.iter() {
+                    i += entry.serialize(&mut bytes[i..]);
+                }
+
+                Some(mmap)
+            }
+            Self::Progress(progress) => {
+                let len = 4 + progress.iter().map(|p| p.serialized_size()).sum::<usize>();
+                let mut mmap = Mmap::create(len);
+                let bytes = mmap.slice_mut();
+
+                bytes[0..4].copy_from_slice(&(progress.len() as u32).to_ne_bytes());
+                let mut i = 4;
+
+                for p in progress.iter() {
+                    i += p.serialize(&mut bytes[i..]);
+                }
+
+                Some(mmap)
+            }
+            Self::Batch(answers) => {
+                let serialized: Vec<_> = answers.iter().map(Self::serialize).collect();
+                let msgs: Vec<_> = answers
+                    .iter()
+                    .zip(serialized.iter())
+                    .map(|(answer, mmap)| (answer.code(), mmap.as_ref().map(Mmap::slice)))
+                    .collect();
+                Some(serialize_batch(&msgs))
+            }
+            Self::Buffer(buffer) => {
+                let mut mmap = Mmap::create(buffer.serialized_size());
+                buffer.serialize(mmap.slice_mut());
+                Some(mmap)
+            }
+            Self::Diagnostics(diagnostics) => {
+                let mut mmap = Mmap::create(diagnostics.serialized_size());
+                diagnostics.serialize(mmap.slice_mut());
+                Some(mmap)
+            }
+            _ => None,
         }
     }
 
@@ -311,13 +858,357 @@ impl Answer {
             4 => {
                 let mmap = socket_msg.shm.unwrap();
                 let bytes = mmap.slice();
-                let size = u32::from_ne_bytes(bytes[0..4].try_into().unwrap()) as usize;
-                let s = std::str::from_utf8(&bytes[4..4 + size])
+                let kind = ErrorKind::from_u8(bytes[0]);
+                let size = u32::from_ne_bytes(bytes[1..5].try_into().unwrap()) as usize;
+                let s = std::str::from_utf8(&bytes[5..5 + size])
                     .expect("received a non utf8 string from socket")
                     .to_string();
-                Self::Err(s)
+                Self::Err(kind, s)
+            }
+            5 => {
+                let mmap = socket_msg.shm.unwrap();
+                let bytes = mmap.slice();
+                let len = u32::from_ne_bytes(bytes[0..4].try_into().unwrap()) as usize;
+                let mut entries = Vec::with_capacity(len);
+
+                let mut i = 4;
+                for _ in 0..len {
+                    let (entry, offset) = CacheEntry::deserialize(&bytes[i..]);
+                    i += offset;
+                    entries.push(entry);
+                }
+
+                Self::CacheList(entries.into())
+            }
+            6 => {
+                let mmap = socket_msg.shm.unwrap();
+                let bytes = mmap.slice();
+                let len = u32::from_ne_bytes(bytes[0..4].try_into().unwrap()) as usize;
+                let mut progress = Vec::with_capacity(len);
+
+                let mut i = 4;
+                for _ in 0..len {
+                    let (p, offset) = TransitionProgress::deserialize(&bytes[i..]);
+                    i += offset;
+                    progress.push(p);
+                }
+
+                Self::Progress(progress.into())
+            }
+            7 => Self::Batch(
+                receive_batch(&socket_msg.shm.unwrap())
+                    .into_iter()
+                    .map(Self::receive)
+                    .collect(),
+            ),
+            8 => {
+                let mmap = socket_msg.shm.unwrap();
+                Self::Buffer(WallpaperBuffer::deserialize(mmap.slice()))
+            }
+            9 => {
+                let mmap = socket_msg.shm.unwrap();
+                Self::Diagnostics(Diagnostics::deserialize(mmap.slice()))
             }
             _ => panic!("Received malformed answer from daemon"),
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+
+    #[test]
+    fn image_request_flags_roundtrip() {
+        let mut builder = ImageRequestBuilder::with_default_transition()
+            .without_output_cache()
+            .force()
+            .debug()
+            .sync();
+        builder.push(
+            ImgSend {
+                path: "0x000000".to_string(),
+                img: Box::new([0; 4 * 3]),
+                dim: (2, 2),
+                format: PixelFormat::Rgb,
+            },
+            &["DP-1".to_string()],
+            None,
+        );
+        let req = receive_image_request(builder.build());
+        assert!(req.transition.is_none());
+        assert!(req.force);
+        assert!(req.debug);
+        assert!(req.sync);
+        assert_eq!(req.imgs.len(), 1);
+        assert_eq!(req.outputs[0][0].str(), "DP-1");
+
+        let mut builder = ImageRequestBuilder::with_default_transition().without_output_cache();
+        builder.push(
+            ImgSend {
+                path: "0x000000".to_string(),
+                img: Box::new([0; 4 * 3]),
+                dim: (2, 2),
+                format: PixelFormat::Rgb,
+            },
+            &[],
+            None,
+        );
+        let req = receive_image_request(builder.build());
+        assert!(!req.force);
+        assert!(!req.debug);
+        assert!(!req.sync);
+    }
+
+    #[test]
+    fn image_sources_roundtrip() {
+        let img = |path: &str, byte| ImgSend {
+            path: path.to_string(),
+            img: Box::new([byte; 4 * 3]),
+            dim: (2, 2),
+            format: PixelFormat::Rgb,
+        };
+        let mut builder = ImageRequestBuilder::with_default_transition().without_output_cache();
+        for (i, output) in ["DP-1", "DP-2"].into_iter().enumerate() {
+            builder.push(img("b.png", i as u8), &[output.to_string()], None);
+            builder.push_source(&img("a.png", 10 + i as u8));
+        }
+        let req = receive_image_request(builder.build());
+        let sources = req.sources.unwrap();
+        assert_eq!(req.imgs.len(), 2);
+        assert_eq!(sources.len(), 2);
+        assert_eq!(req.imgs[1].img.bytes(), [1; 4 * 3]);
+        assert_eq!(req.outputs[1][0].str(), "DP-2");
+        assert_eq!(sources[1].path.str(), "a.png");
+        assert_eq!(sources[1].dim, (2, 2));
+        assert_eq!(sources[1].img.bytes(), [11; 4 * 3]);
+        assert!(req.blends.is_none());
+    }
+
+    #[test]
+    fn image_blends_roundtrip() {
+        let img = |path: &str, byte| ImgSend {
+            path: path.to_string(),
+            img: Box::new([byte; 4 * 3]),
+            dim: (2, 2),
+            format: PixelFormat::Rgb,
+        };
+        let mut builder = ImageRequestBuilder::with_default_transition().without_output_cache();
+        for (i, mode) in [BlendMode::Screen, BlendMode::Overlay]
+            .into_iter()
+            .enumerate()
+        {
+            builder.push(img("rain.gif", i as u8), &["DP-1".to_string()], None);
+            builder.push_source(&img("a.png", 10 + i as u8));
+            builder.push_blend(mode, &img("base.png", 20 + i as u8));
+        }
+        let req = receive_image_request(builder.build());
+        let (sources, blends) = (req.sources.unwrap(), req.blends.unwrap());
+        assert_eq!(blends.len(), 2);
+        assert_eq!(sources[1].img.bytes(), [11; 4 * 3]);
+        assert_eq!(blends[0].mode, BlendMode::Screen);
+        assert_eq!(blends[1].mode, BlendMode::Overlay);
+        assert_eq!(blends[1].base.path.str(), "base.png");
+        assert_eq!(blends[1].base.img.bytes(), [21; 4 * 3]);
+    }
+
+    #[test]
+    fn image_regions_roundtrip() {
+        let img = |byte| ImgSend {
+            path: "a.png".to_string(),
+            img: Box::new([byte; 4 * 3]),
+            dim: (2, 2),
+            format: PixelFormat::Rgb,
+        };
+        let region = |x| Region {
+            x,
+            y: 30,
+            width: 1000,
+            height: 500,
+        };
+        let mut builder = ImageRequestBuilder::with_default_transition().without_output_cache();
+        for (i, output) in ["DP-1", "DP-2"].into_iter().enumerate() {
+            builder.push(img(i as u8), &[output.to_string()], None);
+            builder.push_source(&img(10 + i as u8));
+            builder.push_region(&region(i as u32));
+        }
+        let req = receive_image_request(builder.build());
+        assert_eq!(*req.regions.unwrap(), [region(0), region(1)]);
+        assert_eq!(req.sources.unwrap()[1].img.bytes(), [11; 4 * 3]);
+        assert_eq!(req.outputs[1][0].str(), "DP-2");
+    }
+
+    #[test]
+    fn image_replays_roundtrip() {
+        let img = || ImgSend {
+            path: "a.png".to_string(),
+            img: Box::new([0; 4 * 3]),
+            dim: (2, 2),
+            format: PixelFormat::Rgb,
+        };
+        let mut builder = ImageRequestBuilder::with_default_transition().without_output_cache();
+        builder.push(img(), &["DP-1".to_string()], None);
+        assert!(receive_image_request(builder.build()).replay.is_none());
+
+        let args = vec!["--resize=fit".to_string(), "/a.png".to_string()];
+        let mut builder = ImageRequestBuilder::with_default_transition()
+            .without_output_cache()
+            .replay(args.clone());
+        builder.push(img(), &["DP-1".to_string()], None);
+        let req = receive_image_request(builder.build());
+        let replay: Vec<&str> = req.replay.iter().flatten().map(MmappedStr::str).collect();
+        assert_eq!(replay, args);
+        assert_eq!(req.outputs[0][0].str(), "DP-1");
+    }
+
+    #[test]
+    fn regions_must_fit_their_output() {
+        let region = |x, y, width, height| Region {
+            x,
+            y,
+            width,
+            height,
+        };
+        assert!(region(0, 0, 1920, 1080).fits((1920, 1080)));
+        assert!(region(0, 30, 1920, 1050).fits((1920, 1080)));
+        assert!(!region(0, 31, 1920, 1050).fits((1920, 1080)));
+        assert!(!region(10, 0, 0, 1080).fits((1920, 1080)));
+        assert!(!region(u32::MAX, 0, 1, 1).fits((1920, 1080)));
+    }
+
+    #[test]
+    fn blend_modes_only_brighten_or_keep_contrast() {
+        for (base, img) in [(0, 0), (40, 200), (128, 128), (200, 40), (255, 255)] {
+            let additive = BlendMode::Additive.channel(base, img);
+            let screen = BlendMode::Screen.channel(base, img);
+            assert!(additive >= base.max(img) && screen >= base.max(img));
+            assert!(screen <= additive);
+        }
+        assert_eq!(BlendMode::Additive.channel(200, 100), 255);
+        // black keeps the base as it is
+        for mode in [BlendMode::Additive, BlendMode::Screen] {
+            assert_eq!(mode.channel(77, 0), 77);
+        }
+        assert_eq!(BlendMode::Overlay.channel(0, 200), 0);
+        assert_eq!(BlendMode::Overlay.channel(255, 10), 255);
+        assert_eq!(BlendMode::Overlay.channel(100, 255), 200);
+    }
+
+    #[test]
+    fn streamed_animation_roundtrip() {
+        let animation = StreamedAnimation {
+            clock: AnimationClock::Independent,
+            interpolate: true,
+            warp: true,
+            loop_count: Some(3),
+        };
+        let mut builder = ImageRequestBuilder::with_default_transition().without_output_cache();
+        builder.push_streamed(
+            ImgSend {
+                path: "0x000000".to_string(),
+                img: Box::new([0; 4 * 3]),
+                dim: (2, 2),
+                format: PixelFormat::Rgb,
+            },
+            &["DP-1".to_string()],
+            animation,
+        );
+        let req = receive_image_request(builder.build());
+        assert!(req.animations.is_none());
+        assert_eq!(req.streamed.as_deref(), Some(&[animation][..]));
+        assert_eq!(req.outputs[0][0].str(), "DP-1");
+
+        let frame = crate::compression::Compressor::new()
+            .compress(&[0; 4 * 3], &[255; 4 * 3], PixelFormat::Rgb)
+            .unwrap();
+        let frames = [(frame, Duration::from_millis(40))];
+        let mmap = AnimationFramesSend {
+            img: 2,
+            frames: &frames,
+            last: true,
+        }
+        .create_request();
+        let req = AnimationFramesReq::deserialize(&mmap);
+        assert_eq!(req.img, 2);
+        assert!(req.last);
+        assert_eq!(req.frames.len(), 1);
+        assert_eq!(req.frames[0].1, Duration::from_millis(40));
+    }
+
+    #[test]
+    fn batch_roundtrip() {
+        let clear = ClearSend {
+            color: [1, 2, 3, 255],
+            outputs: Box::new(["DP-1".to_string()]),
+        };
+        let mut builder = ImageRequestBuilder::with_default_transition().without_output_cache();
+        builder.push(
+            ImgSend {
+                path: "0x000000".to_string(),
+                img: Box::new([0; 4 * 3]),
+                dim: (2, 2),
+                format: PixelFormat::Rgb,
+            },
+            &["HDMI-A-1".to_string()],
+            None,
+        );
+        let batch = BatchSend {
+            requests: Box::new([
+                RequestSend::Clear(clear.create_request()),
+                RequestSend::Img(builder.build()),
+                RequestSend::Query,
+            ]),
+        };
+        let msg = SocketMsg {
+            code: 14,
+            shm: Some(batch.create_request().unwrap()),
+        };
+        let RequestRecv::Batch(requests) = RequestRecv::receive(msg) else {
+            panic!("expected a batch");
+        };
+        assert_eq!(requests.len(), 3);
+        assert!(
+            matches!(&requests[0], RequestRecv::Clear(c) if c.color == [1, 2, 3, 255]
+            && c.outputs[0].str() == "DP-1")
+        );
+        assert!(
+            matches!(&requests[1], RequestRecv::Img(img) if img.imgs.len() == 1
+            && img.outputs[0][0].str() == "HDMI-A-1")
+        );
+        assert!(matches!(requests[2], RequestRecv::Query));
+
+        let answer = Answer::Batch(Box::new([
+            Answer::Ok,
+            Answer::Err(ErrorKind::Busy, "busy".to_string()),
+        ]));
+        let msg = SocketMsg {
+            code: answer.code(),
+            shm: answer.serialize(),
+        };
+        let Answer::Batch(answers) = Answer::receive(msg) else {
+            panic!("expected a batch");
+        };
+        assert!(matches!(answers[0], Answer::Ok));
+        assert!(matches!(&answers[1], Answer::Err(ErrorKind::Busy, msg) if msg == "busy"));
+    }
+
+    #[test]
+    fn batches_reject_unbatchable_requests() {
+        let nested = BatchSend {
+            requests: Box::new([RequestSend::Ping]),
+        };
+        for request in [
+            RequestSend::Batch(nested.create_request().unwrap()),
+            RequestSend::Kill,
+            RequestSend::Subscribe,
+            RequestSend::AnimationFrames(Mmap::create(1)),
+        ] {
+            let batch = BatchSend {
+                requests: Box::new([RequestSend::Ping, request]),
+            };
+            assert!(batch.create_request().is_err());
+        }
+    }
+}
@@ -1,4 +1,11 @@
-use std::{path::PathBuf, time::Duration};
+use std::{
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        OnceLock,
+    },
+    time::{Duration, Instant},
+};
 
 use rustix::{
     fd::OwnedFd,
@@ -7,12 +14,241 @@ use rustix::{
 
 use super::Mmap;
 
+/// A message received through the socket.
+///
+/// Every message is a 16 bytes header: the request/answer code (1 byte), `MAGIC` (3 bytes), the
+/// `PROTOCOL_VERSION` (little endian u32) and the payload length (little endian u64). Before we had
+/// a `PROTOCOL_VERSION`, the code was a little endian u64, so older headers have zeroes where we
+/// expect `MAGIC`.
+/// Payloads are never copied through the socket itself: they are written into shared memory, and
+/// the file descriptor is passed along with the header through `SCM_RIGHTS`. The receiving end
+/// then simply maps it (see `shm`).
 pub struct SocketMsg {
     pub(super) code: u8,
     pub(super) shm: Option<Mmap>,
 }
 
+/// How long clients wait for the daemon, in milliseconds, set with `set_client_timeout`. 0 means
+/// `DEFAULT_CLIENT_TIMEOUT`
+static CLIENT_TIMEOUT_MS: AtomicU64 = AtomicU64::new(0);
+
+#[cfg(debug_assertions)]
+const DEFAULT_CLIENT_TIMEOUT: Duration = Duration::from_secs(30); //Some operations take a while to respond in debug mode
+#[cfg(not(debug_assertions))]
+const DEFAULT_CLIENT_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Version of the socket protocol: the codes and layouts of requests and answers. Bump it whenever
+/// they change, so that a client and a daemon of different versions refuse to talk to each other
+/// instead of misreading what the other sends.
+///
+/// Headers without `MAGIC` are from before version 1, when the header was in the machine's own
+/// byte order. Version 1 is also the one that made it little endian, so that change is covered too
+pub const PROTOCOL_VERSION: u32 = 1;
+
+/// Identifies the headers that carry a `PROTOCOL_VERSION`
+const MAGIC: [u8; 3] = *b"sww";
+
+/// Code of the message the daemon answers clients of another `PROTOCOL_VERSION` with: the one of
+/// `Answer::Err`. Its payload is laid out like `Answer::Err`'s was before it had an `ErrorKind`
+/// (see `mismatch_payload`), so that clients from before we had a `PROTOCOL_VERSION` read it as an
+/// error too, instead of panicking on a code they do not know. It must never change, so that every
+/// client can read it
+const MISMATCH_CODE: u8 = 4;
+
+/// `recv_socket_msg`'s error when nothing arrived within the socket's read timeout
+const TIMED_OUT: &str = "timed out waiting for a message";
+
+/// Sets how long the sockets `connect_to_socket` (and `connect_to_socket_with_backoff`) return
+/// wait for each send and read, and how long `read_answer` waits for
+pub fn set_client_timeout(timeout: Duration) {
+    let millis = timeout.as_millis().clamp(1, u64::MAX as u128) as u64;
+    CLIENT_TIMEOUT_MS.store(millis, Ordering::Relaxed);
+}
+
+#[must_use]
+pub fn client_timeout() -> Duration {
+    match CLIENT_TIMEOUT_MS.load(Ordering::Relaxed) {
+        0 => DEFAULT_CLIENT_TIMEOUT,
+        millis => Duration::from_millis(millis),
+    }
+}
+
+/// How reads that would block are retried: at most `max_tries` times, sleeping `sleep` before
+/// each retry. Raising them makes reads more reliable on slow or loaded systems, at the cost of
+/// waiting longer before giving up on a message that is not coming
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ReadRetries {
+    pub max_tries: u32,
+    pub sleep: Duration,
+}
+
+impl Default for ReadRetries {
+    fn default() -> Self {
+        Self {
+            max_tries: 5,
+            sleep: Duration::from_millis(1),
+        }
+    }
+}
+
+impl ReadRetries {
+    /// The default retries, overridden by the `SWWW_READ_TRIES` and `SWWW_READ_SLEEP_MS`
+    /// environment variables. We only read them once, warning about invalid values, which leave
+    /// their default in place. Call it at startup, so that the warnings show up right away
+    pub fn from_env() -> Self {
+        static RETRIES: OnceLock<ReadRetries> = OnceLock::new();
+        *RETRIES.get_or_init(|| {
+            let (retries, warnings) = Self::parse(
+                std::env::var("SWWW_READ_TRIES").ok().as_deref(),
+                std::env::var("SWWW_READ_SLEEP_MS").ok().as_deref(),
+            );
+            for warning in warnings {
+                eprintln!("WARNING: {warning}");
+            }
+            retries
+        })
+    }
+
+    /// The retries the environment variables set, and the warnings about the invalid ones
+    fn parse(max_tries: Option<&str>, sleep_ms: Option<&str>) -> (Self, Vec<String>) {
+        let mut retries = Self::default();
+        let mut warnings = Vec::new();
+        if let Some(max_tries) = max_tries {
+            match max_tries.parse() {
+                Ok(max_tries) => retries.max_tries = max_tries,
+                Err(_) => warnings.push(format!(
+                    "invalid SWWW_READ_TRIES value: {max_tries}, using the default of {}",
+                    retries.max_tries
+                )),
+            }
+        }
+        if let Some(sleep_ms) = sleep_ms {
+            match sleep_ms.parse() {
+                Ok(sleep_ms) => retries.sleep = Duration::from_millis(sleep_ms),
+                Err(_) => warnings.push(format!(
+                    "invalid SWWW_READ_SLEEP_MS value: {sleep_ms}, using the default of {}",
+                    retries.sleep.as_millis()
+                )),
+            }
+        }
+        (retries, warnings)
+    }
+}
+
+/// Reads a message, retrying as `ReadRetries::from_env` says if it has not arrived yet
 pub fn read_socket(stream: &OwnedFd) -> Result<SocketMsg, String> {
+    read_socket_with_retries(stream, ReadRetries::from_env())
+}
+
+/// Like `read_socket`, with explicit `retries`
+pub fn read_socket_with_retries(
+    stream: &OwnedFd,
+    retries: ReadRetries,
+) -> Result<SocketMsg, String> {
+    recv_socket_msg(stream, retries)
+}
+
+/// Like `read_socket`, but gives up as soon as nothing arrives within `timeout`, instead of
+/// retrying
+pub fn read_socket_with_timeout(stream: &OwnedFd, timeout: Duration) -> Result<SocketMsg, String> {
+    net::sockopt::set_socket_timeout(stream, net::sockopt::Timeout::Recv, Some(timeout))
+        .map_err(|e| format!("failed to set read timeout for socket: {e}"))?;
+    let retries = ReadRetries {
+        max_tries: 0,
+        ..ReadRetries::default()
+    };
+    recv_socket_msg(stream, retries)
+}
+
+/// Reads the daemon's answer to a request, failing with a clear error if it does not arrive within
+/// `client_timeout`. Unlike `read_socket`, this never waits any longer than that
+pub fn read_answer(stream: &OwnedFd) -> Result<SocketMsg, String> {
+    read_answer_within(stream, client_timeout())
+}
+
+/// Like `read_answer`, waiting for `timeout` instead of `client_timeout`
+fn read_answer_within(stream: &OwnedFd, timeout: Duration) -> Result<SocketMsg, String> {
+    read_socket_with_timeout(stream, timeout).map_err(|e| {
+        if e == TIMED_OUT {
+            format!(
+                "the daemon did not answer within {:.1}s. It might be stuck: try restarting it, or \
+                 raise the timeout with --query-timeout",
+                timeout.as_secs_f32()
+            )
+        } else {
+            e
+        }
+    })
+}
+
+/// Reads a request for the daemon. Returns `None` if the client speaks another `PROTOCOL_VERSION`,
+/// in which case we already answered it with an error it can read
+pub fn read_request(stream: &OwnedFd) -> Result<Option<SocketMsg>, String> {
+    let (msg, protocol) = recv_raw_socket_msg(stream, ReadRetries::from_env())?;
+    if protocol == Some(PROTOCOL_VERSION) {
+        return Ok(Some(msg));
+    }
+    let client = match protocol {
+        Some(version) => format!("version {version}"),
+        None => "an older version".to_string(),
+    };
+    let e = format!(
+        "swww-daemon speaks version {PROTOCOL_VERSION} of the socket protocol, but the client speaks \
+         {client}. Make sure swww and swww-daemon are the same version, restarting the daemon after \
+         upgrading them"
+    );
+    let payload = mismatch_payload(&e);
+    let mut mmap = Mmap::create(payload.len());
+    mmap.slice_mut().copy_from_slice(&payload);
+    // the client might be gone already, and there is nobody else to tell
+    let _ = send_socket_msg(stream, MISMATCH_CODE, Some(&mmap));
+    Ok(None)
+}
+
+/// The error's length, as a u32 in the machine's own byte order, followed by it as utf8
+fn mismatch_payload(e: &str) -> Vec<u8> {
+    let mut payload = Vec::with_capacity(4 + e.len());
+    payload.extend_from_slice(&(e.len() as u32).to_ne_bytes());
+    payload.extend_from_slice(e.as_bytes());
+    payload
+}
+
+/// The error `mismatch_payload` holds, or `None` if it is malformed
+fn mismatch_error(payload: &[u8]) -> Option<String> {
+    let len = u32::from_ne_bytes(payload.get(0..4)?.try_into().unwrap()) as usize;
+    let e = payload.get(4..4 + len)?;
+    Some(String::from_utf8_lossy(e).into_owned())
+}
+
+/// Reads a message, retrying as `retries` says if the read would block. Fails if the other end
+/// speaks another `PROTOCOL_VERSION`
+fn recv_socket_msg(stream: &OwnedFd, retries: ReadRetries) -> Result<SocketMsg, String> {
+    let (msg, protocol) = recv_raw_socket_msg(stream, retries)?;
+    if protocol.is_some_and(|protocol| protocol != PROTOCOL_VERSION) && msg.code == MISMATCH_CODE {
+        if let Some(e) = msg.shm.as_ref().and_then(|shm| mismatch_error(shm.slice())) {
+            return Err(e);
+        }
+    }
+    match protocol {
+        Some(PROTOCOL_VERSION) => Ok(msg),
+        Some(version) => Err(format!(
+            "swww-daemon speaks version {version} of the socket protocol, but swww speaks version \
+             {PROTOCOL_VERSION}. Make sure they are the same version, restarting the daemon after \
+             upgrading them"
+        )),
+        None => Err(
+            "swww-daemon is older than swww, and speaks another version of the socket protocol. \
+             Restart the daemon after upgrading them"
+                .to_string(),
+        ),
+    }
+}
+
+/// Reads a message, along with the `PROTOCOL_VERSION` of its header (`None` if it has none)
+fn recv_raw_socket_msg(
+    stream: &OwnedFd,
+    retries: ReadRetries,
+) -> Result<(SocketMsg, Option<u32>), String> {
     let mut buf = [0u8; 16];
     let mut ancillary_buf = [0u8; rustix::cmsg_space!(ScmRights(1))];
 
@@ -22,20 +258,22 @@ pub fn read_socket(stream: &OwnedFd) -> Result<SocketMsg, String> {
     loop {
         let iov = rustix::io::IoSliceMut::new(&mut buf);
         match net::recvmsg(stream, &mut [iov], &mut control, RecvFlags::WAITALL) {
+            Ok(msg) if msg.bytes == 0 => return Err("socket was closed".to_string()),
             Ok(_) => break,
             Err(e) => {
-                if e.kind() == std::io::ErrorKind::WouldBlock && tries < 5 {
-                    std::thread::sleep(Duration::from_millis(1));
-                } else {
+                if e.kind() != std::io::ErrorKind::WouldBlock {
                     return Err(format!("failed to read serialized length: {e}"));
+                } else if tries < retries.max_tries {
+                    std::thread::sleep(retries.sleep);
+                } else {
+                    return Err(TIMED_OUT.to_string());
                 }
             }
         }
         tries += 1;
     }
 
-    let code = u64::from_ne_bytes(buf[0..8].try_into().unwrap()) as u8;
-    let len = u64::from_ne_bytes(buf[8..16].try_into().unwrap()) as usize;
+    let (code, protocol, len) = decode_header(&buf);
 
     let shm = if len == 0 {
         None
@@ -46,32 +284,58 @@ pub fn read_socket(stream: &OwnedFd) -> Result<SocketMsg, String> {
         };
         Some(Mmap::from_fd(shm_file, len))
     };
-    Ok(SocketMsg { code, shm })
+    Ok((SocketMsg { code, shm }, protocol))
+}
+
+fn encode_header(code: u8, len: usize) -> [u8; 16] {
+    let mut header = [0u8; 16];
+    header[0] = code;
+    header[1..4].copy_from_slice(&MAGIC);
+    header[4..8].copy_from_slice(&PROTOCOL_VERSION.to_le_bytes());
+    header[8..16].copy_from_slice(&(len as u64).to_le_bytes());
+    header
+}
+
+/// Returns the code, the protocol version (if the header has one) and the payload length
+fn decode_header(header: &[u8; 16]) -> (u8, Option<u32>, usize) {
+    let code = header[0];
+    let protocol = if header[1..4] == MAGIC {
+        Some(u32::from_le_bytes(header[4..8].try_into().unwrap()))
+    } else {
+        None
+    };
+    let len = u64::from_le_bytes(header[8..16].try_into().unwrap()) as usize;
+    (code, protocol, len)
 }
 
+/// Sends the 16 bytes header for `code`. If there is an `mmap`, its length is written into the
+/// header and its file descriptor is sent as ancillary data
 pub(super) fn send_socket_msg(
     stream: &OwnedFd,
-    socket_msg: &mut [u8; 16],
+    code: u8,
     mmap: Option<&Mmap>,
 ) -> rustix::io::Result<bool> {
     let mut ancillary_buf = [0u8; rustix::cmsg_space!(ScmRights(1))];
     let mut ancillary = net::SendAncillaryBuffer::new(&mut ancillary_buf);
 
+    let socket_msg = encode_header(code, mmap.map_or(0, Mmap::len));
     let msg_buf;
     if let Some(mmap) = mmap.as_ref() {
-        socket_msg[8..].copy_from_slice(&(mmap.len() as u64).to_ne_bytes());
         msg_buf = [mmap.fd()];
         let msg = net::SendAncillaryMessage::ScmRights(&msg_buf);
         ancillary.push(msg);
     }
 
     let iov = rustix::io::IoSlice::new(&socket_msg[..]);
-    net::sendmsg(stream, &[iov], &mut ancillary, net::SendFlags::empty())
+    // NOSIGNAL, so that writing to a closed socket is an error instead of killing us with SIGPIPE
+    net::sendmsg(stream, &[iov], &mut ancillary, net::SendFlags::NOSIGNAL)
         .map(|written| written == socket_msg.len())
 }
 
+/// Path of the daemon's socket. Each non-empty `namespace` gets a socket of its own, so that many
+/// independent daemons can run at the same time
 #[must_use]
-pub fn get_socket_path() -> PathBuf {
+pub fn get_socket_path(namespace: &str) -> PathBuf {
     let runtime_dir = if let Ok(dir) = std::env::var("XDG_RUNTIME_DIR") {
         dir
     } else {
@@ -88,6 +352,10 @@ pub fn get_socket_path() -> PathBuf {
     } else {
         socket_name.push_str("wayland-0")
     }
+    if !namespace.is_empty() {
+        socket_name.push('-');
+        socket_name.push_str(namespace);
+    }
     socket_name.push_str(".socket");
 
     socket_path.push(socket_name);
@@ -95,18 +363,37 @@ pub fn get_socket_path() -> PathBuf {
     socket_path
 }
 
-/// We make sure the Stream is always set to blocking mode
-///
-/// * `tries` -  how many times to attempt the connection
-/// * `interval` - how long to wait between attempts, in milliseconds
-pub fn connect_to_socket(addr: &PathBuf, tries: u8, interval: u64) -> Result<OwnedFd, String> {
-    let socket = rustix::net::socket_with(
+fn new_socket() -> OwnedFd {
+    rustix::net::socket_with(
         rustix::net::AddressFamily::UNIX,
         rustix::net::SocketType::STREAM,
         rustix::net::SocketFlags::CLOEXEC,
         None,
     )
-    .expect("failed to create socket file descriptor");
+    .expect("failed to create socket file descriptor")
+}
+
+fn set_read_timeout(socket: &OwnedFd) -> Result<(), String> {
+    let timeout = client_timeout();
+    net::sockopt::set_socket_timeout(socket, net::sockopt::Timeout::Recv, Some(timeout))
+        .map_err(|e| format!("failed to set read timeout for socket: {e}"))?;
+    net::sockopt::set_socket_timeout(socket, net::sockopt::Timeout::Send, Some(timeout))
+        .map_err(|e| format!("failed to set write timeout for socket: {e}"))
+}
+
+/// Removes the read timeout we set when connecting, so that `read_socket` blocks until we get an
+/// answer. Useful when we expect to wait on the daemon for an undetermined amount of time.
+pub fn remove_read_timeout(socket: &OwnedFd) -> Result<(), String> {
+    net::sockopt::set_socket_timeout(socket, net::sockopt::Timeout::Recv, None)
+        .map_err(|e| format!("failed to remove read timeout for socket: {e}"))
+}
+
+/// We make sure the Stream is always set to blocking mode
+///
+/// * `tries` -  how many times to attempt the connection
+/// * `interval` - how long to wait between attempts, in milliseconds
+pub fn connect_to_socket(addr: &PathBuf, tries: u8, interval: u64) -> Result<OwnedFd, String> {
+    let socket = new_socket();
     let addr = net::SocketAddrUnix::new(addr).unwrap();
     //Make sure we try at least once
     let tries = if tries == 0 { 1 } else { tries };
@@ -114,18 +401,7 @@ pub fn connect_to_socket(addr: &PathBuf, tries: u8, interval: u64) -> Result<Own
     for _ in 0..tries {
         match net::connect_unix(&socket, &addr) {
             Ok(()) => {
-                #[cfg(debug_assertions)]
-                let timeout = Duration::from_secs(30); //Some operations take a while to respond in debug mode
-                #[cfg(not(debug_assertions))]
-                let timeout = Duration::from_secs(5);
-                if let Err(e) = net::sockopt::set_socket_timeout(
-                    &socket,
-                    net::sockopt::Timeout::Recv,
-                    Some(timeout),
-                ) {
-                    return Err(format!("failed to set read timeout for socket: {e}"));
-                }
-
+                set_read_timeout(&socket)?;
                 return Ok(socket);
             }
             Err(e) => error = Some(e),
@@ -139,3 +415,215 @@ pub fn connect_to_socket(addr: &PathBuf, tries: u8, interval: u64) -> Result<Own
 
     Err(format!("Failed to connect to socket: {error}"))
 }
+
+/// Like `connect_to_socket`, but meant for when the daemon might have just been launched: while
+/// the socket does not exist (or nobody is listening on it), we keep retrying with an exponential
+/// backoff, for at most `max_wait`.
+pub fn connect_to_socket_with_backoff(
+    addr: &PathBuf,
+    max_wait: Duration,
+) -> Result<OwnedFd, String> {
+    let sock_addr = net::SocketAddrUnix::new(addr).unwrap();
+    let start = Instant::now();
+    let mut delay = Duration::from_millis(10);
+    loop {
+        // a failed connection might leave the socket in an unspecified state, so we always use a
+        // new one
+        let socket = new_socket();
+        match net::connect_unix(&socket, &sock_addr) {
+            Ok(()) => {
+                set_read_timeout(&socket)?;
+                return Ok(socket);
+            }
+            Err(e) => {
+                if !matches!(
+                    e.kind(),
+                    std::io::ErrorKind::NotFound | std::io::ErrorKind::ConnectionRefused
+                ) {
+                    return Err(format!("Failed to connect to socket: {e}"));
+                }
+            }
+        }
+
+        let elapsed = start.elapsed();
+        if elapsed >= max_wait {
+            return Err(format!(
+                "Timed out after {:.1}s waiting for socket {addr:?}. Are you sure swww-daemon is \
+                 running?",
+                max_wait.as_secs_f32()
+            ));
+        }
+        std::thread::sleep(delay.min(max_wait - elapsed));
+        delay = (delay * 2).min(Duration::from_secs(1));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn headers_are_little_endian() {
+        let header = encode_header(7, 0x0102_0304);
+        assert_eq!(header[0], 7);
+        assert_eq!(header[1..4], *b"sww");
+        assert_eq!(header[4..8], PROTOCOL_VERSION.to_le_bytes());
+        assert_eq!(header[8..16], [4, 3, 2, 1, 0, 0, 0, 0]);
+        assert_eq!(
+            decode_header(&header),
+            (7, Some(PROTOCOL_VERSION), 0x0102_0304)
+        );
+    }
+
+    #[test]
+    fn old_headers_have_no_protocol_version() {
+        let mut header = [0u8; 16];
+        header[0] = 3;
+        header[8] = 42;
+        assert_eq!(decode_header(&header), (3, None, 42));
+
+        // and neither do the ones of big endian machines, where the code was the last byte
+        let mut header = [0u8; 16];
+        header[7] = 3;
+        header[15] = 42;
+        assert_eq!(decode_header(&header).1, None);
+    }
+
+    #[test]
+    fn clients_of_other_versions_are_told_why_they_are_rejected() {
+        let (client, daemon) = std::os::unix::net::UnixStream::pair().unwrap();
+        let (client, daemon): (OwnedFd, OwnedFd) = (client.into(), daemon.into());
+
+        let mut header = encode_header(1, 0);
+        header[4..8].copy_from_slice(&(PROTOCOL_VERSION + 1).to_le_bytes());
+        let iov = rustix::io::IoSlice::new(&header);
+        net::sendmsg(
+            &client,
+            &[iov],
+            &mut net::SendAncillaryBuffer::default(),
+            net::SendFlags::empty(),
+        )
+        .unwrap();
+        assert!(read_request(&daemon).unwrap().is_none());
+
+        // a client of that version would read it as the daemon's error, since the versions differ
+        let (msg, protocol) = recv_raw_socket_msg(&client, ReadRetries::default()).unwrap();
+        assert_eq!(
+            (msg.code, protocol),
+            (MISMATCH_CODE, Some(PROTOCOL_VERSION))
+        );
+        let e = mismatch_error(msg.shm.unwrap().slice()).unwrap();
+        assert!(
+            e.contains(&format!(
+                "the client speaks version {}",
+                PROTOCOL_VERSION + 1
+            )),
+            "{e}"
+        );
+
+        assert!(send_socket_msg(&client, 1, None).unwrap());
+        assert_eq!(read_request(&daemon).unwrap().unwrap().code, 1);
+    }
+
+    #[test]
+    fn clients_from_before_protocol_versions_read_the_mismatch_as_an_error() {
+        let payload = mismatch_payload("wrong version");
+        // what they did with `Answer::Err`: a u32 length, and then the utf8 error
+        let len = u32::from_ne_bytes(payload[0..4].try_into().unwrap()) as usize;
+        assert_eq!(
+            std::str::from_utf8(&payload[4..4 + len]),
+            Ok("wrong version")
+        );
+        // and they read the code as the low byte of a little endian u64
+        let header = encode_header(MISMATCH_CODE, payload.len());
+        assert_eq!(
+            u64::from_le_bytes(header[0..8].try_into().unwrap()) as u8,
+            4
+        );
+        let err = super::super::Answer::Err(super::super::ErrorKind::Other, String::new());
+        assert_eq!(err.code(), MISMATCH_CODE);
+
+        assert_eq!(mismatch_error(&payload).unwrap(), "wrong version");
+        assert_eq!(mismatch_error(&payload[..6]), None);
+    }
+
+    #[test]
+    fn messages_roundtrip_through_the_socket() {
+        let (a, b) = std::os::unix::net::UnixStream::pair().unwrap();
+        let (a, b): (OwnedFd, OwnedFd) = (a.into(), b.into());
+
+        let mut mmap = Mmap::create(3);
+        mmap.slice_mut().copy_from_slice(&[1, 2, 3]);
+        assert!(send_socket_msg(&a, 5, Some(&mmap)).unwrap());
+        let msg = read_socket(&b).unwrap();
+        assert_eq!(msg.code, 5);
+        assert_eq!(msg.shm.unwrap().slice(), [1, 2, 3]);
+
+        assert!(send_socket_msg(&a, 2, None).unwrap());
+        let msg = read_socket(&b).unwrap();
+        assert_eq!(msg.code, 2);
+        assert!(msg.shm.is_none());
+    }
+
+    #[test]
+    fn reading_an_answer_times_out() {
+        let (_a, b) = std::os::unix::net::UnixStream::pair().unwrap();
+        let start = Instant::now();
+        let e = read_answer_within(&b.into(), Duration::from_millis(50))
+            .err()
+            .unwrap();
+        assert!(start.elapsed() < Duration::from_secs(1));
+        assert!(e.contains("did not answer within"), "{e}");
+    }
+
+    #[test]
+    fn slow_messages_within_the_retries_are_read() {
+        let (daemon, client) = std::os::unix::net::UnixStream::pair().unwrap();
+        client.set_nonblocking(true).unwrap();
+        let (daemon, client): (OwnedFd, OwnedFd) = (daemon.into(), client.into());
+
+        let producer = std::thread::spawn(move || {
+            std::thread::sleep(Duration::from_millis(20));
+            send_socket_msg(&daemon, 3, None).unwrap();
+            daemon
+        });
+        let retries = ReadRetries {
+            max_tries: 50,
+            sleep: Duration::from_millis(5),
+        };
+        let msg = read_socket_with_retries(&client, retries).unwrap();
+        assert_eq!(msg.code, 3);
+        let _daemon = producer.join().unwrap();
+
+        // the default retries give up long before
+        let e = read_socket_with_retries(&client, ReadRetries::default()).err();
+        assert_eq!(e.as_deref(), Some(TIMED_OUT));
+    }
+
+    #[test]
+    fn read_retries_come_from_the_environment() {
+        assert_eq!(
+            ReadRetries::parse(None, None),
+            (ReadRetries::default(), Vec::new())
+        );
+        assert_eq!(
+            ReadRetries::parse(Some("300"), Some("3")),
+            (
+                ReadRetries {
+                    max_tries: 300,
+                    sleep: Duration::from_millis(3),
+                },
+                Vec::new()
+            )
+        );
+
+        // invalid values are only warned about, keeping their default
+        let (retries, warnings) = ReadRetries::parse(Some("many"), Some("2"));
+        assert_eq!(retries.max_tries, ReadRetries::default().max_tries);
+        assert_eq!(retries.sleep, Duration::from_millis(2));
+        assert_eq!(warnings.len(), 1);
+        let (retries, warnings) = ReadRetries::parse(Some("-1"), Some("-1"));
+        assert_eq!(retries, ReadRetries::default());
+        assert_eq!(warnings.len(), 2);
+    }
+}
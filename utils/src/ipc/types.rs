@@ -1,12 +1,12 @@
 use std::{
     fmt,
-    num::{NonZeroI32, NonZeroU8},
+    num::{NonZeroI32, NonZeroU16, NonZeroU8},
     time::Duration,
 };
 
 use crate::compression::BitPack;
 
-use super::{ImageRequestBuilder, Mmap, MmappedBytes, MmappedStr};
+use super::{Mmap, MmappedBytes, MmappedStr, SerializeBuf};
 
 #[derive(Clone, PartialEq)]
 pub enum Coord {
@@ -21,16 +21,35 @@ pub struct Position {
 }
 
 impl Position {
+    pub const CENTER: Self = Self {
+        x: Coord::Percent(0.5),
+        y: Coord::Percent(0.5),
+    };
+
     #[must_use]
     pub fn new(x: Coord, y: Coord) -> Self {
         Self { x, y }
     }
 
+    /// Converts the position to pixels. By default, x grows from the left and y from the bottom of
+    /// the screen. `invert_x` makes x grow from the right, and `invert_y` makes y grow from the top
     #[must_use]
-    pub fn to_pixel(&self, dim: (u32, u32), invert_y: bool) -> (f32, f32) {
+    pub fn to_pixel(&self, dim: (u32, u32), invert_x: bool, invert_y: bool) -> (f32, f32) {
         let x = match self.x {
-            Coord::Pixel(x) => x,
-            Coord::Percent(x) => x * dim.0 as f32,
+            Coord::Pixel(x) => {
+                if invert_x {
+                    dim.0 as f32 - x
+                } else {
+                    x
+                }
+            }
+            Coord::Percent(x) => {
+                if invert_x {
+                    (1.0 - x) * dim.0 as f32
+                } else {
+                    x * dim.0 as f32
+                }
+            }
         };
 
         let y = match self.y {
@@ -83,6 +102,17 @@ impl BgImg {
             Self::Img(s) => 4 + s.len()
         }
     }
+
+    #[must_use]
+    pub fn json(&self) -> String {
+        match self {
+            Self::Color(color) => format!(
+                "{{\"color\":\"{:02X}{:02X}{:02X}\"}}",
+                color[0], color[1], color[2]
+            ),
+            Self::Img(p) => format!("{{\"image\":{}}}", json_string(p)),
+        }
+    }
 }
 
 impl fmt::Display for BgImg {
@@ -132,6 +162,30 @@ impl PixelFormat {
         }
     }
 
+    /// The format with the same number of channels as this one, whose
+    /// `must_swap_r_and_b_channels` is `swap`
+    #[inline]
+    #[must_use]
+    pub const fn with_r_and_b_swap(self, swap: bool) -> Self {
+        match (self.channels(), swap) {
+            (3, false) => Self::Bgr,
+            (3, true) => Self::Rgb,
+            (_, false) => Self::Xbgr,
+            (_, true) => Self::Xrgb,
+        }
+    }
+
+    #[inline]
+    #[must_use]
+    pub const fn name(&self) -> &'static str {
+        match self {
+            Self::Bgr => "bgr",
+            Self::Rgb => "rgb",
+            Self::Xbgr => "xbgr",
+            Self::Xrgb => "xrgb",
+        }
+    }
+
     #[inline]
     #[must_use]
     pub const fn can_copy_directly_onto_wl_buffer(&self) -> bool {
@@ -144,6 +198,87 @@ impl PixelFormat {
     }
 }
 
+/// Converts rgba8 pixels into `format`, swapping the red and blue channels if it must, and either
+/// dropping the alpha byte (for 3 channel formats) or keeping it as the extra byte.
+///
+/// # Panics
+///
+/// If `src_rgba` is not made of whole pixels, or `dst` does not hold exactly as many pixels as it
+pub fn convert_into(src_rgba: &[u8], dst: &mut [u8], format: PixelFormat) {
+    let channels = format.channels() as usize;
+    assert_eq!(src_rgba.len() % 4, 0, "source is not made of rgba pixels");
+    assert_eq!(
+        src_rgba.len() / 4 * channels,
+        dst.len(),
+        "destination does not fit the source pixels"
+    );
+    let swap = format.must_swap_r_and_b_channels();
+    for (src, dst) in src_rgba.chunks_exact(4).zip(dst.chunks_exact_mut(channels)) {
+        if swap {
+            dst[..3].copy_from_slice(&[src[2], src[1], src[0]]);
+        } else {
+            dst[..3].copy_from_slice(&src[..3]);
+        }
+        if channels == 4 {
+            dst[3] = src[3];
+        }
+    }
+}
+
+/// 4x4 Bayer matrix, for `convert_into_dithered`
+const BAYER: [[u32; 4]; 4] = [[0, 8, 2, 10], [12, 4, 14, 6], [3, 11, 1, 9], [15, 7, 13, 5]];
+
+/// Like `convert_into`, but from rgba16 pixels, `width` to a row, with ordered dithering: instead
+/// of rounding each channel to the nearest 8 bit value, we round it up or down depending on its
+/// position in a 4x4 Bayer matrix, so that smooth gradients do not turn into visible bands. The
+/// result only depends on the pixels and their positions.
+///
+/// # Panics
+///
+/// If `src_rgba16` is not made of whole rows of `width` pixels, or `dst` does not hold exactly as
+/// many pixels as it
+pub fn convert_into_dithered(
+    src_rgba16: &[u16],
+    width: usize,
+    dst: &mut [u8],
+    format: PixelFormat,
+) {
+    let channels = format.channels() as usize;
+    assert_eq!(
+        src_rgba16.len() % (width * 4).max(1),
+        0,
+        "source is not made of rows of rgba pixels"
+    );
+    assert_eq!(
+        src_rgba16.len() / 4 * channels,
+        dst.len(),
+        "destination does not fit the source pixels"
+    );
+    let swap = format.must_swap_r_and_b_channels();
+    for (i, (src, dst)) in src_rgba16
+        .chunks_exact(4)
+        .zip(dst.chunks_exact_mut(channels))
+        .enumerate()
+    {
+        let (x, y) = (i % width, i / width);
+        // where between two 8 bit values we round up, in 32ths of a step
+        let threshold = 2 * BAYER[y % 4][x % 4] + 1;
+        let dither = |value: u16| {
+            let scaled = (value as u32 * 255 * 32 + threshold * 65535) / (65535 * 32);
+            scaled.min(255) as u8
+        };
+        let [r, g, b] = [dither(src[0]), dither(src[1]), dither(src[2])];
+        if swap {
+            dst[..3].copy_from_slice(&[b, g, r]);
+        } else {
+            dst[..3].copy_from_slice(&[r, g, b]);
+        }
+        if channels == 4 {
+            dst[3] = (src[3] >> 8) as u8;
+        }
+    }
+}
+
 #[derive(Clone, Copy, Debug, PartialEq)]
 pub enum Scale {
     Whole(NonZeroI32),
@@ -165,6 +300,15 @@ impl Scale {
         }
     }
 
+    #[inline]
+    #[must_use]
+    pub fn as_f32(&self) -> f32 {
+        match self {
+            Scale::Whole(i) => i.get() as f32,
+            Scale::Fractional(f) => f.get() as f32 / 120.0,
+        }
+    }
+
     #[inline]
     #[must_use]
     pub fn div_dim(&self, width: i32, height: i32) -> (i32, i32) {
@@ -182,27 +326,28 @@ impl Scale {
 
 impl fmt::Display for Scale {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(
-            f,
-            "{}",
-            match self {
-                Scale::Whole(i) => i.get() as f32,
-                Scale::Fractional(f) => f.get() as f32 / 120.0,
-            }
-        )
+        write!(f, "{}", self.as_f32())
     }
 }
 
 #[derive(Clone)]
 pub struct BgInfo {
+    /// `BgInfo::UNNAMED` if the compositor did not tell the daemon the output's name yet
     pub name: String,
     pub dim: (u32, u32),
+    /// Position of the output in the compositor's global layout
+    pub position: (i32, i32),
     pub scale_factor: Scale,
     pub img: BgImg,
     pub pixel_format: PixelFormat,
+    /// Whether an animation is currently playing on the output
+    pub animated: bool,
 }
 
 impl BgInfo {
+    /// The name of outputs that did not get theirs yet
+    pub const UNNAMED: &'static str = "?";
+
     #[inline]
     #[must_use]
     pub fn real_dim(&self) -> (u32, u32) {
@@ -216,18 +361,22 @@ impl BgInfo {
         4 // name len
             + self.name.len()
             + 8 //dim
+            + 8 //position
             + 5 //scale_factor (discriminant + value)
             + self.img.serialized_size()
             + 1 //pixel_format
+            + 1 //animated
     }
 
     pub(super) fn serialize(&self, buf: &mut [u8]) -> usize {
         let Self {
             name,
             dim,
+            position,
             scale_factor,
             img,
             pixel_format,
+            animated,
         } = self;
 
         let len = name.as_bytes().len();
@@ -237,6 +386,9 @@ impl BgInfo {
         buf[i..i + 4].copy_from_slice(&dim.0.to_ne_bytes());
         buf[i + 4..i + 8].copy_from_slice(&dim.1.to_ne_bytes());
         i += 8;
+        buf[i..i + 4].copy_from_slice(&position.0.to_ne_bytes());
+        buf[i + 4..i + 8].copy_from_slice(&position.1.to_ne_bytes());
+        i += 8;
 
         match scale_factor {
             Scale::Whole(value) => {
@@ -267,14 +419,15 @@ impl BgInfo {
         }
 
         buf[i] = *pixel_format as u8;
-        i + 1
+        buf[i + 1] = *animated as u8;
+        i + 2
     }
 
     pub(super) fn deserialize(bytes: &[u8]) -> (Self, usize) {
         let name = deserialize_string(bytes);
         let mut i = name.len() + 4;
 
-        assert!(bytes.len() > i + 17);
+        assert!(bytes.len() > i + 25);
 
         let dim = (
             u32::from_ne_bytes(bytes[i..i + 4].try_into().unwrap()),
@@ -282,6 +435,12 @@ impl BgInfo {
         );
         i += 8;
 
+        let position = (
+            i32::from_ne_bytes(bytes[i..i + 4].try_into().unwrap()),
+            i32::from_ne_bytes(bytes[i + 4..i + 8].try_into().unwrap()),
+        );
+        i += 8;
+
         let scale_factor = if bytes[i] == 0 {
             Scale::Whole(
                 i32::from_ne_bytes(bytes[i + 1..i + 5].try_into().unwrap())
@@ -313,19 +472,40 @@ impl BgInfo {
             2 => PixelFormat::Xbgr,
             _ => PixelFormat::Xrgb,
         };
-        i += 1;
+        let animated = bytes[i + 1] != 0;
+        i += 2;
 
         (
             Self {
                 name,
                 dim,
+                position,
                 scale_factor,
                 img,
                 pixel_format,
+                animated,
             },
             i,
         )
     }
+
+    /// Serializes this into a JSON object, so that scripts don't have to parse our `Display`
+    /// implementation
+    #[must_use]
+    pub fn json(&self) -> String {
+        format!(
+            "{{\"name\":{},\"dim\":[{},{}],\"position\":[{},{}],\"scale_factor\":{},\"img\":{},\"pixel_format\":\"{}\",\"animated\":{}}}",
+            json_string(&self.name),
+            self.dim.0,
+            self.dim.1,
+            self.position.0,
+            self.position.1,
+            self.scale_factor,
+            self.img.json(),
+            self.pixel_format.name(),
+            self.animated,
+        )
+    }
 }
 
 impl fmt::Display for BgInfo {
@@ -334,12 +514,115 @@ impl fmt::Display for BgInfo {
             f,
             "{}: {}x{}, scale: {}, currently displaying: {}",
             self.name, self.dim.0, self.dim.1, self.scale_factor, self.img
+        )?;
+        if self.animated {
+            write!(f, " (animated)")?;
+        }
+        Ok(())
+    }
+}
+
+/// An image stored in the cache, to be restored the next time its output shows up
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CacheEntry {
+    pub output: String,
+    pub img_path: String,
+    /// whether we also cached the frames of the image's animation (for some dimension and pixel
+    /// format)
+    pub animated: bool,
+}
+
+impl CacheEntry {
+    pub(super) fn serialized_size(&self) -> usize {
+        4 + self.output.len() + 4 + self.img_path.len() + 1
+    }
+
+    pub(super) fn serialize(&self, buf: &mut [u8]) -> usize {
+        let mut i = 0;
+        for s in [&self.output, &self.img_path] {
+            let len = s.len();
+            buf[i..i + 4].copy_from_slice(&(len as u32).to_ne_bytes());
+            buf[i + 4..i + 4 + len].copy_from_slice(s.as_bytes());
+            i += 4 + len;
+        }
+        buf[i] = self.animated as u8;
+        i + 1
+    }
+
+    pub(super) fn deserialize(bytes: &[u8]) -> (Self, usize) {
+        let output = deserialize_string(bytes);
+        let mut i = 4 + output.len();
+        let img_path = deserialize_string(&bytes[i..]);
+        i += 4 + img_path.len();
+        let animated = bytes[i] != 0;
+        (
+            Self {
+                output,
+                img_path,
+                animated,
+            },
+            i + 1,
+        )
+    }
+}
+
+impl fmt::Display for CacheEntry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.output, self.img_path)?;
+        if self.animated {
+            write!(f, " (animated)")?;
+        }
+        Ok(())
+    }
+}
+
+/// How far the transition to a new image got in an output, sent to the daemon's subscribers
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TransitionProgress {
+    pub output: String,
+    /// From 0 to 100. Every transition ends with 100, even the ones that have no duration
+    pub percent: u8,
+}
+
+impl TransitionProgress {
+    pub(super) fn serialized_size(&self) -> usize {
+        4 + self.output.len() + 1
+    }
+
+    pub(super) fn serialize(&self, buf: &mut [u8]) -> usize {
+        let len = self.output.len();
+        buf[0..4].copy_from_slice(&(len as u32).to_ne_bytes());
+        buf[4..4 + len].copy_from_slice(self.output.as_bytes());
+        buf[4 + len] = self.percent;
+        4 + len + 1
+    }
+
+    pub(super) fn deserialize(bytes: &[u8]) -> (Self, usize) {
+        let output = deserialize_string(bytes);
+        let i = 4 + output.len();
+        let percent = bytes[i];
+        (Self { output, percent }, i + 1)
+    }
+
+    /// Serializes this into a JSON object, like `BgInfo::json`
+    #[must_use]
+    pub fn json(&self) -> String {
+        format!(
+            "{{\"name\":{},\"transition_progress\":{}}}",
+            json_string(&self.output),
+            self.percent
         )
     }
 }
 
+impl fmt::Display for TransitionProgress {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: transition {}%", self.output, self.percent)
+    }
+}
+
 #[repr(u8)]
-#[derive(Clone, Copy)]
+#[derive(Clone, Copy, Debug)]
 pub enum TransitionType {
     Simple = 0,
     Fade = 1,
@@ -348,70 +631,268 @@ pub enum TransitionType {
     Grow = 4,
     Wave = 5,
     None = 6,
+    Dissolve = 7,
+    Slide = 8,
+    Tiles = 9,
 }
 
+#[derive(Clone)]
 pub struct Transition {
     pub transition_type: TransitionType,
     pub duration: f32,
     pub step: NonZeroU8,
+    /// Whether the daemon should scale `step` for the fps each output ends up with, since it was
+    /// not given explicitly
+    pub auto_step: bool,
     pub fps: u16,
     pub angle: f64,
     pub pos: Position,
     pub bezier: (f32, f32, f32, f32),
     pub wave: (f32, f32),
+    pub invert_x: bool,
     pub invert_y: bool,
+    /// Only used by `TransitionType::Fade`: keep playing the outgoing (and incoming) animations
+    /// while fading between them
+    pub live: bool,
+    /// Only used by `TransitionType::Tiles`: the side of each tile, in pixels
+    pub tile_size: NonZeroU16,
+}
+
+/// Builds a `Transition`, using the same defaults as `swww img`
+pub struct TransitionBuilder {
+    transition_type: TransitionType,
+    duration: f32,
+    step: Option<NonZeroU8>,
+    fps: u16,
+    angle: f64,
+    pos: Position,
+    bezier: (f32, f32, f32, f32),
+    wave: (f32, f32),
+    invert_x: bool,
+    invert_y: bool,
+    live: bool,
+    tile_size: NonZeroU16,
+}
+
+impl Default for TransitionBuilder {
+    fn default() -> Self {
+        Self::new(TransitionType::Simple)
+    }
+}
+
+impl TransitionBuilder {
+    #[must_use]
+    pub fn new(transition_type: TransitionType) -> Self {
+        Self {
+            transition_type,
+            duration: 3.0,
+            step: None,
+            fps: 30,
+            angle: 45.0,
+            pos: Position::new(Coord::Percent(0.5), Coord::Percent(0.5)),
+            bezier: (0.54, 0.0, 0.34, 0.99),
+            wave: (20.0, 20.0),
+            invert_x: false,
+            invert_y: false,
+            live: false,
+            tile_size: Transition::DEFAULT_TILE_SIZE,
+        }
+    }
+
+    /// Duration in seconds
+    #[must_use]
+    pub fn duration(mut self, duration: f32) -> Self {
+        self.duration = duration;
+        self
+    }
+
+    /// If not set, this defaults to 2 for `TransitionType::Simple`, 255 for `TransitionType::None`
+    /// and 90 for everything else, which the daemon then scales for the fps of each output
+    #[must_use]
+    pub fn step(mut self, step: NonZeroU8) -> Self {
+        self.step = Some(step);
+        self
+    }
+
+    /// `Transition::FPS_FROM_OUTPUT` and `Transition::FPS_AUTO` make the daemon choose it for each
+    /// output
+    #[must_use]
+    pub fn fps(mut self, fps: u16) -> Self {
+        self.fps = fps;
+        self
+    }
+
+    /// Angle in degrees
+    #[must_use]
+    pub fn angle(mut self, angle: f64) -> Self {
+        self.angle = angle;
+        self
+    }
+
+    #[must_use]
+    pub fn pos(mut self, pos: Position) -> Self {
+        self.pos = pos;
+        self
+    }
+
+    /// Control points of the bezier curve. The x values (first and third) should be in [0, 1]
+    #[must_use]
+    pub fn bezier(mut self, bezier: (f32, f32, f32, f32)) -> Self {
+        self.bezier = bezier;
+        self
+    }
+
+    #[must_use]
+    pub fn wave(mut self, wave: (f32, f32)) -> Self {
+        self.wave = wave;
+        self
+    }
+
+    #[must_use]
+    pub fn invert_x(mut self, invert_x: bool) -> Self {
+        self.invert_x = invert_x;
+        self
+    }
+
+    #[must_use]
+    pub fn invert_y(mut self, invert_y: bool) -> Self {
+        self.invert_y = invert_y;
+        self
+    }
+
+    /// Only used by `TransitionType::Fade`
+    #[must_use]
+    pub fn live(mut self, live: bool) -> Self {
+        self.live = live;
+        self
+    }
+
+    /// Only used by `TransitionType::Tiles`
+    #[must_use]
+    pub fn tile_size(mut self, tile_size: NonZeroU16) -> Self {
+        self.tile_size = tile_size;
+        self
+    }
+
+    #[must_use]
+    pub fn build(self) -> Transition {
+        let step = self.step.unwrap_or(match self.transition_type {
+            TransitionType::Simple => NonZeroU8::new(2).unwrap(),
+            TransitionType::None => NonZeroU8::MAX,
+            _ => NonZeroU8::new(90).unwrap(),
+        });
+        Transition {
+            transition_type: self.transition_type,
+            duration: self.duration,
+            step,
+            auto_step: self.step.is_none(),
+            fps: self.fps,
+            angle: self.angle,
+            pos: self.pos,
+            bezier: self.bezier,
+            wave: self.wave,
+            invert_x: self.invert_x,
+            invert_y: self.invert_y,
+            live: self.live,
+            tile_size: self.tile_size,
+        }
+    }
 }
 
 impl Transition {
-    pub(super) fn serialize(&self, buf: &mut ImageRequestBuilder) {
+    /// Valid range for the width of each wave in the 'wave' transition. Values close to 0 make
+    /// the sine wave degenerate into noise
+    pub const WAVE_WIDTH: std::ops::RangeInclusive<f32> = 1.0..=500.0;
+    /// Valid range for the height of each wave in the 'wave' transition. Large values make the
+    /// area in which we do the expensive per pixel checks huge
+    pub const WAVE_HEIGHT: std::ops::RangeInclusive<f32> = 0.0..=200.0;
+
+    /// Sentinel `fps` meaning the daemon should use each output's refresh rate
+    pub const FPS_FROM_OUTPUT: u16 = 0;
+    /// Sentinel `fps` meaning the daemon should pick one for each output, from its refresh rate
+    /// and resolution. `swww img --transition-fps` stops right below it, so it is never asked for
+    pub const FPS_AUTO: u16 = u16::MAX;
+
+    /// The tile size of the 'tiles' transition, if none is given
+    pub const DEFAULT_TILE_SIZE: NonZeroU16 = match NonZeroU16::new(64) {
+        Some(size) => size,
+        None => unreachable!(),
+    };
+
+    pub(crate) const SERIALIZED_SIZE: usize = 53;
+
+    /// Clamps `wave` into `WAVE_WIDTH` and `WAVE_HEIGHT`. Non finite values are replaced by the
+    /// default of 20
+    #[must_use]
+    pub fn clamp_wave(wave: (f32, f32)) -> (f32, f32) {
+        let clamp = |v: f32, range: &std::ops::RangeInclusive<f32>| {
+            if v.is_finite() {
+                v.clamp(*range.start(), *range.end())
+            } else {
+                20.0
+            }
+        };
+        (
+            clamp(wave.0, &Self::WAVE_WIDTH),
+            clamp(wave.1, &Self::WAVE_HEIGHT),
+        )
+    }
+
+    pub(crate) fn serialize(&self) -> [u8; Self::SERIALIZED_SIZE] {
         let Self {
             transition_type,
             duration,
             step,
+            auto_step,
             fps,
             angle,
             pos,
             bezier,
             wave,
+            invert_x,
             invert_y,
+            live,
+            tile_size,
         } = self;
 
-        buf.push_byte(*transition_type as u8);
-        buf.extend(&duration.to_ne_bytes());
-        buf.push_byte(step.get());
-        buf.extend(&fps.to_ne_bytes());
-        buf.extend(&angle.to_ne_bytes());
-        match pos.x {
-            Coord::Pixel(f) => {
-                buf.push_byte(0);
-                buf.extend(&f.to_ne_bytes());
-            }
-            Coord::Percent(f) => {
-                buf.push_byte(1);
-                buf.extend(&f.to_ne_bytes());
-            }
-        }
-        match pos.y {
-            Coord::Pixel(f) => {
-                buf.push_byte(0);
-                buf.extend(&f.to_ne_bytes());
-            }
-            Coord::Percent(f) => {
-                buf.push_byte(1);
-                buf.extend(&f.to_ne_bytes());
-            }
-        }
-        buf.extend(&bezier.0.to_ne_bytes());
-        buf.extend(&bezier.1.to_ne_bytes());
-        buf.extend(&bezier.2.to_ne_bytes());
-        buf.extend(&bezier.3.to_ne_bytes());
-        buf.extend(&wave.0.to_ne_bytes());
-        buf.extend(&wave.1.to_ne_bytes());
-        buf.push_byte(*invert_y as u8);
+        let mut buf = [0u8; Self::SERIALIZED_SIZE];
+        buf[0] = *transition_type as u8;
+        buf[1..5].copy_from_slice(&duration.to_ne_bytes());
+        buf[5] = step.get();
+        buf[6..8].copy_from_slice(&fps.to_ne_bytes());
+        buf[8..16].copy_from_slice(&angle.to_ne_bytes());
+        let coord = |coord: &Coord| match coord {
+            Coord::Pixel(f) => (0, f.to_ne_bytes()),
+            Coord::Percent(f) => (1, f.to_ne_bytes()),
+        };
+        let (x_kind, x) = coord(&pos.x);
+        buf[16] = x_kind;
+        buf[17..21].copy_from_slice(&x);
+        let (y_kind, y) = coord(&pos.y);
+        buf[21] = y_kind;
+        buf[22..26].copy_from_slice(&y);
+        buf[26..30].copy_from_slice(&bezier.0.to_ne_bytes());
+        buf[30..34].copy_from_slice(&bezier.1.to_ne_bytes());
+        buf[34..38].copy_from_slice(&bezier.2.to_ne_bytes());
+        buf[38..42].copy_from_slice(&bezier.3.to_ne_bytes());
+        buf[42..46].copy_from_slice(&wave.0.to_ne_bytes());
+        buf[46..50].copy_from_slice(&wave.1.to_ne_bytes());
+        buf[50] =
+            *invert_y as u8 | (*invert_x as u8) << 1 | (*live as u8) << 2 | (*auto_step as u8) << 3;
+        buf[51..53].copy_from_slice(&tile_size.get().to_ne_bytes());
+        buf
     }
 
-    pub(super) fn deserialize(bytes: &[u8]) -> Self {
-        assert!(bytes.len() > 50);
+    /// Creates the shared memory for a `RequestSend::SetDefaultTransition`
+    #[must_use]
+    pub fn create_request(&self) -> Mmap {
+        let mut mmap = Mmap::create(Self::SERIALIZED_SIZE);
+        mmap.slice_mut().copy_from_slice(&self.serialize());
+        mmap
+    }
+
+    pub(crate) fn deserialize(bytes: &[u8]) -> Self {
+        assert!(bytes.len() > 52);
         let transition_type = match bytes[0] {
             0 => TransitionType::Simple,
             1 => TransitionType::Fade,
@@ -419,6 +900,9 @@ impl Transition {
             3 => TransitionType::Wipe,
             4 => TransitionType::Grow,
             5 => TransitionType::Wave,
+            7 => TransitionType::Dissolve,
+            8 => TransitionType::Slide,
+            9 => TransitionType::Tiles,
             _ => TransitionType::None,
         };
         let duration = f32::from_ne_bytes(bytes[1..5].try_into().unwrap());
@@ -446,38 +930,48 @@ impl Transition {
             f32::from_ne_bytes(bytes[38..42].try_into().unwrap()),
         );
 
-        let wave = (
+        let wave = Self::clamp_wave((
             f32::from_ne_bytes(bytes[42..46].try_into().unwrap()),
             f32::from_ne_bytes(bytes[46..50].try_into().unwrap()),
-        );
+        ));
 
-        let invert_y = bytes[50] != 0;
+        let invert_y = bytes[50] & 1 != 0;
+        let invert_x = bytes[50] & 2 != 0;
+        let live = bytes[50] & 4 != 0;
+        let auto_step = bytes[50] & 8 != 0;
+        let tile_size = NonZeroU16::new(u16::from_ne_bytes(bytes[51..53].try_into().unwrap()))
+            .unwrap_or(Self::DEFAULT_TILE_SIZE);
 
         Self {
             transition_type,
             duration,
             step,
+            auto_step,
             fps,
             angle,
             pos,
             bezier,
             wave,
+            invert_x,
             invert_y,
+            live,
+            tile_size,
         }
     }
 }
 
 pub struct ClearSend {
-    pub color: [u8; 3],
+    /// rgba, in the daemon's channel order. An alpha below 255 makes the wallpaper translucent
+    pub color: [u8; 4],
     pub outputs: Box<[String]>,
 }
 
 impl ClearSend {
     pub fn create_request(self) -> Mmap {
         // 1 - output length
-        // 3 - color bytes
+        // 4 - color bytes
         // 4 + output.len() - output len + bytes
-        let len = 4 + self.outputs.iter().map(|o| 4 + o.len()).sum::<usize>();
+        let len = 5 + self.outputs.iter().map(|o| 4 + o.len()).sum::<usize>();
         let mut mmap = Mmap::create(len);
         let bytes = mmap.slice_mut();
         bytes[0] = self.outputs.len() as u8; // we assume someone does not have more than
@@ -489,108 +983,1062 @@ impl ClearSend {
             bytes[i + 4..i + 4 + len as usize].copy_from_slice(output.as_bytes());
             i += 4 + len as usize;
         }
-        bytes[i..i + 3].copy_from_slice(&self.color);
+        bytes[i..i + 4].copy_from_slice(&self.color);
         mmap
     }
 }
 
 pub struct ClearReq {
-    pub color: [u8; 3],
+    pub color: [u8; 4],
     pub outputs: Box<[MmappedStr]>,
 }
 
-pub struct ImgSend {
-    pub path: String,
-    pub dim: (u32, u32),
-    pub format: PixelFormat,
-    pub img: Box<[u8]>,
+impl ClearReq {
+    pub(super) fn deserialize(mmap: &Mmap) -> Self {
+        let bytes = mmap.slice();
+        let len = bytes[0] as usize;
+        let mut outputs = Vec::with_capacity(len);
+        let mut i = 1;
+        for _ in 0..len {
+            let output = MmappedStr::new(mmap, &bytes[i..]);
+            i += 4 + output.str().len();
+            outputs.push(output);
+        }
+        let color = [bytes[i], bytes[i + 1], bytes[i + 2], bytes[i + 3]];
+        Self {
+            color,
+            outputs: outputs.into(),
+        }
+    }
 }
 
-pub struct ImgReq {
-    pub path: MmappedStr,
-    pub dim: (u32, u32),
-    pub format: PixelFormat,
-    pub img: MmappedBytes,
+pub struct ShowPreloadedSend {
+    /// `None` means the daemon's default transition should be used
+    pub transition: Option<Transition>,
+    /// Empty means every output
+    pub outputs: Box<[String]>,
 }
 
-impl ImgReq {
-    pub(super) fn deserialize(mmap: &Mmap, bytes: &[u8]) -> (Self, usize) {
-        let mut i = 0;
-        let path = MmappedStr::new(mmap, &bytes[i..]);
-        i += 4 + path.str().len();
-
-        let img = MmappedBytes::new(mmap, &bytes[i..]);
-        i += 4 + img.bytes().len();
+impl ShowPreloadedSend {
+    pub fn create_request(self) -> Mmap {
+        // 1 - whether a transition follows
+        // 1 - output length
+        // 4 + output.len() - output len + bytes
+        let len = 1
+            + self
+                .transition
+                .as_ref()
+                .map_or(0, |_| Transition::SERIALIZED_SIZE)
+            + 1
+            + self.outputs.iter().map(|o| 4 + o.len()).sum::<usize>();
+        let mut mmap = Mmap::create(len);
+        let bytes = mmap.slice_mut();
+        let mut i = 1;
+        if let Some(transition) = self.transition.as_ref() {
+            bytes[0] = 1;
+            bytes[1..1 + Transition::SERIALIZED_SIZE].copy_from_slice(&transition.serialize());
+            i += Transition::SERIALIZED_SIZE;
+        }
+        bytes[i] = self.outputs.len() as u8;
+        i += 1;
+        for output in self.outputs.iter() {
+            let len = output.len() as u32;
+            bytes[i..i + 4].copy_from_slice(&len.to_ne_bytes());
+            bytes[i + 4..i + 4 + len as usize].copy_from_slice(output.as_bytes());
+            i += 4 + len as usize;
+        }
+        mmap
+    }
+}
 
-        let dim = (
-            u32::from_ne_bytes(bytes[i..i + 4].try_into().unwrap()),
-            u32::from_ne_bytes(bytes[i + 4..i + 8].try_into().unwrap()),
-        );
-        i += 8;
+pub struct ShowPreloadedReq {
+    pub transition: Option<Transition>,
+    pub outputs: Box<[MmappedStr]>,
+}
 
-        let format = match bytes[i] {
-            0 => PixelFormat::Bgr,
-            1 => PixelFormat::Rgb,
-            2 => PixelFormat::Xbgr,
-            _ => PixelFormat::Xrgb,
+impl ShowPreloadedReq {
+    pub(super) fn deserialize(mmap: &Mmap) -> Self {
+        let bytes = mmap.slice();
+        let (transition, mut i) = if bytes[0] == 0 {
+            (None, 1)
+        } else {
+            let transition = Transition::deserialize(&bytes[1..]);
+            (Some(transition), 1 + Transition::SERIALIZED_SIZE)
         };
+        let len = bytes[i] as usize;
         i += 1;
+        let mut outputs = Vec::with_capacity(len);
+        for _ in 0..len {
+            let output = MmappedStr::new(mmap, &bytes[i..]);
+            i += 4 + output.str().len();
+            outputs.push(output);
+        }
+        Self {
+            transition,
+            outputs: outputs.into(),
+        }
+    }
+}
 
-        (
-            Self {
-                path,
-                dim,
-                format,
-                img,
-            },
-            i,
-        )
+/// Sets (or clears) the sticky image: how `swww-daemon` should run `swww` to display an image on
+/// outputs that show up later. The daemon appends `--outputs=<output>` to the arguments
+pub struct StickySend {
+    /// Empty clears the sticky image
+    pub args: Box<[String]>,
+}
+
+impl StickySend {
+    pub fn create_request(&self) -> Mmap {
+        serialize_strings(self.args.iter().map(String::as_str))
     }
 }
 
-pub struct Animation {
-    pub animation: Box<[(BitPack, Duration)]>,
+/// Writes the number of `strings`, followed by the length and bytes of each one
+fn serialize_strings<'a>(strings: impl Iterator<Item = &'a str> + Clone) -> Mmap {
+    // 4 - number of strings
+    // 4 + s.len() - string len + bytes
+    let len = 4 + strings.clone().map(|s| 4 + s.len()).sum::<usize>();
+    let mut mmap = Mmap::create(len);
+    let bytes = mmap.slice_mut();
+    bytes[0..4].copy_from_slice(&(strings.clone().count() as u32).to_ne_bytes());
+    let mut i = 4;
+    for s in strings {
+        let len = s.len() as u32;
+        bytes[i..i + 4].copy_from_slice(&len.to_ne_bytes());
+        bytes[i + 4..i + 4 + len as usize].copy_from_slice(s.as_bytes());
+        i += 4 + len as usize;
+    }
+    mmap
 }
 
-impl Animation {
-    pub(crate) fn serialize(&self, buf: &mut ImageRequestBuilder) {
-        let Self { animation } = self;
+fn deserialize_strings(mmap: &Mmap) -> Vec<String> {
+    let bytes = mmap.slice();
+    let len = u32::from_ne_bytes(bytes[0..4].try_into().unwrap()) as usize;
+    let mut i = 4;
+    let mut strings = Vec::with_capacity(len);
+    for _ in 0..len {
+        // we keep these around for longer than the request, so we copy them out of the mmap
+        let s = MmappedStr::new(mmap, &bytes[i..]);
+        i += 4 + s.str().len();
+        strings.push(s.str().to_string());
+    }
+    strings
+}
+
+pub struct StickyReq {
+    /// Empty clears the sticky image
+    pub args: Box<[String]>,
+}
 
-        buf.extend(&(animation.len() as u32).to_ne_bytes());
-        for (bitpack, duration) in animation.iter() {
-            bitpack.serialize(buf);
-            buf.extend(&duration.as_secs_f64().to_ne_bytes())
+impl StickyReq {
+    pub(super) fn deserialize(mmap: &Mmap) -> Self {
+        Self {
+            args: deserialize_strings(mmap).into(),
         }
     }
+}
 
-    pub(crate) fn deserialize(mmap: &Mmap, bytes: &[u8]) -> (Self, usize) {
-        let mut i = 0;
-        let animation_len = u32::from_ne_bytes(bytes[i..i + 4].try_into().unwrap()) as usize;
-        i += 4;
-        let mut animation = Vec::with_capacity(animation_len);
-        for _ in 0..animation_len {
-            let (anim, offset) = BitPack::deserialize(mmap, &bytes[i..]);
-            i += offset;
-            let duration =
-                Duration::from_secs_f64(f64::from_ne_bytes(bytes[i..i + 8].try_into().unwrap()));
-            i += 8;
-            animation.push((anim, duration));
+/// Sets (or clears) how `swww-daemon` should run `swww` to display an image whenever a workspace
+/// becomes active. The daemon appends `--outputs=<outputs>` to the arguments, with the outputs of
+/// the workspace's group
+pub struct WorkspaceSend {
+    /// the workspace's name, as the compositor advertises it
+    pub workspace: String,
+    /// Empty clears the workspace's image
+    pub args: Box<[String]>,
+}
+
+impl WorkspaceSend {
+    pub fn create_request(&self) -> Mmap {
+        let args = self.args.iter().map(String::as_str);
+        serialize_strings(std::iter::once(self.workspace.as_str()).chain(args))
+    }
+}
+
+pub struct WorkspaceReq {
+    pub workspace: String,
+    /// Empty clears the workspace's image
+    pub args: Box<[String]>,
+}
+
+impl WorkspaceReq {
+    pub(super) fn deserialize(mmap: &Mmap) -> Self {
+        let mut strings = deserialize_strings(mmap).into_iter();
+        Self {
+            workspace: strings.next().unwrap_or_default(),
+            args: strings.collect(),
+        }
+    }
+}
+
+/// Asks the daemon to capture what the compositor currently displays on some outputs, and to set
+/// it as their wallpaper
+pub struct FreezeSend {
+    /// Empty means every output
+    pub outputs: Box<[String]>,
+}
+
+impl FreezeSend {
+    pub fn create_request(&self) -> Mmap {
+        // 1 - output length
+        // 4 + output.len() - output len + bytes
+        let len = 1 + self.outputs.iter().map(|o| 4 + o.len()).sum::<usize>();
+        let mut mmap = Mmap::create(len);
+        let bytes = mmap.slice_mut();
+        bytes[0] = self.outputs.len() as u8;
+        let mut i = 1;
+        for output in self.outputs.iter() {
+            let len = output.len() as u32;
+            bytes[i..i + 4].copy_from_slice(&len.to_ne_bytes());
+            bytes[i + 4..i + 4 + len as usize].copy_from_slice(output.as_bytes());
+            i += 4 + len as usize;
+        }
+        mmap
+    }
+}
+
+pub struct FreezeReq {
+    /// Empty means every output
+    pub outputs: Box<[MmappedStr]>,
+}
+
+impl FreezeReq {
+    pub(super) fn deserialize(mmap: &Mmap) -> Self {
+        let bytes = mmap.slice();
+        let len = bytes[0] as usize;
+        let mut i = 1;
+        let mut outputs = Vec::with_capacity(len);
+        for _ in 0..len {
+            let output = MmappedStr::new(mmap, &bytes[i..]);
+            i += 4 + output.str().len();
+            outputs.push(output);
+        }
+        Self {
+            outputs: outputs.into(),
+        }
+    }
+}
+
+/// Plays the animations of some outputs at exactly `fps` for a while, regardless of their own
+/// frame durations and of `swww-daemon --max-fps`. Once it is over, they go back to their own
+/// rate, capped by `--max-fps`
+pub struct BoostFpsSend {
+    pub fps: NonZeroU16,
+    pub duration: Duration,
+    /// Empty means every output
+    pub outputs: Box<[String]>,
+}
+
+impl BoostFpsSend {
+    pub fn create_request(&self) -> Mmap {
+        // 2 - fps
+        // 4 - duration, in milliseconds
+        // 1 - output length
+        // 4 + output.len() - output len + bytes
+        let len = 2 + 4 + 1 + self.outputs.iter().map(|o| 4 + o.len()).sum::<usize>();
+        let mut mmap = Mmap::create(len);
+        let bytes = mmap.slice_mut();
+        bytes[0..2].copy_from_slice(&self.fps.get().to_ne_bytes());
+        let millis = self.duration.as_millis().min(u32::MAX as u128) as u32;
+        bytes[2..6].copy_from_slice(&millis.to_ne_bytes());
+        bytes[6] = self.outputs.len() as u8;
+        let mut i = 7;
+        for output in self.outputs.iter() {
+            let len = output.len() as u32;
+            bytes[i..i + 4].copy_from_slice(&len.to_ne_bytes());
+            bytes[i + 4..i + 4 + len as usize].copy_from_slice(output.as_bytes());
+            i += 4 + len as usize;
+        }
+        mmap
+    }
+}
+
+pub struct BoostFpsReq {
+    pub fps: NonZeroU16,
+    pub duration: Duration,
+    /// Empty means every output
+    pub outputs: Box<[MmappedStr]>,
+}
+
+impl BoostFpsReq {
+    pub(super) fn deserialize(mmap: &Mmap) -> Self {
+        let bytes = mmap.slice();
+        let fps = u16::from_ne_bytes(bytes[0..2].try_into().unwrap());
+        let millis = u32::from_ne_bytes(bytes[2..6].try_into().unwrap());
+        let len = bytes[6] as usize;
+        let mut i = 7;
+        let mut outputs = Vec::with_capacity(len);
+        for _ in 0..len {
+            let output = MmappedStr::new(mmap, &bytes[i..]);
+            i += 4 + output.str().len();
+            outputs.push(output);
+        }
+        Self {
+            fps: NonZeroU16::new(fps).unwrap_or(NonZeroU16::MIN),
+            duration: Duration::from_millis(millis as u64),
+            outputs: outputs.into(),
+        }
+    }
+}
+
+/// Asks the daemon for what an output is displaying. It answers with an `Answer::Buffer`
+pub struct GetBufferSend {
+    pub output: String,
+}
+
+impl GetBufferSend {
+    pub fn create_request(&self) -> Mmap {
+        let len = self.output.len();
+        let mut mmap = Mmap::create(4 + len);
+        let bytes = mmap.slice_mut();
+        bytes[0..4].copy_from_slice(&(len as u32).to_ne_bytes());
+        bytes[4..4 + len].copy_from_slice(self.output.as_bytes());
+        mmap
+    }
+}
+
+pub struct GetBufferReq {
+    pub output: String,
+}
+
+impl GetBufferReq {
+    pub(super) fn deserialize(mmap: &Mmap) -> Self {
+        Self {
+            output: deserialize_string(mmap.slice()),
+        }
+    }
+}
+
+/// A global the daemon bound, and the version it bound
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct BoundGlobal {
+    pub interface: String,
+    pub version: u32,
+}
+
+/// What the daemon negotiated with the compositor, answering `RequestSend::Diagnostics`
+#[derive(Debug, PartialEq)]
+pub struct Diagnostics {
+    /// In the order the daemon bound them
+    pub globals: Box<[BoundGlobal]>,
+    /// The wl_shm format of the daemon's buffers
+    pub pixel_format: PixelFormat,
+    /// Whether clients must swap the red and blue channels of the images they send. Usually this
+    /// follows from `pixel_format`, unless the daemon was started with `--swap-channels`
+    pub swap_channels: bool,
+}
+
+impl Diagnostics {
+    pub(super) fn serialized_size(&self) -> usize {
+        4 + self
+            .globals
+            .iter()
+            .map(|global| 4 + global.interface.len() + 4)
+            .sum::<usize>()
+            + 2
+    }
+
+    pub(super) fn serialize(&self, buf: &mut [u8]) {
+        buf[0..4].copy_from_slice(&(self.globals.len() as u32).to_ne_bytes());
+        let mut i = 4;
+        for global in self.globals.iter() {
+            let len = global.interface.len();
+            buf[i..i + 4].copy_from_slice(&(len as u32).to_ne_bytes());
+            buf[i + 4..i + 4 + len].copy_from_slice(global.interface.as_bytes());
+            i += 4 + len;
+            buf[i..i + 4].copy_from_slice(&global.version.to_ne_bytes());
+            i += 4;
+        }
+        buf[i] = self.pixel_format as u8;
+        buf[i + 1] = self.swap_channels as u8;
+    }
+
+    pub(super) fn deserialize(bytes: &[u8]) -> Self {
+        let len = u32::from_ne_bytes(bytes[0..4].try_into().unwrap()) as usize;
+        let mut globals = Vec::with_capacity(len);
+        let mut i = 4;
+        for _ in 0..len {
+            let interface = deserialize_string(&bytes[i..]);
+            i += 4 + interface.len();
+            let version = u32::from_ne_bytes(bytes[i..i + 4].try_into().unwrap());
+            i += 4;
+            globals.push(BoundGlobal { interface, version });
+        }
+        let pixel_format = match bytes[i] {
+            0 => PixelFormat::Bgr,
+            1 => PixelFormat::Rgb,
+            2 => PixelFormat::Xbgr,
+            _ => PixelFormat::Xrgb,
+        };
+        Self {
+            globals: globals.into(),
+            pixel_format,
+            swap_channels: bytes[i + 1] != 0,
+        }
+    }
+}
+
+impl fmt::Display for Diagnostics {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "bound globals:")?;
+        for global in self.globals.iter() {
+            writeln!(f, "  {} (version {})", global.interface, global.version)?;
+        }
+        write!(
+            f,
+            "pixel format: {}",
+            format!("{:?}", self.pixel_format).to_lowercase()
+        )?;
+        if self.swap_channels {
+            write!(f, " (clients swap the red and blue channels)")?;
+        }
+        Ok(())
+    }
+}
+
+/// The contents of an output's buffer, exactly as the daemon hands them to the compositor
+pub struct WallpaperBuffer {
+    pub dim: (u32, u32),
+    /// The format of `bytes`. Like in `BgInfo`, this takes into account whether the daemon swaps
+    /// the red and blue channels
+    pub format: PixelFormat,
+    pub bytes: Box<[u8]>,
+}
+
+impl WallpaperBuffer {
+    pub(super) fn serialized_size(&self) -> usize {
+        8 // dim
+            + 1 // format
+            + 4 // bytes len
+            + self.bytes.len()
+    }
+
+    pub(super) fn serialize(&self, buf: &mut [u8]) {
+        buf[0..4].copy_from_slice(&self.dim.0.to_ne_bytes());
+        buf[4..8].copy_from_slice(&self.dim.1.to_ne_bytes());
+        buf[8] = self.format as u8;
+        buf[9..13].copy_from_slice(&(self.bytes.len() as u32).to_ne_bytes());
+        buf[13..13 + self.bytes.len()].copy_from_slice(&self.bytes);
+    }
+
+    pub(super) fn deserialize(bytes: &[u8]) -> Self {
+        let dim = (
+            u32::from_ne_bytes(bytes[0..4].try_into().unwrap()),
+            u32::from_ne_bytes(bytes[4..8].try_into().unwrap()),
+        );
+        let format = match bytes[8] {
+            0 => PixelFormat::Bgr,
+            1 => PixelFormat::Rgb,
+            2 => PixelFormat::Xbgr,
+            _ => PixelFormat::Xrgb,
+        };
+        let len = u32::from_ne_bytes(bytes[9..13].try_into().unwrap()) as usize;
+        Self {
+            dim,
+            format,
+            bytes: bytes[13..13 + len].into(),
+        }
+    }
+
+    /// Converts the buffer into rgb, undoing what clients do to the images they send: dropping
+    /// the extra byte of 4 channel formats, and swapping the red and blue channels back
+    #[must_use]
+    pub fn to_rgb(&self) -> Vec<u8> {
+        let channels = self.format.channels() as usize;
+        let mut rgb = Vec::with_capacity(self.bytes.len() / channels * 3);
+        for pixel in self.bytes.chunks_exact(channels) {
+            if self.format.must_swap_r_and_b_channels() {
+                rgb.extend_from_slice(&[pixel[2], pixel[1], pixel[0]]);
+            } else {
+                rgb.extend_from_slice(&pixel[..3]);
+            }
         }
+        rgb
+    }
+}
+
+pub struct ImgSend {
+    pub path: String,
+    pub dim: (u32, u32),
+    pub format: PixelFormat,
+    pub img: Box<[u8]>,
+}
+
+pub struct ImgReq {
+    pub path: MmappedStr,
+    pub dim: (u32, u32),
+    pub format: PixelFormat,
+    pub img: MmappedBytes,
+}
+
+impl ImgReq {
+    pub(super) fn deserialize(mmap: &Mmap, bytes: &[u8]) -> (Self, usize) {
+        let mut i = 0;
+        let path = MmappedStr::new(mmap, &bytes[i..]);
+        i += 4 + path.str().len();
+
+        let img = MmappedBytes::new(mmap, &bytes[i..]);
+        i += 4 + img.bytes().len();
+
+        let dim = (
+            u32::from_ne_bytes(bytes[i..i + 4].try_into().unwrap()),
+            u32::from_ne_bytes(bytes[i + 4..i + 8].try_into().unwrap()),
+        );
+        i += 8;
+
+        let format = match bytes[i] {
+            0 => PixelFormat::Bgr,
+            1 => PixelFormat::Rgb,
+            2 => PixelFormat::Xbgr,
+            _ => PixelFormat::Xrgb,
+        };
+        i += 1;
+
+        (
+            Self {
+                path,
+                dim,
+                format,
+                img,
+            },
+            i,
+        )
+    }
+}
+
+/// Brightness and gamma correction the daemon applies to the images of a request before
+/// displaying them, so that the same image looks alike on outputs that are dimmer than others.
+/// Only the wallpaper changes, not the rest of the display
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ColorAdjustment {
+    pub brightness: f32,
+    pub gamma: f32,
+}
+
+impl ColorAdjustment {
+    pub const BRIGHTNESS: std::ops::RangeInclusive<f32> = 0.0..=4.0;
+    pub const GAMMA: std::ops::RangeInclusive<f32> = 0.1..=10.0;
+    /// Leaves the images as they are
+    pub const NONE: Self = Self {
+        brightness: 1.0,
+        gamma: 1.0,
+    };
+
+    pub(crate) const SERIALIZED_SIZE: usize = 8;
+
+    /// Clamps `brightness` into `BRIGHTNESS` and `gamma` into `GAMMA`. Non finite values are
+    /// replaced by 1.0, which changes nothing
+    #[must_use]
+    pub fn new(brightness: f32, gamma: f32) -> Self {
+        let clamp = |v: f32, range: &std::ops::RangeInclusive<f32>| {
+            if v.is_finite() {
+                v.clamp(*range.start(), *range.end())
+            } else {
+                1.0
+            }
+        };
+        Self {
+            brightness: clamp(brightness, &Self::BRIGHTNESS),
+            gamma: clamp(gamma, &Self::GAMMA),
+        }
+    }
+
+    #[must_use]
+    pub fn is_none(&self) -> bool {
+        *self == Self::NONE
+    }
+
+    /// The adjusted value of each possible color byte. A gamma above 1 brightens the midtones,
+    /// while the brightness scales everything
+    #[must_use]
+    pub fn table(&self) -> [u8; 256] {
+        let mut table = [0; 256];
+        for (i, v) in table.iter_mut().enumerate() {
+            let normalized = (i as f32 / 255.0).powf(1.0 / self.gamma);
+            *v = (normalized * self.brightness * 255.0)
+                .round()
+                .clamp(0.0, 255.0) as u8;
+        }
+        table
+    }
+
+    pub(crate) fn serialize(&self) -> [u8; Self::SERIALIZED_SIZE] {
+        let mut buf = [0; Self::SERIALIZED_SIZE];
+        buf[0..4].copy_from_slice(&self.brightness.to_ne_bytes());
+        buf[4..8].copy_from_slice(&self.gamma.to_ne_bytes());
+        buf
+    }
+
+    pub(crate) fn deserialize(bytes: &[u8]) -> Self {
+        Self::new(
+            f32::from_ne_bytes(bytes[0..4].try_into().unwrap()),
+            f32::from_ne_bytes(bytes[4..8].try_into().unwrap()),
+        )
+    }
+}
+
+/// When the day starts and ends, for a `DimSchedule`
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Daylight {
+    /// Fixed local times, in minutes since midnight
+    Fixed { sunrise: u16, sunset: u16 },
+    /// The sunrise and sunset at a location, in degrees (north and east are positive)
+    Location { latitude: f32, longitude: f32 },
+}
+
+/// Dims the wallpapers at night: they are displayed as they are during the day, and with their
+/// brightness multiplied by `night_brightness` at night. See `RequestSend::SetDimSchedule`
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct DimSchedule {
+    pub daylight: Daylight,
+    /// Between 0 and 1
+    pub night_brightness: f32,
+    /// How long going from one brightness to the other takes, centered on sunrise and sunset
+    pub fade: Duration,
+}
+
+impl DimSchedule {
+    pub const NIGHT_BRIGHTNESS: std::ops::RangeInclusive<f32> = 0.0..=1.0;
+}
+
+/// Sets (or disables) the daemon's `DimSchedule`
+pub struct DimScheduleSend {
+    /// `None` disables the schedule
+    pub schedule: Option<DimSchedule>,
+}
+
+impl DimScheduleSend {
+    pub fn create_request(&self) -> Mmap {
+        // 1 - 0 (disabled), 1 (fixed times) or 2 (location)
+        // 4 - night brightness
+        // 4 - fade, in seconds
+        // 8 - sunrise and sunset as u32s, or latitude and longitude as f32s
+        let mut mmap = Mmap::create(17);
+        let bytes = mmap.slice_mut();
+        if let Some(schedule) = self.schedule.as_ref() {
+            bytes[1..5].copy_from_slice(&schedule.night_brightness.to_ne_bytes());
+            let fade = schedule.fade.as_secs().min(u32::MAX as u64) as u32;
+            bytes[5..9].copy_from_slice(&fade.to_ne_bytes());
+            match schedule.daylight {
+                Daylight::Fixed { sunrise, sunset } => {
+                    bytes[0] = 1;
+                    bytes[9..13].copy_from_slice(&(sunrise as u32).to_ne_bytes());
+                    bytes[13..17].copy_from_slice(&(sunset as u32).to_ne_bytes());
+                }
+                Daylight::Location {
+                    latitude,
+                    longitude,
+                } => {
+                    bytes[0] = 2;
+                    bytes[9..13].copy_from_slice(&latitude.to_ne_bytes());
+                    bytes[13..17].copy_from_slice(&longitude.to_ne_bytes());
+                }
+            }
+        }
+        mmap
+    }
+}
+
+pub struct DimScheduleReq {
+    pub schedule: Option<DimSchedule>,
+}
+
+impl DimScheduleReq {
+    pub(super) fn deserialize(mmap: &Mmap) -> Self {
+        let bytes = mmap.slice();
+        let u32_at = |i: usize| u32::from_ne_bytes(bytes[i..i + 4].try_into().unwrap());
+        let f32_at = |i: usize| f32::from_ne_bytes(bytes[i..i + 4].try_into().unwrap());
+        let daylight = match bytes[0] {
+            1 => Daylight::Fixed {
+                sunrise: (u32_at(9) % (24 * 60)) as u16,
+                sunset: (u32_at(13) % (24 * 60)) as u16,
+            },
+            2 => Daylight::Location {
+                latitude: f32_at(9),
+                longitude: f32_at(13),
+            },
+            _ => return Self { schedule: None },
+        };
+        let night_brightness = f32_at(1);
+        let night_brightness = if night_brightness.is_finite() {
+            night_brightness.clamp(
+                *DimSchedule::NIGHT_BRIGHTNESS.start(),
+                *DimSchedule::NIGHT_BRIGHTNESS.end(),
+            )
+        } else {
+            1.0
+        };
+        Self {
+            schedule: Some(DimSchedule {
+                daylight,
+                night_brightness,
+                fade: Duration::from_secs(u32_at(5) as u64),
+            }),
+        }
+    }
+}
+
+/// The rectangle of an output a wallpaper covers, in logical pixels from the output's top left
+/// corner. See `ImageRequestBuilder::push_region`
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Region {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl Region {
+    pub(crate) const SERIALIZED_SIZE: usize = 16;
+
+    /// Whether the region is not empty, and fits within an output whose logical dimensions are
+    /// `dim`
+    #[must_use]
+    pub fn fits(&self, dim: (u32, u32)) -> bool {
+        self.width > 0
+            && self.height > 0
+            && self
+                .x
+                .checked_add(self.width)
+                .is_some_and(|end| end <= dim.0)
+            && self
+                .y
+                .checked_add(self.height)
+                .is_some_and(|end| end <= dim.1)
+    }
+
+    pub(crate) fn serialize(&self) -> [u8; Self::SERIALIZED_SIZE] {
+        let mut buf = [0; Self::SERIALIZED_SIZE];
+        for (i, v) in [self.x, self.y, self.width, self.height].iter().enumerate() {
+            buf[i * 4..i * 4 + 4].copy_from_slice(&v.to_ne_bytes());
+        }
+        buf
+    }
+
+    pub(crate) fn deserialize(bytes: &[u8]) -> Self {
+        let v = |i: usize| u32::from_ne_bytes(bytes[i * 4..i * 4 + 4].try_into().unwrap());
+        Self {
+            x: v(0),
+            y: v(1),
+            width: v(2),
+            height: v(3),
+        }
+    }
+}
+
+impl fmt::Display for Region {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{},{},{},{}", self.x, self.y, self.width, self.height)
+    }
+}
+
+/// What kind of error the daemon answered with, so that clients can tell them apart without
+/// parsing the message
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[repr(u8)]
+pub enum ErrorKind {
+    /// Anything that does not fit the other kinds
+    Other = 0,
+    /// The daemon is in the middle of something that prevents it from handling the request, like
+    /// a reset
+    Busy = 1,
+    /// Failed to read or write the cache
+    Cache = 2,
+    /// The compositor did not give us something we need
+    Compositor = 3,
+    /// There is no preloaded image for the requested outputs
+    NotPreloaded = 4,
+    /// The request named an output the daemon does not know about
+    UnknownOutput = 5,
+}
+
+impl ErrorKind {
+    #[must_use]
+    pub const fn from_u8(n: u8) -> Self {
+        match n {
+            1 => Self::Busy,
+            2 => Self::Cache,
+            3 => Self::Compositor,
+            4 => Self::NotPreloaded,
+            5 => Self::UnknownOutput,
+            // so that older clients still understand kinds added later
+            _ => Self::Other,
+        }
+    }
+
+    #[must_use]
+    pub const fn name(&self) -> &'static str {
+        match self {
+            Self::Other => "other",
+            Self::Busy => "busy",
+            Self::Cache => "cache",
+            Self::Compositor => "compositor",
+            Self::NotPreloaded => "not-preloaded",
+            Self::UnknownOutput => "unknown-output",
+        }
+    }
+}
+
+impl fmt::Display for ErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// The error for a request naming an `output` that is not one of the `known` ones, for
+/// `ErrorKind::UnknownOutput`. `pending` is how many outputs did not get their name from the
+/// compositor yet, in which case `output` might be one of them
+#[must_use]
+pub fn unknown_output_message(output: &str, known: &[String], pending: usize) -> String {
+    let mut known = known.to_vec();
+    known.sort_unstable();
+    let mut msg = if known.is_empty() && pending == 0 {
+        format!("output {output} was not found: no outputs are connected")
+    } else if known.is_empty() {
+        format!("output {output} was not found")
+    } else {
+        format!(
+            "output {output} was not found. Valid outputs are: {}",
+            known.join(", ")
+        )
+    };
+    if pending > 0 {
+        msg.push_str(&format!(
+            ". {pending} more output(s) did not get their name from the compositor yet, try again \
+             in a moment"
+        ));
+    }
+    msg
+}
+
+/// How an animation's playhead relates to the other animations running in the daemon
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[repr(u8)]
+pub enum AnimationClock {
+    /// Follow the daemon's shared clock, so that the same animation displayed by different
+    /// requests stays in phase
+    #[default]
+    Locked = 0,
+    /// Start from the first frame and keep time on its own
+    Independent = 1,
+}
+
+/// How the daemon composites an image (and its animation) over a base image, instead of
+/// displaying it as it is. See `ImageRequestBuilder::push_blend`
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[repr(u8)]
+pub enum BlendMode {
+    /// Adds the image to the base, saturating at white
+    Additive = 0,
+    /// Inverse of multiplying the inverses: only ever brightens the base
+    Screen = 1,
+    /// Multiplies the dark parts of the base and screens the bright ones, keeping its contrast
+    Overlay = 2,
+}
+
+impl BlendMode {
+    pub(super) fn from_u8(byte: u8) -> Self {
+        match byte {
+            0 => Self::Additive,
+            1 => Self::Screen,
+            _ => Self::Overlay,
+        }
+    }
+
+    /// Composites a channel of the image over the same channel of the base
+    #[must_use]
+    #[inline]
+    pub fn channel(self, base: u8, img: u8) -> u8 {
+        let (a, b) = (base as u16, img as u16);
+        match self {
+            Self::Additive => (a + b).min(255) as u8,
+            Self::Screen => (255 - (255 - a) * (255 - b) / 255) as u8,
+            Self::Overlay if a < 128 => (2 * a * b / 255) as u8,
+            Self::Overlay => (255 - 2 * (255 - a) * (255 - b) / 255) as u8,
+        }
+    }
+}
+
+/// The base an image is composited over, and how
+pub struct Blend {
+    pub mode: BlendMode,
+    /// Has the same dimensions and format as the image
+    pub base: ImgReq,
+}
+
+pub struct Animation {
+    pub animation: Box<[(BitPack, Duration)]>,
+    /// Note this isn't part of the serialized animation (and thus isn't cached). It is sent
+    /// alongside it, in the `ImageRequestBuilder`
+    pub clock: AnimationClock,
+    /// Whether the daemon should blend between consecutive frames. Like `clock`, this is sent
+    /// alongside the animation
+    pub interpolate: bool,
+    /// Whether the daemon should move what moved between consecutive frames, instead of only
+    /// blending them. Only used with `interpolate`. Sent alongside the animation, like `clock`
+    pub warp: bool,
+    /// How many times to play the animation before stopping at its last frame. `None` means
+    /// forever. Unlike `clock`, this comes from the image itself, so it is serialized (and cached)
+    /// with the frames
+    pub loop_count: Option<u32>,
+}
+
+impl Animation {
+    /// Total duration of a single loop through all the frames
+    #[must_use]
+    pub fn cycle_duration(&self) -> Duration {
+        self.animation.iter().map(|(_, duration)| *duration).sum()
+    }
+
+    pub(crate) fn serialize(&self, buf: &mut impl SerializeBuf) {
+        let Self {
+            animation,
+            loop_count,
+            ..
+        } = self;
+
+        serialize_frames(animation, buf);
+        // 0 means forever
+        buf.extend_from_slice(&loop_count.unwrap_or(0).to_ne_bytes());
+    }
+
+    pub(crate) fn deserialize(mmap: &Mmap, bytes: &[u8]) -> (Self, usize) {
+        let (animation, mut i) = deserialize_frames(mmap, bytes);
+        let loop_count = u32::from_ne_bytes(bytes[i..i + 4].try_into().unwrap());
+        i += 4;
 
         (
             Self {
                 animation: animation.into(),
+                clock: AnimationClock::Locked,
+                interpolate: false,
+                warp: false,
+                loop_count: (loop_count != 0).then_some(loop_count),
             },
             i,
         )
     }
 }
 
+fn serialize_frames(frames: &[(BitPack, Duration)], buf: &mut impl SerializeBuf) {
+    buf.extend_from_slice(&(frames.len() as u32).to_ne_bytes());
+    for (bitpack, duration) in frames.iter() {
+        bitpack.serialize(buf);
+        buf.extend_from_slice(&duration.as_secs_f64().to_ne_bytes())
+    }
+}
+
+fn deserialize_frames(mmap: &Mmap, bytes: &[u8]) -> (Vec<(BitPack, Duration)>, usize) {
+    let mut i = 0;
+    let len = u32::from_ne_bytes(bytes[i..i + 4].try_into().unwrap()) as usize;
+    i += 4;
+    let mut frames = Vec::with_capacity(len);
+    for _ in 0..len {
+        let (frame, offset) = BitPack::deserialize(mmap, &bytes[i..]);
+        i += offset;
+        let duration =
+            Duration::from_secs_f64(f64::from_ne_bytes(bytes[i..i + 8].try_into().unwrap()));
+        i += 8;
+        frames.push((frame, duration));
+    }
+    (frames, i)
+}
+
+/// An animation whose frames are sent after the image request, as the client compresses them,
+/// with `RequestSend::AnimationFrames`. See `ImageRequestBuilder::push_streamed`
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct StreamedAnimation {
+    pub clock: AnimationClock,
+    pub interpolate: bool,
+    pub warp: bool,
+    pub loop_count: Option<u32>,
+}
+
+/// Some of the frames of a `StreamedAnimation`, in order
+pub struct AnimationFramesSend<'a> {
+    /// The index of the image they animate, in the image request
+    pub img: u8,
+    pub frames: &'a [(BitPack, Duration)],
+    /// Whether these are the last frames of the animation
+    pub last: bool,
+}
+
+impl AnimationFramesSend<'_> {
+    pub fn create_request(&self) -> Mmap {
+        let mut buf = vec![self.img, self.last as u8];
+        serialize_frames(self.frames, &mut buf);
+        let mut mmap = Mmap::create(buf.len());
+        mmap.slice_mut().copy_from_slice(&buf);
+        mmap
+    }
+}
+
+pub struct AnimationFramesReq {
+    pub img: usize,
+    pub frames: Box<[(BitPack, Duration)]>,
+    pub last: bool,
+}
+
+impl AnimationFramesReq {
+    pub(super) fn deserialize(mmap: &Mmap) -> Self {
+        let bytes = mmap.slice();
+        let (frames, _) = deserialize_frames(mmap, &bytes[2..]);
+        Self {
+            img: bytes[0] as usize,
+            frames: frames.into(),
+            last: bytes[1] != 0,
+        }
+    }
+}
+
 pub struct ImageReq {
-    pub transition: Transition,
+    /// `None` means the daemon's default transition should be used
+    pub transition: Option<Transition>,
+    /// Whether to display the images even on outputs that already display the same path
+    pub force: bool,
+    /// Whether to log in detail how we handle this request, regardless of the log level
+    pub debug: bool,
+    /// Whether to display the images on the next frame, skipping the transition and animations
+    pub sync: bool,
+    /// Applied to every image (and animation frame) before displaying it
+    pub adjustment: ColorAdjustment,
     pub imgs: Box<[ImgReq]>,
+    /// The images to transition from, one for each of `imgs`, instead of what their outputs
+    /// display. Set with `ImageRequestBuilder::push_source`
+    pub sources: Option<Box<[ImgReq]>>,
+    /// The bases to composite each of `imgs` (and their animations) over. Set with
+    /// `ImageRequestBuilder::push_blend`
+    pub blends: Option<Box<[Blend]>>,
+    /// The part of their outputs each of `imgs` covers, instead of all of them. Set with
+    /// `ImageRequestBuilder::push_region`
+    pub regions: Option<Box<[Region]>>,
     pub outputs: Box<[Box<[MmappedStr]>]>,
     pub animations: Option<Box<[Animation]>>,
+    /// Set instead of `animations` if their frames are streamed after the request. Only for
+    /// `RequestSend::Img`
+    pub streamed: Option<Box<[StreamedAnimation]>>,
+    /// The arguments to display the images again with, when their outputs change resolution. Set
+    /// with `ImageRequestBuilder::replay`
+    pub replay: Option<Box<[MmappedStr]>>,
+}
+
+/// Quotes and escapes `s` so that it is a valid JSON string
+fn json_string(s: &str) -> String {
+    let mut json = String::with_capacity(s.len() + 2);
+    json.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => json.push_str("\\\""),
+            '\\' => json.push_str("\\\\"),
+            '\n' => json.push_str("\\n"),
+            '\r' => json.push_str("\\r"),
+            '\t' => json.push_str("\\t"),
+            c if (c as u32) < 0x20 => json.push_str(&format!("\\u{:04x}", c as u32)),
+            c => json.push(c),
+        }
+    }
+    json.push('"');
+    json
 }
 
 fn deserialize_string(bytes: &[u8]) -> String {
@@ -599,3 +2047,446 @@ fn deserialize_string(bytes: &[u8]) -> String {
         .expect("received a non utf8 string from socket")
         .to_string()
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn converts_rgba_into_every_pixel_format() {
+        let src = [
+            0x11, 0x22, 0x33, 0xff, // pixel 1
+            0xaa, 0xbb, 0xcc, 0x80, // pixel 2
+            0x00, 0x00, 0xff, 0x00, // pixel 3
+        ];
+        for (format, expected) in [
+            (
+                PixelFormat::Bgr,
+                &[0x11, 0x22, 0x33, 0xaa, 0xbb, 0xcc, 0x00, 0x00, 0xff][..],
+            ),
+            (
+                PixelFormat::Rgb,
+                &[0x33, 0x22, 0x11, 0xcc, 0xbb, 0xaa, 0xff, 0x00, 0x00],
+            ),
+            (
+                PixelFormat::Xbgr,
+                &[
+                    0x11, 0x22, 0x33, 0xff, 0xaa, 0xbb, 0xcc, 0x80, 0x00, 0x00, 0xff, 0x00,
+                ],
+            ),
+            (
+                PixelFormat::Xrgb,
+                &[
+                    0x33, 0x22, 0x11, 0xff, 0xcc, 0xbb, 0xaa, 0x80, 0xff, 0x00, 0x00, 0x00,
+                ],
+            ),
+        ] {
+            let mut dst = vec![0; expected.len()];
+            convert_into(&src, &mut dst, format);
+            assert_eq!(dst, expected, "{}", format.name());
+        }
+    }
+
+    #[test]
+    fn dithering_a_gradient_is_deterministic() {
+        // a horizontal gradient between two 8 bit values, which plain rounding turns into two bands
+        let width = 16;
+        let src: Vec<u16> = (0..width * 4)
+            .flat_map(|i| {
+                let value = 0x4000 + (i % width) as u16 * 0x10;
+                [value, value, value, 0xffff]
+            })
+            .collect();
+        let mut dithered = vec![0; src.len() / 4 * 3];
+        convert_into_dithered(&src, width, &mut dithered, PixelFormat::Rgb);
+        let mut again = vec![0; dithered.len()];
+        convert_into_dithered(&src, width, &mut again, PixelFormat::Rgb);
+        assert_eq!(dithered, again);
+
+        let rounded: Vec<u8> = src
+            .chunks_exact(4)
+            .flat_map(|px| {
+                let value = ((px[0] as u32 * 255 + 32767) / 65535) as u8;
+                [value; 3]
+            })
+            .collect();
+        assert_ne!(dithered, rounded);
+        for (dithered, rounded) in dithered.iter().zip(&rounded) {
+            assert!(dithered.abs_diff(*rounded) <= 1);
+        }
+        // each 4x4 block averages to about the same value as the source
+        let sum: u32 = (0..4)
+            .flat_map(|y| (0..4).map(move |x| (y * width + x) * 3))
+            .map(|i| dithered[i] as u32)
+            .sum();
+        let expected = (0..4u32).map(|x| 0x4000 + x * 0x10).sum::<u32>() * 4 * 255 / 65535;
+        assert!(sum.abs_diff(expected) <= 1, "{sum} {expected}");
+    }
+
+    #[test]
+    #[should_panic(expected = "destination does not fit")]
+    fn converting_into_a_wrongly_sized_buffer_panics() {
+        convert_into(&[0; 8], &mut [0; 8], PixelFormat::Rgb);
+    }
+
+    #[test]
+    fn json_strings_are_escaped() {
+        assert_eq!(json_string("DP-1"), r#""DP-1""#);
+        assert_eq!(json_string(r#"a "b" \c"#), r#""a \"b\" \\c""#);
+        assert_eq!(json_string("a\nb\u{1}"), r#""a\nb\u0001""#);
+    }
+
+    #[test]
+    fn error_kinds_roundtrip() {
+        for kind in [
+            ErrorKind::Other,
+            ErrorKind::Busy,
+            ErrorKind::Cache,
+            ErrorKind::Compositor,
+            ErrorKind::NotPreloaded,
+            ErrorKind::UnknownOutput,
+        ] {
+            assert_eq!(ErrorKind::from_u8(kind as u8), kind);
+        }
+        assert_eq!(ErrorKind::from_u8(255), ErrorKind::Other);
+    }
+
+    #[test]
+    fn transition_builder_defaults() {
+        let transition = TransitionBuilder::default().build();
+        assert!(matches!(transition.transition_type, TransitionType::Simple));
+        assert_eq!(transition.step.get(), 2);
+        assert_eq!(transition.duration, 3.0);
+        assert_eq!(transition.fps, 30);
+        assert_eq!(transition.bezier, (0.54, 0.0, 0.34, 0.99));
+
+        let transition = TransitionBuilder::new(TransitionType::Wipe)
+            .angle(90.0)
+            .fps(60)
+            .build();
+        assert_eq!(transition.step.get(), 90);
+        assert_eq!(transition.angle, 90.0);
+        assert_eq!(transition.fps, 60);
+
+        let transition = TransitionBuilder::new(TransitionType::Wipe)
+            .step(NonZeroU8::new(10).unwrap())
+            .build();
+        assert_eq!(transition.step.get(), 10);
+        assert!(!transition.auto_step);
+    }
+
+    #[test]
+    fn transition_serialization_roundtrip() {
+        let transition = TransitionBuilder::new(TransitionType::Wave)
+            .duration(1.5)
+            .fps(144)
+            .angle(30.0)
+            .pos(Position::new(Coord::Pixel(10.0), Coord::Percent(0.25)))
+            .wave((40.0, 5.0))
+            .invert_x(true)
+            .invert_y(true)
+            .live(true)
+            .tile_size(NonZeroU16::new(32).unwrap())
+            .build();
+        let t = Transition::deserialize(&transition.serialize());
+        assert!(matches!(t.transition_type, TransitionType::Wave));
+        assert_eq!(t.duration, 1.5);
+        assert_eq!(t.step.get(), 90);
+        assert!(t.auto_step);
+        assert_eq!(t.fps, 144);
+        assert_eq!(t.angle, 30.0);
+        assert!(matches!(t.pos.x, Coord::Pixel(x) if x == 10.0));
+        assert!(matches!(t.pos.y, Coord::Percent(y) if y == 0.25));
+        assert_eq!(t.bezier, transition.bezier);
+        assert_eq!(t.wave, (40.0, 5.0));
+        assert!(t.invert_x);
+        assert!(t.invert_y);
+        assert!(t.live);
+        assert_eq!(t.tile_size.get(), 32);
+    }
+
+    #[test]
+    fn cache_entry_serialization_roundtrip() {
+        let entry = CacheEntry {
+            output: "DP-1".to_string(),
+            img_path: "/home/user/wallpaper.gif".to_string(),
+            animated: true,
+        };
+        let mut buf = vec![0; entry.serialized_size()];
+        assert_eq!(entry.serialize(&mut buf), buf.len());
+        assert_eq!(CacheEntry::deserialize(&buf), (entry, buf.len()));
+    }
+
+    #[test]
+    fn bg_info_serialization_roundtrip() {
+        let info = BgInfo {
+            name: "DP-1".to_string(),
+            dim: (1920, 1080),
+            position: (0, 0),
+            scale_factor: Scale::Whole(NonZeroI32::new(1).unwrap()),
+            img: BgImg::Img("/home/user/wallpaper.gif".to_string()),
+            pixel_format: PixelFormat::Xrgb,
+            animated: true,
+        };
+        let mut buf = vec![0; info.serialized_size()];
+        assert_eq!(info.serialize(&mut buf), buf.len());
+        let (deserialized, len) = BgInfo::deserialize(&buf);
+        assert_eq!(len, buf.len());
+        assert_eq!(deserialized.name, info.name);
+        assert_eq!(deserialized.img, info.img);
+        assert!(deserialized.animated);
+    }
+
+    #[test]
+    fn show_preloaded_serialization_roundtrip() {
+        let show = ShowPreloadedSend {
+            transition: Some(TransitionBuilder::new(TransitionType::Fade).build()),
+            outputs: Box::new(["DP-1".to_string(), "HDMI-A-1".to_string()]),
+        };
+        let req = ShowPreloadedReq::deserialize(&show.create_request());
+        assert!(matches!(
+            req.transition.map(|t| t.transition_type),
+            Some(TransitionType::Fade)
+        ));
+        let outputs: Vec<&str> = req.outputs.iter().map(|o| o.str()).collect();
+        assert_eq!(outputs, ["DP-1", "HDMI-A-1"]);
+
+        let show = ShowPreloadedSend {
+            transition: None,
+            outputs: Box::new([]),
+        };
+        let req = ShowPreloadedReq::deserialize(&show.create_request());
+        assert!(req.transition.is_none());
+        assert!(req.outputs.is_empty());
+    }
+
+    #[test]
+    fn sticky_serialization_roundtrip() {
+        let sticky = StickySend {
+            args: Box::new(["img".to_string(), "/tmp/wall.png".to_string()]),
+        };
+        let req = StickyReq::deserialize(&sticky.create_request());
+        assert_eq!(&*req.args, ["img", "/tmp/wall.png"]);
+
+        let sticky = StickySend { args: Box::new([]) };
+        assert!(StickyReq::deserialize(&sticky.create_request())
+            .args
+            .is_empty());
+    }
+
+    #[test]
+    fn workspace_serialization_roundtrip() {
+        let workspace = WorkspaceSend {
+            workspace: "2".to_string(),
+            args: Box::new(["img".to_string(), "/tmp/wall.png".to_string()]),
+        };
+        let req = WorkspaceReq::deserialize(&workspace.create_request());
+        assert_eq!(req.workspace, "2");
+        assert_eq!(&*req.args, ["img", "/tmp/wall.png"]);
+
+        let workspace = WorkspaceSend {
+            workspace: "web".to_string(),
+            args: Box::new([]),
+        };
+        let req = WorkspaceReq::deserialize(&workspace.create_request());
+        assert_eq!(req.workspace, "web");
+        assert!(req.args.is_empty());
+    }
+
+    #[test]
+    fn freeze_serialization_roundtrip() {
+        let freeze = FreezeSend {
+            outputs: Box::new(["DP-1".to_string(), "HDMI-A-1".to_string()]),
+        };
+        let req = FreezeReq::deserialize(&freeze.create_request());
+        let outputs: Vec<&str> = req.outputs.iter().map(|o| o.str()).collect();
+        assert_eq!(outputs, ["DP-1", "HDMI-A-1"]);
+
+        let freeze = FreezeSend {
+            outputs: Box::new([]),
+        };
+        assert!(FreezeReq::deserialize(&freeze.create_request())
+            .outputs
+            .is_empty());
+    }
+
+    #[test]
+    fn dim_schedule_serialization_roundtrip() {
+        for schedule in [
+            Some(DimSchedule {
+                daylight: Daylight::Fixed {
+                    sunrise: 7 * 60,
+                    sunset: 19 * 60 + 30,
+                },
+                night_brightness: 0.6,
+                fade: Duration::from_secs(3600),
+            }),
+            Some(DimSchedule {
+                daylight: Daylight::Location {
+                    latitude: 52.5,
+                    longitude: -13.4,
+                },
+                night_brightness: 0.0,
+                fade: Duration::ZERO,
+            }),
+            None,
+        ] {
+            let req = DimScheduleReq::deserialize(&DimScheduleSend { schedule }.create_request());
+            assert_eq!(req.schedule, schedule);
+        }
+    }
+
+    #[test]
+    fn boost_fps_serialization_roundtrip() {
+        let boost = BoostFpsSend {
+            fps: NonZeroU16::new(144).unwrap(),
+            duration: Duration::from_millis(2500),
+            outputs: Box::new(["DP-1".to_string(), "HDMI-A-1".to_string()]),
+        };
+        let req = BoostFpsReq::deserialize(&boost.create_request());
+        assert_eq!(req.fps.get(), 144);
+        assert_eq!(req.duration, Duration::from_millis(2500));
+        let outputs: Vec<&str> = req.outputs.iter().map(|o| o.str()).collect();
+        assert_eq!(outputs, ["DP-1", "HDMI-A-1"]);
+    }
+
+    #[test]
+    fn transition_progress_serialization_roundtrip() {
+        let progress = TransitionProgress {
+            output: "HDMI-A-1".to_string(),
+            percent: 42,
+        };
+        let mut buf = vec![0; progress.serialized_size()];
+        assert_eq!(progress.serialize(&mut buf), buf.len());
+        assert_eq!(
+            TransitionProgress::deserialize(&buf),
+            (progress.clone(), buf.len())
+        );
+        assert_eq!(
+            progress.json(),
+            "{\"name\":\"HDMI-A-1\",\"transition_progress\":42}"
+        );
+    }
+
+    #[test]
+    fn position_corners_to_pixel() {
+        let dim = (200, 100);
+        let pixel = Position::new(Coord::Pixel(20.0), Coord::Pixel(10.0));
+        let percent = Position::new(Coord::Percent(0.1), Coord::Percent(0.1));
+        for pos in [pixel, percent] {
+            // by default, the origin is in the bottom left corner
+            assert_eq!(pos.to_pixel(dim, false, false), (20.0, 90.0));
+            // top left
+            assert_eq!(pos.to_pixel(dim, false, true), (20.0, 10.0));
+            // bottom right
+            assert_eq!(pos.to_pixel(dim, true, false), (180.0, 90.0));
+            // top right
+            assert_eq!(pos.to_pixel(dim, true, true), (180.0, 10.0));
+        }
+    }
+
+    #[test]
+    fn wave_is_clamped() {
+        assert_eq!(Transition::clamp_wave((20.0, 20.0)), (20.0, 20.0));
+        assert_eq!(Transition::clamp_wave((0.0, -5.0)), (1.0, 0.0));
+        assert_eq!(Transition::clamp_wave((1e9, 1e9)), (500.0, 200.0));
+        assert_eq!(
+            Transition::clamp_wave((f32::NAN, f32::INFINITY)),
+            (20.0, 20.0)
+        );
+    }
+
+    #[test]
+    fn color_adjustment() {
+        let table = ColorAdjustment::NONE.table();
+        assert!(table.iter().enumerate().all(|(i, v)| i as u8 == *v));
+
+        let table = ColorAdjustment::new(0.5, 1.0).table();
+        assert_eq!((table[0], table[100], table[255]), (0, 50, 128));
+        let table = ColorAdjustment::new(2.0, 1.0).table();
+        assert_eq!((table[100], table[200]), (200, 255));
+        let table = ColorAdjustment::new(1.0, 2.0).table();
+        assert_eq!((table[0], table[64], table[255]), (0, 128, 255));
+
+        assert_eq!(
+            ColorAdjustment::new(f32::NAN, 100.0),
+            ColorAdjustment::new(1.0, 10.0)
+        );
+        assert_eq!(ColorAdjustment::new(-1.0, 0.0).brightness, 0.0);
+        let adjustment = ColorAdjustment::new(0.8, 1.2);
+        assert_eq!(
+            ColorAdjustment::deserialize(&adjustment.serialize()),
+            adjustment
+        );
+    }
+
+    #[test]
+    fn diagnostics_serialization_roundtrip() {
+        let diagnostics = Diagnostics {
+            globals: Box::new([
+                BoundGlobal {
+                    interface: "wl_compositor".to_string(),
+                    version: 6,
+                },
+                BoundGlobal {
+                    interface: "zwlr_layer_shell_v1".to_string(),
+                    version: 3,
+                },
+            ]),
+            pixel_format: PixelFormat::Xbgr,
+            swap_channels: true,
+        };
+        let mut buf = vec![0; diagnostics.serialized_size()];
+        diagnostics.serialize(&mut buf);
+        assert_eq!(Diagnostics::deserialize(&buf), diagnostics);
+    }
+
+    #[test]
+    fn wallpaper_buffer_to_rgb() {
+        // how a client would have sent a red and a blue pixel
+        let buffer = |format: PixelFormat, bytes: &[u8]| WallpaperBuffer {
+            dim: (2, 1),
+            format,
+            bytes: bytes.into(),
+        };
+        let rgb = [255, 0, 0, 0, 0, 255];
+        assert_eq!(buffer(PixelFormat::Bgr, &rgb).to_rgb(), rgb);
+        assert_eq!(
+            buffer(PixelFormat::Rgb, &[0, 0, 255, 255, 0, 0]).to_rgb(),
+            rgb
+        );
+        assert_eq!(
+            buffer(PixelFormat::Xbgr, &[255, 0, 0, 255, 0, 0, 255, 255]).to_rgb(),
+            rgb
+        );
+        assert_eq!(
+            buffer(PixelFormat::Xrgb, &[0, 0, 255, 255, 255, 0, 0, 255]).to_rgb(),
+            rgb
+        );
+
+        let buffer = buffer(PixelFormat::Xrgb, &[1, 2, 3, 4, 5, 6, 7, 8]);
+        let mut buf = vec![0; buffer.serialized_size()];
+        buffer.serialize(&mut buf);
+        let deserialized = WallpaperBuffer::deserialize(&buf);
+        assert_eq!(deserialized.dim, (2, 1));
+        assert_eq!(deserialized.format, PixelFormat::Xrgb);
+        assert_eq!(deserialized.bytes, buffer.bytes);
+    }
+
+    #[test]
+    fn bg_info_json() {
+        let info = BgInfo {
+            name: "HDMI-A-1".to_string(),
+            dim: (1920, 1080),
+            position: (-1920, 0),
+            scale_factor: Scale::Fractional(NonZeroI32::new(150).unwrap()),
+            img: BgImg::Color([0xff, 0, 0x10]),
+            pixel_format: PixelFormat::Xrgb,
+            animated: false,
+        };
+        assert_eq!(
+            info.json(),
+            r#"{"name":"HDMI-A-1","dim":[1920,1080],"position":[-1920,0],"scale_factor":1.25,"img":{"color":"FF0010"},"pixel_format":"xrgb","animated":false}"#
+        );
+    }
+}
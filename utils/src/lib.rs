@@ -1,3 +1,6 @@
 pub mod cache;
 pub mod compression;
+#[cfg(feature = "ffi")]
+pub mod ffi;
 pub mod ipc;
+pub mod precomputed;
@@ -0,0 +1,171 @@
+//! Precomputed animations, exported with `swww export`.
+//!
+//! These hold everything the client sends the daemon for an animated image, for a single output
+//! size and pixel format, so that `swww img` can send it again without decoding, resizing and
+//! compressing every frame. The file is:
+//!
+//!   1. a header: `MAGIC`, `VERSION`, width, height and pixel format
+//!   2. the first frame, uncompressed
+//!   3. the animation, serialized just like in an image request (the diffs between frames)
+//!
+//! Numbers are stored in native endianness, like everything else we cache.
+
+use std::{
+    fs::File,
+    io::{self, Write},
+    path::Path,
+};
+
+use crate::ipc::{Animation, Mmap, PixelFormat};
+
+const MAGIC: &[u8; 8] = b"SWWWANIM";
+/// Bump this whenever the layout of the file (or of the serialized animation) changes
+const VERSION: u32 = 2;
+/// Magic, version, width, height and pixel format
+const HEADER_SIZE: usize = 8 + 4 + 4 + 4 + 1;
+
+/// The extension `swww img` uses to recognize precomputed animations
+pub const EXTENSION: &str = "swww";
+
+pub struct Precomputed {
+    pub dim: (u32, u32),
+    pub format: PixelFormat,
+    /// The first frame, to be sent as the request's image
+    pub img: Box<[u8]>,
+    pub animation: Animation,
+}
+
+pub fn export(
+    path: &Path,
+    dim: (u32, u32),
+    format: PixelFormat,
+    img: &[u8],
+    animation: &Animation,
+) -> io::Result<()> {
+    let mut buf = Vec::with_capacity(HEADER_SIZE + 4 + img.len());
+    buf.extend_from_slice(MAGIC);
+    buf.extend_from_slice(&VERSION.to_ne_bytes());
+    buf.extend_from_slice(&dim.0.to_ne_bytes());
+    buf.extend_from_slice(&dim.1.to_ne_bytes());
+    buf.push(format as u8);
+    buf.extend_from_slice(&(img.len() as u32).to_ne_bytes());
+    buf.extend_from_slice(img);
+    animation.serialize(&mut buf);
+
+    File::create(path)?.write_all(&buf)
+}
+
+pub fn import(path: &Path) -> Result<Precomputed, String> {
+    let fd = File::open(path)
+        .map_err(|e| format!("failed to open {path:?}: {e}"))?
+        .into();
+    let len = rustix::fs::seek(&fd, rustix::fs::SeekFrom::End(0))
+        .map_err(|e| format!("failed to read {path:?}: {e}"))? as usize;
+    if len < HEADER_SIZE + 4 {
+        return Err(format!("{path:?} is not a precomputed swww animation"));
+    }
+    let mmap = Mmap::from_fd(fd, len);
+    let bytes = mmap.slice();
+
+    if &bytes[0..8] != MAGIC {
+        return Err(format!("{path:?} is not a precomputed swww animation"));
+    }
+    let version = u32::from_ne_bytes(bytes[8..12].try_into().unwrap());
+    if version != VERSION {
+        return Err(format!(
+            "{path:?} was exported with an incompatible version of swww (file version {version}, \
+             expected {VERSION}). Export it again"
+        ));
+    }
+    let dim = (
+        u32::from_ne_bytes(bytes[12..16].try_into().unwrap()),
+        u32::from_ne_bytes(bytes[16..20].try_into().unwrap()),
+    );
+    let format = match bytes[20] {
+        0 => PixelFormat::Bgr,
+        1 => PixelFormat::Rgb,
+        2 => PixelFormat::Xbgr,
+        3 => PixelFormat::Xrgb,
+        n => return Err(format!("{path:?} has an invalid pixel format: {n}")),
+    };
+
+    let mut i = HEADER_SIZE;
+    let img_len = u32::from_ne_bytes(bytes[i..i + 4].try_into().unwrap()) as usize;
+    i += 4;
+    if img_len == 0 || bytes.len() <= i + img_len {
+        return Err(format!("{path:?} is truncated"));
+    }
+    let img = bytes[i..i + img_len].into();
+    i += img_len;
+
+    let animation = match std::panic::catch_unwind(|| Animation::deserialize(&mmap, &bytes[i..])) {
+        Ok((animation, _)) => animation,
+        Err(_) => return Err(format!("{path:?} has a corrupted animation")),
+    };
+
+    Ok(Precomputed {
+        dim,
+        format,
+        img,
+        animation,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+    use crate::{compression::Compressor, ipc::AnimationClock};
+
+    #[test]
+    fn should_import_exported_animations() {
+        let dim = (4, 2);
+        let format = PixelFormat::Rgb;
+        let first = [0u8; 4 * 2 * 3];
+        let second = [255u8; 4 * 2 * 3];
+        let mut compressor = Compressor::new();
+        let animation = Animation {
+            animation: Box::new([
+                (
+                    compressor.compress(&first, &second, format).unwrap(),
+                    Duration::from_millis(50),
+                ),
+                (
+                    compressor.compress(&second, &first, format).unwrap(),
+                    Duration::from_millis(100),
+                ),
+            ]),
+            clock: AnimationClock::Locked,
+            interpolate: false,
+            warp: false,
+            loop_count: Some(3),
+        };
+
+        let path =
+            std::env::temp_dir().join(format!("swww-export-test-{}.swww", std::process::id()));
+        export(&path, dim, format, &first, &animation).unwrap();
+        let imported = import(&path);
+        std::fs::remove_file(&path).unwrap();
+
+        let imported = imported.unwrap();
+        assert_eq!(imported.dim, dim);
+        assert_eq!(imported.format, format);
+        assert_eq!(&*imported.img, &first);
+        assert_eq!(
+            imported.animation.cycle_duration(),
+            Duration::from_millis(150)
+        );
+        assert_eq!(imported.animation.loop_count, Some(3));
+    }
+
+    #[test]
+    fn should_reject_other_files() {
+        let path =
+            std::env::temp_dir().join(format!("swww-export-test-{}.png", std::process::id()));
+        std::fs::write(&path, [0u8; 64]).unwrap();
+        let imported = import(&path);
+        std::fs::remove_file(&path).unwrap();
+        assert!(imported.is_err());
+    }
+}